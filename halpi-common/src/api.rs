@@ -0,0 +1,457 @@
+//! HTTP API models
+//!
+//! Request and response bodies of the daemon's HTTP API. The daemon
+//! serializes and the CLI deserializes these same types, so a field renamed
+//! on one side is a compile error on the other instead of a value silently
+//! missing from the output.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::UsbDefaultsConfig;
+use crate::duration::deserialize_seconds;
+use crate::types::PowerState;
+use crate::units::{Amps, Kelvin, Volts};
+
+/// Version of the HTTP API
+///
+/// Bumped when a response loses or changes a field, or a request changes,
+/// so that clients can tell a daemon they do not understand from a broken
+/// one. Adding fields or endpoints does not need a bump.
+pub const API_VERSION: u32 = 1;
+
+/// API version of daemons that predate [`VersionResponse::api_version`]
+fn legacy_api_version() -> u32 {
+    1
+}
+
+/// Response of `GET /version`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionResponse {
+    pub daemon_version: String,
+    #[serde(default = "legacy_api_version")]
+    pub api_version: u32,
+    /// Controller identity, once it has been read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hardware_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub firmware_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+}
+
+/// Machine-readable cause of an error response
+///
+/// Each code has a fixed HTTP status, so clients can branch on either.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// The request body or parameters are malformed (400)
+    InvalidRequest,
+    /// A value is out of range or of the wrong type (400)
+    InvalidValue,
+    /// A USB port number is not 0-3 (400)
+    InvalidPort,
+    /// A time or datetime cannot be parsed or is out of range (400)
+    InvalidTime,
+    /// The client may not use the endpoint (403)
+    Forbidden,
+    /// A value or configuration key does not exist (404)
+    UnknownKey,
+    /// A value cannot be written (405)
+    ReadOnly,
+    /// The request conflicts with the daemon's state (409)
+    Conflict,
+    /// The controller failed the startup self-test's compatibility check,
+    /// so requests that change it are refused (409)
+    Incompatible,
+    /// The request body exceeds a limit (413)
+    PayloadTooLarge,
+    /// The request body is not of an accepted type (415)
+    UnsupportedMediaType,
+    /// The client exceeded the request rate limit (429)
+    RateLimited,
+    /// Communication with the controller failed (500)
+    DeviceError,
+    /// An operation of the host system failed, e.g. setting the RTC (500)
+    SystemError,
+    /// The controller's firmware or hardware lacks the feature (501)
+    NotSupported,
+    /// The controller is not connected (503)
+    I2cUnavailable,
+    /// The state machine did not respond in time (503)
+    StateMachineUnavailable,
+    /// No measurements have been collected yet (503)
+    NoData,
+    /// A code this version does not know, or none (500)
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+
+impl ErrorCode {
+    /// HTTP status code of responses with this code
+    pub fn status(self) -> u16 {
+        match self {
+            ErrorCode::InvalidRequest
+            | ErrorCode::InvalidValue
+            | ErrorCode::InvalidPort
+            | ErrorCode::InvalidTime => 400,
+            ErrorCode::Forbidden => 403,
+            ErrorCode::UnknownKey => 404,
+            ErrorCode::ReadOnly => 405,
+            ErrorCode::Conflict | ErrorCode::Incompatible => 409,
+            ErrorCode::PayloadTooLarge => 413,
+            ErrorCode::UnsupportedMediaType => 415,
+            ErrorCode::RateLimited => 429,
+            ErrorCode::DeviceError | ErrorCode::SystemError | ErrorCode::Unknown => 500,
+            ErrorCode::NotSupported => 501,
+            ErrorCode::I2cUnavailable | ErrorCode::StateMachineUnavailable | ErrorCode::NoData => {
+                503
+            }
+        }
+    }
+}
+
+/// Body of every error response
+///
+/// The message is under `error` as in halpid 4.x, so existing clients keep
+/// working; `code` and `detail` were added for clients that branch on the
+/// cause. Bodies of daemons without codes parse with [`ErrorCode::Unknown`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiError {
+    #[serde(default)]
+    pub code: ErrorCode,
+    #[serde(rename = "error")]
+    pub message: String,
+    /// Structured context, such as the key that was not found
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<Value>,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            detail: None,
+        }
+    }
+
+    /// Add structured context
+    pub fn with_detail(mut self, detail: Value) -> Self {
+        self.detail = Some(detail);
+        self
+    }
+
+    /// HTTP status code of the response
+    pub fn status(&self) -> u16 {
+        self.code.status()
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+#[cfg(feature = "axum")]
+impl axum::response::IntoResponse for ApiError {
+    fn into_response(self) -> axum::response::Response {
+        let status = axum::http::StatusCode::from_u16(self.status())
+            .unwrap_or(axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+        (status, axum::Json(self)).into_response()
+    }
+}
+
+/// Response of `GET /values`
+///
+/// Values are named as in `GET /values/{key}`; temperatures are in Kelvin.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValuesResponse {
+    pub daemon_version: String,
+    pub hardware_version: String,
+    pub firmware_version: String,
+    pub device_id: String,
+    /// DC input voltage
+    #[serde(rename = "V_in")]
+    pub dcin_voltage: Volts,
+    /// Supercapacitor voltage
+    #[serde(rename = "V_cap")]
+    pub supercap_voltage: Volts,
+    /// Input current
+    #[serde(rename = "I_in")]
+    pub input_current: Amps,
+    /// MCU temperature
+    #[serde(rename = "T_mcu")]
+    pub mcu_temperature: Kelvin,
+    /// PCB temperature
+    #[serde(rename = "T_pcb")]
+    pub pcb_temperature: Kelvin,
+    pub state: PowerState,
+    #[serde(rename = "5v_output_enabled")]
+    pub output_5v_enabled: bool,
+    pub watchdog_enabled: bool,
+    /// Watchdog timeout (seconds)
+    pub watchdog_timeout: f64,
+    /// Watchdog elapsed time (seconds)
+    pub watchdog_elapsed: f32,
+    /// When the measurements were read from the controller
+    #[serde(default)]
+    pub measured_at: DateTime<Utc>,
+    /// Keys of the values corrected by the daemon's `calibration`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub calibrated: Vec<String>,
+}
+
+impl ValuesResponse {
+    /// The response as JSON, with each value also under its
+    /// [`VALUE_KEY_ALIASES`]
+    pub fn with_aliases(&self) -> Value {
+        let mut json = serde_json::to_value(self).unwrap_or_default();
+        if let Value::Object(map) = &mut json {
+            for (alias, key) in VALUE_KEY_ALIASES {
+                if let Some(value) = map.get(key).cloned() {
+                    map.insert(alias.to_string(), value);
+                }
+            }
+        }
+        json
+    }
+}
+
+/// Value keys of the Python daemon (halpid 4.x) with the keys that replaced
+/// them, accepted by `/values/{key}` and included in `GET /values` so that
+/// existing scripts keep working
+pub const VALUE_KEY_ALIASES: [(&str, &str); 1] = [("V_supercap", "V_cap")];
+
+/// Current name of a value key, resolving [`VALUE_KEY_ALIASES`]
+pub fn value_key(key: &str) -> &str {
+    VALUE_KEY_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == key)
+        .map_or(key, |(_, current)| current)
+}
+
+/// Response of `GET /config`, the settings stored in the controller
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigResponse {
+    /// Watchdog timeout (seconds), 0 if disabled
+    pub watchdog_timeout: f64,
+    /// Supercapacitor voltage to power on at (V)
+    pub power_on_threshold: f32,
+    /// Supercapacitor voltage to power off at on supercap alone (V)
+    pub solo_power_off_threshold: f32,
+    pub led_brightness: u8,
+    pub auto_restart: bool,
+    /// Time to run on supercap alone before powering off (seconds)
+    pub solo_depleting_timeout: f64,
+}
+
+/// A controller setting that differs from the daemon's `controller`
+/// configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigDrift {
+    /// Key as in `GET /config`
+    pub key: String,
+    /// Value declared in the configuration
+    pub expected: Value,
+    /// Value stored in the controller
+    pub actual: Value,
+}
+
+/// Response of `GET /config/diff`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigDiffResponse {
+    /// Declared settings that differ, empty if the controller matches
+    pub drift: Vec<ConfigDrift>,
+    /// Whether the daemon writes drifted settings back
+    pub enforce: bool,
+}
+
+/// Response of `GET /usb`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsbState {
+    pub usb0: bool,
+    pub usb1: bool,
+    pub usb2: bool,
+    pub usb3: bool,
+    /// Ports with a state configured for startup
+    #[serde(default)]
+    pub defaults: UsbDefaultsConfig,
+}
+
+impl UsbState {
+    /// State from the port bitfield of the controller
+    pub fn new(port_bits: u8, defaults: UsbDefaultsConfig) -> Self {
+        Self {
+            usb0: port_bits & 0x01 != 0,
+            usb1: port_bits & 0x02 != 0,
+            usb2: port_bits & 0x04 != 0,
+            usb3: port_bits & 0x08 != 0,
+            defaults,
+        }
+    }
+
+    /// Whether ports 0-3 are enabled
+    pub fn ports(&self) -> [bool; 4] {
+        [self.usb0, self.usb1, self.usb2, self.usb3]
+    }
+}
+
+/// Body of `POST /shutdown/schedule`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ScheduleRequest {
+    /// Shut down after a delay in seconds, or a duration such as "45m"
+    Delay {
+        #[serde(deserialize_with = "deserialize_seconds")]
+        delay: u64,
+    },
+    /// Shut down at a time of day ("22:30"), or at a datetime
+    At { at: String },
+}
+
+/// Body of `POST /standby`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StandbyRequest {
+    /// Standby with delay in seconds, or a duration such as "2h30m"
+    Delay {
+        #[serde(deserialize_with = "deserialize_seconds")]
+        delay: u64,
+    },
+    /// Standby with specific datetime (ISO 8601 format)
+    Datetime { datetime: String },
+    /// Standby until sunrise or sunset with an optional offset, such as
+    /// "sunrise+30m", at the configured location
+    WakeAt { wake_at: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_values_response_keys() {
+        let values = ValuesResponse {
+            daemon_version: "5.0.0".to_string(),
+            hardware_version: "1.0.0".to_string(),
+            firmware_version: "3.3.0".to_string(),
+            device_id: "0011223344556677".to_string(),
+            dcin_voltage: Volts(12.0),
+            supercap_voltage: Volts(9.5),
+            input_current: Amps(0.5),
+            mcu_temperature: Kelvin(300.0),
+            pcb_temperature: Kelvin(301.0),
+            state: PowerState::OperationalCoOp,
+            output_5v_enabled: true,
+            watchdog_enabled: true,
+            watchdog_timeout: 10.0,
+            watchdog_elapsed: 0.5,
+            measured_at: Utc::now(),
+            calibrated: Vec::new(),
+        };
+        let json = serde_json::to_value(&values).unwrap();
+        assert_eq!(json["V_in"], 12.0);
+        assert!(json.get("calibrated").is_none());
+        assert_eq!(json["V_cap"], 9.5);
+        assert!(json["measured_at"].is_string());
+        assert_eq!(json["state"], "OperationalCoOp");
+        assert_eq!(json["5v_output_enabled"], true);
+        assert_eq!(
+            serde_json::from_value::<ValuesResponse>(json).unwrap(),
+            values
+        );
+
+        let json = values.with_aliases();
+        assert_eq!(json["V_supercap"], 9.5);
+        assert_eq!(
+            serde_json::from_value::<ValuesResponse>(json).unwrap(),
+            values
+        );
+    }
+
+    #[test]
+    fn test_value_key() {
+        assert_eq!(value_key("V_supercap"), "V_cap");
+        assert_eq!(value_key("V_cap"), "V_cap");
+        assert_eq!(value_key("V_out"), "V_out");
+    }
+
+    #[test]
+    fn test_version_response() {
+        let version: VersionResponse =
+            serde_json::from_value(json!({"daemon_version": "5.0.0"})).unwrap();
+        assert_eq!(version.api_version, 1);
+        assert_eq!(version.firmware_version, None);
+        assert_eq!(
+            serde_json::to_value(&version).unwrap(),
+            json!({"daemon_version": "5.0.0", "api_version": 1})
+        );
+    }
+
+    #[test]
+    fn test_usb_state() {
+        let defaults = UsbDefaultsConfig {
+            usb1: Some(false),
+            ..Default::default()
+        };
+        let state = UsbState::new(0b0101, defaults);
+        assert_eq!(state.ports(), [true, false, true, false]);
+        assert_eq!(
+            serde_json::to_value(state).unwrap(),
+            json!({
+                "usb0": true,
+                "usb1": false,
+                "usb2": true,
+                "usb3": false,
+                "defaults": {"usb1": false},
+            })
+        );
+    }
+
+    #[test]
+    fn test_request_bodies() {
+        let parse = |json: &str| serde_json::from_str::<StandbyRequest>(json).unwrap();
+        assert_eq!(
+            parse(r#"{"delay": "2h30m"}"#),
+            StandbyRequest::Delay { delay: 9000 }
+        );
+        assert_eq!(
+            serde_json::to_value(StandbyRequest::WakeAt {
+                wake_at: "sunrise".to_string()
+            })
+            .unwrap(),
+            json!({"wake_at": "sunrise"})
+        );
+    }
+
+    #[test]
+    fn test_api_error() {
+        let error = ApiError::new(ErrorCode::UnknownKey, "Unknown key: V_out")
+            .with_detail(json!({"key": "V_out"}));
+        assert_eq!(error.status(), 404);
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            json!({"code": "UNKNOWN_KEY", "error": "Unknown key: V_out", "detail": {"key": "V_out"}})
+        );
+        assert_eq!(
+            serde_json::to_value(ApiError::new(ErrorCode::I2cUnavailable, "missing")).unwrap(),
+            json!({"code": "I2C_UNAVAILABLE", "error": "missing"})
+        );
+
+        // Daemons without codes, and codes newer than this version
+        let error: ApiError = serde_json::from_value(json!({"error": "Failed"})).unwrap();
+        assert_eq!(error.code, ErrorCode::Unknown);
+        let error: ApiError =
+            serde_json::from_value(json!({"code": "SOMETHING_NEW", "error": "Failed"})).unwrap();
+        assert_eq!(error.code, ErrorCode::Unknown);
+        assert_eq!(error.to_string(), "Failed");
+    }
+}
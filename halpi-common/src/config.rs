@@ -1,7 +1,10 @@
 //! Configuration types and loading for HALPI2 daemon
 
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::types::Measurements;
+use crate::units::{Amps, Celsius, Kelvin, Volts};
 
 /// Default configuration file location
 pub const DEFAULT_CONFIG_FILE: &str = "/etc/halpid/halpid.conf";
@@ -18,6 +21,27 @@ pub const DEFAULT_BLACKOUT_TIME_LIMIT: f64 = 5.0;
 /// Default blackout voltage limit in volts
 pub const DEFAULT_BLACKOUT_VOLTAGE_LIMIT: f64 = 9.0;
 
+/// Default margin above the blackout voltage limit for power to count as
+/// restored, in volts; none, as in the Python daemon
+pub const DEFAULT_BLACKOUT_VOLTAGE_HYSTERESIS: f64 = 0.0;
+
+/// Default delay until the system wakes up after a blackout standby, in seconds
+pub const DEFAULT_BLACKOUT_WAKE_AFTER: f64 = 3600.0;
+
+/// Default time a clean poweroff takes, in seconds
+pub const DEFAULT_SHUTDOWN_DURATION: f64 = 20.0;
+
+/// Default state machine polling interval in seconds
+pub const DEFAULT_POLL_INTERVAL: f64 = 0.1;
+
+/// Default hardware watchdog timeout in seconds
+pub const DEFAULT_WATCHDOG_TIMEOUT: f64 = 10.0;
+
+/// Minimum ratio of the watchdog timeout to the polling interval
+///
+/// Leaves room for slow or retried polls before the watchdog expires.
+pub const MIN_WATCHDOG_POLL_RATIO: f64 = 10.0;
+
 /// Default socket group name
 pub const DEFAULT_SOCKET_GROUP: &str = "adm";
 
@@ -44,6 +68,33 @@ pub struct Config {
     #[serde(default = "default_i2c_addr")]
     pub i2c_addr: u8,
 
+    /// I2C device node, e.g. a udev symlink for a USB-I2C bridge
+    ///
+    /// Takes precedence over `i2c_bus` when set; the daemon resolves it to
+    /// its bus number at startup.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub i2c_device: Option<PathBuf>,
+
+    /// SMBus packet error checking on I2C transfers
+    ///
+    /// Detects corrupted transfers, e.g. on long cables. Only used if the
+    /// controller firmware supports it.
+    #[serde(default)]
+    pub i2c_pec: bool,
+
+    /// USB port power set at startup
+    #[serde(default, skip_serializing_if = "UsbDefaultsConfig::is_empty")]
+    pub usb_defaults: UsbDefaultsConfig,
+
+    /// Additional controllers, e.g. an expansion power board
+    ///
+    /// The controller configured above remains the primary one: it runs the
+    /// power state machine and watchdog and is served at the top-level API
+    /// paths. Additional controllers are only served under
+    /// `/devices/{id}`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<DeviceConfig>,
+
     /// Blackout time limit in seconds
     ///
     /// Input voltage glitches shorter than this time will not trigger shutdown
@@ -57,6 +108,54 @@ pub struct Config {
     #[serde(default = "default_blackout_voltage_limit")]
     pub blackout_voltage_limit: f64,
 
+    /// Volts above the blackout voltage limit the input must rise to before
+    /// power counts as restored
+    ///
+    /// Keeps a supply hovering at the limit from flapping between blackout
+    /// and normal operation. Off by default.
+    #[serde(default = "default_blackout_voltage_hysteresis")]
+    pub blackout_voltage_hysteresis: f64,
+
+    /// What the daemon does when the blackout time limit is exceeded
+    #[serde(default)]
+    pub blackout_action: BlackoutAction,
+
+    /// Seconds until the controller wakes the system with `standby`
+    #[serde(default = "default_blackout_wake_after")]
+    pub blackout_wake_after: f64,
+
+    /// Shell command run by the `command` blackout action
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub blackout_command: String,
+
+    /// Start a blackout shutdown before the time limit once the estimated
+    /// supercap runtime drops below `shutdown_duration`
+    ///
+    /// Off by default, so that blackouts only end with the time limit as in
+    /// the Python daemon.
+    #[serde(default)]
+    pub runtime_shutdown: bool,
+
+    /// Seconds a clean poweroff takes, with `runtime_shutdown`
+    ///
+    /// 0 disables the early shutdown.
+    #[serde(default = "default_shutdown_duration")]
+    pub shutdown_duration: f64,
+
+    /// State machine polling interval in seconds
+    ///
+    /// Bounds how quickly a blackout is detected. Every poll reads the
+    /// controller, which also feeds its watchdog.
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: f64,
+
+    /// Hardware watchdog timeout in seconds
+    ///
+    /// The controller power-cycles the system if the daemon does not talk to
+    /// it for this long. Must be at least ten times the polling interval.
+    #[serde(default = "default_watchdog_timeout")]
+    pub watchdog_timeout: f64,
+
     /// Path to UNIX socket for daemon communication
     ///
     /// If None, auto-determined based on user privileges:
@@ -69,198 +168,3603 @@ pub struct Config {
     #[serde(default = "default_socket_group")]
     pub socket_group: String,
 
+    /// User to run as once the socket and devices are open
+    ///
+    /// If None, the daemon keeps the user it was started as.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+
+    /// Group to run as, instead of the primary group of `user`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+
     /// Command to execute for system poweroff
     #[serde(default = "default_poweroff_command")]
     pub poweroff: String,
+
+    /// Power off and hibernate through systemd-logind
+    ///
+    /// The daemon also holds a delay inhibitor lock while a blackout shutdown
+    /// is pending. `poweroff` is only run if logind cannot be reached or if
+    /// it is not the default command; set this to false to always run it.
+    #[serde(default = "default_true")]
+    pub logind: bool,
+
+    /// NMEA 2000 transmission settings
+    #[serde(default)]
+    pub nmea2000: Nmea2000Config,
+
+    /// InfluxDB export settings
+    #[serde(default)]
+    pub influxdb: InfluxDbConfig,
+
+    /// UPower-compatible D-Bus battery device settings
+    #[serde(default)]
+    pub upower: UpowerConfig,
+
+    /// Network UPS Tools (NUT) server settings
+    #[serde(default)]
+    pub nut: NutConfig,
+
+    /// SNMP AgentX subagent settings
+    #[serde(default)]
+    pub snmp: SnmpConfig,
+
+    /// Webhook notification settings
+    #[serde(default)]
+    pub webhooks: WebhooksConfig,
+
+    /// Scripts run on power events
+    #[serde(default)]
+    pub hooks: HooksConfig,
+
+    /// Warning and delay before the blackout action
+    #[serde(default)]
+    pub shutdown_grace: ShutdownGraceConfig,
+
+    /// Over-temperature protection
+    #[serde(default)]
+    pub temperature: TemperatureConfig,
+
+    /// Input overcurrent and current spike detection
+    #[serde(default)]
+    pub input_current: InputCurrentConfig,
+
+    /// Corrections of the primary controller's measured values
+    #[serde(default, skip_serializing_if = "CalibrationConfig::is_empty")]
+    pub calibration: CalibrationConfig,
+
+    /// Glitch rejection and smoothing of the measurements
+    #[serde(default, skip_serializing_if = "MeasurementFilterConfig::is_off")]
+    pub measurement_filter: MeasurementFilterConfig,
+
+    /// Input voltage sag and surge detection
+    #[serde(default)]
+    pub voltage_events: VoltageEventsConfig,
+
+    /// Controller settings checked for drift
+    #[serde(default)]
+    pub controller: ControllerConfig,
+
+    /// Controller checks when the daemon starts
+    #[serde(default)]
+    pub self_test: SelfTestConfig,
+
+    /// Linux watchdog device petted by the state machine
+    #[serde(default)]
+    pub kernel_watchdog: KernelWatchdogConfig,
+
+    /// Shutdown button or switch on a GPIO line
+    #[serde(default)]
+    pub gpio_button: GpioButtonConfig,
+
+    /// Dimming of the front LED at night
+    #[serde(default)]
+    pub led_night: LedNightConfig,
+
+    /// Probes of the software the system runs, with failure actions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub health_checks: Vec<HealthCheckConfig>,
+
+    /// Recurring windows in which the system is put in standby
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub power_schedule: Vec<PowerWindowConfig>,
+
+    /// Position of the system, for sunrise and sunset wakeups
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<LocationConfig>,
+
+    /// Log output settings
+    #[serde(default)]
+    pub logging: LoggingConfig,
+
+    /// Origins of browser clients allowed to call the API
+    #[serde(default)]
+    pub cors: CorsConfig,
+
+    /// Limits on API requests that access a controller
+    #[serde(default)]
+    pub api_limits: ApiLimitsConfig,
 }
 
-// Default value functions for serde
-fn default_i2c_bus() -> u8 {
-    DEFAULT_I2C_BUS
+/// Action taken when the blackout time limit is exceeded
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum BlackoutAction {
+    /// Request a shutdown from the controller and run `poweroff`
+    #[default]
+    Poweroff,
+    /// Set an RTC wake alarm, request standby and run `poweroff`
+    Standby,
+    /// Request a shutdown and run `blackout-command` instead of `poweroff`
+    Command,
+    /// Request a shutdown and hibernate the system
+    Hibernate,
 }
 
-fn default_i2c_addr() -> u8 {
-    DEFAULT_I2C_ADDR
+impl BlackoutAction {
+    /// Action name as used in configuration and the API
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlackoutAction::Poweroff => "poweroff",
+            BlackoutAction::Standby => "standby",
+            BlackoutAction::Command => "command",
+            BlackoutAction::Hibernate => "hibernate",
+        }
+    }
 }
 
-fn default_blackout_time_limit() -> f64 {
-    DEFAULT_BLACKOUT_TIME_LIMIT
+/// Device id of the primary controller in `/devices/{id}`
+pub const DEFAULT_DEVICE_ID: &str = "default";
+
+/// Additional HALPI2 controller
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DeviceConfig {
+    /// Identifier used in API paths (letters, digits, `-` and `_`)
+    pub id: String,
+
+    /// I2C bus number
+    #[serde(default = "default_i2c_bus")]
+    pub i2c_bus: u8,
+
+    /// I2C device address
+    #[serde(default = "default_i2c_addr")]
+    pub i2c_addr: u8,
+
+    /// I2C device node, taking precedence over `i2c_bus` when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub i2c_device: Option<PathBuf>,
+
+    /// USB port power set at startup
+    #[serde(default, skip_serializing_if = "UsbDefaultsConfig::is_empty")]
+    pub usb_defaults: UsbDefaultsConfig,
+
+    /// Corrections of the measured values
+    #[serde(default, skip_serializing_if = "CalibrationConfig::is_empty")]
+    pub calibration: CalibrationConfig,
 }
 
-fn default_blackout_voltage_limit() -> f64 {
-    DEFAULT_BLACKOUT_VOLTAGE_LIMIT
+/// USB port power set when the daemon connects to a controller
+///
+/// Ports not listed keep their state, e.g. to turn off a seldom-used
+/// accessory at boot while leaving the others as the firmware set them.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct UsbDefaultsConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usb0: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usb1: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usb2: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usb3: Option<bool>,
 }
 
-fn default_socket_group() -> String {
-    DEFAULT_SOCKET_GROUP.to_string()
+impl UsbDefaultsConfig {
+    /// Default state of ports 0-3, if set
+    pub fn ports(&self) -> [Option<bool>; 4] {
+        [self.usb0, self.usb1, self.usb2, self.usb3]
+    }
+
+    /// True if no port has a default
+    pub fn is_empty(&self) -> bool {
+        self.ports().iter().all(Option::is_none)
+    }
+
+    /// Apply the defaults to a USB port state bitfield
+    pub fn apply(&self, port_bits: u8) -> u8 {
+        self.ports().into_iter().enumerate().fold(
+            port_bits,
+            |bits, (port, enabled)| match enabled {
+                Some(true) => bits | (1 << port),
+                Some(false) => bits & !(1 << port),
+                None => bits,
+            },
+        )
+    }
 }
 
-fn default_poweroff_command() -> String {
-    DEFAULT_POWEROFF_COMMAND.to_string()
+/// Default SocketCAN interface for NMEA 2000
+pub const DEFAULT_N2K_INTERFACE: &str = "can0";
+
+/// Default preferred NMEA 2000 source address
+pub const DEFAULT_N2K_SOURCE_ADDRESS: u8 = 128;
+
+/// Default NMEA 2000 transmission interval in seconds
+///
+/// Matches the 1.5 s default rate of the DC/battery status PGNs.
+pub const DEFAULT_N2K_INTERVAL: f64 = 1.5;
+
+/// NMEA 2000 (SocketCAN) transmission configuration
+///
+/// When enabled, the daemon broadcasts Battery Status (PGN 127508) for the
+/// DC supply and the supercapacitor, and DC Detailed Status (PGN 127506)
+/// for the supercapacitor.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Nmea2000Config {
+    /// Enable NMEA 2000 transmission
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// SocketCAN interface name (e.g., `can0`)
+    #[serde(default = "default_n2k_interface")]
+    pub interface: String,
+
+    /// Preferred source address, claimed at startup
+    #[serde(default = "default_n2k_source_address")]
+    pub source_address: u8,
+
+    /// Battery instance used for the DC input supply
+    #[serde(default)]
+    pub supply_instance: u8,
+
+    /// Battery/DC instance used for the supercapacitor
+    #[serde(default = "default_n2k_supercap_instance")]
+    pub supercap_instance: u8,
+
+    /// Transmission interval in seconds
+    #[serde(default = "default_n2k_interval")]
+    pub interval: f64,
 }
 
-impl Default for Config {
+fn default_n2k_interface() -> String {
+    DEFAULT_N2K_INTERFACE.to_string()
+}
+
+fn default_n2k_source_address() -> u8 {
+    DEFAULT_N2K_SOURCE_ADDRESS
+}
+
+fn default_n2k_supercap_instance() -> u8 {
+    1
+}
+
+fn default_n2k_interval() -> f64 {
+    DEFAULT_N2K_INTERVAL
+}
+
+impl Default for Nmea2000Config {
     fn default() -> Self {
         Self {
-            i2c_bus: DEFAULT_I2C_BUS,
-            i2c_addr: DEFAULT_I2C_ADDR,
-            blackout_time_limit: DEFAULT_BLACKOUT_TIME_LIMIT,
-            blackout_voltage_limit: DEFAULT_BLACKOUT_VOLTAGE_LIMIT,
-            socket: None,
-            socket_group: DEFAULT_SOCKET_GROUP.to_string(),
-            poweroff: DEFAULT_POWEROFF_COMMAND.to_string(),
+            enabled: false,
+            interface: DEFAULT_N2K_INTERFACE.to_string(),
+            source_address: DEFAULT_N2K_SOURCE_ADDRESS,
+            supply_instance: 0,
+            supercap_instance: default_n2k_supercap_instance(),
+            interval: DEFAULT_N2K_INTERVAL,
         }
     }
 }
 
-impl Config {
-    /// Load configuration from a YAML file
-    ///
-    /// Returns `Ok(Config)` if the file exists and is valid YAML.
-    /// Returns an error if the file exists but cannot be read or parsed.
-    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self, ConfigError> {
-        let path = path.as_ref();
-        let contents =
-            std::fs::read_to_string(path).map_err(|e| ConfigError::FileRead(path.into(), e))?;
+/// Default InfluxDB measurement name
+pub const DEFAULT_INFLUX_MEASUREMENT: &str = "halpi";
 
-        serde_yaml::from_str(&contents)
-            .map_err(|e| ConfigError::YamlParse(path.into(), e.to_string()))
-    }
+/// Default InfluxDB write interval in seconds
+pub const DEFAULT_INFLUX_INTERVAL: f64 = 10.0;
 
-    /// Load configuration from a file if it exists, otherwise return defaults
-    ///
-    /// This is useful for the default config file location where a missing file is not an error.
-    pub fn from_file_or_default(path: impl AsRef<std::path::Path>) -> Result<Self, ConfigError> {
-        let path = path.as_ref();
-        if path.exists() {
-            Self::from_file(path)
-        } else {
-            Ok(Self::default())
-        }
-    }
+/// Default number of points buffered while InfluxDB is unreachable
+pub const DEFAULT_INFLUX_BUFFER_SIZE: usize = 8640;
 
-    /// Validate configuration values
-    ///
-    /// Returns an error if any values are out of acceptable ranges
-    pub fn validate(&self) -> Result<(), ConfigError> {
-        // Validate I2C bus (0-255, but realistically 0-10 on RPi)
-        if self.i2c_bus > 10 {
-            return Err(ConfigError::InvalidValue(format!(
-                "i2c-bus {} is unusually high (expected 0-10)",
-                self.i2c_bus
-            )));
-        }
+/// InfluxDB line-protocol export configuration
+///
+/// Both InfluxDB v1 and v2 are supported. If `org` is set, the v2 write API
+/// (`/api/v2/write`) is used and `bucket` names the bucket; otherwise the v1
+/// API (`/write`) is used and `bucket` names the database.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct InfluxDbConfig {
+    /// Enable InfluxDB export
+    #[serde(default)]
+    pub enabled: bool,
 
-        // Validate blackout time limit (must be positive, reasonable upper bound)
-        if self.blackout_time_limit <= 0.0 {
-            return Err(ConfigError::InvalidValue(
-                "blackout_time_limit must be positive".to_string(),
-            ));
-        }
-        if self.blackout_time_limit > 3600.0 {
-            return Err(ConfigError::InvalidValue(
-                "blackout_time_limit must be <= 3600 seconds (1 hour)".to_string(),
-            ));
-        }
+    /// Base URL of the InfluxDB server (e.g., `http://localhost:8086`)
+    #[serde(default)]
+    pub url: String,
 
-        // Validate blackout voltage limit (typical range: 5-15V)
-        if self.blackout_voltage_limit < 5.0 || self.blackout_voltage_limit > 15.0 {
-            return Err(ConfigError::InvalidValue(format!(
-                "blackout-voltage-limit {} is out of range (expected 5.0-15.0 volts)",
-                self.blackout_voltage_limit
-            )));
-        }
+    /// API token, sent as `Authorization: Token <token>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
 
-        Ok(())
-    }
+    /// Organization (v2 only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org: Option<String>,
+
+    /// Bucket (v2) or database (v1) name
+    #[serde(default)]
+    pub bucket: String,
+
+    /// Measurement name used for the written points
+    #[serde(default = "default_influx_measurement")]
+    pub measurement: String,
 
-    /// Merge another Config into this one, overriding fields that are explicitly set
+    /// Write interval in seconds
+    #[serde(default = "default_influx_interval")]
+    pub interval: f64,
+
+    /// Maximum number of points kept in memory while the server is unreachable
     ///
-    /// This is used to implement the precedence: CLI > file > defaults
-    pub fn merge(&mut self, other: Config) {
-        self.i2c_bus = other.i2c_bus;
-        self.i2c_addr = other.i2c_addr;
-        self.blackout_time_limit = other.blackout_time_limit;
-        self.blackout_voltage_limit = other.blackout_voltage_limit;
+    /// When the buffer is full, the oldest points are dropped.
+    #[serde(default = "default_influx_buffer_size")]
+    pub buffer_size: usize,
+}
 
-        // Only override if explicitly set in other
-        if other.socket.is_some() {
-            self.socket = other.socket;
-        }
+fn default_influx_measurement() -> String {
+    DEFAULT_INFLUX_MEASUREMENT.to_string()
+}
 
-        if other.socket_group != DEFAULT_SOCKET_GROUP {
-            self.socket_group = other.socket_group;
-        }
+fn default_influx_interval() -> f64 {
+    DEFAULT_INFLUX_INTERVAL
+}
+
+fn default_influx_buffer_size() -> usize {
+    DEFAULT_INFLUX_BUFFER_SIZE
+}
 
-        if other.poweroff != DEFAULT_POWEROFF_COMMAND {
-            self.poweroff = other.poweroff;
+impl Default for InfluxDbConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: String::new(),
+            token: None,
+            org: None,
+            bucket: String::new(),
+            measurement: DEFAULT_INFLUX_MEASUREMENT.to_string(),
+            interval: DEFAULT_INFLUX_INTERVAL,
+            buffer_size: DEFAULT_INFLUX_BUFFER_SIZE,
         }
     }
 }
 
-/// Configuration loading errors
-#[derive(Debug, thiserror::Error)]
-pub enum ConfigError {
-    #[error("Failed to read config file {0}: {1}")]
-    FileRead(PathBuf, #[source] std::io::Error),
+/// Default UPower property update interval in seconds
+pub const DEFAULT_UPOWER_INTERVAL: f64 = 2.0;
 
-    #[error("Failed to parse YAML config file {0}: {1}")]
-    YamlParse(PathBuf, String),
+/// UPower-compatible D-Bus battery device configuration
+///
+/// When enabled, the daemon owns `fi.hatlabs.halpid` on the system bus and
+/// exports the supercapacitor as an `org.freedesktop.UPower.Device`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct UpowerConfig {
+    /// Enable the D-Bus battery device
+    #[serde(default)]
+    pub enabled: bool,
 
-    #[error("Invalid configuration value: {0}")]
-    InvalidValue(String),
+    /// Property update interval in seconds
+    #[serde(default = "default_upower_interval")]
+    pub interval: f64,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+fn default_upower_interval() -> f64 {
+    DEFAULT_UPOWER_INTERVAL
+}
 
-    #[test]
-    fn test_default_config() {
-        let config = Config::default();
-        assert_eq!(config.i2c_bus, 1);
-        assert_eq!(config.i2c_addr, 0x6D);
-        assert_eq!(config.blackout_time_limit, 5.0);
-        assert_eq!(config.blackout_voltage_limit, 9.0);
-        assert_eq!(config.socket, None);
-        assert_eq!(config.socket_group, "adm");
-        assert_eq!(config.poweroff, "/sbin/poweroff");
+impl Default for UpowerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: DEFAULT_UPOWER_INTERVAL,
+        }
     }
+}
 
-    #[test]
-    fn test_validate_valid_config() {
-        let config = Config::default();
-        assert!(config.validate().is_ok());
-    }
+/// Default NUT listen address (the standard upsd port, loopback only)
+pub const DEFAULT_NUT_LISTEN: &str = "127.0.0.1:3493";
 
-    #[test]
-    fn test_validate_invalid_blackout_time() {
-        let config = Config {
-            blackout_time_limit: -1.0,
-            ..Default::default()
-        };
-        assert!(config.validate().is_err());
+/// Default UPS name announced to NUT clients
+pub const DEFAULT_NUT_UPS_NAME: &str = "halpi";
 
-        let config = Config {
-            blackout_time_limit: 5000.0,
-            ..Default::default()
-        };
-        assert!(config.validate().is_err());
-    }
+/// Default supercap charge (percent) below which the UPS reports low battery
+pub const DEFAULT_NUT_LOW_CHARGE: u8 = 20;
 
-    #[test]
-    fn test_validate_invalid_blackout_voltage() {
-        let config = Config {
-            blackout_voltage_limit: 3.0,
-            ..Default::default()
-        };
-        assert!(config.validate().is_err());
+/// Network UPS Tools (NUT) compatible server configuration
+///
+/// When enabled, the daemon speaks the `upsd` network protocol so that
+/// `upsmon` and other NUT clients can monitor the HALPI2 like any UPS.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct NutConfig {
+    /// Enable the NUT server
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// TCP address to listen on; use `0.0.0.0:3493` to serve remote clients
+    #[serde(default = "default_nut_listen")]
+    pub listen: String,
+
+    /// UPS name announced to clients (`<ups-name>@<host>` in upsmon.conf)
+    #[serde(default = "default_nut_ups_name")]
+    pub ups_name: String,
+
+    /// Username required for LOGIN and PRIMARY; if unset, any client may log in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+
+    /// Password for `username`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+
+    /// Charge percentage below which `LB` (low battery) is reported during a blackout
+    #[serde(default = "default_nut_low_charge")]
+    pub low_charge: u8,
+}
+
+fn default_nut_listen() -> String {
+    DEFAULT_NUT_LISTEN.to_string()
+}
+
+fn default_nut_ups_name() -> String {
+    DEFAULT_NUT_UPS_NAME.to_string()
+}
+
+fn default_nut_low_charge() -> u8 {
+    DEFAULT_NUT_LOW_CHARGE
+}
+
+impl Default for NutConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: DEFAULT_NUT_LISTEN.to_string(),
+            ups_name: DEFAULT_NUT_UPS_NAME.to_string(),
+            username: None,
+            password: None,
+            low_charge: DEFAULT_NUT_LOW_CHARGE,
+        }
+    }
+}
+
+/// Default AgentX master socket (net-snmp default)
+pub const DEFAULT_SNMP_MASTER: &str = "/var/agentx/master";
+
+/// SNMP AgentX subagent configuration
+///
+/// When enabled, the daemon registers a read-only subtree with the local
+/// SNMP master agent. `base-oid` must be set to an OID under an enterprise
+/// number the operator is entitled to use.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SnmpConfig {
+    /// Enable the AgentX subagent
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Master agent address: a Unix socket path or `tcp:host:port`
+    #[serde(default = "default_snmp_master")]
+    pub master: String,
+
+    /// Root of the registered subtree (e.g., `1.3.6.1.4.1.<enterprise>.1`)
+    #[serde(default)]
+    pub base_oid: String,
+}
+
+fn default_snmp_master() -> String {
+    DEFAULT_SNMP_MASTER.to_string()
+}
+
+impl Default for SnmpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            master: DEFAULT_SNMP_MASTER.to_string(),
+            base_oid: String::new(),
+        }
+    }
+}
+
+/// Default temperature alarm limit in degrees Celsius
+pub const DEFAULT_WEBHOOK_TEMPERATURE_LIMIT: f64 = 70.0;
+
+/// Default number of delivery retries per webhook
+pub const DEFAULT_WEBHOOK_RETRIES: u32 = 5;
+
+/// Default initial delay between delivery retries in seconds
+pub const DEFAULT_WEBHOOK_RETRY_DELAY: f64 = 2.0;
+
+/// Event kinds that can trigger a webhook
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WebhookEvent {
+    /// Input power was lost and the device is running from the supercap
+    BlackoutStart,
+    /// Input power was restored
+    BlackoutEnd,
+    /// The controller entered a shutdown state
+    Shutdown,
+    /// MCU or PCB temperature exceeded the configured limit
+    TemperatureAlarm,
+    /// The controller came back with a different firmware version
+    FirmwareUpdate,
+}
+
+impl WebhookEvent {
+    /// Event name as used in configuration and payloads
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::BlackoutStart => "blackout-start",
+            WebhookEvent::BlackoutEnd => "blackout-end",
+            WebhookEvent::Shutdown => "shutdown",
+            WebhookEvent::TemperatureAlarm => "temperature-alarm",
+            WebhookEvent::FirmwareUpdate => "firmware-update",
+        }
+    }
+}
+
+/// A single webhook target
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct WebhookEndpoint {
+    /// URL that receives the JSON payload via POST
+    pub url: String,
+
+    /// Events delivered to this endpoint (all events if empty)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<WebhookEvent>,
+}
+
+impl WebhookEndpoint {
+    /// True if this endpoint subscribes to `event`
+    pub fn wants(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+/// Webhook notification configuration
+///
+/// When enabled, the daemon POSTs a JSON payload to each endpoint when a
+/// subscribed event occurs. Failed deliveries are retried with exponential
+/// backoff.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct WebhooksConfig {
+    /// Enable webhook notifications
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Webhook targets
+    #[serde(default)]
+    pub endpoints: Vec<WebhookEndpoint>,
+
+    /// MCU/PCB temperature that raises a temperature alarm (°C)
+    #[serde(default = "default_webhook_temperature_limit")]
+    pub temperature_limit: f64,
+
+    /// Number of retries after a failed delivery
+    #[serde(default = "default_webhook_retries")]
+    pub retries: u32,
+
+    /// Delay before the first retry in seconds; doubled on each attempt
+    #[serde(default = "default_webhook_retry_delay")]
+    pub retry_delay: f64,
+}
+
+fn default_webhook_temperature_limit() -> f64 {
+    DEFAULT_WEBHOOK_TEMPERATURE_LIMIT
+}
+
+fn default_webhook_retries() -> u32 {
+    DEFAULT_WEBHOOK_RETRIES
+}
+
+fn default_webhook_retry_delay() -> f64 {
+    DEFAULT_WEBHOOK_RETRY_DELAY
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoints: Vec::new(),
+            temperature_limit: DEFAULT_WEBHOOK_TEMPERATURE_LIMIT,
+            retries: DEFAULT_WEBHOOK_RETRIES,
+            retry_delay: DEFAULT_WEBHOOK_RETRY_DELAY,
+        }
+    }
+}
+
+/// Default time a hook may run before it is killed, in seconds
+pub const DEFAULT_HOOK_TIMEOUT: f64 = 10.0;
+
+/// Events that can run a hook
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookEvent {
+    /// Input voltage dropped below the blackout limit
+    BlackoutStart,
+    /// Input voltage recovered before the blackout time limit
+    PowerRestored,
+    /// The blackout time limit was exceeded; runs before the poweroff command
+    PreShutdown,
+    /// The controller started entering standby
+    StandbyEnter,
+    /// The controller considers the host unresponsive
+    HostUnresponsive,
+}
+
+impl HookEvent {
+    /// All hook events
+    pub const ALL: [HookEvent; 5] = [
+        HookEvent::BlackoutStart,
+        HookEvent::PowerRestored,
+        HookEvent::PreShutdown,
+        HookEvent::StandbyEnter,
+        HookEvent::HostUnresponsive,
+    ];
+
+    /// Event name as used in configuration and the hook environment
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::BlackoutStart => "blackout-start",
+            HookEvent::PowerRestored => "power-restored",
+            HookEvent::PreShutdown => "pre-shutdown",
+            HookEvent::StandbyEnter => "standby-enter",
+            HookEvent::HostUnresponsive => "host-unresponsive",
+        }
+    }
+}
+
+/// Hook script configuration
+///
+/// Each event can run an executable, e.g. to stop chartplotter software or
+/// sync logs before power is cut. Hooks run without blocking the state
+/// machine and are killed after `timeout`. The poweroff command waits for
+/// the `pre-shutdown` hook to finish.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct HooksConfig {
+    /// Run when a blackout is detected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blackout_start: Option<PathBuf>,
+
+    /// Run when input power returns during a blackout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub power_restored: Option<PathBuf>,
+
+    /// Run before the poweroff command
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_shutdown: Option<PathBuf>,
+
+    /// Run when the controller starts entering standby
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub standby_enter: Option<PathBuf>,
+
+    /// Run when the controller reports the host as unresponsive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_unresponsive: Option<PathBuf>,
+
+    /// Time a hook may run before it is killed, in seconds
+    #[serde(default = "default_hook_timeout")]
+    pub timeout: f64,
+}
+
+impl HooksConfig {
+    /// Executable configured for `event`
+    pub fn path(&self, event: HookEvent) -> Option<&std::path::Path> {
+        match event {
+            HookEvent::BlackoutStart => self.blackout_start.as_deref(),
+            HookEvent::PowerRestored => self.power_restored.as_deref(),
+            HookEvent::PreShutdown => self.pre_shutdown.as_deref(),
+            HookEvent::StandbyEnter => self.standby_enter.as_deref(),
+            HookEvent::HostUnresponsive => self.host_unresponsive.as_deref(),
+        }
+    }
+}
+
+fn default_hook_timeout() -> f64 {
+    DEFAULT_HOOK_TIMEOUT
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            blackout_start: None,
+            power_restored: None,
+            pre_shutdown: None,
+            standby_enter: None,
+            host_unresponsive: None,
+            timeout: DEFAULT_HOOK_TIMEOUT,
+        }
+    }
+}
+
+/// Default supercap voltage margin that ends the grace period, in volts
+pub const DEFAULT_GRACE_SUPERCAP_MARGIN: f64 = 1.0;
+
+/// Default time in which returning power cancels a shutdown, in seconds
+pub const DEFAULT_GRACE_ABORT_WINDOW: f64 = 2.0;
+
+/// Grace period before the blackout action
+///
+/// Gives logged-in users and services time to react before power is cut.
+/// The grace period ends early when the supercap voltage comes within
+/// `supercap_margin` of the controller's power-off threshold, so the
+/// remaining charge still covers a clean shutdown.
+///
+/// If input power returns before the blackout action is carried out, the
+/// shutdown is cancelled. The action waits at least `abort_window` for that.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ShutdownGraceConfig {
+    /// Delay before the blackout action in seconds (0 disables it)
+    #[serde(default)]
+    pub period: f64,
+
+    /// Broadcast a `wall` message to logged-in users when it starts
+    #[serde(default = "default_true")]
+    pub wall: bool,
+
+    /// Supercap voltage above the power-off threshold at which the grace
+    /// period ends early, in volts
+    #[serde(default = "default_grace_supercap_margin")]
+    pub supercap_margin: f64,
+
+    /// Shortest wait before the blackout action in which returning power
+    /// cancels the shutdown, in seconds
+    #[serde(default = "default_grace_abort_window")]
+    pub abort_window: f64,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_grace_supercap_margin() -> f64 {
+    DEFAULT_GRACE_SUPERCAP_MARGIN
+}
+
+fn default_grace_abort_window() -> f64 {
+    DEFAULT_GRACE_ABORT_WINDOW
+}
+
+impl Default for ShutdownGraceConfig {
+    fn default() -> Self {
+        Self {
+            period: 0.0,
+            wall: true,
+            supercap_margin: DEFAULT_GRACE_SUPERCAP_MARGIN,
+            abort_window: DEFAULT_GRACE_ABORT_WINDOW,
+        }
+    }
+}
+
+/// Default temperature that raises a warning, in °C
+pub const DEFAULT_TEMPERATURE_WARNING: f64 = 70.0;
+
+/// Default temperature at which USB ports are shed, in °C
+pub const DEFAULT_TEMPERATURE_SHED: f64 = 80.0;
+
+/// Default temperature that shuts the system down, in °C
+pub const DEFAULT_TEMPERATURE_SHUTDOWN: f64 = 85.0;
+
+/// Default drop below a limit before it counts as cleared, in °C
+pub const DEFAULT_TEMPERATURE_HYSTERESIS: f64 = 5.0;
+
+/// Over-temperature protection
+///
+/// Applies to the higher of the MCU and PCB temperatures. Above `warning`
+/// the daemon raises an alert, above `shed` it turns off `shed-usb-ports`,
+/// and above `shutdown` it shuts the system down. A limit counts as cleared
+/// once the temperature is `hysteresis` below it; shed ports are then
+/// turned back on.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TemperatureConfig {
+    /// Enable over-temperature protection
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Warning limit in °C
+    #[serde(default = "default_temperature_warning")]
+    pub warning: f64,
+
+    /// USB port shedding limit in °C
+    #[serde(default = "default_temperature_shed")]
+    pub shed: f64,
+
+    /// USB ports (0-3) turned off above `shed`; none by default
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shed_usb_ports: Vec<u8>,
+
+    /// Protective shutdown limit in °C
+    #[serde(default = "default_temperature_shutdown")]
+    pub shutdown: f64,
+
+    /// Hysteresis in °C
+    #[serde(default = "default_temperature_hysteresis")]
+    pub hysteresis: f64,
+}
+
+fn default_temperature_warning() -> f64 {
+    DEFAULT_TEMPERATURE_WARNING
+}
+
+fn default_temperature_shed() -> f64 {
+    DEFAULT_TEMPERATURE_SHED
+}
+
+fn default_temperature_shutdown() -> f64 {
+    DEFAULT_TEMPERATURE_SHUTDOWN
+}
+
+fn default_temperature_hysteresis() -> f64 {
+    DEFAULT_TEMPERATURE_HYSTERESIS
+}
+
+impl Default for TemperatureConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            warning: DEFAULT_TEMPERATURE_WARNING,
+            shed: DEFAULT_TEMPERATURE_SHED,
+            shed_usb_ports: Vec::new(),
+            shutdown: DEFAULT_TEMPERATURE_SHUTDOWN,
+            hysteresis: DEFAULT_TEMPERATURE_HYSTERESIS,
+        }
+    }
+}
+
+impl TemperatureConfig {
+    /// Bitmask of `shed-usb-ports` as used by the USB port register
+    pub fn shed_mask(&self) -> u8 {
+        self.shed_usb_ports
+            .iter()
+            .fold(0, |mask, port| mask | (1 << port))
+    }
+}
+
+/// Default sustained input current that counts as overcurrent, in amperes
+pub const DEFAULT_INPUT_CURRENT_LIMIT: f64 = 4.0;
+
+/// Default time the input current must stay above or below the limit, in seconds
+pub const DEFAULT_INPUT_CURRENT_SUSTAIN: f64 = 5.0;
+
+/// Default rise above the average input current that counts as a spike, in amperes
+pub const DEFAULT_INPUT_CURRENT_SPIKE: f64 = 2.0;
+
+/// Input overcurrent and current spike detection
+///
+/// The input current is in overcurrent once it has stayed above `limit`
+/// for `sustain` seconds, and back to normal once it has stayed below it
+/// as long. Overcurrent raises an alert and turns off `shed-usb-ports`
+/// until the current is normal again. A reading `spike` above the running
+/// average raises a spike alert; 0 disables spike detection.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct InputCurrentConfig {
+    /// Enable input current monitoring
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Overcurrent limit in A
+    #[serde(default = "default_input_current_limit")]
+    pub limit: f64,
+
+    /// Seconds the limit must be exceeded, or cleared, before it counts
+    #[serde(default = "default_input_current_sustain")]
+    pub sustain: f64,
+
+    /// Spike threshold above the average current in A (0 = disabled)
+    #[serde(default = "default_input_current_spike")]
+    pub spike: f64,
+
+    /// USB ports (0-3) turned off during overcurrent; none by default
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shed_usb_ports: Vec<u8>,
+}
+
+fn default_input_current_limit() -> f64 {
+    DEFAULT_INPUT_CURRENT_LIMIT
+}
+
+fn default_input_current_sustain() -> f64 {
+    DEFAULT_INPUT_CURRENT_SUSTAIN
+}
+
+fn default_input_current_spike() -> f64 {
+    DEFAULT_INPUT_CURRENT_SPIKE
+}
+
+impl Default for InputCurrentConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            limit: DEFAULT_INPUT_CURRENT_LIMIT,
+            sustain: DEFAULT_INPUT_CURRENT_SUSTAIN,
+            spike: DEFAULT_INPUT_CURRENT_SPIKE,
+            shed_usb_ports: Vec::new(),
+        }
+    }
+}
+
+impl InputCurrentConfig {
+    /// Bitmask of `shed-usb-ports` as used by the USB port register
+    pub fn shed_mask(&self) -> u8 {
+        self.shed_usb_ports
+            .iter()
+            .fold(0, |mask, port| mask | (1 << port))
+    }
+}
+
+/// Correction of one measured channel: `raw * gain + offset`
+///
+/// Temperatures are corrected in degrees Celsius, so the offset is in °C
+/// (or K) and the gain scales around 0 °C.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelCalibration {
+    /// Added after the gain, in the unit of the channel
+    #[serde(default)]
+    pub offset: f64,
+
+    /// Factor applied to the raw reading
+    #[serde(default = "default_calibration_gain")]
+    pub gain: f64,
+}
+
+fn default_calibration_gain() -> f64 {
+    1.0
+}
+
+impl Default for ChannelCalibration {
+    fn default() -> Self {
+        Self {
+            offset: 0.0,
+            gain: 1.0,
+        }
+    }
+}
+
+impl ChannelCalibration {
+    /// True if the correction leaves readings unchanged
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Correct a raw reading
+    pub fn apply(&self, raw: f32) -> f32 {
+        (raw as f64 * self.gain + self.offset) as f32
+    }
+
+    /// Correct a raw temperature reading
+    pub fn apply_temperature(&self, raw: Kelvin) -> Kelvin {
+        Celsius(self.apply(raw.celsius().0)).kelvin()
+    }
+}
+
+/// Corrections of the measured values against a reference meter
+///
+/// Applied by the daemon to every reading after decoding, so the state
+/// machine, the API and the exporters all see the corrected values. Channels
+/// not listed are used as read.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CalibrationConfig {
+    /// DC input voltage (`V_in`)
+    #[serde(default, skip_serializing_if = "ChannelCalibration::is_identity")]
+    pub v_in: ChannelCalibration,
+
+    /// Supercapacitor voltage (`V_cap`)
+    #[serde(default, skip_serializing_if = "ChannelCalibration::is_identity")]
+    pub v_supercap: ChannelCalibration,
+
+    /// Input current (`I_in`)
+    #[serde(default, skip_serializing_if = "ChannelCalibration::is_identity")]
+    pub i_in: ChannelCalibration,
+
+    /// MCU temperature (`T_mcu`)
+    #[serde(default, skip_serializing_if = "ChannelCalibration::is_identity")]
+    pub t_mcu: ChannelCalibration,
+
+    /// PCB temperature (`T_pcb`)
+    #[serde(default, skip_serializing_if = "ChannelCalibration::is_identity")]
+    pub t_pcb: ChannelCalibration,
+}
+
+impl CalibrationConfig {
+    /// True if no channel is corrected
+    pub fn is_empty(&self) -> bool {
+        self.channels()
+            .iter()
+            .all(|(_, channel)| channel.is_identity())
+    }
+
+    /// Channels by their `GET /values` key
+    pub fn channels(&self) -> [(&'static str, &ChannelCalibration); 5] {
+        [
+            ("V_in", &self.v_in),
+            ("V_cap", &self.v_supercap),
+            ("I_in", &self.i_in),
+            ("T_mcu", &self.t_mcu),
+            ("T_pcb", &self.t_pcb),
+        ]
+    }
+
+    /// Keys of the values corrected by this calibration
+    pub fn calibrated_keys(&self) -> Vec<String> {
+        self.channels()
+            .into_iter()
+            .filter(|(_, channel)| !channel.is_identity())
+            .map(|(key, _)| key.to_string())
+            .collect()
+    }
+
+    /// Correct freshly decoded measurements
+    pub fn apply(&self, measurements: &mut Measurements) {
+        measurements.dcin_voltage = Volts(self.v_in.apply(measurements.dcin_voltage.0));
+        measurements.supercap_voltage =
+            Volts(self.v_supercap.apply(measurements.supercap_voltage.0));
+        measurements.input_current = Amps(self.i_in.apply(measurements.input_current.0));
+        measurements.mcu_temperature = self.t_mcu.apply_temperature(measurements.mcu_temperature);
+        measurements.pcb_temperature = self.t_pcb.apply_temperature(measurements.pcb_temperature);
+    }
+
+    fn validate(&self, section: &str) -> Result<(), ConfigError> {
+        for (key, channel) in self.channels() {
+            let name = match key {
+                "V_in" => "v-in",
+                "V_cap" => "v-supercap",
+                "I_in" => "i-in",
+                "T_mcu" => "t-mcu",
+                _ => "t-pcb",
+            };
+            if !(0.5..=1.5).contains(&channel.gain) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "{}.{}.gain {} is out of range (expected 0.5-1.5)",
+                    section, name, channel.gain
+                )));
+            }
+            if !(-10.0..=10.0).contains(&channel.offset) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "{}.{}.offset {} is out of range (expected -10 to 10)",
+                    section, name, channel.offset
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Filtering of one measured channel
+///
+/// With `reject-glitches`, each reading is replaced by the median of it and
+/// the two before, so a single-sample glitch is dropped and a real step is
+/// followed one poll later. `smoothing` is the weight of the running average
+/// in an exponential moving average of the readings; 0 disables it.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ChannelFilter {
+    /// Drop single-sample outliers
+    #[serde(default)]
+    pub reject_glitches: bool,
+
+    /// Exponential smoothing factor (0 = off, below 1)
+    #[serde(default)]
+    pub smoothing: f64,
+}
+
+impl ChannelFilter {
+    /// True if readings pass unfiltered
+    pub fn is_off(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Filtering of the measurements the state machine acts on
+///
+/// Applied after calibration and before blackout, temperature and current
+/// evaluation, so that an ADC glitch cannot start a blackout countdown. The
+/// measurement events carry the filtered values; `GET /values` reads the
+/// controller directly. Channels not listed are not filtered.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct MeasurementFilterConfig {
+    /// DC input voltage (`V_in`)
+    #[serde(default, skip_serializing_if = "ChannelFilter::is_off")]
+    pub v_in: ChannelFilter,
+
+    /// Supercapacitor voltage (`V_cap`)
+    #[serde(default, skip_serializing_if = "ChannelFilter::is_off")]
+    pub v_supercap: ChannelFilter,
+
+    /// Input current (`I_in`)
+    #[serde(default, skip_serializing_if = "ChannelFilter::is_off")]
+    pub i_in: ChannelFilter,
+
+    /// MCU temperature (`T_mcu`)
+    #[serde(default, skip_serializing_if = "ChannelFilter::is_off")]
+    pub t_mcu: ChannelFilter,
+
+    /// PCB temperature (`T_pcb`)
+    #[serde(default, skip_serializing_if = "ChannelFilter::is_off")]
+    pub t_pcb: ChannelFilter,
+}
+
+impl MeasurementFilterConfig {
+    /// True if no channel is filtered
+    pub fn is_off(&self) -> bool {
+        self.channels().iter().all(|(_, channel)| channel.is_off())
+    }
+
+    /// Channels by their name in the configuration
+    pub fn channels(&self) -> [(&'static str, &ChannelFilter); 5] {
+        [
+            ("v-in", &self.v_in),
+            ("v-supercap", &self.v_supercap),
+            ("i-in", &self.i_in),
+            ("t-mcu", &self.t_mcu),
+            ("t-pcb", &self.t_pcb),
+        ]
+    }
+}
+
+/// Default input voltage below which a sag is recorded, in volts
+pub const DEFAULT_VOLTAGE_SAG: f64 = 11.0;
+
+/// Default input voltage above which a surge is recorded, in volts
+pub const DEFAULT_VOLTAGE_SURGE: f64 = 15.0;
+
+/// Sag and surge detection on the input voltage
+///
+/// While V_in is below `sag` or above `surge`, its lowest or highest value
+/// is tracked; once it is back in range, an event with the depth beyond
+/// the limit and the duration is published to the event history, unless it
+/// lasted less than `min-duration` seconds. Useful to diagnose failing
+/// alternator regulators and marginal supplies.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct VoltageEventsConfig {
+    /// Enable sag and surge detection
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Sag limit in V
+    #[serde(default = "default_voltage_sag")]
+    pub sag: f64,
+
+    /// Surge limit in V
+    #[serde(default = "default_voltage_surge")]
+    pub surge: f64,
+
+    /// Seconds an excursion must last to be recorded
+    #[serde(default)]
+    pub min_duration: f64,
+}
+
+fn default_voltage_sag() -> f64 {
+    DEFAULT_VOLTAGE_SAG
+}
+
+fn default_voltage_surge() -> f64 {
+    DEFAULT_VOLTAGE_SURGE
+}
+
+impl Default for VoltageEventsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sag: DEFAULT_VOLTAGE_SAG,
+            surge: DEFAULT_VOLTAGE_SURGE,
+            min_duration: 0.0,
+        }
+    }
+}
+
+/// Default seconds between checks of the controller settings
+pub const DEFAULT_CONTROLLER_CHECK_INTERVAL: f64 = 60.0;
+
+/// Settings stored in the controller, as declared for the daemon to keep
+///
+/// Keys are those of `GET /config`. Every `check-interval` seconds the
+/// daemon reads the declared settings back and reports those that differ in
+/// `GET /health` and `GET /config/diff`; with `enforce`, it writes the
+/// declared values back, e.g. after the controller reset itself. Settings
+/// not listed are left alone.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ControllerConfig {
+    /// Supercapacitor voltage to power on at (V)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub power_on_threshold: Option<f64>,
+
+    /// Supercapacitor voltage to power off at on supercap alone (V)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub solo_power_off_threshold: Option<f64>,
+
+    /// LED brightness (0-255)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub led_brightness: Option<u8>,
+
+    /// Power the system on again once power returns
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_restart: Option<bool>,
+
+    /// Time to run on supercap alone before powering off (seconds)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub solo_depleting_timeout: Option<f64>,
+
+    /// Seconds between checks
+    #[serde(default = "default_controller_check_interval")]
+    pub check_interval: f64,
+
+    /// Write drifted settings back
+    #[serde(default)]
+    pub enforce: bool,
+}
+
+fn default_controller_check_interval() -> f64 {
+    DEFAULT_CONTROLLER_CHECK_INTERVAL
+}
+
+impl Default for ControllerConfig {
+    fn default() -> Self {
+        Self {
+            power_on_threshold: None,
+            solo_power_off_threshold: None,
+            led_brightness: None,
+            auto_restart: None,
+            solo_depleting_timeout: None,
+            check_interval: DEFAULT_CONTROLLER_CHECK_INTERVAL,
+            enforce: false,
+        }
+    }
+}
+
+impl ControllerConfig {
+    /// True if no setting is declared
+    pub fn is_empty(&self) -> bool {
+        self.power_on_threshold.is_none()
+            && self.solo_power_off_threshold.is_none()
+            && self.led_brightness.is_none()
+            && self.auto_restart.is_none()
+            && self.solo_depleting_timeout.is_none()
+    }
+}
+
+/// Default oldest supported controller firmware
+pub const DEFAULT_SELF_TEST_MIN_FIRMWARE: &str = "2.0.0";
+
+/// Controller checks when the daemon starts
+///
+/// Before arming the watchdog, the state machine reads the registers the
+/// daemon depends on and compares the firmware with `min-firmware` and the
+/// hardware with the versions the daemon knows. Failures are logged, shown
+/// in the systemd status and reported in `GET /health`. With unsupported
+/// firmware or hardware, the API refuses requests that change the
+/// controller; monitoring and the blackout handling carry on.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SelfTestConfig {
+    /// Run the self-test
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Oldest supported firmware version, e.g. `3.1.0`
+    #[serde(default = "default_self_test_min_firmware")]
+    pub min_firmware: String,
+}
+
+fn default_self_test_min_firmware() -> String {
+    DEFAULT_SELF_TEST_MIN_FIRMWARE.to_string()
+}
+
+impl Default for SelfTestConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_firmware: default_self_test_min_firmware(),
+        }
+    }
+}
+
+impl SelfTestConfig {
+    /// The `min-firmware` version
+    ///
+    /// # Errors
+    /// Returns a description of the problem if it is not a version.
+    pub fn min_firmware(&self) -> Result<crate::types::Version, String> {
+        self.min_firmware.parse()
+    }
+}
+
+/// Default Linux watchdog device
+pub const DEFAULT_KERNEL_WATCHDOG_DEVICE: &str = "/dev/watchdog";
+
+/// Linux watchdog device petted by the state machine
+///
+/// The device is petted after every successful state machine iteration, so
+/// the Pi's own watchdog (the SoC watchdog or softdog) reboots the system
+/// if the daemon hangs or dies. The daemon closes the device cleanly on
+/// exit, together with disabling the HALPI2 watchdog. The device cannot be
+/// shared: systemd's `RuntimeWatchdogSec` must not be set at the same time.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct KernelWatchdogConfig {
+    /// Open and pet the watchdog device
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Watchdog device path
+    #[serde(default = "default_kernel_watchdog_device")]
+    pub device: PathBuf,
+
+    /// Watchdog timeout in seconds; the driver default if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u32>,
+}
+
+fn default_kernel_watchdog_device() -> PathBuf {
+    PathBuf::from(DEFAULT_KERNEL_WATCHDOG_DEVICE)
+}
+
+impl Default for KernelWatchdogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            device: default_kernel_watchdog_device(),
+            timeout: None,
+        }
+    }
+}
+
+/// Default GPIO chip of the shutdown button
+pub const DEFAULT_GPIO_CHIP: &str = "/dev/gpiochip0";
+
+/// Default time the shutdown button must be held, in seconds
+pub const DEFAULT_GPIO_DEBOUNCE: f64 = 0.1;
+
+/// Bias of a GPIO input line
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum GpioBias {
+    /// Pull the line up, for a switch to ground
+    #[default]
+    PullUp,
+    /// Pull the line down, for a switch to 3.3 V
+    PullDown,
+    /// No bias, for lines driven externally
+    Disabled,
+}
+
+/// Shutdown button or switch on a GPIO line
+///
+/// Once the line has been active for `debounce` seconds, the daemon
+/// requests a shutdown like `POST /shutdown`. A switch that stays active,
+/// such as an ignition switch, triggers only once until it is released.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct GpioButtonConfig {
+    /// Watch the GPIO line
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// GPIO chip device
+    #[serde(default = "default_gpio_chip")]
+    pub chip: PathBuf,
+
+    /// Line offset on the chip; the BCM GPIO number on the Pi header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin: Option<u32>,
+
+    /// The line is active when low, e.g. a button to ground
+    #[serde(default = "default_true")]
+    pub active_low: bool,
+
+    /// Line bias
+    #[serde(default)]
+    pub bias: GpioBias,
+
+    /// Seconds the line must stay active
+    #[serde(default = "default_gpio_debounce")]
+    pub debounce: f64,
+}
+
+fn default_gpio_chip() -> PathBuf {
+    PathBuf::from(DEFAULT_GPIO_CHIP)
+}
+
+fn default_gpio_debounce() -> f64 {
+    DEFAULT_GPIO_DEBOUNCE
+}
+
+impl Default for GpioButtonConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            chip: default_gpio_chip(),
+            pin: None,
+            active_low: true,
+            bias: GpioBias::default(),
+            debounce: DEFAULT_GPIO_DEBOUNCE,
+        }
+    }
+}
+
+/// Default start of the LED night mode, local time
+pub const DEFAULT_LED_NIGHT_START: &str = "22:00";
+
+/// Default end of the LED night mode, local time
+pub const DEFAULT_LED_NIGHT_END: &str = "07:00";
+
+/// Default LED brightness at night (0-255)
+pub const DEFAULT_LED_NIGHT_BRIGHTNESS: u8 = 8;
+
+/// Dimming of the front LED at night
+///
+/// At `start`, local time, the LED brightness is lowered to `brightness`;
+/// at `end`, it is restored. Only the transitions change the brightness,
+/// so a brightness set in between is kept until the next one.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LedNightConfig {
+    /// Dim the LED at night
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Start of the night as `HH:MM`
+    #[serde(default = "default_led_night_start")]
+    pub start: String,
+
+    /// End of the night as `HH:MM`
+    #[serde(default = "default_led_night_end")]
+    pub end: String,
+
+    /// Brightness at night (0-255)
+    #[serde(default = "default_led_night_brightness")]
+    pub brightness: u8,
+
+    /// Brightness restored at the end of the night; the brightness before
+    /// dimming if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub day_brightness: Option<u8>,
+}
+
+impl LedNightConfig {
+    /// The `start` and `end` times of day
+    ///
+    /// # Errors
+    /// Returns a description of the problem if a time is not `HH:MM` or
+    /// both are the same.
+    pub fn times(&self) -> Result<(chrono::NaiveTime, chrono::NaiveTime), String> {
+        let (start, end) = (
+            parse_time_of_day(&self.start)?,
+            parse_time_of_day(&self.end)?,
+        );
+        if start == end {
+            return Err(format!("start and end are both {}", self.start));
+        }
+        Ok((start, end))
+    }
+}
+
+fn default_led_night_start() -> String {
+    DEFAULT_LED_NIGHT_START.to_string()
+}
+
+fn default_led_night_end() -> String {
+    DEFAULT_LED_NIGHT_END.to_string()
+}
+
+fn default_led_night_brightness() -> u8 {
+    DEFAULT_LED_NIGHT_BRIGHTNESS
+}
+
+impl Default for LedNightConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            start: default_led_night_start(),
+            end: default_led_night_end(),
+            brightness: DEFAULT_LED_NIGHT_BRIGHTNESS,
+            day_brightness: None,
+        }
+    }
+}
+
+/// Default interval between runs of a health check, in seconds
+pub const DEFAULT_HEALTH_CHECK_INTERVAL: f64 = 30.0;
+
+/// Default time a health check may take, in seconds
+pub const DEFAULT_HEALTH_CHECK_TIMEOUT: f64 = 10.0;
+
+/// Default consecutive failures of a health check before its action
+pub const DEFAULT_HEALTH_CHECK_FAILURES: u32 = 3;
+
+/// Action taken when a health check keeps failing
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum HealthAction {
+    /// Log the failure
+    Log,
+    /// Log the failure and raise an alert
+    #[default]
+    Alert,
+    /// Reboot the system
+    Reboot,
+    /// Stop feeding the HALPI2 watchdog, so the controller power-cycles
+    /// the system
+    Watchdog,
+    /// Turn `usb-port` off and on again
+    PowerCycleUsb,
+}
+
+impl HealthAction {
+    /// Action name as used in configuration and logs
+    pub fn name(&self) -> &'static str {
+        match self {
+            HealthAction::Log => "log",
+            HealthAction::Alert => "alert",
+            HealthAction::Reboot => "reboot",
+            HealthAction::Watchdog => "watchdog",
+            HealthAction::PowerCycleUsb => "power-cycle-usb",
+        }
+    }
+
+    /// True for actions that interrupt the system or a device
+    ///
+    /// These are held off in maintenance mode.
+    pub fn is_disruptive(&self) -> bool {
+        matches!(
+            self,
+            HealthAction::Reboot | HealthAction::Watchdog | HealthAction::PowerCycleUsb
+        )
+    }
+}
+
+/// What a health check probes
+#[derive(Debug, Clone, PartialEq)]
+pub enum HealthProbe<'a> {
+    /// A shell command that must exit successfully
+    Command(&'a str),
+    /// A URL that must answer GET requests with a 2xx status
+    Http(&'a str),
+    /// A `host:port` that must accept TCP connections
+    Tcp(&'a str),
+    /// A systemd unit that must be active
+    Unit(&'a str),
+    /// A file that must have been modified within `max-age` seconds
+    FileAge(&'a Path, f64),
+}
+
+/// A health check
+///
+/// Exactly one of `command`, `http`, `tcp`, `unit` and `file` selects the
+/// probe. The check runs every `interval` seconds; after `failures`
+/// consecutive failures, the daemon takes the `action`, once until the
+/// check passes again.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct HealthCheckConfig {
+    /// Name used in logs and alerts
+    pub name: String,
+
+    /// Shell command that must exit with status 0
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+
+    /// URL that must answer with a 2xx status
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<String>,
+
+    /// `host:port` that must accept TCP connections
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tcp: Option<String>,
+
+    /// systemd unit that must be active
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+
+    /// File that must have been modified within `max-age` seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+
+    /// Maximum age of `file` in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<f64>,
+
+    /// Seconds between runs
+    #[serde(default = "default_health_check_interval")]
+    pub interval: f64,
+
+    /// Seconds a run may take before it counts as failed
+    #[serde(default = "default_health_check_timeout")]
+    pub timeout: f64,
+
+    /// Consecutive failures before the action
+    #[serde(default = "default_health_check_failures")]
+    pub failures: u32,
+
+    /// What to do once the check keeps failing
+    #[serde(default)]
+    pub action: HealthAction,
+
+    /// USB port (0-3) for `power-cycle-usb`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usb_port: Option<u8>,
+}
+
+fn default_health_check_interval() -> f64 {
+    DEFAULT_HEALTH_CHECK_INTERVAL
+}
+
+fn default_health_check_timeout() -> f64 {
+    DEFAULT_HEALTH_CHECK_TIMEOUT
+}
+
+fn default_health_check_failures() -> u32 {
+    DEFAULT_HEALTH_CHECK_FAILURES
+}
+
+impl HealthCheckConfig {
+    /// The probe selected by the check's settings
+    ///
+    /// # Errors
+    /// Returns a description of the problem unless exactly one probe is
+    /// configured, or if `file` lacks `max-age`.
+    pub fn probe(&self) -> Result<HealthProbe<'_>, String> {
+        let mut probes = Vec::new();
+        if let Some(command) = &self.command {
+            probes.push(HealthProbe::Command(command));
+        }
+        if let Some(url) = &self.http {
+            probes.push(HealthProbe::Http(url));
+        }
+        if let Some(address) = &self.tcp {
+            probes.push(HealthProbe::Tcp(address));
+        }
+        if let Some(unit) = &self.unit {
+            probes.push(HealthProbe::Unit(unit));
+        }
+        if let Some(path) = &self.file {
+            let max_age = self.max_age.ok_or("file needs max-age")?;
+            probes.push(HealthProbe::FileAge(path, max_age));
+        }
+        match probes.len() {
+            1 => Ok(probes.remove(0)),
+            0 => Err("needs one of command, http, tcp, unit or file".to_string()),
+            _ => Err("has more than one of command, http, tcp, unit and file".to_string()),
+        }
+    }
+}
+
+/// A recurring standby window
+///
+/// At `off`, local time, the daemon programs the RTC to wake the system at
+/// `on` and requests standby. A window whose `on` is not after `off` ends on
+/// the following day.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PowerWindowConfig {
+    /// Start of standby as `HH:MM`
+    pub off: String,
+
+    /// Wake time as `HH:MM`
+    pub on: String,
+
+    /// Days on which the window starts (`mon`-`sun`); every day if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<chrono::Weekday>,
+}
+
+impl PowerWindowConfig {
+    /// The `off` and `on` times of day
+    ///
+    /// # Errors
+    /// Returns a description of the problem if a time is not `HH:MM` or
+    /// both are the same.
+    pub fn times(&self) -> Result<(chrono::NaiveTime, chrono::NaiveTime), String> {
+        let (off, on) = (parse_time_of_day(&self.off)?, parse_time_of_day(&self.on)?);
+        if off == on {
+            return Err(format!("off and on are both {}", self.off));
+        }
+        Ok((off, on))
+    }
+
+    /// True if the window starts on `day`
+    pub fn starts_on(&self, day: chrono::Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+}
+
+/// Parse a time of day given as `HH:MM`
+fn parse_time_of_day(time: &str) -> Result<chrono::NaiveTime, String> {
+    chrono::NaiveTime::parse_from_str(time, "%H:%M")
+        .map_err(|_| format!("time '{}' is not HH:MM", time))
+}
+
+/// Geographic position in decimal degrees
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LocationConfig {
+    /// Latitude, positive north (-90 to 90)
+    pub latitude: f64,
+
+    /// Longitude, positive east (-180 to 180)
+    pub longitude: f64,
+}
+
+/// Default API requests per second per client
+pub const DEFAULT_API_RATE: f64 = 20.0;
+
+/// Default API requests a client may make at once
+pub const DEFAULT_API_BURST: u32 = 40;
+
+/// Default API requests accessing a controller at the same time
+pub const DEFAULT_API_MAX_CONCURRENT: usize = 2;
+
+/// Default maximum size of an uploaded firmware image in KiB
+pub const DEFAULT_MAX_FIRMWARE_KB: usize = 2048;
+
+/// Limits on the API requests that access a controller (values, config,
+/// USB, LED and firmware upload), so that a client polling in a tight loop
+/// cannot delay the state machine's access to the I2C bus
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ApiLimitsConfig {
+    /// Requests per second per client process (0 = unlimited)
+    #[serde(default = "default_api_rate")]
+    pub rate: f64,
+
+    /// Requests a client may make in a burst before the rate applies
+    #[serde(default = "default_api_burst")]
+    pub burst: u32,
+
+    /// Requests running at the same time; further ones wait their turn
+    #[serde(default = "default_api_max_concurrent")]
+    pub max_concurrent: usize,
+
+    /// Largest firmware image accepted by `POST /flash` in KiB
+    #[serde(default = "default_max_firmware_kb")]
+    pub max_firmware_kb: usize,
+}
+
+impl ApiLimitsConfig {
+    /// Largest firmware image accepted by `POST /flash` in bytes
+    pub fn max_firmware_size(&self) -> usize {
+        self.max_firmware_kb * 1024
+    }
+}
+
+impl Default for ApiLimitsConfig {
+    fn default() -> Self {
+        Self {
+            rate: DEFAULT_API_RATE,
+            burst: DEFAULT_API_BURST,
+            max_concurrent: DEFAULT_API_MAX_CONCURRENT,
+            max_firmware_kb: DEFAULT_MAX_FIRMWARE_KB,
+        }
+    }
+}
+
+fn default_api_rate() -> f64 {
+    DEFAULT_API_RATE
+}
+
+fn default_api_burst() -> u32 {
+    DEFAULT_API_BURST
+}
+
+fn default_api_max_concurrent() -> usize {
+    DEFAULT_API_MAX_CONCURRENT
+}
+
+fn default_max_firmware_kb() -> usize {
+    DEFAULT_MAX_FIRMWARE_KB
+}
+
+/// Cross-origin resource sharing for browser clients, such as a Cockpit
+/// plugin or a dashboard served from another origin
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API, such as `https://halpi.local:9090`,
+    /// or `*` for any; no CORS headers are sent if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_origins: Vec<String>,
+}
+
+impl CorsConfig {
+    /// Whether any origin is allowed
+    pub fn allows_any(&self) -> bool {
+        self.allowed_origins.iter().any(|origin| origin == "*")
+    }
+}
+
+/// Default maximum log file size in megabytes before rotation
+pub const DEFAULT_LOG_MAX_SIZE_MB: u64 = 10;
+
+/// Default number of rotated log files to keep
+pub const DEFAULT_LOG_KEEP: usize = 5;
+
+/// Time-based log file rotation schedule
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogRotation {
+    /// Rotate on size only
+    #[default]
+    Never,
+    /// Rotate at the start of every hour (UTC)
+    Hourly,
+    /// Rotate at midnight (UTC)
+    Daily,
+}
+
+/// Log line format for stderr and file output
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!(
+                "unknown log format '{}' (expected 'text' or 'json')",
+                other
+            )),
+        }
+    }
+}
+
+/// Log output configuration
+///
+/// Logs always go to the journal or stderr. Setting `file` additionally
+/// writes them to a file that is rotated by size and/or time; rotated files
+/// are renamed to `<file>.1`, `<file>.2`, ... with the oldest removed once
+/// `keep` is exceeded.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// Line format for stderr and file output
+    ///
+    /// Entries sent to journald always carry structured fields.
+    #[serde(default)]
+    pub format: LogFormat,
+
+    /// Log file path (file logging disabled if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+
+    /// Time-based rotation schedule
+    #[serde(default)]
+    pub rotation: LogRotation,
+
+    /// Rotate when the file exceeds this size in megabytes (0 = no limit)
+    #[serde(default = "default_log_max_size_mb")]
+    pub max_size_mb: u64,
+
+    /// Number of rotated files to keep
+    #[serde(default = "default_log_keep")]
+    pub keep: usize,
+
+    /// Log every I2C transfer (direction, register, bytes, duration and
+    /// attempt) at trace level
+    #[serde(default)]
+    pub i2c_trace: bool,
+
+    /// Write I2C transfer traces to this file instead of the main log
+    ///
+    /// Implies `i2c_trace`. Rotated like the main log file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub i2c_trace_file: Option<PathBuf>,
+
+    /// Log every API request (method, path, status, duration and the
+    /// client's uid and pid) at info level
+    #[serde(default)]
+    pub access_log: bool,
+
+    /// Write the API access log to this file instead of the main log
+    ///
+    /// Implies `access_log`. Rotated like the main log file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_log_file: Option<PathBuf>,
+}
+
+impl LoggingConfig {
+    /// Whether API requests are logged
+    pub fn access_log_enabled(&self) -> bool {
+        self.access_log || self.access_log_file.is_some()
+    }
+}
+
+fn default_log_max_size_mb() -> u64 {
+    DEFAULT_LOG_MAX_SIZE_MB
+}
+
+fn default_log_keep() -> usize {
+    DEFAULT_LOG_KEEP
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Text,
+            file: None,
+            rotation: LogRotation::Never,
+            max_size_mb: DEFAULT_LOG_MAX_SIZE_MB,
+            keep: DEFAULT_LOG_KEEP,
+            i2c_trace: false,
+            i2c_trace_file: None,
+            access_log: false,
+            access_log_file: None,
+        }
+    }
+}
+
+// Default value functions for serde
+fn default_i2c_bus() -> u8 {
+    DEFAULT_I2C_BUS
+}
+
+fn default_i2c_addr() -> u8 {
+    DEFAULT_I2C_ADDR
+}
+
+fn default_blackout_time_limit() -> f64 {
+    DEFAULT_BLACKOUT_TIME_LIMIT
+}
+
+fn default_blackout_voltage_limit() -> f64 {
+    DEFAULT_BLACKOUT_VOLTAGE_LIMIT
+}
+
+fn default_blackout_voltage_hysteresis() -> f64 {
+    DEFAULT_BLACKOUT_VOLTAGE_HYSTERESIS
+}
+
+fn default_blackout_wake_after() -> f64 {
+    DEFAULT_BLACKOUT_WAKE_AFTER
+}
+
+fn default_shutdown_duration() -> f64 {
+    DEFAULT_SHUTDOWN_DURATION
+}
+
+fn default_poll_interval() -> f64 {
+    DEFAULT_POLL_INTERVAL
+}
+
+fn default_watchdog_timeout() -> f64 {
+    DEFAULT_WATCHDOG_TIMEOUT
+}
+
+fn default_socket_group() -> String {
+    DEFAULT_SOCKET_GROUP.to_string()
+}
+
+fn default_poweroff_command() -> String {
+    DEFAULT_POWEROFF_COMMAND.to_string()
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            i2c_bus: DEFAULT_I2C_BUS,
+            i2c_addr: DEFAULT_I2C_ADDR,
+            i2c_device: None,
+            i2c_pec: false,
+            usb_defaults: UsbDefaultsConfig::default(),
+            devices: Vec::new(),
+            blackout_time_limit: DEFAULT_BLACKOUT_TIME_LIMIT,
+            blackout_voltage_limit: DEFAULT_BLACKOUT_VOLTAGE_LIMIT,
+            blackout_voltage_hysteresis: DEFAULT_BLACKOUT_VOLTAGE_HYSTERESIS,
+            blackout_action: BlackoutAction::default(),
+            blackout_wake_after: DEFAULT_BLACKOUT_WAKE_AFTER,
+            blackout_command: String::new(),
+            runtime_shutdown: false,
+            shutdown_duration: DEFAULT_SHUTDOWN_DURATION,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            watchdog_timeout: DEFAULT_WATCHDOG_TIMEOUT,
+            socket: None,
+            socket_group: DEFAULT_SOCKET_GROUP.to_string(),
+            user: None,
+            group: None,
+            poweroff: DEFAULT_POWEROFF_COMMAND.to_string(),
+            logind: true,
+            nmea2000: Nmea2000Config::default(),
+            influxdb: InfluxDbConfig::default(),
+            upower: UpowerConfig::default(),
+            nut: NutConfig::default(),
+            snmp: SnmpConfig::default(),
+            webhooks: WebhooksConfig::default(),
+            hooks: HooksConfig::default(),
+            shutdown_grace: ShutdownGraceConfig::default(),
+            temperature: TemperatureConfig::default(),
+            input_current: InputCurrentConfig::default(),
+            calibration: CalibrationConfig::default(),
+            measurement_filter: MeasurementFilterConfig::default(),
+            voltage_events: VoltageEventsConfig::default(),
+            controller: ControllerConfig::default(),
+            self_test: SelfTestConfig::default(),
+            kernel_watchdog: KernelWatchdogConfig::default(),
+            gpio_button: GpioButtonConfig::default(),
+            led_night: LedNightConfig::default(),
+            health_checks: Vec::new(),
+            power_schedule: Vec::new(),
+            location: None,
+            logging: LoggingConfig::default(),
+            cors: CorsConfig::default(),
+            api_limits: ApiLimitsConfig::default(),
+        }
+    }
+}
+
+/// Configuration layer with only the settings it explicitly sets
+///
+/// The configuration file and the command line options are each read into
+/// a `PartialConfig` and applied on top of the defaults with
+/// [`Config::merge`], so a setting given with its default value still
+/// overrides a lower layer. Sections are set as a whole.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PartialConfig {
+    pub i2c_bus: Option<u8>,
+    pub i2c_addr: Option<u8>,
+    pub i2c_device: Option<PathBuf>,
+    pub i2c_pec: Option<bool>,
+    pub usb_defaults: Option<UsbDefaultsConfig>,
+    pub devices: Option<Vec<DeviceConfig>>,
+    pub blackout_time_limit: Option<f64>,
+    pub blackout_voltage_limit: Option<f64>,
+    pub blackout_voltage_hysteresis: Option<f64>,
+    pub blackout_action: Option<BlackoutAction>,
+    pub blackout_wake_after: Option<f64>,
+    pub blackout_command: Option<String>,
+    pub runtime_shutdown: Option<bool>,
+    pub shutdown_duration: Option<f64>,
+    pub poll_interval: Option<f64>,
+    pub watchdog_timeout: Option<f64>,
+    pub socket: Option<PathBuf>,
+    pub socket_group: Option<String>,
+    pub user: Option<String>,
+    pub group: Option<String>,
+    pub poweroff: Option<String>,
+    pub logind: Option<bool>,
+    pub nmea2000: Option<Nmea2000Config>,
+    pub influxdb: Option<InfluxDbConfig>,
+    pub upower: Option<UpowerConfig>,
+    pub nut: Option<NutConfig>,
+    pub snmp: Option<SnmpConfig>,
+    pub webhooks: Option<WebhooksConfig>,
+    pub hooks: Option<HooksConfig>,
+    pub shutdown_grace: Option<ShutdownGraceConfig>,
+    pub temperature: Option<TemperatureConfig>,
+    pub input_current: Option<InputCurrentConfig>,
+    pub calibration: Option<CalibrationConfig>,
+    pub measurement_filter: Option<MeasurementFilterConfig>,
+    pub voltage_events: Option<VoltageEventsConfig>,
+    pub controller: Option<ControllerConfig>,
+    pub self_test: Option<SelfTestConfig>,
+    pub kernel_watchdog: Option<KernelWatchdogConfig>,
+    pub gpio_button: Option<GpioButtonConfig>,
+    pub led_night: Option<LedNightConfig>,
+    pub health_checks: Option<Vec<HealthCheckConfig>>,
+    pub power_schedule: Option<Vec<PowerWindowConfig>>,
+    pub location: Option<LocationConfig>,
+    pub logging: Option<LoggingConfig>,
+    pub cors: Option<CorsConfig>,
+    pub api_limits: Option<ApiLimitsConfig>,
+}
+
+impl From<PartialConfig> for Config {
+    /// The defaults with `partial` applied
+    fn from(partial: PartialConfig) -> Self {
+        let mut config = Config::default();
+        config.merge(partial);
+        config
+    }
+}
+
+impl Config {
+    /// Load configuration from a YAML file
+    ///
+    /// Returns `Ok(Config)` if the file exists and is valid YAML.
+    /// Returns an error if the file exists but cannot be read or parsed.
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::FileRead(path.into(), e))?;
+
+        serde_yaml::from_str::<PartialConfig>(&contents)
+            .map(Config::from)
+            .map_err(|e| ConfigError::YamlParse(path.into(), e.to_string()))
+    }
+
+    /// Load a configuration file merged with its drop-in fragments
+    ///
+    /// Fragments are the `*.conf` files in the file's [drop-in
+    /// directory](Self::dropin_dir), applied in lexical order after the main
+    /// file. Mappings are merged key by key, so a fragment only needs to
+    /// contain the settings it changes; lists and values replace the earlier
+    /// ones. A missing drop-in directory is not an error.
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let mut merged = read_yaml(path)?;
+        for fragment in dropin_files(&Self::dropin_dir(path))? {
+            merge_yaml(&mut merged, read_yaml(&fragment)?);
+        }
+        serde_yaml::from_value::<PartialConfig>(merged)
+            .map(Config::from)
+            .map_err(|e| ConfigError::YamlParse(path.into(), e.to_string()))
+    }
+
+    /// Drop-in directory of the configuration file at `path`
+    ///
+    /// The file name with `.d` appended, e.g. `/etc/halpid/halpid.conf.d`.
+    pub fn dropin_dir(path: &std::path::Path) -> PathBuf {
+        let mut dir = path.as_os_str().to_owned();
+        dir.push(".d");
+        PathBuf::from(dir)
+    }
+
+    /// Load configuration from a file if it exists, otherwise return defaults
+    ///
+    /// This is useful for the default config file location where a missing file is not an error.
+    pub fn from_file_or_default(path: impl AsRef<std::path::Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        if path.exists() {
+            Self::from_file(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// Validate configuration values
+    ///
+    /// Returns an error if any values are out of acceptable ranges
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        // Validate I2C bus (0-255, but realistically 0-10 on RPi)
+        if self.i2c_bus > 10 {
+            return Err(ConfigError::InvalidValue(format!(
+                "i2c-bus {} is unusually high (expected 0-10)",
+                self.i2c_bus
+            )));
+        }
+
+        self.validate_reloadable()?;
+        self.validate_timing()?;
+
+        if self.logging.file.is_some() && self.logging.keep == 0 {
+            return Err(ConfigError::InvalidValue(
+                "logging.keep must be at least 1".to_string(),
+            ));
+        }
+
+        self.validate_devices()?;
+
+        let limits = &self.api_limits;
+        if !(limits.rate.is_finite() && limits.rate >= 0.0) {
+            return Err(ConfigError::InvalidValue(format!(
+                "api-limits.rate {} must be 0 or more requests per second",
+                limits.rate
+            )));
+        }
+        if limits.burst == 0 {
+            return Err(ConfigError::InvalidValue(
+                "api-limits.burst must be at least 1".to_string(),
+            ));
+        }
+        if !(1..=32).contains(&limits.max_concurrent) {
+            return Err(ConfigError::InvalidValue(format!(
+                "api-limits.max-concurrent {} is out of range (expected 1-32)",
+                limits.max_concurrent
+            )));
+        }
+        if !(64..=16384).contains(&limits.max_firmware_kb) {
+            return Err(ConfigError::InvalidValue(format!(
+                "api-limits.max-firmware-kb {} is out of range (expected 64-16384)",
+                limits.max_firmware_kb
+            )));
+        }
+
+        // Origins are a scheme and a host with an optional port, no path
+        for origin in &self.cors.allowed_origins {
+            let valid = origin == "*"
+                || origin
+                    .strip_prefix("http://")
+                    .or_else(|| origin.strip_prefix("https://"))
+                    .is_some_and(|host| !host.is_empty() && !host.contains(['/', ' ']));
+            if !valid {
+                return Err(ConfigError::InvalidValue(format!(
+                    "cors.allowed-origins: invalid origin '{}' (expected e.g. https://host:port or *)",
+                    origin
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validate the polling interval and the watchdog timeouts
+    ///
+    /// Part of [`validate`](Self::validate). The hardware watchdog timeout
+    /// is sent to the controller in milliseconds as a 16-bit value.
+    pub fn validate_timing(&self) -> Result<(), ConfigError> {
+        if !(0.01..=1.0).contains(&self.poll_interval) {
+            return Err(ConfigError::InvalidValue(format!(
+                "poll-interval {} is out of range (expected 0.01-1 seconds)",
+                self.poll_interval
+            )));
+        }
+        if !(1.0..=65.0).contains(&self.watchdog_timeout) {
+            return Err(ConfigError::InvalidValue(format!(
+                "watchdog-timeout {} is out of range (expected 1-65 seconds)",
+                self.watchdog_timeout
+            )));
+        }
+        if self.watchdog_timeout < MIN_WATCHDOG_POLL_RATIO * self.poll_interval {
+            return Err(ConfigError::InvalidValue(format!(
+                "watchdog-timeout {} must be at least {} times poll-interval {}",
+                self.watchdog_timeout, MIN_WATCHDOG_POLL_RATIO, self.poll_interval
+            )));
+        }
+        let kernel = &self.kernel_watchdog;
+        if kernel.enabled
+            && let Some(timeout) = kernel.timeout
+            && !(1..=600).contains(&timeout)
+        {
+            return Err(ConfigError::InvalidValue(format!(
+                "kernel-watchdog.timeout {} is out of range (expected 1-600 seconds)",
+                timeout
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether to power off through logind rather than run `poweroff`
+    ///
+    /// A custom `poweroff` command always runs, even with `logind` on.
+    pub fn logind_power_off(&self) -> bool {
+        self.logind && self.poweroff == DEFAULT_POWEROFF_COMMAND
+    }
+
+    /// Input voltage above which power counts as restored after a blackout
+    pub fn blackout_recovery_voltage(&self) -> f64 {
+        self.blackout_voltage_limit + self.blackout_voltage_hysteresis
+    }
+
+    /// Hardware watchdog timeout in milliseconds, as sent to the controller
+    pub fn watchdog_timeout_ms(&self) -> u16 {
+        (self.watchdog_timeout * 1000.0).round() as u16
+    }
+
+    /// Calibration of a controller by its `/devices/{id}` ID
+    pub fn calibration_for(&self, device_id: &str) -> &CalibrationConfig {
+        self.devices
+            .iter()
+            .find(|device| device.id == device_id)
+            .map_or(&self.calibration, |device| &device.calibration)
+    }
+
+    /// Validate the settings that can change while the daemon runs
+    ///
+    /// Part of [`validate`](Self::validate): the blackout limits and the
+    /// exporter and notifier sections. The daemon checks these before
+    /// applying a reloaded configuration.
+    pub fn validate_reloadable(&self) -> Result<(), ConfigError> {
+        // Validate blackout time limit (must be positive, reasonable upper bound)
+        if self.blackout_time_limit <= 0.0 {
+            return Err(ConfigError::InvalidValue(
+                "blackout_time_limit must be positive".to_string(),
+            ));
+        }
+        if self.blackout_time_limit > 3600.0 {
+            return Err(ConfigError::InvalidValue(
+                "blackout_time_limit must be <= 3600 seconds (1 hour)".to_string(),
+            ));
+        }
+
+        // Validate blackout voltage limit (typical range: 5-15V)
+        if self.blackout_voltage_limit < 5.0 || self.blackout_voltage_limit > 15.0 {
+            return Err(ConfigError::InvalidValue(format!(
+                "blackout-voltage-limit {} is out of range (expected 5.0-15.0 volts)",
+                self.blackout_voltage_limit
+            )));
+        }
+        if !(0.0..=3.0).contains(&self.blackout_voltage_hysteresis) {
+            return Err(ConfigError::InvalidValue(format!(
+                "blackout-voltage-hysteresis {} is out of range (expected 0-3 volts)",
+                self.blackout_voltage_hysteresis
+            )));
+        }
+
+        if self.blackout_action == BlackoutAction::Standby
+            && (self.blackout_wake_after < 60.0 || self.blackout_wake_after > 604800.0)
+        {
+            return Err(ConfigError::InvalidValue(format!(
+                "blackout-wake-after {} is out of range (expected 60-604800 seconds)",
+                self.blackout_wake_after
+            )));
+        }
+        if self.blackout_action == BlackoutAction::Command && self.blackout_command.is_empty() {
+            return Err(ConfigError::InvalidValue(
+                "blackout-command must be set for blackout-action: command".to_string(),
+            ));
+        }
+        if !(0.0..=300.0).contains(&self.shutdown_duration) {
+            return Err(ConfigError::InvalidValue(format!(
+                "shutdown-duration {} is out of range (expected 0-300 seconds)",
+                self.shutdown_duration
+            )));
+        }
+
+        if self.nmea2000.enabled {
+            if self.nmea2000.interface.is_empty() {
+                return Err(ConfigError::InvalidValue(
+                    "nmea2000.interface must not be empty".to_string(),
+                ));
+            }
+            if self.nmea2000.source_address > 251 {
+                return Err(ConfigError::InvalidValue(format!(
+                    "nmea2000.source-address {} is out of range (expected 0-251)",
+                    self.nmea2000.source_address
+                )));
+            }
+            if self.nmea2000.interval < 0.1 || self.nmea2000.interval > 60.0 {
+                return Err(ConfigError::InvalidValue(format!(
+                    "nmea2000.interval {} is out of range (expected 0.1-60 seconds)",
+                    self.nmea2000.interval
+                )));
+            }
+        }
+
+        if self.influxdb.enabled {
+            let url = &self.influxdb.url;
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(ConfigError::InvalidValue(format!(
+                    "influxdb.url '{}' must start with http:// or https://",
+                    url
+                )));
+            }
+            if self.influxdb.bucket.is_empty() {
+                return Err(ConfigError::InvalidValue(
+                    "influxdb.bucket must not be empty".to_string(),
+                ));
+            }
+            if self.influxdb.measurement.is_empty() {
+                return Err(ConfigError::InvalidValue(
+                    "influxdb.measurement must not be empty".to_string(),
+                ));
+            }
+            if self.influxdb.interval < 1.0 || self.influxdb.interval > 3600.0 {
+                return Err(ConfigError::InvalidValue(format!(
+                    "influxdb.interval {} is out of range (expected 1-3600 seconds)",
+                    self.influxdb.interval
+                )));
+            }
+        }
+
+        if self.upower.enabled && (self.upower.interval < 0.5 || self.upower.interval > 60.0) {
+            return Err(ConfigError::InvalidValue(format!(
+                "upower.interval {} is out of range (expected 0.5-60 seconds)",
+                self.upower.interval
+            )));
+        }
+
+        if self.nut.enabled {
+            if self.nut.listen.parse::<std::net::SocketAddr>().is_err() {
+                return Err(ConfigError::InvalidValue(format!(
+                    "nut.listen '{}' is not a valid address (expected e.g. 127.0.0.1:3493)",
+                    self.nut.listen
+                )));
+            }
+            let name = &self.nut.ups_name;
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            {
+                return Err(ConfigError::InvalidValue(format!(
+                    "nut.ups-name '{}' must be non-empty and contain only letters, digits, '-', '_' or '.'",
+                    name
+                )));
+            }
+            if self.nut.username.is_some() != self.nut.password.is_some() {
+                return Err(ConfigError::InvalidValue(
+                    "nut.username and nut.password must be set together".to_string(),
+                ));
+            }
+            if self.nut.low_charge > 100 {
+                return Err(ConfigError::InvalidValue(format!(
+                    "nut.low-charge {} is out of range (expected 0-100)",
+                    self.nut.low_charge
+                )));
+            }
+        }
+
+        if self.snmp.enabled {
+            let subids: Vec<&str> = self
+                .snmp
+                .base_oid
+                .trim_start_matches('.')
+                .split('.')
+                .collect();
+            if subids.len() < 2 || subids.iter().any(|s| s.parse::<u32>().is_err()) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "snmp.base-oid '{}' must be a numeric OID (e.g. 1.3.6.1.4.1.<enterprise>.1)",
+                    self.snmp.base_oid
+                )));
+            }
+            if self.snmp.master.is_empty() {
+                return Err(ConfigError::InvalidValue(
+                    "snmp.master must not be empty".to_string(),
+                ));
+            }
+        }
+
+        if self.webhooks.enabled {
+            if self.webhooks.endpoints.is_empty() {
+                return Err(ConfigError::InvalidValue(
+                    "webhooks.endpoints must not be empty".to_string(),
+                ));
+            }
+            for endpoint in &self.webhooks.endpoints {
+                let url = &endpoint.url;
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    return Err(ConfigError::InvalidValue(format!(
+                        "webhooks endpoint url '{}' must start with http:// or https://",
+                        url
+                    )));
+                }
+            }
+            if self.webhooks.retry_delay <= 0.0 || self.webhooks.retry_delay > 600.0 {
+                return Err(ConfigError::InvalidValue(format!(
+                    "webhooks.retry-delay {} is out of range (expected 0-600 seconds)",
+                    self.webhooks.retry_delay
+                )));
+            }
+        }
+
+        for event in HookEvent::ALL {
+            if let Some(path) = self.hooks.path(event)
+                && !path.is_absolute()
+            {
+                return Err(ConfigError::InvalidValue(format!(
+                    "hooks.{} '{}' must be an absolute path",
+                    event.name(),
+                    path.display()
+                )));
+            }
+        }
+        if self.hooks.timeout < 1.0 || self.hooks.timeout > 120.0 {
+            return Err(ConfigError::InvalidValue(format!(
+                "hooks.timeout {} is out of range (expected 1-120 seconds)",
+                self.hooks.timeout
+            )));
+        }
+
+        let grace = &self.shutdown_grace;
+        if !(0.0..=300.0).contains(&grace.period) {
+            return Err(ConfigError::InvalidValue(format!(
+                "shutdown-grace.period {} is out of range (expected 0-300 seconds)",
+                grace.period
+            )));
+        }
+        if !(0.0..=5.0).contains(&grace.supercap_margin) {
+            return Err(ConfigError::InvalidValue(format!(
+                "shutdown-grace.supercap-margin {} is out of range (expected 0-5 volts)",
+                grace.supercap_margin
+            )));
+        }
+        if !(0.0..=60.0).contains(&grace.abort_window) {
+            return Err(ConfigError::InvalidValue(format!(
+                "shutdown-grace.abort-window {} is out of range (expected 0-60 seconds)",
+                grace.abort_window
+            )));
+        }
+
+        let temperature = &self.temperature;
+        if temperature.enabled {
+            for (name, limit) in [
+                ("warning", temperature.warning),
+                ("shed", temperature.shed),
+                ("shutdown", temperature.shutdown),
+            ] {
+                if !(0.0..=125.0).contains(&limit) {
+                    return Err(ConfigError::InvalidValue(format!(
+                        "temperature.{} {} is out of range (expected 0-125 °C)",
+                        name, limit
+                    )));
+                }
+            }
+            if temperature.warning > temperature.shed || temperature.shed > temperature.shutdown {
+                return Err(ConfigError::InvalidValue(
+                    "temperature limits must satisfy warning <= shed <= shutdown".to_string(),
+                ));
+            }
+            if !(0.0..=20.0).contains(&temperature.hysteresis) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "temperature.hysteresis {} is out of range (expected 0-20 °C)",
+                    temperature.hysteresis
+                )));
+            }
+            if let Some(port) = temperature.shed_usb_ports.iter().find(|port| **port > 3) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "temperature.shed-usb-ports: invalid port {} (expected 0-3)",
+                    port
+                )));
+            }
+        }
+
+        let current = &self.input_current;
+        if current.enabled {
+            if !(0.1..=20.0).contains(&current.limit) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "input-current.limit {} is out of range (expected 0.1-20 A)",
+                    current.limit
+                )));
+            }
+            if !(0.1..=600.0).contains(&current.sustain) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "input-current.sustain {} is out of range (expected 0.1-600 seconds)",
+                    current.sustain
+                )));
+            }
+            if !(0.0..=20.0).contains(&current.spike) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "input-current.spike {} is out of range (expected 0-20 A)",
+                    current.spike
+                )));
+            }
+            if let Some(port) = current.shed_usb_ports.iter().find(|port| **port > 3) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "input-current.shed-usb-ports: invalid port {} (expected 0-3)",
+                    port
+                )));
+            }
+        }
+
+        self.calibration.validate("calibration")?;
+        for device in &self.devices {
+            device
+                .calibration
+                .validate(&format!("devices: '{}' calibration", device.id))?;
+        }
+        for (name, channel) in self.measurement_filter.channels() {
+            if !(0.0..1.0).contains(&channel.smoothing) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "measurement-filter.{}.smoothing {} is out of range (expected 0 to below 1)",
+                    name, channel.smoothing
+                )));
+            }
+        }
+
+        let voltage = &self.voltage_events;
+        if voltage.enabled {
+            if !(0.0..=40.0).contains(&voltage.sag) || !(0.0..=40.0).contains(&voltage.surge) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "voltage-events: sag {} and surge {} must be within 0-40 volts",
+                    voltage.sag, voltage.surge
+                )));
+            }
+            if voltage.sag >= voltage.surge {
+                return Err(ConfigError::InvalidValue(format!(
+                    "voltage-events.sag {} must be below surge {}",
+                    voltage.sag, voltage.surge
+                )));
+            }
+            if !(0.0..=60.0).contains(&voltage.min_duration) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "voltage-events.min-duration {} is out of range (expected 0-60 seconds)",
+                    voltage.min_duration
+                )));
+            }
+        }
+
+        let controller = &self.controller;
+        let vcap_max = crate::protocol::VCAP_MAX as f64;
+        for (name, threshold) in [
+            ("power-on-threshold", controller.power_on_threshold),
+            (
+                "solo-power-off-threshold",
+                controller.solo_power_off_threshold,
+            ),
+        ] {
+            if let Some(threshold) = threshold
+                && !(0.0..=vcap_max).contains(&threshold)
+            {
+                return Err(ConfigError::InvalidValue(format!(
+                    "controller.{} {} is out of range (expected 0-{} volts)",
+                    name, threshold, vcap_max
+                )));
+            }
+        }
+        if let Some(timeout) = controller.solo_depleting_timeout
+            && !(0.0..=u32::MAX as f64 / 1000.0).contains(&timeout)
+        {
+            return Err(ConfigError::InvalidValue(format!(
+                "controller.solo-depleting-timeout {} is out of range",
+                timeout
+            )));
+        }
+        if !(1.0..=86400.0).contains(&controller.check_interval) {
+            return Err(ConfigError::InvalidValue(format!(
+                "controller.check-interval {} is out of range (expected 1-86400 seconds)",
+                controller.check_interval
+            )));
+        }
+        // Each check feeds the hardware watchdog like the state machine does
+        if !controller.is_empty() && controller.check_interval <= self.watchdog_timeout {
+            return Err(ConfigError::InvalidValue(format!(
+                "controller.check-interval {} must be longer than watchdog-timeout {}",
+                controller.check_interval, self.watchdog_timeout
+            )));
+        }
+        if controller.enforce && controller.led_brightness.is_some() && self.led_night.enabled {
+            return Err(ConfigError::InvalidValue(
+                "controller.led-brightness cannot be enforced while led-night is enabled"
+                    .to_string(),
+            ));
+        }
+
+        if let Err(e) = self.self_test.min_firmware() {
+            return Err(ConfigError::InvalidValue(format!(
+                "self-test.min-firmware: {}",
+                e
+            )));
+        }
+
+        for (key, name) in [("user", &self.user), ("group", &self.group)] {
+            if let Some(name) = name
+                && (name.is_empty() || name.contains(['\0', ':', '/']))
+            {
+                return Err(ConfigError::InvalidValue(format!(
+                    "{} {:?} is not a valid name",
+                    key, name
+                )));
+            }
+        }
+
+        let button = &self.gpio_button;
+        if button.enabled {
+            if button.pin.is_none() {
+                return Err(ConfigError::InvalidValue(
+                    "gpio-button.pin must be set when enabled".to_string(),
+                ));
+            }
+            if !(0.01..=60.0).contains(&button.debounce) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "gpio-button.debounce {} is out of range (expected 0.01-60 seconds)",
+                    button.debounce
+                )));
+            }
+        }
+
+        if self.led_night.enabled
+            && let Err(e) = self.led_night.times()
+        {
+            return Err(ConfigError::InvalidValue(format!("led-night: {}", e)));
+        }
+
+        let mut names = Vec::new();
+        for check in &self.health_checks {
+            let invalid = |problem: String| {
+                ConfigError::InvalidValue(format!("health-checks: '{}' {}", check.name, problem))
+            };
+            if check.name.is_empty() || names.contains(&check.name.as_str()) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "health-checks: name '{}' is empty or already in use",
+                    check.name
+                )));
+            }
+            names.push(&check.name);
+            match check.probe().map_err(invalid)? {
+                HealthProbe::Http(url)
+                    if !url.starts_with("http://") && !url.starts_with("https://") =>
+                {
+                    return Err(invalid(format!(
+                        "http '{}' must start with http:// or https://",
+                        url
+                    )));
+                }
+                HealthProbe::Tcp(address) => {
+                    let port = address
+                        .rsplit_once(':')
+                        .map(|(_, port)| port.parse::<u16>());
+                    if !matches!(port, Some(Ok(port)) if port > 0) {
+                        return Err(invalid(format!("tcp '{}' must be host:port", address)));
+                    }
+                }
+                HealthProbe::FileAge(_, max_age) if max_age <= 0.0 => {
+                    return Err(invalid(format!("max-age {} must be positive", max_age)));
+                }
+                _ => {}
+            }
+            if !(1.0..=86400.0).contains(&check.interval) {
+                return Err(invalid(format!(
+                    "interval {} is out of range (expected 1-86400 seconds)",
+                    check.interval
+                )));
+            }
+            if !(0.1..=600.0).contains(&check.timeout) {
+                return Err(invalid(format!(
+                    "timeout {} is out of range (expected 0.1-600 seconds)",
+                    check.timeout
+                )));
+            }
+            if check.failures == 0 {
+                return Err(invalid("failures must be at least 1".to_string()));
+            }
+            let usb_port = check.usb_port.filter(|port| *port <= 3);
+            if (check.action == HealthAction::PowerCycleUsb) != usb_port.is_some() {
+                return Err(invalid(format!(
+                    "usb-port must be 0-3 with action power-cycle-usb, and unset otherwise (got {:?})",
+                    check.usb_port
+                )));
+            }
+        }
+
+        for (index, window) in self.power_schedule.iter().enumerate() {
+            window.times().map_err(|problem| {
+                ConfigError::InvalidValue(format!("power-schedule[{}]: {}", index, problem))
+            })?;
+        }
+
+        if let Some(location) = &self.location {
+            if !(-90.0..=90.0).contains(&location.latitude) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "location.latitude {} is out of range (expected -90 to 90)",
+                    location.latitude
+                )));
+            }
+            if !(-180.0..=180.0).contains(&location.longitude) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "location.longitude {} is out of range (expected -180 to 180)",
+                    location.longitude
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copy of the configuration with secrets masked, for showing to users
+    pub fn redacted(&self) -> Self {
+        const MASK: &str = "********";
+        let mut config = self.clone();
+        if config.influxdb.token.is_some() {
+            config.influxdb.token = Some(MASK.to_string());
+        }
+        if config.nut.password.is_some() {
+            config.nut.password = Some(MASK.to_string());
+        }
+        config
+    }
+
+    /// USB defaults of the controller with device id `id`
+    pub fn usb_defaults_for(&self, id: &str) -> Option<&UsbDefaultsConfig> {
+        if id == DEFAULT_DEVICE_ID {
+            return Some(&self.usb_defaults);
+        }
+        self.devices
+            .iter()
+            .find(|device| device.id == id)
+            .map(|device| &device.usb_defaults)
+    }
+
+    /// Validate additional device ids and locations
+    ///
+    /// Part of [`validate`](Self::validate); the daemon also checks this
+    /// separately at startup since the ids become API paths.
+    pub fn validate_devices(&self) -> Result<(), ConfigError> {
+        let mut ids = vec![DEFAULT_DEVICE_ID];
+        let mut locations = vec![(self.i2c_device.as_ref(), self.i2c_bus, self.i2c_addr)];
+        for device in &self.devices {
+            let valid_id = !device.id.is_empty()
+                && device
+                    .id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid_id {
+                return Err(ConfigError::InvalidValue(format!(
+                    "devices: invalid id '{}' (expected letters, digits, '-' and '_')",
+                    device.id
+                )));
+            }
+            if ids.contains(&device.id.as_str()) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "devices: id '{}' is already in use",
+                    device.id
+                )));
+            }
+            ids.push(&device.id);
+
+            // Devices given by path are compared after resolving at startup
+            let location = (device.i2c_device.as_ref(), device.i2c_bus, device.i2c_addr);
+            if location.0.is_none() && locations.contains(&location) {
+                return Err(ConfigError::InvalidValue(format!(
+                    "devices: '{}' uses bus {}, address 0x{:02X} of another controller",
+                    device.id, device.i2c_bus, device.i2c_addr
+                )));
+            }
+            locations.push(location);
+        }
+        Ok(())
+    }
+
+    /// Apply the settings explicitly set in `other` on top of this one
+    ///
+    /// Used to layer the configuration: defaults, then the file, then the
+    /// command line. An I2C bus number without a device path also clears
+    /// the path of the lower layer, since the path would take precedence.
+    pub fn merge(&mut self, other: PartialConfig) {
+        if let Some(i2c_bus) = other.i2c_bus {
+            self.i2c_bus = i2c_bus;
+            if other.i2c_device.is_none() {
+                self.i2c_device = None;
+            }
+        }
+        if let Some(i2c_addr) = other.i2c_addr {
+            self.i2c_addr = i2c_addr;
+        }
+        if let Some(i2c_device) = other.i2c_device {
+            self.i2c_device = Some(i2c_device);
+        }
+        if let Some(i2c_pec) = other.i2c_pec {
+            self.i2c_pec = i2c_pec;
+        }
+        if let Some(usb_defaults) = other.usb_defaults {
+            self.usb_defaults = usb_defaults;
+        }
+        if let Some(devices) = other.devices {
+            self.devices = devices;
+        }
+        if let Some(blackout_time_limit) = other.blackout_time_limit {
+            self.blackout_time_limit = blackout_time_limit;
+        }
+        if let Some(blackout_voltage_limit) = other.blackout_voltage_limit {
+            self.blackout_voltage_limit = blackout_voltage_limit;
+        }
+        if let Some(blackout_voltage_hysteresis) = other.blackout_voltage_hysteresis {
+            self.blackout_voltage_hysteresis = blackout_voltage_hysteresis;
+        }
+        if let Some(blackout_action) = other.blackout_action {
+            self.blackout_action = blackout_action;
+        }
+        if let Some(blackout_wake_after) = other.blackout_wake_after {
+            self.blackout_wake_after = blackout_wake_after;
+        }
+        if let Some(blackout_command) = other.blackout_command {
+            self.blackout_command = blackout_command;
+        }
+        if let Some(runtime_shutdown) = other.runtime_shutdown {
+            self.runtime_shutdown = runtime_shutdown;
+        }
+        if let Some(shutdown_duration) = other.shutdown_duration {
+            self.shutdown_duration = shutdown_duration;
+        }
+        if let Some(poll_interval) = other.poll_interval {
+            self.poll_interval = poll_interval;
+        }
+        if let Some(watchdog_timeout) = other.watchdog_timeout {
+            self.watchdog_timeout = watchdog_timeout;
+        }
+        if let Some(socket) = other.socket {
+            self.socket = Some(socket);
+        }
+        if let Some(socket_group) = other.socket_group {
+            self.socket_group = socket_group;
+        }
+        if let Some(user) = other.user {
+            self.user = Some(user);
+        }
+        if let Some(group) = other.group {
+            self.group = Some(group);
+        }
+        if let Some(poweroff) = other.poweroff {
+            self.poweroff = poweroff;
+        }
+        if let Some(logind) = other.logind {
+            self.logind = logind;
+        }
+        if let Some(nmea2000) = other.nmea2000 {
+            self.nmea2000 = nmea2000;
+        }
+        if let Some(influxdb) = other.influxdb {
+            self.influxdb = influxdb;
+        }
+        if let Some(upower) = other.upower {
+            self.upower = upower;
+        }
+        if let Some(nut) = other.nut {
+            self.nut = nut;
+        }
+        if let Some(snmp) = other.snmp {
+            self.snmp = snmp;
+        }
+        if let Some(webhooks) = other.webhooks {
+            self.webhooks = webhooks;
+        }
+        if let Some(hooks) = other.hooks {
+            self.hooks = hooks;
+        }
+        if let Some(shutdown_grace) = other.shutdown_grace {
+            self.shutdown_grace = shutdown_grace;
+        }
+        if let Some(temperature) = other.temperature {
+            self.temperature = temperature;
+        }
+        if let Some(input_current) = other.input_current {
+            self.input_current = input_current;
+        }
+        if let Some(calibration) = other.calibration {
+            self.calibration = calibration;
+        }
+        if let Some(measurement_filter) = other.measurement_filter {
+            self.measurement_filter = measurement_filter;
+        }
+        if let Some(voltage_events) = other.voltage_events {
+            self.voltage_events = voltage_events;
+        }
+        if let Some(controller) = other.controller {
+            self.controller = controller;
+        }
+        if let Some(self_test) = other.self_test {
+            self.self_test = self_test;
+        }
+        if let Some(kernel_watchdog) = other.kernel_watchdog {
+            self.kernel_watchdog = kernel_watchdog;
+        }
+        if let Some(gpio_button) = other.gpio_button {
+            self.gpio_button = gpio_button;
+        }
+        if let Some(led_night) = other.led_night {
+            self.led_night = led_night;
+        }
+        if let Some(health_checks) = other.health_checks {
+            self.health_checks = health_checks;
+        }
+        if let Some(power_schedule) = other.power_schedule {
+            self.power_schedule = power_schedule;
+        }
+        if let Some(location) = other.location {
+            self.location = Some(location);
+        }
+        if let Some(logging) = other.logging {
+            self.logging = logging;
+        }
+        if let Some(cors) = other.cors {
+            self.cors = cors;
+        }
+        if let Some(api_limits) = other.api_limits {
+            self.api_limits = api_limits;
+        }
+    }
+}
+
+/// Read a YAML file; an empty file is an empty mapping
+fn read_yaml(path: &std::path::Path) -> Result<serde_yaml::Value, ConfigError> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| ConfigError::FileRead(path.into(), e))?;
+    let value: serde_yaml::Value = serde_yaml::from_str(&contents)
+        .map_err(|e| ConfigError::YamlParse(path.into(), e.to_string()))?;
+    Ok(match value {
+        serde_yaml::Value::Null => serde_yaml::Value::Mapping(Default::default()),
+        value => value,
+    })
+}
+
+/// `*.conf` files in `dir`, sorted by name
+fn dropin_files(dir: &std::path::Path) -> Result<Vec<PathBuf>, ConfigError> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(ConfigError::FileRead(dir.into(), e)),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|e| ConfigError::FileRead(dir.into(), e))?
+            .path();
+        if path.extension().is_some_and(|ext| ext == "conf") && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Merge `overlay` into `base`, recursing into mappings present in both
+fn merge_yaml(base: &mut serde_yaml::Value, overlay: serde_yaml::Value) {
+    match (base, overlay) {
+        (serde_yaml::Value::Mapping(base), serde_yaml::Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_yaml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Configuration loading errors
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file {0}: {1}")]
+    FileRead(PathBuf, #[source] std::io::Error),
+
+    #[error("Failed to parse YAML config file {0}: {1}")]
+    YamlParse(PathBuf, String),
+
+    #[error("Invalid configuration value: {0}")]
+    InvalidValue(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PowerState;
+
+    #[test]
+    fn test_default_config() {
+        let config = Config::default();
+        assert_eq!(config.i2c_bus, 1);
+        assert_eq!(config.i2c_addr, 0x6D);
+        assert_eq!(config.blackout_time_limit, 5.0);
+        assert_eq!(config.blackout_voltage_limit, 9.0);
+        assert_eq!(config.socket, None);
+        assert_eq!(config.socket_group, "adm");
+        assert_eq!(config.poweroff, "/sbin/poweroff");
+        assert!(config.logind);
+    }
+
+    #[test]
+    fn test_logind_power_off() {
+        let mut config = Config::default();
+        assert!(config.logind_power_off());
+
+        config.poweroff = "/usr/local/bin/halt".to_string();
+        assert!(config.logind);
+        assert!(!config.logind_power_off());
+
+        config.poweroff = DEFAULT_POWEROFF_COMMAND.to_string();
+        config.logind = false;
+        assert!(!config.logind_power_off());
+    }
+
+    #[test]
+    fn test_validate_valid_config() {
+        let config = Config::default();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_invalid_blackout_time() {
+        let config = Config {
+            blackout_time_limit: -1.0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = Config {
+            blackout_time_limit: 5000.0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_invalid_blackout_voltage() {
+        let config = Config {
+            blackout_voltage_limit: 3.0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = Config {
+            blackout_voltage_limit: 20.0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_blackout_voltage_hysteresis() {
+        let config: Config =
+            serde_yaml::from_str("blackout-voltage-limit: 9.0\nblackout-voltage-hysteresis: 0.5\n")
+                .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.blackout_recovery_voltage(), 9.5);
+        // Off by default, so blackouts end as in the Python daemon
+        assert_eq!(
+            Config::default().blackout_recovery_voltage(),
+            DEFAULT_BLACKOUT_VOLTAGE_LIMIT
+        );
+
+        for hysteresis in [-0.1, 5.0, f64::NAN] {
+            let config = Config {
+                blackout_voltage_hysteresis: hysteresis,
+                ..Default::default()
+            };
+            assert!(config.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn test_temperature_yaml() {
+        let yaml = "temperature:\n  enabled: true\n  shed-usb-ports: [2, 3]\n  shutdown: 90\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.temperature.enabled);
+        assert_eq!(config.temperature.warning, DEFAULT_TEMPERATURE_WARNING);
+        assert_eq!(config.temperature.shutdown, 90.0);
+        assert_eq!(config.temperature.shed_mask(), 0b1100);
+        assert!(config.validate().is_ok());
+
+        let mut config = config;
+        config.temperature.shed = 95.0;
+        assert!(config.validate().is_err());
+        config.temperature.shed = 80.0;
+        config.temperature.shed_usb_ports = vec![4];
+        assert!(config.validate().is_err());
+
+        // Not checked while disabled
+        config.temperature.enabled = false;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_input_current_yaml() {
+        let yaml = "input-current:\n  enabled: true\n  limit: 3.5\n  shed-usb-ports: [0]\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.input_current.enabled);
+        assert_eq!(config.input_current.limit, 3.5);
+        assert_eq!(config.input_current.sustain, DEFAULT_INPUT_CURRENT_SUSTAIN);
+        assert_eq!(config.input_current.shed_mask(), 0b0001);
+        assert!(config.validate().is_ok());
+
+        let mut config = config;
+        config.input_current.sustain = 0.0;
+        assert!(config.validate().is_err());
+        config.input_current.sustain = 5.0;
+        config.input_current.shed_usb_ports = vec![7];
+        assert!(config.validate().is_err());
+
+        // Not checked while disabled
+        config.input_current.enabled = false;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_calibration() {
+        let yaml =
+            "calibration:\n  v-in:\n    offset: -0.12\n  t-pcb:\n    gain: 1.1\n    offset: 2\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.calibration.v_in.gain, 1.0);
+        assert_eq!(config.calibration.calibrated_keys(), ["V_in", "T_pcb"]);
+        assert!(Config::default().calibration.is_empty());
+
+        let mut measurements = Measurements {
+            dcin_voltage: Volts(12.0),
+            supercap_voltage: Volts(9.5),
+            input_current: Amps(0.5),
+            mcu_temperature: Kelvin(300.0),
+            pcb_temperature: Celsius(20.0).kelvin(),
+            power_state: PowerState::OperationalCoOp,
+            watchdog_elapsed: 0.0,
+            measured_at: Default::default(),
+        };
+        config.calibration.apply(&mut measurements);
+        assert!((measurements.dcin_voltage.0 - 11.88).abs() < 1e-4);
+        assert_eq!(measurements.supercap_voltage, Volts(9.5));
+        assert_eq!(measurements.mcu_temperature, Kelvin(300.0));
+        // The gain scales degrees Celsius
+        assert!((measurements.pcb_temperature.celsius().0 - 24.0).abs() < 1e-3);
+
+        let mut config = config;
+        config.calibration.i_in.gain = 0.0;
+        assert!(config.validate().is_err());
+        config.calibration.i_in.gain = 1.0;
+        config.calibration.i_in.offset = f64::NAN;
+        assert!(config.validate().is_err());
+        config.calibration.i_in.offset = 0.0;
+
+        // Additional controllers have their own calibration
+        let config: Config = serde_yaml::from_str(
+            "devices:\n  - id: expansion\n    i2c-addr: 0x6E\n    calibration:\n      i-in:\n        offset: 0.05\n",
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert!(config.calibration_for(DEFAULT_DEVICE_ID).is_empty());
+        assert_eq!(config.calibration_for("expansion").i_in.offset, 0.05);
+    }
+
+    #[test]
+    fn test_measurement_filter_yaml() {
+        let yaml = "measurement-filter:\n  v-in:\n    reject-glitches: true\n    smoothing: 0.5\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.validate().is_ok());
+        assert!(config.measurement_filter.v_in.reject_glitches);
+        assert!(config.measurement_filter.v_supercap.is_off());
+        assert!(!config.measurement_filter.is_off());
+        assert!(Config::default().measurement_filter.is_off());
+
+        let mut config = config;
+        config.measurement_filter.t_pcb.smoothing = 1.0;
+        assert!(config.validate().is_err());
+        config.measurement_filter.t_pcb.smoothing = -0.1;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_voltage_events_yaml() {
+        let yaml = "voltage-events:\n  enabled: true\n  sag: 11.5\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.voltage_events.enabled);
+        assert_eq!(config.voltage_events.sag, 11.5);
+        assert_eq!(config.voltage_events.surge, DEFAULT_VOLTAGE_SURGE);
+        assert!(config.validate().is_ok());
+
+        let mut config = config;
+        config.voltage_events.surge = 11.0;
+        assert!(config.validate().is_err());
+        config.voltage_events.surge = 15.0;
+        config.voltage_events.min_duration = -1.0;
+        assert!(config.validate().is_err());
+
+        // Not checked while disabled
+        config.voltage_events.enabled = false;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_controller_yaml() {
+        let yaml = "controller:\n  led-brightness: 40\n  auto-restart: true\n  enforce: true\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.controller.led_brightness, Some(40));
+        assert_eq!(config.controller.power_on_threshold, None);
+        assert_eq!(
+            config.controller.check_interval,
+            DEFAULT_CONTROLLER_CHECK_INTERVAL
+        );
+        assert!(config.controller.enforce);
+        assert!(!config.controller.is_empty());
+        assert!(Config::default().controller.is_empty());
+        assert!(config.validate().is_ok());
+
+        let mut config = config;
+        config.controller.power_on_threshold = Some(12.0);
+        assert!(config.validate().is_err());
+        config.controller.power_on_threshold = Some(9.0);
+        config.controller.check_interval = 0.0;
+        assert!(config.validate().is_err());
+        config.controller.check_interval = config.watchdog_timeout;
+        assert!(config.validate().is_err());
+        config.controller.check_interval = DEFAULT_CONTROLLER_CHECK_INTERVAL;
+        // The night dimming would be undone at every check
+        config.led_night.enabled = true;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_self_test_yaml() {
+        let config = Config::default();
+        assert!(config.self_test.enabled);
+        assert_eq!(
+            config.self_test.min_firmware().unwrap(),
+            crate::types::Version::new(2, 0, 0)
+        );
+
+        let config: Config = serde_yaml::from_str("self-test:\n  min-firmware: 3.2.0\n").unwrap();
+        assert!(config.self_test.enabled);
+        assert_eq!(
+            config.self_test.min_firmware().unwrap(),
+            crate::types::Version::new(3, 2, 0)
+        );
+        assert!(config.validate().is_ok());
+
+        let mut config = config;
+        config.self_test.min_firmware = "3.2".to_string();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_user_group_yaml() {
+        let config = Config::default();
+        assert_eq!(config.user, None);
+        assert_eq!(config.group, None);
+
+        let config: Config = serde_yaml::from_str(
+            "user: halpid
+group: i2c
+",
+        )
+        .unwrap();
+        assert_eq!(config.user.as_deref(), Some("halpid"));
+        assert_eq!(config.group.as_deref(), Some("i2c"));
+        assert!(config.validate().is_ok());
+
+        let mut config = config;
+        config.user = Some(String::new());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_kernel_watchdog_yaml() {
+        let config: Config =
+            serde_yaml::from_str("kernel-watchdog:\n  enabled: true\n  timeout: 15\n").unwrap();
+        assert!(config.kernel_watchdog.enabled);
+        assert_eq!(
+            config.kernel_watchdog.device,
+            PathBuf::from(DEFAULT_KERNEL_WATCHDOG_DEVICE)
+        );
+        assert_eq!(config.kernel_watchdog.timeout, Some(15));
+        assert!(config.validate().is_ok());
+
+        let mut config = config;
+        config.kernel_watchdog.timeout = Some(0);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_power_schedule_yaml() {
+        let yaml = "power-schedule:
+  - off: \"01:00\"
+    on: \"06:00\"
+  - off: \"22:00\"
+    on: \"07:30\"
+    days: [mon, Tue, friday]
+";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let windows = &config.power_schedule;
+        assert_eq!(windows.len(), 2);
+        assert!(windows[0].starts_on(chrono::Weekday::Sun));
+        assert_eq!(
+            windows[1].days,
+            [
+                chrono::Weekday::Mon,
+                chrono::Weekday::Tue,
+                chrono::Weekday::Fri
+            ]
+        );
+        assert!(!windows[1].starts_on(chrono::Weekday::Sun));
+        assert!(config.validate().is_ok());
+
+        let mut config = config;
+        config.power_schedule[0].on = "6am".into();
+        assert!(config.validate().is_err());
+        config.power_schedule[0].on = "01:00".into();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_location_yaml() {
+        let config: Config =
+            serde_yaml::from_str("location: {latitude: 60.17, longitude: 24.94}").unwrap();
+        assert_eq!(
+            config.location,
+            Some(LocationConfig {
+                latitude: 60.17,
+                longitude: 24.94
+            })
+        );
+        assert!(config.validate().is_ok());
+        assert_eq!(Config::default().location, None);
+
+        let mut config = config;
+        config.location = Some(LocationConfig {
+            latitude: 91.0,
+            longitude: 24.94,
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_gpio_button_yaml() {
+        let yaml = "gpio-button:
+  enabled: true
+  pin: 17
+  debounce: 2
+";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let button = &config.gpio_button;
+        assert_eq!(button.pin, Some(17));
+        assert_eq!(button.chip, Path::new(DEFAULT_GPIO_CHIP));
+        assert!(button.active_low);
+        assert_eq!(button.bias, GpioBias::PullUp);
+        assert_eq!(button.debounce, 2.0);
+        assert!(config.validate().is_ok());
+
+        let mut config = config;
+        config.gpio_button.pin = None;
+        assert!(config.validate().is_err());
+        config.gpio_button.enabled = false;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_led_night_yaml() {
+        let yaml = "led-night:
+  enabled: true
+  start: \"23:30\"
+  day-brightness: 200
+";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let night = &config.led_night;
+        assert_eq!(night.start, "23:30");
+        assert_eq!(night.end, DEFAULT_LED_NIGHT_END);
+        assert_eq!(night.brightness, DEFAULT_LED_NIGHT_BRIGHTNESS);
+        assert_eq!(night.day_brightness, Some(200));
+        assert!(config.validate().is_ok());
+
+        let mut config = config;
+        config.led_night.end = "23:30".into();
+        assert!(config.validate().is_err());
+        config.led_night.end = "7am".into();
+        assert!(config.validate().is_err());
+        config.led_night.enabled = false;
+        assert!(config.validate().is_ok());
+
+        let yaml = "led-night:\n  brightness: 300\n";
+        assert!(serde_yaml::from_str::<Config>(yaml).is_err());
+    }
+
+    #[test]
+    fn test_health_checks_yaml() {
+        let yaml = "health-checks:
+  - name: signalk
+    http: http://localhost:3000/signalk
+    action: reboot
+  - name: gps
+    file: /run/gps/last-fix
+    max-age: 60
+    interval: 10
+    action: power-cycle-usb
+    usb-port: 2
+";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let checks = &config.health_checks;
+        assert_eq!(checks.len(), 2);
+        assert_eq!(checks[0].interval, DEFAULT_HEALTH_CHECK_INTERVAL);
+        assert_eq!(checks[0].failures, DEFAULT_HEALTH_CHECK_FAILURES);
+        assert_eq!(
+            checks[0].probe(),
+            Ok(HealthProbe::Http("http://localhost:3000/signalk"))
+        );
+        assert_eq!(
+            checks[1].probe(),
+            Ok(HealthProbe::FileAge(Path::new("/run/gps/last-fix"), 60.0))
+        );
+        assert!(config.validate().is_ok());
+
+        let mut config = config;
+        config.health_checks[1].name = "signalk".into();
+        assert!(config.validate().is_err());
+        config.health_checks[1].name = "gps".into();
+        config.health_checks[1].usb_port = None;
+        assert!(config.validate().is_err());
+        config.health_checks[1].usb_port = Some(2);
+        config.health_checks[1].tcp = Some("localhost:10110".into());
+        assert!(config.validate().is_err());
+        config.health_checks[1].tcp = None;
+        config.health_checks[1].max_age = None;
+        assert!(config.validate().is_err());
+        config.health_checks[1].max_age = Some(60.0);
+        config.health_checks[0].http = Some("localhost:3000".into());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_shutdown_duration() {
+        let config: Config = serde_yaml::from_str("shutdown-duration: 0\n").unwrap();
+        assert_eq!(config.shutdown_duration, 0.0);
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            Config::default().shutdown_duration,
+            DEFAULT_SHUTDOWN_DURATION
+        );
+        // Blackouts only end with the time limit unless turned on
+        assert!(!Config::default().runtime_shutdown);
+        let config: Config = serde_yaml::from_str(
+            "runtime-shutdown: true
+",
+        )
+        .unwrap();
+        assert!(config.runtime_shutdown);
+        assert_eq!(config.shutdown_duration, DEFAULT_SHUTDOWN_DURATION);
 
         let config = Config {
-            blackout_voltage_limit: 20.0,
+            shutdown_duration: 600.0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_reloadable() {
+        // Startup-only settings are not checked on reload
+        let config = Config {
+            i2c_bus: 13,
             ..Default::default()
         };
         assert!(config.validate().is_err());
+        assert!(config.validate_reloadable().is_ok());
+
+        let config = Config {
+            blackout_voltage_limit: 3.0,
+            ..Default::default()
+        };
+        assert!(config.validate_reloadable().is_err());
+    }
+
+    #[test]
+    fn test_load_dropins() {
+        let dir = std::env::temp_dir().join(format!("halpi-config-{}", std::process::id()));
+        let path = dir.join("halpid.conf");
+        let dropins = Config::dropin_dir(&path);
+        assert_eq!(dropins, dir.join("halpid.conf.d"));
+        std::fs::create_dir_all(&dropins).unwrap();
+
+        std::fs::write(
+            &path,
+            "blackout-time-limit: 10.0\ninfluxdb:\n  bucket: boat\n",
+        )
+        .unwrap();
+        // Without fragments, the main file is loaded as is
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config, Config::from_file(&path).unwrap());
+
+        std::fs::write(
+            dropins.join("20-signalk.conf"),
+            "influxdb:\n  enabled: true\n  url: http://localhost:8086\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dropins.join("10-limits.conf"),
+            "blackout-time-limit: 20.0\n",
+        )
+        .unwrap();
+        std::fs::write(dropins.join("30-override.conf"), "").unwrap();
+        std::fs::write(dropins.join("ignored.bak"), "not: [valid").unwrap();
+
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.blackout_time_limit, 20.0);
+        assert!(config.influxdb.enabled);
+        assert_eq!(config.influxdb.url, "http://localhost:8086");
+        // Settings from the main file in the same section are kept
+        assert_eq!(config.influxdb.bucket, "boat");
+
+        std::fs::write(dropins.join("40-bad.conf"), "unknown-key: 1\n").unwrap();
+        assert!(matches!(
+            Config::load(&path),
+            Err(ConfigError::YamlParse(..))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
@@ -272,6 +3776,7 @@ blackout-time-limit: 10.0
 blackout-voltage-limit: 8.5
 socket-group: users
 poweroff: /usr/bin/poweroff
+logind: false
 "#;
         let config: Config = serde_yaml::from_str(yaml).unwrap();
         assert_eq!(config.i2c_bus, 2);
@@ -280,6 +3785,105 @@ poweroff: /usr/bin/poweroff
         assert_eq!(config.blackout_voltage_limit, 8.5);
         assert_eq!(config.socket_group, "users");
         assert_eq!(config.poweroff, "/usr/bin/poweroff");
+        assert!(!config.logind);
+        assert_eq!(config.i2c_device, None);
+        assert!(!config.i2c_pec);
+    }
+
+    #[test]
+    fn test_usb_defaults_yaml() {
+        let yaml = "usb-defaults:
+  usb2: false
+devices:
+  - id: expansion
+    i2c-addr: 0x6E
+    usb-defaults:
+      usb0: true
+";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let defaults = config.usb_defaults;
+        assert_eq!(defaults.ports(), [None, None, Some(false), None]);
+        assert_eq!(defaults.apply(0b1111), 0b1011);
+        assert_eq!(defaults.apply(0b0000), 0b0000);
+        assert_eq!(config.devices[0].usb_defaults.apply(0b0000), 0b0001);
+        assert!(!defaults.is_empty());
+        assert!(Config::default().usb_defaults.is_empty());
+        assert_eq!(config.usb_defaults_for(DEFAULT_DEVICE_ID), Some(&defaults));
+        assert_eq!(
+            config.usb_defaults_for("expansion").unwrap().usb0,
+            Some(true)
+        );
+        assert_eq!(config.usb_defaults_for("missing"), None);
+
+        assert!(serde_yaml::from_str::<Config>("usb-defaults:\n  usb4: false\n").is_err());
+    }
+
+    #[test]
+    fn test_devices() {
+        let yaml = r#"
+devices:
+  - id: expansion
+    i2c-addr: 0x6E
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.devices.len(), 1);
+        assert_eq!(config.devices[0].id, "expansion");
+        assert_eq!(config.devices[0].i2c_bus, DEFAULT_I2C_BUS);
+        assert_eq!(config.devices[0].i2c_addr, 0x6E);
+        assert!(config.validate().is_ok());
+
+        let device = |id: &str, addr| DeviceConfig {
+            id: id.to_string(),
+            i2c_bus: DEFAULT_I2C_BUS,
+            i2c_addr: addr,
+            i2c_device: None,
+            usb_defaults: UsbDefaultsConfig::default(),
+            calibration: CalibrationConfig::default(),
+        };
+        for devices in [
+            vec![device("default", 0x6E)],
+            vec![device("a/b", 0x6E)],
+            vec![device("", 0x6E)],
+            vec![device("a", 0x6E), device("a", 0x6F)],
+            // Same location as the primary controller
+            vec![device("a", DEFAULT_I2C_ADDR)],
+        ] {
+            let config = Config {
+                devices,
+                ..Default::default()
+            };
+            assert!(config.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn test_i2c_pec() {
+        let config: Config = serde_yaml::from_str("i2c-pec: true\n").unwrap();
+        assert!(config.i2c_pec);
+
+        let mut base = Config::default();
+        base.merge(serde_yaml::from_str("i2c-pec: true\n").unwrap());
+        assert!(base.i2c_pec);
+    }
+
+    #[test]
+    fn test_i2c_device_path() {
+        let yaml = "i2c-device: /dev/i2c-usb\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.i2c_device, Some(PathBuf::from("/dev/i2c-usb")));
+        assert_eq!(config.i2c_bus, DEFAULT_I2C_BUS);
+
+        let mut base = Config::default();
+        base.merge(serde_yaml::from_str(yaml).unwrap());
+        assert_eq!(base.i2c_device, Some(PathBuf::from("/dev/i2c-usb")));
+
+        // A bus number from a higher layer replaces the path
+        base.merge(PartialConfig {
+            i2c_bus: Some(3),
+            ..Default::default()
+        });
+        assert_eq!(base.i2c_device, None);
+        assert_eq!(base.i2c_bus, 3);
     }
 
     #[test]
@@ -295,9 +3899,9 @@ blackout-time-limit: 15.0
     #[test]
     fn test_config_merge() {
         let mut base = Config::default();
-        let override_config = Config {
-            i2c_bus: 3,
-            blackout_time_limit: 20.0,
+        let override_config = PartialConfig {
+            i2c_bus: Some(3),
+            blackout_time_limit: Some(20.0),
             ..Default::default()
         };
 
@@ -306,5 +3910,403 @@ blackout-time-limit: 15.0
         assert_eq!(base.i2c_bus, 3);
         assert_eq!(base.blackout_time_limit, 20.0);
         assert_eq!(base.socket_group, "adm"); // unchanged
+
+        // An empty layer changes nothing
+        let before = base.clone();
+        base.merge(PartialConfig::default());
+        assert_eq!(base, before);
+    }
+
+    #[test]
+    fn test_blackout_action() {
+        assert_eq!(Config::default().blackout_action, BlackoutAction::Poweroff);
+
+        let config: Config =
+            serde_yaml::from_str("blackout-action: standby\nblackout-wake-after: 7200\n").unwrap();
+        assert_eq!(config.blackout_action, BlackoutAction::Standby);
+        assert_eq!(config.blackout_wake_after, 7200.0);
+        assert!(config.validate().is_ok());
+
+        let config: Config = serde_yaml::from_str("blackout-action: command\n").unwrap();
+        assert!(config.validate().is_err());
+        let config = Config {
+            blackout_command: "systemctl suspend".to_string(),
+            ..config
+        };
+        assert!(config.validate().is_ok());
+
+        let config = Config {
+            blackout_action: BlackoutAction::Standby,
+            blackout_wake_after: 10.0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        assert!(serde_yaml::from_str::<Config>("blackout-action: reboot\n").is_err());
+    }
+
+    #[test]
+    fn test_timing() {
+        let config: Config =
+            serde_yaml::from_str("poll-interval: 0.5\nwatchdog-timeout: 30\n").unwrap();
+        assert_eq!(config.poll_interval, 0.5);
+        assert_eq!(config.watchdog_timeout_ms(), 30000);
+        assert!(config.validate().is_ok());
+        assert_eq!(Config::default().watchdog_timeout_ms(), 10000);
+
+        for (poll_interval, watchdog_timeout) in [
+            (0.0, 10.0),
+            (2.0, 30.0),
+            (0.1, 0.5),
+            (0.1, 70.0),
+            (0.5, 4.0),
+        ] {
+            let config = Config {
+                poll_interval,
+                watchdog_timeout,
+                ..Default::default()
+            };
+            assert!(config.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn test_config_merge_precedence() {
+        // defaults < file < command line, even for values equal to the default
+        let file: PartialConfig = serde_yaml::from_str(
+            "socket-group: users\npoweroff: /usr/local/bin/halt\nnut:\n  enabled: true\n",
+        )
+        .unwrap();
+        let cli = PartialConfig {
+            socket_group: Some(DEFAULT_SOCKET_GROUP.to_string()),
+            poweroff: Some(DEFAULT_POWEROFF_COMMAND.to_string()),
+            ..Default::default()
+        };
+
+        let mut config = Config::from(file.clone());
+        assert_eq!(config.socket_group, "users");
+        assert_eq!(config.poweroff, "/usr/local/bin/halt");
+        assert!(config.nut.enabled);
+
+        config.merge(cli);
+        assert_eq!(config.socket_group, DEFAULT_SOCKET_GROUP);
+        assert_eq!(config.poweroff, DEFAULT_POWEROFF_COMMAND);
+        assert!(config.nut.enabled);
+
+        // A file that restates a default still overrides an earlier layer
+        let mut config = Config::from(file);
+        config.merge(serde_yaml::from_str("nut:\n  enabled: false\ni2c-pec: false\n").unwrap());
+        assert_eq!(config.nut, NutConfig::default());
+        assert!(!config.i2c_pec);
+        assert_eq!(config.socket_group, "users");
+    }
+
+    #[test]
+    fn test_yaml_nmea2000_section() {
+        let yaml = r#"
+nmea2000:
+  enabled: true
+  interface: can1
+  supercap-instance: 3
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.nmea2000.enabled);
+        assert_eq!(config.nmea2000.interface, "can1");
+        assert_eq!(config.nmea2000.supply_instance, 0); // default
+        assert_eq!(config.nmea2000.supercap_instance, 3);
+        assert_eq!(config.nmea2000.interval, DEFAULT_N2K_INTERVAL);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_invalid_nmea2000() {
+        let mut config = Config::default();
+        config.nmea2000.enabled = true;
+        config.nmea2000.source_address = 254;
+        assert!(config.validate().is_err());
+
+        // Disabled sections are not validated
+        config.nmea2000.enabled = false;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_yaml_influxdb_section() {
+        let yaml = r#"
+influxdb:
+  enabled: true
+  url: http://influx.local:8086
+  org: boat
+  bucket: telemetry
+  token: secret
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.influxdb.enabled);
+        assert_eq!(config.influxdb.org.as_deref(), Some("boat"));
+        assert_eq!(config.influxdb.bucket, "telemetry");
+        assert_eq!(config.influxdb.measurement, DEFAULT_INFLUX_MEASUREMENT);
+        assert_eq!(config.influxdb.buffer_size, DEFAULT_INFLUX_BUFFER_SIZE);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_invalid_influxdb() {
+        let mut config = Config::default();
+        config.influxdb.enabled = true;
+        config.influxdb.url = "influx.local:8086".to_string();
+        config.influxdb.bucket = "halpi".to_string();
+        assert!(config.validate().is_err());
+
+        config.influxdb.url = "http://influx.local:8086".to_string();
+        assert!(config.validate().is_ok());
+
+        config.influxdb.bucket.clear();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_invalid_upower() {
+        let mut config = Config::default();
+        config.upower.enabled = true;
+        assert!(config.validate().is_ok());
+
+        config.upower.interval = 0.1;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_invalid_nut() {
+        let mut config = Config::default();
+        config.nut.enabled = true;
+        assert!(config.validate().is_ok());
+
+        config.nut.listen = "localhost".to_string();
+        assert!(config.validate().is_err());
+        config.nut.listen = DEFAULT_NUT_LISTEN.to_string();
+
+        config.nut.ups_name = "my ups".to_string();
+        assert!(config.validate().is_err());
+        config.nut.ups_name = DEFAULT_NUT_UPS_NAME.to_string();
+
+        config.nut.username = Some("monuser".to_string());
+        assert!(config.validate().is_err());
+        config.nut.password = Some("secret".to_string());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_snmp_base_oid() {
+        let mut config = Config::default();
+        config.snmp.enabled = true;
+        assert!(config.validate().is_err()); // base-oid is required
+
+        config.snmp.base_oid = "1.3.6.1.4.1.x".to_string();
+        assert!(config.validate().is_err());
+
+        config.snmp.base_oid = "1.3.6.1.4.1.8072.9999".to_string();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_webhooks_yaml() {
+        let yaml = r#"
+webhooks:
+  enabled: true
+  endpoints:
+    - url: https://ntfy.sh/my-halpi
+    - url: http://hooks.local/power
+      events: [blackout-start, blackout-end]
+  temperature-limit: 65
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.webhooks.enabled);
+        assert_eq!(config.webhooks.endpoints.len(), 2);
+        assert_eq!(config.webhooks.temperature_limit, 65.0);
+        assert_eq!(config.webhooks.retries, DEFAULT_WEBHOOK_RETRIES);
+
+        let all = &config.webhooks.endpoints[0];
+        assert!(all.wants(WebhookEvent::FirmwareUpdate));
+        let power = &config.webhooks.endpoints[1];
+        assert!(power.wants(WebhookEvent::BlackoutEnd));
+        assert!(!power.wants(WebhookEvent::TemperatureAlarm));
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_invalid_webhooks() {
+        let mut config = Config::default();
+        config.webhooks.enabled = true;
+        assert!(config.validate().is_err()); // no endpoints
+
+        config.webhooks.endpoints.push(WebhookEndpoint {
+            url: "ntfy.sh/my-halpi".to_string(),
+            events: Vec::new(),
+        });
+        assert!(config.validate().is_err());
+
+        config.webhooks.endpoints[0].url = "https://ntfy.sh/my-halpi".to_string();
+        assert!(config.validate().is_ok());
+
+        config.webhooks.retry_delay = 0.0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_hooks_yaml() {
+        let yaml = r#"
+hooks:
+  pre-shutdown: /usr/local/bin/stop-opencpn
+  blackout-start: /usr/local/bin/sync-logs
+  timeout: 20
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            config.hooks.path(HookEvent::PreShutdown),
+            Some(std::path::Path::new("/usr/local/bin/stop-opencpn"))
+        );
+        assert_eq!(config.hooks.path(HookEvent::StandbyEnter), None);
+        assert_eq!(config.hooks.timeout, 20.0);
+        assert!(config.validate().is_ok());
+
+        let mut config = Config::default();
+        config.hooks.power_restored = Some(PathBuf::from("restart-opencpn"));
+        assert!(config.validate().is_err());
+        config.hooks.power_restored = None;
+        config.hooks.timeout = 0.0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_shutdown_grace_yaml() {
+        let config: Config =
+            serde_yaml::from_str("shutdown-grace:\n  period: 30\n  wall: false\n").unwrap();
+        assert_eq!(config.shutdown_grace.period, 30.0);
+        assert!(!config.shutdown_grace.wall);
+        assert_eq!(
+            config.shutdown_grace.supercap_margin,
+            DEFAULT_GRACE_SUPERCAP_MARGIN
+        );
+        assert_eq!(
+            config.shutdown_grace.abort_window,
+            DEFAULT_GRACE_ABORT_WINDOW
+        );
+        assert!(config.validate().is_ok());
+
+        let mut config = Config::default();
+        assert_eq!(config.shutdown_grace.period, 0.0);
+        config.shutdown_grace.period = 600.0;
+        assert!(config.validate().is_err());
+        config.shutdown_grace.period = 0.0;
+        config.shutdown_grace.abort_window = 90.0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_cors_yaml() {
+        let yaml = r#"
+cors:
+  allowed-origins:
+    - https://halpi.local:9090
+    - http://localhost:3000
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.cors.allowed_origins.len(), 2);
+        assert!(!config.cors.allows_any());
+        assert!(config.validate().is_ok());
+        assert!(Config::default().cors.allowed_origins.is_empty());
+
+        let mut config = Config::default();
+        config.cors.allowed_origins = vec!["*".to_string()];
+        assert!(config.cors.allows_any());
+        assert!(config.validate().is_ok());
+        for origin in ["halpi.local", "https://halpi.local/", "ftp://halpi.local"] {
+            config.cors.allowed_origins = vec![origin.to_string()];
+            assert!(config.validate().is_err(), "{}", origin);
+        }
+    }
+
+    #[test]
+    fn test_api_limits_yaml() {
+        let config: Config = serde_yaml::from_str("api-limits:\n  rate: 5\n").unwrap();
+        assert_eq!(config.api_limits.rate, 5.0);
+        assert_eq!(config.api_limits.burst, DEFAULT_API_BURST);
+        assert!(config.validate().is_ok());
+
+        let mut config = Config::default();
+        config.api_limits.rate = 0.0;
+        assert!(config.validate().is_ok());
+        config.api_limits.rate = -1.0;
+        assert!(config.validate().is_err());
+        config.api_limits.rate = 1.0;
+        config.api_limits.burst = 0;
+        assert!(config.validate().is_err());
+        config.api_limits.burst = 1;
+        config.api_limits.max_concurrent = 0;
+        assert!(config.validate().is_err());
+        config.api_limits.max_concurrent = 1;
+        config.api_limits.max_firmware_kb = 32;
+        assert!(config.validate().is_err());
+        assert_eq!(Config::default().api_limits.max_firmware_size(), 2 << 20);
+    }
+
+    #[test]
+    fn test_logging_yaml() {
+        let yaml = r#"
+logging:
+  file: /var/log/halpid/halpid.log
+  rotation: daily
+  keep: 7
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            config.logging.file,
+            Some(PathBuf::from("/var/log/halpid/halpid.log"))
+        );
+        assert_eq!(config.logging.rotation, LogRotation::Daily);
+        assert_eq!(config.logging.max_size_mb, DEFAULT_LOG_MAX_SIZE_MB);
+        assert_eq!(config.logging.keep, 7);
+        assert!(!config.logging.i2c_trace);
+        assert!(config.validate().is_ok());
+
+        let mut config = config;
+        config.logging.keep = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_i2c_trace_yaml() {
+        let yaml = r#"
+logging:
+  i2c-trace: true
+  i2c-trace-file: /var/log/halpid/i2c.log
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.logging.i2c_trace);
+        assert_eq!(
+            config.logging.i2c_trace_file,
+            Some(PathBuf::from("/var/log/halpid/i2c.log"))
+        );
+    }
+
+    #[test]
+    fn test_access_log_yaml() {
+        let config: Config = serde_yaml::from_str("logging:\n  access-log: true\n").unwrap();
+        assert!(config.logging.access_log_enabled());
+        assert!(!Config::default().logging.access_log_enabled());
+
+        let yaml = "logging:\n  access-log-file: /var/log/halpid/access.log\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(!config.logging.access_log);
+        assert!(config.logging.access_log_enabled());
+    }
+
+    #[test]
+    fn test_log_format() {
+        let config: Config = serde_yaml::from_str("logging:\n  format: json\n").unwrap();
+        assert_eq!(config.logging.format, LogFormat::Json);
+        assert_eq!(Config::default().logging.format, LogFormat::Text);
+
+        assert_eq!("json".parse::<LogFormat>(), Ok(LogFormat::Json));
+        assert_eq!("text".parse::<LogFormat>(), Ok(LogFormat::Text));
+        assert!("xml".parse::<LogFormat>().is_err());
     }
 }
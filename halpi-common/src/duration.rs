@@ -0,0 +1,104 @@
+//! Human-friendly durations
+//!
+//! Shared by the CLI arguments and the API request bodies that take a
+//! delay or duration, so both accept the same syntax.
+
+use serde::{Deserialize, Deserializer};
+
+/// Parse a duration such as `90`, `90s`, `90m`, `2h30m` or `1d` into seconds
+///
+/// A plain number is in seconds. Otherwise, the duration is a sequence of
+/// numbers with a unit each: `d`, `h`, `m` or `s`.
+pub fn parse_duration(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let invalid = || format!("invalid duration '{}' (e.g. 90s, 30m, 2h30m, 1d)", text);
+    if text.is_empty() {
+        return Err(invalid());
+    }
+    if text.bytes().all(|b| b.is_ascii_digit()) {
+        return text.parse::<u64>().map_err(|_| invalid());
+    }
+
+    let mut rest = text;
+    let mut total: u64 = 0;
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .filter(|split| *split > 0)
+            .ok_or_else(invalid)?;
+        let (number, tail) = rest.split_at(split);
+        let mut chars = tail.chars();
+        let multiplier = match chars.next() {
+            Some('s') => 1,
+            Some('m') => 60,
+            Some('h') => 3600,
+            Some('d') => 86400,
+            _ => return Err(invalid()),
+        };
+        total = number
+            .parse::<u64>()
+            .ok()
+            .and_then(|number| number.checked_mul(multiplier))
+            .and_then(|seconds| total.checked_add(seconds))
+            .ok_or_else(invalid)?;
+        rest = chars.as_str();
+    }
+    Ok(total)
+}
+
+/// Deserialize a duration in seconds from a number or a duration string
+///
+/// For use with `#[serde(deserialize_with = "...")]`.
+pub fn deserialize_seconds<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Seconds {
+        Number(u64),
+        Text(String),
+    }
+
+    match Seconds::deserialize(deserializer)? {
+        Seconds::Number(seconds) => Ok(seconds),
+        Seconds::Text(text) => parse_duration(&text).map_err(serde::de::Error::custom),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Ok(90));
+        assert_eq!(parse_duration("90s"), Ok(90));
+        assert_eq!(parse_duration("30m"), Ok(1800));
+        assert_eq!(parse_duration("90m"), Ok(5400));
+        assert_eq!(parse_duration("2h"), Ok(7200));
+        assert_eq!(parse_duration("2h30m"), Ok(9000));
+        assert_eq!(parse_duration("1d"), Ok(86400));
+        assert_eq!(parse_duration("1d2h3m4s"), Ok(93784));
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("2h30").is_err());
+        assert!(parse_duration("5 weeks").is_err());
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("99999999999999999999d").is_err());
+    }
+
+    #[test]
+    fn test_deserialize_seconds() {
+        #[derive(Deserialize)]
+        struct Request {
+            #[serde(deserialize_with = "deserialize_seconds")]
+            delay: u64,
+        }
+
+        let parse = |json: &str| serde_json::from_str::<Request>(json).map(|r| r.delay);
+        assert_eq!(parse(r#"{"delay": 300}"#).unwrap(), 300);
+        assert_eq!(parse(r#"{"delay": "2h30m"}"#).unwrap(), 9000);
+        assert!(parse(r#"{"delay": "soon"}"#).is_err());
+        assert!(parse(r#"{"delay": -5}"#).is_err());
+    }
+}
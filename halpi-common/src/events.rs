@@ -0,0 +1,275 @@
+//! Daemon events
+//!
+//! Typed events published on the daemon's internal event bus. They are
+//! serializable so the same representation can be streamed to clients.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::types::{Measurements, PowerState};
+use crate::units::Volts;
+
+/// An event published by the daemon
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum DaemonEvent {
+    /// A fresh measurement sample from the controller
+    Measurement(Sample),
+    /// The controller power state changed
+    StateTransition {
+        timestamp: DateTime<Utc>,
+        from: PowerState,
+        to: PowerState,
+    },
+    /// A condition the daemon acted on or wants to draw attention to
+    Alert(Alert),
+    /// The input voltage left the configured range for a while
+    VoltageExcursion(VoltageExcursion),
+    /// Firmware upload progress
+    Dfu {
+        timestamp: DateTime<Utc>,
+        #[serde(flatten)]
+        progress: DfuProgress,
+    },
+}
+
+impl DaemonEvent {
+    /// Short event name, matching the serialized `type` tag
+    pub fn name(&self) -> &'static str {
+        match self {
+            DaemonEvent::Measurement(_) => "measurement",
+            DaemonEvent::StateTransition { .. } => "state-transition",
+            DaemonEvent::Alert(_) => "alert",
+            DaemonEvent::VoltageExcursion(_) => "voltage-excursion",
+            DaemonEvent::Dfu { .. } => "dfu",
+        }
+    }
+
+    /// When the event happened
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        match self {
+            DaemonEvent::Measurement(sample) => sample.timestamp,
+            DaemonEvent::StateTransition { timestamp, .. } | DaemonEvent::Dfu { timestamp, .. } => {
+                *timestamp
+            }
+            DaemonEvent::Alert(alert) => alert.timestamp,
+            DaemonEvent::VoltageExcursion(excursion) => excursion.timestamp,
+        }
+    }
+}
+
+/// A timestamped measurement sample
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub measurements: Measurements,
+}
+
+impl Sample {
+    /// Stamp measurements with the time they were read
+    pub fn new(measurements: Measurements) -> Self {
+        Self {
+            timestamp: measurements.measured_at,
+            measurements,
+        }
+    }
+
+    /// Stamp measurements with the current time
+    pub fn now(measurements: Measurements) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            measurements,
+        }
+    }
+}
+
+/// Alert categories
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AlertKind {
+    /// Input voltage dropped below the blackout limit
+    BlackoutDetected,
+    /// Input voltage recovered before the blackout time limit
+    PowerRestored,
+    /// The daemon started the shutdown sequence
+    ShutdownInitiated,
+    /// A pending shutdown was cancelled before the blackout action
+    ShutdownCancelled,
+    /// The controller temperature exceeded a protection limit
+    OverTemperature,
+    /// The controller temperature is back below the warning limit
+    TemperatureNormal,
+    /// The daemon started a shutdown because of the controller temperature
+    OverTemperatureShutdown,
+    /// The input current stayed above the overcurrent limit
+    InputOvercurrent,
+    /// The input current stayed below the overcurrent limit again
+    InputCurrentNormal,
+    /// The input current rose abruptly above its average
+    InputCurrentSpike,
+    /// A health check failed repeatedly; its action is being taken
+    HealthCheckFailed,
+    /// A health check that had failed repeatedly passes again
+    HealthCheckRecovered,
+}
+
+/// An alert raised by the daemon
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Alert {
+    pub timestamp: DateTime<Utc>,
+    pub kind: AlertKind,
+    pub message: String,
+}
+
+impl Alert {
+    /// Create an alert stamped with the current time
+    pub fn new(kind: AlertKind, message: impl Into<String>) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+/// Direction of a voltage excursion
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExcursionKind {
+    /// The input voltage dropped below the sag limit
+    Sag,
+    /// The input voltage rose above the surge limit
+    Surge,
+}
+
+impl ExcursionKind {
+    /// Name as serialized, e.g. "sag"
+    pub fn name(self) -> &'static str {
+        match self {
+            ExcursionKind::Sag => "sag",
+            ExcursionKind::Surge => "surge",
+        }
+    }
+}
+
+/// A sag or surge of the input voltage, published once it is over
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VoltageExcursion {
+    /// When the voltage left the range
+    pub timestamp: DateTime<Utc>,
+    pub kind: ExcursionKind,
+    /// Lowest voltage of a sag, highest of a surge
+    pub extreme: Volts,
+    /// How far the extreme was beyond the limit
+    pub depth: Volts,
+    /// Seconds the voltage stayed beyond the limit
+    pub duration: f64,
+}
+
+/// Firmware upload stages
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "stage", rename_all = "kebab-case")]
+pub enum DfuProgress {
+    /// Upload of `total` bytes started
+    Started { total: usize },
+    /// `written` of `total` bytes have been transferred
+    Writing { written: usize, total: usize },
+    /// The image was transferred and committed
+    Completed { total: usize },
+    /// The upload failed
+    Failed { error: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Amps, Kelvin, Volts};
+
+    fn measurements() -> Measurements {
+        Measurements {
+            dcin_voltage: Volts(12.0),
+            supercap_voltage: Volts(9.5),
+            input_current: Amps(0.5),
+            mcu_temperature: Kelvin(300.0),
+            pcb_temperature: Kelvin(300.0),
+            power_state: PowerState::OperationalCoOp,
+            watchdog_elapsed: 0.0,
+            measured_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_measurement_event_json() {
+        let event = DaemonEvent::Measurement(Sample::now(measurements()));
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "measurement");
+        assert_eq!(json["dcin_voltage"], 12.0);
+        assert!(json["timestamp"].is_string());
+
+        let back: DaemonEvent = serde_json::from_value(json).unwrap();
+        assert_eq!(back, event);
+    }
+
+    #[test]
+    fn test_sample_measured_at() {
+        let mut measurements = measurements();
+        measurements.measured_at -= chrono::Duration::seconds(5);
+        let sample = Sample::new(measurements.clone());
+        assert_eq!(sample.timestamp, measurements.measured_at);
+
+        // Samples of daemons that did not report it read as the epoch
+        let mut json = serde_json::to_value(&sample).unwrap();
+        json.as_object_mut().unwrap().remove("measured_at");
+        let old: Sample = serde_json::from_value(json).unwrap();
+        assert_eq!(old.measurements.measured_at, DateTime::<Utc>::default());
+    }
+
+    #[test]
+    fn test_voltage_excursion_json() {
+        let event = DaemonEvent::VoltageExcursion(VoltageExcursion {
+            timestamp: Utc::now(),
+            kind: ExcursionKind::Sag,
+            extreme: Volts(10.5),
+            depth: Volts(0.5),
+            duration: 1.2,
+        });
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "voltage-excursion");
+        assert_eq!(json["kind"], "sag");
+        assert_eq!(json["depth"], 0.5);
+        assert_eq!(event.name(), "voltage-excursion");
+
+        let back: DaemonEvent = serde_json::from_value(json).unwrap();
+        assert_eq!(back, event);
+    }
+
+    #[test]
+    fn test_dfu_event_json() {
+        let event = DaemonEvent::Dfu {
+            timestamp: Utc::now(),
+            progress: DfuProgress::Writing {
+                written: 4096,
+                total: 65536,
+            },
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "dfu");
+        assert_eq!(json["stage"], "writing");
+        assert_eq!(json["written"], 4096);
+        assert_eq!(event.name(), "dfu");
+    }
+
+    #[test]
+    fn test_alert_event_json() {
+        let event = DaemonEvent::Alert(Alert::new(AlertKind::ShutdownInitiated, "test"));
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "alert");
+        assert_eq!(json["kind"], "shutdown-initiated");
+        assert_eq!(json["message"], "test");
+        assert_eq!(
+            event.timestamp(),
+            serde_json::from_value::<DateTime<Utc>>(json["timestamp"].clone()).unwrap()
+        );
+    }
+}
@@ -1,8 +1,12 @@
 //! Shared types and utilities for HALPI2 daemon and CLI
 
+pub mod api;
 pub mod config;
+pub mod duration;
 pub mod error;
+pub mod events;
 pub mod protocol;
 pub mod types;
+pub mod units;
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -5,6 +5,9 @@
 
 use serde::{Deserialize, Serialize};
 
+use crate::types::Version;
+use crate::units::ZERO_CELSIUS;
+
 /// Flash block size for firmware updates (4 KiB)
 pub const FLASH_BLOCK_SIZE: usize = 4096;
 
@@ -50,6 +53,12 @@ pub const REG_SOLO_DEPLETING_TIMEOUT: u8 = 0x19;
 /// USB port enable state (byte, bitfield for 4 ports)
 pub const REG_USB_PORT_STATE: u8 = 0x1A;
 
+/// LED pattern (byte, LedPattern enum)
+pub const REG_LED_PATTERN: u8 = 0x1B;
+
+/// LED color (3 bytes: red, green, blue)
+pub const REG_LED_COLOR: u8 = 0x1C;
+
 /// DC input voltage (word, analog scaled)
 pub const REG_DCIN_VOLTAGE: u8 = 0x20;
 
@@ -65,6 +74,43 @@ pub const REG_MCU_TEMPERATURE: u8 = 0x23;
 /// PCB temperature (word, analog scaled, Kelvin)
 pub const REG_PCB_TEMPERATURE: u8 = 0x24;
 
+/// Length of the measurement block (bytes)
+///
+/// Firmware that supports block reads returns the five analog words
+/// 0x20–0x24 in one transfer starting at `REG_DCIN_VOLTAGE`, and the state
+/// and watchdog elapsed bytes 0x15–0x16 in one transfer starting at
+/// `REG_STATE`.
+pub const MEASUREMENT_BLOCK_LEN: usize = 10;
+
+/// First firmware version supporting measurement block reads
+pub const MEASUREMENT_BLOCK_MIN_FIRMWARE: (u8, u8, u8) = (3, 1, 0);
+
+/// First firmware version encoding analog values as 16-bit words
+///
+/// Firmware 2.x and earlier use a single byte per analog register (see
+/// [`analog_byte_to_float`]).
+pub const WORD_ANALOG_MIN_FIRMWARE: (u8, u8, u8) = (3, 0, 0);
+
+/// First firmware version with the LED brightness register
+pub const LED_BRIGHTNESS_MIN_FIRMWARE: (u8, u8, u8) = (2, 0, 0);
+
+/// First firmware version with the LED pattern and color registers
+pub const LED_PATTERN_MIN_FIRMWARE: (u8, u8, u8) = (3, 3, 0);
+
+/// First firmware version that checks and appends SMBus PEC bytes
+pub const PEC_MIN_FIRMWARE: (u8, u8, u8) = (3, 2, 0);
+
+/// First firmware version with the USB port power register
+pub const USB_PORTS_MIN_FIRMWARE: (u8, u8, u8) = (2, 0, 0);
+
+/// First hardware version with switchable USB port power
+///
+/// Prototype boards (hardware 0.x) power the USB ports directly.
+pub const USB_PORTS_MIN_HARDWARE: (u8, u8, u8) = (1, 0, 0);
+
+/// Hardware major versions whose registers this protocol describes
+pub const SUPPORTED_HARDWARE_MAJOR: std::ops::RangeInclusive<u8> = 0..=1;
+
 /// Device unique ID (8 bytes)
 pub const REG_DEVICE_ID: u8 = 0x25;
 
@@ -246,6 +292,175 @@ impl DFUState {
     }
 }
 
+// ============================================================================
+// LED Pattern and Color
+// ============================================================================
+
+/// Front LED pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[repr(u8)]
+pub enum LedPattern {
+    /// The firmware indicates the power state (default)
+    Status = 0,
+    /// LED off
+    Off = 1,
+    /// Steady light in the set color
+    Solid = 2,
+    /// Blink once a second
+    Blink = 3,
+    /// Blink four times a second
+    FastBlink = 4,
+    /// Fade in and out
+    Pulse = 5,
+}
+
+impl LedPattern {
+    pub const ALL: [LedPattern; 6] = [
+        LedPattern::Status,
+        LedPattern::Off,
+        LedPattern::Solid,
+        LedPattern::Blink,
+        LedPattern::FastBlink,
+        LedPattern::Pulse,
+    ];
+
+    /// Create LedPattern from a byte value
+    pub fn from_byte(value: u8) -> Result<Self, ProtocolError> {
+        Self::ALL
+            .into_iter()
+            .find(|pattern| pattern.to_byte() == value)
+            .ok_or(ProtocolError::InvalidLedPattern(value))
+    }
+
+    /// Convert LedPattern to byte value
+    pub fn to_byte(self) -> u8 {
+        self as u8
+    }
+
+    /// Pattern name as used in the API
+    pub fn name(self) -> &'static str {
+        match self {
+            LedPattern::Status => "status",
+            LedPattern::Off => "off",
+            LedPattern::Solid => "solid",
+            LedPattern::Blink => "blink",
+            LedPattern::FastBlink => "fast-blink",
+            LedPattern::Pulse => "pulse",
+        }
+    }
+}
+
+impl std::str::FromStr for LedPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|pattern| pattern.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(|pattern| pattern.name()).collect();
+                format!(
+                    "unknown LED pattern '{}' (expected {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Front LED color, written as `#rrggbb` or a basic color name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct LedColor {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl LedColor {
+    /// Named colors accepted besides `#rrggbb`
+    const NAMES: [(&'static str, [u8; 3]); 8] = [
+        ("red", [0xFF, 0x00, 0x00]),
+        ("green", [0x00, 0xFF, 0x00]),
+        ("blue", [0x00, 0x00, 0xFF]),
+        ("white", [0xFF, 0xFF, 0xFF]),
+        ("yellow", [0xFF, 0xFF, 0x00]),
+        ("orange", [0xFF, 0x80, 0x00]),
+        ("cyan", [0x00, 0xFF, 0xFF]),
+        ("magenta", [0xFF, 0x00, 0xFF]),
+    ];
+
+    /// Create LedColor from the register bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProtocolError> {
+        match bytes {
+            [red, green, blue, ..] => Ok(Self {
+                red: *red,
+                green: *green,
+                blue: *blue,
+            }),
+            _ => Err(ProtocolError::InsufficientData {
+                expected: 3,
+                got: bytes.len(),
+            }),
+        }
+    }
+
+    /// Convert LedColor to register bytes
+    pub fn to_bytes(self) -> [u8; 3] {
+        [self.red, self.green, self.blue]
+    }
+}
+
+impl std::fmt::Display for LedColor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.red, self.green, self.blue)
+    }
+}
+
+impl std::str::FromStr for LedColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid LED color '{}' (expected #rrggbb or a color name)",
+                s
+            )
+        };
+        if let Some((_, bytes)) = Self::NAMES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+        {
+            return Self::from_bytes(bytes).map_err(|_| invalid());
+        }
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+        Ok(Self {
+            red: component(0)?,
+            green: component(2)?,
+            blue: component(4)?,
+        })
+    }
+}
+
+impl TryFrom<String> for LedColor {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<LedColor> for String {
+    fn from(color: LedColor) -> Self {
+        color.to_string()
+    }
+}
+
 // ============================================================================
 // Encoding/Decoding Functions
 // ============================================================================
@@ -266,6 +481,19 @@ pub fn decode_word(bytes: &[u8]) -> Result<u16, ProtocolError> {
     Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
 }
 
+/// Decode `N` consecutive big-endian 16-bit values
+pub fn decode_words<const N: usize>(bytes: &[u8]) -> Result<[u16; N], ProtocolError> {
+    if bytes.len() < 2 * N {
+        return Err(ProtocolError::InsufficientData {
+            expected: 2 * N,
+            got: bytes.len(),
+        });
+    }
+    Ok(std::array::from_fn(|i| {
+        u16::from_be_bytes([bytes[2 * i], bytes[2 * i + 1]])
+    }))
+}
+
 /// Encode a 32-bit value as big-endian bytes
 pub fn encode_u32(value: u32) -> [u8; 4] {
     value.to_be_bytes()
@@ -282,6 +510,41 @@ pub fn decode_u32(bytes: &[u8]) -> Result<u32, ProtocolError> {
     Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
 }
 
+// ============================================================================
+// Packet Error Checking
+// ============================================================================
+
+/// SMBus packet error code (CRC-8, polynomial x^8 + x^2 + x + 1)
+///
+/// Computed over every byte on the wire, including the address bytes: for a
+/// register read, `[addr << 1, reg, addr << 1 | 1, data...]`; for a write,
+/// `[addr << 1, reg, data...]`.
+pub fn smbus_pec(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// PEC of a register read of `data` from the device at `addr`
+pub fn read_pec(addr: u8, reg: u8, data: &[u8]) -> u8 {
+    let mut bytes = vec![addr << 1, reg, (addr << 1) | 1];
+    bytes.extend_from_slice(data);
+    smbus_pec(&bytes)
+}
+
+/// PEC of a register write of `data` to the device at `addr`
+pub fn write_pec(addr: u8, reg: u8, data: &[u8]) -> u8 {
+    let mut bytes = vec![addr << 1, reg];
+    bytes.extend_from_slice(data);
+    smbus_pec(&bytes)
+}
+
 // ============================================================================
 // Analog Scaling Functions
 // ============================================================================
@@ -334,22 +597,152 @@ pub const DCIN_MAX: f32 = 40.0;
 pub const I_MAX: f32 = 3.3;
 
 /// Minimum temperature in Kelvin (-40°C)
-pub const TEMP_MIN_KELVIN: f32 = 273.15 - 40.0;
+pub const TEMP_MIN_KELVIN: f32 = ZERO_CELSIUS - 40.0;
 
 /// Maximum temperature in Kelvin (+100°C)
-pub const TEMP_MAX_KELVIN: f32 = 273.15 + 100.0;
+pub const TEMP_MAX_KELVIN: f32 = ZERO_CELSIUS + 100.0;
 
 /// Temperature range (TEMP_MAX_KELVIN - TEMP_MIN_KELVIN)
 pub const TEMP_RANGE_KELVIN: f32 = TEMP_MAX_KELVIN - TEMP_MIN_KELVIN;
 
-/// Convert Kelvin to Celsius
-pub fn kelvin_to_celsius(kelvin: f32) -> f32 {
-    kelvin - 273.15
+// ============================================================================
+// Firmware Features
+// ============================================================================
+
+/// Protocol features that depend on the firmware or hardware version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// Analog registers encoded as 16-bit words instead of bytes
+    WordAnalog,
+    /// Measurement block reads
+    MeasurementBlock,
+    /// LED brightness register
+    LedBrightness,
+    /// SMBus packet error checking
+    Pec,
+    /// LED pattern and color registers
+    LedPattern,
+    /// USB port power switches
+    UsbPorts,
 }
 
-/// Convert Celsius to Kelvin
-pub fn celsius_to_kelvin(celsius: f32) -> f32 {
-    celsius + 273.15
+impl Feature {
+    pub const ALL: [Feature; 6] = [
+        Feature::WordAnalog,
+        Feature::MeasurementBlock,
+        Feature::LedBrightness,
+        Feature::Pec,
+        Feature::LedPattern,
+        Feature::UsbPorts,
+    ];
+
+    /// First firmware version supporting the feature
+    pub fn min_firmware(self) -> Version {
+        let (major, minor, patch) = match self {
+            Feature::WordAnalog => WORD_ANALOG_MIN_FIRMWARE,
+            Feature::MeasurementBlock => MEASUREMENT_BLOCK_MIN_FIRMWARE,
+            Feature::LedBrightness => LED_BRIGHTNESS_MIN_FIRMWARE,
+            Feature::Pec => PEC_MIN_FIRMWARE,
+            Feature::LedPattern => LED_PATTERN_MIN_FIRMWARE,
+            Feature::UsbPorts => USB_PORTS_MIN_FIRMWARE,
+        };
+        Version::new(major, minor, patch)
+    }
+
+    /// First hardware version with the feature, if it depends on the
+    /// hardware
+    pub fn min_hardware(self) -> Option<Version> {
+        match self {
+            Feature::UsbPorts => {
+                let (major, minor, patch) = USB_PORTS_MIN_HARDWARE;
+                Some(Version::new(major, minor, patch))
+            }
+            _ => None,
+        }
+    }
+
+    /// True if `firmware` supports the feature
+    ///
+    /// An unavailable version supports no optional features. The hardware
+    /// is not considered; see [`Capabilities::supports`].
+    pub fn is_supported(self, firmware: &Version) -> bool {
+        !firmware.is_unavailable() && *firmware >= self.min_firmware()
+    }
+
+    /// Key of the feature in capability maps, e.g. `led_pattern`
+    pub fn key(self) -> &'static str {
+        match self {
+            Feature::WordAnalog => "word_analog",
+            Feature::MeasurementBlock => "measurement_block",
+            Feature::LedBrightness => "led_brightness",
+            Feature::Pec => "pec",
+            Feature::LedPattern => "led_pattern",
+            Feature::UsbPorts => "usb_ports",
+        }
+    }
+
+    /// Human-readable feature name, for error messages
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::WordAnalog => "word-encoded analog registers",
+            Feature::MeasurementBlock => "measurement block reads",
+            Feature::LedBrightness => "LED brightness",
+            Feature::Pec => "packet error checking",
+            Feature::LedPattern => "LED patterns and colors",
+            Feature::UsbPorts => "USB port power switching",
+        }
+    }
+}
+
+/// Hardware and firmware versions of a controller, which determine the
+/// features it has
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub hardware: Version,
+    pub firmware: Version,
+}
+
+impl Capabilities {
+    /// True if both the firmware and the hardware support `feature`
+    ///
+    /// An unprogrammed hardware version is taken to support every feature,
+    /// since the hardware revision cannot be told.
+    pub fn supports(&self, feature: Feature) -> bool {
+        feature.is_supported(&self.firmware)
+            && feature
+                .min_hardware()
+                .is_none_or(|min| self.hardware.is_unavailable() || self.hardware >= min)
+    }
+
+    /// Every feature with whether the controller has it
+    pub fn map(&self) -> Vec<(Feature, bool)> {
+        Feature::ALL
+            .into_iter()
+            .map(|feature| (feature, self.supports(feature)))
+            .collect()
+    }
+
+    /// Why `feature` is missing, e.g. "LED patterns and colors requires
+    /// firmware 3.3.0 or later (controller runs 3.2.0)"
+    pub fn explain(&self, feature: Feature) -> String {
+        if !feature.is_supported(&self.firmware) {
+            return format!(
+                "{} requires firmware {} or later (controller runs {})",
+                feature.name(),
+                feature.min_firmware(),
+                self.firmware
+            );
+        }
+        match feature.min_hardware() {
+            Some(min) if !self.supports(feature) => format!(
+                "{} requires hardware {} or later (controller is {})",
+                feature.name(),
+                min,
+                self.hardware
+            ),
+            _ => format!("{} is supported", feature.name()),
+        }
+    }
 }
 
 // ============================================================================
@@ -365,6 +758,9 @@ pub enum ProtocolError {
     #[error("Invalid DFU state value: {0}")]
     InvalidDFUState(u8),
 
+    #[error("Invalid LED pattern value: {0}")]
+    InvalidLedPattern(u8),
+
     #[error("Insufficient data: expected {expected} bytes, got {got}")]
     InsufficientData { expected: usize, got: usize },
 }
@@ -372,6 +768,7 @@ pub enum ProtocolError {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::units::{Celsius, Kelvin};
 
     #[test]
     fn test_encode_decode_word() {
@@ -381,6 +778,119 @@ mod tests {
         assert_eq!(decode_word(&bytes).unwrap(), value);
     }
 
+    #[test]
+    fn test_decode_words() {
+        let bytes = [0x12, 0x34, 0x00, 0x01, 0xFF, 0xFF];
+        assert_eq!(decode_words::<3>(&bytes).unwrap(), [0x1234, 0x0001, 0xFFFF]);
+        assert_eq!(decode_words::<2>(&bytes).unwrap(), [0x1234, 0x0001]);
+        assert!(matches!(
+            decode_words::<4>(&bytes),
+            Err(ProtocolError::InsufficientData {
+                expected: 8,
+                got: 6
+            })
+        ));
+    }
+
+    #[test]
+    fn test_smbus_pec() {
+        // CRC-8/SMBUS check value
+        assert_eq!(smbus_pec(b"123456789"), 0xF4);
+        assert_eq!(smbus_pec(&[]), 0x00);
+
+        assert_eq!(
+            read_pec(0x6D, REG_STATE, &[0x02]),
+            smbus_pec(&[0xDA, 0x15, 0xDB, 0x02])
+        );
+        assert_eq!(
+            write_pec(0x6D, REG_LED_BRIGHTNESS, &[0x80]),
+            smbus_pec(&[0xDA, 0x17, 0x80])
+        );
+    }
+
+    #[test]
+    fn test_feature_support() {
+        let legacy = Version::new(2, 5, 0);
+        let current = Version::new(3, 1, 0);
+        assert!(!Feature::WordAnalog.is_supported(&legacy));
+        assert!(Feature::WordAnalog.is_supported(&current));
+        assert!(Feature::LedBrightness.is_supported(&legacy));
+        assert!(!Feature::LedBrightness.is_supported(&Version::new(1, 9, 0)));
+        assert!(!Feature::MeasurementBlock.is_supported(&Version::new_alpha(3, 1, 0, 2)));
+        assert!(Feature::MeasurementBlock.is_supported(&current));
+
+        let unavailable = Version::from_bytes([255, 255, 255, 255]);
+        assert!(!Feature::WordAnalog.is_supported(&unavailable));
+        assert_eq!(Feature::LedBrightness.min_firmware().to_string(), "2.0.0");
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let mut capabilities = Capabilities {
+            hardware: Version::new(1, 0, 0),
+            firmware: Version::new(3, 2, 0),
+        };
+        assert!(capabilities.supports(Feature::UsbPorts));
+        assert!(!capabilities.supports(Feature::LedPattern));
+        assert_eq!(
+            capabilities.explain(Feature::LedPattern),
+            "LED patterns and colors requires firmware 3.3.0 or later (controller runs 3.2.0)"
+        );
+
+        capabilities.hardware = Version::new(0, 9, 0);
+        assert!(!capabilities.supports(Feature::UsbPorts));
+        assert!(capabilities.supports(Feature::Pec));
+        assert_eq!(
+            capabilities.explain(Feature::UsbPorts),
+            "USB port power switching requires hardware 1.0.0 or later (controller is 0.9.0)"
+        );
+        let map = capabilities.map();
+        assert_eq!(map.len(), Feature::ALL.len());
+        assert!(map.contains(&(Feature::UsbPorts, false)));
+
+        // Unprogrammed hardware
+        capabilities.hardware = Version::from_bytes([0, 0, 255, 255]);
+        assert!(capabilities.supports(Feature::UsbPorts));
+    }
+
+    #[test]
+    fn test_led_pattern() {
+        for pattern in LedPattern::ALL {
+            assert_eq!(LedPattern::from_byte(pattern.to_byte()).unwrap(), pattern);
+            assert_eq!(pattern.name().parse::<LedPattern>(), Ok(pattern));
+            assert_eq!(
+                serde_json::to_value(pattern).unwrap(),
+                serde_json::json!(pattern.name())
+            );
+        }
+        assert!(LedPattern::from_byte(6).is_err());
+        assert!("strobe".parse::<LedPattern>().is_err());
+        assert!(!Feature::LedPattern.is_supported(&Version::new(3, 2, 0)));
+    }
+
+    #[test]
+    fn test_led_color() {
+        let orange = LedColor {
+            red: 0xFF,
+            green: 0x80,
+            blue: 0x00,
+        };
+        assert_eq!("#ff8000".parse(), Ok(orange));
+        assert_eq!("FF8000".parse(), Ok(orange));
+        assert_eq!("Orange".parse(), Ok(orange));
+        assert_eq!(orange.to_string(), "#ff8000");
+        assert_eq!(LedColor::from_bytes(&orange.to_bytes()).unwrap(), orange);
+        assert!("#ff80".parse::<LedColor>().is_err());
+        assert!("#ff80zz".parse::<LedColor>().is_err());
+        assert!("+ff8000".parse::<LedColor>().is_err());
+        assert!(LedColor::from_bytes(&[1, 2]).is_err());
+
+        let json = serde_json::json!("#00ff00");
+        let green: LedColor = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(green).unwrap(), json);
+        assert!(serde_json::from_value::<LedColor>(serde_json::json!("#00ff")).is_err());
+    }
+
     #[test]
     fn test_encode_decode_u32() {
         let value: u32 = 0x12345678;
@@ -458,35 +968,21 @@ mod tests {
         assert_eq!(raw_back, raw);
     }
 
-    #[test]
-    fn test_temperature_conversion() {
-        // 0°C should be 273.15K
-        assert_eq!(celsius_to_kelvin(0.0), 273.15);
-        assert_eq!(kelvin_to_celsius(273.15), 0.0);
-
-        // 25°C should be 298.15K
-        let kelvin = celsius_to_kelvin(25.0);
-        assert!((kelvin - 298.15).abs() < 0.01);
-        assert!((kelvin_to_celsius(kelvin) - 25.0).abs() < 0.01);
-    }
-
     #[test]
     fn test_temperature_scaling() {
         // Temperature is stored as offset from TEMP_MIN
         // For 25°C (298.15K), offset from TEMP_MIN (233.15K) is 65K
-        let temp_celsius = 25.0;
-        let temp_kelvin = celsius_to_kelvin(temp_celsius);
-        let offset = temp_kelvin - TEMP_MIN_KELVIN;
+        let temp_celsius = Celsius(25.0);
+        let offset = temp_celsius.kelvin().0 - TEMP_MIN_KELVIN;
 
         // Encode as 16-bit value
         let raw = float_to_analog_word(offset, TEMP_RANGE_KELVIN);
 
         // Decode back
         let decoded_offset = analog_word_to_float(raw, TEMP_RANGE_KELVIN);
-        let decoded_kelvin = decoded_offset + TEMP_MIN_KELVIN;
-        let decoded_celsius = kelvin_to_celsius(decoded_kelvin);
+        let decoded_celsius = Kelvin(decoded_offset + TEMP_MIN_KELVIN).celsius();
 
         // Should match original (within tolerance)
-        assert!((decoded_celsius - temp_celsius).abs() < 0.5);
+        assert!((decoded_celsius.0 - temp_celsius.0).abs() < 0.5);
     }
 }
@@ -5,14 +5,21 @@
 //! - Measurements: Combined sensor readings from the device
 //! - PowerState: Current power management state
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+use crate::protocol::VCAP_MAX;
+use crate::units::{Amps, Kelvin, Volts};
+
 /// Version information for hardware or firmware
 ///
 /// Format: major.minor.patch[-alpha]
-/// Alpha byte 0xFF (255) indicates a release version (no alpha suffix)
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Alpha byte 0xFF (255) indicates a release version (no alpha suffix), so
+/// versions order with alpha releases before the corresponding release.
+/// Check [`Version::is_unavailable`] before comparing: its sentinel bytes
+/// order above or below every real version.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Version {
     pub major: u8,
     pub minor: u8,
@@ -82,6 +89,36 @@ impl fmt::Display for Version {
     }
 }
 
+impl std::str::FromStr for Version {
+    type Err = String;
+
+    /// Parse a version as displayed, e.g. "3.1.2" or "3.1.2-a5"
+    ///
+    /// "-a255" is refused, since alpha 255 is displayed as the release.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid version '{}'", text);
+        let (release, alpha) = match text.trim().split_once("-a") {
+            Some((release, alpha)) => (
+                release,
+                alpha
+                    .parse()
+                    .ok()
+                    .filter(|alpha| *alpha != 255)
+                    .ok_or_else(invalid)?,
+            ),
+            None => (text.trim(), 255),
+        };
+        let parts: Vec<u8> = release
+            .split('.')
+            .map(|part| part.parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        match parts[..] {
+            [major, minor, patch] => Ok(Self::new_alpha(major, minor, patch, alpha)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
 /// Power management state
 ///
 /// These values must match the HALPI2 firmware state machine exactly
@@ -126,6 +163,14 @@ impl PowerState {
         }
     }
 
+    /// True while the controller is running from the supercapacitor
+    pub fn is_blackout(&self) -> bool {
+        matches!(
+            self,
+            PowerState::BlackoutSolo | PowerState::BlackoutCoOp | PowerState::BlackoutShutdown
+        )
+    }
+
     /// Get the state name as a string
     pub fn name(&self) -> &'static str {
         match self {
@@ -153,37 +198,106 @@ impl fmt::Display for PowerState {
     }
 }
 
+impl std::str::FromStr for PowerState {
+    type Err = String;
+
+    /// Parse a state name, ignoring case, e.g. "OperationalCoOp"
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        (0..=u8::MAX)
+            .map_while(PowerState::from_byte)
+            .find(|state| state.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("Unknown power state '{}'", name))
+    }
+}
+
+/// Unit temperatures are displayed in
+///
+/// The controller and the API report Kelvin; clients convert with
+/// [`TemperatureUnit::convert_kelvin`] so all outputs agree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl TemperatureUnit {
+    /// Convert a temperature in Kelvin to this unit
+    pub fn convert_kelvin(self, kelvin: f64) -> f64 {
+        let celsius = kelvin - 273.15;
+        match self {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    /// Unit symbol, e.g. "°C"
+    pub fn symbol(self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "°C",
+            TemperatureUnit::Fahrenheit => "°F",
+        }
+    }
+}
+
+impl std::str::FromStr for TemperatureUnit {
+    type Err = String;
+
+    /// Parse a unit name or symbol, e.g. "fahrenheit" or "F"
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.trim_start_matches('°').to_ascii_lowercase().as_str() {
+            "c" | "celsius" => Ok(TemperatureUnit::Celsius),
+            "f" | "fahrenheit" => Ok(TemperatureUnit::Fahrenheit),
+            _ => Err(format!(
+                "Unknown temperature unit '{}', expected celsius or fahrenheit",
+                text
+            )),
+        }
+    }
+}
+
 /// Combined sensor measurements from the HALPI2 device
 ///
-/// All temperature values are stored in Kelvin internally but can be
-/// converted to Celsius for display using the helper methods.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Temperatures are in Kelvin, as reported by the controller; convert with
+/// [`Kelvin::celsius`] for display.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Measurements {
-    /// DC input voltage (V)
-    pub dcin_voltage: f32,
-    /// Supercapacitor voltage (V)
-    pub supercap_voltage: f32,
-    /// Input current (A)
-    pub input_current: f32,
-    /// MCU temperature (Kelvin)
-    pub mcu_temperature: f32,
-    /// PCB temperature (Kelvin)
-    pub pcb_temperature: f32,
+    /// DC input voltage
+    pub dcin_voltage: Volts,
+    /// Supercapacitor voltage
+    pub supercap_voltage: Volts,
+    /// Input current
+    pub input_current: Amps,
+    /// MCU temperature
+    pub mcu_temperature: Kelvin,
+    /// PCB temperature
+    pub pcb_temperature: Kelvin,
     /// Current power state
     pub power_state: PowerState,
     /// Watchdog elapsed time (seconds)
     pub watchdog_elapsed: f32,
+    /// When the values were read from the controller
+    ///
+    /// Lets consumers of cached or streamed measurements tell how old they
+    /// are; the Unix epoch if the daemon did not report it.
+    #[serde(default)]
+    pub measured_at: DateTime<Utc>,
 }
 
 impl Measurements {
-    /// Convert MCU temperature from Kelvin to Celsius
-    pub fn mcu_temperature_celsius(&self) -> f32 {
-        self.mcu_temperature - 273.15
-    }
-
-    /// Convert PCB temperature from Kelvin to Celsius
-    pub fn pcb_temperature_celsius(&self) -> f32 {
-        self.pcb_temperature - 273.15
+    /// Estimate the usable supercapacitor charge as a fraction (0.0-1.0)
+    ///
+    /// Stored energy scales with V², so the fraction is computed between
+    /// `empty_voltage` (where the controller cuts power) and `VCAP_MAX`.
+    pub fn supercap_charge(&self, empty_voltage: Volts) -> f32 {
+        let empty_voltage = empty_voltage.0;
+        let v = self.supercap_voltage.0.clamp(empty_voltage, VCAP_MAX);
+        let usable = VCAP_MAX * VCAP_MAX - empty_voltage * empty_voltage;
+        if usable <= 0.0 {
+            return 0.0;
+        }
+        (v * v - empty_voltage * empty_voltage) / usable
     }
 }
 
@@ -207,6 +321,17 @@ mod tests {
         assert!(!version.is_unavailable());
     }
 
+    #[test]
+    fn test_version_from_str() {
+        assert_eq!("3.1.2".parse(), Ok(Version::new(3, 1, 2)));
+        assert_eq!("3.1.2-a5".parse(), Ok(Version::new_alpha(3, 1, 2, 5)));
+        assert!("N/A".parse::<Version>().is_err());
+        assert!("3.1".parse::<Version>().is_err());
+        assert!("3.1.2-b1".parse::<Version>().is_err());
+        assert!("3.1.2-a255".parse::<Version>().is_err());
+        assert_eq!(" 3.1.2\n".parse(), Ok(Version::new(3, 1, 2)));
+    }
+
     #[test]
     fn test_version_from_bytes() {
         let bytes = [3, 1, 2, 255];
@@ -218,6 +343,16 @@ mod tests {
         assert_eq!(version_alpha.to_string(), "3.1.2-a5");
     }
 
+    #[test]
+    fn test_version_ordering() {
+        assert!(Version::new(3, 1, 0) > Version::new(3, 0, 9));
+        assert!(Version::new(3, 1, 0) > Version::new_alpha(3, 1, 0, 5));
+        assert!(Version::new_alpha(3, 1, 0, 5) > Version::new(3, 0, 0));
+        assert!(Version::new(4, 0, 0) > Version::new(3, 255, 255));
+        assert!("3.1.2-a9".parse::<Version>().unwrap() < "3.1.2".parse().unwrap());
+        assert!("3.1.2-a10".parse::<Version>().unwrap() > "3.1.2-a9".parse().unwrap());
+    }
+
     #[test]
     fn test_version_unavailable() {
         // Major = 0xFF indicates firmware not present
@@ -246,26 +381,77 @@ mod tests {
         assert_eq!(PowerState::Standby.name(), "Standby");
     }
 
+    #[test]
+    fn test_power_state_is_blackout() {
+        assert!(PowerState::BlackoutSolo.is_blackout());
+        assert!(PowerState::BlackoutShutdown.is_blackout());
+        assert!(!PowerState::OperationalCoOp.is_blackout());
+        assert!(!PowerState::PoweredDownBlackout.is_blackout());
+    }
+
     #[test]
     fn test_power_state_display() {
         assert_eq!(PowerState::PowerOff.to_string(), "PowerOff");
         assert_eq!(PowerState::OperationalCoOp.to_string(), "OperationalCoOp");
     }
 
+    #[test]
+    fn test_power_state_from_str() {
+        assert_eq!("OperationalCoOp".parse(), Ok(PowerState::OperationalCoOp));
+        assert_eq!("standby".parse(), Ok(PowerState::Standby));
+        assert!("Operational".parse::<PowerState>().is_err());
+    }
+
     #[test]
     fn test_measurements_temperature_conversion() {
         let measurements = Measurements {
-            dcin_voltage: 12.5,
-            supercap_voltage: 10.2,
-            input_current: 1.5,
-            mcu_temperature: 298.15, // 25°C in Kelvin
-            pcb_temperature: 303.15, // 30°C in Kelvin
+            dcin_voltage: Volts(12.5),
+            supercap_voltage: Volts(10.2),
+            input_current: Amps(1.5),
+            mcu_temperature: Kelvin(298.15), // 25°C in Kelvin
+            pcb_temperature: Kelvin(303.15), // 30°C in Kelvin
             power_state: PowerState::OperationalSolo,
             watchdog_elapsed: 2.5,
+            measured_at: Utc::now(),
         };
 
-        assert!((measurements.mcu_temperature_celsius() - 25.0).abs() < 0.01);
-        assert!((measurements.pcb_temperature_celsius() - 30.0).abs() < 0.01);
+        assert!((measurements.mcu_temperature.celsius().0 - 25.0).abs() < 0.01);
+        assert!((measurements.pcb_temperature.celsius().0 - 30.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_temperature_unit() {
+        assert!((TemperatureUnit::Celsius.convert_kelvin(298.15) - 25.0).abs() < 1e-9);
+        assert!((TemperatureUnit::Fahrenheit.convert_kelvin(298.15) - 77.0).abs() < 1e-9);
+        assert!((TemperatureUnit::Fahrenheit.convert_kelvin(233.15) + 40.0).abs() < 1e-9);
+        assert_eq!(TemperatureUnit::Fahrenheit.symbol(), "°F");
+
+        assert_eq!("Fahrenheit".parse(), Ok(TemperatureUnit::Fahrenheit));
+        assert_eq!("°C".parse(), Ok(TemperatureUnit::Celsius));
+        assert!("kelvin".parse::<TemperatureUnit>().is_err());
+    }
+
+    #[test]
+    fn test_measurements_supercap_charge() {
+        let mut measurements = Measurements {
+            dcin_voltage: Volts(12.0),
+            supercap_voltage: Volts(VCAP_MAX),
+            input_current: Amps(0.5),
+            mcu_temperature: Kelvin(298.15),
+            pcb_temperature: Kelvin(298.15),
+            power_state: PowerState::OperationalCoOp,
+            watchdog_elapsed: 0.0,
+            measured_at: Utc::now(),
+        };
+        assert!((measurements.supercap_charge(Volts(6.0)) - 1.0).abs() < 0.001);
+
+        measurements.supercap_voltage = Volts(5.0); // below empty voltage
+        assert_eq!(measurements.supercap_charge(Volts(6.0)), 0.0);
+
+        // Energy-based: half the voltage span holds less than half the energy
+        measurements.supercap_voltage = Volts(8.5);
+        let charge = measurements.supercap_charge(Volts(6.0));
+        assert!(charge > 0.4 && charge < 0.5);
     }
 
     #[test]
@@ -288,13 +474,14 @@ mod tests {
     #[test]
     fn test_measurements_json_serialization() {
         let measurements = Measurements {
-            dcin_voltage: 12.5,
-            supercap_voltage: 10.2,
-            input_current: 1.5,
-            mcu_temperature: 298.15,
-            pcb_temperature: 303.15,
+            dcin_voltage: Volts(12.5),
+            supercap_voltage: Volts(10.2),
+            input_current: Amps(1.5),
+            mcu_temperature: Kelvin(298.15),
+            pcb_temperature: Kelvin(303.15),
             power_state: PowerState::OperationalSolo,
             watchdog_elapsed: 2.5,
+            measured_at: Utc::now(),
         };
 
         let json = serde_json::to_string(&measurements).unwrap();
@@ -0,0 +1,127 @@
+//! Units of measured values
+//!
+//! Voltages, currents and temperatures carry their unit in their type, so a
+//! temperature in Kelvin cannot be passed where Celsius is expected. They
+//! serialize as plain numbers, keeping the API unchanged, and the raw `f32`
+//! is only taken out where a value is scaled for the protocol or formatted.
+//! `Display` appends the unit symbol and honors a precision, e.g.
+//! `format!("{:.2}", volts)` gives `12.00 V`.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::TemperatureUnit;
+
+/// 0 °C in Kelvin
+pub const ZERO_CELSIUS: f32 = 273.15;
+
+macro_rules! unit {
+    ($(#[$meta:meta])* $name:ident, $symbol:literal) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+        #[serde(transparent)]
+        pub struct $name(pub f32);
+
+        impl $name {
+            /// Unit symbol, e.g. "V"
+            pub const SYMBOL: &'static str = $symbol;
+
+            /// The value as `f64`, for statistics and JSON
+            pub fn as_f64(self) -> f64 {
+                self.0 as f64
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match f.precision() {
+                    Some(precision) => write!(f, "{:.*} {}", precision, self.0, $symbol),
+                    None => write!(f, "{} {}", self.0, $symbol),
+                }
+            }
+        }
+    };
+}
+
+unit!(
+    /// Voltage in volts
+    Volts,
+    "V"
+);
+unit!(
+    /// Current in amperes
+    Amps,
+    "A"
+);
+unit!(
+    /// Temperature in Kelvin, as reported by the controller and the API
+    Kelvin,
+    "K"
+);
+unit!(
+    /// Temperature in degrees Celsius
+    Celsius,
+    "°C"
+);
+
+impl Kelvin {
+    /// The temperature in Celsius
+    pub fn celsius(self) -> Celsius {
+        Celsius(self.0 - ZERO_CELSIUS)
+    }
+
+    /// The temperature in a display unit
+    pub fn in_unit(self, unit: TemperatureUnit) -> f64 {
+        unit.convert_kelvin(self.as_f64())
+    }
+}
+
+impl Celsius {
+    /// The temperature in Kelvin
+    pub fn kelvin(self) -> Kelvin {
+        Kelvin(self.0 + ZERO_CELSIUS)
+    }
+}
+
+impl From<Kelvin> for Celsius {
+    fn from(kelvin: Kelvin) -> Self {
+        kelvin.celsius()
+    }
+}
+
+impl From<Celsius> for Kelvin {
+    fn from(celsius: Celsius) -> Self {
+        celsius.kelvin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temperature_conversion() {
+        assert_eq!(Celsius::from(Kelvin(273.15)), Celsius(0.0));
+        assert_eq!(Celsius(0.0).kelvin(), Kelvin(273.15));
+        assert!((Kelvin(298.15).celsius().0 - 25.0).abs() < 1e-4);
+        assert!((Kelvin(298.15).in_unit(TemperatureUnit::Fahrenheit) - 77.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(format!("{:.2}", Volts(12.0)), "12.00 V");
+        assert_eq!(format!("{:.1}", Amps(1.234)), "1.2 A");
+        assert_eq!(format!("{:.1}", Kelvin(300.0).celsius()), "26.9 °C");
+        assert_eq!(Kelvin(300.0).to_string(), "300 K");
+    }
+
+    #[test]
+    fn test_serde_transparent() {
+        assert_eq!(serde_json::to_string(&Volts(12.5)).unwrap(), "12.5");
+        assert_eq!(
+            serde_json::from_str::<Kelvin>("300.0").unwrap(),
+            Kelvin(300.0)
+        );
+    }
+}
@@ -1,53 +1,369 @@
-//! HTTP client for communicating with halpid daemon via Unix socket
+//! HTTP client for communicating with halpid daemon
+//!
+//! The daemon is reached on its Unix socket, or over TCP at an HTTP base
+//! URL, e.g. through a reverse proxy on another host that checks a bearer
+//! token.
 
 use anyhow::{Context, Result};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use halpi_common::api::{
+    API_VERSION, ApiError, ConfigDiffResponse, ConfigResponse, ScheduleRequest, StandbyRequest,
+    UsbState, ValuesResponse, VersionResponse,
+};
+use halpi_common::events::DaemonEvent;
+use halpi_common::protocol::{LedColor, LedPattern};
 use http_body_util::BodyExt;
+use hyper::header::AUTHORIZATION;
+use hyper::http::request::Builder;
 use hyper::{Method, Request, StatusCode};
 use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use serde::Serialize;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
 
+#[cfg(unix)]
+use hyper::{Response, body::Incoming};
 #[cfg(unix)]
 use hyperlocal::{UnixClientExt, UnixConnector, Uri};
 
 /// Default Unix socket path for halpid daemon
 const DEFAULT_SOCKET_PATH: &str = "/run/halpid/halpid.sock";
 
+/// Default time to wait for the daemon to answer a request
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default number of retries when the daemon cannot be reached
+pub const DEFAULT_RETRIES: u32 = 2;
+
+/// Pause between attempts, long enough for a restarting daemon to bind its
+/// socket
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Pause before reopening a broken event stream
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Where to look when the daemon cannot be reached
+const DAEMON_HINT: &str = "is halpid running? Check `systemctl status halpid`";
+
+/// How long to wait for the daemon and how often to retry reaching it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientOptions {
+    /// Time to wait for the daemon to accept and answer a request; `None`
+    /// waits indefinitely
+    pub timeout: Option<Duration>,
+    /// Further attempts when the daemon cannot be reached
+    pub retries: u32,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Some(DEFAULT_TIMEOUT),
+            retries: DEFAULT_RETRIES,
+        }
+    }
+}
+
+/// Options of clients created with [`HalpiClient::new`], set from the
+/// command line
+static DEFAULT_OPTIONS: OnceLock<ClientOptions> = OnceLock::new();
+
+/// Set the options of clients created afterwards; only the first call has
+/// an effect
+pub fn set_default_options(options: ClientOptions) {
+    let _ = DEFAULT_OPTIONS.set(options);
+}
+
+/// Where the daemon is reached
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    /// Unix socket at a path
+    Socket(PathBuf),
+    /// HTTP API at a base URL such as `http://nav-pi.local:8080`, with a
+    /// token sent as `Authorization: Bearer <token>`
+    Http {
+        base_url: String,
+        token: Option<String>,
+    },
+}
+
+impl Endpoint {
+    /// HTTP endpoint from `host:port` or an `http://` URL
+    ///
+    /// # Errors
+    /// Returns an error for other schemes, URLs with a path, or anything
+    /// that is not a host.
+    pub fn http(host: &str, token: Option<String>) -> Result<Self> {
+        let url = if host.contains("://") {
+            host.to_string()
+        } else {
+            format!("http://{}", host)
+        };
+        let invalid =
+            || anyhow::anyhow!("Invalid host '{}', expected e.g. nav-pi.local:8080", host);
+        let uri: hyper::Uri = url.parse().map_err(|_| invalid())?;
+        match uri.scheme_str() {
+            Some("http") => {}
+            Some("https") => anyhow::bail!(
+                "HTTPS is not supported; reach the daemon over plain HTTP, e.g. through an SSH tunnel"
+            ),
+            _ => return Err(invalid()),
+        }
+        let authority = uri.authority().ok_or_else(invalid)?;
+        if !matches!(uri.path(), "" | "/") || uri.query().is_some() {
+            return Err(invalid());
+        }
+        Ok(Endpoint::Http {
+            base_url: format!("http://{}", authority),
+            token,
+        })
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Socket(path) => write!(f, "{}", path.display()),
+            Endpoint::Http { base_url, .. } => write!(f, "{}", base_url),
+        }
+    }
+}
+
+/// Endpoint of clients created with [`HalpiClient::new`], set from the
+/// command line
+static DEFAULT_ENDPOINT: OnceLock<Endpoint> = OnceLock::new();
+
+/// Set the endpoint of clients created afterwards; only the first call has
+/// an effect
+pub fn set_default_endpoint(endpoint: Endpoint) {
+    let _ = DEFAULT_ENDPOINT.set(endpoint);
+}
+
+/// Outcome of the API version check, made once per process before the
+/// first request
+static COMPATIBILITY: tokio::sync::OnceCell<std::result::Result<(), String>> =
+    tokio::sync::OnceCell::const_new();
+
+/// How the daemon's API version relates to this client's
+#[derive(Debug, PartialEq)]
+enum Compatibility {
+    Compatible,
+    /// Newer daemon, which may still answer as expected
+    Newer(String),
+    /// Older daemon, which lacks what this client expects
+    Older(String),
+}
+
+/// Compare the API version of a daemon with [`API_VERSION`]
+fn compatibility(version: &VersionResponse, cli_version: &str) -> Compatibility {
+    let describe = |relation| {
+        format!(
+            "halpid {} speaks API version {}, {} than version {} of this halpi {}",
+            version.daemon_version, version.api_version, relation, API_VERSION, cli_version
+        )
+    };
+    match version.api_version.cmp(&API_VERSION) {
+        std::cmp::Ordering::Equal => Compatibility::Compatible,
+        std::cmp::Ordering::Greater => Compatibility::Newer(format!(
+            "{}; upgrade halpi if commands fail",
+            describe("newer")
+        )),
+        std::cmp::Ordering::Less => Compatibility::Older(format!(
+            "{}; upgrade halpid, or install the halpi that came with it",
+            describe("older")
+        )),
+    }
+}
+
 /// HTTP client for communicating with halpid daemon
 pub struct HalpiClient {
-    socket_path: PathBuf,
+    endpoint: Endpoint,
+    options: ClientOptions,
     #[cfg(unix)]
     client: Client<UnixConnector, String>,
+    http: Client<HttpConnector, String>,
 }
 
 impl HalpiClient {
-    /// Create a new client with default socket path
+    /// Create a new client for the endpoint set from the command line,
+    /// by default the daemon socket
     pub fn new() -> Self {
-        Self::with_socket_path(DEFAULT_SOCKET_PATH)
+        match DEFAULT_ENDPOINT.get() {
+            Some(endpoint) => Self::with_endpoint(endpoint.clone()),
+            None => Self::with_socket_path(DEFAULT_SOCKET_PATH),
+        }
     }
 
     /// Create a new client with custom socket path
     pub fn with_socket_path<P: AsRef<Path>>(path: P) -> Self {
+        Self::with_endpoint(Endpoint::Socket(path.as_ref().to_path_buf()))
+    }
+
+    /// Create a new client for an endpoint
+    pub fn with_endpoint(endpoint: Endpoint) -> Self {
         #[cfg(unix)]
         let client = Client::unix();
 
         Self {
-            socket_path: path.as_ref().to_path_buf(),
+            endpoint,
+            options: DEFAULT_OPTIONS.get().copied().unwrap_or_default(),
             #[cfg(unix)]
             client,
+            http: Client::builder(TokioExecutor::new()).build_http(),
+        }
+    }
+
+    /// Path of the daemon socket, `None` if the daemon is reached over TCP
+    pub fn socket_path(&self) -> Option<&Path> {
+        match &self.endpoint {
+            Endpoint::Socket(path) => Some(path),
+            Endpoint::Http { .. } => None,
+        }
+    }
+
+    /// Start a request to `path` on the daemon, with the token if any
+    #[cfg(unix)]
+    fn request(&self, method: Method, path: &str) -> Builder {
+        let builder = Request::builder().method(method);
+        match &self.endpoint {
+            Endpoint::Socket(socket_path) => {
+                builder.uri::<hyper::Uri>(Uri::new(socket_path, path).into())
+            }
+            Endpoint::Http { base_url, token } => {
+                let builder = builder.uri(format!("{}{}", base_url, path));
+                match token {
+                    Some(token) => builder.header(AUTHORIZATION, format!("Bearer {}", token)),
+                    None => builder,
+                }
+            }
+        }
+    }
+
+    /// Send a request with an optional JSON body once the daemon's API
+    /// version has been checked
+    #[cfg(unix)]
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Response<Incoming>> {
+        self.check_compatibility().await?;
+        self.send_unchecked(method, path, body).await
+    }
+
+    /// Fetch `/version` before the first request, warning about a newer
+    /// daemon and refusing an older one
+    ///
+    /// Daemons that do not answer `/version` with a version report, e.g.
+    /// behind a proxy that only forwards some endpoints, are not checked.
+    #[cfg(unix)]
+    async fn check_compatibility(&self) -> Result<()> {
+        let outcome = COMPATIBILITY
+            .get_or_try_init(|| async {
+                let response = self.send_unchecked(Method::GET, "/version", None).await?;
+                if response.status() != StatusCode::OK {
+                    return Ok::<_, anyhow::Error>(Ok(()));
+                }
+                let body = response
+                    .into_body()
+                    .collect()
+                    .await
+                    .context("Failed to read response body")?
+                    .to_bytes();
+                let Ok(version) = serde_json::from_slice(&body) else {
+                    return Ok(Ok(()));
+                };
+                Ok(match compatibility(&version, env!("CARGO_PKG_VERSION")) {
+                    Compatibility::Compatible => Ok(()),
+                    Compatibility::Newer(warning) => {
+                        eprintln!("Warning: {}", warning);
+                        Ok(())
+                    }
+                    Compatibility::Older(error) => Err(error),
+                })
+            })
+            .await?;
+        outcome.clone().map_err(anyhow::Error::msg)
+    }
+
+    /// Send a request with an optional JSON body, retrying while the
+    /// daemon cannot be reached
+    ///
+    /// Only the response head is awaited within the timeout, so streamed
+    /// bodies may take longer.
+    #[cfg(unix)]
+    async fn send_unchecked(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Response<Incoming>> {
+        let body = body.map(serde_json::to_string).transpose()?;
+        let mut attempt = 0;
+        loop {
+            let mut builder = self.request(method.clone(), path);
+            if body.is_some() {
+                builder = builder.header("Content-Type", "application/json");
+            }
+            let request = builder
+                .body(body.clone().unwrap_or_default())
+                .context("Failed to build request")?;
+
+            let response = async {
+                match self.endpoint {
+                    Endpoint::Socket(_) => self.client.request(request).await,
+                    Endpoint::Http { .. } => self.http.request(request).await,
+                }
+            };
+            let result = match self.options.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, response)
+                    .await
+                    .map_err(|_| timeout),
+                None => Ok(response.await),
+            };
+            let error = match result {
+                Ok(Ok(response)) => return Ok(response),
+                // The request never reached the daemon, so sending it again
+                // cannot repeat an action
+                Ok(Err(e)) if e.is_connect() => anyhow::anyhow!(
+                    "Failed to connect to daemon at {}: {}",
+                    self.endpoint,
+                    anyhow::Error::new(e).root_cause()
+                ),
+                Ok(Err(e)) => {
+                    return Err(anyhow::Error::new(e).context("Failed to connect to daemon"));
+                }
+                // Anything but a GET may have been acted on already
+                Err(timeout) if method == Method::GET => {
+                    anyhow::anyhow!("Daemon did not respond within {:?}", timeout)
+                }
+                Err(timeout) => anyhow::bail!(
+                    "Daemon did not respond within {:?}; {}",
+                    timeout,
+                    DAEMON_HINT
+                ),
+            };
+            if attempt >= self.options.retries {
+                if attempt == 0 {
+                    anyhow::bail!("{}; {}", error, DAEMON_HINT);
+                }
+                anyhow::bail!("{} (tried {} times); {}", error, attempt + 1, DAEMON_HINT);
+            }
+            attempt += 1;
+            tokio::time::sleep(RETRY_DELAY).await;
         }
     }
 
     /// Send a GET request to the specified path
     #[cfg(unix)]
     async fn get(&self, path: &str) -> Result<Value> {
-        let url = Uri::new(&self.socket_path, path);
-        let response = self
-            .client
-            .get(url.into())
-            .await
-            .context("Failed to connect to daemon")?;
+        let response = self.send(Method::GET, path, None).await?;
 
         let status = response.status();
         let body_bytes = response
@@ -58,7 +374,7 @@ impl HalpiClient {
             .to_bytes();
 
         if status != StatusCode::OK {
-            let error_msg = String::from_utf8_lossy(&body_bytes);
+            let error_msg = error_message(&body_bytes);
             anyhow::bail!("Request failed ({}): {}", status, error_msg);
         }
 
@@ -68,21 +384,7 @@ impl HalpiClient {
     /// Send a PUT request with JSON body
     #[cfg(unix)]
     async fn put(&self, path: &str, body: &Value) -> Result<()> {
-        let url = Uri::new(&self.socket_path, path);
-        let body_str = serde_json::to_string(body)?;
-
-        let req = Request::builder()
-            .method(Method::PUT)
-            .uri::<hyper::Uri>(url.into())
-            .header("Content-Type", "application/json")
-            .body(body_str)
-            .context("Failed to build request")?;
-
-        let response = self
-            .client
-            .request(req)
-            .await
-            .context("Failed to connect to daemon")?;
+        let response = self.send(Method::PUT, path, Some(body)).await?;
 
         let status = response.status();
         if status != StatusCode::NO_CONTENT && status != StatusCode::OK {
@@ -92,7 +394,7 @@ impl HalpiClient {
                 .await
                 .context("Failed to read error response")?
                 .to_bytes();
-            let error_msg = String::from_utf8_lossy(&body_bytes);
+            let error_msg = error_message(&body_bytes);
             anyhow::bail!("Request failed ({}): {}", status, error_msg);
         }
 
@@ -101,22 +403,29 @@ impl HalpiClient {
 
     /// Send a POST request with JSON body
     #[cfg(unix)]
-    async fn post(&self, path: &str, body: &Value) -> Result<()> {
-        let url = Uri::new(&self.socket_path, path);
-        let body_str = serde_json::to_string(body)?;
-
-        let req = Request::builder()
-            .method(Method::POST)
-            .uri::<hyper::Uri>(url.into())
-            .header("Content-Type", "application/json")
-            .body(body_str)
-            .context("Failed to build request")?;
-
-        let response = self
-            .client
-            .request(req)
-            .await
-            .context("Failed to connect to daemon")?;
+    async fn post<B: Serialize>(&self, path: &str, body: &B) -> Result<()> {
+        let body = serde_json::to_value(body)?;
+        let response = self.send(Method::POST, path, Some(&body)).await?;
+
+        let status = response.status();
+        if status != StatusCode::NO_CONTENT && status != StatusCode::OK {
+            let body_bytes = response
+                .into_body()
+                .collect()
+                .await
+                .context("Failed to read error response")?
+                .to_bytes();
+            let error_msg = error_message(&body_bytes);
+            anyhow::bail!("Request failed ({}): {}", status, error_msg);
+        }
+
+        Ok(())
+    }
+
+    /// Send a DELETE request to the specified path
+    #[cfg(unix)]
+    async fn delete(&self, path: &str) -> Result<()> {
+        let response = self.send(Method::DELETE, path, None).await?;
 
         let status = response.status();
         if status != StatusCode::NO_CONTENT && status != StatusCode::OK {
@@ -126,7 +435,7 @@ impl HalpiClient {
                 .await
                 .context("Failed to read error response")?
                 .to_bytes();
-            let error_msg = String::from_utf8_lossy(&body_bytes);
+            let error_msg = error_message(&body_bytes);
             anyhow::bail!("Request failed ({}): {}", status, error_msg);
         }
 
@@ -134,7 +443,7 @@ impl HalpiClient {
     }
 
     /// Get all sensor values and device information
-    pub async fn get_values(&self) -> Result<HashMap<String, Value>> {
+    pub async fn get_values(&self) -> Result<ValuesResponse> {
         #[cfg(unix)]
         {
             let value = self.get("/values").await?;
@@ -145,11 +454,159 @@ impl HalpiClient {
         anyhow::bail!("Unix sockets not supported on this platform")
     }
 
-    /// Get a specific value by key
+    /// Get the daemon and controller identity, I2C location and uptime
+    pub async fn get_info(&self) -> Result<Value> {
+        #[cfg(unix)]
+        {
+            self.get("/info").await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Collect the support bundle (administrators only)
+    pub async fn get_support_bundle(&self) -> Result<Value> {
+        #[cfg(unix)]
+        {
+            self.get("/debug/bundle").await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Get the recent state transitions and alerts, optionally only those
+    /// of the last `since_seconds`
+    pub async fn get_events(&self, since_seconds: Option<u64>) -> Result<Value> {
+        #[cfg(unix)]
+        {
+            match since_seconds {
+                Some(since) => self.get(&format!("/events?since={}", since)).await,
+                None => self.get("/events").await,
+            }
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Subscribe to the event stream
+    ///
+    /// Measurement samples are only included if `measurements` is set.
+    /// When the stream breaks, e.g. while the daemon restarts, it is
+    /// reopened after [`RECONNECT_DELAY`] for as long as the returned stream
+    /// is polled; events published in the meantime are missed. Events of
+    /// types newer than this client are skipped.
     ///
-    /// This method is currently unused, but is retained for potential future API expansion
-    /// where fetching individual values by key may be required.
-    #[allow(dead_code)]
+    /// # Errors
+    /// Returns an error if the stream cannot be opened in the first place.
+    pub async fn subscribe_events(&self, measurements: bool) -> Result<BoxStream<'_, DaemonEvent>> {
+        #[cfg(unix)]
+        {
+            let path = format!("/events/stream?measurements={}", measurements);
+            let body = self.open_event_stream(&path).await?;
+            let subscription = Subscription {
+                client: self,
+                path,
+                body: Some(body),
+                buffer: String::new(),
+            };
+            Ok(stream::unfold(subscription, Subscription::next).boxed())
+        }
+
+        #[cfg(not(unix))]
+        {
+            let _ = measurements;
+            anyhow::bail!("Unix sockets not supported on this platform")
+        }
+    }
+
+    /// Open the event stream at `path`
+    #[cfg(unix)]
+    async fn open_event_stream(&self, path: &str) -> Result<Incoming> {
+        let response = self.send(Method::GET, path, None).await?;
+        let status = response.status();
+        if status != StatusCode::OK {
+            anyhow::bail!("Request failed ({})", status);
+        }
+        Ok(response.into_body())
+    }
+
+    /// Get the minimum, maximum and average measurements over the last
+    /// `window_seconds`
+    pub async fn get_stats(&self, window_seconds: u64) -> Result<Value> {
+        #[cfg(unix)]
+        {
+            self.get(&format!("/stats?window={}", window_seconds)).await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Get the measurement history of the last `since_seconds`
+    pub async fn get_measurement_history(&self, since_seconds: u64) -> Result<Value> {
+        #[cfg(unix)]
+        {
+            self.get(&format!("/stats/history?since={}", since_seconds))
+                .await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Get daemon health and I2C error statistics
+    pub async fn get_health(&self) -> Result<Value> {
+        #[cfg(unix)]
+        {
+            self.get("/health").await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Get the daemon state and maintenance mode
+    pub async fn get_state(&self) -> Result<Value> {
+        #[cfg(unix)]
+        {
+            self.get("/state").await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Switch maintenance mode on for `duration_seconds`, or off
+    pub async fn set_maintenance(
+        &self,
+        enabled: bool,
+        duration_seconds: Option<u64>,
+    ) -> Result<()> {
+        #[cfg(unix)]
+        {
+            let body = serde_json::json!({"enabled": enabled, "duration": duration_seconds});
+            self.post("/maintenance", &body).await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Scan the I2C buses for the controller
+    pub async fn scan(&self) -> Result<Value> {
+        #[cfg(unix)]
+        {
+            self.get("/debug/scan").await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Get a specific value by key
     pub async fn get_value(&self, key: &str) -> Result<Value> {
         #[cfg(unix)]
         {
@@ -160,8 +617,19 @@ impl HalpiClient {
         anyhow::bail!("Unix sockets not supported on this platform")
     }
 
+    /// Set a writable runtime value
+    pub async fn set_value(&self, key: &str, value: Value) -> Result<()> {
+        #[cfg(unix)]
+        {
+            self.put(&format!("/values/{}", key), &value).await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
     /// Get daemon configuration
-    pub async fn get_config(&self) -> Result<HashMap<String, Value>> {
+    pub async fn get_config(&self) -> Result<ConfigResponse> {
         #[cfg(unix)]
         {
             let value = self.get("/config").await?;
@@ -172,6 +640,18 @@ impl HalpiClient {
         anyhow::bail!("Unix sockets not supported on this platform")
     }
 
+    /// Compare the controller settings with the daemon's `controller` section
+    pub async fn get_config_diff(&self) -> Result<ConfigDiffResponse> {
+        #[cfg(unix)]
+        {
+            let value = self.get("/config/diff").await?;
+            serde_json::from_value(value).context("Failed to parse config diff response")
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
     /// Set a configuration value
     pub async fn set_config(&self, key: &str, value: Value) -> Result<()> {
         #[cfg(unix)]
@@ -184,7 +664,7 @@ impl HalpiClient {
     }
 
     /// Get USB port states
-    pub async fn get_usb_ports(&self) -> Result<HashMap<String, bool>> {
+    pub async fn get_usb_ports(&self) -> Result<UsbState> {
         #[cfg(unix)]
         {
             let value = self.get("/usb").await?;
@@ -218,11 +698,139 @@ impl HalpiClient {
         anyhow::bail!("Unix sockets not supported on this platform")
     }
 
+    /// Request a shutdown with auto-restart
+    pub async fn reboot(&self) -> Result<()> {
+        #[cfg(unix)]
+        {
+            self.post("/reboot", &serde_json::json!({})).await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Get the scheduled shutdown
+    pub async fn get_shutdown_schedule(&self) -> Result<Value> {
+        #[cfg(unix)]
+        {
+            self.get("/shutdown/schedule").await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Schedule a shutdown in `delay_seconds`
+    pub async fn schedule_shutdown_in(&self, delay_seconds: u64) -> Result<()> {
+        #[cfg(unix)]
+        {
+            let body = ScheduleRequest::Delay {
+                delay: delay_seconds,
+            };
+            self.post("/shutdown/schedule", &body).await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Schedule a shutdown at a time of day or datetime
+    pub async fn schedule_shutdown_at(&self, at: &str) -> Result<()> {
+        #[cfg(unix)]
+        {
+            let body = ScheduleRequest::At { at: at.to_string() };
+            self.post("/shutdown/schedule", &body).await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Clear the scheduled shutdown
+    pub async fn cancel_shutdown_schedule(&self) -> Result<()> {
+        #[cfg(unix)]
+        {
+            self.delete("/shutdown/schedule").await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Get the hardware RTC time and its drift from the system time
+    pub async fn get_rtc(&self) -> Result<Value> {
+        #[cfg(unix)]
+        {
+            self.get("/rtc").await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Set the hardware RTC to `time`, or to the system time
+    pub async fn set_rtc(&self, time: Option<&str>) -> Result<()> {
+        #[cfg(unix)]
+        {
+            let body = serde_json::json!({"time": time});
+            self.put("/rtc", &body).await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Get the LED brightness, pattern and color
+    pub async fn get_led(&self) -> Result<Value> {
+        #[cfg(unix)]
+        {
+            self.get("/led").await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Set the LED brightness, pattern and/or color
+    pub async fn set_led(
+        &self,
+        brightness: Option<u8>,
+        pattern: Option<LedPattern>,
+        color: Option<LedColor>,
+    ) -> Result<()> {
+        #[cfg(unix)]
+        {
+            let body = serde_json::json!({
+                "brightness": brightness,
+                "pattern": pattern,
+                "color": color,
+            });
+            self.put("/led", &body).await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Ramp the LED up and down for `duration_seconds`
+    pub async fn identify(&self, duration_seconds: u64) -> Result<()> {
+        #[cfg(unix)]
+        {
+            let body = serde_json::json!({"duration": duration_seconds});
+            self.post("/identify", &body).await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
     /// Request system standby with wakeup time
-    pub async fn standby_with_delay(&self, delay_seconds: u32) -> Result<()> {
+    pub async fn standby_with_delay(&self, delay_seconds: u64) -> Result<()> {
         #[cfg(unix)]
         {
-            let body = serde_json::json!({"delay": delay_seconds});
+            let body = StandbyRequest::Delay {
+                delay: delay_seconds,
+            };
             self.post("/standby", &body).await
         }
 
@@ -234,7 +842,9 @@ impl HalpiClient {
     pub async fn standby_at_datetime(&self, datetime: &str) -> Result<()> {
         #[cfg(unix)]
         {
-            let body = serde_json::json!({"datetime": datetime});
+            let body = StandbyRequest::Datetime {
+                datetime: datetime.to_string(),
+            };
             self.post("/standby", &body).await
         }
 
@@ -242,6 +852,42 @@ impl HalpiClient {
         anyhow::bail!("Unix sockets not supported on this platform")
     }
 
+    /// Request system standby until sunrise or sunset, e.g. `sunrise+30m`
+    pub async fn standby_until_sun(&self, wake_at: &str) -> Result<()> {
+        #[cfg(unix)]
+        {
+            let body = StandbyRequest::WakeAt {
+                wake_at: wake_at.to_string(),
+            };
+            self.post("/standby", &body).await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Get the RTC wake alarm
+    pub async fn get_standby(&self) -> Result<Value> {
+        #[cfg(unix)]
+        {
+            self.get("/standby").await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
+    /// Clear the RTC wake alarm
+    pub async fn cancel_standby(&self) -> Result<()> {
+        #[cfg(unix)]
+        {
+            self.delete("/standby").await
+        }
+
+        #[cfg(not(unix))]
+        anyhow::bail!("Unix sockets not supported on this platform")
+    }
+
     /// Upload firmware file to device
     pub async fn upload_firmware(&self, firmware_data: Vec<u8>, filename: &str) -> Result<()> {
         #[cfg(unix)]
@@ -280,24 +926,28 @@ impl HalpiClient {
             body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
 
             // Send POST request using a separate client for binary bodies
-            let url = Uri::new(&self.socket_path, "/flash");
             let content_type = format!("multipart/form-data; boundary={}", boundary);
-
-            // Create a client that can handle binary bodies
-            let binary_client: Client<UnixConnector, Full<Bytes>> = Client::unix();
-
-            let req = Request::builder()
-                .method(Method::POST)
-                .uri::<hyper::Uri>(url.into())
+            let req = self
+                .request(Method::POST, "/flash")
                 .header("Content-Type", content_type)
                 .header("Content-Length", body.len())
                 .body(Full::new(Bytes::from(body)))
                 .context("Failed to build request")?;
 
-            let response = binary_client
-                .request(req)
-                .await
-                .context("Failed to connect to daemon")?;
+            // Not limited by the timeout: the daemon answers once the
+            // controller has been flashed
+            let response = match self.endpoint {
+                Endpoint::Socket(_) => {
+                    let binary_client: Client<UnixConnector, Full<Bytes>> = Client::unix();
+                    binary_client.request(req).await
+                }
+                Endpoint::Http { .. } => {
+                    let binary_client: Client<HttpConnector, Full<Bytes>> =
+                        Client::builder(TokioExecutor::new()).build_http();
+                    binary_client.request(req).await
+                }
+            }
+            .with_context(|| format!("Failed to connect to daemon; {}", DAEMON_HINT))?;
 
             let status = response.status();
             if status != StatusCode::NO_CONTENT && status != StatusCode::OK {
@@ -307,7 +957,7 @@ impl HalpiClient {
                     .await
                     .context("Failed to read error response")?
                     .to_bytes();
-                let error_msg = String::from_utf8_lossy(&body_bytes);
+                let error_msg = error_message(&body_bytes);
                 anyhow::bail!("Firmware upload failed ({}): {}", status, error_msg);
             }
 
@@ -325,6 +975,77 @@ impl Default for HalpiClient {
     }
 }
 
+/// An open event stream and the messages read from it so far
+#[cfg(unix)]
+struct Subscription<'a> {
+    client: &'a HalpiClient,
+    path: String,
+    /// `None` while reconnecting
+    body: Option<Incoming>,
+    buffer: String,
+}
+
+#[cfg(unix)]
+impl Subscription<'_> {
+    /// Wait for the next event, reconnecting as needed
+    async fn next(mut self) -> Option<(DaemonEvent, Self)> {
+        loop {
+            if let Some(event) = self.buffered_event() {
+                return Some((event, self));
+            }
+            match self.body.as_mut() {
+                Some(body) => match body.frame().await {
+                    Some(Ok(frame)) => {
+                        if let Ok(data) = frame.into_data() {
+                            self.buffer.push_str(&String::from_utf8_lossy(&data));
+                        }
+                    }
+                    // The daemon closed the stream or went away
+                    _ => {
+                        self.body = None;
+                        self.buffer.clear();
+                    }
+                },
+                None => {
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    self.body = self.client.open_event_stream(&self.path).await.ok();
+                }
+            }
+        }
+    }
+
+    /// Take the next complete event out of the buffer
+    fn buffered_event(&mut self) -> Option<DaemonEvent> {
+        while let Some(end) = self.buffer.find("\n\n") {
+            let message: String = self.buffer.drain(..end + 2).collect();
+            if let Some(event) =
+                sse_data(&message).and_then(|data| serde_json::from_str(&data).ok())
+            {
+                return Some(event);
+            }
+        }
+        None
+    }
+}
+
+/// Error message of a response body, or the body itself if it is not an
+/// error body
+fn error_message(body: &[u8]) -> String {
+    serde_json::from_slice::<ApiError>(body)
+        .map(|body| body.message)
+        .unwrap_or_else(|_| String::from_utf8_lossy(body).into_owned())
+}
+
+/// Data of a server-sent event message, `None` for comments and keep-alives
+fn sse_data(message: &str) -> Option<String> {
+    let lines: Vec<&str> = message
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(|data| data.strip_prefix(' ').unwrap_or(data))
+        .collect();
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -332,24 +1053,158 @@ mod tests {
     #[test]
     fn test_client_new() {
         let client = HalpiClient::new();
-        assert_eq!(client.socket_path.to_str().unwrap(), DEFAULT_SOCKET_PATH);
+        assert_eq!(
+            client.socket_path().unwrap().to_str().unwrap(),
+            DEFAULT_SOCKET_PATH
+        );
     }
 
     #[test]
     fn test_client_with_socket_path() {
         let custom_path = "/tmp/test.sock";
         let client = HalpiClient::with_socket_path(custom_path);
-        assert_eq!(client.socket_path.to_str().unwrap(), custom_path);
+        assert_eq!(client.socket_path().unwrap().to_str().unwrap(), custom_path);
     }
 
     #[test]
     fn test_client_default() {
         let client = HalpiClient::default();
-        assert_eq!(client.socket_path.to_str().unwrap(), DEFAULT_SOCKET_PATH);
+        assert_eq!(
+            client.socket_path().unwrap().to_str().unwrap(),
+            DEFAULT_SOCKET_PATH
+        );
+    }
+
+    #[test]
+    fn test_http_endpoint() {
+        let endpoint = Endpoint::http("nav-pi.local:8080", Some("secret".into())).unwrap();
+        assert_eq!(
+            endpoint,
+            Endpoint::Http {
+                base_url: "http://nav-pi.local:8080".to_string(),
+                token: Some("secret".to_string()),
+            }
+        );
+        assert_eq!(endpoint.to_string(), "http://nav-pi.local:8080");
+        assert_eq!(
+            Endpoint::http("http://10.0.0.5:8080/", None)
+                .unwrap()
+                .to_string(),
+            "http://10.0.0.5:8080"
+        );
+        assert!(Endpoint::http("https://nav-pi.local", None).is_err());
+        assert!(Endpoint::http("ftp://nav-pi.local", None).is_err());
+        assert!(Endpoint::http("nav-pi.local:8080/api", None).is_err());
+        assert!(Endpoint::http("nav pi", None).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_request_token() {
+        let client = HalpiClient::with_endpoint(
+            Endpoint::http("nav-pi.local:8080", Some("secret".into())).unwrap(),
+        );
+        assert!(client.socket_path().is_none());
+        let request = client.request(Method::GET, "/values").body(()).unwrap();
+        assert_eq!(request.uri(), "http://nav-pi.local:8080/values");
+        assert_eq!(request.headers()[AUTHORIZATION], "Bearer secret");
+
+        let request = HalpiClient::new()
+            .request(Method::GET, "/values")
+            .body(())
+            .unwrap();
+        assert!(request.headers().get(AUTHORIZATION).is_none());
     }
 
     #[test]
     fn test_default_socket_path_value() {
         assert_eq!(DEFAULT_SOCKET_PATH, "/run/halpid/halpid.sock");
     }
+
+    #[test]
+    fn test_client_options() {
+        let client = HalpiClient::new();
+        assert_eq!(client.options, ClientOptions::default());
+        assert_eq!(client.options.timeout, Some(DEFAULT_TIMEOUT));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unreachable_daemon() {
+        let mut client = HalpiClient::with_socket_path("/nonexistent/halpid.sock");
+        client.options = ClientOptions {
+            timeout: Some(Duration::from_secs(1)),
+            retries: 1,
+        };
+        let error = client.get_info().await.unwrap_err().to_string();
+        assert!(error.starts_with("Failed to connect to daemon at /nonexistent/halpid.sock"));
+        assert!(error.contains("tried 2 times"));
+        assert!(error.contains("systemctl status halpid"));
+    }
+
+    #[test]
+    fn test_compatibility() {
+        let version = |api_version| VersionResponse {
+            daemon_version: "5.1.0".to_string(),
+            api_version,
+            hardware_version: None,
+            firmware_version: None,
+            device_id: None,
+        };
+        assert_eq!(
+            compatibility(&version(API_VERSION), "5.0.0"),
+            Compatibility::Compatible
+        );
+        let Compatibility::Newer(warning) = compatibility(&version(API_VERSION + 1), "5.0.0")
+        else {
+            panic!("expected a newer daemon");
+        };
+        assert!(warning.starts_with("halpid 5.1.0 speaks API version 2, newer than version 1"));
+        assert!(matches!(
+            compatibility(&version(API_VERSION - 1), "5.0.0"),
+            Compatibility::Older(_)
+        ));
+    }
+
+    #[test]
+    fn test_error_message() {
+        assert_eq!(
+            error_message(br#"{"error": "Unknown key: V_out"}"#),
+            "Unknown key: V_out"
+        );
+        assert_eq!(error_message(b"Bad Gateway"), "Bad Gateway");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_subscription_buffered_event() {
+        let client = HalpiClient::new();
+        let mut subscription = Subscription {
+            client: &client,
+            path: "/events/stream?measurements=false".to_string(),
+            body: None,
+            buffer: concat!(
+                ":\n\n",
+                "event: reboot\ndata: {\"type\":\"reboot\"}\n\n",
+                "event: state-transition\ndata: {\"type\":\"state-transition\",",
+                "\"timestamp\":\"2025-12-31T23:59:59Z\",\"from\":\"OperationalCoOp\",",
+                "\"to\":\"BlackoutCoOp\"}\n\n",
+                "event: alert\ndata: {\"type\":"
+            )
+            .to_string(),
+        };
+        // Keep-alives and unknown event types are skipped
+        let event = subscription.buffered_event().unwrap();
+        assert_eq!(event.name(), "state-transition");
+        // An incomplete message waits for more data
+        assert!(subscription.buffered_event().is_none());
+        assert_eq!(subscription.buffer, "event: alert\ndata: {\"type\":");
+    }
+
+    #[test]
+    fn test_sse_data() {
+        let message = "event: alert\ndata: {\"type\":\"alert\"}\n\n";
+        assert_eq!(sse_data(message).as_deref(), Some("{\"type\":\"alert\"}"));
+        assert_eq!(sse_data(":\n\n"), None);
+    }
 }
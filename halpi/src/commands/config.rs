@@ -1,6 +1,9 @@
 //! Configuration command implementation
 
+use std::collections::BTreeMap;
+
 use anyhow::Result;
+use halpi_common::api::{ConfigDiffResponse, ConfigResponse};
 use serde_json::Value;
 
 use crate::client::HalpiClient;
@@ -8,7 +11,7 @@ use crate::client::HalpiClient;
 /// Display all configuration values
 pub async fn config_get_all() -> Result<()> {
     let client = HalpiClient::new();
-    let config = client.get_config().await?;
+    let config = config_values(&client.get_config().await?)?;
 
     println!();
     for (key, value) in &config {
@@ -23,7 +26,7 @@ pub async fn config_get_all() -> Result<()> {
 /// Display a specific configuration value
 pub async fn config_get(key: &str) -> Result<()> {
     let client = HalpiClient::new();
-    let config = client.get_config().await?;
+    let config = config_values(&client.get_config().await?)?;
 
     match config.get(key) {
         Some(value) => {
@@ -49,6 +52,40 @@ pub async fn config_set(key: &str, value_str: &str) -> Result<()> {
     Ok(())
 }
 
+/// Display the settings that differ from the daemon's `controller` section
+pub async fn config_diff() -> Result<()> {
+    let client = HalpiClient::new();
+    print!("{}", format_diff(&client.get_config_diff().await?));
+    Ok(())
+}
+
+fn format_diff(diff: &ConfigDiffResponse) -> String {
+    if diff.drift.is_empty() {
+        return "Controller settings match the configuration\n".to_string();
+    }
+    let mut out = format!(
+        "\n{:<30} {:>15} {:>15}\n",
+        "Key", "Controller", "Configured"
+    );
+    for setting in &diff.drift {
+        out += &format!(
+            "{:<30} {:>15} {:>15}\n",
+            setting.key,
+            format_value(&setting.actual),
+            format_value(&setting.expected)
+        );
+    }
+    if diff.enforce {
+        out += "\nThe daemon restores the configured values at its next check\n";
+    }
+    out + "\n"
+}
+
+/// Configuration values by key, in key order
+pub fn config_values(config: &ConfigResponse) -> Result<BTreeMap<String, Value>> {
+    Ok(serde_json::from_value(serde_json::to_value(config)?)?)
+}
+
 /// Parse a string value into appropriate JSON type
 fn parse_value(value_str: &str) -> Result<Value> {
     // Try parsing as boolean first
@@ -96,3 +133,35 @@ fn format_value(value: &Value) -> String {
         _ => value.to_string(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halpi_common::api::ConfigDrift;
+    use serde_json::json;
+
+    #[test]
+    fn test_format_diff() {
+        let mut diff = ConfigDiffResponse {
+            drift: Vec::new(),
+            enforce: false,
+        };
+        assert_eq!(
+            format_diff(&diff),
+            "Controller settings match the configuration\n"
+        );
+
+        diff.drift.push(ConfigDrift {
+            key: "power_on_threshold".to_string(),
+            expected: json!(9.0),
+            actual: json!(8.5),
+        });
+        let out = format_diff(&diff);
+        assert!(out.contains("power_on_threshold"));
+        assert!(out.contains("8.5"));
+        assert!(!out.contains("restores"));
+
+        diff.enforce = true;
+        assert!(format_diff(&diff).contains("restores"));
+    }
+}
@@ -0,0 +1,69 @@
+//! Diagnose command implementation
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::client::HalpiClient;
+
+/// Display daemon health and I2C error statistics
+pub async fn diagnose() -> Result<()> {
+    let client = HalpiClient::new();
+    let health = client.get_health().await?;
+
+    print_health(&health);
+
+    Ok(())
+}
+
+/// Print the health report
+fn print_health(health: &Value) {
+    println!();
+    print_row("status", health["status"].as_str().unwrap_or("unknown"));
+    let sampling = health["sampling"].as_bool().unwrap_or(false);
+    print_row("sampling", if sampling { "yes" } else { "no" });
+    println!();
+
+    let totals = &health["i2c"]["totals"];
+    for key in [
+        "transfers",
+        "retries",
+        "transient_errors",
+        "permanent_errors",
+    ] {
+        print_row(
+            &format!("i2c_{}", key),
+            &totals[key].as_u64().unwrap_or(0).to_string(),
+        );
+    }
+    print_row(
+        "i2c_reopens",
+        &health["i2c"]["reopens"].as_u64().unwrap_or(0).to_string(),
+    );
+    println!();
+
+    match health["i2c"]["registers"].as_object() {
+        Some(registers) if !registers.is_empty() => {
+            println!(
+                "{:<10} {:>10} {:>10} {:>10} {:>10}",
+                "register", "transfers", "retries", "transient", "permanent"
+            );
+            for (register, stats) in registers {
+                println!(
+                    "{:<10} {:>10} {:>10} {:>10} {:>10}",
+                    register,
+                    stats["transfers"].as_u64().unwrap_or(0),
+                    stats["retries"].as_u64().unwrap_or(0),
+                    stats["transient_errors"].as_u64().unwrap_or(0),
+                    stats["permanent_errors"].as_u64().unwrap_or(0),
+                );
+            }
+        }
+        _ => println!("No I2C errors recorded"),
+    }
+    println!();
+}
+
+/// Print a formatted table row
+fn print_row(key: &str, value: &str) {
+    println!("{:<24} {:>15}", key, value);
+}
@@ -0,0 +1,364 @@
+//! Doctor command implementation
+//!
+//! Runs the checks support would ask for and suggests a fix for each
+//! failed one. Checks that need the daemon are skipped if it cannot be
+//! reached.
+
+use std::io::ErrorKind;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+use anyhow::Result;
+use halpi_common::protocol::Feature;
+use halpi_common::types::Version;
+use serde_json::Value;
+
+use crate::client::HalpiClient;
+
+/// Group owning the daemon socket
+const SOCKET_GROUP: &str = "halpid";
+
+/// Result of one check
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Pass(String),
+    /// Something to look at, with a fix
+    Warn(String, String),
+    /// Something broken, with a fix
+    Fail(String, String),
+}
+
+/// Check the daemon, its controller and access to it
+///
+/// # Errors
+/// Returns an error if any check failed.
+pub async fn doctor() -> Result<()> {
+    let client = HalpiClient::new();
+    let mut checks = Vec::new();
+    let reachable = match client.socket_path() {
+        Some(path) => {
+            checks.push(("socket", check_socket(path)));
+            let reachable = matches!(checks[0].1, Outcome::Pass(_));
+            if reachable {
+                checks.push(("access", check_access(path)));
+            }
+            reachable
+        }
+        // Over TCP, connection problems are reported as daemon errors
+        None => true,
+    };
+
+    match client.get_info().await {
+        Ok(info) => {
+            checks.push(("version", check_version(&info, env!("CARGO_PKG_VERSION"))));
+            match client.get_health().await {
+                Ok(health) => {
+                    checks.push(("i2c", check_health(&health)));
+                    if let Some(outcome) = check_self_test(&health) {
+                        checks.push(("self-test", outcome));
+                    }
+                }
+                Err(e) => checks.push(("i2c", daemon_error(e))),
+            }
+            checks.push(("firmware", check_firmware(&info)));
+        }
+        Err(e) if reachable => checks.push(("daemon", daemon_error(e))),
+        Err(_) => {}
+    }
+
+    let mut failed = 0;
+    for (name, outcome) in &checks {
+        match outcome {
+            Outcome::Pass(message) => println!("[ OK ] {:<9} {}", name, message),
+            Outcome::Warn(message, fix) => {
+                println!("[WARN] {:<9} {}", name, message);
+                println!("       Fix: {}", fix);
+            }
+            Outcome::Fail(message, fix) => {
+                failed += 1;
+                println!("[FAIL] {:<9} {}", name, message);
+                println!("       Fix: {}", fix);
+            }
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{} of {} checks failed", failed, checks.len());
+    }
+    Ok(())
+}
+
+/// The socket exists and accepts connections
+fn check_socket(path: &Path) -> Outcome {
+    let start = "Start the daemon with `sudo systemctl start halpid` and check \
+                 `journalctl -u halpid`"
+        .to_string();
+    match std::fs::metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {}
+        Ok(_) => return Outcome::Fail(format!("{} is not a socket", path.display()), start),
+        Err(_) => return Outcome::Fail(format!("{} does not exist", path.display()), start),
+    }
+    match UnixStream::connect(path) {
+        Ok(_) => Outcome::Pass(format!("{} accepts connections", path.display())),
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => Outcome::Fail(
+            format!("Permission denied on {}", path.display()),
+            join_group_fix(),
+        ),
+        Err(e) => Outcome::Fail(
+            format!("Cannot connect to {}: {}", path.display(), e),
+            "Restart the daemon with `sudo systemctl restart halpid`".to_string(),
+        ),
+    }
+}
+
+/// The socket is group-writable and the user is root or in its group
+fn check_access(path: &Path) -> Outcome {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return Outcome::Fail(
+            format!("Cannot read {}", path.display()),
+            "Check the permissions of the socket directory".to_string(),
+        );
+    };
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    let (uid, groups) = parse_credentials(&status);
+    if uid == Some(0) {
+        return Outcome::Pass("Running as root".to_string());
+    }
+    if metadata.mode() & 0o060 != 0o060 {
+        return Outcome::Warn(
+            format!(
+                "Socket mode is {:o}, not group read-write",
+                metadata.mode() & 0o777
+            ),
+            "Restart the daemon, which sets the mode to 660".to_string(),
+        );
+    }
+    if groups.contains(&metadata.gid()) {
+        Outcome::Pass(format!("Member of the {} group", SOCKET_GROUP))
+    } else {
+        Outcome::Warn(
+            format!("Not a member of the {} group", SOCKET_GROUP),
+            join_group_fix(),
+        )
+    }
+}
+
+fn join_group_fix() -> String {
+    format!(
+        "Add yourself to the group with `sudo usermod -aG {} $USER` and log in again",
+        SOCKET_GROUP
+    )
+}
+
+/// Real user id and supplementary groups from `/proc/self/status`
+fn parse_credentials(status: &str) -> (Option<u32>, Vec<u32>) {
+    let field = |name: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .unwrap_or_default()
+    };
+    let uid = field("Uid:")
+        .split_whitespace()
+        .next()
+        .and_then(|uid| uid.parse().ok());
+    let groups = field("Groups:")
+        .split_whitespace()
+        .filter_map(|gid| gid.parse().ok())
+        .collect();
+    (uid, groups)
+}
+
+/// The daemon runs the same version as the CLI
+fn check_version(info: &Value, cli_version: &str) -> Outcome {
+    let daemon_version = info["daemon_version"].as_str().unwrap_or("unknown");
+    if daemon_version == cli_version {
+        Outcome::Pass(format!("Daemon and CLI are version {}", cli_version))
+    } else {
+        Outcome::Warn(
+            format!("Daemon is version {}, CLI {}", daemon_version, cli_version),
+            "Install the same version of both and restart the daemon".to_string(),
+        )
+    }
+}
+
+/// The controller is connected and polled without I2C errors
+fn check_health(health: &Value) -> Outcome {
+    let i2c_fix = "Check that the HALPI2 is attached and I2C is enabled \
+                   (`dtparam=i2c_arm=on`), and run `halpi scan`"
+        .to_string();
+    if health["device"] != "present" {
+        return Outcome::Fail("Controller not connected".to_string(), i2c_fix);
+    }
+    if health["sampling"] != true {
+        return Outcome::Fail(
+            "Controller connected but not being polled".to_string(),
+            "Check `journalctl -u halpid` for errors and restart the daemon".to_string(),
+        );
+    }
+    let totals = &health["i2c"]["totals"];
+    let permanent = totals["permanent_errors"].as_u64().unwrap_or(0);
+    if permanent > 0 {
+        return Outcome::Warn(
+            format!(
+                "{} permanent I2C errors in {} transfers",
+                permanent,
+                totals["transfers"].as_u64().unwrap_or(0)
+            ),
+            "Check the connection to the HALPI2 and run `halpi diagnose`".to_string(),
+        );
+    }
+    Outcome::Pass("Controller connected and polled".to_string())
+}
+
+/// The controller passed the daemon's startup self-test; `None` if it has
+/// not run
+fn check_self_test(health: &Value) -> Option<Outcome> {
+    let report = health.get("self_test")?;
+    let failures: Vec<&str> = report["failures"]
+        .as_array()
+        .map(|failures| failures.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    Some(if report["compatible"] == false {
+        Outcome::Fail(
+            format!("Controller not supported: {}", failures.join("; ")),
+            format!(
+                "Update the firmware with `halpi flash <firmware.bin>` (at least {})",
+                report["min_firmware"].as_str().unwrap_or("unknown")
+            ),
+        )
+    } else if !failures.is_empty() {
+        Outcome::Warn(
+            failures.join("; "),
+            "Check the connection to the HALPI2 and run `halpi diagnose`".to_string(),
+        )
+    } else {
+        Outcome::Pass("Controller passed the self-test".to_string())
+    })
+}
+
+/// The firmware supports every protocol feature
+fn check_firmware(info: &Value) -> Outcome {
+    let Some(firmware) = info["firmware_version"].as_str() else {
+        return Outcome::Warn(
+            "Firmware version unknown".to_string(),
+            "Connect the controller first".to_string(),
+        );
+    };
+    let Ok(version) = firmware.parse::<Version>() else {
+        return Outcome::Fail(
+            format!("No valid firmware ({})", firmware),
+            "Flash the firmware with `halpi flash <firmware.bin>`".to_string(),
+        );
+    };
+    let missing: Vec<String> = Feature::ALL
+        .into_iter()
+        .filter(|feature| !feature.is_supported(&version))
+        .map(|feature| format!("{} ({})", feature.name(), feature.min_firmware()))
+        .collect();
+    if missing.is_empty() {
+        Outcome::Pass(format!("Firmware {} supports all features", version))
+    } else {
+        Outcome::Warn(
+            format!("Firmware {} lacks {}", version, missing.join(", ")),
+            "Update the firmware with `halpi flash <firmware.bin>`".to_string(),
+        )
+    }
+}
+
+fn daemon_error(error: anyhow::Error) -> Outcome {
+    Outcome::Fail(
+        format!("{:#}", error),
+        "Check `journalctl -u halpid` for errors".to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_credentials() {
+        let status = "Name:\thalpi\nUid:\t1000\t1000\t1000\t1000\nGid:\t1000\t1000\t1000\t1000\nGroups:\t4 27 996 1000 \n";
+        assert_eq!(
+            parse_credentials(status),
+            (Some(1000), vec![4, 27, 996, 1000])
+        );
+        assert_eq!(parse_credentials(""), (None, vec![]));
+    }
+
+    #[test]
+    fn test_check_version() {
+        let info = json!({"daemon_version": "5.0.0"});
+        assert!(matches!(check_version(&info, "5.0.0"), Outcome::Pass(_)));
+        assert!(matches!(check_version(&info, "5.1.0"), Outcome::Warn(..)));
+    }
+
+    #[test]
+    fn test_check_health() {
+        let health = json!({
+            "device": "present",
+            "sampling": true,
+            "i2c": {"totals": {"transfers": 100, "permanent_errors": 0}},
+        });
+        assert!(matches!(check_health(&health), Outcome::Pass(_)));
+
+        let mut errors = health.clone();
+        errors["i2c"]["totals"]["permanent_errors"] = json!(3);
+        assert!(matches!(check_health(&errors), Outcome::Warn(..)));
+
+        let missing = json!({"device": "missing", "sampling": false});
+        assert!(matches!(check_health(&missing), Outcome::Fail(..)));
+    }
+
+    #[test]
+    fn test_check_self_test() {
+        assert_eq!(check_self_test(&json!({"device": "missing"})), None);
+
+        let mut health = json!({"self_test": {
+            "min_firmware": "2.0.0",
+            "failures": [],
+            "compatible": true,
+        }});
+        assert!(matches!(check_self_test(&health), Some(Outcome::Pass(_))));
+
+        health["self_test"]["failures"] = json!(["cannot read the device ID: NAK"]);
+        assert!(matches!(check_self_test(&health), Some(Outcome::Warn(..))));
+
+        health["self_test"]["compatible"] = json!(false);
+        health["self_test"]["min_firmware"] = json!("3.0.0");
+        health["self_test"]["failures"] = json!(["firmware 2.1.0 is older than the minimum 3.0.0"]);
+        let Some(Outcome::Fail(message, fix)) = check_self_test(&health) else {
+            panic!("expected a failure");
+        };
+        assert!(message.ends_with("older than the minimum 3.0.0"));
+        assert!(fix.contains("3.0.0"));
+    }
+
+    #[test]
+    fn test_check_firmware() {
+        let outcome = check_firmware(&json!({"firmware_version": "3.3.0"}));
+        assert!(matches!(outcome, Outcome::Pass(_)));
+
+        let outcome = check_firmware(&json!({"firmware_version": "3.1.0"}));
+        let Outcome::Warn(message, _) = outcome else {
+            panic!("Expected a warning");
+        };
+        assert!(message.contains("LED patterns and colors (3.3.0)"));
+        assert!(!message.contains("measurement block"));
+
+        let outcome = check_firmware(&json!({"firmware_version": "3.3.0-a2"}));
+        assert!(matches!(outcome, Outcome::Warn(..)));
+
+        let outcome = check_firmware(&json!({"firmware_version": "N/A"}));
+        assert!(matches!(outcome, Outcome::Fail(..)));
+        assert!(matches!(check_firmware(&json!({})), Outcome::Warn(..)));
+    }
+
+    #[test]
+    fn test_check_socket_missing() {
+        let outcome = check_socket(Path::new("/nonexistent/halpid.sock"));
+        assert!(matches!(outcome, Outcome::Fail(..)));
+    }
+}
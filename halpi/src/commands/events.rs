@@ -0,0 +1,123 @@
+//! Events command implementation
+
+use anyhow::Result;
+use futures_util::StreamExt;
+use halpi_common::events::{DaemonEvent, DfuProgress};
+use serde_json::Value;
+
+use crate::client::HalpiClient;
+
+/// Print the recent state transitions, alerts and voltage excursions, then
+/// optionally follow new events until interrupted
+pub async fn events(follow: bool, since: Option<u64>, json: bool) -> Result<()> {
+    let client = HalpiClient::new();
+    let history = client.get_events(since).await?;
+    for event in history.as_array().into_iter().flatten() {
+        print_event(event, json);
+    }
+    if follow {
+        let mut events = client.subscribe_events(false).await?;
+        while let Some(event) = events.next().await {
+            if json {
+                println!("{}", serde_json::to_string(&event)?);
+            } else {
+                println!("{}", describe_event(&event));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Print an event as a JSON line or as text
+fn print_event(event: &Value, json: bool) {
+    if json {
+        println!("{}", event);
+        return;
+    }
+    match serde_json::from_value::<DaemonEvent>(event.clone()) {
+        Ok(event) => println!("{}", describe_event(&event)),
+        // Event types newer than this CLI
+        Err(_) => println!("{}", event),
+    }
+}
+
+/// One line describing an event, starting with its time
+fn describe_event(event: &DaemonEvent) -> String {
+    let details = match event {
+        DaemonEvent::Measurement(sample) => {
+            let m = &sample.measurements;
+            format!(
+                "input {:.2} {:.2}, supercap {:.2}, {}",
+                m.dcin_voltage, m.input_current, m.supercap_voltage, m.power_state
+            )
+        }
+        DaemonEvent::StateTransition { from, to, .. } => format!("{} -> {}", from, to),
+        DaemonEvent::Alert(alert) => {
+            let kind = serde_json::to_value(alert.kind).unwrap_or_default();
+            format!("{}: {}", kind.as_str().unwrap_or("alert"), alert.message)
+        }
+        DaemonEvent::VoltageExcursion(excursion) => format!(
+            "{} to {:.2} ({:.2} beyond the limit) for {:.1} s",
+            excursion.kind.name(),
+            excursion.extreme,
+            excursion.depth,
+            excursion.duration
+        ),
+        DaemonEvent::Dfu { progress, .. } => match progress {
+            DfuProgress::Started { total } => format!("started, {} bytes", total),
+            DfuProgress::Writing { written, total } => {
+                format!("writing, {} of {} bytes", written, total)
+            }
+            DfuProgress::Completed { total } => format!("completed, {} bytes", total),
+            DfuProgress::Failed { error } => format!("failed: {}", error),
+        },
+    };
+    format!(
+        "{}  {:<16} {}",
+        event.timestamp().format("%Y-%m-%d %H:%M:%S"),
+        event.name(),
+        details
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_describe_event() {
+        let event: DaemonEvent = serde_json::from_value(json!({
+            "type": "state-transition",
+            "timestamp": "2025-12-31T23:59:59Z",
+            "from": "OperationalCoOp",
+            "to": "BlackoutCoOp",
+        }))
+        .unwrap();
+        let line = describe_event(&event);
+        assert!(line.starts_with("2025-12-31 23:59:59  state-transition "));
+        assert!(line.ends_with("OperationalCoOp -> BlackoutCoOp"));
+
+        let event: DaemonEvent = serde_json::from_value(json!({
+            "type": "alert",
+            "timestamp": "2025-12-31T23:59:59Z",
+            "kind": "blackout-detected",
+            "message": "Input voltage 9.0 V",
+        }))
+        .unwrap();
+        assert!(describe_event(&event).ends_with("blackout-detected: Input voltage 9.0 V"));
+
+        let event: DaemonEvent = serde_json::from_value(json!({
+            "type": "voltage-excursion",
+            "timestamp": "2025-12-31T23:59:59Z",
+            "kind": "sag",
+            "extreme": 10.5,
+            "depth": 0.5,
+            "duration": 1.3,
+        }))
+        .unwrap();
+        assert!(
+            describe_event(&event).ends_with("sag to 10.50 V (0.50 V beyond the limit) for 1.3 s")
+        );
+    }
+}
@@ -0,0 +1,131 @@
+//! Export command implementation
+//!
+//! Writes the daemon's measurement history as CSV for spreadsheets or as
+//! JSON. The daemon keeps a day of history in memory; it starts over when
+//! the daemon restarts.
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use halpi_common::types::TemperatureUnit;
+use serde_json::Value;
+
+use crate::client::HalpiClient;
+
+/// Quantities in each row, as named by the daemon
+const QUANTITIES: [&str; 5] = ["V_in", "I_in", "V_cap", "T_mcu", "T_pcb"];
+
+/// Output format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// One row per interval, temperatures in °C or °F
+    Csv,
+    /// The daemon's response, temperatures in Kelvin
+    Json,
+}
+
+/// Export the history of the last `since` seconds to `out`, or to stdout
+///
+/// # Errors
+/// Returns an error if the daemon cannot be reached or the file cannot be
+/// written.
+pub async fn export(
+    format: ExportFormat,
+    since: u64,
+    out: Option<&Path>,
+    unit: TemperatureUnit,
+) -> Result<()> {
+    let client = HalpiClient::new();
+    let history = client.get_measurement_history(since).await?;
+    let contents = match format {
+        ExportFormat::Csv => to_csv(&history, unit),
+        ExportFormat::Json => serde_json::to_string_pretty(&history)? + "\n",
+    };
+    match out {
+        Some(out) => {
+            std::fs::write(out, contents)
+                .with_context(|| format!("Failed to write {}", out.display()))?;
+            let rows = history["rows"].as_array().map_or(0, Vec::len);
+            eprintln!(
+                "Wrote {} rows at {} s resolution to {}",
+                rows,
+                history["resolution_s"],
+                out.display()
+            );
+        }
+        None => print!("{}", contents),
+    }
+    Ok(())
+}
+
+/// CSV with a header and the minimum, average and maximum of each quantity
+fn to_csv(history: &Value, unit: TemperatureUnit) -> String {
+    let mut csv = String::from("timestamp,samples");
+    for name in QUANTITIES {
+        let _ = write!(csv, ",{name}_min,{name}_avg,{name}_max");
+    }
+    csv.push('\n');
+
+    for row in history["rows"].as_array().into_iter().flatten() {
+        let _ = write!(
+            csv,
+            "{},{}",
+            row["timestamp"].as_str().unwrap_or_default(),
+            row["samples"]
+        );
+        for name in QUANTITIES {
+            for key in ["min", "avg", "max"] {
+                match row[name][key].as_f64() {
+                    // Temperatures are reported in Kelvin
+                    Some(value) if name.starts_with("T_") => {
+                        let _ = write!(csv, ",{:.3}", unit.convert_kelvin(value));
+                    }
+                    Some(value) => {
+                        let _ = write!(csv, ",{:.3}", value);
+                    }
+                    None => csv.push(','),
+                }
+            }
+        }
+        csv.push('\n');
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_to_csv() {
+        let summary = |value: f64| json!({"min": value, "avg": value, "max": value});
+        let history = json!({
+            "resolution_s": 60,
+            "rows": [{
+                "timestamp": "2025-12-31T23:59:00Z",
+                "samples": 600,
+                "V_in": {"min": 11.5, "avg": 12.0, "max": 12.5},
+                "I_in": summary(0.5),
+                "V_cap": summary(9.5),
+                "T_mcu": summary(300.0),
+                "T_pcb": summary(298.15),
+            }],
+        });
+        let csv = to_csv(&history, TemperatureUnit::Celsius);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("timestamp,samples,V_in_min,V_in_avg,V_in_max,"));
+        assert!(lines[0].ends_with(",T_pcb_min,T_pcb_avg,T_pcb_max"));
+        assert!(lines[1].starts_with("2025-12-31T23:59:00Z,600,11.500,12.000,12.500,"));
+        assert!(lines[1].ends_with(",25.000,25.000,25.000"));
+
+        let csv = to_csv(&history, TemperatureUnit::Fahrenheit);
+        assert!(csv.trim_end().ends_with(",77.000,77.000,77.000"));
+
+        let csv = to_csv(&json!({"rows": []}), TemperatureUnit::Celsius);
+        assert_eq!(csv.lines().count(), 1);
+    }
+}
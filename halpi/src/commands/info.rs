@@ -0,0 +1,64 @@
+//! Info command implementation
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::client::HalpiClient;
+
+/// Show the daemon and controller identity
+pub async fn info(json: bool) -> Result<()> {
+    let client = HalpiClient::new();
+    let report = client.get_info().await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_info(&report);
+    }
+    Ok(())
+}
+
+fn print_info(report: &Value) {
+    let text = |key: &str| report[key].as_str().unwrap_or("unknown").to_string();
+    println!("halpi version:    {}", env!("CARGO_PKG_VERSION"));
+    println!("Daemon version:   {}", text("daemon_version"));
+    println!("Firmware version: {}", text("firmware_version"));
+    println!("Hardware version: {}", text("hardware_version"));
+    println!("Device ID:        {}", text("device_id"));
+    let present = if report["device_present"] == true {
+        ""
+    } else {
+        " (not connected)"
+    };
+    println!(
+        "I2C:              bus {}, address {}{}",
+        report["i2c_bus"],
+        text("i2c_addr"),
+        present
+    );
+    println!("Socket:           {}", text("socket"));
+    if let Some(uptime) = report["uptime_s"].as_u64() {
+        println!("Daemon uptime:    {}", describe_uptime(uptime));
+    }
+}
+
+/// Describe an uptime in seconds in days, hours and minutes
+fn describe_uptime(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86400, seconds % 86400 / 3600, seconds % 3600 / 60);
+    match (days, hours) {
+        (0, 0) => format!("{}m {}s", minutes, seconds % 60),
+        (0, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h {}m", days, hours, minutes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_uptime() {
+        assert_eq!(describe_uptime(42), "0m 42s");
+        assert_eq!(describe_uptime(3 * 3600 + 120), "3h 2m");
+        assert_eq!(describe_uptime(2 * 86400 + 3600 + 60), "2d 1h 1m");
+    }
+}
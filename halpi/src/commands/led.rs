@@ -0,0 +1,44 @@
+//! LED command implementation
+
+use anyhow::Result;
+use halpi_common::protocol::{LedColor, LedPattern};
+use serde_json::Value;
+
+use crate::client::HalpiClient;
+
+/// Show the LED brightness, pattern and color
+pub async fn led_status() -> Result<()> {
+    let client = HalpiClient::new();
+    print_led(&client.get_led().await?);
+    Ok(())
+}
+
+/// Set the LED brightness, pattern and/or color
+pub async fn led_set(
+    brightness: Option<u8>,
+    pattern: Option<LedPattern>,
+    color: Option<LedColor>,
+) -> Result<()> {
+    let client = HalpiClient::new();
+    client.set_led(brightness, pattern, color).await?;
+    print_led(&client.get_led().await?);
+    Ok(())
+}
+
+/// Ramp the LED up and down for `duration` seconds
+pub async fn identify(duration: u64) -> Result<()> {
+    HalpiClient::new().identify(duration).await?;
+    println!("Ramping the LED for {} seconds", duration);
+    Ok(())
+}
+
+fn print_led(report: &Value) {
+    let show = |value: &Value| match value {
+        Value::Null => "unsupported by the firmware".to_string(),
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+    println!("Brightness: {}", show(&report["brightness"]));
+    println!("Pattern:    {}", show(&report["pattern"]));
+    println!("Color:      {}", show(&report["color"]));
+}
@@ -0,0 +1,34 @@
+//! Maintenance mode command implementation
+
+use anyhow::Result;
+
+use crate::client::HalpiClient;
+
+/// Enable maintenance mode, for the daemon's default duration if not given
+pub async fn maintenance_on(duration_seconds: Option<u64>) -> Result<()> {
+    let client = HalpiClient::new();
+    client.set_maintenance(true, duration_seconds).await?;
+    print_status(&client).await
+}
+
+/// Disable maintenance mode
+pub async fn maintenance_off() -> Result<()> {
+    let client = HalpiClient::new();
+    client.set_maintenance(false, None).await?;
+    println!("Maintenance mode off");
+    Ok(())
+}
+
+/// Show whether maintenance mode is on
+pub async fn maintenance_status() -> Result<()> {
+    print_status(&HalpiClient::new()).await
+}
+
+async fn print_status(client: &HalpiClient) -> Result<()> {
+    let state = client.get_state().await?;
+    match state["maintenance_until"].as_str() {
+        Some(until) => println!("Maintenance mode on until {}", until),
+        None => println!("Maintenance mode off"),
+    }
+    Ok(())
+}
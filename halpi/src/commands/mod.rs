@@ -1,7 +1,20 @@
 //! CLI command implementations
 
 pub mod config;
+pub mod diagnose;
+pub mod doctor;
+pub mod events;
+pub mod export;
 pub mod flash;
+pub mod info;
+pub mod led;
+pub mod maintenance;
+pub mod rtc;
+pub mod scan;
+pub mod set;
 pub mod shutdown;
+pub mod stats;
 pub mod status;
+pub mod support_bundle;
 pub mod usb;
+pub mod wait;
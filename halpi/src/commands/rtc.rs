@@ -0,0 +1,60 @@
+//! RTC command implementation
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::client::HalpiClient;
+
+/// Show the hardware RTC time and its drift from the system time
+pub async fn rtc_status() -> Result<()> {
+    let client = HalpiClient::new();
+    print_rtc(&client.get_rtc().await?);
+    Ok(())
+}
+
+/// Set the hardware RTC to `time`, or to the system time
+pub async fn rtc_set(time: Option<&str>) -> Result<()> {
+    let client = HalpiClient::new();
+    client.set_rtc(time).await?;
+    print_rtc(&client.get_rtc().await?);
+    Ok(())
+}
+
+fn print_rtc(report: &Value) {
+    println!(
+        "RTC time:    {}",
+        report["rtc"].as_str().unwrap_or("unknown")
+    );
+    println!(
+        "System time: {}",
+        report["system"].as_str().unwrap_or("unknown")
+    );
+    if let Some(drift) = report["drift_s"].as_f64() {
+        println!("Drift:       {}", describe_drift(drift));
+    }
+    if report["system_synchronized"] == false {
+        println!("Warning: the system clock is not synchronized");
+    }
+}
+
+/// Describe how far the RTC is ahead of or behind the system clock
+fn describe_drift(drift: f64) -> String {
+    // The RTC only counts whole seconds
+    if drift.abs() < 1.0 {
+        return format!("{:+.1} s (in sync)", drift);
+    }
+    let direction = if drift > 0.0 { "ahead" } else { "behind" };
+    format!("{:+.1} s (RTC {})", drift, direction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_drift() {
+        assert_eq!(describe_drift(0.4), "+0.4 s (in sync)");
+        assert_eq!(describe_drift(12.25), "+12.2 s (RTC ahead)");
+        assert_eq!(describe_drift(-3.0), "-3.0 s (RTC behind)");
+    }
+}
@@ -0,0 +1,62 @@
+//! Scan command implementation
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::client::HalpiClient;
+
+/// Scan the I2C buses for the controller
+pub async fn scan() -> Result<()> {
+    let client = HalpiClient::new();
+    let report = client.scan().await?;
+
+    print_report(&report);
+
+    Ok(())
+}
+
+/// Print the scan report
+fn print_report(report: &Value) {
+    let configured = &report["configured"];
+    println!();
+    println!(
+        "Configured: bus {}, address {}",
+        configured["bus"],
+        configured["addr"].as_str().unwrap_or("unknown")
+    );
+    let buses: Vec<String> = report["buses"]
+        .as_array()
+        .map(|buses| buses.iter().map(Value::to_string).collect())
+        .unwrap_or_default();
+    if buses.is_empty() {
+        println!("No I2C buses found; is the I2C interface enabled?");
+        println!();
+        return;
+    }
+    println!("Scanned buses: {}", buses.join(", "));
+    println!();
+
+    match report["detected"].as_array() {
+        Some(detected) if !detected.is_empty() => {
+            println!(
+                "{:<6} {:<8} {:<10} {:<10}",
+                "bus", "address", "hardware", "firmware"
+            );
+            for device in detected {
+                println!(
+                    "{:<6} {:<8} {:<10} {:<10}",
+                    device["bus"],
+                    device["addr"].as_str().unwrap_or(""),
+                    device["hardware_version"].as_str().unwrap_or(""),
+                    device["firmware_version"].as_str().unwrap_or(""),
+                );
+            }
+        }
+        _ => println!("No HALPI2 controller found"),
+    }
+    if let Some(suggestion) = report["suggestion"].as_str() {
+        println!();
+        println!("{}", suggestion);
+    }
+    println!();
+}
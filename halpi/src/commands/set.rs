@@ -0,0 +1,162 @@
+//! Set command implementation
+//!
+//! Values are checked before they are sent, and unknown keys are answered
+//! with the closest writable key.
+
+use anyhow::Result;
+use serde_json::{Value, json};
+
+use crate::client::HalpiClient;
+
+/// Writable runtime values and what they take
+const WRITABLE: [(&str, &str); 3] = [
+    ("led_brightness", "an integer from 0 to 255"),
+    ("5v_output_enabled", "true or false (on/off, 1/0)"),
+    ("watchdog_timeout", "seconds from 0 to 65.535, 0 disables"),
+];
+
+/// Values that can be read with `halpi status` but not set
+const READ_ONLY: [&str; 13] = [
+    "daemon_version",
+    "hardware_version",
+    "firmware_version",
+    "device_id",
+    "V_in",
+    "V_cap",
+    "I_in",
+    "T_mcu",
+    "T_pcb",
+    "state",
+    "watchdog_enabled",
+    "watchdog_elapsed",
+    "usb_port_state",
+];
+
+/// Set a writable runtime value
+///
+/// # Errors
+/// Returns an error if the key is not writable, the value is invalid or the
+/// daemon rejects it.
+pub async fn set(key: &str, value: &str) -> Result<()> {
+    let parsed = parse_setting(key, value)?;
+    let client = HalpiClient::new();
+    client.set_value(key, parsed).await?;
+    println!("'{}' set to: {}", key, value);
+    Ok(())
+}
+
+/// Parse a value for a writable key
+fn parse_setting(key: &str, value: &str) -> Result<Value> {
+    let parsed = match key {
+        "led_brightness" => value.parse::<u8>().ok().map(|v| json!(v)),
+        "5v_output_enabled" => match value.to_ascii_lowercase().as_str() {
+            "true" | "on" | "1" => Some(json!(true)),
+            "false" | "off" | "0" => Some(json!(false)),
+            _ => None,
+        },
+        "watchdog_timeout" => value
+            .parse::<f64>()
+            .ok()
+            .filter(|v| (0.0..=65.535).contains(v))
+            .map(|v| json!(v)),
+        _ if READ_ONLY.contains(&key) => {
+            anyhow::bail!("'{}' is read-only; writable keys: {}", key, writable_keys())
+        }
+        _ => match closest_key(key) {
+            Some(closest) => anyhow::bail!(
+                "Unknown key '{}'. Did you mean '{}'? Writable keys: {}",
+                key,
+                closest,
+                writable_keys()
+            ),
+            None => anyhow::bail!("Unknown key '{}'. Writable keys: {}", key, writable_keys()),
+        },
+    };
+    parsed.ok_or_else(|| {
+        let expected = WRITABLE
+            .iter()
+            .find(|(name, _)| *name == key)
+            .map_or("", |(_, expected)| expected);
+        anyhow::anyhow!(
+            "Invalid value '{}' for {}: expected {}",
+            value,
+            key,
+            expected
+        )
+    })
+}
+
+fn writable_keys() -> String {
+    WRITABLE.map(|(key, _)| key).join(", ")
+}
+
+/// The writable key closest to a mistyped one, if any is close
+fn closest_key(key: &str) -> Option<&'static str> {
+    WRITABLE
+        .iter()
+        .map(|(name, _)| (*name, edit_distance(&key.to_ascii_lowercase(), name)))
+        .filter(|(_, distance)| *distance <= 3)
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(name, _)| name)
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_setting() {
+        assert_eq!(parse_setting("led_brightness", "128").unwrap(), json!(128));
+        assert!(parse_setting("led_brightness", "256").is_err());
+        assert_eq!(
+            parse_setting("5v_output_enabled", "off").unwrap(),
+            json!(false)
+        );
+        assert_eq!(
+            parse_setting("5v_output_enabled", "1").unwrap(),
+            json!(true)
+        );
+        assert!(parse_setting("5v_output_enabled", "maybe").is_err());
+        assert_eq!(
+            parse_setting("watchdog_timeout", "10.5").unwrap(),
+            json!(10.5)
+        );
+        assert!(parse_setting("watchdog_timeout", "70").is_err());
+
+        let error = parse_setting("V_in", "12").unwrap_err().to_string();
+        assert!(error.contains("read-only"));
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let error = parse_setting("led_brightnes", "12")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Did you mean 'led_brightness'?"));
+        let error = parse_setting("fan_speed", "12").unwrap_err().to_string();
+        assert!(!error.contains("Did you mean"));
+        assert!(error.contains("led_brightness, 5v_output_enabled, watchdog_timeout"));
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("watchdog_timeout", "watchdog_timeout"), 0);
+        assert_eq!(edit_distance("watchdog_timout", "watchdog_timeout"), 1);
+        assert_eq!(edit_distance("5v_output", "5v_output_enabled"), 8);
+    }
+}
@@ -1,4 +1,4 @@
-//! Shutdown and standby command implementation
+//! Shutdown, standby and reboot command implementation
 
 use anyhow::Result;
 
@@ -12,14 +12,71 @@ pub async fn shutdown() -> Result<()> {
     Ok(())
 }
 
+/// Request a shutdown after which the controller restarts the system
+pub async fn reboot() -> Result<()> {
+    let client = HalpiClient::new();
+    client.reboot().await?;
+    println!("Reboot requested (shutdown with auto-restart)");
+    Ok(())
+}
+
+/// Schedule a shutdown in `delay_seconds`
+pub async fn schedule_in(delay_seconds: u64) -> Result<()> {
+    let client = HalpiClient::new();
+    client.schedule_shutdown_in(delay_seconds).await?;
+    print_schedule(&client).await
+}
+
+/// Schedule a shutdown at a time of day ("22:30") or datetime
+pub async fn schedule_at(at: &str) -> Result<()> {
+    let client = HalpiClient::new();
+    client.schedule_shutdown_at(at).await?;
+    print_schedule(&client).await
+}
+
+/// Clear the scheduled shutdown
+pub async fn cancel_schedule() -> Result<()> {
+    let client = HalpiClient::new();
+    client.cancel_shutdown_schedule().await?;
+    println!("No shutdown scheduled");
+    Ok(())
+}
+
+/// Show the scheduled shutdown
+pub async fn schedule_status() -> Result<()> {
+    print_schedule(&HalpiClient::new()).await
+}
+
+async fn print_schedule(client: &HalpiClient) -> Result<()> {
+    let schedule = client.get_shutdown_schedule().await?;
+    match (schedule["at"].as_str(), schedule["remaining_s"].as_i64()) {
+        (Some(at), Some(remaining)) => println!(
+            "Shutdown scheduled at {} (in {}m {}s)",
+            at,
+            remaining / 60,
+            remaining % 60
+        ),
+        _ => println!("No shutdown scheduled"),
+    }
+    Ok(())
+}
+
 /// Request system standby with delay
-pub async fn standby_delay(delay_seconds: u32) -> Result<()> {
+pub async fn standby_delay(delay_seconds: u64) -> Result<()> {
     let client = HalpiClient::new();
     client.standby_with_delay(delay_seconds).await?;
     println!("Standby requested with wakeup in {} seconds", delay_seconds);
     Ok(())
 }
 
+/// Request system standby until sunrise or sunset
+pub async fn standby_sun(wake_at: &str) -> Result<()> {
+    let client = HalpiClient::new();
+    client.standby_until_sun(wake_at).await?;
+    println!("Standby requested with wakeup at {}", wake_at);
+    Ok(())
+}
+
 /// Request system standby with datetime
 pub async fn standby_datetime(datetime: &str) -> Result<()> {
     let client = HalpiClient::new();
@@ -27,3 +84,27 @@ pub async fn standby_datetime(datetime: &str) -> Result<()> {
     println!("Standby requested with wakeup at {}", datetime);
     Ok(())
 }
+
+/// Clear the RTC wake alarm
+pub async fn standby_cancel() -> Result<()> {
+    let client = HalpiClient::new();
+    client.cancel_standby().await?;
+    println!("Wake alarm cleared");
+    Ok(())
+}
+
+/// Show the RTC wake alarm
+pub async fn standby_status() -> Result<()> {
+    let client = HalpiClient::new();
+    let alarm = client.get_standby().await?;
+    match (alarm["at"].as_str(), alarm["remaining_s"].as_i64()) {
+        (Some(at), Some(remaining)) => println!(
+            "Wake alarm set for {} (in {}h {}m)",
+            at,
+            remaining / 3600,
+            remaining % 3600 / 60
+        ),
+        _ => println!("No wake alarm set"),
+    }
+    Ok(())
+}
@@ -0,0 +1,86 @@
+//! Stats command implementation
+
+use anyhow::Result;
+use halpi_common::types::TemperatureUnit;
+use serde_json::Value;
+
+use crate::client::HalpiClient;
+
+/// Quantities shown, with their unit and decimals; temperatures have no
+/// unit here as it is chosen by the user
+const ROWS: [(&str, &str, usize); 5] = [
+    ("V_in", "V", 2),
+    ("I_in", "A", 2),
+    ("V_cap", "V", 2),
+    ("T_mcu", "", 1),
+    ("T_pcb", "", 1),
+];
+
+/// Show the minimum, maximum and average measurements over the last
+/// `window` seconds
+pub async fn stats(window: u64, temperature_unit: TemperatureUnit) -> Result<()> {
+    let client = HalpiClient::new();
+    let report = client.get_stats(window).await?;
+    print_stats(&report, temperature_unit);
+    Ok(())
+}
+
+fn print_stats(report: &Value, temperature_unit: TemperatureUnit) {
+    println!();
+    println!(
+        "Last {} ({} samples)",
+        describe_window(report["window_s"].as_u64().unwrap_or(0)),
+        report["samples"]
+    );
+    println!();
+    println!("{:<8} {:>8} {:>8} {:>8}", "", "min", "avg", "max");
+    for (name, unit, decimals) in ROWS {
+        let summary = &report["values"][name];
+        let unit = if unit.is_empty() {
+            temperature_unit.symbol()
+        } else {
+            unit
+        };
+        let cell = |key: &str| match summary[key].as_f64() {
+            // Temperatures are reported in Kelvin
+            Some(value) if name.starts_with("T_") => {
+                format!("{:.*}", decimals, temperature_unit.convert_kelvin(value))
+            }
+            Some(value) => format!("{:.*}", decimals, value),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:<8} {:>8} {:>8} {:>8}  {}",
+            name,
+            cell("min"),
+            cell("avg"),
+            cell("max"),
+            unit
+        );
+    }
+    println!();
+}
+
+/// Describe a window in seconds, e.g. "15m" or "1h 30m"
+fn describe_window(seconds: u64) -> String {
+    match (seconds / 3600, seconds % 3600 / 60, seconds % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, 0) => format!("{}m", m),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, 0, 0) => format!("{}h", h),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_window() {
+        assert_eq!(describe_window(45), "45s");
+        assert_eq!(describe_window(900), "15m");
+        assert_eq!(describe_window(90), "1m 30s");
+        assert_eq!(describe_window(3600), "1h");
+    }
+}
@@ -0,0 +1,114 @@
+//! Support bundle command implementation
+//!
+//! Writes each section of the daemon's support bundle to its own file and
+//! packs them with `tar`.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use crate::client::HalpiClient;
+
+/// Collect a support bundle into `output`, or into a file named after the
+/// time it was generated
+///
+/// # Errors
+/// Returns an error if the daemon cannot be reached, refuses the request
+/// or the archive cannot be written.
+pub async fn support_bundle(output: Option<PathBuf>) -> Result<()> {
+    let client = HalpiClient::new();
+    let bundle = client
+        .get_support_bundle()
+        .await
+        .context("Failed to collect the support bundle (run as root)")?;
+
+    let name = bundle_name(bundle["generated_at"].as_str().unwrap_or_default());
+    let output = output.unwrap_or_else(|| PathBuf::from(format!("{}.tar.gz", name)));
+    let staging = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+    let result = write_archive(&bundle, &staging, &name, &output);
+    let _ = std::fs::remove_dir_all(&staging);
+    result?;
+
+    println!("Support bundle written to {}", output.display());
+    println!("Attach it to your issue; secrets in the configuration are masked.");
+    Ok(())
+}
+
+/// Archive name from the generation time, e.g. `halpi-support-20251231T235959`
+fn bundle_name(generated_at: &str) -> String {
+    let stamp: String = generated_at
+        .chars()
+        .take_while(|c| *c != '.' && *c != '+')
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect();
+    format!("halpi-support-{}", stamp)
+}
+
+fn write_archive(bundle: &Value, staging: &Path, name: &str, output: &Path) -> Result<()> {
+    let dir = staging.join(name);
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    for (file, contents) in bundle_files(bundle)? {
+        std::fs::write(dir.join(&file), contents)
+            .with_context(|| format!("Failed to write {}", file))?;
+    }
+
+    let status = Command::new("tar")
+        .arg("-czf")
+        .arg(output)
+        .arg("-C")
+        .arg(staging)
+        .arg(name)
+        .status()
+        .context("Failed to run tar")?;
+    if !status.success() {
+        anyhow::bail!("tar failed with {}", status);
+    }
+    Ok(())
+}
+
+/// File names and contents for the sections of `bundle`
+///
+/// Logs are plain text; every other section is pretty-printed JSON.
+fn bundle_files(bundle: &Value) -> Result<Vec<(String, String)>> {
+    let Some(sections) = bundle.as_object() else {
+        anyhow::bail!("Unexpected support bundle format");
+    };
+    sections
+        .iter()
+        .map(|(key, value)| match value {
+            Value::String(text) if key == "logs" => Ok(("logs.txt".to_string(), text.clone())),
+            _ => Ok((
+                format!("{}.json", key),
+                serde_json::to_string_pretty(value)? + "\n",
+            )),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_bundle_name() {
+        assert_eq!(
+            bundle_name("2025-12-31T23:59:59.123456+00:00"),
+            "halpi-support-20251231T235959"
+        );
+        assert_eq!(bundle_name(""), "halpi-support-");
+    }
+
+    #[test]
+    fn test_bundle_files() {
+        let bundle = json!({"logs": "line 1\n", "state": {"state": "ok"}});
+        let files = bundle_files(&bundle).unwrap();
+        assert_eq!(files[0], ("logs.txt".to_string(), "line 1\n".to_string()));
+        assert_eq!(files[1].0, "state.json");
+        assert!(files[1].1.contains("\"state\": \"ok\""));
+
+        assert!(bundle_files(&json!([])).is_err());
+    }
+}
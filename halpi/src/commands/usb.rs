@@ -11,11 +11,13 @@ pub async fn usb_status() -> Result<()> {
 
     println!();
     println!("USB Port States:");
-    for i in 0..4 {
-        let key = format!("usb{}", i);
-        if let Some(&enabled) = ports.get(&key) {
-            let status = if enabled { "enabled" } else { "disabled" };
-            println!("  Port {}: {}", i, status);
+    let defaults = ports.defaults.ports();
+    for (i, enabled) in ports.ports().into_iter().enumerate() {
+        let status = if enabled { "enabled" } else { "disabled" };
+        match defaults[i] {
+            Some(true) => println!("  Port {}: {} (on at startup)", i, status),
+            Some(false) => println!("  Port {}: {} (off at startup)", i, status),
+            None => println!("  Port {}: {}", i, status),
         }
     }
     println!();
@@ -0,0 +1,261 @@
+//! Wait command implementation
+//!
+//! Follows the measurement samples on the daemon's event stream until the
+//! power state and every value condition hold for the same sample.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Result;
+use futures_util::StreamExt;
+use halpi_common::api::value_key;
+use halpi_common::events::DaemonEvent;
+use halpi_common::types::{Measurements, PowerState};
+
+use crate::client::HalpiClient;
+
+/// Comparison operators, longest first so that `>=` is not read as `>`
+const OPERATORS: [(&str, Comparison); 7] = [
+    (">=", Comparison::GreaterOrEqual),
+    ("<=", Comparison::LessOrEqual),
+    ("==", Comparison::Equal),
+    ("!=", Comparison::NotEqual),
+    (">", Comparison::Greater),
+    ("<", Comparison::Less),
+    ("=", Comparison::Equal),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Comparison {
+    fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Greater => value > threshold,
+            Comparison::GreaterOrEqual => value >= threshold,
+            Comparison::Less => value < threshold,
+            Comparison::LessOrEqual => value <= threshold,
+            Comparison::Equal => value == threshold,
+            Comparison::NotEqual => value != threshold,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+        }
+    }
+}
+
+/// A value condition such as `V_supercap>=8.0`
+///
+/// Values are named and in the units of `halpi get`; temperatures are in
+/// Kelvin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    key: &'static str,
+    comparison: Comparison,
+    threshold: f64,
+}
+
+impl Condition {
+    /// Whether the condition holds for a sample
+    fn holds(&self, measurements: &Measurements) -> bool {
+        self.comparison
+            .holds(value(measurements, self.key), self.threshold)
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid condition '{}', expected e.g. V_supercap>=8.0",
+                text
+            )
+        };
+        let start = text.find(['<', '>', '=', '!']).ok_or_else(invalid)?;
+        let (key, rest) = text.split_at(start);
+        let (symbol, comparison) = OPERATORS
+            .into_iter()
+            .find(|(symbol, _)| rest.starts_with(symbol))
+            .ok_or_else(invalid)?;
+        let threshold = rest[symbol.len()..].trim().parse().map_err(|_| invalid())?;
+        let key = match value_key(key.trim()) {
+            "V_in" => "V_in",
+            "V_cap" => "V_cap",
+            "I_in" => "I_in",
+            "T_mcu" => "T_mcu",
+            "T_pcb" => "T_pcb",
+            "watchdog_elapsed" => "watchdog_elapsed",
+            other => {
+                return Err(format!(
+                    "Unknown value '{}', expected one of V_in, V_cap, I_in, T_mcu, T_pcb, \
+                     watchdog_elapsed",
+                    other
+                ));
+            }
+        };
+        Ok(Self {
+            key,
+            comparison,
+            threshold,
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            self.key,
+            self.comparison.symbol(),
+            self.threshold
+        )
+    }
+}
+
+/// Value of a sample by its `halpi get` name
+fn value(measurements: &Measurements, key: &str) -> f64 {
+    match key {
+        "V_in" => measurements.dcin_voltage.as_f64(),
+        "V_cap" => measurements.supercap_voltage.as_f64(),
+        "I_in" => measurements.input_current.as_f64(),
+        "T_mcu" => measurements.mcu_temperature.as_f64(),
+        "T_pcb" => measurements.pcb_temperature.as_f64(),
+        _ => measurements.watchdog_elapsed as f64,
+    }
+}
+
+/// Whether a sample is in `state`, if given, and meets every condition
+fn is_met(
+    measurements: &Measurements,
+    state: Option<PowerState>,
+    conditions: &[Condition],
+) -> bool {
+    state.is_none_or(|state| measurements.power_state == state)
+        && conditions
+            .iter()
+            .all(|condition| condition.holds(measurements))
+}
+
+/// Block until the controller is in `state` and every condition holds
+///
+/// The event stream is reopened if the daemon restarts meanwhile.
+///
+/// # Errors
+/// Returns an error if `timeout` seconds pass first, or the daemon cannot
+/// be reached.
+pub async fn wait(
+    state: Option<PowerState>,
+    conditions: Vec<Condition>,
+    timeout: Option<u64>,
+) -> Result<()> {
+    let client = HalpiClient::new();
+    let mut events = client.subscribe_events(true).await?;
+    let met = async {
+        while let Some(event) = events.next().await {
+            if let DaemonEvent::Measurement(sample) = event
+                && is_met(&sample.measurements, state, &conditions)
+            {
+                return;
+            }
+        }
+    };
+    match timeout {
+        Some(timeout) => {
+            if tokio::time::timeout(Duration::from_secs(timeout), met)
+                .await
+                .is_err()
+            {
+                anyhow::bail!(
+                    "Timed out after {} s waiting for {}",
+                    timeout,
+                    describe(state, &conditions)
+                );
+            }
+        }
+        None => met.await,
+    }
+    Ok(())
+}
+
+fn describe(state: Option<PowerState>, conditions: &[Condition]) -> String {
+    state
+        .map(|state| format!("state {}", state))
+        .into_iter()
+        .chain(conditions.iter().map(Condition::to_string))
+        .collect::<Vec<_>>()
+        .join(" and ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halpi_common::units::{Amps, Kelvin, Volts};
+
+    fn measurements(supercap_voltage: f32) -> Measurements {
+        Measurements {
+            dcin_voltage: Volts(12.0),
+            supercap_voltage: Volts(supercap_voltage),
+            input_current: Amps(0.5),
+            mcu_temperature: Kelvin(300.0),
+            pcb_temperature: Kelvin(300.0),
+            power_state: PowerState::OperationalCoOp,
+            watchdog_elapsed: 0.0,
+            measured_at: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_parse_condition() {
+        let condition: Condition = "V_supercap>=8.0".parse().unwrap();
+        assert_eq!(condition.to_string(), "V_cap>=8");
+        let condition: Condition = "I_in < 1.5".parse().unwrap();
+        assert_eq!(condition.comparison, Comparison::Less);
+        assert_eq!(condition.threshold, 1.5);
+        let condition: Condition = "T_pcb=300".parse().unwrap();
+        assert_eq!(condition.comparison, Comparison::Equal);
+
+        assert!("V_supercap".parse::<Condition>().is_err());
+        assert!("V_out>1".parse::<Condition>().is_err());
+        assert!("V_in>=high".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn test_is_met() {
+        let conditions = vec!["V_cap>=8.0".parse().unwrap(), "V_in>11".parse().unwrap()];
+        assert!(is_met(&measurements(8.5), None, &conditions));
+        assert!(!is_met(&measurements(7.5), None, &conditions));
+        assert!(is_met(
+            &measurements(8.5),
+            Some(PowerState::OperationalCoOp),
+            &conditions
+        ));
+        assert!(!is_met(
+            &measurements(8.5),
+            Some(PowerState::BlackoutCoOp),
+            &[]
+        ));
+        assert_eq!(
+            describe(Some(PowerState::OperationalCoOp), &conditions),
+            "state OperationalCoOp and V_cap>=8 and V_in>11"
+        );
+    }
+}
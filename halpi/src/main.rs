@@ -1,7 +1,16 @@
 mod client;
 mod commands;
+mod query;
+mod template;
 
-use clap::{Parser, Subcommand};
+use clap::{ArgGroup, Parser, Subcommand};
+use commands::export::ExportFormat;
+use commands::wait::Condition;
+use halpi_common::duration::parse_duration;
+use halpi_common::protocol::{LedColor, LedPattern};
+use halpi_common::types::{PowerState, TemperatureUnit};
+use std::path::PathBuf;
+use std::time::Duration;
 
 /// HALPI2 command-line interface
 #[derive(Parser)]
@@ -9,30 +18,203 @@ use clap::{Parser, Subcommand};
 #[command(about = "HALPI2 command-line interface", long_about = None)]
 #[command(version)]
 struct Cli {
+    /// Show temperatures in °F (default: $HALPI_TEMPERATURE_UNIT, or °C)
+    #[arg(long, global = true)]
+    fahrenheit: bool,
+
+    /// Print only part of the command's JSON result, e.g. V_in,
+    /// i2c.totals.transfers or rows[0]
+    #[arg(long, global = true)]
+    query: Option<String>,
+
+    /// How long to wait for the daemon to answer (e.g. 5, 30s, 1m; 0 waits
+    /// indefinitely)
+    #[arg(
+        long,
+        global = true,
+        default_value = "10",
+        value_parser = parse_duration
+    )]
+    connect_timeout: u64,
+
+    /// Same as --connect-timeout, given before the command (`wait` has its
+    /// own --timeout); takes precedence over --connect-timeout
+    #[arg(long, value_parser = parse_duration)]
+    timeout: Option<u64>,
+
+    /// How many more times to try reaching a daemon that is not running yet
+    #[arg(long, global = true, default_value_t = client::DEFAULT_RETRIES)]
+    retries: u32,
+
+    /// Reach the daemon over TCP at host:port or an http:// URL instead of
+    /// its Unix socket (default: $HALPI_HOST)
+    #[arg(long, global = true)]
+    host: Option<String>,
+
+    /// Bearer token to send with --host (default: $HALPI_TOKEN)
+    #[arg(long, global = true)]
+    token: Option<String>,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
+/// Environment variable with the preferred temperature unit
+const TEMPERATURE_UNIT_ENV: &str = "HALPI_TEMPERATURE_UNIT";
+
+/// Environment variable with the daemon to reach over TCP
+const HOST_ENV: &str = "HALPI_HOST";
+
+/// Environment variable with the token for the daemon reached over TCP
+const TOKEN_ENV: &str = "HALPI_TOKEN";
+
+/// Temperature unit from `--fahrenheit` or the environment
+fn temperature_unit(fahrenheit: bool, preference: Option<&str>) -> TemperatureUnit {
+    if fahrenheit {
+        return TemperatureUnit::Fahrenheit;
+    }
+    match preference.map(str::parse) {
+        Some(Ok(unit)) => unit,
+        Some(Err(e)) => {
+            eprintln!("Warning: {}: {}", TEMPERATURE_UNIT_ENV, e);
+            TemperatureUnit::default()
+        }
+        None => TemperatureUnit::default(),
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Display status and measurement data from the device
-    Status,
+    Status {
+        /// Print values into a template instead, e.g. '{V_in:.1}V {state}'
+        #[arg(long)]
+        format: Option<String>,
+    },
+    /// Get a single value, e.g. V_in or state
+    Get {
+        /// Value name
+        key: String,
+        /// Print the value into a template, e.g. '{V_in:.1}V'
+        #[arg(long)]
+        format: Option<String>,
+    },
     /// Display version information
     Version,
+    /// Show the minimum, average and maximum voltages, current and
+    /// temperatures over a recent window
+    Stats {
+        /// Window to summarize (e.g. 90s, 15m; at most 1h)
+        #[arg(long, default_value = "15m", value_parser = parse_duration)]
+        window: u64,
+    },
+    /// Wait until the controller reaches a state and/or values meet
+    /// conditions, for scripts
+    #[command(group(ArgGroup::new("condition").required(true).multiple(true)))]
+    Wait {
+        /// Power state to wait for (e.g. OperationalCoOp)
+        #[arg(long, group = "condition")]
+        state: Option<PowerState>,
+        /// Value condition to wait for, in the units of `halpi get` (e.g.
+        /// "V_supercap>=8.0"); may be repeated, all must hold
+        #[arg(long = "value", group = "condition")]
+        values: Vec<Condition>,
+        /// Give up with an error after this long (e.g. 60, 5m)
+        #[arg(long, value_parser = parse_duration)]
+        timeout: Option<u64>,
+    },
+    /// Export the measurement history kept by the daemon (up to a day)
+    Export {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+        /// How far back to export (e.g. 30m, 6h; at most 24h)
+        #[arg(long, default_value = "24h", value_parser = parse_duration)]
+        since: u64,
+        /// File to write (default: standard output)
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Display versions, device ID, I2C location, socket and uptime
+    Info {
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Set a runtime value: led_brightness, 5v_output_enabled or
+    /// watchdog_timeout
+    Set {
+        /// Value name
+        key: String,
+        /// New value
+        value: String,
+    },
     /// Get or set configuration values
     Config {
         #[command(subcommand)]
         action: Option<ConfigAction>,
     },
-    /// Shutdown or standby the system
+    /// Shut down the system, now or at a scheduled time
     Shutdown {
-        /// Enter standby mode instead of shutdown
-        #[arg(long, requires = "time")]
+        /// Enter standby mode instead of shutdown; replaced by `halpi standby`
+        #[arg(long, hide = true, requires = "wake", conflicts_with_all = ["at", "delay", "cancel", "scheduled"])]
         standby: bool,
-        /// Wakeup time for standby (seconds or datetime string)
-        #[arg(long)]
+        /// Wakeup time for standby: a delay (e.g. 300, 90m, 2h30m, 1d), or a
+        /// datetime with an optional time zone (e.g. "2025-12-31 23:59:59
+        /// Europe/Helsinki"); without one, the datetime is in the system's
+        /// local time zone
+        #[arg(long, hide = true, group = "wake")]
         time: Option<String>,
+        /// Wake up for standby at sunrise or sunset at the location
+        /// configured in the daemon, with an optional offset (e.g. sunrise,
+        /// sunrise+30m, sunset-1h)
+        #[arg(long, hide = true, group = "wake", requires = "standby")]
+        wake_at: Option<String>,
+        /// Shut down at a time of day (e.g. 22:30) or datetime
+        #[arg(long, conflicts_with_all = ["delay", "cancel", "scheduled"])]
+        at: Option<String>,
+        /// Shut down after a delay (e.g. 90s, 45m, 2h)
+        #[arg(
+            long = "in",
+            value_parser = parse_duration,
+            conflicts_with_all = ["cancel", "scheduled"]
+        )]
+        delay: Option<u64>,
+        /// Cancel the scheduled shutdown
+        #[arg(long, conflicts_with = "scheduled")]
+        cancel: bool,
+        /// Show the scheduled shutdown
+        #[arg(long)]
+        scheduled: bool,
     },
+    /// Enter standby and let the controller wake the system up, or show or
+    /// clear the wake alarm
+    #[command(group(ArgGroup::new("standby").required(true)))]
+    Standby {
+        /// Wake up after a delay (e.g. 300, 90m, 2h30m, 1d)
+        #[arg(long = "in", group = "standby", value_parser = parse_duration)]
+        delay: Option<u64>,
+        /// Wake up at a datetime with an optional time zone (e.g.
+        /// "2025-12-31 23:59:59 Europe/Helsinki"); without one, the
+        /// datetime is in the system's local time zone
+        #[arg(long, group = "standby")]
+        at: Option<String>,
+        /// Wake up at sunrise or sunset at the location configured in the
+        /// daemon, with an optional offset (e.g. sunrise, sunrise+30m,
+        /// sunset-1h)
+        #[arg(long, group = "standby")]
+        wake_at: Option<String>,
+        /// Clear the wake alarm
+        #[arg(long, group = "standby")]
+        cancel: bool,
+        /// Show the wake alarm
+        #[arg(long, group = "standby")]
+        status: bool,
+    },
+    /// Shut down and let the controller power the system back on
+    ///
+    /// Enables auto-restart, which stays enabled.
+    Reboot,
     /// Control USB port power
     Usb {
         #[command(subcommand)]
@@ -43,6 +225,51 @@ enum Commands {
         /// Path to firmware binary file
         firmware: String,
     },
+    /// Display daemon health and I2C error statistics
+    Diagnose,
+    /// Check the daemon, controller and permissions and suggest fixes
+    Doctor,
+    /// Show recent state transitions, alerts and voltage excursions
+    Events {
+        /// Keep printing new events until interrupted
+        #[arg(short, long)]
+        follow: bool,
+        /// Only events from this long ago (e.g. 30m, 1h, 2d)
+        #[arg(long, value_parser = parse_duration)]
+        since: Option<u64>,
+        /// Print each event as a JSON line
+        #[arg(long)]
+        json: bool,
+    },
+    /// Collect values, configuration, events, logs and registers into an
+    /// archive to attach to issues (as root)
+    SupportBundle {
+        /// Archive to write (default: halpi-support-<time>.tar.gz)
+        output: Option<PathBuf>,
+    },
+    /// Scan the I2C buses for the controller
+    Scan,
+    /// Suspend blackout shutdowns, e.g. for bench work
+    Maintenance {
+        #[command(subcommand)]
+        action: Option<MaintenanceAction>,
+    },
+    /// Show or set the hardware RTC time
+    Rtc {
+        #[command(subcommand)]
+        action: Option<RtcAction>,
+    },
+    /// Show or set the front LED brightness, pattern and color
+    Led {
+        #[command(subcommand)]
+        action: Option<LedAction>,
+    },
+    /// Ramp the front LED up and down to find this unit
+    Identify {
+        /// How long to ramp the LED (e.g. 30, 2m; at most 5m)
+        #[arg(long, default_value = "10", value_parser = parse_duration)]
+        duration: u64,
+    },
 }
 
 #[derive(Subcommand)]
@@ -59,6 +286,8 @@ enum ConfigAction {
         /// Value to set
         value: String,
     },
+    /// Compare the controller settings with the daemon configuration
+    Diff,
 }
 
 #[derive(Subcommand)]
@@ -75,29 +304,138 @@ enum UsbAction {
     },
 }
 
+#[derive(Subcommand)]
+enum MaintenanceAction {
+    /// Enable maintenance mode
+    On {
+        /// How long to stay in maintenance mode (e.g. 90s, 30m, 2h; default 1h)
+        #[arg(long = "for", value_parser = parse_duration)]
+        duration: Option<u64>,
+    },
+    /// Disable maintenance mode
+    Off,
+}
+
+#[derive(Subcommand)]
+enum RtcAction {
+    /// Set the RTC, to the system time unless a time is given
+    Set {
+        /// Time to set (ISO 8601, e.g. 2025-11-08T12:00:00Z)
+        time: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum LedAction {
+    /// Set the LED; settings not given are kept
+    #[command(group(ArgGroup::new("led").required(true).multiple(true)))]
+    Set {
+        /// Brightness (0-255)
+        #[arg(long, group = "led")]
+        brightness: Option<u8>,
+        /// Pattern: status (the firmware shows the power state), off, solid,
+        /// blink, fast-blink or pulse; needs firmware 3.3.0
+        #[arg(long, group = "led")]
+        pattern: Option<LedPattern>,
+        /// Color as #rrggbb or a name (red, green, blue, white, yellow,
+        /// orange, cyan, magenta); needs firmware 3.3.0
+        #[arg(long, group = "led")]
+        color: Option<LedColor>,
+    },
+}
+
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
+    let preference = std::env::var(TEMPERATURE_UNIT_ENV).ok();
+    let unit = temperature_unit(cli.fahrenheit, preference.as_deref());
+    let host = cli.host.or_else(|| std::env::var(HOST_ENV).ok());
+    if let Some(host) = host {
+        let token = cli.token.or_else(|| std::env::var(TOKEN_ENV).ok());
+        match client::Endpoint::http(&host, token) {
+            Ok(endpoint) => client::set_default_endpoint(endpoint),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    let connect_timeout = cli.timeout.unwrap_or(cli.connect_timeout);
+    client::set_default_options(client::ClientOptions {
+        timeout: (connect_timeout > 0).then(|| Duration::from_secs(connect_timeout)),
+        retries: cli.retries,
+    });
+
+    let result = match cli.query {
+        Some(expression) => match json_result(cli.command).await {
+            Ok(value) => query::select(&value, &expression).and_then(|value| query::print(&value)),
+            Err(e) => Err(e),
+        },
+        None => run(cli.command, unit).await,
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
 
-    let result = match cli.command {
-        Some(Commands::Status) => commands::status::status().await,
+/// Run a command
+async fn run(command: Option<Commands>, unit: TemperatureUnit) -> anyhow::Result<()> {
+    match command {
+        Some(Commands::Status { format }) => {
+            commands::status::status(format.as_deref(), unit).await
+        }
+        Some(Commands::Get { key, format }) => {
+            commands::status::get(&key, format.as_deref(), unit).await
+        }
+        Some(Commands::Stats { window }) => commands::stats::stats(window, unit).await,
+        Some(Commands::Wait {
+            state,
+            values,
+            timeout,
+        }) => commands::wait::wait(state, values, timeout).await,
+        Some(Commands::Export { format, since, out }) => {
+            commands::export::export(format, since, out.as_deref(), unit).await
+        }
+        Some(Commands::Info { json }) => commands::info::info(json).await,
         Some(Commands::Version) | None => {
             println!("halpi version {}", env!("CARGO_PKG_VERSION"));
             Ok(())
         }
+        Some(Commands::Set { key, value }) => commands::set::set(&key, &value).await,
         Some(Commands::Config { action }) => match action {
             Some(ConfigAction::Get { key }) => commands::config::config_get(&key).await,
             Some(ConfigAction::Set { key, value }) => {
                 commands::config::config_set(&key, &value).await
             }
+            Some(ConfigAction::Diff) => commands::config::config_diff().await,
             None => commands::config::config_get_all().await,
         },
-        Some(Commands::Shutdown { standby, time }) => {
-            if standby {
-                // Clap enforces that time is present when standby is true (via requires attribute)
-                let t = time.unwrap();
-                // Try to parse as integer (seconds), otherwise treat as datetime
-                if let Ok(delay) = t.parse::<u32>() {
+        Some(Commands::Shutdown {
+            standby,
+            time,
+            wake_at,
+            at,
+            delay,
+            cancel,
+            scheduled,
+        }) => {
+            if let Some(at) = at {
+                commands::shutdown::schedule_at(&at).await
+            } else if let Some(delay) = delay {
+                commands::shutdown::schedule_in(delay).await
+            } else if cancel {
+                commands::shutdown::cancel_schedule().await
+            } else if scheduled {
+                commands::shutdown::schedule_status().await
+            } else if standby {
+                // Clap enforces that time or wake-at is present when standby is true
+                let t = time.unwrap_or_default();
+                if let Some(wake_at) = wake_at {
+                    commands::shutdown::standby_sun(&wake_at).await
+                // Try to parse as a duration (e.g. 300, 90m, 2h30m), otherwise treat as datetime
+                } else if let Ok(delay) = parse_duration(&t) {
                     commands::shutdown::standby_delay(delay).await
                 } else {
                     commands::shutdown::standby_datetime(&t).await
@@ -106,17 +444,103 @@ async fn main() {
                 commands::shutdown::shutdown().await
             }
         }
+        Some(Commands::Standby {
+            delay,
+            at,
+            wake_at,
+            cancel,
+            ..
+        }) => {
+            if let Some(delay) = delay {
+                commands::shutdown::standby_delay(delay).await
+            } else if let Some(at) = at {
+                commands::shutdown::standby_datetime(&at).await
+            } else if let Some(wake_at) = wake_at {
+                commands::shutdown::standby_sun(&wake_at).await
+            } else if cancel {
+                commands::shutdown::standby_cancel().await
+            } else {
+                commands::shutdown::standby_status().await
+            }
+        }
+        Some(Commands::Reboot) => commands::shutdown::reboot().await,
         Some(Commands::Usb { action }) => match action {
             Some(UsbAction::Enable { port }) => commands::usb::usb_enable(&port).await,
             Some(UsbAction::Disable { port }) => commands::usb::usb_disable(&port).await,
             None => commands::usb::usb_status().await,
         },
         Some(Commands::Flash { firmware }) => commands::flash::flash(&firmware).await,
-    };
+        Some(Commands::Diagnose) => commands::diagnose::diagnose().await,
+        Some(Commands::Doctor) => commands::doctor::doctor().await,
+        Some(Commands::Events {
+            follow,
+            since,
+            json,
+        }) => commands::events::events(follow, since, json).await,
+        Some(Commands::SupportBundle { output }) => {
+            commands::support_bundle::support_bundle(output).await
+        }
+        Some(Commands::Scan) => commands::scan::scan().await,
+        Some(Commands::Maintenance { action }) => match action {
+            Some(MaintenanceAction::On { duration }) => {
+                commands::maintenance::maintenance_on(duration).await
+            }
+            Some(MaintenanceAction::Off) => commands::maintenance::maintenance_off().await,
+            None => commands::maintenance::maintenance_status().await,
+        },
+        Some(Commands::Rtc { action }) => match action {
+            Some(RtcAction::Set { time }) => commands::rtc::rtc_set(time.as_deref()).await,
+            None => commands::rtc::rtc_status().await,
+        },
+        Some(Commands::Led { action }) => match action {
+            Some(LedAction::Set {
+                brightness,
+                pattern,
+                color,
+            }) => commands::led::led_set(brightness, pattern, color).await,
+            None => commands::led::led_status().await,
+        },
+        Some(Commands::Identify { duration }) => commands::led::identify(duration).await,
+    }
+}
 
-    if let Err(e) = result {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+/// JSON result of a command that shows something, for `--query`
+async fn json_result(command: Option<Commands>) -> anyhow::Result<serde_json::Value> {
+    use serde_json::json;
+
+    let client = client::HalpiClient::new();
+    match command {
+        Some(Commands::Status { .. }) => Ok(json!(client.get_values().await?)),
+        Some(Commands::Get { key, .. }) => client.get_value(&key).await,
+        Some(Commands::Info { .. }) => client.get_info().await,
+        Some(Commands::Version) | None => Ok(json!({"version": env!("CARGO_PKG_VERSION")})),
+        Some(Commands::Stats { window }) => client.get_stats(window).await,
+        Some(Commands::Events {
+            follow: false,
+            since,
+            ..
+        }) => client.get_events(since).await,
+        Some(Commands::Export { since, .. }) => client.get_measurement_history(since).await,
+        Some(Commands::Config { action: None }) => Ok(json!(client.get_config().await?)),
+        Some(Commands::Config {
+            action: Some(ConfigAction::Get { key }),
+        }) => commands::config::config_values(&client.get_config().await?)?
+            .remove(&key)
+            .ok_or_else(|| anyhow::anyhow!("Configuration key '{}' not found", key)),
+        Some(Commands::Config {
+            action: Some(ConfigAction::Diff),
+        }) => Ok(json!(client.get_config_diff().await?)),
+        Some(Commands::Shutdown {
+            scheduled: true, ..
+        }) => client.get_shutdown_schedule().await,
+        Some(Commands::Standby { status: true, .. }) => client.get_standby().await,
+        Some(Commands::Usb { action: None }) => Ok(json!(client.get_usb_ports().await?)),
+        Some(Commands::Diagnose) => client.get_health().await,
+        Some(Commands::Scan) => client.scan().await,
+        Some(Commands::Maintenance { action: None }) => client.get_state().await,
+        Some(Commands::Rtc { action: None }) => client.get_rtc().await,
+        Some(Commands::Led { action: None }) => client.get_led().await,
+        Some(_) => anyhow::bail!("--query only works with commands that show something"),
     }
 }
 
@@ -134,7 +558,28 @@ mod tests {
     #[test]
     fn test_cli_status_command() {
         let cli = Cli::try_parse_from(["halpi", "status"]).unwrap();
-        assert!(matches!(cli.command, Some(Commands::Status)));
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Status { format: None })
+        ));
+        let cli = Cli::try_parse_from(["halpi", "status", "--format", "{V_in:.1}V"]).unwrap();
+        match cli.command {
+            Some(Commands::Status { format }) => assert_eq!(format.as_deref(), Some("{V_in:.1}V")),
+            _ => panic!("Expected Status command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_get() {
+        let cli = Cli::try_parse_from(["halpi", "get", "V_in"]).unwrap();
+        match cli.command {
+            Some(Commands::Get { key, format }) => {
+                assert_eq!(key, "V_in");
+                assert_eq!(format, None);
+            }
+            _ => panic!("Expected Get command"),
+        }
+        assert!(Cli::try_parse_from(["halpi", "get"]).is_err());
     }
 
     #[test]
@@ -185,7 +630,7 @@ mod tests {
     fn test_cli_shutdown() {
         let cli = Cli::try_parse_from(["halpi", "shutdown"]).unwrap();
         match cli.command {
-            Some(Commands::Shutdown { standby, time }) => {
+            Some(Commands::Shutdown { standby, time, .. }) => {
                 assert!(!standby);
                 assert!(time.is_none());
             }
@@ -197,7 +642,7 @@ mod tests {
     fn test_cli_standby_with_delay() {
         let cli = Cli::try_parse_from(["halpi", "shutdown", "--standby", "--time", "300"]).unwrap();
         match cli.command {
-            Some(Commands::Shutdown { standby, time }) => {
+            Some(Commands::Shutdown { standby, time, .. }) => {
                 assert!(standby);
                 assert_eq!(time, Some("300".to_string()));
             }
@@ -216,7 +661,7 @@ mod tests {
         ])
         .unwrap();
         match cli.command {
-            Some(Commands::Shutdown { standby, time }) => {
+            Some(Commands::Shutdown { standby, time, .. }) => {
                 assert!(standby);
                 assert_eq!(time, Some("2025-12-31T23:59:59".to_string()));
             }
@@ -224,6 +669,80 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cli_shutdown_schedule() {
+        let cli = Cli::try_parse_from(["halpi", "shutdown", "--at", "22:30"]).unwrap();
+        match cli.command {
+            Some(Commands::Shutdown { at, delay, .. }) => {
+                assert_eq!(at, Some("22:30".to_string()));
+                assert!(delay.is_none());
+            }
+            _ => panic!("Expected Shutdown command"),
+        }
+
+        let cli = Cli::try_parse_from(["halpi", "shutdown", "--in", "45m"]).unwrap();
+        match cli.command {
+            Some(Commands::Shutdown { at, delay, .. }) => {
+                assert!(at.is_none());
+                assert_eq!(delay, Some(2700));
+            }
+            _ => panic!("Expected Shutdown command"),
+        }
+
+        let cli = Cli::try_parse_from(["halpi", "shutdown", "--cancel"]).unwrap();
+        match cli.command {
+            Some(Commands::Shutdown { cancel, .. }) => assert!(cancel),
+            _ => panic!("Expected Shutdown command"),
+        }
+
+        assert!(
+            Cli::try_parse_from(["halpi", "shutdown", "--at", "22:30", "--in", "45m"]).is_err()
+        );
+        assert!(
+            Cli::try_parse_from([
+                "halpi",
+                "shutdown",
+                "--standby",
+                "--time",
+                "300",
+                "--in",
+                "45m"
+            ])
+            .is_err()
+        );
+        assert!(Cli::try_parse_from(["halpi", "shutdown", "--in", "soon"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_standby_wake_at() {
+        let cli =
+            Cli::try_parse_from(["halpi", "shutdown", "--standby", "--wake-at", "sunrise+30m"])
+                .unwrap();
+        match cli.command {
+            Some(Commands::Shutdown {
+                standby, wake_at, ..
+            }) => {
+                assert!(standby);
+                assert_eq!(wake_at.as_deref(), Some("sunrise+30m"));
+            }
+            _ => panic!("Expected Shutdown command"),
+        }
+
+        assert!(Cli::try_parse_from(["halpi", "shutdown", "--wake-at", "sunrise"]).is_err());
+        assert!(
+            Cli::try_parse_from([
+                "halpi",
+                "shutdown",
+                "--standby",
+                "--time",
+                "300",
+                "--wake-at",
+                "sunrise"
+            ])
+            .is_err()
+        );
+    }
+
     #[test]
     fn test_cli_usb_status() {
         let cli = Cli::try_parse_from(["halpi", "usb"]).unwrap();
@@ -266,6 +785,392 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cli_diagnose() {
+        let cli = Cli::try_parse_from(["halpi", "diagnose"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::Diagnose)));
+    }
+
+    #[test]
+    fn test_cli_scan() {
+        let cli = Cli::try_parse_from(["halpi", "scan"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::Scan)));
+    }
+
+    #[test]
+    fn test_cli_led() {
+        let cli = Cli::try_parse_from(["halpi", "led"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::Led { action: None })));
+
+        let cli = Cli::try_parse_from([
+            "halpi",
+            "led",
+            "set",
+            "--pattern",
+            "fast-blink",
+            "--color",
+            "#ff8000",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(Commands::Led {
+                action:
+                    Some(LedAction::Set {
+                        brightness,
+                        pattern,
+                        color,
+                    }),
+            }) => {
+                assert_eq!(brightness, None);
+                assert_eq!(pattern, Some(LedPattern::FastBlink));
+                assert_eq!(color.unwrap().to_string(), "#ff8000");
+            }
+            _ => panic!("Expected Led set command"),
+        }
+
+        assert!(Cli::try_parse_from(["halpi", "led", "set"]).is_err());
+        assert!(Cli::try_parse_from(["halpi", "led", "set", "--pattern", "strobe"]).is_err());
+        assert!(Cli::try_parse_from(["halpi", "led", "set", "--brightness", "300"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_standby() {
+        let cli = Cli::try_parse_from(["halpi", "standby", "--in", "2h"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Standby {
+                delay: Some(7200),
+                ..
+            })
+        ));
+        let cli = Cli::try_parse_from(["halpi", "standby", "--at", "2025-12-31 23:59:59"]).unwrap();
+        match cli.command {
+            Some(Commands::Standby { at, .. }) => {
+                assert_eq!(at.as_deref(), Some("2025-12-31 23:59:59"))
+            }
+            _ => panic!("Expected Standby command"),
+        }
+        let cli = Cli::try_parse_from(["halpi", "standby", "--cancel"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Standby { cancel: true, .. })
+        ));
+        let cli = Cli::try_parse_from(["halpi", "standby", "--status"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Standby { status: true, .. })
+        ));
+
+        assert!(Cli::try_parse_from(["halpi", "standby"]).is_err());
+        assert!(Cli::try_parse_from(["halpi", "standby", "--in", "2h", "--cancel"]).is_err());
+        assert!(Cli::try_parse_from(["halpi", "standby", "--in", "soon"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_doctor() {
+        let cli = Cli::try_parse_from(["halpi", "doctor"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::Doctor)));
+    }
+
+    #[test]
+    fn test_cli_support_bundle() {
+        let cli = Cli::try_parse_from(["halpi", "support-bundle"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::SupportBundle { output: None })
+        ));
+        let cli = Cli::try_parse_from(["halpi", "support-bundle", "out.tar.gz"]).unwrap();
+        match cli.command {
+            Some(Commands::SupportBundle { output }) => {
+                assert_eq!(output, Some(PathBuf::from("out.tar.gz")))
+            }
+            _ => panic!("Expected SupportBundle command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_query() {
+        let cli = Cli::try_parse_from(["halpi", "status", "--query", "V_in"]).unwrap();
+        assert_eq!(cli.query.as_deref(), Some("V_in"));
+        let cli = Cli::try_parse_from(["halpi", "--query", "i2c", "diagnose"]).unwrap();
+        assert_eq!(cli.query.as_deref(), Some("i2c"));
+        assert!(matches!(cli.command, Some(Commands::Diagnose)));
+    }
+
+    #[test]
+    fn test_cli_client_options() {
+        let cli = Cli::try_parse_from(["halpi", "status"]).unwrap();
+        assert_eq!(cli.connect_timeout, client::DEFAULT_TIMEOUT.as_secs());
+        assert_eq!(cli.retries, client::DEFAULT_RETRIES);
+        let cli = Cli::try_parse_from([
+            "halpi",
+            "wait",
+            "--state",
+            "OperationalCoOp",
+            "--timeout",
+            "5m",
+            "--connect-timeout",
+            "30s",
+            "--retries",
+            "0",
+        ])
+        .unwrap();
+        assert_eq!(cli.connect_timeout, 30);
+        assert_eq!(cli.retries, 0);
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Wait {
+                timeout: Some(300),
+                ..
+            })
+        ));
+
+        let cli = Cli::try_parse_from([
+            "halpi",
+            "--timeout",
+            "1m",
+            "wait",
+            "--state",
+            "OperationalCoOp",
+            "--timeout",
+            "5m",
+        ])
+        .unwrap();
+        assert_eq!(cli.timeout, Some(60));
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Wait {
+                timeout: Some(300),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_cli_host() {
+        let cli = Cli::try_parse_from([
+            "halpi",
+            "--host",
+            "nav-pi.local:8080",
+            "status",
+            "--token",
+            "secret",
+        ])
+        .unwrap();
+        assert_eq!(cli.host.as_deref(), Some("nav-pi.local:8080"));
+        assert_eq!(cli.token.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn test_temperature_unit() {
+        let cli = Cli::try_parse_from(["halpi", "status", "--fahrenheit"]).unwrap();
+        assert!(cli.fahrenheit);
+        assert_eq!(
+            temperature_unit(cli.fahrenheit, Some("celsius")),
+            TemperatureUnit::Fahrenheit
+        );
+        assert_eq!(
+            temperature_unit(false, Some("fahrenheit")),
+            TemperatureUnit::Fahrenheit
+        );
+        assert_eq!(
+            temperature_unit(false, Some("kelvin")),
+            TemperatureUnit::Celsius
+        );
+        assert_eq!(temperature_unit(false, None), TemperatureUnit::Celsius);
+    }
+
+    #[test]
+    fn test_cli_set() {
+        let cli = Cli::try_parse_from(["halpi", "set", "led_brightness", "128"]).unwrap();
+        match cli.command {
+            Some(Commands::Set { key, value }) => {
+                assert_eq!(key, "led_brightness");
+                assert_eq!(value, "128");
+            }
+            _ => panic!("Expected Set command"),
+        }
+        assert!(Cli::try_parse_from(["halpi", "set", "led_brightness"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_wait() {
+        let cli = Cli::try_parse_from([
+            "halpi",
+            "wait",
+            "--state",
+            "OperationalCoOp",
+            "--timeout",
+            "60",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(Commands::Wait {
+                state,
+                values,
+                timeout,
+            }) => {
+                assert_eq!(state, Some(PowerState::OperationalCoOp));
+                assert!(values.is_empty());
+                assert_eq!(timeout, Some(60));
+            }
+            _ => panic!("Expected Wait command"),
+        }
+        let cli = Cli::try_parse_from([
+            "halpi",
+            "wait",
+            "--value",
+            "V_supercap>=8.0",
+            "--value",
+            "V_in>11",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(Commands::Wait { state, values, .. }) => {
+                assert_eq!(state, None);
+                assert_eq!(values.len(), 2);
+            }
+            _ => panic!("Expected Wait command"),
+        }
+        assert!(Cli::try_parse_from(["halpi", "wait"]).is_err());
+        assert!(Cli::try_parse_from(["halpi", "wait", "--state", "Running"]).is_err());
+        assert!(Cli::try_parse_from(["halpi", "wait", "--value", "V_in"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_export() {
+        let cli = Cli::try_parse_from(["halpi", "export"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Export {
+                format: ExportFormat::Csv,
+                since: 86400,
+                out: None
+            })
+        ));
+        let cli = Cli::try_parse_from([
+            "halpi", "export", "--format", "json", "--since", "6h", "--out", "h.json",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(Commands::Export { format, since, out }) => {
+                assert_eq!(format, ExportFormat::Json);
+                assert_eq!(since, 6 * 3600);
+                assert_eq!(out, Some(PathBuf::from("h.json")));
+            }
+            _ => panic!("Expected Export command"),
+        }
+        assert!(Cli::try_parse_from(["halpi", "export", "--format", "xlsx"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_stats() {
+        let cli = Cli::try_parse_from(["halpi", "stats"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::Stats { window: 900 })));
+        let cli = Cli::try_parse_from(["halpi", "stats", "--window", "1h"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Stats { window: 3600 })
+        ));
+        assert!(Cli::try_parse_from(["halpi", "stats", "--window", "soon"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_events() {
+        let cli = Cli::try_parse_from(["halpi", "events"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Events {
+                follow: false,
+                since: None,
+                json: false
+            })
+        ));
+        let cli =
+            Cli::try_parse_from(["halpi", "events", "-f", "--since", "1h", "--json"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Events {
+                follow: true,
+                since: Some(3600),
+                json: true
+            })
+        ));
+        assert!(Cli::try_parse_from(["halpi", "events", "--since", "soon"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_info() {
+        let cli = Cli::try_parse_from(["halpi", "info"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::Info { json: false })));
+        let cli = Cli::try_parse_from(["halpi", "info", "--json"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::Info { json: true })));
+    }
+
+    #[test]
+    fn test_cli_reboot() {
+        let cli = Cli::try_parse_from(["halpi", "reboot"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::Reboot)));
+        assert!(Cli::try_parse_from(["halpi", "reboot", "--standby"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_identify() {
+        let cli = Cli::try_parse_from(["halpi", "identify"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Identify { duration: 10 })
+        ));
+        let cli = Cli::try_parse_from(["halpi", "identify", "--duration", "1m"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Identify { duration: 60 })
+        ));
+    }
+
+    #[test]
+    fn test_cli_maintenance() {
+        let cli = Cli::try_parse_from(["halpi", "maintenance", "on", "--for", "30m"]).unwrap();
+        match cli.command {
+            Some(Commands::Maintenance { action }) => match action {
+                Some(MaintenanceAction::On { duration }) => assert_eq!(duration, Some(1800)),
+                _ => panic!("Expected On action"),
+            },
+            _ => panic!("Expected Maintenance command"),
+        }
+
+        let cli = Cli::try_parse_from(["halpi", "maintenance", "off"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Maintenance {
+                action: Some(MaintenanceAction::Off)
+            })
+        ));
+        assert!(Cli::try_parse_from(["halpi", "maintenance", "on", "--for", "soon"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_rtc() {
+        let cli = Cli::try_parse_from(["halpi", "rtc"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::Rtc { action: None })));
+
+        let cli = Cli::try_parse_from(["halpi", "rtc", "set"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Rtc {
+                action: Some(RtcAction::Set { time: None })
+            })
+        ));
+
+        let cli = Cli::try_parse_from(["halpi", "rtc", "set", "2025-11-08T12:00:00Z"]).unwrap();
+        match cli.command {
+            Some(Commands::Rtc {
+                action: Some(RtcAction::Set { time }),
+            }) => assert_eq!(time.as_deref(), Some("2025-11-08T12:00:00Z")),
+            _ => panic!("Expected Rtc set command"),
+        }
+    }
+
     #[test]
     fn test_cli_standby_requires_time() {
         // This should fail because --standby requires --time
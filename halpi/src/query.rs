@@ -0,0 +1,175 @@
+//! JSON result queries
+//!
+//! Selects part of a command's JSON result with a dotted, JSONPath-like
+//! expression, for scripts on images without `jq`:
+//!
+//! - `V_in` or `$.V_in` - a field
+//! - `i2c.totals.transfers` - a nested field
+//! - `rows[0]` or `rows.0` - an array element; negative indices count from
+//!   the end
+//! - `["5v_output_enabled"]` - a field whose name needs quoting
+//! - `rows[*].V_in` or `rows.*.V_in` - the field of every element, as an
+//!   array
+
+use anyhow::Result;
+use serde_json::Value;
+
+/// One step of a query
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Field(String),
+    Index(i64),
+    Wildcard,
+}
+
+/// Parse a query into steps
+fn parse(query: &str) -> Result<Vec<Step>> {
+    let invalid = |reason: &str| anyhow::anyhow!("Invalid query '{}': {}", query, reason);
+    let text = query.trim();
+    let text = text.strip_prefix('$').unwrap_or(text);
+    let mut steps = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(|| invalid("missing ']'"))?;
+            let inner = after[..end].trim();
+            steps.push(if inner == "*" {
+                Step::Wildcard
+            } else if let Some(name) = inner
+                .strip_prefix('"')
+                .and_then(|inner| inner.strip_suffix('"'))
+                .or_else(|| {
+                    inner
+                        .strip_prefix('\'')
+                        .and_then(|inner| inner.strip_suffix('\''))
+                })
+            {
+                Step::Field(name.to_string())
+            } else {
+                Step::Index(
+                    inner
+                        .parse()
+                        .map_err(|_| invalid("expected an index, * or a quoted name in []"))?,
+                )
+            });
+            rest = &after[end + 1..];
+            continue;
+        }
+        // Also skips the "." of "$.V_in"
+        let segment = rest.strip_prefix('.').unwrap_or(rest);
+        let end = segment.find(['.', '[']).unwrap_or(segment.len());
+        let name = &segment[..end];
+        if name.is_empty() {
+            return Err(invalid("empty field name"));
+        }
+        steps.push(match name {
+            "*" => Step::Wildcard,
+            // Numeric segments index arrays, and objects by name
+            _ => match name.parse() {
+                Ok(index) => Step::Index(index),
+                Err(_) => Step::Field(name.to_string()),
+            },
+        });
+        rest = &segment[end..];
+    }
+    Ok(steps)
+}
+
+/// Select the part of `value` a query names
+///
+/// # Errors
+/// Returns an error if the query is invalid or names something that does
+/// not exist.
+pub fn select(value: &Value, query: &str) -> Result<Value> {
+    let steps = parse(query)?;
+    select_steps(value, &steps).ok_or_else(|| anyhow::anyhow!("No value at '{}'", query))
+}
+
+fn select_steps(value: &Value, steps: &[Step]) -> Option<Value> {
+    let Some((step, rest)) = steps.split_first() else {
+        return Some(value.clone());
+    };
+    match (step, value) {
+        (Step::Field(name), Value::Object(map)) => select_steps(map.get(name)?, rest),
+        (Step::Index(index), Value::Object(map)) => {
+            select_steps(map.get(&index.to_string())?, rest)
+        }
+        (Step::Index(index), Value::Array(items)) => {
+            let len = items.len() as i64;
+            let index = if *index < 0 { len + index } else { *index };
+            select_steps(items.get(usize::try_from(index).ok()?)?, rest)
+        }
+        (Step::Wildcard, Value::Array(items)) => Some(Value::Array(
+            items
+                .iter()
+                .filter_map(|item| select_steps(item, rest))
+                .collect(),
+        )),
+        (Step::Wildcard, Value::Object(map)) => Some(Value::Array(
+            map.values()
+                .filter_map(|item| select_steps(item, rest))
+                .collect(),
+        )),
+        _ => None,
+    }
+}
+
+/// Print a selected value: strings without quotes, other scalars as JSON
+/// and arrays and objects pretty-printed
+pub fn print(value: &Value) -> Result<()> {
+    match value {
+        Value::String(text) => println!("{}", text),
+        Value::Array(_) | Value::Object(_) => println!("{}", serde_json::to_string_pretty(value)?),
+        other => println!("{}", other),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn result() -> Value {
+        json!({
+            "V_in": 12.3,
+            "5v_output_enabled": true,
+            "i2c": {"totals": {"transfers": 100}},
+            "rows": [{"V_in": 12.0}, {"V_in": 11.5}],
+            "usb": {"0": true, "1": false},
+        })
+    }
+
+    #[test]
+    fn test_select() {
+        let result = result();
+        assert_eq!(select(&result, "V_in").unwrap(), json!(12.3));
+        assert_eq!(select(&result, "$.V_in").unwrap(), json!(12.3));
+        assert_eq!(select(&result, "i2c.totals.transfers").unwrap(), json!(100));
+        assert_eq!(select(&result, "rows[1].V_in").unwrap(), json!(11.5));
+        assert_eq!(select(&result, "rows.0.V_in").unwrap(), json!(12.0));
+        assert_eq!(select(&result, "rows[-1].V_in").unwrap(), json!(11.5));
+        assert_eq!(
+            select(&result, "rows[*].V_in").unwrap(),
+            json!([12.0, 11.5])
+        );
+        assert_eq!(select(&result, "rows.*.V_in").unwrap(), json!([12.0, 11.5]));
+        assert_eq!(
+            select(&result, "[\"5v_output_enabled\"]").unwrap(),
+            json!(true)
+        );
+        assert_eq!(select(&result, "usb.1").unwrap(), json!(false));
+        assert_eq!(select(&result, "$").unwrap(), result);
+    }
+
+    #[test]
+    fn test_select_errors() {
+        let result = result();
+        assert!(select(&result, "V_out").is_err());
+        assert!(select(&result, "rows[5]").is_err());
+        assert!(select(&result, "V_in.x").is_err());
+        assert!(select(&result, "rows[0").is_err());
+        assert!(select(&result, "rows[x]").is_err());
+        assert!(select(&result, "i2c..totals").is_err());
+    }
+}
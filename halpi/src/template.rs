@@ -0,0 +1,166 @@
+//! Output templates
+//!
+//! Renders values into a user template such as `{V_in:.1}V {state}`, for
+//! status bars that would otherwise need a JSON pipeline. `{key}` inserts a
+//! value, `{key:.N}` a number with N decimals, and `{{` and `}}` insert
+//! literal braces. Temperatures are converted to the selected unit.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use anyhow::Result;
+use halpi_common::types::TemperatureUnit;
+use serde_json::Value;
+
+/// Values given in Kelvin by the daemon
+const TEMPERATURES: [&str; 2] = ["T_mcu", "T_pcb"];
+
+/// Render `template` with `values`
+///
+/// # Errors
+/// Returns an error for unbalanced braces, unknown keys or an invalid
+/// format specification.
+pub fn render(
+    template: &str,
+    values: &HashMap<String, Value>,
+    unit: TemperatureUnit,
+) -> Result<String> {
+    let mut output = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                output.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                output.push('}');
+            }
+            '{' => {
+                let mut field = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    field.push(c);
+                }
+                if !closed {
+                    anyhow::bail!("Unclosed '{{' in template");
+                }
+                render_field(&mut output, &field, values, unit)?;
+            }
+            '}' => anyhow::bail!("Unmatched '}}' in template; use '}}}}' for a brace"),
+            c => output.push(c),
+        }
+    }
+    Ok(output)
+}
+
+fn render_field(
+    output: &mut String,
+    field: &str,
+    values: &HashMap<String, Value>,
+    unit: TemperatureUnit,
+) -> Result<()> {
+    let (key, spec) = field.split_once(':').unwrap_or((field, ""));
+    let Some(value) = values.get(key) else {
+        let mut keys: Vec<&str> = values.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        anyhow::bail!(
+            "Unknown key '{}' in template; keys: {}",
+            key,
+            keys.join(", ")
+        );
+    };
+    let precision = match spec {
+        "" => None,
+        _ => match spec.strip_prefix('.').map(str::parse::<usize>) {
+            Some(Ok(precision)) => Some(precision),
+            _ => anyhow::bail!(
+                "Invalid format '{}' for {}, expected e.g. {{{}:.1}}",
+                spec,
+                key,
+                key
+            ),
+        },
+    };
+
+    match value.as_f64() {
+        Some(number) => {
+            let number = if TEMPERATURES.contains(&key) {
+                unit.convert_kelvin(number)
+            } else {
+                number
+            };
+            match precision {
+                Some(precision) => write!(output, "{:.*}", precision, number)?,
+                None if value.is_f64() || TEMPERATURES.contains(&key) => {
+                    write!(output, "{}", number)?
+                }
+                None => write!(output, "{}", value)?,
+            }
+        }
+        None if precision.is_some() => {
+            anyhow::bail!("{} is not a number and cannot take a precision", key)
+        }
+        None => match value {
+            Value::String(text) => output.push_str(text),
+            Value::Null => output.push_str("N/A"),
+            other => write!(output, "{}", other)?,
+        },
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn values() -> HashMap<String, Value> {
+        serde_json::from_value(json!({
+            "V_in": 12.345,
+            "state": "OperationalCoOp",
+            "T_mcu": 298.15,
+            "usb_port_state": 15,
+            "5v_output_enabled": true,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_render() {
+        let render = |template| render(template, &values(), TemperatureUnit::Celsius);
+        assert_eq!(
+            render("{V_in:.1}V {state}").unwrap(),
+            "12.3V OperationalCoOp"
+        );
+        assert_eq!(render("{T_mcu:.0}°C").unwrap(), "25°C");
+        assert_eq!(
+            render("{usb_port_state} {5v_output_enabled}").unwrap(),
+            "15 true"
+        );
+        assert_eq!(render("{{{V_in}}}").unwrap(), "{12.345}");
+    }
+
+    #[test]
+    fn test_render_fahrenheit() {
+        let output = render("{T_mcu:.1}", &values(), TemperatureUnit::Fahrenheit).unwrap();
+        assert_eq!(output, "77.0");
+    }
+
+    #[test]
+    fn test_render_errors() {
+        let render = |template| render(template, &values(), TemperatureUnit::Celsius);
+        let error = render("{V_out}").unwrap_err().to_string();
+        assert!(error.contains("Unknown key 'V_out'"));
+        assert!(error.contains("V_in"));
+        assert!(render("{V_in").is_err());
+        assert!(render("V_in}").is_err());
+        assert!(render("{V_in:x}").is_err());
+        assert!(render("{state:.1}").is_err());
+    }
+}
@@ -0,0 +1,113 @@
+//! System clock sanity checks
+//!
+//! `rtcwake` programs the alarm relative to the current system time, so a
+//! wakeup after a delay works even with a wrong clock. A wakeup at a given
+//! datetime does not: with a clock that was never set, it would be
+//! scheduled years ahead or in the past. Such wakeups are refused when the
+//! clock is clearly wrong, and logged with a warning when it is not
+//! synchronized.
+
+use chrono::{DateTime, Utc};
+use tracing::warn;
+
+/// Earliest plausible system time (2025-01-01T00:00:00Z)
+///
+/// A clock before it was never set, e.g. after booting without network or
+/// a working RTC.
+pub const MIN_PLAUSIBLE_TIME: i64 = 1_735_689_600;
+
+/// Largest maximum error of a synchronized clock, in microseconds
+///
+/// The limit timedated uses for "System clock synchronized".
+const MAX_SYNCED_ERROR_US: libc::c_long = 16_000_000;
+
+/// Why a wakeup time was refused
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum WakeTimeError {
+    #[error("system clock reads {}, which is clearly wrong", .0.to_rfc3339())]
+    ClockImplausible(DateTime<Utc>),
+    #[error("wakeup time {} is in the past", .0.to_rfc3339())]
+    InPast(DateTime<Utc>),
+}
+
+/// Whether the kernel considers the system clock synchronized
+///
+/// Returns `None` if the kernel cannot be asked.
+pub fn synchronized() -> Option<bool> {
+    // SAFETY: an all-zero timex with modes 0 only reads the clock state
+    let mut timex: libc::timex = unsafe { std::mem::zeroed() };
+    // SAFETY: `timex` outlives the call
+    if unsafe { libc::adjtimex(&mut timex) } < 0 {
+        return None;
+    }
+    Some(timex.maxerror < MAX_SYNCED_ERROR_US)
+}
+
+/// Check that a wakeup can be scheduled at `wake_at`
+///
+/// # Errors
+/// Returns an error if the system clock is clearly wrong, or `wake_at` is
+/// not in the future.
+pub fn check_wake_time(wake_at: DateTime<Utc>) -> Result<(), WakeTimeError> {
+    let synchronized = check_wake_time_at(wake_at, Utc::now(), synchronized())?;
+    if !synchronized {
+        warn!(
+            "System clock is not synchronized, wakeup at {} may be off",
+            wake_at.to_rfc3339()
+        );
+    }
+    Ok(())
+}
+
+/// Check `wake_at` against the system time `now`
+///
+/// Returns whether the clock can be trusted to be synchronized.
+fn check_wake_time_at(
+    wake_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+    synchronized: Option<bool>,
+) -> Result<bool, WakeTimeError> {
+    if now.timestamp() < MIN_PLAUSIBLE_TIME {
+        return Err(WakeTimeError::ClockImplausible(now));
+    }
+    if wake_at <= now {
+        return Err(WakeTimeError::InPast(wake_at));
+    }
+    Ok(synchronized == Some(true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    #[test]
+    fn test_check_wake_time() {
+        let now = at("2025-11-08T12:00:00Z");
+        let wake_at = at("2025-11-09T06:00:00Z");
+        assert_eq!(check_wake_time_at(wake_at, now, Some(true)), Ok(true));
+        assert_eq!(check_wake_time_at(wake_at, now, Some(false)), Ok(false));
+        assert_eq!(check_wake_time_at(wake_at, now, None), Ok(false));
+
+        assert_eq!(
+            check_wake_time_at(now, now, Some(true)),
+            Err(WakeTimeError::InPast(now))
+        );
+
+        // A Pi that booted without network or RTC
+        let unset = at("1970-01-01T00:01:00Z");
+        assert_eq!(
+            check_wake_time_at(wake_at, unset, Some(false)),
+            Err(WakeTimeError::ClockImplausible(unset))
+        );
+    }
+
+    #[test]
+    fn test_synchronized() {
+        // Either answer is fine, but asking must not fail on Linux
+        assert!(synchronized().is_some());
+    }
+}
@@ -0,0 +1,141 @@
+//! Linux watchdog device
+//!
+//! With `kernel-watchdog` enabled, the daemon opens the kernel watchdog
+//! device and the state machine pets it after every successful iteration,
+//! so the Pi's own watchdog also reboots the system if the daemon hangs or
+//! dies. Like the HALPI2 watchdog, it is disabled when the daemon exits
+//! cleanly: writing the magic character `V` before closing the device
+//! stops the kernel watchdog, unless the driver was built with `nowayout`.
+//! A crash closes the device without it, which leaves the watchdog running.
+//!
+//! The handle is shared, so the device stays open across restarts of the
+//! state machine task.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use tokio::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// `WDIOC_SETTIMEOUT`, i.e. `_IOWR('W', 6, int)`
+const WDIOC_SETTIMEOUT: u32 = 0xC004_5706;
+
+/// Shortest time between two keepalives
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct Device {
+    file: File,
+    petted: Option<Instant>,
+}
+
+/// Shared handle to the open watchdog device
+#[derive(Debug, Clone)]
+pub struct KernelWatchdog {
+    device: Arc<Mutex<Option<Device>>>,
+}
+
+impl KernelWatchdog {
+    /// Open the watchdog device at `path`, which starts the watchdog
+    ///
+    /// Sets the timeout to `timeout` seconds if given; the driver may round
+    /// it.
+    ///
+    /// # Errors
+    /// Returns an error if the device cannot be opened, e.g. because systemd
+    /// or another watchdog daemon holds it, or rejects the timeout.
+    pub fn open(path: &Path, timeout: Option<u32>) -> io::Result<Self> {
+        let file = OpenOptions::new().write(true).open(path)?;
+        let mut watchdog = Device { file, petted: None };
+        if let Some(timeout) = timeout {
+            let mut seconds = timeout as libc::c_int;
+            // SAFETY: the descriptor is open and `seconds` outlives the call
+            let result = unsafe {
+                libc::ioctl(
+                    watchdog.file.as_raw_fd(),
+                    WDIOC_SETTIMEOUT as _,
+                    &mut seconds,
+                )
+            };
+            if result < 0 {
+                let error = io::Error::last_os_error();
+                // Stop the watchdog the open started
+                let _ = watchdog.file.write_all(b"V");
+                return Err(error);
+            }
+            info!(
+                "Kernel watchdog {} timeout set to {}s",
+                path.display(),
+                seconds
+            );
+        }
+        watchdog.keepalive()?;
+        Ok(Self {
+            device: Arc::new(Mutex::new(Some(watchdog))),
+        })
+    }
+
+    /// Pet the watchdog, at most once per second
+    pub fn pet(&self) {
+        let mut device = self.device.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(device) = device.as_mut() else {
+            return;
+        };
+        let due = device
+            .petted
+            .is_none_or(|petted| petted.elapsed() >= KEEPALIVE_INTERVAL);
+        if due && let Err(e) = device.keepalive() {
+            warn!("Failed to pet kernel watchdog: {}", e);
+        }
+    }
+
+    /// Stop the watchdog and close the device
+    ///
+    /// Petting does nothing afterwards.
+    pub fn disarm(&self) {
+        let mut device = self.device.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(mut device) = device.take() else {
+            return;
+        };
+        match device.file.write_all(b"V") {
+            Ok(()) => info!("Kernel watchdog disabled"),
+            Err(e) => warn!("Failed to disable kernel watchdog: {}", e),
+        }
+    }
+}
+
+impl Device {
+    fn keepalive(&mut self) -> io::Result<()> {
+        self.file.write_all(b"\0")?;
+        self.petted = Some(Instant::now());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pet_and_disarm() {
+        let path = std::env::temp_dir().join(format!("halpid-watchdog-{}", std::process::id()));
+        std::fs::write(&path, b"").unwrap();
+
+        let watchdog = KernelWatchdog::open(&path, None).unwrap();
+        // Within the keepalive interval of the open
+        watchdog.pet();
+        watchdog.disarm();
+        watchdog.pet();
+        watchdog.disarm();
+        assert_eq!(std::fs::read(&path).unwrap(), b"\0V");
+
+        // A regular file has no timeout to set
+        assert!(KernelWatchdog::open(&path, Some(15)).is_err());
+        assert!(KernelWatchdog::open(&path.with_extension("missing"), None).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,5 +1,14 @@
 //! Daemon orchestration and signal handling
 
+pub mod clock;
+pub mod kernel_watchdog;
+pub mod notify;
+pub mod power;
+pub mod privileges;
+pub mod reboot;
+pub mod rtc;
+pub mod safety;
+pub mod services;
 pub mod signals;
 
 pub use signals::wait_for_signal;
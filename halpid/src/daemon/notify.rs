@@ -0,0 +1,160 @@
+//! systemd service manager notifications
+//!
+//! Implements the `sd_notify` protocol: state strings such as `READY=1` are
+//! sent as datagrams to the socket named by `NOTIFY_SOCKET`. When the daemon
+//! is not started by systemd the variable is unset and notifications are
+//! silently skipped.
+
+use std::io;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+/// Environment variable holding the notification socket path
+const NOTIFY_SOCKET: &str = "NOTIFY_SOCKET";
+
+/// Parse a `NOTIFY_SOCKET` value into a socket address
+///
+/// Values starting with `@` name a socket in the abstract namespace.
+fn socket_addr(value: &str) -> io::Result<SocketAddr> {
+    match value.strip_prefix('@') {
+        Some(name) => {
+            #[cfg(target_os = "linux")]
+            {
+                use std::os::linux::net::SocketAddrExt;
+                SocketAddr::from_abstract_name(name)
+            }
+            #[cfg(not(target_os = "linux"))]
+            {
+                let _ = name;
+                Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "abstract sockets are only supported on Linux",
+                ))
+            }
+        }
+        None => SocketAddr::from_pathname(value),
+    }
+}
+
+/// Send a notification to the socket at `addr`
+fn notify_to(addr: &str, state: &str) -> io::Result<()> {
+    let socket = UnixDatagram::unbound()?;
+    socket.send_to_addr(state.as_bytes(), &socket_addr(addr)?)?;
+    Ok(())
+}
+
+/// Send a raw notification such as `"READY=1"` to the service manager
+///
+/// Returns false if not running under systemd or the notification failed.
+pub fn notify(state: &str) -> bool {
+    let Ok(addr) = std::env::var(NOTIFY_SOCKET) else {
+        return false;
+    };
+    match notify_to(&addr, state) {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::debug!("Failed to notify service manager: {}", e);
+            false
+        }
+    }
+}
+
+/// Tell systemd that startup is complete
+pub fn ready() -> bool {
+    notify("READY=1")
+}
+
+/// Update the status line shown by `systemctl status`
+pub fn status(text: &str) -> bool {
+    // The status is a single line; a newline would start a new assignment
+    notify(&format!("STATUS={}", text.replace('\n', " ")))
+}
+
+/// Tell systemd that the daemon is shutting down
+pub fn stopping() -> bool {
+    notify("STOPPING=1")
+}
+
+/// Tell systemd that the daemon is alive
+pub fn watchdog() -> bool {
+    notify("WATCHDOG=1")
+}
+
+/// Watchdog timeout configured with `WatchdogSec=`, if any
+///
+/// systemd restarts the service if no `WATCHDOG=1` arrives within this
+/// time.
+pub fn watchdog_timeout() -> Option<Duration> {
+    parse_watchdog(
+        std::env::var("WATCHDOG_USEC").ok().as_deref(),
+        std::env::var("WATCHDOG_PID").ok().as_deref(),
+        std::process::id(),
+    )
+}
+
+/// Interpret `WATCHDOG_USEC` and `WATCHDOG_PID`
+///
+/// The watchdog applies to this process only if `WATCHDOG_PID` is unset or
+/// names it.
+fn parse_watchdog(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    if let Some(pid) = pid
+        && pid.parse::<u32>().ok() != Some(own_pid)
+    {
+        return None;
+    }
+    let usec = usec?.parse::<u64>().ok().filter(|usec| *usec > 0)?;
+    Some(Duration::from_micros(usec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_socket_addr() {
+        let addr = socket_addr("/run/systemd/notify").unwrap();
+        assert_eq!(
+            addr.as_pathname(),
+            Some(std::path::Path::new("/run/systemd/notify"))
+        );
+
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = socket_addr("@halpid-notify").unwrap();
+            assert_eq!(addr.as_abstract_name(), Some(&b"halpid-notify"[..]));
+        }
+    }
+
+    #[test]
+    fn test_parse_watchdog() {
+        assert_eq!(
+            parse_watchdog(Some("5000000"), None, 42),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            parse_watchdog(Some("5000000"), Some("42"), 42),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(parse_watchdog(Some("5000000"), Some("41"), 42), None);
+        assert_eq!(parse_watchdog(Some("0"), None, 42), None);
+        assert_eq!(parse_watchdog(Some("soon"), None, 42), None);
+        assert_eq!(parse_watchdog(None, None, 42), None);
+    }
+
+    #[test]
+    fn test_notify_to() {
+        let dir = std::env::temp_dir().join(format!("halpid-notify-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notify");
+        let _ = std::fs::remove_file(&path);
+        let manager = UnixDatagram::bind(&path).unwrap();
+
+        notify_to(path.to_str().unwrap(), "READY=1").unwrap();
+        let mut buf = [0u8; 64];
+        let n = manager.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,92 @@
+//! System power actions
+//!
+//! Shared by the standby endpoint and the blackout actions of the state
+//! machine.
+
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// sysfs attribute of the RTC wake alarm used by `rtcwake`
+pub const WAKE_ALARM_PATH: &str = "/sys/class/rtc/rtc0/wakealarm";
+
+/// Set the RTC wake alarm to the Unix `timestamp` using `rtcwake`
+///
+/// The system is not suspended; the controller powers it up again when
+/// the alarm fires after standby.
+///
+/// # Errors
+/// Returns an error if `rtcwake` cannot be run or fails.
+pub async fn set_wake_alarm(timestamp: u64) -> anyhow::Result<()> {
+    let output = tokio::process::Command::new("rtcwake")
+        .arg("-m")
+        .arg("no") // Don't suspend, just set alarm
+        .arg("-t")
+        .arg(timestamp.to_string())
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to execute rtcwake: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("rtcwake failed: {}", stderr);
+    }
+    Ok(())
+}
+
+/// Read the RTC wake alarm at `path` as a Unix timestamp
+///
+/// Returns None if no alarm is set.
+///
+/// # Errors
+/// Returns an error if the attribute cannot be read.
+pub fn wake_alarm(path: &Path) -> io::Result<Option<u64>> {
+    Ok(parse_wake_alarm(&std::fs::read_to_string(path)?))
+}
+
+/// Clear the RTC wake alarm at `path`
+///
+/// # Errors
+/// Returns an error if the attribute cannot be written.
+pub fn clear_wake_alarm(path: &Path) -> io::Result<()> {
+    std::fs::write(path, "0")
+}
+
+/// Parse the contents of the `wakealarm` attribute, empty when unset
+fn parse_wake_alarm(text: &str) -> Option<u64> {
+    text.trim().parse().ok().filter(|&timestamp| timestamp > 0)
+}
+
+/// Start `command` with `sh -c` without waiting for it
+///
+/// Matches the Python implementation, which runs the poweroff command
+/// through the shell.
+pub fn spawn_shell(command: &str) -> anyhow::Result<()> {
+    Command::new("sh").arg("-c").arg(command).spawn()?;
+    Ok(())
+}
+
+/// Broadcast `message` to all logged-in users with `wall`
+///
+/// Failures are only logged; the message is a courtesy.
+pub fn broadcast(message: &str) {
+    if let Err(e) = Command::new("wall").arg(message).spawn() {
+        tracing::warn!("Failed to run wall: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_wake_alarm() {
+        assert_eq!(parse_wake_alarm("1767225599\n"), Some(1767225599));
+        assert_eq!(parse_wake_alarm("\n"), None);
+        assert_eq!(parse_wake_alarm("0"), None);
+    }
+
+    #[test]
+    fn test_wake_alarm_missing() {
+        assert!(wake_alarm(Path::new("/nonexistent/wakealarm")).is_err());
+    }
+}
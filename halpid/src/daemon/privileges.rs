@@ -0,0 +1,177 @@
+//! Running as an unprivileged user
+//!
+//! The service is started as root so the daemon can open the I2C and
+//! watchdog devices and bind the API socket. With `user` configured, it then
+//! switches to that user, its supplementary groups and its primary group, or
+//! `group` if given, which cannot be undone. The supplementary groups keep
+//! the access the daemon needs later: `i2c` to reopen the bus after the
+//! controller reconnects or to disable the watchdog after a crash. Powering
+//! off through logind then depends on polkit allowing it for the user, and
+//! setting the RTC or the system clock is refused.
+
+use std::ffi::CString;
+use std::io;
+
+use tracing::info;
+
+/// User and group IDs to switch to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    /// User name and ID, unless only the group changes
+    pub user: Option<(String, libc::uid_t)>,
+    pub gid: libc::gid_t,
+}
+
+impl Credentials {
+    /// Look up the configured `user` and `group`
+    ///
+    /// Returns `None` if neither is configured.
+    ///
+    /// # Errors
+    /// Returns `NotFound` for an unknown user or group.
+    pub fn resolve(user: Option<&str>, group: Option<&str>) -> io::Result<Option<Self>> {
+        let user = user.map(lookup_user).transpose()?;
+        let gid = match group {
+            Some(group) => lookup_group(group)?,
+            None => match &user {
+                Some((_, _, gid)) => *gid,
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(Self {
+            user: user.map(|(name, uid, _)| (name, uid)),
+            gid,
+        }))
+    }
+
+    /// User ID the daemon runs as after [`apply`](Self::apply)
+    pub fn uid(&self) -> libc::uid_t {
+        match &self.user {
+            Some((_, uid)) => *uid,
+            // SAFETY: getuid cannot fail
+            None => unsafe { libc::getuid() },
+        }
+    }
+
+    /// Switch the process to the credentials
+    ///
+    /// Applies to every thread of the process. Nothing is done if the
+    /// process already runs with them.
+    ///
+    /// # Errors
+    /// Returns an error if the process is not running as root, or a switch
+    /// fails; the process may then have switched some of the IDs.
+    pub fn apply(&self) -> io::Result<()> {
+        // SAFETY: these calls cannot fail
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        if uid == self.uid() && gid == self.gid {
+            return Ok(());
+        }
+        if uid != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "changing user or group requires starting as root",
+            ));
+        }
+
+        match &self.user {
+            Some((name, _)) => {
+                let name = c_name(name)?;
+                // SAFETY: `name` outlives the call
+                check(unsafe { libc::initgroups(name.as_ptr(), self.gid) })?;
+            }
+            // SAFETY: the group list outlives the call
+            None => check(unsafe { libc::setgroups(1, &self.gid) })?,
+        }
+        // SAFETY: plain system calls; glibc applies them to all threads
+        check(unsafe { libc::setgid(self.gid) })?;
+        if let Some((name, uid)) = &self.user {
+            // SAFETY: as above
+            check(unsafe { libc::setuid(*uid) })?;
+            // SAFETY: as above
+            if *uid != 0 && unsafe { libc::setuid(0) } == 0 {
+                return Err(io::Error::other(format!(
+                    "root privileges could be regained after switching to {}",
+                    name
+                )));
+            }
+            info!("Running as user {} (uid {}, gid {})", name, uid, self.gid);
+        } else {
+            info!("Running as group {}", self.gid);
+        }
+        Ok(())
+    }
+}
+
+/// Name, user ID and primary group ID of user `name`
+fn lookup_user(name: &str) -> io::Result<(String, libc::uid_t, libc::gid_t)> {
+    let name_c = c_name(name)?;
+    // SAFETY: `name_c` outlives the call; the result is read before any
+    // other lookup, at startup
+    let passwd = unsafe { libc::getpwnam(name_c.as_ptr()) };
+    if passwd.is_null() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("user {} not found", name),
+        ));
+    }
+    // SAFETY: checked for null above
+    let (uid, gid) = unsafe { ((*passwd).pw_uid, (*passwd).pw_gid) };
+    Ok((name.to_string(), uid, gid))
+}
+
+/// Group ID of group `name`
+fn lookup_group(name: &str) -> io::Result<libc::gid_t> {
+    let name_c = c_name(name)?;
+    // SAFETY: as in `lookup_user`
+    let group = unsafe { libc::getgrnam(name_c.as_ptr()) };
+    if group.is_null() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("group {} not found", name),
+        ));
+    }
+    // SAFETY: checked for null above
+    Ok(unsafe { (*group).gr_gid })
+}
+
+fn c_name(name: &str) -> io::Result<CString> {
+    CString::new(name).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid name {:?}", name),
+        )
+    })
+}
+
+/// Error for a failed system call returning `result`
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(Credentials::resolve(None, None).unwrap(), None);
+
+        let root = Credentials::resolve(Some("root"), None).unwrap().unwrap();
+        assert_eq!(root.user, Some(("root".to_string(), 0)));
+        assert_eq!(root.gid, 0);
+        assert_eq!(root.uid(), 0);
+
+        let group = Credentials::resolve(None, Some("root")).unwrap().unwrap();
+        assert_eq!(group.user, None);
+        assert_eq!(group.gid, 0);
+
+        let missing = Credentials::resolve(Some("no-such-halpid-user"), None).unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+        assert!(Credentials::resolve(Some("root"), Some("no-such-halpid-group")).is_err());
+    }
+}
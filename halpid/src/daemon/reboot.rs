@@ -0,0 +1,60 @@
+//! Auto-restart across an API reboot
+//!
+//! `POST /reboot` turns the controller's auto-restart on so that it powers
+//! the system up again after the shutdown. The setting is kept in the
+//! controller, where it would also restart the system after every later
+//! blackout or poweroff. If auto-restart was off, a marker file in the
+//! state directory records that, and the daemon turns it off again once it
+//! has started and reached the controller.
+
+use std::io;
+use std::path::Path;
+
+use tracing::info;
+
+use crate::i2c::DeviceHandle;
+
+/// Directory of the files the daemon keeps across restarts
+pub const STATE_DIR: &str = "/var/lib/halpid";
+
+/// Marker of a reboot that turned auto-restart on
+pub const AUTO_RESTART_MARKER: &str = "/var/lib/halpid/reboot-auto-restart";
+
+/// Create the state directory, owned by `owner` if privileges are dropped
+///
+/// # Errors
+/// Returns an error if the directory cannot be created or its owner set.
+pub fn prepare_state_dir(owner: Option<libc::uid_t>) -> io::Result<()> {
+    std::fs::create_dir_all(STATE_DIR)?;
+    if let Some(uid) = owner {
+        std::os::unix::fs::chown(STATE_DIR, Some(uid), None)?;
+    }
+    Ok(())
+}
+
+/// Record that auto-restart was off before a reboot
+///
+/// # Errors
+/// Returns an error if the marker cannot be written.
+pub fn mark_auto_restart(path: &Path) -> io::Result<()> {
+    std::fs::write(path, "")
+}
+
+/// Turn auto-restart off again if a reboot turned it on
+///
+/// Waits for the controller. The marker is only removed once the setting
+/// is written, so a restart before that tries again.
+///
+/// # Errors
+/// Returns an error if the setting cannot be written or the marker removed.
+pub async fn restore_auto_restart(device: DeviceHandle, path: &Path) -> anyhow::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    device
+        .with_present(|device| device.set_auto_restart(false))
+        .await??;
+    std::fs::remove_file(path)?;
+    info!("Turned auto-restart off again after the reboot");
+    Ok(())
+}
@@ -0,0 +1,129 @@
+//! Hardware real-time clock
+//!
+//! Reads and sets the RTC through the Linux RTC device, which keeps the
+//! time in UTC like `hwclock --utc`. The RTC has a resolution of one
+//! second.
+
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
+use chrono::{DateTime, Datelike, NaiveDate, Timelike, Utc};
+
+/// Default RTC device, also used by `rtcwake`
+pub const DEFAULT_RTC_DEVICE: &str = "/dev/rtc0";
+
+/// `RTC_RD_TIME`, i.e. `_IOR('p', 0x09, struct rtc_time)`
+const RTC_RD_TIME: u32 = 0x8024_7009;
+
+/// `RTC_SET_TIME`, i.e. `_IOW('p', 0x0a, struct rtc_time)`
+const RTC_SET_TIME: u32 = 0x4024_700A;
+
+/// `struct rtc_time` from `linux/rtc.h`
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct RtcTime {
+    tm_sec: libc::c_int,
+    tm_min: libc::c_int,
+    tm_hour: libc::c_int,
+    tm_mday: libc::c_int,
+    tm_mon: libc::c_int,
+    tm_year: libc::c_int,
+    tm_wday: libc::c_int,
+    tm_yday: libc::c_int,
+    tm_isdst: libc::c_int,
+}
+
+impl RtcTime {
+    fn from_datetime(time: DateTime<Utc>) -> Self {
+        Self {
+            tm_sec: time.second() as _,
+            tm_min: time.minute() as _,
+            tm_hour: time.hour() as _,
+            tm_mday: time.day() as _,
+            tm_mon: time.month0() as _,
+            tm_year: time.year() - 1900,
+            tm_wday: time.weekday().num_days_from_sunday() as _,
+            tm_yday: time.ordinal0() as _,
+            tm_isdst: 0,
+        }
+    }
+
+    fn to_datetime(self) -> Option<DateTime<Utc>> {
+        let date = NaiveDate::from_ymd_opt(
+            self.tm_year + 1900,
+            u32::try_from(self.tm_mon + 1).ok()?,
+            u32::try_from(self.tm_mday).ok()?,
+        )?;
+        let time = date.and_hms_opt(
+            u32::try_from(self.tm_hour).ok()?,
+            u32::try_from(self.tm_min).ok()?,
+            u32::try_from(self.tm_sec).ok()?,
+        )?;
+        Some(time.and_utc())
+    }
+}
+
+/// Read the time of the RTC device at `path`
+///
+/// # Errors
+/// Returns an error if the device cannot be opened or read, e.g. because
+/// the RTC has lost its time, or holds an invalid date.
+pub fn read(path: &Path) -> io::Result<DateTime<Utc>> {
+    let file = File::open(path)?;
+    let mut time = RtcTime::default();
+    // SAFETY: the descriptor is open and `time` outlives the call
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), RTC_RD_TIME as _, &mut time) };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    time.to_datetime().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("RTC holds an invalid time: {:?}", time),
+        )
+    })
+}
+
+/// Set the RTC device at `path` to `time`
+///
+/// # Errors
+/// Returns an error if the device cannot be opened or rejects the time.
+pub fn set(path: &Path, time: DateTime<Utc>) -> io::Result<()> {
+    let file = File::open(path)?;
+    let time = RtcTime::from_datetime(time);
+    // SAFETY: the descriptor is open and `time` outlives the call
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), RTC_SET_TIME as _, &time) };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rtc_time_conversion() {
+        let time = DateTime::parse_from_rfc3339("2025-11-08T12:34:56Z")
+            .unwrap()
+            .to_utc();
+        let rtc = RtcTime::from_datetime(time);
+        assert_eq!(rtc.tm_year, 125);
+        assert_eq!(rtc.tm_mon, 10);
+        assert_eq!(rtc.tm_mday, 8);
+        assert_eq!(rtc.tm_wday, 6);
+        assert_eq!(rtc.to_datetime(), Some(time));
+
+        let invalid = RtcTime { tm_mon: 12, ..rtc };
+        assert_eq!(invalid.to_datetime(), None);
+    }
+
+    #[test]
+    fn test_read_not_an_rtc() {
+        assert!(read(Path::new("/dev/null")).is_err());
+        assert!(read(Path::new("/nonexistent/rtc")).is_err());
+    }
+}
@@ -0,0 +1,121 @@
+//! Hardware watchdog safety net
+//!
+//! Once enabled, the controller cuts power if the watchdog is not fed for
+//! 10 seconds. Normal shutdown disables it in [`cleanup`], but a panic or an
+//! exit path that skips cleanup would leave it running and lead to a hard
+//! power cut shortly after the daemon dies. The panic hook and the guard in
+//! this module disable the watchdog through a separately opened device
+//! handle, so they work even if the shared device lock is unavailable.
+//!
+//! A panic does not necessarily end the process: panics in spawned tasks are
+//! caught by the runtime. The state machine therefore re-enables the
+//! watchdog if it is still running after a panic (see [`take_disarmed`]).
+//!
+//! [`cleanup`]: super::signals::cleanup
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tracing::{error, info};
+
+use crate::i2c::{HalpiDevice, I2cError};
+
+/// Set when the watchdog was disabled by the panic hook
+static DISARMED: AtomicBool = AtomicBool::new(false);
+
+/// Disable the hardware watchdog through a new device handle
+fn disable_watchdog(bus: u8, addr: u8) -> Result<(), I2cError> {
+    HalpiDevice::new(bus, addr)?.set_watchdog_timeout(0)
+}
+
+/// Install a panic hook that disables the hardware watchdog
+///
+/// The previous hook still runs first, so the panic message is printed as
+/// usual.
+pub fn install_panic_hook(bus: u8, addr: u8) {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |panic_info| {
+        previous(panic_info);
+        error!("Panic: {}", panic_info);
+        match disable_watchdog(bus, addr) {
+            Ok(()) => {
+                set_disarmed();
+                error!("Disabled hardware watchdog after panic");
+            }
+            Err(e) => error!("Failed to disable hardware watchdog after panic: {}", e),
+        }
+    }));
+}
+
+/// Record that the watchdog is disabled and should be re-enabled
+pub fn set_disarmed() {
+    DISARMED.store(true, Ordering::SeqCst);
+}
+
+/// True (once) if the panic hook has disabled the watchdog since the last
+/// call
+///
+/// Called by the state machine, which re-enables the watchdog when it is
+/// still running.
+pub fn take_disarmed() -> bool {
+    DISARMED.swap(false, Ordering::SeqCst)
+}
+
+/// Disables the hardware watchdog when dropped, unless defused
+///
+/// Held by `main` for the lifetime of the daemon and defused after cleanup
+/// has disabled the watchdog.
+pub struct WatchdogGuard {
+    bus: u8,
+    addr: u8,
+    armed: bool,
+}
+
+impl WatchdogGuard {
+    pub fn new(bus: u8, addr: u8) -> Self {
+        Self {
+            bus,
+            addr,
+            armed: true,
+        }
+    }
+
+    /// The watchdog has been disabled; dropping the guard does nothing
+    pub fn defuse(&mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for WatchdogGuard {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        match disable_watchdog(self.bus, self.addr) {
+            Ok(()) => info!("Disabled hardware watchdog on exit"),
+            Err(e) => error!("Failed to disable hardware watchdog on exit: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_disarmed() {
+        set_disarmed();
+        assert!(take_disarmed());
+        assert!(!take_disarmed());
+    }
+
+    #[test]
+    fn test_guard_without_hardware() {
+        // Dropping must not panic when the device cannot be opened
+        let guard = WatchdogGuard::new(250, 0x6D);
+        drop(guard);
+
+        let mut guard = WatchdogGuard::new(250, 0x6D);
+        guard.defuse();
+        assert!(!guard.armed);
+    }
+}
@@ -0,0 +1,344 @@
+//! Optional exporters and notifiers
+//!
+//! NMEA 2000, InfluxDB, UPower, NUT, SNMP, webhooks, the health checks, the
+//! GPIO shutdown button, the LED night mode and the controller settings
+//! drift check each run as a supervised task taking a copy of their
+//! configuration section at start. When the configuration is reloaded,
+//! [`Services::apply`] restarts the services whose section changed, so new
+//! settings take effect without restarting the daemon and interrupting the
+//! state machine.
+
+use halpi_common::config::Config;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+use crate::events::EventBus;
+use crate::i2c::DeviceHandle;
+use crate::state_machine::StatusHandle;
+use crate::tasks::{self, RestartPolicy};
+use crate::{drift, gpio, health, influx, led_night, n2k, nut, snmp, upower, webhooks};
+
+/// An optional service configured by its own section
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    Nmea2000,
+    InfluxDb,
+    Upower,
+    Nut,
+    Snmp,
+    Webhooks,
+    HealthChecks,
+    GpioButton,
+    LedNight,
+    Controller,
+}
+
+impl Service {
+    pub const ALL: [Service; 10] = [
+        Service::Nmea2000,
+        Service::InfluxDb,
+        Service::Upower,
+        Service::Nut,
+        Service::Snmp,
+        Service::Webhooks,
+        Service::HealthChecks,
+        Service::GpioButton,
+        Service::LedNight,
+        Service::Controller,
+    ];
+
+    /// Task name, also the configuration section name
+    pub fn name(self) -> &'static str {
+        match self {
+            Service::Nmea2000 => "nmea2000",
+            Service::InfluxDb => "influxdb",
+            Service::Upower => "upower",
+            Service::Nut => "nut",
+            Service::Snmp => "snmp",
+            Service::Webhooks => "webhooks",
+            Service::HealthChecks => "health-checks",
+            Service::GpioButton => "gpio-button",
+            Service::LedNight => "led-night",
+            Service::Controller => "controller",
+        }
+    }
+
+    /// True if the service is enabled in `config`
+    pub fn enabled(self, config: &Config) -> bool {
+        match self {
+            Service::Nmea2000 => config.nmea2000.enabled,
+            Service::InfluxDb => config.influxdb.enabled,
+            Service::Upower => config.upower.enabled,
+            Service::Nut => config.nut.enabled,
+            Service::Snmp => config.snmp.enabled,
+            Service::Webhooks => config.webhooks.enabled,
+            Service::HealthChecks => !config.health_checks.is_empty(),
+            Service::GpioButton => config.gpio_button.enabled,
+            Service::LedNight => config.led_night.enabled,
+            Service::Controller => !config.controller.is_empty(),
+        }
+    }
+
+    /// True if the service's section differs between `a` and `b`
+    pub fn changed(self, a: &Config, b: &Config) -> bool {
+        match self {
+            Service::Nmea2000 => a.nmea2000 != b.nmea2000,
+            Service::InfluxDb => a.influxdb != b.influxdb,
+            Service::Upower => a.upower != b.upower,
+            Service::Nut => a.nut != b.nut,
+            Service::Snmp => a.snmp != b.snmp,
+            Service::Webhooks => a.webhooks != b.webhooks,
+            Service::HealthChecks => a.health_checks != b.health_checks,
+            Service::GpioButton => a.gpio_button != b.gpio_button,
+            Service::LedNight => a.led_night != b.led_night,
+            Service::Controller => a.controller != b.controller,
+        }
+    }
+
+    /// Start the service as a supervised task
+    fn spawn(
+        self,
+        device: DeviceHandle,
+        events: EventBus,
+        status: StatusHandle,
+        config: &Config,
+        offline: bool,
+    ) -> JoinHandle<anyhow::Result<()>> {
+        let policy = RestartPolicy::SERVICE;
+        match self {
+            Service::Nmea2000 => {
+                let n2k_config = config.nmea2000.clone();
+                tasks::supervise(self.name(), policy, move || {
+                    let device = device.clone();
+                    let events = events.clone();
+                    let n2k_config = n2k_config.clone();
+                    async move {
+                        info!("Starting NMEA 2000 transmitter on {}", n2k_config.interface);
+                        n2k::run(device, events, n2k_config).await
+                    }
+                })
+            }
+            Service::InfluxDb => {
+                let influx_config = config.influxdb.clone();
+                tasks::supervise(self.name(), policy, move || {
+                    let device = device.clone();
+                    let events = events.clone();
+                    let influx_config = influx_config.clone();
+                    async move {
+                        info!("Starting InfluxDB exporter for {}", influx_config.url);
+                        influx::run(device, events, influx_config).await;
+                        Ok(())
+                    }
+                })
+            }
+            Service::Upower => {
+                let upower_config = config.upower.clone();
+                tasks::supervise(self.name(), policy, move || {
+                    let device = device.clone();
+                    let events = events.clone();
+                    let upower_config = upower_config.clone();
+                    async move {
+                        info!("Starting UPower battery device");
+                        upower::run(device, events, upower_config).await
+                    }
+                })
+            }
+            Service::Nut => {
+                let nut_config = config.nut.clone();
+                tasks::supervise(self.name(), policy, move || {
+                    let device = device.clone();
+                    let events = events.clone();
+                    let nut_config = nut_config.clone();
+                    async move {
+                        info!("Starting NUT server");
+                        nut::run(device, events, nut_config).await
+                    }
+                })
+            }
+            Service::Snmp => {
+                let snmp_config = config.snmp.clone();
+                tasks::supervise(self.name(), policy, move || {
+                    let device = device.clone();
+                    let events = events.clone();
+                    let snmp_config = snmp_config.clone();
+                    async move {
+                        info!("Starting SNMP AgentX subagent");
+                        snmp::run(device, events, snmp_config).await
+                    }
+                })
+            }
+            Service::Webhooks => {
+                let webhooks_config = config.webhooks.clone();
+                tasks::supervise(self.name(), policy, move || {
+                    let device = device.clone();
+                    let events = events.clone();
+                    let webhooks_config = webhooks_config.clone();
+                    async move {
+                        info!(
+                            "Starting webhook notifier ({} endpoints)",
+                            webhooks_config.endpoints.len()
+                        );
+                        webhooks::run(device, events, webhooks_config).await
+                    }
+                })
+            }
+            Service::HealthChecks => {
+                let checks = config.health_checks.clone();
+                let use_logind = config.logind;
+                tasks::supervise(self.name(), policy, move || {
+                    let device = device.clone();
+                    let events = events.clone();
+                    let status = status.clone();
+                    let checks = checks.clone();
+                    async move {
+                        info!("Starting health checks ({} checks)", checks.len());
+                        health::run(device, events, status, checks, use_logind, offline).await
+                    }
+                })
+            }
+            Service::GpioButton => {
+                let button_config = config.gpio_button.clone();
+                tasks::supervise(self.name(), policy, move || {
+                    let device = device.clone();
+                    let events = events.clone();
+                    let button_config = button_config.clone();
+                    async move {
+                        info!("Starting shutdown button watcher");
+                        gpio::run(device, events, button_config).await
+                    }
+                })
+            }
+            Service::LedNight => {
+                let night_config = config.led_night.clone();
+                tasks::supervise(self.name(), policy, move || {
+                    let device = device.clone();
+                    let status = status.clone();
+                    let night_config = night_config.clone();
+                    async move {
+                        info!(
+                            "Starting LED night mode ({}-{})",
+                            night_config.start, night_config.end
+                        );
+                        led_night::run(device, status, night_config).await
+                    }
+                })
+            }
+            Service::Controller => {
+                let controller_config = config.controller.clone();
+                tasks::supervise(self.name(), policy, move || {
+                    let device = device.clone();
+                    let status = status.clone();
+                    let controller_config = controller_config.clone();
+                    async move {
+                        info!(
+                            "Starting controller settings check (every {}s)",
+                            controller_config.check_interval
+                        );
+                        drift::run(device, status, controller_config).await
+                    }
+                })
+            }
+        }
+    }
+}
+
+/// The running optional services
+pub struct Services {
+    device: DeviceHandle,
+    events: EventBus,
+    status: StatusHandle,
+    /// The controller is simulated or replayed
+    offline: bool,
+    running: Vec<(Service, JoinHandle<anyhow::Result<()>>)>,
+}
+
+impl Services {
+    /// Start the services enabled in `config`
+    ///
+    /// When `offline`, the health check reboot action is only logged.
+    pub fn start(
+        device: DeviceHandle,
+        events: EventBus,
+        status: StatusHandle,
+        config: &Config,
+        offline: bool,
+    ) -> Self {
+        let mut services = Self {
+            device,
+            events,
+            status,
+            offline,
+            running: Vec::new(),
+        };
+        for service in Service::ALL {
+            if service.enabled(config) {
+                services.spawn(service, config);
+            }
+        }
+        services
+    }
+
+    fn spawn(&mut self, service: Service, config: &Config) {
+        let handle = service.spawn(
+            self.device.clone(),
+            self.events.clone(),
+            self.status.clone(),
+            config,
+            self.offline,
+        );
+        self.running.push((service, handle));
+    }
+
+    /// Stop a running service and wait for it to release its resources,
+    /// such as a listening socket
+    async fn stop(&mut self, service: Service) {
+        let Some(index) = self.running.iter().position(|(s, _)| *s == service) else {
+            return;
+        };
+        let (_, handle) = self.running.swap_remove(index);
+        handle.abort();
+        let _ = handle.await;
+    }
+
+    /// Restart, start or stop the services whose section changed from
+    /// `previous` to `config`
+    pub async fn apply(&mut self, previous: &Config, config: &Config) {
+        for service in Service::ALL {
+            if !service.changed(previous, config) {
+                continue;
+            }
+            self.stop(service).await;
+            if service.enabled(config) {
+                if service.enabled(previous) {
+                    info!("Restarting {} with the new configuration", service.name());
+                }
+                self.spawn(service, config);
+            } else if service.enabled(previous) {
+                info!("Stopped {}", service.name());
+                if service == Service::Controller {
+                    self.status.set_drift(None);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_sections() {
+        let previous = Config::default();
+        let mut config = previous.clone();
+        config.blackout_voltage_limit = 8.0;
+        assert!(Service::ALL.iter().all(|s| !s.changed(&previous, &config)));
+
+        config.influxdb.interval = 30.0;
+        let changed: Vec<_> = Service::ALL
+            .into_iter()
+            .filter(|s| s.changed(&previous, &config))
+            .collect();
+        assert_eq!(changed, vec![Service::InfluxDb]);
+    }
+}
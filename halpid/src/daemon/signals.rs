@@ -1,16 +1,25 @@
-//! Signal handling for graceful shutdown
-
-use std::path::Path;
-use tracing::info;
+//! Signal handling for graceful shutdown and configuration reload
+//!
+//! SIGINT and SIGTERM shut the daemon down. SIGHUP reloads the
+//! configuration file: the blackout limits, the poweroff command and the
+//! exporter and notifier sections take effect without a restart, while the
+//! I2C, socket, device, logging and timing settings keep their startup
+//! values. With a simulated or replayed controller, the blackout action and
+//! the commands it runs stay as they were at startup too.
 
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing::warn;
+
+use halpi_common::config::{Config, ConfigError, PartialConfig};
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
 
 #[cfg(unix)]
 use tokio::signal::unix::{SignalKind, signal};
 
-use crate::i2c::HalpiDevice;
+use super::notify;
+use super::services::Services;
+use crate::i2c::{DeviceHandle, I2cError};
 
 /// Wait for SIGINT or SIGTERM signal
 pub async fn wait_for_signal() {
@@ -41,24 +50,183 @@ pub async fn wait_for_signal() {
     }
 }
 
+/// Reload the configuration on every SIGHUP
+///
+/// `overrides` holds the command line options, which keep precedence
+/// over the file. A configuration that fails to load or validate is
+/// rejected and the running configuration stays in place. The state
+/// machine, and with it the hardware watchdog, keeps running throughout;
+/// it picks up the new blackout limits on its next iteration. `offline` is
+/// set when the controller is simulated or replayed.
+pub async fn reload_on_hangup(
+    path: Option<PathBuf>,
+    config: Arc<RwLock<Config>>,
+    overrides: PartialConfig,
+    mut services: Services,
+    offline: bool,
+) -> anyhow::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut sighup = signal(SignalKind::hangup())?;
+        while sighup.recv().await.is_some() {
+            info!("Received SIGHUP");
+            let Some(path) = &path else {
+                warn!("No configuration file given, nothing to reload");
+                continue;
+            };
+            let previous = config.read().await.clone();
+            let reloaded = match reload_config(path, &previous, &overrides, offline) {
+                Ok(reloaded) => reloaded,
+                Err(e) => {
+                    error!("Keeping the running configuration: {}", e);
+                    continue;
+                }
+            };
+            *config.write().await = reloaded.clone();
+            services.apply(&previous, &reloaded).await;
+            info!("Reloaded configuration from {}", path.display());
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (path, config, overrides, &mut services, offline);
+        std::future::pending::<()>().await;
+    }
+
+    Ok(())
+}
+
+/// Load `path` and its drop-in fragments as the new configuration
+/// replacing `running`
+///
+/// Settings that only take effect at startup keep their running values, with
+/// a warning if the file changes them. When `offline`, the blackout action,
+/// `poweroff` and `blackout-command` keep their running values as well, so
+/// that a reload cannot make a simulated blackout power off the host.
+///
+/// # Errors
+/// Returns an error if the file cannot be read or parsed, or if the
+/// reloadable settings are invalid.
+pub fn reload_config(
+    path: &Path,
+    running: &Config,
+    overrides: &PartialConfig,
+    offline: bool,
+) -> Result<Config, ConfigError> {
+    let mut config = Config::load(path)?;
+    config.merge(overrides.clone());
+    for setting in keep_startup_settings(running, &mut config) {
+        warn!("{} changed; restart the daemon to apply it", setting);
+    }
+    if offline {
+        config.poweroff = running.poweroff.clone();
+        config.blackout_command = running.blackout_command.clone();
+        config.blackout_action = running.blackout_action;
+    }
+    config.validate_reloadable()?;
+    Ok(config)
+}
+
+/// Copy the startup-only settings from `running` into `config`
+///
+/// Returns the names of the settings that differed.
+fn keep_startup_settings(running: &Config, config: &mut Config) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    // The running bus number was resolved from i2c-device if that is set
+    if config.i2c_device != running.i2c_device {
+        changed.push("i2c-device");
+    } else if config.i2c_device.is_none() && config.i2c_bus != running.i2c_bus {
+        changed.push("i2c-bus");
+    }
+    if config.i2c_addr != running.i2c_addr {
+        changed.push("i2c-addr");
+    }
+    if config.i2c_pec != running.i2c_pec {
+        changed.push("i2c-pec");
+    }
+    if config.devices != running.devices {
+        changed.push("devices");
+    }
+    if config.socket != running.socket {
+        changed.push("socket");
+    }
+    if config.socket_group != running.socket_group {
+        changed.push("socket-group");
+    }
+    if config.user != running.user {
+        changed.push("user");
+    }
+    if config.group != running.group {
+        changed.push("group");
+    }
+    if config.logging != running.logging {
+        changed.push("logging");
+    }
+    if config.poll_interval != running.poll_interval {
+        changed.push("poll-interval");
+    }
+    if config.watchdog_timeout != running.watchdog_timeout {
+        changed.push("watchdog-timeout");
+    }
+    if config.kernel_watchdog != running.kernel_watchdog {
+        changed.push("kernel-watchdog");
+    }
+    if config.self_test != running.self_test {
+        changed.push("self-test");
+    }
+    if config.cors != running.cors {
+        changed.push("cors");
+    }
+    if config.api_limits != running.api_limits {
+        changed.push("api-limits");
+    }
+
+    config.i2c_bus = running.i2c_bus;
+    config.i2c_addr = running.i2c_addr;
+    config.i2c_device = running.i2c_device.clone();
+    config.i2c_pec = running.i2c_pec;
+    config.devices = running.devices.clone();
+    config.socket = running.socket.clone();
+    config.socket_group = running.socket_group.clone();
+    config.user = running.user.clone();
+    config.group = running.group.clone();
+    config.logging = running.logging.clone();
+    config.poll_interval = running.poll_interval;
+    config.watchdog_timeout = running.watchdog_timeout;
+    config.kernel_watchdog = running.kernel_watchdog.clone();
+    config.self_test = running.self_test.clone();
+    config.cors = running.cors.clone();
+    config.api_limits = running.api_limits;
+    changed
+}
+
 /// Cleanup function to run before shutdown
 ///
 /// This function:
+/// - Tells systemd that the daemon is stopping
 /// - Disables the hardware watchdog (critical for safety)
 /// - Removes the Unix socket file
 /// - Flushes logs
-pub async fn cleanup(device: Arc<Mutex<HalpiDevice>>, socket_path: &Path) {
+///
+/// Returns true if the watchdog was disabled.
+pub async fn cleanup(device: DeviceHandle, socket_path: &Path) -> bool {
     info!("Running cleanup before shutdown");
+    notify::stopping();
 
     // Disable watchdog - CRITICAL for hardware safety
-    {
-        let mut dev = device.lock().await;
-        if let Err(e) = dev.set_watchdog_timeout(0) {
-            warn!("Failed to disable watchdog during shutdown: {}", e);
-        } else {
+    let watchdog_disabled = match device.run(|dev| dev.set_watchdog_timeout(0)).await {
+        Ok(()) => {
             info!("Watchdog disabled");
+            true
         }
-    }
+        // A controller that was never connected never had its watchdog enabled
+        Err(I2cError::DeviceMissing { .. }) => true,
+        Err(e) => {
+            warn!("Failed to disable watchdog during shutdown: {}", e);
+            false
+        }
+    };
 
     // Remove Unix socket file
     if socket_path.exists() {
@@ -71,4 +239,102 @@ pub async fn cleanup(device: Arc<Mutex<HalpiDevice>>, socket_path: &Path) {
 
     // Flush logs (tracing handles this automatically on drop)
     info!("Cleanup complete");
+
+    watchdog_disabled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keep_startup_settings() {
+        let running = Config {
+            i2c_bus: 3,
+            i2c_device: Some(PathBuf::from("/dev/halpi-i2c")),
+            ..Default::default()
+        };
+
+        // The bus number in the file does not matter while a path is set
+        let mut config = Config {
+            i2c_device: running.i2c_device.clone(),
+            blackout_voltage_limit: 8.0,
+            ..Default::default()
+        };
+        assert!(keep_startup_settings(&running, &mut config).is_empty());
+        assert_eq!(config.i2c_bus, 3);
+        assert_eq!(config.blackout_voltage_limit, 8.0);
+
+        let mut config = Config {
+            i2c_addr: 0x6E,
+            socket: Some(PathBuf::from("/tmp/halpid.sock")),
+            watchdog_timeout: 30.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            keep_startup_settings(&running, &mut config),
+            vec!["i2c-device", "i2c-addr", "socket", "watchdog-timeout"]
+        );
+        assert_eq!(config, running);
+    }
+
+    #[test]
+    fn test_reload_config() {
+        let dir = std::env::temp_dir().join(format!("halpid-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("halpid.conf");
+        let running = Config::default();
+
+        std::fs::write(&path, "blackout-time-limit: 10.0\ni2c-addr: 0x6E\n").unwrap();
+        let config = reload_config(&path, &running, &PartialConfig::default(), false).unwrap();
+        assert_eq!(config.blackout_time_limit, 10.0);
+        assert_eq!(config.i2c_addr, running.i2c_addr);
+
+        // Command line options keep precedence
+        let overrides = PartialConfig {
+            blackout_time_limit: Some(2.0),
+            ..Default::default()
+        };
+        let config = reload_config(&path, &running, &overrides, false).unwrap();
+        assert_eq!(config.blackout_time_limit, 2.0);
+
+        std::fs::write(&path, "blackout-voltage-limit: 3.0\n").unwrap();
+        assert!(reload_config(&path, &running, &PartialConfig::default(), false).is_err());
+        std::fs::write(&path, "unknown-key: 1\n").unwrap();
+        assert!(reload_config(&path, &running, &PartialConfig::default(), false).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reload_config_offline() {
+        let dir = std::env::temp_dir().join(format!("halpid-offline-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("halpid.conf");
+        // As at startup with --simulate or --replay
+        let running = Config {
+            poweroff: String::new(),
+            ..Default::default()
+        };
+
+        std::fs::write(
+            &path,
+            "poweroff: /sbin/poweroff
+blackout-action: command
+blackout-command: /sbin/halt
+blackout-time-limit: 10.0
+",
+        )
+        .unwrap();
+        let config = reload_config(&path, &running, &PartialConfig::default(), true).unwrap();
+        assert_eq!(config.poweroff, "");
+        assert_eq!(config.blackout_action, running.blackout_action);
+        assert_eq!(config.blackout_command, running.blackout_command);
+        assert_eq!(config.blackout_time_limit, 10.0);
+
+        let config = reload_config(&path, &running, &PartialConfig::default(), false).unwrap();
+        assert_eq!(config.poweroff, "/sbin/poweroff");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,436 @@
+//! D-Bus bus connection over a Unix socket
+//!
+//! Authenticates with SASL EXTERNAL, registers with the bus (`Hello`), and
+//! splits into a sender handle and a stream of incoming messages. Unix file
+//! descriptor passing is negotiated so that replies can carry descriptors,
+//! such as logind inhibitor locks; sending descriptors is not supported.
+
+use anyhow::{Context, Result, bail};
+use std::collections::VecDeque;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Interest};
+use tokio::net::UnixStream;
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::sync::{Mutex, mpsc};
+use tracing::debug;
+
+use super::wire::{FIXED_HEADER_SIZE, Message, MessageType, Value};
+
+/// Default system bus socket
+pub const SYSTEM_BUS_SOCKET: &str = "/run/dbus/system_bus_socket";
+
+/// Well-known name of the bus daemon
+pub const BUS_NAME: &str = "org.freedesktop.DBus";
+
+const BUS_PATH: &str = "/org/freedesktop/DBus";
+
+/// RequestName flag: fail instead of queueing if the name is taken
+const NAME_FLAG_DO_NOT_QUEUE: u32 = 0x4;
+
+/// RequestName reply: we are now the primary owner
+const NAME_REPLY_PRIMARY_OWNER: u32 = 1;
+
+/// RequestName reply: we already owned the name
+const NAME_REPLY_ALREADY_OWNER: u32 = 4;
+
+/// Most file descriptors accepted with a single read
+const MAX_FDS_PER_READ: usize = 16;
+
+/// Handle for sending messages on a bus connection
+#[derive(Clone)]
+pub struct Sender {
+    writer: Arc<Mutex<OwnedWriteHalf>>,
+    serial: Arc<AtomicU32>,
+}
+
+impl Sender {
+    /// Send a message, returning its serial number
+    ///
+    /// # Errors
+    /// Returns an error if the connection is closed.
+    pub async fn send(&self, msg: &Message) -> Result<u32> {
+        let serial = self.serial.fetch_add(1, Ordering::Relaxed);
+        let bytes = msg.encode(serial);
+        self.writer
+            .lock()
+            .await
+            .write_all(&bytes)
+            .await
+            .context("Failed to write to D-Bus")?;
+        Ok(serial)
+    }
+}
+
+/// An authenticated bus connection
+pub struct Connection {
+    pub sender: Sender,
+    /// Unique name assigned by the bus (e.g., `:1.42`)
+    pub unique_name: String,
+    incoming: mpsc::Receiver<(Message, Vec<OwnedFd>)>,
+}
+
+impl Connection {
+    /// Connect to the system bus
+    ///
+    /// Honors `DBUS_SYSTEM_BUS_ADDRESS` for `unix:path=` addresses.
+    ///
+    /// # Errors
+    /// Returns an error if the bus is unreachable or authentication fails.
+    pub async fn system() -> Result<Self> {
+        let path = std::env::var("DBUS_SYSTEM_BUS_ADDRESS")
+            .ok()
+            .and_then(|addr| parse_unix_address(&addr))
+            .unwrap_or_else(|| PathBuf::from(SYSTEM_BUS_SOCKET));
+        Self::connect(&path).await
+    }
+
+    /// Connect to the bus listening on the given socket
+    ///
+    /// # Errors
+    /// Returns an error if the bus is unreachable or authentication fails.
+    pub async fn connect(path: &std::path::Path) -> Result<Self> {
+        let stream = UnixStream::connect(path)
+            .await
+            .with_context(|| format!("Failed to connect to D-Bus at {}", path.display()))?;
+        let (read_half, mut write_half) = stream.into_split();
+        let mut reader = BufReader::new(read_half);
+
+        authenticate(&mut reader, &mut write_half).await?;
+
+        // Bytes the bus sent right after authenticating may already be buffered
+        let socket = SocketReader {
+            buf: reader.buffer().to_vec(),
+            half: reader.into_inner(),
+            fds: VecDeque::new(),
+        };
+        let (tx, incoming) = mpsc::channel(64);
+        tokio::spawn(read_loop(socket, tx));
+
+        let mut conn = Self {
+            sender: Sender {
+                writer: Arc::new(Mutex::new(write_half)),
+                serial: Arc::new(AtomicU32::new(1)),
+            },
+            unique_name: String::new(),
+            incoming,
+        };
+
+        let reply = conn
+            .call(Message::method_call(BUS_NAME, BUS_PATH, BUS_NAME, "Hello"))
+            .await
+            .context("D-Bus Hello failed")?;
+        conn.unique_name = reply
+            .body
+            .first()
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        debug!("Connected to D-Bus as {}", conn.unique_name);
+
+        Ok(conn)
+    }
+
+    /// Request ownership of a well-known bus name
+    ///
+    /// # Errors
+    /// Returns an error if the name is owned by another connection or the
+    /// bus policy forbids owning it.
+    pub async fn request_name(&mut self, name: &str) -> Result<()> {
+        let reply = self
+            .call(
+                Message::method_call(BUS_NAME, BUS_PATH, BUS_NAME, "RequestName").with_body(vec![
+                    Value::Str(name.into()),
+                    Value::U32(NAME_FLAG_DO_NOT_QUEUE),
+                ]),
+            )
+            .await?;
+        match reply.body.first() {
+            Some(Value::U32(NAME_REPLY_PRIMARY_OWNER | NAME_REPLY_ALREADY_OWNER)) => Ok(()),
+            _ => bail!("D-Bus name {} is already owned", name),
+        }
+    }
+
+    /// Call a method and wait for its reply
+    ///
+    /// Messages received while waiting are discarded, so this is only meant
+    /// for setup before the caller starts processing incoming messages.
+    ///
+    /// # Errors
+    /// Returns an error on connection loss or if the call returns an error.
+    pub async fn call(&mut self, msg: Message) -> Result<Message> {
+        let (reply, _fds) = self.call_with_fds(msg).await?;
+        Ok(reply)
+    }
+
+    /// Call a method and wait for its reply and the descriptors sent with it
+    ///
+    /// `h` values in the reply body index into the returned descriptors.
+    /// Other messages are discarded as with [`Connection::call`].
+    ///
+    /// # Errors
+    /// Returns an error on connection loss or if the call returns an error.
+    pub async fn call_with_fds(&mut self, msg: Message) -> Result<(Message, Vec<OwnedFd>)> {
+        let serial = self.sender.send(&msg).await?;
+        while let Some((reply, fds)) = self.incoming.recv().await {
+            if reply.reply_serial != Some(serial) {
+                continue;
+            }
+            if reply.msg_type == MessageType::Error {
+                bail!(
+                    "{}: {}",
+                    reply.error_name.as_deref().unwrap_or("D-Bus error"),
+                    reply.body.first().and_then(Value::as_str).unwrap_or("")
+                );
+            }
+            return Ok((reply, fds));
+        }
+        bail!("D-Bus connection closed")
+    }
+
+    /// Receive the next incoming message, or `None` when the connection closes
+    ///
+    /// Descriptors sent with the message are closed.
+    pub async fn recv(&mut self) -> Option<Message> {
+        self.incoming.recv().await.map(|(msg, _fds)| msg)
+    }
+}
+
+/// Perform SASL EXTERNAL authentication with the current effective uid
+async fn authenticate(
+    reader: &mut BufReader<OwnedReadHalf>,
+    writer: &mut OwnedWriteHalf,
+) -> Result<()> {
+    let uid = unsafe { libc::geteuid() }.to_string();
+    let hex_uid: String = uid.bytes().map(|b| format!("{:02x}", b)).collect();
+
+    writer
+        .write_all(format!("\0AUTH EXTERNAL {}\r\n", hex_uid).as_bytes())
+        .await?;
+
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    if !line.starts_with("OK ") {
+        bail!("D-Bus authentication rejected: {}", line.trim());
+    }
+
+    // Without descriptor passing, only calls returning descriptors fail
+    writer.write_all(b"NEGOTIATE_UNIX_FD\r\n").await?;
+    line.clear();
+    reader.read_line(&mut line).await?;
+    if !line.starts_with("AGREE_UNIX_FD") {
+        debug!("D-Bus refused file descriptor passing: {}", line.trim());
+    }
+
+    writer.write_all(b"BEGIN\r\n").await?;
+    Ok(())
+}
+
+/// Forward incoming messages to the connection until the socket closes
+async fn read_loop(mut socket: SocketReader, tx: mpsc::Sender<(Message, Vec<OwnedFd>)>) {
+    loop {
+        match socket.read_message().await {
+            Ok(incoming) => {
+                if tx.send(incoming).await.is_err() {
+                    return;
+                }
+            }
+            Err(e) => {
+                debug!("D-Bus read loop ended: {:#}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// Reads messages and the descriptors passed with them off the socket
+struct SocketReader {
+    half: OwnedReadHalf,
+    /// Received bytes not yet consumed as a message
+    buf: Vec<u8>,
+    /// Received descriptors not yet assigned to a message
+    fds: VecDeque<OwnedFd>,
+}
+
+impl SocketReader {
+    async fn read_message(&mut self) -> Result<(Message, Vec<OwnedFd>)> {
+        while self.buf.len() < FIXED_HEADER_SIZE {
+            self.fill().await?;
+        }
+        let mut fixed = [0u8; FIXED_HEADER_SIZE];
+        fixed.copy_from_slice(&self.buf[..FIXED_HEADER_SIZE]);
+        let total = Message::total_length(&fixed)?;
+        while self.buf.len() < total {
+            self.fill().await?;
+        }
+
+        let msg = Message::decode(&self.buf[..total])?;
+        self.buf.drain(..total);
+
+        // The bus sends a message's descriptors with its first bytes
+        let count = msg.unix_fds as usize;
+        if count > self.fds.len() {
+            bail!(
+                "D-Bus message announces {} file descriptors, received {}",
+                count,
+                self.fds.len()
+            );
+        }
+        Ok((msg, self.fds.drain(..count).collect()))
+    }
+
+    /// Read more bytes, and any descriptors sent with them, into the buffers
+    async fn fill(&mut self) -> Result<()> {
+        let stream: &UnixStream = self.half.as_ref();
+        let mut chunk = [0u8; 4096];
+        loop {
+            stream.readable().await?;
+            let fd = stream.as_raw_fd();
+            match stream.try_io(Interest::READABLE, || {
+                recv_with_fds(fd, &mut chunk, &mut self.fds)
+            }) {
+                Ok(0) => bail!("D-Bus connection closed"),
+                Ok(n) => {
+                    self.buf.extend_from_slice(&chunk[..n]);
+                    return Ok(());
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(e).context("Failed to read from D-Bus"),
+            }
+        }
+    }
+}
+
+/// `recvmsg` on `fd`, appending descriptors passed with `SCM_RIGHTS` to `fds`
+fn recv_with_fds(fd: RawFd, buf: &mut [u8], fds: &mut VecDeque<OwnedFd>) -> io::Result<usize> {
+    // u64 elements keep the control buffer aligned for cmsghdr
+    let mut control = [0u64; MAX_FDS_PER_READ / 2 + 4];
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    // SAFETY: an all-zero msghdr is valid; the pointers set below outlive the call
+    let mut header: libc::msghdr = unsafe { std::mem::zeroed() };
+    header.msg_iov = &mut iov;
+    header.msg_iovlen = 1;
+    header.msg_control = control.as_mut_ptr().cast();
+    header.msg_controllen = std::mem::size_of_val(&control) as _;
+
+    // SAFETY: header describes valid, writable buffers
+    let n = unsafe { libc::recvmsg(fd, &mut header, libc::MSG_CMSG_CLOEXEC) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: the kernel filled in the control buffer described by header
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&header) };
+    while !cmsg.is_null() {
+        // SAFETY: cmsg points to a complete control message header
+        let (level, kind, len) =
+            unsafe { ((*cmsg).cmsg_level, (*cmsg).cmsg_type, (*cmsg).cmsg_len) };
+        if level == libc::SOL_SOCKET && kind == libc::SCM_RIGHTS {
+            // SAFETY: CMSG_LEN(0) is the header size, the rest is the descriptor array
+            #[allow(clippy::unnecessary_cast)] // cmsg_len is u32 on some targets
+            let data_len = len as usize - unsafe { libc::CMSG_LEN(0) } as usize;
+            let data = unsafe { libc::CMSG_DATA(cmsg) }.cast::<libc::c_int>();
+            for i in 0..data_len / std::mem::size_of::<libc::c_int>() {
+                // SAFETY: the kernel installed these descriptors for us to own
+                let raw = unsafe { data.add(i).read_unaligned() };
+                fds.push_back(unsafe { OwnedFd::from_raw_fd(raw) });
+            }
+        }
+        // SAFETY: both pointers come from the same msghdr
+        cmsg = unsafe { libc::CMSG_NXTHDR(&header, cmsg) };
+    }
+    Ok(n as usize)
+}
+
+/// Extract the socket path from a `unix:path=...` bus address
+fn parse_unix_address(address: &str) -> Option<PathBuf> {
+    address.split(';').find_map(|addr| {
+        let params = addr.strip_prefix("unix:")?;
+        params
+            .split(',')
+            .find_map(|kv| kv.strip_prefix("path="))
+            .map(PathBuf::from)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_unix_address() {
+        assert_eq!(
+            parse_unix_address("unix:path=/run/dbus/system_bus_socket"),
+            Some(PathBuf::from("/run/dbus/system_bus_socket"))
+        );
+        assert_eq!(
+            parse_unix_address("tcp:host=x;unix:guid=abc,path=/tmp/bus"),
+            Some(PathBuf::from("/tmp/bus"))
+        );
+        assert_eq!(parse_unix_address("unix:abstract=/tmp/x"), None);
+    }
+
+    /// Send `bytes` with `fd` attached, as the bus does
+    fn send_with_fd(socket: &std::os::unix::net::UnixStream, bytes: &[u8], fd: RawFd) {
+        let mut control = [0u64; 4];
+        let mut iov = libc::iovec {
+            iov_base: bytes.as_ptr().cast_mut().cast(),
+            iov_len: bytes.len(),
+        };
+        let mut header: libc::msghdr = unsafe { std::mem::zeroed() };
+        header.msg_iov = &mut iov;
+        header.msg_iovlen = 1;
+        header.msg_control = control.as_mut_ptr().cast();
+        header.msg_controllen =
+            unsafe { libc::CMSG_SPACE(std::mem::size_of::<RawFd>() as u32) } as _;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&header);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<RawFd>() as u32) as _;
+            libc::CMSG_DATA(cmsg).cast::<RawFd>().write_unaligned(fd);
+            assert_eq!(
+                libc::sendmsg(socket.as_raw_fd(), &header, 0),
+                bytes.len() as isize
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_message_with_fd() {
+        let (ours, theirs) = std::os::unix::net::UnixStream::pair().unwrap();
+        ours.set_nonblocking(true).unwrap();
+        let (read_half, _write_half) = UnixStream::from_std(ours).unwrap().into_split();
+
+        let signal = Message::signal("/a", "b.c", "D").encode(1);
+        let mut reply = Message::method_return(&Message::method_call("a.b", "/c", "d.e", "F"))
+            .with_body(vec![Value::U32(0)]);
+        reply.unix_fds = 1;
+        let (pipe, _) = std::io::pipe().unwrap();
+
+        // Part of the first message was already read during authentication
+        let mut socket = SocketReader {
+            half: read_half,
+            buf: signal[..4].to_vec(),
+            fds: VecDeque::new(),
+        };
+        let mut theirs = theirs;
+        std::io::Write::write_all(&mut theirs, &signal[4..]).unwrap();
+        send_with_fd(&theirs, &reply.encode(3), pipe.as_raw_fd());
+
+        let (msg, fds) = socket.read_message().await.unwrap();
+        assert_eq!(msg.member.as_deref(), Some("D"));
+        assert!(fds.is_empty());
+
+        let (msg, fds) = socket.read_message().await.unwrap();
+        assert_eq!(msg.unix_fds, 1);
+        assert_eq!(fds.len(), 1);
+        assert_ne!(fds[0].as_raw_fd(), pipe.as_raw_fd());
+    }
+}
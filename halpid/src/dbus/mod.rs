@@ -0,0 +1,35 @@
+//! Minimal D-Bus client
+//!
+//! Just enough of the protocol to own a bus name, export objects with
+//! read-only properties, and emit signals. There is no proxy generation or
+//! type mapping; messages are built from [`wire::Value`] trees directly.
+
+pub mod connection;
+pub mod wire;
+
+pub use connection::Connection;
+pub use wire::{Message, MessageType, Value};
+
+/// Standard properties interface
+pub const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+
+/// Standard introspection interface
+pub const INTROSPECTABLE_INTERFACE: &str = "org.freedesktop.DBus.Introspectable";
+
+/// Standard peer interface
+pub const PEER_INTERFACE: &str = "org.freedesktop.DBus.Peer";
+
+/// Error returned for unknown methods
+pub const ERROR_UNKNOWN_METHOD: &str = "org.freedesktop.DBus.Error.UnknownMethod";
+
+/// Error returned for calls on paths we do not export
+pub const ERROR_UNKNOWN_OBJECT: &str = "org.freedesktop.DBus.Error.UnknownObject";
+
+/// Error returned for unknown properties
+pub const ERROR_UNKNOWN_PROPERTY: &str = "org.freedesktop.DBus.Error.UnknownProperty";
+
+/// Error returned when setting a read-only property
+pub const ERROR_PROPERTY_READ_ONLY: &str = "org.freedesktop.DBus.Error.PropertyReadOnly";
+
+/// Error returned for malformed arguments
+pub const ERROR_INVALID_ARGS: &str = "org.freedesktop.DBus.Error.InvalidArgs";
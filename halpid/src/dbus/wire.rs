@@ -0,0 +1,735 @@
+//! D-Bus wire format: value marshalling and message framing
+//!
+//! Implements the subset of the D-Bus specification the daemon needs:
+//! little-endian messages with basic types, arrays, structs, dict entries,
+//! and variants. Unix file descriptors (`h`) are read as their index into
+//! the descriptors passed with the message.
+
+use std::fmt;
+
+/// Largest message accepted from the bus (the spec limit is 128 MiB)
+pub const MAX_MESSAGE_SIZE: usize = 1 << 24;
+
+/// Length of the fixed part of the message header
+pub const FIXED_HEADER_SIZE: usize = 16;
+
+/// Flag on method calls that do not expect a reply
+pub const FLAG_NO_REPLY_EXPECTED: u8 = 0x1;
+
+/// Wire format errors
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum WireError {
+    #[error("Message truncated")]
+    Truncated,
+
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+
+    #[error("Invalid message: {0}")]
+    Invalid(String),
+}
+
+/// A D-Bus value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Byte(u8),
+    Bool(bool),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    Double(f64),
+    Str(String),
+    ObjectPath(String),
+    Signature(String),
+    Variant(Box<Value>),
+    /// Array with its element signature (needed to encode empty arrays)
+    Array(String, Vec<Value>),
+    Struct(Vec<Value>),
+    DictEntry(Box<Value>, Box<Value>),
+}
+
+impl Value {
+    /// Signature of this value's type
+    pub fn signature(&self) -> String {
+        match self {
+            Value::Byte(_) => "y".into(),
+            Value::Bool(_) => "b".into(),
+            Value::I32(_) => "i".into(),
+            Value::U32(_) => "u".into(),
+            Value::I64(_) => "x".into(),
+            Value::U64(_) => "t".into(),
+            Value::Double(_) => "d".into(),
+            Value::Str(_) => "s".into(),
+            Value::ObjectPath(_) => "o".into(),
+            Value::Signature(_) => "g".into(),
+            Value::Variant(_) => "v".into(),
+            Value::Array(elem, _) => format!("a{}", elem),
+            Value::Struct(fields) => {
+                let inner: String = fields.iter().map(Value::signature).collect();
+                format!("({})", inner)
+            }
+            Value::DictEntry(k, v) => format!("{{{}{}}}", k.signature(), v.signature()),
+        }
+    }
+
+    /// Wrap in a variant
+    pub fn variant(self) -> Value {
+        Value::Variant(Box::new(self))
+    }
+
+    /// Borrow the contents of a string-like value
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) | Value::ObjectPath(s) | Value::Signature(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Build an `a{sv}` dictionary from name/value pairs
+    pub fn dict(entries: Vec<(String, Value)>) -> Value {
+        Value::Array(
+            "{sv}".into(),
+            entries
+                .into_iter()
+                .map(|(k, v)| Value::DictEntry(Box::new(Value::Str(k)), Box::new(v.variant())))
+                .collect(),
+        )
+    }
+}
+
+/// Message type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    MethodCall = 1,
+    MethodReturn = 2,
+    Error = 3,
+    Signal = 4,
+}
+
+impl MessageType {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(MessageType::MethodCall),
+            2 => Some(MessageType::MethodReturn),
+            3 => Some(MessageType::Error),
+            4 => Some(MessageType::Signal),
+            _ => None,
+        }
+    }
+}
+
+// Header field codes
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_ERROR_NAME: u8 = 4;
+const FIELD_REPLY_SERIAL: u8 = 5;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SENDER: u8 = 7;
+const FIELD_SIGNATURE: u8 = 8;
+const FIELD_UNIX_FDS: u8 = 9;
+
+/// A D-Bus message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub msg_type: MessageType,
+    pub flags: u8,
+    pub serial: u32,
+    pub path: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+    pub error_name: Option<String>,
+    pub reply_serial: Option<u32>,
+    pub destination: Option<String>,
+    pub sender: Option<String>,
+    /// Number of file descriptors sent along with the message
+    ///
+    /// `h` values in the body index into these; the connection passes the
+    /// descriptors themselves separately.
+    pub unix_fds: u32,
+    pub body: Vec<Value>,
+}
+
+impl Message {
+    fn empty(msg_type: MessageType) -> Self {
+        Self {
+            msg_type,
+            flags: 0,
+            serial: 0,
+            path: None,
+            interface: None,
+            member: None,
+            error_name: None,
+            reply_serial: None,
+            destination: None,
+            sender: None,
+            unix_fds: 0,
+            body: Vec::new(),
+        }
+    }
+
+    /// Create a method call
+    pub fn method_call(destination: &str, path: &str, interface: &str, member: &str) -> Self {
+        Self {
+            destination: Some(destination.into()),
+            path: Some(path.into()),
+            interface: Some(interface.into()),
+            member: Some(member.into()),
+            ..Self::empty(MessageType::MethodCall)
+        }
+    }
+
+    /// Create a signal
+    pub fn signal(path: &str, interface: &str, member: &str) -> Self {
+        Self {
+            path: Some(path.into()),
+            interface: Some(interface.into()),
+            member: Some(member.into()),
+            ..Self::empty(MessageType::Signal)
+        }
+    }
+
+    /// Create a successful reply to `call`
+    pub fn method_return(call: &Message) -> Self {
+        Self {
+            destination: call.sender.clone(),
+            reply_serial: Some(call.serial),
+            ..Self::empty(MessageType::MethodReturn)
+        }
+    }
+
+    /// Create an error reply to `call`
+    pub fn error(call: &Message, name: &str, text: &str) -> Self {
+        Self {
+            destination: call.sender.clone(),
+            reply_serial: Some(call.serial),
+            error_name: Some(name.into()),
+            body: vec![Value::Str(text.into())],
+            ..Self::empty(MessageType::Error)
+        }
+    }
+
+    /// Append body arguments
+    pub fn with_body(mut self, body: Vec<Value>) -> Self {
+        self.body = body;
+        self
+    }
+
+    /// True if the sender does not want a reply
+    pub fn no_reply_expected(&self) -> bool {
+        self.flags & FLAG_NO_REPLY_EXPECTED != 0
+    }
+
+    /// Body signature
+    pub fn signature(&self) -> String {
+        self.body.iter().map(Value::signature).collect()
+    }
+
+    /// Serialize with the given serial number
+    pub fn encode(&self, serial: u32) -> Vec<u8> {
+        let mut body = Writer::default();
+        for value in &self.body {
+            body.write(value);
+        }
+
+        let mut fields = Vec::new();
+        let mut push = |code: u8, value: Value| {
+            fields.push(Value::Struct(vec![Value::Byte(code), value.variant()]));
+        };
+        if let Some(path) = &self.path {
+            push(FIELD_PATH, Value::ObjectPath(path.clone()));
+        }
+        if let Some(interface) = &self.interface {
+            push(FIELD_INTERFACE, Value::Str(interface.clone()));
+        }
+        if let Some(member) = &self.member {
+            push(FIELD_MEMBER, Value::Str(member.clone()));
+        }
+        if let Some(error_name) = &self.error_name {
+            push(FIELD_ERROR_NAME, Value::Str(error_name.clone()));
+        }
+        if let Some(reply_serial) = self.reply_serial {
+            push(FIELD_REPLY_SERIAL, Value::U32(reply_serial));
+        }
+        if let Some(destination) = &self.destination {
+            push(FIELD_DESTINATION, Value::Str(destination.clone()));
+        }
+        if let Some(sender) = &self.sender {
+            push(FIELD_SENDER, Value::Str(sender.clone()));
+        }
+        if !self.body.is_empty() {
+            push(FIELD_SIGNATURE, Value::Signature(self.signature()));
+        }
+        if self.unix_fds > 0 {
+            push(FIELD_UNIX_FDS, Value::U32(self.unix_fds));
+        }
+
+        let mut header = Writer::default();
+        header.write(&Value::Byte(b'l'));
+        header.write(&Value::Byte(self.msg_type as u8));
+        header.write(&Value::Byte(self.flags));
+        header.write(&Value::Byte(1)); // protocol version
+        header.write(&Value::U32(body.buf.len() as u32));
+        header.write(&Value::U32(serial));
+        header.write(&Value::Array("(yv)".into(), fields));
+        header.align(8);
+
+        let mut out = header.buf;
+        out.extend_from_slice(&body.buf);
+        out
+    }
+
+    /// Total message length given the first 16 bytes of a message
+    ///
+    /// # Errors
+    /// Returns an error for big-endian or oversized messages.
+    pub fn total_length(fixed: &[u8; FIXED_HEADER_SIZE]) -> Result<usize, WireError> {
+        if fixed[0] != b'l' {
+            return Err(WireError::Invalid(
+                "only little-endian messages are supported".into(),
+            ));
+        }
+        let body_len = u32::from_le_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]) as usize;
+        let fields_len = u32::from_le_bytes([fixed[12], fixed[13], fixed[14], fixed[15]]) as usize;
+        let header_len = pad_to(FIXED_HEADER_SIZE + fields_len, 8);
+        let total = header_len + body_len;
+        if total > MAX_MESSAGE_SIZE {
+            return Err(WireError::Invalid(format!(
+                "message too large ({} bytes)",
+                total
+            )));
+        }
+        Ok(total)
+    }
+
+    /// Parse a complete message
+    ///
+    /// # Errors
+    /// Returns an error if the message is malformed.
+    pub fn decode(buf: &[u8]) -> Result<Self, WireError> {
+        if buf.len() < FIXED_HEADER_SIZE {
+            return Err(WireError::Truncated);
+        }
+        let mut fixed = [0u8; FIXED_HEADER_SIZE];
+        fixed.copy_from_slice(&buf[..FIXED_HEADER_SIZE]);
+        let total = Self::total_length(&fixed)?;
+        if buf.len() < total {
+            return Err(WireError::Truncated);
+        }
+
+        let msg_type = MessageType::from_u8(buf[1])
+            .ok_or_else(|| WireError::Invalid(format!("unknown message type {}", buf[1])))?;
+        let mut msg = Self::empty(msg_type);
+        msg.flags = buf[2];
+        let body_len = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]) as usize;
+        msg.serial = u32::from_le_bytes([buf[8], buf[9], buf[10], buf[11]]);
+
+        let mut reader = Reader::new(&buf[..total - body_len]);
+        reader.pos = 12;
+        let fields = reader.read("a(yv)")?;
+
+        let mut signature = String::new();
+        if let Value::Array(_, fields) = fields {
+            for field in fields {
+                let Value::Struct(parts) = field else {
+                    continue;
+                };
+                let (Some(Value::Byte(code)), Some(Value::Variant(value))) =
+                    (parts.first(), parts.get(1))
+                else {
+                    continue;
+                };
+                let text = value.as_str().map(str::to_string);
+                match *code {
+                    FIELD_PATH => msg.path = text,
+                    FIELD_INTERFACE => msg.interface = text,
+                    FIELD_MEMBER => msg.member = text,
+                    FIELD_ERROR_NAME => msg.error_name = text,
+                    FIELD_REPLY_SERIAL => {
+                        if let Value::U32(serial) = **value {
+                            msg.reply_serial = Some(serial);
+                        }
+                    }
+                    FIELD_DESTINATION => msg.destination = text,
+                    FIELD_SENDER => msg.sender = text,
+                    FIELD_SIGNATURE => signature = text.unwrap_or_default(),
+                    FIELD_UNIX_FDS => {
+                        if let Value::U32(count) = **value {
+                            msg.unix_fds = count;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut body = Reader::new(&buf[total - body_len..total]);
+        for ty in split_signature(&signature)? {
+            msg.body.push(body.read(ty)?);
+        }
+
+        Ok(msg)
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {}.{} on {}",
+            self.msg_type,
+            self.interface.as_deref().unwrap_or("-"),
+            self.member.as_deref().unwrap_or("-"),
+            self.path.as_deref().unwrap_or("-")
+        )
+    }
+}
+
+fn pad_to(len: usize, align: usize) -> usize {
+    len.div_ceil(align) * align
+}
+
+/// Alignment of the type starting with `code`
+fn alignment(code: u8) -> usize {
+    match code {
+        b'y' | b'g' | b'v' => 1,
+        b'n' | b'q' => 2,
+        b'x' | b't' | b'd' | b'(' | b'{' => 8,
+        _ => 4,
+    }
+}
+
+/// Split a signature into its complete types
+///
+/// # Errors
+/// Returns an error if the signature is unbalanced or contains unknown codes.
+pub fn split_signature(sig: &str) -> Result<Vec<&str>, WireError> {
+    let mut types = Vec::new();
+    let mut rest = sig;
+    while !rest.is_empty() {
+        let len = single_type_len(rest.as_bytes())?;
+        types.push(&rest[..len]);
+        rest = &rest[len..];
+    }
+    Ok(types)
+}
+
+/// Length of the first complete type in a signature
+fn single_type_len(sig: &[u8]) -> Result<usize, WireError> {
+    let invalid = || WireError::InvalidSignature(String::from_utf8_lossy(sig).into_owned());
+    match sig.first() {
+        Some(
+            b'y' | b'b' | b'n' | b'q' | b'i' | b'u' | b'x' | b't' | b'd' | b's' | b'o' | b'g'
+            | b'v' | b'h',
+        ) => Ok(1),
+        Some(b'a') => Ok(1 + single_type_len(&sig[1..])?),
+        Some(&open @ (b'(' | b'{')) => {
+            let close = if open == b'(' { b')' } else { b'}' };
+            let mut pos = 1;
+            while sig.get(pos).ok_or_else(invalid)? != &close {
+                pos += single_type_len(&sig[pos..])?;
+            }
+            Ok(pos + 1)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Marshals values into a byte buffer
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn align(&mut self, align: usize) {
+        let padded = pad_to(self.buf.len(), align);
+        self.buf.resize(padded, 0);
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.align(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_str(&mut self, value: &str) {
+        self.write_u32(value.len() as u32);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn write_signature(&mut self, value: &str) {
+        self.buf.push(value.len() as u8);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn write(&mut self, value: &Value) {
+        match value {
+            Value::Byte(v) => self.buf.push(*v),
+            Value::Bool(v) => self.write_u32(*v as u32),
+            Value::I32(v) => self.write_u32(*v as u32),
+            Value::U32(v) => self.write_u32(*v),
+            Value::I64(v) => {
+                self.align(8);
+                self.buf.extend_from_slice(&v.to_le_bytes());
+            }
+            Value::U64(v) => {
+                self.align(8);
+                self.buf.extend_from_slice(&v.to_le_bytes());
+            }
+            Value::Double(v) => {
+                self.align(8);
+                self.buf.extend_from_slice(&v.to_le_bytes());
+            }
+            Value::Str(v) | Value::ObjectPath(v) => self.write_str(v),
+            Value::Signature(v) => self.write_signature(v),
+            Value::Variant(inner) => {
+                self.write_signature(&inner.signature());
+                self.write(inner);
+            }
+            Value::Array(elem, items) => {
+                self.write_u32(0);
+                let len_pos = self.buf.len() - 4;
+                self.align(alignment(elem.as_bytes().first().copied().unwrap_or(b'y')));
+                let start = self.buf.len();
+                for item in items {
+                    self.write(item);
+                }
+                let len = (self.buf.len() - start) as u32;
+                self.buf[len_pos..len_pos + 4].copy_from_slice(&len.to_le_bytes());
+            }
+            Value::Struct(fields) => {
+                self.align(8);
+                for field in fields {
+                    self.write(field);
+                }
+            }
+            Value::DictEntry(k, v) => {
+                self.align(8);
+                self.write(k);
+                self.write(v);
+            }
+        }
+    }
+}
+
+/// Unmarshals values from a byte buffer
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn align(&mut self, align: usize) -> Result<(), WireError> {
+        let padded = pad_to(self.pos, align);
+        if padded > self.buf.len() {
+            return Err(WireError::Truncated);
+        }
+        self.pos = padded;
+        Ok(())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], WireError> {
+        let end = self.pos.checked_add(len).ok_or(WireError::Truncated)?;
+        let bytes = self.buf.get(self.pos..end).ok_or(WireError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn read_fixed<const N: usize>(&mut self) -> Result<[u8; N], WireError> {
+        self.align(N)?;
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, WireError> {
+        Ok(u32::from_le_bytes(self.read_fixed()?))
+    }
+
+    fn read_string(&mut self, len: usize) -> Result<String, WireError> {
+        let bytes = self.take(len)?;
+        self.take(1)?; // nul terminator
+        String::from_utf8(bytes.to_vec()).map_err(|_| WireError::Invalid("invalid UTF-8".into()))
+    }
+
+    fn read_signature(&mut self) -> Result<String, WireError> {
+        let len = self.take(1)?[0] as usize;
+        self.read_string(len)
+    }
+
+    /// Read one value of the given single complete type
+    fn read(&mut self, sig: &str) -> Result<Value, WireError> {
+        let bytes = sig.as_bytes();
+        let value = match bytes.first() {
+            Some(b'y') => Value::Byte(self.take(1)?[0]),
+            Some(b'b') => Value::Bool(self.read_u32()? != 0),
+            Some(b'n') => Value::I32(i16::from_le_bytes(self.read_fixed()?) as i32),
+            Some(b'q') => Value::U32(u16::from_le_bytes(self.read_fixed()?) as u32),
+            Some(b'i') => Value::I32(self.read_u32()? as i32),
+            Some(b'u' | b'h') => Value::U32(self.read_u32()?),
+            Some(b'x') => Value::I64(i64::from_le_bytes(self.read_fixed()?)),
+            Some(b't') => Value::U64(u64::from_le_bytes(self.read_fixed()?)),
+            Some(b'd') => Value::Double(f64::from_le_bytes(self.read_fixed()?)),
+            Some(b's') => {
+                let len = self.read_u32()? as usize;
+                Value::Str(self.read_string(len)?)
+            }
+            Some(b'o') => {
+                let len = self.read_u32()? as usize;
+                Value::ObjectPath(self.read_string(len)?)
+            }
+            Some(b'g') => Value::Signature(self.read_signature()?),
+            Some(b'v') => {
+                let inner_sig = self.read_signature()?;
+                if split_signature(&inner_sig)?.len() != 1 {
+                    return Err(WireError::InvalidSignature(inner_sig));
+                }
+                Value::Variant(Box::new(self.read(&inner_sig)?))
+            }
+            Some(b'a') => {
+                let elem = &sig[1..];
+                let len = self.read_u32()? as usize;
+                self.align(alignment(elem.as_bytes()[0]))?;
+                let end = self.pos.checked_add(len).ok_or(WireError::Truncated)?;
+                if end > self.buf.len() {
+                    return Err(WireError::Truncated);
+                }
+                let mut items = Vec::new();
+                while self.pos < end {
+                    items.push(self.read(elem)?);
+                }
+                Value::Array(elem.to_string(), items)
+            }
+            Some(b'(') => {
+                self.align(8)?;
+                let mut fields = Vec::new();
+                for ty in split_signature(&sig[1..sig.len() - 1])? {
+                    fields.push(self.read(ty)?);
+                }
+                Value::Struct(fields)
+            }
+            Some(b'{') => {
+                self.align(8)?;
+                let inner = split_signature(&sig[1..sig.len() - 1])?;
+                if inner.len() != 2 {
+                    return Err(WireError::InvalidSignature(sig.to_string()));
+                }
+                let key = self.read(inner[0])?;
+                let value = self.read(inner[1])?;
+                Value::DictEntry(Box::new(key), Box::new(value))
+            }
+            _ => return Err(WireError::InvalidSignature(sig.to_string())),
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_signature() {
+        assert_eq!(
+            split_signature("sa{sv}as(ii)").unwrap(),
+            vec!["s", "a{sv}", "as", "(ii)"]
+        );
+        assert!(split_signature("a{sv").is_err());
+        assert!(split_signature("z").is_err());
+    }
+
+    #[test]
+    fn test_hello_encoding() {
+        let msg = Message::method_call(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "Hello",
+        );
+        let bytes = msg.encode(1);
+        assert_eq!(&bytes[..4], &[b'l', 1, 0, 1]);
+        assert_eq!(&bytes[4..8], &0u32.to_le_bytes()); // empty body
+        assert_eq!(&bytes[8..12], &1u32.to_le_bytes());
+        assert_eq!(bytes.len() % 8, 0);
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let mut msg = Message::signal(
+            "/org/freedesktop/UPower/devices/battery_halpi",
+            "org.freedesktop.DBus.Properties",
+            "PropertiesChanged",
+        )
+        .with_body(vec![
+            Value::Str("org.freedesktop.UPower.Device".into()),
+            Value::dict(vec![
+                ("Percentage".into(), Value::Double(87.5)),
+                ("State".into(), Value::U32(2)),
+                ("TimeToEmpty".into(), Value::I64(42)),
+            ]),
+            Value::Array("s".into(), vec![]),
+        ]);
+        msg.sender = Some(":1.42".into());
+
+        let bytes = msg.encode(7);
+        let mut fixed = [0u8; FIXED_HEADER_SIZE];
+        fixed.copy_from_slice(&bytes[..FIXED_HEADER_SIZE]);
+        assert_eq!(Message::total_length(&fixed).unwrap(), bytes.len());
+
+        let mut decoded = Message::decode(&bytes).unwrap();
+        assert_eq!(decoded.serial, 7);
+        decoded.serial = 0;
+        assert_eq!(decoded, msg);
+    }
+
+    #[test]
+    fn test_decode_truncated() {
+        let bytes = Message::method_call("a.b", "/", "a.b", "C")
+            .with_body(vec![Value::Str("hello".into())])
+            .encode(1);
+        assert_eq!(
+            Message::decode(&bytes[..bytes.len() - 2]),
+            Err(WireError::Truncated)
+        );
+    }
+
+    #[test]
+    fn test_error_reply_targets_caller() {
+        let mut call = Message::method_call("x.y", "/", "x.y", "Z");
+        call.serial = 9;
+        call.sender = Some(":1.5".into());
+        let reply = Message::error(&call, "org.freedesktop.DBus.Error.Failed", "nope");
+        assert_eq!(reply.reply_serial, Some(9));
+        assert_eq!(reply.destination.as_deref(), Some(":1.5"));
+        assert_eq!(reply.signature(), "s");
+    }
+
+    #[test]
+    fn test_unix_fds_roundtrip() {
+        let mut reply = Message::method_return(&Message::method_call(
+            "org.freedesktop.login1",
+            "/org/freedesktop/login1",
+            "org.freedesktop.login1.Manager",
+            "Inhibit",
+        ))
+        .with_body(vec![Value::U32(0)]);
+        reply.unix_fds = 1;
+
+        let decoded = Message::decode(&reply.encode(9)).unwrap();
+        assert_eq!(decoded.unix_fds, 1);
+        assert_eq!(decoded.body, vec![Value::U32(0)]);
+        assert_eq!(
+            Message::decode(&Message::signal("/", "a.b", "C").encode(1))
+                .unwrap()
+                .unix_fds,
+            0
+        );
+    }
+}
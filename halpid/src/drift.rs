@@ -0,0 +1,243 @@
+//! Controller configuration drift
+//!
+//! The `controller` section declares settings stored in the controller's
+//! registers. A controller that reset itself, or was changed by another
+//! tool, no longer matches them; this service reads the declared settings
+//! back every `check-interval` seconds and records the differences for
+//! `GET /health`. With `enforce`, drifted settings are written back.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{Value, json};
+use tokio::time::{Duration, interval};
+use tracing::{debug, info, warn};
+
+use halpi_common::api::ConfigDrift;
+use halpi_common::config::ControllerConfig;
+
+use crate::i2c::{DeviceHandle, HalpiDevice, I2cError};
+use crate::state_machine::StatusHandle;
+
+/// Largest difference of a voltage threshold that is not drift
+///
+/// Older firmware stores the thresholds in a byte, in steps of about 0.04 V.
+const VOLTAGE_TOLERANCE: f64 = 0.05;
+
+/// Largest difference of a timeout in seconds that is not drift
+const TIMEOUT_TOLERANCE: f64 = 0.001;
+
+/// Result of the last drift check
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DriftReport {
+    pub checked_at: DateTime<Utc>,
+    pub drift: Vec<ConfigDrift>,
+}
+
+/// Declared settings with their `GET /config` key and value
+fn declared(config: &ControllerConfig) -> Vec<(&'static str, Value)> {
+    [
+        (
+            "power_on_threshold",
+            config.power_on_threshold.map(|v| json!(v)),
+        ),
+        (
+            "solo_power_off_threshold",
+            config.solo_power_off_threshold.map(|v| json!(v)),
+        ),
+        ("led_brightness", config.led_brightness.map(|v| json!(v))),
+        ("auto_restart", config.auto_restart.map(|v| json!(v))),
+        (
+            "solo_depleting_timeout",
+            config.solo_depleting_timeout.map(|v| json!(v)),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| value.map(|value| (key, value)))
+    .collect()
+}
+
+/// True if a setting read from the controller differs from its declared value
+fn differs(key: &str, expected: &Value, actual: &Value) -> bool {
+    let tolerance = match key {
+        "power_on_threshold" | "solo_power_off_threshold" => VOLTAGE_TOLERANCE,
+        "solo_depleting_timeout" => TIMEOUT_TOLERANCE,
+        _ => return expected != actual,
+    };
+    match (expected.as_f64(), actual.as_f64()) {
+        (Some(expected), Some(actual)) => (expected - actual).abs() > tolerance,
+        _ => true,
+    }
+}
+
+/// Read a setting, in the units of `GET /config`
+fn read(device: &mut HalpiDevice, key: &str) -> Result<Value, I2cError> {
+    Ok(match key {
+        "power_on_threshold" => json!(device.get_power_on_threshold()?),
+        "solo_power_off_threshold" => json!(device.get_solo_power_off_threshold()?),
+        "led_brightness" => json!(device.get_led_brightness()?),
+        "auto_restart" => json!(device.get_auto_restart()?),
+        _ => json!(device.get_solo_depleting_timeout()? as f64 / 1000.0),
+    })
+}
+
+/// Write a declared setting
+fn write(device: &mut HalpiDevice, key: &str, value: &Value) -> Result<(), I2cError> {
+    let number = value.as_f64().unwrap_or_default();
+    match key {
+        "power_on_threshold" => device.set_power_on_threshold(number as f32),
+        "solo_power_off_threshold" => device.set_solo_power_off_threshold(number as f32),
+        "led_brightness" => device.set_led_brightness(number as u8),
+        "auto_restart" => device.set_auto_restart(value.as_bool().unwrap_or_default()),
+        _ => device.set_solo_depleting_timeout((number * 1000.0).round() as u32),
+    }
+}
+
+/// Declared settings that differ from those stored in the controller
+///
+/// # Errors
+/// Returns `I2cError` if a setting cannot be read.
+pub fn check(
+    device: &mut HalpiDevice,
+    config: &ControllerConfig,
+) -> Result<Vec<ConfigDrift>, I2cError> {
+    let mut drift = Vec::new();
+    for (key, expected) in declared(config) {
+        let actual = read(device, key)?;
+        if differs(key, &expected, &actual) {
+            drift.push(ConfigDrift {
+                key: key.to_string(),
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok(drift)
+}
+
+/// Write the declared values of drifted settings back
+///
+/// # Errors
+/// Returns `I2cError` if a setting cannot be written.
+pub fn enforce(device: &mut HalpiDevice, drift: &[ConfigDrift]) -> Result<(), I2cError> {
+    for setting in drift {
+        write(device, &setting.key, &setting.expected)?;
+    }
+    Ok(())
+}
+
+/// Check the controller settings every `check-interval` seconds
+///
+/// Drift is logged when it changes; with `enforce`, it is corrected and
+/// the check repeated, so the report shows what remains. Settings of a
+/// controller that failed the self-test's compatibility check are only
+/// checked, not written. No checks are made while the system shuts down.
+pub async fn run(
+    device: DeviceHandle,
+    status: StatusHandle,
+    config: ControllerConfig,
+) -> anyhow::Result<()> {
+    let mut ticker = interval(Duration::from_secs_f64(config.check_interval));
+    let mut reported: Vec<ConfigDrift> = Vec::new();
+    loop {
+        ticker.tick().await;
+        if status.is_shutting_down() {
+            continue;
+        }
+        let checked = {
+            let config = config.clone();
+            let restore = config.enforce && status.is_compatible();
+            device
+                .run(move |device| {
+                    let found = check(device, &config)?;
+                    if !restore || found.is_empty() {
+                        return Ok((Vec::new(), found));
+                    }
+                    enforce(device, &found)?;
+                    Ok((found, check(device, &config)?))
+                })
+                .await
+        };
+        let (restored, drift) = match checked {
+            Ok(checked) => checked,
+            Err(e) => {
+                debug!("Cannot check the controller settings: {}", e);
+                continue;
+            }
+        };
+
+        if !restored.is_empty() {
+            info!(
+                "Restored drifted controller settings: {}",
+                describe(&restored)
+            );
+        }
+        if !drift.is_empty() && drift != reported {
+            warn!(
+                "Controller settings differ from the configuration: {}",
+                describe(&drift)
+            );
+        }
+        reported = drift.clone();
+        status.set_drift(Some(DriftReport {
+            checked_at: Utc::now(),
+            drift,
+        }));
+    }
+}
+
+/// Drifted settings as "key actual (expected expected), ..."
+pub fn describe(drift: &[ConfigDrift]) -> String {
+    drift
+        .iter()
+        .map(|setting| {
+            format!(
+                "{} {} (expected {})",
+                setting.key, setting.actual, setting.expected
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_declared() {
+        let config = ControllerConfig {
+            led_brightness: Some(40),
+            solo_depleting_timeout: Some(5.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            declared(&config),
+            [
+                ("led_brightness", json!(40)),
+                ("solo_depleting_timeout", json!(5.0))
+            ]
+        );
+        assert!(declared(&ControllerConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn test_differs() {
+        // Thresholds read back in the controller's resolution
+        assert!(!differs("power_on_threshold", &json!(9.0), &json!(9.03)));
+        assert!(differs("power_on_threshold", &json!(9.0), &json!(8.5)));
+        assert!(!differs("solo_depleting_timeout", &json!(5), &json!(5.0)));
+        assert!(differs("led_brightness", &json!(40), &json!(255)));
+        assert!(!differs("auto_restart", &json!(true), &json!(true)));
+        assert!(differs("auto_restart", &json!(true), &json!(false)));
+    }
+
+    #[test]
+    fn test_describe() {
+        let drift = vec![ConfigDrift {
+            key: "led_brightness".to_string(),
+            expected: json!(40),
+            actual: json!(255),
+        }];
+        assert_eq!(describe(&drift), "led_brightness 255 (expected 40)");
+    }
+}
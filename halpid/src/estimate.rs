@@ -0,0 +1,83 @@
+//! Supercapacitor runtime estimation
+//!
+//! Shared by the state machine, which shuts down when the estimated runtime
+//! no longer covers a clean poweroff, and the UPower device, which reports
+//! it as the time to empty.
+
+use halpi_common::units::Volts;
+
+/// Smoothing factor for the discharge rate estimate
+const RATE_SMOOTHING: f64 = 0.3;
+
+/// Estimates time to empty from the observed supercapacitor discharge
+///
+/// Stored energy is proportional to V², so the time left is the remaining
+/// V² headroom divided by the smoothed rate at which V² is falling. This
+/// needs no knowledge of the capacitance or the load.
+#[derive(Debug, Default)]
+pub struct DischargeEstimator {
+    last: Option<(f64, f64)>,
+    rate: Option<f64>,
+}
+
+impl DischargeEstimator {
+    /// Feed a sample taken at time `t` (seconds) and return the estimate in seconds
+    ///
+    /// Returns `None` when not discharging or before a rate is known.
+    pub fn update(
+        &mut self,
+        t: f64,
+        voltage: Volts,
+        empty_voltage: Volts,
+        discharging: bool,
+    ) -> Option<f64> {
+        if !discharging {
+            self.last = None;
+            self.rate = None;
+            return None;
+        }
+
+        let v2 = voltage.as_f64().powi(2);
+        if let Some((last_t, last_v2)) = self.last {
+            let dt = t - last_t;
+            if dt > 0.0 {
+                let sample = (last_v2 - v2) / dt;
+                self.rate = Some(match self.rate {
+                    Some(rate) => rate + RATE_SMOOTHING * (sample - rate),
+                    None => sample,
+                });
+            }
+        }
+        self.last = Some((t, v2));
+
+        let headroom = v2 - empty_voltage.as_f64().powi(2);
+        match self.rate {
+            Some(rate) if rate > 0.0 => Some((headroom.max(0.0) / rate).round()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimator_linear_discharge() {
+        let mut est = DischargeEstimator::default();
+        // V² falls by 2 per second from 100 (10 V); empty at 6 V (36)
+        assert_eq!(est.update(0.0, Volts(10.0), Volts(6.0), true), None);
+        let v = (98.0f32).sqrt();
+        let remaining = est.update(1.0, Volts(v), Volts(6.0), true).unwrap();
+        assert!((remaining - 31.0).abs() <= 1.0);
+    }
+
+    #[test]
+    fn test_estimator_resets_when_charging() {
+        let mut est = DischargeEstimator::default();
+        est.update(0.0, Volts(10.0), Volts(6.0), true);
+        est.update(1.0, Volts(9.9), Volts(6.0), true);
+        assert_eq!(est.update(2.0, Volts(9.9), Volts(6.0), false), None);
+        assert_eq!(est.update(3.0, Volts(9.8), Volts(6.0), true), None);
+    }
+}
@@ -0,0 +1,272 @@
+//! Internal event bus
+//!
+//! The state machine is the only task that samples the controller on a
+//! schedule. It publishes each sample, together with state transitions and
+//! alerts, on a broadcast channel; other subsystems subscribe instead of
+//! polling the device themselves. Besides saving I2C traffic this matters
+//! for the watchdog: any I2C access feeds it, so independent pollers would
+//! keep the controller alive after the state machine has stopped.
+//!
+//! Subscribers that only need the current values on their own schedule can
+//! read [`EventBus::current`] instead of consuming every sample. The most
+//! recent state transitions, alerts and voltage excursions are kept in
+//! [`EventBus::history`] for support bundles, and the samples of the last
+//! day are summarized in [`EventBus::stats`] and
+//! [`EventBus::measurement_history`].
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{broadcast, watch};
+use tokio::time::Duration;
+
+use halpi_common::events::{DaemonEvent, Sample};
+
+use crate::stats::{HistoryRow, MeasurementStats, StatsReport};
+
+/// Number of events buffered per subscriber before it starts lagging
+pub const CHANNEL_CAPACITY: usize = 1024;
+
+/// Number of state transitions, alerts and voltage excursions kept for support bundles
+pub const HISTORY_CAPACITY: usize = 200;
+
+/// Age after which the latest sample is no longer considered current
+///
+/// The state machine samples at least once a second (every 100 ms by
+/// default), so a sample this old means it has stopped polling or the
+/// controller is not responding.
+pub const STALE_AFTER: Duration = Duration::from_secs(5);
+
+/// Cloneable handle to the daemon event bus
+#[derive(Clone)]
+pub struct EventBus {
+    events: broadcast::Sender<DaemonEvent>,
+    latest: watch::Sender<Option<Sample>>,
+    /// Recent state transitions, alerts and voltage excursions, oldest first
+    history: Arc<Mutex<VecDeque<DaemonEvent>>>,
+    /// Aggregates of the recent measurement samples
+    stats: Arc<Mutex<MeasurementStats>>,
+}
+
+impl EventBus {
+    /// Create a bus with no subscribers
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let (latest, _) = watch::channel(None);
+        Self {
+            events,
+            latest,
+            history: Arc::default(),
+            stats: Arc::default(),
+        }
+    }
+
+    /// Publish an event to all current subscribers
+    ///
+    /// Measurement samples also replace the value returned by [`latest`].
+    /// Publishing never blocks; events are dropped if nobody is subscribed.
+    ///
+    /// [`latest`]: EventBus::latest
+    pub fn publish(&self, event: DaemonEvent) {
+        match &event {
+            DaemonEvent::Measurement(sample) => {
+                self.stats.lock().unwrap().record(sample);
+                self.latest.send_replace(Some(sample.clone()));
+            }
+            DaemonEvent::StateTransition { .. }
+            | DaemonEvent::Alert(_)
+            | DaemonEvent::VoltageExcursion(_) => {
+                let mut history = self.history.lock().unwrap();
+                if history.len() == HISTORY_CAPACITY {
+                    history.pop_front();
+                }
+                history.push_back(event.clone());
+            }
+            DaemonEvent::Dfu { .. } => {}
+        }
+        let _ = self.events.send(event);
+    }
+
+    /// The most recent state transitions, alerts and voltage excursions, oldest first
+    pub fn history(&self) -> Vec<DaemonEvent> {
+        self.history.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Minimum, maximum and average of the measurements of the last
+    /// `window_s` seconds, `None` if none were published in it
+    pub fn stats(&self, window_s: u64) -> Option<StatsReport> {
+        self.stats
+            .lock()
+            .unwrap()
+            .report(window_s, chrono::Utc::now())
+    }
+
+    /// Per-second or per-minute summaries of the measurements of the last
+    /// `since_s` seconds, with their resolution in seconds
+    pub fn measurement_history(&self, since_s: u64) -> (u64, Vec<HistoryRow>) {
+        self.stats
+            .lock()
+            .unwrap()
+            .history(since_s, chrono::Utc::now())
+    }
+
+    /// Subscribe to events published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<DaemonEvent> {
+        self.events.subscribe()
+    }
+
+    /// Number of active subscribers
+    pub fn subscriber_count(&self) -> usize {
+        self.events.receiver_count()
+    }
+
+    /// Number of events not yet received by the slowest subscriber
+    ///
+    /// Reaching [`CHANNEL_CAPACITY`] means that subscriber is lagging and
+    /// losing events.
+    pub fn queue_depth(&self) -> usize {
+        self.events.len()
+    }
+
+    /// The most recent measurement sample, if any has been published
+    pub fn latest(&self) -> Option<Sample> {
+        self.latest.borrow().clone()
+    }
+
+    /// Wait until the first measurement sample has been published
+    pub async fn wait_for_sample(&self) {
+        let mut latest = self.latest.subscribe();
+        // The sender lives in `self`, so the channel cannot close
+        let _ = latest.wait_for(Option::is_some).await;
+    }
+
+    /// The latest sample, unless it is older than [`STALE_AFTER`]
+    pub fn current(&self) -> Option<Sample> {
+        self.latest().filter(|sample| {
+            let age = chrono::Utc::now() - sample.timestamp;
+            age.to_std().map_or(true, |age| age < STALE_AFTER)
+        })
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use halpi_common::events::{Alert, AlertKind};
+    use halpi_common::types::{Measurements, PowerState};
+    use halpi_common::units::{Amps, Kelvin, Volts};
+
+    fn sample(v_in: f32) -> Sample {
+        Sample::now(Measurements {
+            dcin_voltage: Volts(v_in),
+            supercap_voltage: Volts(9.5),
+            input_current: Amps(0.5),
+            mcu_temperature: Kelvin(300.0),
+            pcb_temperature: Kelvin(300.0),
+            power_state: PowerState::OperationalCoOp,
+            watchdog_elapsed: 0.0,
+            measured_at: Utc::now(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_publish_subscribe() {
+        let bus = EventBus::new();
+        let mut rx = bus.subscribe();
+
+        bus.publish(DaemonEvent::Alert(Alert::new(
+            AlertKind::BlackoutDetected,
+            "test",
+        )));
+        bus.publish(DaemonEvent::Measurement(sample(12.0)));
+
+        assert_eq!(bus.subscriber_count(), 1);
+        assert_eq!(bus.queue_depth(), 2);
+        assert_eq!(rx.recv().await.unwrap().name(), "alert");
+        assert_eq!(rx.recv().await.unwrap().name(), "measurement");
+        assert_eq!(bus.queue_depth(), 0);
+    }
+
+    #[test]
+    fn test_latest_sample() {
+        let bus = EventBus::new();
+        assert!(bus.latest().is_none());
+
+        // Publishing without subscribers still updates the latest sample
+        bus.publish(DaemonEvent::Measurement(sample(12.0)));
+        bus.publish(DaemonEvent::Measurement(sample(11.5)));
+        assert_eq!(bus.latest().unwrap().measurements.dcin_voltage, Volts(11.5));
+        assert_eq!(bus.stats(60).unwrap().samples, 2);
+
+        bus.publish(DaemonEvent::Alert(Alert::new(
+            AlertKind::PowerRestored,
+            "x",
+        )));
+        assert_eq!(bus.latest().unwrap().measurements.dcin_voltage, Volts(11.5));
+    }
+
+    #[test]
+    fn test_history() {
+        let bus = EventBus::new();
+        bus.publish(DaemonEvent::Measurement(sample(12.0)));
+        bus.publish(DaemonEvent::StateTransition {
+            timestamp: chrono::Utc::now(),
+            from: PowerState::OperationalCoOp,
+            to: PowerState::BlackoutCoOp,
+        });
+        for i in 0..HISTORY_CAPACITY {
+            bus.publish(DaemonEvent::Alert(Alert::new(
+                AlertKind::BlackoutDetected,
+                i.to_string(),
+            )));
+        }
+
+        // Samples are not kept, and the oldest entries make room
+        let history = bus.history();
+        assert_eq!(history.len(), HISTORY_CAPACITY);
+        assert!(history.iter().all(|event| event.name() == "alert"));
+        match history.last() {
+            Some(DaemonEvent::Alert(alert)) => {
+                assert_eq!(alert.message, (HISTORY_CAPACITY - 1).to_string())
+            }
+            _ => panic!("Expected an alert"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_sample() {
+        let bus = EventBus::new();
+        let waiter = {
+            let bus = bus.clone();
+            tokio::spawn(async move { bus.wait_for_sample().await })
+        };
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        bus.publish(DaemonEvent::Measurement(sample(12.0)));
+        waiter.await.unwrap();
+
+        // Returns immediately once a sample exists
+        bus.wait_for_sample().await;
+    }
+
+    #[test]
+    fn test_current_ignores_stale_sample() {
+        let bus = EventBus::new();
+        let mut old = sample(12.0);
+        old.timestamp -= chrono::Duration::seconds(10);
+        bus.publish(DaemonEvent::Measurement(old));
+        assert!(bus.latest().is_some());
+        assert!(bus.current().is_none());
+
+        bus.publish(DaemonEvent::Measurement(sample(12.0)));
+        assert!(bus.current().is_some());
+    }
+}
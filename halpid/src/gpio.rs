@@ -0,0 +1,265 @@
+//! Shutdown button on a GPIO line
+//!
+//! Requests the line as an input through the GPIO character device (uAPI
+//! v2) and polls its value. Once it has been active for the debounce time,
+//! the controller is asked to shut down, like with `POST /shutdown`. The
+//! line must be released before it can trigger again, so an ignition
+//! switch left off does not repeat the request.
+
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+
+use anyhow::Context;
+use tokio::time::{Duration, Instant, interval};
+use tracing::{info, warn};
+
+use halpi_common::config::{GpioBias, GpioButtonConfig};
+use halpi_common::events::{Alert, AlertKind, DaemonEvent};
+
+use crate::events::EventBus;
+use crate::i2c::DeviceHandle;
+
+/// How often the line is read
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// `GPIO_V2_GET_LINE_IOCTL`, i.e. `_IOWR(0xB4, 0x07, struct gpio_v2_line_request)`
+const GPIO_V2_GET_LINE_IOCTL: u32 = 0xC250_B407;
+
+/// `GPIO_V2_LINE_GET_VALUES_IOCTL`, i.e. `_IOWR(0xB4, 0x0E, struct gpio_v2_line_values)`
+const GPIO_V2_LINE_GET_VALUES_IOCTL: u32 = 0xC010_B40E;
+
+const GPIO_V2_LINE_FLAG_ACTIVE_LOW: u64 = 1 << 1;
+const GPIO_V2_LINE_FLAG_INPUT: u64 = 1 << 2;
+const GPIO_V2_LINE_FLAG_BIAS_PULL_UP: u64 = 1 << 8;
+const GPIO_V2_LINE_FLAG_BIAS_PULL_DOWN: u64 = 1 << 9;
+const GPIO_V2_LINE_FLAG_BIAS_DISABLED: u64 = 1 << 10;
+
+/// `struct gpio_v2_line_config_attribute` from `linux/gpio.h`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct LineConfigAttribute {
+    id: u32,
+    padding: u32,
+    value: u64,
+    mask: u64,
+}
+
+/// `struct gpio_v2_line_config`
+#[repr(C)]
+struct LineConfig {
+    flags: u64,
+    num_attrs: u32,
+    padding: [u32; 5],
+    attrs: [LineConfigAttribute; 10],
+}
+
+/// `struct gpio_v2_line_request`
+#[repr(C)]
+struct LineRequest {
+    offsets: [u32; 64],
+    consumer: [u8; 32],
+    config: LineConfig,
+    num_lines: u32,
+    event_buffer_size: u32,
+    padding: [u32; 5],
+    fd: i32,
+}
+
+/// `struct gpio_v2_line_values`
+#[repr(C)]
+struct LineValues {
+    bits: u64,
+    mask: u64,
+}
+
+const _: () = assert!(std::mem::size_of::<LineRequest>() == 592);
+const _: () = assert!(std::mem::size_of::<LineValues>() == 16);
+
+/// A GPIO line requested as an input
+#[derive(Debug)]
+pub struct InputLine {
+    fd: OwnedFd,
+}
+
+impl InputLine {
+    /// Request line `offset` of the GPIO chip at `chip` as an input
+    ///
+    /// # Errors
+    /// Returns an error if the chip cannot be opened, or the line does not
+    /// exist or is in use.
+    pub fn request(chip: &Path, offset: u32, bias: GpioBias, active_low: bool) -> io::Result<Self> {
+        let chip = File::open(chip)?;
+
+        let mut flags = GPIO_V2_LINE_FLAG_INPUT
+            | match bias {
+                GpioBias::PullUp => GPIO_V2_LINE_FLAG_BIAS_PULL_UP,
+                GpioBias::PullDown => GPIO_V2_LINE_FLAG_BIAS_PULL_DOWN,
+                GpioBias::Disabled => GPIO_V2_LINE_FLAG_BIAS_DISABLED,
+            };
+        if active_low {
+            flags |= GPIO_V2_LINE_FLAG_ACTIVE_LOW;
+        }
+        let mut consumer = [0u8; 32];
+        consumer[..6].copy_from_slice(b"halpid");
+        let mut offsets = [0u32; 64];
+        offsets[0] = offset;
+        let mut request = LineRequest {
+            offsets,
+            consumer,
+            config: LineConfig {
+                flags,
+                num_attrs: 0,
+                padding: [0; 5],
+                attrs: [LineConfigAttribute {
+                    id: 0,
+                    padding: 0,
+                    value: 0,
+                    mask: 0,
+                }; 10],
+            },
+            num_lines: 1,
+            event_buffer_size: 0,
+            padding: [0; 5],
+            fd: -1,
+        };
+
+        // SAFETY: the descriptor is open and `request` outlives the call
+        let result =
+            unsafe { libc::ioctl(chip.as_raw_fd(), GPIO_V2_GET_LINE_IOCTL as _, &mut request) };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the kernel returned a new descriptor owned by us
+        let fd = unsafe { OwnedFd::from_raw_fd(request.fd) };
+        Ok(Self { fd })
+    }
+
+    /// Read whether the line is active
+    ///
+    /// # Errors
+    /// Returns an error if the line cannot be read.
+    pub fn is_active(&self) -> io::Result<bool> {
+        let mut values = LineValues { bits: 0, mask: 1 };
+        // SAFETY: the descriptor is open and `values` outlives the call
+        let result = unsafe {
+            libc::ioctl(
+                self.fd.as_raw_fd(),
+                GPIO_V2_LINE_GET_VALUES_IOCTL as _,
+                &mut values,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(values.bits & 1 != 0)
+    }
+}
+
+/// Debounces the line and fires once per activation
+#[derive(Debug, Default)]
+pub struct Debouncer {
+    active_since: Option<Instant>,
+    fired: bool,
+}
+
+impl Debouncer {
+    /// Update with the line state at `now`
+    ///
+    /// Returns true once the line has been active for `debounce`, and not
+    /// again until it has been released.
+    pub fn update(&mut self, active: bool, now: Instant, debounce: Duration) -> bool {
+        if !active {
+            *self = Self::default();
+            return false;
+        }
+        let since = *self.active_since.get_or_insert(now);
+        if self.fired || now.duration_since(since) < debounce {
+            return false;
+        }
+        self.fired = true;
+        true
+    }
+}
+
+/// Watch the button and request a shutdown when it is pressed
+///
+/// # Errors
+/// Returns an error if the line cannot be requested or read.
+pub async fn run(
+    device: DeviceHandle,
+    events: EventBus,
+    config: GpioButtonConfig,
+) -> anyhow::Result<()> {
+    let pin = config
+        .pin
+        .ok_or_else(|| anyhow::anyhow!("gpio-button.pin is not set"))?;
+    let line = InputLine::request(&config.chip, pin, config.bias, config.active_low)
+        .with_context(|| format!("Cannot request GPIO {} on {}", pin, config.chip.display()))?;
+    let debounce = Duration::from_secs_f64(config.debounce);
+
+    let mut debouncer = Debouncer::default();
+    let mut ticker = interval(POLL_INTERVAL);
+    loop {
+        ticker.tick().await;
+        if !debouncer.update(line.is_active()?, Instant::now(), debounce) {
+            continue;
+        }
+
+        warn!(
+            "Shutdown button on GPIO {} pressed, requesting shutdown",
+            pin
+        );
+        events.publish(DaemonEvent::Alert(Alert::new(
+            AlertKind::ShutdownInitiated,
+            format!("Shutdown button on GPIO {} pressed", pin),
+        )));
+        match device.run(|device| device.request_shutdown()).await {
+            Ok(()) => info!("Shutdown requested"),
+            Err(e) => warn!("Failed to request shutdown: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debouncer() {
+        let debounce = Duration::from_millis(100);
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut debouncer = Debouncer::default();
+
+        // A bounce shorter than the debounce time
+        assert!(!debouncer.update(true, at(0), debounce));
+        assert!(!debouncer.update(false, at(20), debounce));
+
+        assert!(!debouncer.update(true, at(40), debounce));
+        assert!(!debouncer.update(true, at(120), debounce));
+        assert!(debouncer.update(true, at(140), debounce));
+        // Held down
+        assert!(!debouncer.update(true, at(1000), debounce));
+
+        // Released and pressed again
+        assert!(!debouncer.update(false, at(1020), debounce));
+        assert!(!debouncer.update(true, at(1040), debounce));
+        assert!(debouncer.update(true, at(1140), debounce));
+    }
+
+    #[test]
+    fn test_request_not_a_chip() {
+        assert!(InputLine::request(Path::new("/dev/null"), 17, GpioBias::PullUp, true).is_err());
+        assert!(
+            InputLine::request(
+                Path::new("/nonexistent/gpiochip"),
+                17,
+                GpioBias::PullUp,
+                true
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,471 @@
+//! Health checks
+//!
+//! Unattended systems, such as boats left on their own, should recover from
+//! hung navigation software or a wedged USB device without someone on
+//! board. Each entry of `health-checks` probes one thing (a command, an
+//! HTTP endpoint, a TCP port, a systemd unit or the age of a file) on its
+//! own interval. After the configured number of consecutive failures, the
+//! daemon takes the check's action once, until the check passes again:
+//! log, raise an alert, power-cycle a USB port, reboot, or suspend all
+//! access to the controller. Every I2C transfer feeds the HALPI2 watchdog,
+//! so the latter lets the watchdog expire and the controller power-cycles
+//! the system, which also recovers from a wedged kernel.
+//!
+//! All checks are evaluated by one task. Maintenance mode holds off the
+//! disruptive actions, so services can be stopped for work on the system.
+
+use std::path::Path;
+use std::process::Stdio;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result, bail};
+use tokio::net::TcpStream;
+use tokio::process::Command;
+use tokio::time::{Duration, Instant, sleep, sleep_until, timeout};
+use tracing::{error, info, warn};
+
+use halpi_common::config::{HealthAction, HealthCheckConfig, HealthProbe};
+use halpi_common::events::{Alert, AlertKind, DaemonEvent};
+
+use crate::daemon::power;
+use crate::dbus::{self, Connection, Message, Value};
+use crate::events::EventBus;
+use crate::http_client::HttpClient;
+use crate::i2c::DeviceHandle;
+use crate::logind;
+use crate::state_machine::StatusHandle;
+
+/// How long a USB port stays off when power-cycled
+const USB_OFF_TIME: Duration = Duration::from_secs(2);
+
+const SYSTEMD_BUS_NAME: &str = "org.freedesktop.systemd1";
+
+const SYSTEMD_PATH: &str = "/org/freedesktop/systemd1";
+
+const SYSTEMD_MANAGER_INTERFACE: &str = "org.freedesktop.systemd1.Manager";
+
+const SYSTEMD_UNIT_INTERFACE: &str = "org.freedesktop.systemd1.Unit";
+
+/// What a check result means for the check's action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    /// The first failure after passing
+    Failing,
+    /// Enough consecutive failures to take the action
+    Act,
+    /// Passing again after failing; `acted` if the action was taken
+    Recovered { acted: bool },
+}
+
+/// Consecutive failures of a check
+#[derive(Debug, Default)]
+pub struct Failures {
+    count: u32,
+    acted: bool,
+}
+
+impl Failures {
+    /// Record a result, given the failures needed for the action
+    ///
+    /// [`Transition::Act`] is returned for every failure from the
+    /// `threshold`th on until [`acted`](Self::acted) is called.
+    pub fn record(&mut self, passed: bool, threshold: u32) -> Option<Transition> {
+        if passed {
+            let failed = self.count > 0;
+            let acted = self.acted;
+            *self = Self::default();
+            return failed.then_some(Transition::Recovered { acted });
+        }
+        self.count = self.count.saturating_add(1);
+        if self.count >= threshold && !self.acted {
+            Some(Transition::Act)
+        } else if self.count == 1 {
+            Some(Transition::Failing)
+        } else {
+            None
+        }
+    }
+
+    /// The action has been taken for the current failures
+    pub fn acted(&mut self) {
+        self.acted = true;
+    }
+
+    /// Consecutive failures so far
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+}
+
+/// A configured check and its schedule
+struct Check {
+    config: HealthCheckConfig,
+    client: HttpClient,
+    due: Instant,
+    failures: Failures,
+    /// Whether holding off the action in maintenance mode was logged
+    held_off: bool,
+}
+
+/// What to do after a check's action
+enum Outcome {
+    Continue,
+    /// The system is going down; stop checking
+    Stop,
+}
+
+/// Run the configured health checks
+///
+/// Returns once an action has taken the system down. When `offline`, with a
+/// simulated or replayed controller, the reboot action is only logged.
+pub async fn run(
+    device: DeviceHandle,
+    events: EventBus,
+    status: StatusHandle,
+    configs: Vec<HealthCheckConfig>,
+    use_logind: bool,
+    offline: bool,
+) -> Result<()> {
+    let start = Instant::now();
+    let mut checks: Vec<Check> = configs
+        .into_iter()
+        .map(|config| Check {
+            client: HttpClient::new(Duration::from_secs_f64(config.timeout)),
+            config,
+            due: start,
+            failures: Failures::default(),
+            held_off: false,
+        })
+        .collect();
+    if checks.is_empty() {
+        return Ok(());
+    }
+
+    loop {
+        let due = checks.iter().map(|check| check.due).min().unwrap_or(start);
+        sleep_until(due).await;
+
+        for check in checks
+            .iter_mut()
+            .filter(|check| check.due <= Instant::now())
+        {
+            check.due = Instant::now() + Duration::from_secs_f64(check.config.interval);
+            let result = probe_with_timeout(&check.config, &check.client).await;
+            let name = &check.config.name;
+            let transition = check.failures.record(result.is_ok(), check.config.failures);
+            match (transition, &result) {
+                (Some(Transition::Failing), Err(e)) => {
+                    warn!(check = %name, "Health check {} failed: {:#}", name, e);
+                }
+                (Some(Transition::Recovered { acted }), _) => {
+                    info!(check = %name, "Health check {} passes again", name);
+                    if acted && check.config.action != HealthAction::Log {
+                        events.publish(DaemonEvent::Alert(Alert::new(
+                            AlertKind::HealthCheckRecovered,
+                            format!("Health check {} passes again", name),
+                        )));
+                    }
+                    check.held_off = false;
+                }
+                (Some(Transition::Act), Err(e)) => {
+                    let action = check.config.action;
+                    if action.is_disruptive() && status.maintenance_until().is_some() {
+                        if !check.held_off {
+                            info!(
+                                check = %name,
+                                "Health check {} failing, no {} in maintenance mode",
+                                name,
+                                action.name()
+                            );
+                            check.held_off = true;
+                        }
+                        continue;
+                    }
+                    check.failures.acted();
+                    let message = format!(
+                        "Health check {} failed {} times ({:#}), action {}",
+                        name,
+                        check.failures.count(),
+                        e,
+                        action.name()
+                    );
+                    error!(check = %name, "{}", message);
+                    if action != HealthAction::Log {
+                        events.publish(DaemonEvent::Alert(Alert::new(
+                            AlertKind::HealthCheckFailed,
+                            message,
+                        )));
+                    }
+                    if let Outcome::Stop = act(&device, &check.config, use_logind, offline).await {
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Take the action of a failing check
+async fn act(
+    device: &DeviceHandle,
+    config: &HealthCheckConfig,
+    use_logind: bool,
+    offline: bool,
+) -> Outcome {
+    match config.action {
+        HealthAction::Log | HealthAction::Alert => Outcome::Continue,
+        HealthAction::Reboot if offline => {
+            warn!("Dry-run mode: not rebooting without a real controller");
+            Outcome::Stop
+        }
+        HealthAction::Reboot => match reboot(use_logind).await {
+            Ok(()) => Outcome::Stop,
+            Err(e) => {
+                error!("Failed to reboot: {:#}", e);
+                Outcome::Continue
+            }
+        },
+        HealthAction::Watchdog => {
+            warn!("Suspending controller access; the HALPI2 watchdog will power-cycle the system");
+            device.suspend();
+            Outcome::Stop
+        }
+        HealthAction::PowerCycleUsb => {
+            if let Some(port) = config.usb_port
+                && let Err(e) = power_cycle_usb(device, port).await
+            {
+                warn!("Failed to power-cycle USB port {}: {}", port, e);
+            }
+            Outcome::Continue
+        }
+    }
+}
+
+/// Reboot through logind, or with `systemctl reboot`
+async fn reboot(use_logind: bool) -> Result<()> {
+    if use_logind {
+        match logind::reboot().await {
+            Ok(()) => return Ok(()),
+            Err(e) => warn!("Failed to reboot through logind: {:#}", e),
+        }
+    }
+    power::spawn_shell("systemctl reboot")
+}
+
+/// Turn USB `port` off and on again, if it is on
+async fn power_cycle_usb(device: &DeviceHandle, port: u8) -> Result<(), crate::i2c::I2cError> {
+    let mask = 1 << port;
+    let ports = device.run(|device| device.get_usb_port_state()).await?;
+    if ports & mask == 0 {
+        info!("USB port {} is off, not power-cycling it", port);
+        return Ok(());
+    }
+    info!("Power-cycling USB port {}", port);
+    device
+        .run(move |device| device.set_usb_port_state(ports & !mask))
+        .await?;
+    sleep(USB_OFF_TIME).await;
+    device
+        .run(move |device| {
+            let ports = device.get_usb_port_state()?;
+            device.set_usb_port_state(ports | mask)
+        })
+        .await
+}
+
+/// Run a check's probe, failing it if it takes longer than its timeout
+async fn probe_with_timeout(config: &HealthCheckConfig, client: &HttpClient) -> Result<()> {
+    let limit = Duration::from_secs_f64(config.timeout);
+    let probe = config.probe().map_err(anyhow::Error::msg)?;
+    timeout(limit, run_probe(probe, client))
+        .await
+        .unwrap_or_else(|_| bail!("no result within {:.1} s", limit.as_secs_f64()))
+}
+
+/// Run a probe; an error describes why it failed
+async fn run_probe(probe: HealthProbe<'_>, client: &HttpClient) -> Result<()> {
+    match probe {
+        HealthProbe::Command(command) => {
+            let status = Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .kill_on_drop(true)
+                .status()
+                .await
+                .context("failed to run command")?;
+            if !status.success() {
+                bail!("command {}", status);
+            }
+        }
+        HealthProbe::Http(url) => {
+            let response = client.get(url).await?;
+            if !response.is_success() {
+                bail!("status {}", response.status);
+            }
+        }
+        HealthProbe::Tcp(address) => {
+            TcpStream::connect(address).await?;
+        }
+        HealthProbe::Unit(unit) => {
+            let state = unit_active_state(unit).await?;
+            if state != "active" && state != "reloading" {
+                bail!("unit is {}", state);
+            }
+        }
+        HealthProbe::FileAge(path, max_age) => {
+            let age = file_age(path).await?;
+            if age.as_secs_f64() > max_age {
+                bail!("last modified {:.0} s ago", age.as_secs_f64());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Time since `path` was last modified
+async fn file_age(path: &Path) -> Result<Duration> {
+    let modified = tokio::fs::metadata(path)
+        .await
+        .with_context(|| format!("cannot read {}", path.display()))?
+        .modified()?;
+    // A modification time in the future counts as fresh
+    Ok(SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default())
+}
+
+/// `ActiveState` of a systemd unit
+async fn unit_active_state(unit: &str) -> Result<String> {
+    let mut conn = Connection::system().await?;
+    let reply = conn
+        .call(
+            Message::method_call(
+                SYSTEMD_BUS_NAME,
+                SYSTEMD_PATH,
+                SYSTEMD_MANAGER_INTERFACE,
+                "GetUnit",
+            )
+            .with_body(vec![Value::Str(unit.into())]),
+        )
+        .await
+        .context("unit not loaded")?;
+    let path = reply
+        .body
+        .first()
+        .and_then(Value::as_str)
+        .context("GetUnit returned no object path")?;
+
+    let reply = conn
+        .call(
+            Message::method_call(SYSTEMD_BUS_NAME, path, dbus::PROPERTIES_INTERFACE, "Get")
+                .with_body(vec![
+                    Value::Str(SYSTEMD_UNIT_INTERFACE.into()),
+                    Value::Str("ActiveState".into()),
+                ]),
+        )
+        .await?;
+    match reply.body.first() {
+        Some(Value::Variant(state)) => state
+            .as_str()
+            .map(str::to_string)
+            .context("ActiveState is not a string"),
+        _ => bail!("ActiveState missing from reply"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failures() {
+        let mut failures = Failures::default();
+        assert_eq!(failures.record(true, 3), None);
+        assert_eq!(failures.record(false, 3), Some(Transition::Failing));
+        assert_eq!(failures.record(false, 3), None);
+        assert_eq!(failures.record(false, 3), Some(Transition::Act));
+        // Until the action has been taken
+        assert_eq!(failures.record(false, 3), Some(Transition::Act));
+        failures.acted();
+        assert_eq!(failures.record(false, 3), None);
+        assert_eq!(
+            failures.record(true, 3),
+            Some(Transition::Recovered { acted: true })
+        );
+
+        assert_eq!(failures.record(false, 1), Some(Transition::Act));
+        assert_eq!(
+            failures.record(true, 1),
+            Some(Transition::Recovered { acted: false })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_probes() {
+        let client = HttpClient::default();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        assert!(run_probe(HealthProbe::Tcp(&address), &client).await.is_ok());
+        drop(listener);
+        assert!(
+            run_probe(HealthProbe::Tcp(&address), &client)
+                .await
+                .is_err()
+        );
+
+        assert!(
+            run_probe(HealthProbe::Command("true"), &client)
+                .await
+                .is_ok()
+        );
+        assert!(
+            run_probe(HealthProbe::Command("exit 3"), &client)
+                .await
+                .is_err()
+        );
+
+        let path = std::env::temp_dir().join(format!("halpid-health-{}", std::process::id()));
+        std::fs::write(&path, b"").unwrap();
+        assert!(
+            run_probe(HealthProbe::FileAge(&path, 60.0), &client)
+                .await
+                .is_ok()
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(
+            run_probe(HealthProbe::FileAge(&path, 60.0), &client)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_probe_timeout() {
+        let config = HealthCheckConfig {
+            name: "slow".into(),
+            command: Some("sleep 10".into()),
+            http: None,
+            tcp: None,
+            unit: None,
+            file: None,
+            max_age: None,
+            interval: 30.0,
+            timeout: 0.1,
+            failures: 1,
+            action: HealthAction::Log,
+            usb_port: None,
+        };
+        let started = std::time::Instant::now();
+        assert!(
+            probe_with_timeout(&config, &HttpClient::default())
+                .await
+                .is_err()
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,133 @@
+//! Hook scripts for power events
+//!
+//! The state machine runs the executable configured for an event in a task
+//! of its own, so a slow script never delays blackout detection or the
+//! polling that feeds the hardware watchdog. The event name is passed in
+//! `HALPID_EVENT`. Scripts that outlive the configured timeout are killed.
+
+use std::path::Path;
+use std::process::Stdio;
+
+use tokio::process::Command;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+use tracing::{info, warn};
+
+use halpi_common::config::{HookEvent, HooksConfig};
+
+/// Start the hook configured for `event`
+///
+/// Returns the task running the hook, which completes when the hook has
+/// exited or was killed, or `None` if no hook is configured.
+pub fn run(config: &HooksConfig, event: HookEvent) -> Option<JoinHandle<()>> {
+    let path = config.path(event)?.to_path_buf();
+    let timeout = Duration::from_secs_f64(config.timeout);
+    Some(tokio::spawn(async move {
+        execute(&path, event, timeout).await;
+    }))
+}
+
+/// Run `path` for `event` and wait for it, at most `timeout`
+async fn execute(path: &Path, event: HookEvent, timeout: Duration) {
+    info!(event = event.name(), "Running hook {}", path.display());
+    let mut child = match Command::new(path)
+        .env("HALPID_EVENT", event.name())
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            warn!(
+                event = event.name(),
+                "Failed to run hook {}: {}",
+                path.display(),
+                e
+            );
+            return;
+        }
+    };
+
+    match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(Ok(status)) if status.success() => {
+            info!(event = event.name(), "Hook {} finished", path.display());
+        }
+        Ok(Ok(status)) => {
+            warn!(
+                event = event.name(),
+                "Hook {} failed: {}",
+                path.display(),
+                status
+            );
+        }
+        Ok(Err(e)) => {
+            warn!(
+                event = event.name(),
+                "Failed to wait for hook {}: {}",
+                path.display(),
+                e
+            );
+        }
+        Err(_) => {
+            warn!(
+                event = event.name(),
+                "Hook {} did not finish within {:.1}s, killing it",
+                path.display(),
+                timeout.as_secs_f64()
+            );
+            let _ = child.kill().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn script(dir: &Path, name: &str, body: &str) -> std::path::PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn test_run_hook() {
+        let dir = std::env::temp_dir().join(format!("halpid-hooks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("event");
+        let config = HooksConfig {
+            pre_shutdown: Some(script(
+                &dir,
+                "pre-shutdown",
+                &format!("echo $HALPID_EVENT > {}", out.display()),
+            )),
+            standby_enter: Some(script(&dir, "standby", "sleep 10")),
+            power_restored: Some(dir.join("missing")),
+            timeout: 0.2,
+            ..Default::default()
+        };
+
+        assert!(run(&config, HookEvent::BlackoutStart).is_none());
+
+        run(&config, HookEvent::PreShutdown).unwrap().await.unwrap();
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "pre-shutdown\n");
+
+        // Killed after the timeout rather than waited for
+        let started = std::time::Instant::now();
+        run(&config, HookEvent::StandbyEnter)
+            .unwrap()
+            .await
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // A missing executable is logged, not fatal
+        run(&config, HookEvent::PowerRestored)
+            .unwrap()
+            .await
+            .unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,252 @@
+//! Outbound HTTP client for exporters and notifiers
+//!
+//! Plain `http://` URLs are handled in-process with hyper. There is no TLS
+//! stack linked into the daemon, so `https://` requests are delegated to the
+//! system `curl` binary. Request headers and body are passed to curl on stdin
+//! so that credentials never show up in the process list.
+
+use anyhow::{Context, Result, bail};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::{Method, Request};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::time::{Duration, timeout};
+
+/// Default timeout for a single request
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Status code and body of a completed request
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: Bytes,
+}
+
+impl HttpResponse {
+    /// True for 2xx responses
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// HTTP client for outbound requests
+#[derive(Clone)]
+pub struct HttpClient {
+    client: Client<HttpConnector, Full<Bytes>>,
+    timeout: Duration,
+}
+
+impl HttpClient {
+    /// Create a client with the given per-request timeout
+    pub fn new(timeout: Duration) -> Self {
+        let client = Client::builder(TokioExecutor::new()).build_http();
+        Self { client, timeout }
+    }
+
+    /// Send a GET request
+    ///
+    /// # Errors
+    /// Returns an error if the request could not be sent or timed out.
+    /// Non-2xx responses are not errors; check [`HttpResponse::is_success`].
+    pub async fn get(&self, url: &str) -> Result<HttpResponse> {
+        self.request(Method::GET, url, &[], Bytes::new()).await
+    }
+
+    /// Send a POST request
+    ///
+    /// # Errors
+    /// Returns an error if the request could not be sent or timed out.
+    /// Non-2xx responses are not errors; check [`HttpResponse::is_success`].
+    pub async fn post(
+        &self,
+        url: &str,
+        headers: &[(&str, String)],
+        body: impl Into<Bytes>,
+    ) -> Result<HttpResponse> {
+        self.request(Method::POST, url, headers, body.into()).await
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        url: &str,
+        headers: &[(&str, String)],
+        body: Bytes,
+    ) -> Result<HttpResponse> {
+        if url.starts_with("https://") {
+            return self.request_curl(method, url, headers, body).await;
+        }
+
+        let mut builder = Request::builder().method(method).uri(url);
+        for (name, value) in headers {
+            builder = builder.header(*name, value);
+        }
+        let req = builder
+            .body(Full::new(body))
+            .context("Failed to build request")?;
+
+        let response = timeout(self.timeout, async {
+            let response = self.client.request(req).await?;
+            let status = response.status().as_u16();
+            let body = response.into_body().collect().await?.to_bytes();
+            anyhow::Ok(HttpResponse { status, body })
+        })
+        .await
+        .context("Request timed out")?
+        .with_context(|| format!("Request to {} failed", url))?;
+
+        Ok(response)
+    }
+
+    async fn request_curl(
+        &self,
+        method: Method,
+        url: &str,
+        headers: &[(&str, String)],
+        body: Bytes,
+    ) -> Result<HttpResponse> {
+        let config = curl_config(&method, url, headers, &body, self.timeout);
+
+        let mut child = Command::new("curl")
+            .arg("--config")
+            .arg("-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .context("Failed to run curl for HTTPS request")?;
+
+        let mut stdin = child.stdin.take().context("curl stdin unavailable")?;
+        stdin.write_all(config.as_bytes()).await?;
+        drop(stdin);
+
+        let output = timeout(
+            self.timeout + Duration::from_secs(1),
+            child.wait_with_output(),
+        )
+        .await
+        .context("Request timed out")??;
+
+        if !output.status.success() {
+            bail!(
+                "Request to {} failed: {}",
+                url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        parse_curl_output(&output.stdout)
+    }
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIMEOUT)
+    }
+}
+
+/// Build a curl config file (see `curl --config`) describing the request
+fn curl_config(
+    method: &Method,
+    url: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+    timeout: Duration,
+) -> String {
+    let mut config = String::new();
+    config.push_str("silent\nshow-error\n");
+    config.push_str(&format!("max-time = {}\n", timeout.as_secs().max(1)));
+    config.push_str(&format!("request = {}\n", quote(method.as_str())));
+    config.push_str(&format!("url = {}\n", quote(url)));
+    config.push_str(&format!("write-out = {}\n", quote("\n%{http_code}")));
+    for (name, value) in headers {
+        config.push_str(&format!(
+            "header = {}\n",
+            quote(&format!("{}: {}", name, value))
+        ));
+    }
+    if !body.is_empty() {
+        config.push_str(&format!(
+            "data-raw = {}\n",
+            quote(&String::from_utf8_lossy(body))
+        ));
+    }
+    config
+}
+
+/// Quote a value for a curl config file
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Split curl output into body and the trailing status code line
+fn parse_curl_output(stdout: &[u8]) -> Result<HttpResponse> {
+    let split = stdout
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .context("Malformed curl output")?;
+    let status = std::str::from_utf8(&stdout[split + 1..])
+        .ok()
+        .and_then(|s| s.trim().parse::<u16>().ok())
+        .context("Malformed curl status code")?;
+
+    Ok(HttpResponse {
+        status,
+        body: Bytes::copy_from_slice(&stdout[..split]),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_escapes() {
+        assert_eq!(quote(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+        assert_eq!(quote("x\ny"), r#""x\ny""#);
+    }
+
+    #[test]
+    fn test_curl_config_contains_headers() {
+        let config = curl_config(
+            &Method::POST,
+            "https://example.com/write",
+            &[("Authorization", "Token abc".to_string())],
+            b"m v=1",
+            Duration::from_secs(5),
+        );
+        assert!(config.contains("url = \"https://example.com/write\"\n"));
+        assert!(config.contains("header = \"Authorization: Token abc\"\n"));
+        assert!(config.contains("data-raw = \"m v=1\"\n"));
+        assert!(config.contains("max-time = 5\n"));
+    }
+
+    #[test]
+    fn test_parse_curl_output() {
+        let response = parse_curl_output(b"{\"ok\":true}\n204").unwrap();
+        assert_eq!(response.status, 204);
+        assert_eq!(&response.body[..], b"{\"ok\":true}");
+        assert!(response.is_success());
+
+        assert!(parse_curl_output(b"no status").is_err());
+    }
+}
@@ -332,6 +332,9 @@ impl HalpiDevice {
         // Commit the update
         self.commit_dfu()?;
 
+        // The controller restarts into the new firmware
+        self.forget_firmware();
+
         Ok(())
     }
 }
@@ -0,0 +1,276 @@
+//! Shared handle to a device that may not be connected yet
+//!
+//! All `HalpiDevice` methods block, sleeping between retries and during
+//! DFU, so the handle runs every operation on tokio's blocking thread pool
+//! ([`DeviceHandle::run`]). A slow or stuck bus then cannot stall the
+//! runtime, including signal handling and the HTTP server.
+//!
+//! The daemon starts even if the HALPI2 controller cannot be reached, for
+//! example when the package is installed before the HAT is attached or the
+//! I2C overlay is enabled. The handle then holds no device until
+//! [`DeviceHandle::reconnect`] manages to open and probe it; until then,
+//! operations fail with [`I2cError::DeviceMissing`].
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use super::device::{DeviceOptions, HalpiDevice, I2cError};
+
+/// Interval between attempts to connect a missing device
+pub const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Cloneable, mutex-protected handle to the controller
+#[derive(Clone)]
+pub struct DeviceHandle {
+    device: Arc<Mutex<Option<HalpiDevice>>>,
+    present: watch::Sender<bool>,
+    /// Set once device access is suspended for good
+    suspended: Arc<AtomicBool>,
+    bus: u8,
+    addr: u8,
+    options: DeviceOptions,
+}
+
+impl DeviceHandle {
+    /// Handle to an opened device
+    pub fn new(device: HalpiDevice) -> Self {
+        let location = (device.bus(), device.addr());
+        Self::from_parts(Some(device), location, DeviceOptions::default())
+    }
+
+    /// Handle to a device that has not been connected yet
+    pub fn missing(bus: u8, addr: u8) -> Self {
+        Self::from_parts(None, (bus, addr), DeviceOptions::default())
+    }
+
+    fn from_parts(
+        device: Option<HalpiDevice>,
+        (bus, addr): (u8, u8),
+        options: DeviceOptions,
+    ) -> Self {
+        let (present, _) = watch::channel(device.is_some());
+        Self {
+            device: Arc::new(Mutex::new(device)),
+            present,
+            suspended: Arc::new(AtomicBool::new(false)),
+            bus,
+            addr,
+            options,
+        }
+    }
+
+    /// Open and probe the device, or return a handle without one
+    ///
+    /// Opening succeeds as soon as the I2C bus exists, so the firmware
+    /// version is read to make sure the controller actually responds.
+    /// `options` also apply to the device opened by
+    /// [`reconnect`](Self::reconnect).
+    pub async fn open(bus: u8, addr: u8, options: DeviceOptions) -> Result<Self, (Self, I2cError)> {
+        let result = {
+            let options = options.clone();
+            tokio::task::spawn_blocking(move || connect(bus, addr, options))
+        }
+        .await
+        .unwrap_or(Err(I2cError::Cancelled));
+        match result {
+            Ok(device) => Ok(Self::from_parts(Some(device), (bus, addr), options)),
+            Err(e) => Err((Self::from_parts(None, (bus, addr), options), e)),
+        }
+    }
+
+    /// I2C bus number
+    pub fn bus(&self) -> u8 {
+        self.bus
+    }
+
+    /// I2C device address
+    pub fn addr(&self) -> u8 {
+        self.addr
+    }
+
+    /// True if the device has been connected
+    pub fn is_present(&self) -> bool {
+        *self.present.borrow()
+    }
+
+    /// Stop all further device access
+    ///
+    /// Every I2C transfer feeds the controller watchdog, so this lets it
+    /// expire and power-cycle the system. Operations fail with
+    /// [`I2cError::Suspended`] from now on; there is no way back.
+    pub fn suspend(&self) {
+        self.suspended.store(true, Ordering::SeqCst);
+    }
+
+    /// True if device access has been suspended
+    pub fn is_suspended(&self) -> bool {
+        self.suspended.load(Ordering::SeqCst)
+    }
+
+    /// True if an operation currently holds the device lock
+    pub fn is_locked(&self) -> bool {
+        self.device.try_lock().is_err()
+    }
+
+    /// Run `operation` with exclusive access to the device
+    ///
+    /// The operation runs on the blocking thread pool; the calling task
+    /// yields until it completes. A panic in the operation is propagated to
+    /// the caller.
+    ///
+    /// # Errors
+    /// Returns `I2cError::DeviceMissing` if the device is not connected, or
+    /// `I2cError::Suspended` once access has been suspended.
+    pub async fn with<T, F>(&self, operation: F) -> Result<T, I2cError>
+    where
+        F: FnOnce(&mut HalpiDevice) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        if self.is_suspended() {
+            return Err(I2cError::Suspended);
+        }
+        let device = self.device.clone();
+        let missing = I2cError::DeviceMissing {
+            bus: self.bus,
+            addr: self.addr,
+        };
+        let result = tokio::task::spawn_blocking(move || {
+            // A panic in an earlier operation leaves the device usable
+            let mut device = device.lock().unwrap_or_else(PoisonError::into_inner);
+            device.as_mut().map(operation).ok_or(missing)
+        })
+        .await;
+        match result {
+            Ok(result) => result,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(_) => Err(I2cError::Cancelled),
+        }
+    }
+
+    /// Run a fallible `operation` with exclusive access to the device
+    ///
+    /// Like [`with`](Self::with), with the operation's own error flattened
+    /// into the result.
+    pub async fn run<T, F>(&self, operation: F) -> Result<T, I2cError>
+    where
+        F: FnOnce(&mut HalpiDevice) -> Result<T, I2cError> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.with(operation).await?
+    }
+
+    /// Wait until the device is connected, then run `operation` on it
+    pub async fn with_present<T, F>(&self, operation: F) -> Result<T, I2cError>
+    where
+        F: FnOnce(&mut HalpiDevice) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.wait_present().await;
+        self.with(operation).await
+    }
+
+    /// Wait until the device is connected
+    pub async fn wait_present(&self) {
+        let mut present = self.present.subscribe();
+        // The sender lives in self, so the channel cannot close while waiting
+        let _ = present.wait_for(|present| *present).await;
+    }
+
+    /// Retry connecting a missing device until it responds
+    ///
+    /// Returns immediately if the device is already connected.
+    pub async fn reconnect(&self, interval: Duration) {
+        let mut attempts = 0u32;
+        while !self.is_present() {
+            let (bus, addr, options) = (self.bus, self.addr, self.options.clone());
+            let device = self.device.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                let connected = connect(bus, addr, options)?;
+                *device.lock().unwrap_or_else(PoisonError::into_inner) = Some(connected);
+                Ok::<_, I2cError>(())
+            })
+            .await;
+            match result {
+                Ok(Ok(())) => {
+                    self.present.send_replace(true);
+                    info!(
+                        "Connected to HALPI2 controller at bus {}, address 0x{:02X}",
+                        bus, addr
+                    );
+                    return;
+                }
+                Ok(Err(e)) => {
+                    attempts += 1;
+                    // Log the first failure only; retries are expected
+                    if attempts == 1 {
+                        warn!(
+                            "Waiting for HALPI2 controller, retrying every {} s: {}",
+                            interval.as_secs(),
+                            e
+                        );
+                    }
+                }
+                Err(e) => warn!("Device connection attempt failed: {}", e),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+/// Open the device and check that the controller responds
+fn connect(bus: u8, addr: u8, options: DeviceOptions) -> Result<HalpiDevice, I2cError> {
+    let mut device = HalpiDevice::new(bus, addr)?;
+    device.set_pec(options.pec);
+    device.set_recorder(options.record);
+    device.firmware_version()?;
+    Ok(device)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_missing_device() {
+        let handle = DeviceHandle::missing(250, 0x6D);
+        assert!(!handle.is_present());
+        assert!(!handle.is_locked());
+        assert!(matches!(
+            handle.run(|device| device.get_power_state()).await,
+            Err(I2cError::DeviceMissing {
+                bus: 250,
+                addr: 0x6D
+            })
+        ));
+        assert!(matches!(
+            handle.with(|device| device.stats().clone()).await,
+            Err(I2cError::DeviceMissing { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_suspend() {
+        let handle = DeviceHandle::missing(250, 0x6D);
+        handle.clone().suspend();
+        assert!(handle.is_suspended());
+        assert!(matches!(
+            handle.run(|device| device.get_power_state()).await,
+            Err(I2cError::Suspended)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_open_without_hardware() {
+        // Bus 250 does not exist
+        let Err((handle, e)) = DeviceHandle::open(250, 0x6D, DeviceOptions::default()).await else {
+            panic!("opening a nonexistent bus succeeded");
+        };
+        assert_eq!(e.kind(), "device_open");
+        assert_eq!((handle.bus(), handle.addr()), (250, 0x6D));
+        assert!(!handle.is_present());
+    }
+}
@@ -0,0 +1,135 @@
+//! Cached controller identity
+//!
+//! Hardware version, firmware version and device ID do not change while the
+//! daemon runs, except when new firmware is flashed. They are read once, on
+//! first use after the controller is connected, and served from the cache
+//! until [`IdentityCache::invalidate`] is called after a firmware update.
+
+use std::sync::{Arc, PoisonError, RwLock};
+
+use halpi_common::protocol::Capabilities;
+use halpi_common::types::Version;
+
+use super::device::I2cError;
+use super::handle::DeviceHandle;
+
+/// Device ID reported when it cannot be read
+pub const UNKNOWN_DEVICE_ID: &str = "0000000000000000";
+
+/// Static identity of the controller
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    pub hardware_version: Version,
+    pub firmware_version: Version,
+    pub device_id: String,
+}
+
+impl DeviceIdentity {
+    /// Identity reported when the controller cannot be read
+    pub fn unknown() -> Self {
+        Self {
+            hardware_version: Version::from_bytes([255, 0, 0, 0]),
+            firmware_version: Version::from_bytes([255, 0, 0, 0]),
+            device_id: UNKNOWN_DEVICE_ID.to_string(),
+        }
+    }
+
+    /// Features of the controller's hardware and firmware
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            hardware: self.hardware_version.clone(),
+            firmware: self.firmware_version.clone(),
+        }
+    }
+}
+
+/// Shared, lazily filled identity cache
+#[derive(Clone, Default)]
+pub struct IdentityCache {
+    identity: Arc<RwLock<Option<DeviceIdentity>>>,
+}
+
+impl IdentityCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cached identity, if it has been read
+    pub fn cached(&self) -> Option<DeviceIdentity> {
+        self.identity
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Cached identity, reading it from `device` on first use
+    ///
+    /// A failed read is not cached, so the next call tries again.
+    pub async fn get(&self, device: &DeviceHandle) -> Result<DeviceIdentity, I2cError> {
+        if let Some(identity) = self.cached() {
+            return Ok(identity);
+        }
+        let identity = device
+            .run(|device| {
+                Ok(DeviceIdentity {
+                    hardware_version: device.get_hardware_version()?,
+                    firmware_version: device.get_firmware_version()?,
+                    device_id: device.get_device_id()?,
+                })
+            })
+            .await?;
+        self.store(identity.clone());
+        Ok(identity)
+    }
+
+    fn store(&self, identity: DeviceIdentity) {
+        *self
+            .identity
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Some(identity);
+    }
+
+    /// Forget the cached identity, e.g. after a firmware update
+    pub fn invalidate(&self) {
+        *self
+            .identity
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cache_and_invalidate() {
+        let cache = IdentityCache::new();
+        assert_eq!(cache.cached(), None);
+
+        // Missing device: nothing is cached
+        let device = DeviceHandle::missing(250, 0x6D);
+        assert!(cache.get(&device).await.is_err());
+        assert_eq!(cache.cached(), None);
+
+        let identity = DeviceIdentity {
+            hardware_version: Version::new(1, 0, 0),
+            firmware_version: Version::new(3, 1, 0),
+            device_id: "0123456789abcdef".to_string(),
+        };
+        cache.store(identity.clone());
+        // Served from the cache without touching the device
+        assert_eq!(cache.get(&device).await.unwrap(), identity);
+
+        cache.invalidate();
+        assert_eq!(cache.cached(), None);
+    }
+
+    #[test]
+    fn test_unknown_identity() {
+        let identity = DeviceIdentity::unknown();
+        assert_eq!(identity.hardware_version.to_string(), "N/A");
+        assert_eq!(identity.firmware_version.to_string(), "N/A");
+        assert_eq!(identity.device_id, UNKNOWN_DEVICE_ID);
+    }
+}
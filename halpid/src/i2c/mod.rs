@@ -6,4 +6,24 @@ pub mod device;
 
 pub mod dfu;
 
-pub use device::HalpiDevice;
+pub mod handle;
+
+pub mod identity;
+
+pub mod record;
+
+pub mod scan;
+
+pub mod scenario;
+
+pub mod sim;
+
+pub mod stats;
+
+pub use device::{DeviceOptions, HalpiDevice, I2cError};
+pub use handle::DeviceHandle;
+pub use identity::{DeviceIdentity, IdentityCache};
+pub use record::{Recorder, Replay};
+pub use scenario::Scenario;
+pub use sim::Simulator;
+pub use stats::I2cStats;
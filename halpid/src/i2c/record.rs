@@ -0,0 +1,374 @@
+//! Recording and replay of register transfers
+//!
+//! `halpid --record FILE` appends every transfer to the controller, with
+//! its bytes and outcome, to FILE as JSON lines:
+//!
+//! ```json
+//! {"t":12.503,"bus":1,"addr":109,"op":"read","reg":21,"data":"04 00"}
+//! {"t":12.61,"bus":1,"addr":109,"op":"write","reg":18,"data":"27 10","errno":121}
+//! ```
+//!
+//! `t` is seconds since the recording started and `data` holds the bytes
+//! after the register number, including a PEC byte. `halpid --replay FILE`
+//! serves the transfers back instead of a controller, so an interaction
+//! recorded in the field runs the same way on a developer machine.
+//!
+//! A replay serves each transfer from the next recorded transfer of the
+//! same kind, register and length, skipping the ones in between: requests
+//! to the API interleave differently on every run, but the values follow
+//! the recording in order. Replays are paced to the recorded times, so the
+//! state machine sees blackouts last as long as they did.
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use i2cdev::linux::LinuxI2CError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Errno of a transfer with no recorded counterpart
+const NO_RECORD: i32 = libc::EREMOTEIO;
+
+/// Direction of a recorded transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    Read,
+    Write,
+}
+
+impl Op {
+    fn as_str(self) -> &'static str {
+        match self {
+            Op::Read => "read",
+            Op::Write => "write",
+        }
+    }
+}
+
+/// A recorded register transfer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transaction {
+    /// Seconds since the recording started
+    pub t: f64,
+    pub bus: u8,
+    pub addr: u8,
+    pub op: Op,
+    pub reg: u8,
+    /// Bytes read or written after the register number; empty for a
+    /// failed read
+    #[serde(with = "hex_bytes")]
+    pub data: Vec<u8>,
+    /// Errno of a failed transfer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub errno: Option<i32>,
+}
+
+/// Bytes as space-separated hex, like the I2C trace events
+mod hex_bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        serializer.serialize_str(&hex.join(" "))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        String::deserialize(deserializer)?
+            .split_whitespace()
+            .map(|byte| u8::from_str_radix(byte, 16).map_err(serde::de::Error::custom))
+            .collect()
+    }
+}
+
+/// Errno of a failed transfer
+fn errno(error: &LinuxI2CError) -> i32 {
+    match error {
+        LinuxI2CError::Errno(errno) => *errno,
+        LinuxI2CError::Io(error) => error.raw_os_error().unwrap_or(libc::EIO),
+    }
+}
+
+/// Cloneable handle to a recording file
+///
+/// Lines are written as the transfers happen, so a recording survives a
+/// crash of the daemon.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    file: Arc<Mutex<LineWriter<File>>>,
+    start: Instant,
+}
+
+impl Recorder {
+    /// Start a recording, appending to `path`
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be opened.
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open recording {}", path.display()))?;
+        Ok(Self {
+            file: Arc::new(Mutex::new(LineWriter::new(file))),
+            start: Instant::now(),
+        })
+    }
+
+    /// Record a transfer of `data` and its outcome
+    pub(super) fn record(
+        &self,
+        (bus, addr): (u8, u8),
+        op: Op,
+        reg: u8,
+        data: &[u8],
+        result: &Result<(), LinuxI2CError>,
+    ) {
+        let failed_read = op == Op::Read && result.is_err();
+        let transaction = Transaction {
+            t: (self.start.elapsed().as_secs_f64() * 1000.0).round() / 1000.0,
+            bus,
+            addr,
+            op,
+            reg,
+            data: if failed_read {
+                Vec::new()
+            } else {
+                data.to_vec()
+            },
+            errno: result.as_ref().err().map(errno),
+        };
+        let mut line = serde_json::to_string(&transaction).unwrap_or_default();
+        line.push('\n');
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            tracing::debug!("Failed to record I2C transfer: {}", e);
+        }
+    }
+}
+
+/// Counts of the transfers of a replay
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayProgress {
+    /// Transfers served from the recording
+    pub served: usize,
+    /// Recorded transfers skipped to find a match
+    pub skipped: usize,
+    /// Writes whose bytes differ from the recorded ones
+    pub mismatched: usize,
+    /// Transfers with no recorded counterpart left
+    pub unmatched: usize,
+    /// Recorded transfers not served yet
+    pub remaining: usize,
+}
+
+#[derive(Debug)]
+struct Playback {
+    transactions: VecDeque<Transaction>,
+    paced: bool,
+    /// Time of the first transfer, and its recorded time
+    start: Option<(Instant, f64)>,
+    progress: ReplayProgress,
+}
+
+impl Playback {
+    /// Take the next recorded transfer of `op` on `reg` whose data has
+    /// `len` bytes (any, for writes)
+    fn take(&mut self, addr: u8, op: Op, reg: u8, len: Option<usize>) -> Option<Transaction> {
+        let index = self.transactions.iter().position(|transaction| {
+            transaction.addr == addr
+                && transaction.op == op
+                && transaction.reg == reg
+                && (transaction.errno.is_some()
+                    || len.is_none_or(|len| transaction.data.len() == len))
+        });
+        let Some(index) = index else {
+            self.progress.unmatched += 1;
+            if self.progress.unmatched == 1 {
+                tracing::warn!(
+                    "Replay has no recorded {} of register 0x{:02X} left",
+                    op.as_str(),
+                    reg
+                );
+            }
+            return None;
+        };
+        self.progress.skipped += index;
+        self.progress.served += 1;
+        let transaction = self.transactions.drain(..=index).next_back()?;
+        if self.paced {
+            let (start, recorded) = *self.start.get_or_insert((Instant::now(), transaction.t));
+            let due = start + Duration::from_secs_f64((transaction.t - recorded).max(0.0));
+            thread::sleep(due.saturating_duration_since(Instant::now()));
+        }
+        Some(transaction)
+    }
+}
+
+/// Cloneable handle to a recording being replayed
+#[derive(Debug, Clone)]
+pub struct Replay {
+    playback: Arc<Mutex<Playback>>,
+}
+
+impl Replay {
+    /// Replay `transactions`, paced to their recorded times or as fast as
+    /// they are requested
+    pub fn new(transactions: Vec<Transaction>, paced: bool) -> Self {
+        Self {
+            playback: Arc::new(Mutex::new(Playback {
+                transactions: transactions.into(),
+                paced,
+                start: None,
+                progress: ReplayProgress::default(),
+            })),
+        }
+    }
+
+    /// Load a recording made with `--record`
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or a line is not a
+    /// transaction.
+    pub fn load(path: &Path, paced: bool) -> anyhow::Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open recording {}", path.display()))?;
+        let mut transactions = Vec::new();
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.with_context(|| format!("Failed to read {}", path.display()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let transaction = serde_json::from_str(&line).with_context(|| {
+                format!("Invalid transaction at {}:{}", path.display(), number + 1)
+            })?;
+            transactions.push(transaction);
+        }
+        Ok(Self::new(transactions, paced))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Playback> {
+        self.playback.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Counts of the transfers served so far
+    pub fn progress(&self) -> ReplayProgress {
+        let playback = self.lock();
+        ReplayProgress {
+            remaining: playback.transactions.len(),
+            ..playback.progress
+        }
+    }
+
+    /// Serve a register read of `buffer.len()` bytes at `reg`, including
+    /// the PEC byte if any
+    pub(super) fn read(&self, addr: u8, reg: u8, buffer: &mut [u8]) -> Result<(), LinuxI2CError> {
+        let transaction = self
+            .lock()
+            .take(addr, Op::Read, reg, Some(buffer.len()))
+            .ok_or(LinuxI2CError::Errno(NO_RECORD))?;
+        if let Some(errno) = transaction.errno {
+            return Err(LinuxI2CError::Errno(errno));
+        }
+        buffer.copy_from_slice(&transaction.data);
+        Ok(())
+    }
+
+    /// Serve a register write of `data`, the register followed by the
+    /// values
+    pub(super) fn write(&self, addr: u8, data: &[u8]) -> Result<(), LinuxI2CError> {
+        let Some((&reg, values)) = data.split_first() else {
+            return Err(LinuxI2CError::Errno(NO_RECORD));
+        };
+        let mut playback = self.lock();
+        let transaction = playback
+            .take(addr, Op::Write, reg, None)
+            .ok_or(LinuxI2CError::Errno(NO_RECORD))?;
+        if transaction.data != values {
+            playback.progress.mismatched += 1;
+            tracing::debug!(
+                "Replayed write of register 0x{:02X} differs from the recording",
+                reg
+            );
+        }
+        match transaction.errno {
+            Some(errno) => Err(LinuxI2CError::Errno(errno)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(op: Op, reg: u8, data: &[u8], errno: Option<i32>) -> Transaction {
+        Transaction {
+            t: 0.0,
+            bus: 1,
+            addr: 0x6D,
+            op,
+            reg,
+            data: data.to_vec(),
+            errno,
+        }
+    }
+
+    #[test]
+    fn test_transaction_json() {
+        let line = r#"{"t":1.5,"bus":1,"addr":109,"op":"read","reg":21,"data":"04 0A"}"#;
+        let parsed: Transaction = serde_json::from_str(line).unwrap();
+        assert_eq!(parsed.data, [0x04, 0x0A]);
+        assert_eq!(parsed.errno, None);
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), line);
+
+        let failed = transaction(Op::Write, 0x12, &[], Some(121));
+        let json = serde_json::to_string(&failed).unwrap();
+        assert!(json.contains(r#""data":"","errno":121"#));
+        assert_eq!(serde_json::from_str::<Transaction>(&json).unwrap(), failed);
+    }
+
+    #[test]
+    fn test_replay() {
+        let replay = Replay::new(
+            vec![
+                transaction(Op::Read, 0x15, &[0x01], None),
+                transaction(Op::Read, 0x20, &[0x00, 0x10], None),
+                transaction(Op::Read, 0x15, &[], Some(libc::EREMOTEIO)),
+                transaction(Op::Write, 0x17, &[0x80], None),
+                transaction(Op::Read, 0x15, &[0x02], None),
+            ],
+            false,
+        );
+        let mut buffer = [0; 1];
+        replay.read(0x6D, 0x15, &mut buffer).unwrap();
+        assert_eq!(buffer, [0x01]);
+
+        // The voltage read is skipped, the recorded error replayed
+        assert!(matches!(
+            replay.read(0x6D, 0x15, &mut buffer),
+            Err(LinuxI2CError::Errno(libc::EREMOTEIO))
+        ));
+        replay.write(0x6D, &[0x17, 0x40]).unwrap();
+        replay.read(0x6D, 0x15, &mut buffer).unwrap();
+        assert_eq!(buffer, [0x02]);
+        assert!(replay.read(0x6D, 0x15, &mut buffer).is_err());
+
+        assert_eq!(
+            replay.progress(),
+            ReplayProgress {
+                served: 4,
+                skipped: 1,
+                mismatched: 1,
+                unmatched: 1,
+                remaining: 0,
+            }
+        );
+    }
+}
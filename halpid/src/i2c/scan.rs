@@ -0,0 +1,215 @@
+//! I2C bus scan for the HALPI2 controller
+//!
+//! When the configured bus or address does not answer, the daemon scans all
+//! I2C buses for a device that answers the HALPI2 identity registers and
+//! suggests the location it was found at. Each candidate is probed with a
+//! single read of the hardware and firmware version registers, without
+//! retries or statistics, so a scan takes well under a second even on
+//! systems with many adapters.
+//!
+//! Adapters are identified by bus number throughout the daemon;
+//! [`bus_for_path`] maps a device node given by path, such as a udev symlink
+//! for a USB-I2C bridge, to its number.
+
+use std::io;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::Path;
+
+use halpi_common::config::DEFAULT_I2C_ADDR;
+use halpi_common::protocol;
+use halpi_common::types::Version;
+use i2cdev::core::{I2CMessage, I2CTransfer};
+use i2cdev::linux::{LinuxI2CDevice, LinuxI2CMessage};
+
+/// A device that answered the identity registers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Detected {
+    pub bus: u8,
+    pub addr: u8,
+    pub hardware_version: Version,
+    pub firmware_version: Version,
+}
+
+/// Bus numbers of all I2C adapters in `/dev`, in ascending order
+pub fn buses() -> Vec<u8> {
+    let Ok(entries) = std::fs::read_dir("/dev") else {
+        return Vec::new();
+    };
+    let mut buses: Vec<u8> = entries
+        .filter_map(|entry| bus_number(&entry.ok()?.file_name().to_string_lossy()))
+        .collect();
+    buses.sort_unstable();
+    buses
+}
+
+/// Parse the bus number from a device name such as `i2c-1`
+fn bus_number(name: &str) -> Option<u8> {
+    name.strip_prefix("i2c-")?.parse().ok()
+}
+
+/// Character device major number of `i2c-dev` nodes
+const I2C_DEV_MAJOR: u32 = 89;
+
+/// Bus number of the I2C device node at `path`
+///
+/// Symlinks are followed. The bus number is the node's minor number, so
+/// this also works for nodes that are not named `i2c-N`.
+///
+/// # Errors
+/// Returns an error if `path` does not exist or is not an I2C device node.
+pub fn bus_for_path(path: &Path) -> io::Result<u8> {
+    let metadata = std::fs::metadata(path)?;
+    let rdev = metadata.rdev();
+    if !metadata.file_type().is_char_device() || libc::major(rdev) != I2C_DEV_MAJOR {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not an I2C device node", path.display()),
+        ));
+    }
+    u8::try_from(libc::minor(rdev)).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} has an unsupported bus number", path.display()),
+        )
+    })
+}
+
+/// Addresses to probe: the configured one and the HALPI2 default
+pub fn candidate_addresses(configured: u8) -> Vec<u8> {
+    let mut addrs = vec![configured];
+    if configured != DEFAULT_I2C_ADDR {
+        addrs.push(DEFAULT_I2C_ADDR);
+    }
+    addrs
+}
+
+/// Probe `addrs` on every I2C bus
+///
+/// Blocks for the duration of the scan; call it from the blocking thread
+/// pool.
+pub fn scan(addrs: &[u8]) -> Vec<Detected> {
+    buses()
+        .into_iter()
+        .flat_map(|bus| addrs.iter().filter_map(move |&addr| probe(bus, addr)))
+        .collect()
+}
+
+/// Read the identity registers at `bus`/`addr`, if a controller answers
+fn probe(bus: u8, addr: u8) -> Option<Detected> {
+    let path = format!("/dev/i2c-{}", bus);
+    let mut device = LinuxI2CDevice::new(&path, addr as u16).ok()?;
+    let hardware = read_version(&mut device, addr, protocol::REG_HARDWARE_VERSION)?;
+    let firmware = read_version(&mut device, addr, protocol::REG_FIRMWARE_VERSION)?;
+    looks_like_controller(hardware, firmware).then(|| Detected {
+        bus,
+        addr,
+        hardware_version: Version::from_bytes(hardware),
+        firmware_version: Version::from_bytes(firmware),
+    })
+}
+
+/// Read a 4-byte version register in a single transfer
+fn read_version(device: &mut LinuxI2CDevice, addr: u8, reg: u8) -> Option<[u8; 4]> {
+    let write_data = [reg];
+    let mut bytes = [0u8; 4];
+    let mut messages = [
+        LinuxI2CMessage::write(&write_data).with_address(addr as u16),
+        LinuxI2CMessage::read(&mut bytes).with_address(addr as u16),
+    ];
+    device.transfer(&mut messages).ok()?;
+    Some(bytes)
+}
+
+/// True if the version registers hold plausible HALPI2 versions
+///
+/// Other devices usually answer with constant bytes (a pulled-up bus reads
+/// `0xFF`, cleared registers `0x00`). HALPI2 firmware versions start at 1.
+fn looks_like_controller(hardware: [u8; 4], firmware: [u8; 4]) -> bool {
+    let constant = |bytes: [u8; 4]| bytes.iter().all(|b| *b == bytes[0]);
+    !constant(hardware) && !constant(firmware) && firmware[0] != 0 && firmware[0] != 0xFF
+}
+
+/// Settings to change if the controller answers somewhere other than the
+/// configured bus and address
+pub fn suggestion(detected: &[Detected], bus: u8, addr: u8) -> Option<String> {
+    let found = detected.iter().find(|d| (d.bus, d.addr) != (bus, addr))?;
+    let mut settings = Vec::new();
+    if found.bus != bus {
+        settings.push(format!("i2c-bus: {}", found.bus));
+    }
+    if found.addr != addr {
+        settings.push(format!("i2c-addr: 0x{:02X}", found.addr));
+    }
+    Some(format!(
+        "HALPI2 controller found at bus {}, address 0x{:02X} (firmware {}); set {} in the configuration",
+        found.bus,
+        found.addr,
+        found.firmware_version,
+        settings.join(" and ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bus_number() {
+        assert_eq!(bus_number("i2c-1"), Some(1));
+        assert_eq!(bus_number("i2c-22"), Some(22));
+        assert_eq!(bus_number("i2c-dev"), None);
+        assert_eq!(bus_number("tty1"), None);
+    }
+
+    #[test]
+    fn test_bus_for_path_rejects_other_files() {
+        assert_eq!(
+            bus_for_path(Path::new("/dev/null")).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            bus_for_path(Path::new("/nonexistent/i2c-1"))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_candidate_addresses() {
+        assert_eq!(
+            candidate_addresses(DEFAULT_I2C_ADDR),
+            vec![DEFAULT_I2C_ADDR]
+        );
+        assert_eq!(candidate_addresses(0x6E), vec![0x6E, DEFAULT_I2C_ADDR]);
+    }
+
+    #[test]
+    fn test_looks_like_controller() {
+        assert!(looks_like_controller([0, 3, 0, 255], [3, 1, 0, 255]));
+        assert!(!looks_like_controller([255; 4], [255; 4]));
+        assert!(!looks_like_controller([0; 4], [0; 4]));
+        assert!(!looks_like_controller([0, 3, 0, 255], [0, 0, 1, 255]));
+    }
+
+    #[test]
+    fn test_suggestion() {
+        let detected = |bus, addr| Detected {
+            bus,
+            addr,
+            hardware_version: Version::from_bytes([0, 3, 0, 255]),
+            firmware_version: Version::from_bytes([3, 1, 0, 255]),
+        };
+
+        // Found where configured: nothing to suggest
+        assert_eq!(suggestion(&[detected(1, 0x6D)], 1, 0x6D), None);
+        assert_eq!(suggestion(&[], 1, 0x6D), None);
+
+        let hint = suggestion(&[detected(3, 0x6D)], 1, 0x6D).unwrap();
+        assert!(hint.contains("bus 3, address 0x6D"));
+        assert!(hint.contains("set i2c-bus: 3 in"));
+
+        let hint = suggestion(&[detected(3, 0x6D)], 1, 0x6E).unwrap();
+        assert!(hint.contains("set i2c-bus: 3 and i2c-addr: 0x6D in"));
+    }
+}
@@ -0,0 +1,166 @@
+//! Scenarios played back by the simulated controller
+//!
+//! A scenario is a YAML file of steps, each applied `at` seconds after the
+//! simulator starts. A step sets V_in, the supercap voltage or a
+//! temperature, or injects a [`Fault`] as `POST /debug/sim` does; values
+//! hold until a later step changes them, and the supercap charges and
+//! discharges from the value it was set to. Since the steps are applied at
+//! their scheduled time whenever the controller is read, a scenario plays
+//! back the same way on every run:
+//!
+//! ```yaml
+//! steps:
+//!   - at: 5
+//!     v_in: 0.0
+//!   - at: 20
+//!     v_in: 12.0
+//!   - at: 30
+//!     fault: {fault: nak, register: 0x15, count: 2}
+//! ```
+
+use std::path::Path;
+
+use anyhow::Context;
+use halpi_common::protocol;
+use halpi_common::units::ZERO_CELSIUS;
+use serde::Deserialize;
+
+use super::sim::Fault;
+
+/// A scenario file
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    /// Steps in the order of their `at` times
+    pub steps: Vec<Step>,
+}
+
+/// Changes applied at one point of a scenario
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    /// Seconds after the start of the simulation
+    pub at: f64,
+    /// V_in of the power supply (V)
+    #[serde(default)]
+    pub v_in: Option<f32>,
+    /// Supercap voltage (V)
+    #[serde(default)]
+    pub v_supercap: Option<f32>,
+    /// MCU temperature (°C)
+    #[serde(default)]
+    pub t_mcu: Option<f32>,
+    /// PCB temperature (°C)
+    #[serde(default)]
+    pub t_pcb: Option<f32>,
+    /// Fault injected at this point
+    #[serde(default)]
+    pub fault: Option<Fault>,
+}
+
+impl Scenario {
+    /// Load and validate a scenario file
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or parsed, or a step is
+    /// invalid.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read scenario {}", path.display()))?;
+        let scenario: Scenario = serde_yaml::from_str(&text)
+            .with_context(|| format!("Failed to parse scenario {}", path.display()))?;
+        scenario
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid scenario {}: {}", path.display(), e))?;
+        Ok(scenario)
+    }
+
+    /// Check the order of the steps and their values
+    ///
+    /// # Errors
+    /// Returns a description of the first invalid step.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut previous = 0.0;
+        for (index, step) in self.steps.iter().enumerate() {
+            let invalid =
+                |problem: String| format!("step {} (at {} s): {}", index + 1, step.at, problem);
+            if !step.at.is_finite() || step.at < previous {
+                return Err(invalid(
+                    "steps must be in order of non-negative times".to_string(),
+                ));
+            }
+            previous = step.at;
+
+            for (name, value, max) in [
+                ("v_in", step.v_in, protocol::DCIN_MAX),
+                ("v_supercap", step.v_supercap, protocol::VCAP_MAX),
+            ] {
+                if let Some(value) = value
+                    && !(0.0..=max).contains(&value)
+                {
+                    return Err(invalid(format!(
+                        "{} {} is out of range (expected 0-{} volts)",
+                        name, value, max
+                    )));
+                }
+            }
+            let (min, max) = (
+                protocol::TEMP_MIN_KELVIN - ZERO_CELSIUS,
+                protocol::TEMP_MAX_KELVIN - ZERO_CELSIUS,
+            );
+            for (name, value) in [("t_mcu", step.t_mcu), ("t_pcb", step.t_pcb)] {
+                if let Some(value) = value
+                    && !(min..=max).contains(&value)
+                {
+                    return Err(invalid(format!(
+                        "{} {} is out of range (expected {}-{} °C)",
+                        name, value, min, max
+                    )));
+                }
+            }
+            if let Some(fault) = &step.fault {
+                fault.validate().map_err(invalid)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_example_scenario() {
+        let scenario: Scenario =
+            serde_yaml::from_str(include_str!("../../scenarios/blackout.yaml")).unwrap();
+        assert!(scenario.validate().is_ok());
+        assert!(scenario.steps.len() > 1);
+        assert_eq!(scenario.steps[0].at, 0.0);
+    }
+
+    #[test]
+    fn test_steps() {
+        let yaml = "steps:\n  - at: 5\n    v_in: 0\n  - at: 8\n    fault: {fault: nak, count: 2}\n";
+        let scenario: Scenario = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(scenario.steps[0].v_in, Some(0.0));
+        assert_eq!(
+            scenario.steps[1].fault,
+            Some(Fault::Nak {
+                register: None,
+                count: 2
+            })
+        );
+        assert!(scenario.validate().is_ok());
+
+        let mut unordered = scenario.clone();
+        unordered.steps[1].at = 1.0;
+        assert!(unordered.validate().is_err());
+
+        let mut out_of_range = scenario;
+        out_of_range.steps[0].v_supercap = Some(20.0);
+        assert!(out_of_range.validate().unwrap_err().starts_with("step 1"));
+
+        assert!(serde_yaml::from_str::<Scenario>("steps:\n  - at: 1\n    v_out: 5\n").is_err());
+    }
+}
@@ -0,0 +1,1031 @@
+//! Simulated HALPI2 controller
+//!
+//! With `--simulate`, the daemon talks to this model of the controller
+//! firmware instead of `/dev/i2c-N`, so the API, the state machine and the
+//! CLI can run on any machine. The model keeps the registers, charges and
+//! discharges the supercap, and follows V_in into and out of blackouts.
+//! Faults injected with `POST /debug/sim` (see [`Fault`]) exercise the
+//! blackout and error paths on demand, and a [`Scenario`] given with
+//! `--scenario` plays them back on a schedule.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use halpi_common::protocol::{self, DFUState, FLASH_BLOCK_SIZE, Feature};
+use halpi_common::types::{PowerState, Version};
+use halpi_common::units::{Celsius, Volts, ZERO_CELSIUS};
+use i2cdev::linux::LinuxI2CError;
+use serde::{Deserialize, Serialize};
+
+use super::scenario::{Scenario, Step};
+
+/// Errno of a transfer the controller does not acknowledge
+const NAK: i32 = libc::EREMOTEIO;
+
+/// Firmware version reported by the simulated controller
+pub const FIRMWARE_VERSION: Version = Version {
+    major: 3,
+    minor: 3,
+    patch: 0,
+    alpha: 255,
+};
+
+/// Hardware version reported by the simulated controller
+pub const HARDWARE_VERSION: Version = Version {
+    major: 1,
+    minor: 0,
+    patch: 0,
+    alpha: 255,
+};
+
+/// Device ID of the simulated controller, "SIMHALPI" in ASCII
+const DEVICE_ID: [u8; 8] = *b"SIMHALPI";
+
+/// V_in of the simulated power supply
+pub const NOMINAL_V_IN: f32 = 12.0;
+
+/// V_in below which the controller runs on the supercap
+const BLACKOUT_V_IN: f32 = 9.0;
+
+/// Supercap voltage when fully charged
+const SUPERCAP_FULL: f32 = 10.0;
+
+/// Supercap charging and discharging rates, in V/s
+const CHARGE_RATE: f32 = 0.1;
+const DISCHARGE_RATE: f32 = 0.05;
+
+/// Input current while the system runs from V_in, in A
+const OPERATING_CURRENT: f32 = 0.8;
+
+/// Temperatures of the sensors without a ramp, in °C
+const NOMINAL_MCU_TEMPERATURE: f32 = 45.0;
+const NOMINAL_PCB_TEMPERATURE: f32 = 35.0;
+
+/// A fault injected into the simulated controller
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "fault", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Fault {
+    /// V_in drops to `v_in` for `duration` seconds, or until cleared
+    VoltageDrop {
+        v_in: f32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duration: Option<f64>,
+    },
+    /// The next `count` transfers, of `register` or any, are not
+    /// acknowledged
+    Nak {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        register: Option<u8>,
+        #[serde(default = "default_nak_count")]
+        count: u32,
+    },
+    /// The next firmware update fails with `state` once `after_blocks`
+    /// blocks have been written
+    DfuError {
+        state: DfuFailure,
+        #[serde(default)]
+        after_blocks: u16,
+    },
+    /// The temperature of `sensor` ramps linearly to `celsius` over
+    /// `duration` seconds and stays there
+    TemperatureRamp {
+        sensor: Sensor,
+        celsius: f32,
+        duration: f64,
+    },
+    /// Remove all faults and return V_in and the temperatures to nominal
+    Clear,
+}
+
+fn default_nak_count() -> u32 {
+    1
+}
+
+impl Fault {
+    /// Check the fault's parameters
+    ///
+    /// # Errors
+    /// Returns a description of the first invalid parameter.
+    pub fn validate(&self) -> Result<(), String> {
+        let valid_duration = |duration: f64| duration.is_finite() && duration > 0.0;
+        match self {
+            Fault::VoltageDrop { v_in, duration } => {
+                if !(0.0..=protocol::DCIN_MAX).contains(v_in) {
+                    return Err(format!(
+                        "v_in {} is out of range (expected 0-{} volts)",
+                        v_in,
+                        protocol::DCIN_MAX
+                    ));
+                }
+                if duration.is_some_and(|duration| !valid_duration(duration)) {
+                    return Err("duration must be positive".to_string());
+                }
+            }
+            Fault::Nak { count, .. } if *count == 0 => {
+                return Err("count must be at least 1".to_string());
+            }
+            Fault::TemperatureRamp {
+                celsius, duration, ..
+            } => {
+                let (min, max) = (
+                    protocol::TEMP_MIN_KELVIN - ZERO_CELSIUS,
+                    protocol::TEMP_MAX_KELVIN - ZERO_CELSIUS,
+                );
+                if !(min..=max).contains(celsius) {
+                    return Err(format!(
+                        "celsius {} is out of range (expected {}-{})",
+                        celsius, min, max
+                    ));
+                }
+                if !valid_duration(*duration) {
+                    return Err("duration must be positive".to_string());
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// DFU error state of a [`Fault::DfuError`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DfuFailure {
+    CrcError,
+    DataLengthError,
+    WriteError,
+    ProtocolError,
+}
+
+impl DfuFailure {
+    fn state(self) -> DFUState {
+        match self {
+            DfuFailure::CrcError => DFUState::CrcError,
+            DfuFailure::DataLengthError => DFUState::DataLengthError,
+            DfuFailure::WriteError => DFUState::WriteError,
+            DfuFailure::ProtocolError => DFUState::ProtocolError,
+        }
+    }
+}
+
+/// Temperature sensor of a [`Fault::TemperatureRamp`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sensor {
+    Mcu,
+    Pcb,
+}
+
+/// Values of the simulated controller and its active faults
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SimSnapshot {
+    pub dcin_voltage: Volts,
+    pub supercap_voltage: Volts,
+    pub input_current: f32,
+    pub mcu_temperature: Celsius,
+    pub pcb_temperature: Celsius,
+    pub state: PowerState,
+    pub dfu_state: DFUState,
+    pub faults: Vec<Fault>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scenario: Option<ScenarioProgress>,
+}
+
+/// Progress of the scenario being played back
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScenarioProgress {
+    /// Seconds since the start of the scenario
+    pub elapsed: f64,
+    pub steps_left: usize,
+}
+
+/// A scenario being played back
+#[derive(Debug)]
+struct Playback {
+    start: Instant,
+    steps: VecDeque<Step>,
+}
+
+impl Playback {
+    /// Take the next step if it is due at `now`, with the time it was due
+    fn next(&mut self, now: Instant) -> Option<(Instant, Step)> {
+        let at = self.start + Duration::from_secs_f64(self.steps.front()?.at);
+        if at > now {
+            return None;
+        }
+        self.steps.pop_front().map(|step| (at, step))
+    }
+}
+
+/// A temperature on its way to a new value
+#[derive(Debug, Clone, Copy)]
+struct Ramp {
+    from: f32,
+    to: f32,
+    start: Instant,
+    duration: Duration,
+}
+
+impl Ramp {
+    fn value(&self, now: Instant) -> f32 {
+        let progress = (now.saturating_duration_since(self.start).as_secs_f32()
+            / self.duration.as_secs_f32())
+        .min(1.0);
+        self.from + (self.to - self.from) * progress
+    }
+}
+
+/// Registers and physical state of the simulated controller
+#[derive(Debug)]
+struct Controller {
+    /// Time the state was last advanced to
+    updated: Instant,
+    hardware: Version,
+    firmware: Version,
+    state: PowerState,
+    supercap_voltage: f32,
+    temperatures: [f32; 2],
+    ramps: [Option<Ramp>; 2],
+    /// V_in of the power supply, without a voltage drop
+    supply_v_in: f32,
+    /// V_in during a voltage drop, and when the drop ends
+    voltage_drop: Option<(f32, Option<Instant>)>,
+    naks: Vec<(Option<u8>, u32)>,
+    dfu_fault: Option<(DfuFailure, u16)>,
+    scenario: Option<Playback>,
+    raspi_power: u8,
+    watchdog_timeout: u16,
+    /// Time of the last transfer, each of which feeds the watchdog
+    watchdog_fed: Instant,
+    power_on_threshold: f32,
+    solo_power_off_threshold: f32,
+    led_brightness: u8,
+    auto_restart: u8,
+    solo_depleting_timeout: u32,
+    usb_port_state: u8,
+    led_pattern: u8,
+    led_color: [u8; 3],
+    dfu_state: DFUState,
+    dfu_blocks: u16,
+    dfu_total: u16,
+}
+
+impl Controller {
+    fn new(now: Instant) -> Self {
+        Self {
+            updated: now,
+            hardware: HARDWARE_VERSION,
+            firmware: FIRMWARE_VERSION,
+            state: PowerState::OperationalCoOp,
+            supercap_voltage: SUPERCAP_FULL,
+            temperatures: [NOMINAL_MCU_TEMPERATURE, NOMINAL_PCB_TEMPERATURE],
+            ramps: [None; 2],
+            supply_v_in: NOMINAL_V_IN,
+            voltage_drop: None,
+            naks: Vec::new(),
+            dfu_fault: None,
+            scenario: None,
+            raspi_power: 1,
+            watchdog_timeout: 0,
+            watchdog_fed: now,
+            power_on_threshold: 9.0,
+            solo_power_off_threshold: 8.0,
+            led_brightness: 255,
+            auto_restart: 1,
+            solo_depleting_timeout: 0,
+            usb_port_state: 0x0F,
+            led_pattern: 0,
+            led_color: [0, 0, 0],
+            dfu_state: DFUState::Idle,
+            dfu_blocks: 0,
+            dfu_total: 0,
+        }
+    }
+
+    fn dcin_voltage(&self) -> f32 {
+        self.voltage_drop.map_or(self.supply_v_in, |(v_in, _)| v_in)
+    }
+
+    fn input_current(&self) -> f32 {
+        if self.dcin_voltage() < BLACKOUT_V_IN {
+            0.0
+        } else {
+            OPERATING_CURRENT
+        }
+    }
+
+    fn temperature(&self, sensor: Sensor, now: Instant) -> f32 {
+        let index = sensor as usize;
+        self.ramps[index].map_or(self.temperatures[index], |ramp| ramp.value(now))
+    }
+
+    /// Move the simulation forward to `now`, applying the scenario steps
+    /// due on the way at their scheduled times
+    fn advance(&mut self, now: Instant) {
+        while let Some((at, step)) = self
+            .scenario
+            .as_mut()
+            .and_then(|playback| playback.next(now))
+        {
+            self.simulate(at);
+            self.apply(step, at);
+        }
+        self.simulate(now);
+    }
+
+    /// Move the physical state forward to `now`
+    fn simulate(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f32();
+        self.updated = now;
+
+        if let Some((_, Some(until))) = self.voltage_drop
+            && now >= until
+        {
+            self.voltage_drop = None;
+        }
+        for (temperature, slot) in self.temperatures.iter_mut().zip(&mut self.ramps) {
+            if let Some(ramp) = slot
+                && now >= ramp.start + ramp.duration
+            {
+                *temperature = ramp.to;
+                *slot = None;
+            }
+        }
+
+        // Once shut down or in standby, only a power cycle brings the
+        // system back
+        if matches!(
+            self.state,
+            PowerState::BlackoutShutdown
+                | PowerState::ManualShutdown
+                | PowerState::PoweredDownBlackout
+                | PowerState::PoweredDownManual
+                | PowerState::EnteringStandby
+                | PowerState::Standby
+        ) {
+            return;
+        }
+        if self.dcin_voltage() < BLACKOUT_V_IN {
+            self.state = PowerState::BlackoutCoOp;
+            self.supercap_voltage = (self.supercap_voltage - DISCHARGE_RATE * elapsed).max(0.0);
+            if self.supercap_voltage <= self.solo_power_off_threshold {
+                self.state = PowerState::PoweredDownBlackout;
+            }
+        } else {
+            self.state = PowerState::OperationalCoOp;
+            self.supercap_voltage =
+                (self.supercap_voltage + CHARGE_RATE * elapsed).min(SUPERCAP_FULL);
+        }
+    }
+
+    /// Take a NAK fault matching `reg`, if any
+    fn nak(&mut self, reg: u8) -> Result<(), LinuxI2CError> {
+        let Some(index) = self
+            .naks
+            .iter()
+            .position(|(register, _)| register.is_none_or(|register| register == reg))
+        else {
+            return Ok(());
+        };
+        self.naks[index].1 -= 1;
+        if self.naks[index].1 == 0 {
+            self.naks.remove(index);
+        }
+        Err(LinuxI2CError::Errno(NAK))
+    }
+
+    fn encode_analog(&self, value: f32, scale: f32) -> Vec<u8> {
+        if Feature::WordAnalog.is_supported(&self.firmware) {
+            protocol::encode_word(protocol::float_to_analog_word(value, scale)).to_vec()
+        } else {
+            vec![protocol::float_to_analog_byte(value, scale)]
+        }
+    }
+
+    fn decode_analog(&self, bytes: &[u8], scale: f32) -> Option<f32> {
+        if Feature::WordAnalog.is_supported(&self.firmware) {
+            let word = protocol::decode_word(bytes).ok()?;
+            Some(protocol::analog_word_to_float(word, scale))
+        } else {
+            Some(protocol::analog_byte_to_float(*bytes.first()?, scale))
+        }
+    }
+
+    fn encode_temperature(&self, celsius: f32) -> Vec<u8> {
+        let kelvin = Celsius(celsius).kelvin().0;
+        self.encode_analog(
+            kelvin - protocol::TEMP_MIN_KELVIN,
+            protocol::TEMP_RANGE_KELVIN,
+        )
+    }
+
+    /// Contents of the register `reg`
+    fn register(&self, reg: u8, now: Instant) -> Vec<u8> {
+        let version =
+            |version: &Version| vec![version.major, version.minor, version.patch, version.alpha];
+        match reg {
+            protocol::REG_HARDWARE_VERSION => version(&self.hardware),
+            protocol::REG_FIRMWARE_VERSION => version(&self.firmware),
+            protocol::REG_RASPI_POWER_STATE => vec![self.raspi_power],
+            protocol::REG_WATCHDOG_TIMEOUT => protocol::encode_word(self.watchdog_timeout).to_vec(),
+            protocol::REG_POWER_ON_THRESHOLD => {
+                self.encode_analog(self.power_on_threshold, protocol::VCAP_MAX)
+            }
+            protocol::REG_SOLO_POWEROFF_THRESHOLD => {
+                self.encode_analog(self.solo_power_off_threshold, protocol::VCAP_MAX)
+            }
+            protocol::REG_STATE => vec![self.state as u8],
+            protocol::REG_WATCHDOG_ELAPSED => {
+                let elapsed = now.saturating_duration_since(self.watchdog_fed);
+                vec![(elapsed.as_millis() / 100).min(255) as u8]
+            }
+            protocol::REG_LED_BRIGHTNESS => vec![self.led_brightness],
+            protocol::REG_AUTO_RESTART => vec![self.auto_restart],
+            protocol::REG_SOLO_DEPLETING_TIMEOUT => {
+                protocol::encode_u32(self.solo_depleting_timeout).to_vec()
+            }
+            protocol::REG_USB_PORT_STATE => vec![self.usb_port_state],
+            protocol::REG_LED_PATTERN => vec![self.led_pattern],
+            protocol::REG_LED_COLOR => self.led_color.to_vec(),
+            protocol::REG_DCIN_VOLTAGE => {
+                self.encode_analog(self.dcin_voltage(), protocol::DCIN_MAX)
+            }
+            protocol::REG_SUPERCAP_VOLTAGE => {
+                self.encode_analog(self.supercap_voltage, protocol::VCAP_MAX)
+            }
+            protocol::REG_INPUT_CURRENT => {
+                self.encode_analog(self.input_current(), protocol::I_MAX)
+            }
+            protocol::REG_MCU_TEMPERATURE => {
+                self.encode_temperature(self.temperature(Sensor::Mcu, now))
+            }
+            protocol::REG_PCB_TEMPERATURE => {
+                self.encode_temperature(self.temperature(Sensor::Pcb, now))
+            }
+            protocol::REG_DEVICE_ID => DEVICE_ID.to_vec(),
+            protocol::REG_DFU_STATUS => vec![self.dfu_state.to_byte()],
+            protocol::REG_DFU_BLOCKS_WRITTEN => protocol::encode_word(self.dfu_blocks).to_vec(),
+            _ => vec![0],
+        }
+    }
+
+    /// Read `buffer.len()` bytes starting at `reg`, continuing into the
+    /// following registers like the firmware's block reads
+    fn read(&mut self, reg: u8, buffer: &mut [u8], now: Instant) -> Result<(), LinuxI2CError> {
+        self.nak(reg)?;
+        self.advance(now);
+        self.watchdog_fed = now;
+        let mut bytes = Vec::with_capacity(buffer.len());
+        let mut next = reg;
+        while bytes.len() < buffer.len() {
+            bytes.extend(self.register(next, now));
+            next = next.wrapping_add(1);
+        }
+        buffer.copy_from_slice(&bytes[..buffer.len()]);
+        Ok(())
+    }
+
+    /// Write `values` to `reg`
+    fn write(&mut self, reg: u8, values: &[u8], now: Instant) -> Result<(), LinuxI2CError> {
+        self.nak(reg)?;
+        self.advance(now);
+        self.watchdog_fed = now;
+        let short = || LinuxI2CError::Errno(NAK);
+        let byte = values.first().copied().ok_or_else(short);
+        match reg {
+            protocol::REG_RASPI_POWER_STATE => self.raspi_power = byte?,
+            protocol::REG_WATCHDOG_TIMEOUT => {
+                self.watchdog_timeout = protocol::decode_word(values).map_err(|_| short())?;
+            }
+            protocol::REG_POWER_ON_THRESHOLD => {
+                self.power_on_threshold = self
+                    .decode_analog(values, protocol::VCAP_MAX)
+                    .ok_or_else(short)?;
+            }
+            protocol::REG_SOLO_POWEROFF_THRESHOLD => {
+                self.solo_power_off_threshold = self
+                    .decode_analog(values, protocol::VCAP_MAX)
+                    .ok_or_else(short)?;
+            }
+            protocol::REG_LED_BRIGHTNESS => self.led_brightness = byte?,
+            protocol::REG_AUTO_RESTART => self.auto_restart = byte?,
+            protocol::REG_SOLO_DEPLETING_TIMEOUT => {
+                self.solo_depleting_timeout = protocol::decode_u32(values).map_err(|_| short())?;
+            }
+            protocol::REG_USB_PORT_STATE => self.usb_port_state = byte? & 0x0F,
+            protocol::REG_LED_PATTERN => self.led_pattern = byte?,
+            protocol::REG_LED_COLOR => {
+                self.led_color = values.try_into().map_err(|_| short())?;
+            }
+            protocol::REG_REQUEST_SHUTDOWN => {
+                self.state = if self.state == PowerState::BlackoutCoOp {
+                    PowerState::BlackoutShutdown
+                } else {
+                    PowerState::ManualShutdown
+                };
+            }
+            protocol::REG_REQUEST_STANDBY => self.state = PowerState::EnteringStandby,
+            protocol::REG_DFU_START => {
+                let size = protocol::decode_u32(values).map_err(|_| short())?;
+                self.dfu_total = (size as usize).div_ceil(FLASH_BLOCK_SIZE) as u16;
+                self.dfu_blocks = 0;
+                self.dfu_state = DFUState::Updating;
+                self.check_dfu_fault();
+            }
+            protocol::REG_DFU_UPLOAD_BLOCK => self.upload_block(values),
+            protocol::REG_DFU_COMMIT => {
+                // The controller restarts into the new firmware
+                self.dfu_state = if self.dfu_state == DFUState::ReadyToCommit {
+                    DFUState::Idle
+                } else {
+                    DFUState::ProtocolError
+                };
+            }
+            protocol::REG_DFU_ABORT => self.dfu_state = DFUState::Idle,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Take a firmware block in the format of `HalpiDevice::upload_block`
+    fn upload_block(&mut self, values: &[u8]) {
+        if self.dfu_state != DFUState::Updating {
+            self.dfu_state = DFUState::ProtocolError;
+            return;
+        }
+        let Some((header, data)) = values.split_at_checked(8) else {
+            self.dfu_state = DFUState::DataLengthError;
+            return;
+        };
+        let crc = protocol::decode_u32(&header[..4]).unwrap_or_default();
+        if crc != crc32fast::hash(&values[4..]) {
+            self.dfu_state = DFUState::CrcError;
+            return;
+        }
+        let length = protocol::decode_word(&header[6..]).unwrap_or_default();
+        if usize::from(length) != data.len() {
+            self.dfu_state = DFUState::DataLengthError;
+            return;
+        }
+        self.dfu_blocks += 1;
+        if self.dfu_blocks >= self.dfu_total {
+            self.dfu_state = DFUState::ReadyToCommit;
+        }
+        self.check_dfu_fault();
+    }
+
+    /// Fail the update once the injected DFU error is due
+    fn check_dfu_fault(&mut self) {
+        if let Some((failure, after_blocks)) = self.dfu_fault
+            && self.dfu_blocks >= after_blocks
+        {
+            self.dfu_state = failure.state();
+            self.dfu_fault = None;
+        }
+    }
+
+    fn inject(&mut self, fault: Fault, now: Instant) {
+        self.advance(now);
+        self.apply_fault(fault, now);
+    }
+
+    fn apply_fault(&mut self, fault: Fault, now: Instant) {
+        match fault {
+            Fault::VoltageDrop { v_in, duration } => {
+                let until = duration.map(|duration| now + Duration::from_secs_f64(duration));
+                self.voltage_drop = Some((v_in, until));
+            }
+            Fault::Nak { register, count } => self.naks.push((register, count)),
+            Fault::DfuError {
+                state,
+                after_blocks,
+            } => self.dfu_fault = Some((state, after_blocks)),
+            Fault::TemperatureRamp {
+                sensor,
+                celsius,
+                duration,
+            } => {
+                self.ramps[sensor as usize] = Some(Ramp {
+                    from: self.temperature(sensor, now),
+                    to: celsius,
+                    start: now,
+                    duration: Duration::from_secs_f64(duration),
+                });
+            }
+            Fault::Clear => {
+                self.supply_v_in = NOMINAL_V_IN;
+                self.voltage_drop = None;
+                self.naks.clear();
+                self.dfu_fault = None;
+                self.ramps = [None; 2];
+                self.temperatures = [NOMINAL_MCU_TEMPERATURE, NOMINAL_PCB_TEMPERATURE];
+            }
+        }
+    }
+
+    /// Apply a scenario step at `now`
+    fn apply(&mut self, step: Step, now: Instant) {
+        if let Some(v_in) = step.v_in {
+            self.supply_v_in = v_in;
+        }
+        if let Some(v_supercap) = step.v_supercap {
+            self.supercap_voltage = v_supercap;
+        }
+        for (sensor, celsius) in [(Sensor::Mcu, step.t_mcu), (Sensor::Pcb, step.t_pcb)] {
+            if let Some(celsius) = celsius {
+                self.temperatures[sensor as usize] = celsius;
+                self.ramps[sensor as usize] = None;
+            }
+        }
+        if let Some(fault) = step.fault {
+            self.apply_fault(fault, now);
+        }
+    }
+
+    fn faults(&self, now: Instant) -> Vec<Fault> {
+        let remaining = |until: Instant| until.saturating_duration_since(now).as_secs_f64();
+        let mut faults = Vec::new();
+        if let Some((v_in, until)) = self.voltage_drop {
+            faults.push(Fault::VoltageDrop {
+                v_in,
+                duration: until.map(remaining),
+            });
+        }
+        faults.extend(
+            self.naks
+                .iter()
+                .map(|&(register, count)| Fault::Nak { register, count }),
+        );
+        if let Some((state, after_blocks)) = self.dfu_fault {
+            faults.push(Fault::DfuError {
+                state,
+                after_blocks,
+            });
+        }
+        for (sensor, ramp) in [Sensor::Mcu, Sensor::Pcb].into_iter().zip(self.ramps) {
+            if let Some(ramp) = ramp {
+                faults.push(Fault::TemperatureRamp {
+                    sensor,
+                    celsius: ramp.to,
+                    duration: remaining(ramp.start + ramp.duration),
+                });
+            }
+        }
+        faults
+    }
+
+    fn play(&mut self, scenario: Scenario, start: Instant) {
+        self.scenario = Some(Playback {
+            start,
+            steps: scenario.steps.into(),
+        });
+    }
+
+    fn snapshot(&mut self, now: Instant) -> SimSnapshot {
+        self.advance(now);
+        SimSnapshot {
+            dcin_voltage: Volts(self.dcin_voltage()),
+            supercap_voltage: Volts(self.supercap_voltage),
+            input_current: self.input_current(),
+            mcu_temperature: Celsius(self.temperature(Sensor::Mcu, now)),
+            pcb_temperature: Celsius(self.temperature(Sensor::Pcb, now)),
+            state: self.state,
+            dfu_state: self.dfu_state,
+            faults: self.faults(now),
+            scenario: self.scenario.as_ref().map(|playback| ScenarioProgress {
+                elapsed: now.saturating_duration_since(playback.start).as_secs_f64(),
+                steps_left: playback.steps.len(),
+            }),
+        }
+    }
+}
+
+/// Cloneable handle to a simulated controller
+///
+/// The clone held by the device serves its transfers, the others inject
+/// faults and inspect the simulation.
+#[derive(Debug, Clone)]
+pub struct Simulator {
+    controller: Arc<Mutex<Controller>>,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Simulator {
+    /// A controller running from V_in with a charged supercap
+    pub fn new() -> Self {
+        Self {
+            controller: Arc::new(Mutex::new(Controller::new(Instant::now()))),
+        }
+    }
+
+    /// A controller playing back `scenario`, which starts now
+    pub fn with_scenario(scenario: Scenario) -> Self {
+        let simulator = Self::new();
+        simulator.lock().play(scenario, Instant::now());
+        simulator
+    }
+
+    /// Report `hardware` as the hardware version instead of
+    /// [`HARDWARE_VERSION`], e.g. to simulate a prototype board
+    pub fn set_hardware(&self, hardware: Version) {
+        self.lock().hardware = hardware;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Controller> {
+        self.controller
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Inject `fault`, replacing an earlier one of the same kind except
+    /// for NAKs, which queue up
+    ///
+    /// # Errors
+    /// Returns a description of an invalid fault parameter.
+    pub fn inject(&self, fault: Fault) -> Result<(), String> {
+        fault.validate()?;
+        self.lock().inject(fault, Instant::now());
+        Ok(())
+    }
+
+    /// Current values and active faults
+    pub fn snapshot(&self) -> SimSnapshot {
+        self.lock().snapshot(Instant::now())
+    }
+
+    /// Serve a register read of `buffer.len()` bytes at `reg`
+    ///
+    /// With `pec`, the last byte of `buffer` receives the PEC byte.
+    pub(super) fn read(
+        &self,
+        addr: u8,
+        reg: u8,
+        buffer: &mut [u8],
+        pec: bool,
+    ) -> Result<(), LinuxI2CError> {
+        let (data, pec_byte) = buffer.split_at_mut(buffer.len() - usize::from(pec));
+        self.lock().read(reg, data, Instant::now())?;
+        if let Some(pec_byte) = pec_byte.first_mut() {
+            *pec_byte = protocol::read_pec(addr, reg, data);
+        }
+        Ok(())
+    }
+
+    /// Serve a register write of `data`, the register followed by the
+    /// values and, with `pec`, the PEC byte
+    pub(super) fn write(&self, data: &[u8], pec: bool) -> Result<(), LinuxI2CError> {
+        let Some((&reg, values)) = data.split_first() else {
+            return Err(LinuxI2CError::Errno(NAK));
+        };
+        let values = &values[..values.len().saturating_sub(usize::from(pec))];
+        self.lock().write(reg, values, Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(controller: &mut Controller, reg: u8, count: usize, now: Instant) -> Vec<u8> {
+        let mut buffer = vec![0; count];
+        controller.read(reg, &mut buffer, now).unwrap();
+        buffer
+    }
+
+    fn dcin_voltage(controller: &mut Controller, now: Instant) -> f32 {
+        let word = protocol::decode_word(&read(controller, protocol::REG_DCIN_VOLTAGE, 2, now));
+        protocol::analog_word_to_float(word.unwrap(), protocol::DCIN_MAX)
+    }
+
+    #[test]
+    fn test_voltage_drop() {
+        let start = Instant::now();
+        let at = |seconds: u64| start + Duration::from_secs(seconds);
+        let mut controller = Controller::new(start);
+        assert!((dcin_voltage(&mut controller, at(0)) - NOMINAL_V_IN).abs() < 0.01);
+
+        controller.inject(
+            Fault::VoltageDrop {
+                v_in: 0.0,
+                duration: Some(20.0),
+            },
+            at(0),
+        );
+        assert!(dcin_voltage(&mut controller, at(10)) < 0.01);
+        assert_eq!(controller.state, PowerState::BlackoutCoOp);
+        assert!((controller.supercap_voltage - 9.5).abs() < 1e-4);
+        assert_eq!(controller.faults(at(10)).len(), 1);
+
+        // The drop ends by itself and the supercap charges again
+        assert!((dcin_voltage(&mut controller, at(21)) - NOMINAL_V_IN).abs() < 0.01);
+        assert_eq!(controller.state, PowerState::OperationalCoOp);
+        assert!(controller.faults(at(21)).is_empty());
+    }
+
+    #[test]
+    fn test_supercap_depleted() {
+        let start = Instant::now();
+        let mut controller = Controller::new(start);
+        controller.inject(
+            Fault::VoltageDrop {
+                v_in: 0.0,
+                duration: None,
+            },
+            start,
+        );
+        controller.advance(start + Duration::from_secs(60));
+        assert_eq!(controller.state, PowerState::PoweredDownBlackout);
+        // Powered down stays powered down when V_in returns
+        controller.inject(Fault::Clear, start + Duration::from_secs(61));
+        assert_eq!(controller.state, PowerState::PoweredDownBlackout);
+    }
+
+    #[test]
+    fn test_scenario() {
+        let scenario: Scenario = serde_yaml::from_str(
+            "steps:
+              - {at: 10, v_in: 0}
+              - {at: 20, v_in: 12, t_pcb: 60}
+              - {at: 30, v_supercap: 5, fault: {fault: nak, count: 2}}",
+        )
+        .unwrap();
+        let start = Instant::now();
+        let at = |seconds: u64| start + Duration::from_secs(seconds);
+        let mut controller = Controller::new(start);
+        controller.play(scenario, start);
+
+        // Reading late applies the steps at their scheduled times: 10 s of
+        // discharge, then 2 s of charge
+        let snapshot = controller.snapshot(at(22));
+        assert!((snapshot.supercap_voltage.0 - 9.7).abs() < 1e-4);
+        assert_eq!(snapshot.state, PowerState::OperationalCoOp);
+        assert_eq!(snapshot.pcb_temperature, Celsius(60.0));
+        assert_eq!(
+            snapshot.scenario,
+            Some(ScenarioProgress {
+                elapsed: 22.0,
+                steps_left: 1
+            })
+        );
+
+        controller.advance(at(30));
+        assert!((controller.supercap_voltage - 5.0).abs() < 1e-4);
+        assert_eq!(controller.faults(at(30)).len(), 1);
+        assert!(controller.snapshot(at(31)).scenario.unwrap().steps_left == 0);
+    }
+
+    #[test]
+    fn test_nak() {
+        let now = Instant::now();
+        let mut controller = Controller::new(now);
+        controller.inject(
+            Fault::Nak {
+                register: Some(protocol::REG_STATE),
+                count: 2,
+            },
+            now,
+        );
+        let mut buffer = [0; 1];
+        // Other registers are unaffected
+        assert!(
+            controller
+                .read(protocol::REG_LED_BRIGHTNESS, &mut buffer, now)
+                .is_ok()
+        );
+        for _ in 0..2 {
+            assert!(matches!(
+                controller.read(protocol::REG_STATE, &mut buffer, now),
+                Err(LinuxI2CError::Errno(NAK))
+            ));
+        }
+        assert!(
+            controller
+                .read(protocol::REG_STATE, &mut buffer, now)
+                .is_ok()
+        );
+        assert!(controller.faults(now).is_empty());
+    }
+
+    #[test]
+    fn test_temperature_ramp() {
+        let start = Instant::now();
+        let at = |seconds: u64| start + Duration::from_secs(seconds);
+        let mut controller = Controller::new(start);
+        controller.inject(
+            Fault::TemperatureRamp {
+                sensor: Sensor::Pcb,
+                celsius: 85.0,
+                duration: 10.0,
+            },
+            at(0),
+        );
+        assert!((controller.temperature(Sensor::Pcb, at(5)) - 60.0).abs() < 1e-4);
+        assert_eq!(
+            controller.temperature(Sensor::Mcu, at(5)),
+            NOMINAL_MCU_TEMPERATURE
+        );
+        controller.advance(at(20));
+        assert_eq!(controller.temperature(Sensor::Pcb, at(20)), 85.0);
+        assert!(controller.faults(at(20)).is_empty());
+    }
+
+    #[test]
+    fn test_dfu_error() {
+        let now = Instant::now();
+        let mut controller = Controller::new(now);
+        controller.inject(
+            Fault::DfuError {
+                state: DfuFailure::WriteError,
+                after_blocks: 1,
+            },
+            now,
+        );
+        let size = (2 * FLASH_BLOCK_SIZE) as u32;
+        controller
+            .write(protocol::REG_DFU_START, &protocol::encode_u32(size), now)
+            .unwrap();
+        assert_eq!(controller.dfu_state, DFUState::Updating);
+
+        let mut block = Vec::new();
+        block.extend(protocol::encode_word(0));
+        block.extend(protocol::encode_word(4));
+        block.extend([1, 2, 3, 4]);
+        let mut message = protocol::encode_u32(crc32fast::hash(&block)).to_vec();
+        message.extend(&block);
+        controller
+            .write(protocol::REG_DFU_UPLOAD_BLOCK, &message, now)
+            .unwrap();
+        assert_eq!(controller.dfu_state, DFUState::WriteError);
+        assert_eq!(controller.dfu_blocks, 1);
+    }
+
+    #[test]
+    fn test_block_read_and_pec() {
+        let simulator = Simulator::new();
+        // State and watchdog elapsed in one read, with a PEC byte
+        let mut buffer = [0; 3];
+        simulator
+            .read(0x6D, protocol::REG_STATE, &mut buffer, true)
+            .unwrap();
+        assert_eq!(buffer[0], PowerState::OperationalCoOp as u8);
+        assert_eq!(
+            buffer[2],
+            protocol::read_pec(0x6D, protocol::REG_STATE, &buffer[..2])
+        );
+
+        let mut buffer = [0; protocol::MEASUREMENT_BLOCK_LEN];
+        simulator
+            .read(0x6D, protocol::REG_DCIN_VOLTAGE, &mut buffer, false)
+            .unwrap();
+        let [dcin, ..] = protocol::decode_words::<5>(&buffer).unwrap();
+        let v_in = protocol::analog_word_to_float(dcin, protocol::DCIN_MAX);
+        assert!((v_in - NOMINAL_V_IN).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_fault_json() {
+        let fault: Fault =
+            serde_json::from_str(r#"{"fault": "voltage-drop", "v_in": 0, "duration": 30}"#)
+                .unwrap();
+        assert_eq!(
+            fault,
+            Fault::VoltageDrop {
+                v_in: 0.0,
+                duration: Some(30.0)
+            }
+        );
+        let fault: Fault = serde_json::from_str(r#"{"fault": "nak"}"#).unwrap();
+        assert_eq!(
+            fault,
+            Fault::Nak {
+                register: None,
+                count: 1
+            }
+        );
+        assert!(serde_json::from_str::<Fault>(r#"{"fault": "nak", "bogus": 1}"#).is_err());
+
+        assert!(
+            Fault::TemperatureRamp {
+                sensor: Sensor::Mcu,
+                celsius: 200.0,
+                duration: 10.0
+            }
+            .validate()
+            .is_err()
+        );
+        assert!(
+            Fault::Nak {
+                register: None,
+                count: 0
+            }
+            .validate()
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! I2C error and retry statistics
+//!
+//! Counts are kept per register so that a flaky bus (errors everywhere) can
+//! be told apart from a firmware problem with a specific register.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// Length of the window in which transient errors are counted
+pub const ERROR_WINDOW: Duration = Duration::from_secs(60);
+
+/// Number of transient errors within [`ERROR_WINDOW`] considered a spike
+pub const ERROR_SPIKE_THRESHOLD: u32 = 10;
+
+/// Minimum interval between high error rate warnings
+const WARNING_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Number of consecutive failed transfers after which the device is reopened
+pub const REOPEN_THRESHOLD: u32 = 5;
+
+/// Counters for one register
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RegisterStats {
+    /// Transfers started
+    pub transfers: u64,
+    /// Additional attempts after a transient error
+    pub retries: u64,
+    /// Failed attempts that were retried or exhausted the retries
+    pub transient_errors: u64,
+    /// Transfers that failed for good
+    pub permanent_errors: u64,
+}
+
+impl RegisterStats {
+    fn add(&mut self, other: &RegisterStats) {
+        self.transfers += other.transfers;
+        self.retries += other.retries;
+        self.transient_errors += other.transient_errors;
+        self.permanent_errors += other.permanent_errors;
+    }
+}
+
+/// I2C statistics of one device
+#[derive(Debug, Clone)]
+pub struct I2cStats {
+    registers: BTreeMap<u8, RegisterStats>,
+    window_start: Option<Instant>,
+    window_errors: u32,
+    last_warning: Option<Instant>,
+    consecutive_failures: u32,
+    reopens: u64,
+}
+
+impl I2cStats {
+    pub fn new() -> Self {
+        Self {
+            registers: BTreeMap::new(),
+            window_start: None,
+            window_errors: 0,
+            last_warning: None,
+            consecutive_failures: 0,
+            reopens: 0,
+        }
+    }
+
+    /// Counters for `reg`, created on first use
+    pub(crate) fn register_mut(&mut self, reg: u8) -> &mut RegisterStats {
+        self.registers.entry(reg).or_default()
+    }
+
+    /// Record a transient error on `reg` at `now`
+    ///
+    /// Returns the number of errors in the current window if they amount to
+    /// a spike that has not been warned about recently.
+    pub(crate) fn record_transient(&mut self, reg: u8, now: Instant) -> Option<u32> {
+        self.register_mut(reg).transient_errors += 1;
+
+        match self.window_start {
+            Some(start) if now.duration_since(start) < ERROR_WINDOW => self.window_errors += 1,
+            _ => {
+                self.window_start = Some(now);
+                self.window_errors = 1;
+            }
+        }
+
+        let recently_warned = self
+            .last_warning
+            .is_some_and(|last| now.duration_since(last) < WARNING_INTERVAL);
+        if self.window_errors >= ERROR_SPIKE_THRESHOLD && !recently_warned {
+            self.last_warning = Some(now);
+            Some(self.window_errors)
+        } else {
+            None
+        }
+    }
+
+    /// Record whether a transfer succeeded
+    ///
+    /// Returns true if [`REOPEN_THRESHOLD`] transfers in a row have failed
+    /// and the device should be reopened. The count then starts over.
+    pub(crate) fn record_outcome(&mut self, ok: bool) -> bool {
+        if ok {
+            self.consecutive_failures = 0;
+            return false;
+        }
+        self.consecutive_failures += 1;
+        if self.consecutive_failures >= REOPEN_THRESHOLD {
+            self.consecutive_failures = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Record that the device was reopened
+    pub(crate) fn record_reopen(&mut self) {
+        self.reopens += 1;
+    }
+
+    /// Number of times the device was reopened after persistent errors
+    pub fn reopens(&self) -> u64 {
+        self.reopens
+    }
+
+    /// Counters of all registers accessed so far, in register order
+    pub fn registers(&self) -> impl Iterator<Item = (u8, &RegisterStats)> {
+        self.registers.iter().map(|(reg, stats)| (*reg, stats))
+    }
+
+    /// Counters summed over all registers
+    pub fn totals(&self) -> RegisterStats {
+        let mut totals = RegisterStats::default();
+        for stats in self.registers.values() {
+            totals.add(stats);
+        }
+        totals
+    }
+
+    /// Totals, reopens and the registers with errors, keyed by hex register
+    /// address
+    pub fn to_json(&self) -> serde_json::Value {
+        let registers: serde_json::Map<String, serde_json::Value> = self
+            .registers()
+            .filter(|(_, stats)| stats.transient_errors > 0 || stats.permanent_errors > 0)
+            .map(|(reg, stats)| (format!("0x{:02X}", reg), serde_json::json!(stats)))
+            .collect();
+        serde_json::json!({
+            "totals": self.totals(),
+            "reopens": self.reopens,
+            "registers": registers,
+        })
+    }
+}
+
+impl Default for I2cStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_totals_and_json() {
+        let mut stats = I2cStats::new();
+        let now = Instant::now();
+        stats.register_mut(0x20).transfers += 3;
+        stats.register_mut(0x21).transfers += 1;
+        stats.register_mut(0x21).retries += 1;
+        stats.record_transient(0x21, now);
+        stats.register_mut(0x21).permanent_errors += 1;
+
+        let totals = stats.totals();
+        assert_eq!(totals.transfers, 4);
+        assert_eq!(totals.retries, 1);
+        assert_eq!(totals.transient_errors, 1);
+        assert_eq!(totals.permanent_errors, 1);
+
+        let json = stats.to_json();
+        assert_eq!(json["totals"]["transfers"], 4);
+        assert_eq!(json["registers"]["0x21"]["permanent_errors"], 1);
+        assert!(json["registers"].get("0x20").is_none());
+    }
+
+    #[test]
+    fn test_spike_warning_is_rate_limited() {
+        let mut stats = I2cStats::new();
+        let start = Instant::now();
+
+        for i in 1..ERROR_SPIKE_THRESHOLD {
+            assert_eq!(stats.record_transient(0x20, start), None, "error {}", i);
+        }
+        assert_eq!(
+            stats.record_transient(0x20, start),
+            Some(ERROR_SPIKE_THRESHOLD)
+        );
+        assert_eq!(stats.record_transient(0x20, start), None);
+
+        // A new window after the warning interval warns again
+        let later = start + WARNING_INTERVAL;
+        for _ in 1..ERROR_SPIKE_THRESHOLD {
+            assert_eq!(stats.record_transient(0x20, later), None);
+        }
+        assert_eq!(
+            stats.record_transient(0x20, later),
+            Some(ERROR_SPIKE_THRESHOLD)
+        );
+    }
+
+    #[test]
+    fn test_reopen_after_consecutive_failures() {
+        let mut stats = I2cStats::new();
+        for _ in 1..REOPEN_THRESHOLD {
+            assert!(!stats.record_outcome(false));
+        }
+        // A success in between starts the count over
+        assert!(!stats.record_outcome(true));
+        for _ in 1..REOPEN_THRESHOLD {
+            assert!(!stats.record_outcome(false));
+        }
+        assert!(stats.record_outcome(false));
+        assert!(!stats.record_outcome(false));
+
+        stats.record_reopen();
+        assert_eq!(stats.reopens(), 1);
+        assert_eq!(stats.to_json()["reopens"], 1);
+    }
+
+    #[test]
+    fn test_errors_spread_out_do_not_warn() {
+        let mut stats = I2cStats::new();
+        let start = Instant::now();
+        for i in 0..3 * ERROR_SPIKE_THRESHOLD {
+            let now = start + ERROR_WINDOW / 5 * i;
+            assert_eq!(stats.record_transient(0x20, now), None);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Identify a controller by ramping its LED
+//!
+//! Lets installers with several units tell which one they are talking to.
+//! The LED brightness ramps up and down until the time is up and is then
+//! restored. A new request while the LED is ramping extends the time.
+
+use std::sync::{Arc, Mutex};
+
+use tokio::time::{Duration, Instant, sleep};
+use tracing::{debug, info};
+
+use crate::i2c::DeviceHandle;
+use crate::tasks;
+
+/// Default identify duration, in seconds
+pub const DEFAULT_IDENTIFY_DURATION: u64 = 10;
+
+/// Longest identify duration, in seconds
+pub const MAX_IDENTIFY_DURATION: u64 = 300;
+
+/// Time between brightness changes
+const STEP: Duration = Duration::from_millis(50);
+
+/// Steps for one ramp up and down
+const RAMP_STEPS: u32 = 20;
+
+/// LED brightness at `step` of the ramp
+fn ramp(step: u32) -> u8 {
+    let half = RAMP_STEPS / 2;
+    let phase = step % RAMP_STEPS;
+    let level = if phase <= half {
+        phase
+    } else {
+        RAMP_STEPS - phase
+    };
+    (level * 255 / half) as u8
+}
+
+/// The end of the running identification of one controller, if any
+#[derive(Debug, Clone, Default)]
+pub struct Identify {
+    until: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Identify {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ramp the LED of `device` for `duration`, then restore `brightness`
+    ///
+    /// Extends a running identification instead of starting another one.
+    pub fn start(&self, device: DeviceHandle, brightness: u8, duration: Duration) {
+        {
+            let mut until = self.until.lock().unwrap();
+            let running = until.is_some();
+            *until = Some(Instant::now() + duration);
+            if running {
+                return;
+            }
+        }
+
+        info!("Identify: ramping the LED for {} s", duration.as_secs());
+        let this = self.clone();
+        tasks::spawn("identify", async move {
+            let mut step = 0;
+            while !this.finished() {
+                let level = ramp(step);
+                if let Err(e) = device
+                    .run(move |device| device.set_led_brightness(level))
+                    .await
+                {
+                    debug!("Failed to set LED brightness: {}", e);
+                }
+                step = step.wrapping_add(1);
+                sleep(STEP).await;
+            }
+            device
+                .run(move |device| device.set_led_brightness(brightness))
+                .await?;
+            info!("Identify done, LED brightness restored to {}", brightness);
+            Ok(())
+        });
+    }
+
+    /// Run a running identification until `duration` from now
+    ///
+    /// Returns false if none is running.
+    pub fn extend(&self, duration: Duration) -> bool {
+        let mut until = self.until.lock().unwrap();
+        if until.is_none() {
+            return false;
+        }
+        *until = Some(Instant::now() + duration);
+        info!("Identify extended to {} s from now", duration.as_secs());
+        true
+    }
+
+    /// True if an identification is running
+    pub fn running(&self) -> bool {
+        self.until.lock().unwrap().is_some()
+    }
+
+    /// Clear the end time once it has passed
+    fn finished(&self) -> bool {
+        let mut until = self.until.lock().unwrap();
+        if until.is_some_and(|until| Instant::now() < until) {
+            return false;
+        }
+        *until = None;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ramp() {
+        assert_eq!(ramp(0), 0);
+        assert_eq!(ramp(5), 127);
+        assert_eq!(ramp(10), 255);
+        assert_eq!(ramp(15), 127);
+        assert_eq!(ramp(RAMP_STEPS), 0);
+        assert!((0..RAMP_STEPS).all(|step| ramp(step) == ramp(step + RAMP_STEPS)));
+    }
+
+    #[tokio::test]
+    async fn test_identify() {
+        let identify = Identify::new();
+        let device = DeviceHandle::missing(1, 0x6D);
+        identify.start(device, 128, Duration::from_millis(300));
+        assert!(identify.running());
+
+        sleep(Duration::from_millis(200)).await;
+        assert!(identify.extend(Duration::from_millis(300)));
+        sleep(Duration::from_millis(200)).await;
+        assert!(identify.running());
+
+        sleep(Duration::from_millis(300)).await;
+        assert!(!identify.running());
+        assert!(!identify.extend(Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,318 @@
+//! InfluxDB line-protocol exporter
+//!
+//! Samples the controller measurements at a fixed interval and writes them
+//! to InfluxDB. Points are buffered in memory while the server is
+//! unreachable and flushed in order once it comes back; when the buffer
+//! fills up, the oldest points are dropped.
+
+use std::collections::VecDeque;
+use tokio::time::{Duration, MissedTickBehavior, interval};
+use tracing::{debug, info, warn};
+
+use halpi_common::config::InfluxDbConfig;
+use halpi_common::types::Measurements;
+
+use crate::events::EventBus;
+use crate::http_client::HttpClient;
+use crate::i2c::DeviceHandle;
+
+/// Maximum number of points sent in a single write request
+const MAX_BATCH_SIZE: usize = 1000;
+
+/// Bounded FIFO of line-protocol points awaiting delivery
+#[derive(Debug)]
+pub struct LineBuffer {
+    lines: VecDeque<String>,
+    capacity: usize,
+    dropped: u64,
+}
+
+impl LineBuffer {
+    /// Create an empty buffer holding at most `capacity` points
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            capacity: capacity.max(1),
+            dropped: 0,
+        }
+    }
+
+    /// Append a point, dropping the oldest one if the buffer is full
+    pub fn push(&mut self, line: String) {
+        if self.lines.len() >= self.capacity {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
+        self.lines.push_back(line);
+    }
+
+    /// Number of buffered points
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// True if no points are buffered
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Number of points dropped due to overflow since creation
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Join up to `max` of the oldest points into a request body
+    ///
+    /// Returns the body and the number of points it contains.
+    pub fn batch(&self, max: usize) -> (String, usize) {
+        let count = self.lines.len().min(max);
+        let body = self
+            .lines
+            .iter()
+            .take(count)
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("\n");
+        (body, count)
+    }
+
+    /// Remove the `count` oldest points after a successful write
+    pub fn consume(&mut self, count: usize) {
+        self.lines.drain(..count.min(self.lines.len()));
+    }
+}
+
+/// Format a measurement sample as a line-protocol point
+///
+/// Field names match the keys of the `/values` endpoint.
+pub fn format_line(
+    measurement: &str,
+    device_id: &str,
+    m: &Measurements,
+    timestamp_ms: i64,
+) -> String {
+    format!(
+        "{},device_id={} V_in={},V_cap={},I_in={},T_mcu={},T_pcb={},watchdog_elapsed={},state=\"{}\" {}",
+        escape_key(measurement),
+        escape_key(device_id),
+        m.dcin_voltage.0,
+        m.supercap_voltage.0,
+        m.input_current.0,
+        m.mcu_temperature.0,
+        m.pcb_temperature.0,
+        m.watchdog_elapsed,
+        escape_string(m.power_state.name()),
+        timestamp_ms
+    )
+}
+
+/// Build the write endpoint URL for the configured API version
+pub fn write_url(config: &InfluxDbConfig) -> String {
+    let base = config.url.trim_end_matches('/');
+    match &config.org {
+        Some(org) => format!(
+            "{}/api/v2/write?org={}&bucket={}&precision=ms",
+            base,
+            encode_query(org),
+            encode_query(&config.bucket)
+        ),
+        None => format!(
+            "{}/write?db={}&precision=ms",
+            base,
+            encode_query(&config.bucket)
+        ),
+    }
+}
+
+/// Escape a measurement name or tag value
+fn escape_key(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | ' ' | '=') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Escape a string field value
+fn escape_string(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Percent-encode a query parameter value
+fn encode_query(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+/// Run the exporter until the daemon shuts down
+pub async fn run(device: DeviceHandle, events: EventBus, config: InfluxDbConfig) {
+    let client = HttpClient::default();
+    let url = write_url(&config);
+    let mut headers = vec![("Content-Type", "text/plain; charset=utf-8".to_string())];
+    if let Some(token) = &config.token {
+        headers.push(("Authorization", format!("Token {}", token)));
+    }
+
+    // Identity is only known once the controller is connected
+    let device_id = device
+        .with_present(|dev| dev.get_device_id())
+        .await
+        .and_then(|id| id)
+        .unwrap_or_else(|_| "0000000000000000".to_string());
+
+    let mut buffer = LineBuffer::new(config.buffer_size);
+    let mut reachable = true;
+    let mut ticker = interval(Duration::from_secs_f64(config.interval));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+
+        match events.current() {
+            Some(sample) => buffer.push(format_line(
+                &config.measurement,
+                &device_id,
+                &sample.measurements,
+                sample.timestamp.timestamp_millis(),
+            )),
+            None => debug!("Skipping InfluxDB sample: no current measurements"),
+        }
+
+        // Flush as much of the backlog as the server accepts
+        while !buffer.is_empty() {
+            let (body, count) = buffer.batch(MAX_BATCH_SIZE);
+            match client.post(&url, &headers, body).await {
+                Ok(response) if response.is_success() => {
+                    buffer.consume(count);
+                    if !reachable {
+                        info!(
+                            "InfluxDB reachable again, flushed {} buffered points",
+                            count
+                        );
+                        reachable = true;
+                    }
+                }
+                Ok(response) if (400..500).contains(&response.status) => {
+                    // Rejected data will never be accepted; drop it rather than retry forever
+                    warn!(
+                        "InfluxDB rejected {} points ({}): {}",
+                        count,
+                        response.status,
+                        String::from_utf8_lossy(&response.body).trim()
+                    );
+                    buffer.consume(count);
+                }
+                Ok(response) => {
+                    if reachable {
+                        warn!("InfluxDB write failed ({}), buffering", response.status);
+                        reachable = false;
+                    }
+                    break;
+                }
+                Err(e) => {
+                    if reachable {
+                        warn!("InfluxDB unreachable, buffering: {:#}", e);
+                        reachable = false;
+                    }
+                    break;
+                }
+            }
+        }
+
+        if buffer.dropped() > 0 && !reachable {
+            debug!(
+                "InfluxDB buffer holds {} points, {} dropped",
+                buffer.len(),
+                buffer.dropped()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use halpi_common::types::PowerState;
+    use halpi_common::units::{Amps, Kelvin, Volts};
+
+    fn sample() -> Measurements {
+        Measurements {
+            dcin_voltage: Volts(12.5),
+            supercap_voltage: Volts(10.0),
+            input_current: Amps(0.5),
+            mcu_temperature: Kelvin(300.0),
+            pcb_temperature: Kelvin(301.5),
+            power_state: PowerState::OperationalCoOp,
+            watchdog_elapsed: 0.25,
+            measured_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_format_line() {
+        let line = format_line("halpi", "abcd", &sample(), 1700000000000);
+        assert_eq!(
+            line,
+            "halpi,device_id=abcd V_in=12.5,V_cap=10,I_in=0.5,T_mcu=300,T_pcb=301.5,\
+             watchdog_elapsed=0.25,state=\"OperationalCoOp\" 1700000000000"
+        );
+    }
+
+    #[test]
+    fn test_escape_key() {
+        assert_eq!(escape_key("my meas,x=1"), "my\\ meas\\,x\\=1");
+    }
+
+    #[test]
+    fn test_write_url_v1_and_v2() {
+        let mut config = InfluxDbConfig {
+            url: "http://localhost:8086/".to_string(),
+            bucket: "halpi data".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            write_url(&config),
+            "http://localhost:8086/write?db=halpi%20data&precision=ms"
+        );
+
+        config.org = Some("boat".to_string());
+        assert_eq!(
+            write_url(&config),
+            "http://localhost:8086/api/v2/write?org=boat&bucket=halpi%20data&precision=ms"
+        );
+    }
+
+    #[test]
+    fn test_line_buffer_drops_oldest() {
+        let mut buffer = LineBuffer::new(2);
+        buffer.push("a".to_string());
+        buffer.push("b".to_string());
+        buffer.push("c".to_string());
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.dropped(), 1);
+        assert_eq!(buffer.batch(10), ("b\nc".to_string(), 2));
+    }
+
+    #[test]
+    fn test_line_buffer_batch_and_consume() {
+        let mut buffer = LineBuffer::new(10);
+        for i in 0..5 {
+            buffer.push(i.to_string());
+        }
+        let (body, count) = buffer.batch(3);
+        assert_eq!(body, "0\n1\n2");
+        buffer.consume(count);
+        assert_eq!(buffer.batch(10), ("3\n4".to_string(), 2));
+    }
+}
@@ -0,0 +1,188 @@
+//! Night mode for the front LED
+//!
+//! Lowers the LED brightness between the configured hours and restores it
+//! afterwards. Only the transitions change the brightness, so a brightness
+//! set through the API during the night is kept until the morning. The
+//! controller is only read at the transitions, and not at all while the
+//! system shuts down, so that the hardware watchdog can run out.
+
+use chrono::{Local, NaiveTime};
+use tokio::time::{Duration, interval};
+use tracing::{debug, info, warn};
+
+use halpi_common::config::LedNightConfig;
+
+use crate::i2c::{DeviceHandle, I2cError};
+use crate::state_machine::StatusHandle;
+
+/// How often the time of day is checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Brightness restored if neither `day-brightness` nor the brightness
+/// before dimming is known, e.g. after a restart during the night
+const DEFAULT_DAY_BRIGHTNESS: u8 = 255;
+
+/// True if `now` is between `start` and `end`, which may span midnight
+pub fn is_night(start: NaiveTime, end: NaiveTime, now: NaiveTime) -> bool {
+    if start < end {
+        start <= now && now < end
+    } else {
+        now >= start || now < end
+    }
+}
+
+/// Tracks night and day and the brightness to restore
+#[derive(Debug, Default)]
+pub struct NightMode {
+    night: Option<bool>,
+    saved: Option<u8>,
+}
+
+impl NightMode {
+    /// Whether `night` differs from the last update, so the brightness is
+    /// needed
+    pub fn changes(&self, night: bool) -> bool {
+        self.night != Some(night)
+    }
+
+    /// Update with whether it is night and the current brightness
+    ///
+    /// Returns the brightness to set when night starts or ends. Starting
+    /// during the day changes nothing.
+    pub fn update(&mut self, night: bool, current: u8, config: &LedNightConfig) -> Option<u8> {
+        let previous = self.night.replace(night);
+        if previous == Some(night) {
+            return None;
+        }
+        if night {
+            if current == config.brightness {
+                return None;
+            }
+            self.saved = Some(current);
+            Some(config.brightness)
+        } else if previous.is_some() {
+            let saved = self.saved.take();
+            Some(
+                config
+                    .day_brightness
+                    .or(saved)
+                    .unwrap_or(DEFAULT_DAY_BRIGHTNESS),
+            )
+        } else {
+            None
+        }
+    }
+}
+
+/// Dim the LED at night and restore it in the morning
+///
+/// Returns when the firmware has no LED brightness register.
+///
+/// # Errors
+/// Returns an error if the configured times are invalid.
+pub async fn run(
+    device: DeviceHandle,
+    status: StatusHandle,
+    config: LedNightConfig,
+) -> anyhow::Result<()> {
+    let (start, end) = config
+        .times()
+        .map_err(|e| anyhow::anyhow!("led-night: {}", e))?;
+
+    let mut mode = NightMode::default();
+    let mut ticker = interval(CHECK_INTERVAL);
+    loop {
+        ticker.tick().await;
+        let night = is_night(start, end, Local::now().time());
+        if !mode.changes(night) || status.is_shutting_down() {
+            continue;
+        }
+        let current = match device.run(|device| device.get_led_brightness()).await {
+            Ok(current) => current,
+            Err(e @ I2cError::Unsupported { .. }) => {
+                warn!("LED night mode disabled: {}", e);
+                return Ok(());
+            }
+            Err(e) => {
+                debug!("Cannot read LED brightness: {}", e);
+                continue;
+            }
+        };
+
+        let Some(brightness) = mode.update(night, current, &config) else {
+            continue;
+        };
+        match device
+            .run(move |device| device.set_led_brightness(brightness))
+            .await
+        {
+            Ok(()) if night => info!("Night mode: LED brightness lowered to {}", brightness),
+            Ok(()) => info!(
+                "Night mode ended: LED brightness restored to {}",
+                brightness
+            ),
+            Err(e) => warn!("Failed to set LED brightness: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(text: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(text, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_is_night() {
+        let (start, end) = (time("22:00"), time("07:00"));
+        assert!(is_night(start, end, time("22:00")));
+        assert!(is_night(start, end, time("03:00")));
+        assert!(!is_night(start, end, time("07:00")));
+        assert!(!is_night(start, end, time("12:00")));
+
+        // Not spanning midnight
+        let (start, end) = (time("01:00"), time("05:00"));
+        assert!(is_night(start, end, time("02:00")));
+        assert!(!is_night(start, end, time("23:00")));
+    }
+
+    #[test]
+    fn test_night_mode() {
+        let config = LedNightConfig {
+            enabled: true,
+            ..LedNightConfig::default()
+        };
+        let mut mode = NightMode::default();
+
+        // Starting during the day leaves the LED alone
+        assert!(mode.changes(false));
+        assert_eq!(mode.update(false, 100, &config), None);
+        assert!(!mode.changes(false));
+        assert!(mode.changes(true));
+        assert_eq!(mode.update(false, 100, &config), None);
+
+        assert_eq!(mode.update(true, 100, &config), Some(config.brightness));
+        // A brightness set during the night is kept
+        assert_eq!(mode.update(true, 50, &config), None);
+        assert_eq!(mode.update(false, 50, &config), Some(100));
+        assert_eq!(mode.update(false, 100, &config), None);
+
+        // Restarted during the night, with the LED already dimmed
+        let mut mode = NightMode::default();
+        assert_eq!(mode.update(true, config.brightness, &config), None);
+        assert_eq!(
+            mode.update(false, config.brightness, &config),
+            Some(DEFAULT_DAY_BRIGHTNESS)
+        );
+
+        let config = LedNightConfig {
+            day_brightness: Some(180),
+            ..config
+        };
+        let mut mode = NightMode::default();
+        assert_eq!(mode.update(true, 100, &config), Some(config.brightness));
+        assert_eq!(mode.update(false, 8, &config), Some(180));
+    }
+}
@@ -0,0 +1,200 @@
+//! Rotating log file writer
+//!
+//! Rotation renames the active file to `<file>.1`, shifting older files up
+//! by one and deleting those beyond the retention count, then starts a new
+//! file. Writes are synchronous; at the daemon's log volume that is cheaper
+//! than a background writer thread.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+
+use halpi_common::config::{LogRotation, LoggingConfig};
+
+/// Log file writer with size- and time-based rotation
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: LogRotation,
+    max_size: u64,
+    keep: usize,
+    file: File,
+    size: u64,
+    period: Option<i64>,
+}
+
+impl RotatingFile {
+    /// Open (or create) the log file described by `config`
+    pub fn open(path: &Path, config: &LoggingConfig) -> io::Result<Self> {
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let metadata = file.metadata()?;
+
+        // An existing file belongs to the period it was last written in, so
+        // a restart after midnight still rotates yesterday's log
+        let modified = metadata
+            .modified()
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_else(|_| Utc::now());
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            rotation: config.rotation,
+            max_size: config.max_size_mb.saturating_mul(1024 * 1024),
+            keep: config.keep.max(1),
+            file,
+            size: metadata.len(),
+            period: period(config.rotation, modified),
+        })
+    }
+
+    /// Path of the `index`th rotated file
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    /// Shift rotated files and start a new active file
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        let _ = fs::remove_file(self.rotated_path(self.keep));
+        for index in (1..self.keep).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(index + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    /// Write `buf`, rotating first if `now` starts a new period or the
+    /// write would exceed the size limit
+    fn write_at(&mut self, buf: &[u8], now: DateTime<Utc>) -> io::Result<usize> {
+        let current = period(self.rotation, now);
+        let period_changed = current != self.period;
+        let too_big =
+            self.max_size > 0 && self.size > 0 && self.size + buf.len() as u64 > self.max_size;
+        if period_changed && self.size == 0 {
+            self.period = current;
+        } else if period_changed || too_big {
+            // Keep logging to the old file if rotation fails (e.g. disk full)
+            if self.rotate().is_ok() {
+                self.period = current;
+            }
+        }
+
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_at(buf, Utc::now())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Rotation period index for `time`, or None without time-based rotation
+fn period(rotation: LogRotation, time: DateTime<Utc>) -> Option<i64> {
+    match rotation {
+        LogRotation::Never => None,
+        LogRotation::Hourly => Some(time.timestamp().div_euclid(3600)),
+        LogRotation::Daily => Some(time.timestamp().div_euclid(86400)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("halpid-log-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_size_rotation_and_retention() {
+        let dir = temp_dir("size");
+        let path = dir.join("halpid.log");
+        let config = LoggingConfig {
+            file: Some(path.clone()),
+            keep: 2,
+            ..Default::default()
+        };
+        let mut log = RotatingFile::open(&path, &config).unwrap();
+        log.max_size = 10;
+
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            log.write_all(line.as_bytes()).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("halpid.log.1")).unwrap(),
+            "third\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("halpid.log.2")).unwrap(),
+            "second\n"
+        );
+        assert!(!dir.join("halpid.log.3").exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_daily_rotation() {
+        let dir = temp_dir("daily");
+        let path = dir.join("halpid.log");
+        let config = LoggingConfig {
+            file: Some(path.clone()),
+            rotation: LogRotation::Daily,
+            ..Default::default()
+        };
+        let mut log = RotatingFile::open(&path, &config).unwrap();
+
+        let today = Utc::now();
+        log.write_at(b"today\n", today).unwrap();
+        log.write_at(b"still today\n", today).unwrap();
+        assert!(!dir.join("halpid.log.1").exists());
+
+        log.write_at(b"tomorrow\n", today + chrono::Duration::days(1))
+            .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "tomorrow\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("halpid.log.1")).unwrap(),
+            "today\nstill today\n"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_period() {
+        let time = DateTime::from_timestamp(86400 * 3 + 3600 * 5 + 10, 0).unwrap();
+        assert_eq!(period(LogRotation::Never, time), None);
+        assert_eq!(period(LogRotation::Daily, time), Some(3));
+        assert_eq!(period(LogRotation::Hourly, time), Some(3 * 24 + 5));
+    }
+}
@@ -0,0 +1,277 @@
+//! Native systemd journal output
+//!
+//! Writes each event as a datagram in the journal's native protocol, so
+//! structured event and span fields become journal fields. Field names are
+//! upper-cased, e.g. `voltage = 11.2` is stored as `VOLTAGE=11.2` and can be
+//! matched with `journalctl -u halpid VOLTAGE=11.2` or inspected with
+//! `journalctl -u halpid -o json`.
+
+use std::fmt::{self, Write as _};
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Default journald native protocol socket
+pub const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Syslog identifier attached to every entry
+const SYSLOG_IDENTIFIER: &str = "halpid";
+
+/// Tracing layer that sends events to journald
+pub struct JournaldLayer {
+    socket: UnixDatagram,
+}
+
+impl JournaldLayer {
+    /// Connect to the system journal
+    pub fn connect() -> io::Result<Self> {
+        Self::connect_to(JOURNALD_SOCKET)
+    }
+
+    /// Connect to a journal socket at `path`
+    pub fn connect_to(path: impl AsRef<Path>) -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Self { socket })
+    }
+}
+
+/// True if stderr is connected to the journal
+///
+/// systemd sets `JOURNAL_STREAM` to the device and inode of the stream it
+/// attaches to stdout/stderr; comparing them with stderr tells whether our
+/// output would end up in the journal anyway.
+pub fn stderr_is_journal() -> bool {
+    let Ok(value) = std::env::var("JOURNAL_STREAM") else {
+        return false;
+    };
+    let Some((dev, ino)) = value.split_once(':') else {
+        return false;
+    };
+    let (Ok(dev), Ok(ino)) = (dev.parse::<u64>(), ino.parse::<u64>()) else {
+        return false;
+    };
+
+    let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+    // SAFETY: fstat only writes into the provided buffer
+    if unsafe { libc::fstat(libc::STDERR_FILENO, stat.as_mut_ptr()) } != 0 {
+        return false;
+    }
+    // SAFETY: fstat succeeded, so the buffer is initialized
+    let stat = unsafe { stat.assume_init() };
+    // The field widths differ between 32-bit and 64-bit targets
+    #[allow(clippy::unnecessary_cast)]
+    let matches = stat.st_dev as u64 == dev && stat.st_ino as u64 == ino;
+    matches
+}
+
+/// Syslog priority for a tracing level
+fn priority(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        Level::DEBUG | Level::TRACE => 7,
+    }
+}
+
+/// Convert a tracing field name to a valid journal field name
+///
+/// Journal field names consist of uppercase letters, digits and
+/// underscores, must not start with a digit and may not start with an
+/// underscore (those are reserved for trusted fields).
+pub fn field_name(name: &str) -> Option<String> {
+    let mut out: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let trimmed = out.trim_start_matches(|c: char| c == '_' || c.is_ascii_digit());
+    if trimmed.is_empty() {
+        return None;
+    }
+    out = trimmed.to_string();
+    out.truncate(64);
+    Some(out)
+}
+
+/// Append a field in the native protocol encoding
+///
+/// Values containing newlines use the length-prefixed binary form.
+pub fn put_field(buf: &mut Vec<u8>, name: &str, value: &[u8]) {
+    buf.extend_from_slice(name.as_bytes());
+    if value.contains(&b'\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value);
+    buf.push(b'\n');
+}
+
+/// Collects tracing fields into native protocol encoding
+struct FieldVisitor<'a> {
+    buf: &'a mut Vec<u8>,
+}
+
+impl FieldVisitor<'_> {
+    fn put(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            put_field(self.buf, "MESSAGE", value.as_bytes());
+        } else if let Some(name) = field_name(field.name()) {
+            put_field(self.buf, &name, value.as_bytes());
+        }
+    }
+}
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.put(field, value);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let mut text = String::new();
+        let _ = write!(text, "{:?}", value);
+        self.put(field, &text);
+    }
+}
+
+/// Encoded fields of a span, stored in its extensions
+struct SpanFields(Vec<u8>);
+
+impl<S> Layer<S> for JournaldLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(
+        &self,
+        attrs: &tracing::span::Attributes<'_>,
+        id: &tracing::span::Id,
+        ctx: Context<'_, S>,
+    ) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut buf = Vec::new();
+        attrs.record(&mut FieldVisitor { buf: &mut buf });
+        span.extensions_mut().insert(SpanFields(buf));
+    }
+
+    fn on_record(
+        &self,
+        id: &tracing::span::Id,
+        values: &tracing::span::Record<'_>,
+        ctx: Context<'_, S>,
+    ) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(fields) = extensions.get_mut::<SpanFields>() {
+            values.record(&mut FieldVisitor { buf: &mut fields.0 });
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let meta = event.metadata();
+        let mut buf = Vec::with_capacity(256);
+
+        put_field(
+            &mut buf,
+            "PRIORITY",
+            priority(meta.level()).to_string().as_bytes(),
+        );
+        put_field(&mut buf, "SYSLOG_IDENTIFIER", SYSLOG_IDENTIFIER.as_bytes());
+        put_field(&mut buf, "TARGET", meta.target().as_bytes());
+        if let Some(file) = meta.file() {
+            put_field(&mut buf, "CODE_FILE", file.as_bytes());
+        }
+        if let Some(line) = meta.line() {
+            put_field(&mut buf, "CODE_LINE", line.to_string().as_bytes());
+        }
+
+        // Span fields first, so event fields of the same name come last
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if let Some(fields) = span.extensions().get::<SpanFields>() {
+                    buf.extend_from_slice(&fields.0);
+                }
+            }
+        }
+        event.record(&mut FieldVisitor { buf: &mut buf });
+
+        // Logging must never fail the caller; entries too large for a
+        // single datagram are dropped
+        let _ = self.socket.send(&buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_field_name() {
+        assert_eq!(field_name("voltage").as_deref(), Some("VOLTAGE"));
+        assert_eq!(field_name("error.kind").as_deref(), Some("ERROR_KIND"));
+        assert_eq!(field_name("_private").as_deref(), Some("PRIVATE"));
+        assert_eq!(field_name("1st").as_deref(), Some("ST"));
+        assert_eq!(field_name("__"), None);
+    }
+
+    #[test]
+    fn test_put_field() {
+        let mut buf = Vec::new();
+        put_field(&mut buf, "MESSAGE", b"hello");
+        assert_eq!(buf, b"MESSAGE=hello\n");
+
+        let mut buf = Vec::new();
+        put_field(&mut buf, "MESSAGE", b"a\nb");
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\n");
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn test_layer_sends_structured_fields() {
+        let dir = std::env::temp_dir().join(format!("halpid-journald-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("socket");
+        let _ = std::fs::remove_file(&path);
+        let journal = UnixDatagram::bind(&path).unwrap();
+
+        let layer = JournaldLayer::connect_to(&path).unwrap();
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("i2c", register = "0x20");
+            let _guard = span.enter();
+            tracing::warn!(voltage = 8.5, state = ?"Blackout", "Detected blackout");
+        });
+
+        let mut buf = [0u8; 4096];
+        let n = journal.recv(&mut buf).unwrap();
+        let entry = String::from_utf8_lossy(&buf[..n]);
+        assert!(entry.contains("PRIORITY=4\n"));
+        assert!(entry.contains("SYSLOG_IDENTIFIER=halpid\n"));
+        assert!(entry.contains("MESSAGE=Detected blackout\n"));
+        assert!(entry.contains("VOLTAGE=8.5\n"));
+        assert!(entry.contains("STATE=\"Blackout\"\n"));
+        assert!(entry.contains("REGISTER=0x20\n"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
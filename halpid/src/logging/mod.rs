@@ -0,0 +1,129 @@
+//! Logging setup
+//!
+//! When the daemon runs under systemd with stderr connected to the journal,
+//! log events are sent to journald directly with their structured fields.
+//! Otherwise they are written to stderr as text or JSON lines. Either way,
+//! events can additionally be written to a rotating log file in the same
+//! format.
+//!
+//! With `i2c-trace`, every I2C transfer is logged at trace level under
+//! [`TRACE_TARGET`]; with `i2c-trace-file`, those events go to a separate
+//! rotating file only, keeping the main log readable. The API access log
+//! works the same way under [`ACCESS_TARGET`] with `access-log` and
+//! `access-log-file`, at info level.
+
+pub mod file;
+pub mod journald;
+pub mod json;
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::Level;
+use tracing_subscriber::filter::{Targets, filter_fn};
+use tracing_subscriber::{EnvFilter, Layer, fmt, layer::SubscriberExt, util::SubscriberInitExt};
+
+use halpi_common::config::{LogFormat, LoggingConfig};
+
+use file::RotatingFile;
+use journald::JournaldLayer;
+use json::JsonLayer;
+
+use crate::i2c::device::TRACE_TARGET;
+use crate::server::app::ACCESS_TARGET;
+
+/// Install the global tracing subscriber
+///
+/// The filter comes from `RUST_LOG` and defaults to `halpid=info`; I2C
+/// transfer tracing enables [`TRACE_TARGET`] and the access log
+/// [`ACCESS_TARGET`] on top of it.
+pub fn init(config: &LoggingConfig) {
+    let mut filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| "halpid=info".into());
+    if config.i2c_trace || config.i2c_trace_file.is_some() {
+        let directive = format!("{}=trace", TRACE_TARGET)
+            .parse()
+            .expect("valid filter directive");
+        filter = filter.add_directive(directive);
+    }
+    if config.access_log_enabled() {
+        let directive = format!("{}=info", ACCESS_TARGET)
+            .parse()
+            .expect("valid filter directive");
+        filter = filter.add_directive(directive);
+    }
+
+    let journald = journald::stderr_is_journal()
+        .then(JournaldLayer::connect)
+        .and_then(Result::ok);
+    let to_stderr = journald.is_none();
+    let as_json = config.format == LogFormat::Json;
+    let text = (to_stderr && !as_json).then(fmt::layer);
+    let json = (to_stderr && as_json).then(|| JsonLayer::new(std::io::stderr));
+
+    let (log_file, file_error) = open_file(&config.file, config);
+    let (text_file, json_file) = match log_file {
+        Some(file) if as_json => (None, Some(JsonLayer::new(Mutex::new(file)))),
+        Some(file) => (
+            Some(fmt::layer().with_ansi(false).with_writer(Mutex::new(file))),
+            None,
+        ),
+        None => (None, None),
+    };
+
+    let (trace_file, trace_error) = open_file(&config.i2c_trace_file, config);
+    let (access_file, access_error) = open_file(&config.access_log_file, config);
+    // Transfer traces and the access log go either to their own file or to
+    // the main outputs
+    let separate_trace = trace_file.is_some();
+    let separate_access = access_file.is_some();
+    let trace_layer = trace_file.map(|file| {
+        fmt::layer()
+            .with_ansi(false)
+            .with_writer(Mutex::new(file))
+            .with_filter(Targets::new().with_target(TRACE_TARGET, Level::TRACE))
+    });
+    let access_layer = access_file.map(|file| {
+        fmt::layer()
+            .with_ansi(false)
+            .with_writer(Mutex::new(file))
+            .with_filter(Targets::new().with_target(ACCESS_TARGET, Level::INFO))
+    });
+    let outputs = Layer::and_then(journald, text)
+        .and_then(json)
+        .and_then(text_file)
+        .and_then(json_file)
+        .with_filter(filter_fn(move |metadata| {
+            !(separate_trace && metadata.target() == TRACE_TARGET
+                || separate_access && metadata.target() == ACCESS_TARGET)
+        }));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(outputs)
+        .with(trace_layer)
+        .with(access_layer)
+        .init();
+
+    if let Some((path, e)) = file_error {
+        tracing::warn!("Failed to open log file {}: {}", path.display(), e);
+    }
+    if let Some((path, e)) = trace_error {
+        tracing::warn!("Failed to open I2C trace file {}: {}", path.display(), e);
+    }
+    if let Some((path, e)) = access_error {
+        tracing::warn!("Failed to open access log {}: {}", path.display(), e);
+    }
+}
+
+/// Open the rotating file at `path`, if set, returning the error otherwise
+fn open_file<'a>(
+    path: &'a Option<PathBuf>,
+    config: &LoggingConfig,
+) -> (Option<RotatingFile>, Option<(&'a Path, std::io::Error)>) {
+    match path {
+        Some(path) => match RotatingFile::open(path, config) {
+            Ok(file) => (Some(file), None),
+            Err(e) => (None, Some((path.as_path(), e))),
+        },
+        None => (None, None),
+    }
+}
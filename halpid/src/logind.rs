@@ -0,0 +1,99 @@
+//! systemd-logind shutdown integration
+//!
+//! Powering off through `org.freedesktop.login1.Manager` rather than a
+//! poweroff command lets logind honour other inhibitors and lets systemd
+//! order the shutdown of units, including flushing the journal. While a
+//! blackout shutdown is pending, the daemon also holds a delay inhibitor
+//! lock, so a shutdown started by someone else waits for the pre-shutdown
+//! hook and grace period.
+
+use anyhow::{Context, Result};
+use std::os::fd::OwnedFd;
+use tokio::time::Duration;
+
+use crate::dbus::{Connection, Message, Value};
+
+/// Bus name of systemd-logind
+pub const BUS_NAME: &str = "org.freedesktop.login1";
+
+const MANAGER_PATH: &str = "/org/freedesktop/login1";
+
+const MANAGER_INTERFACE: &str = "org.freedesktop.login1.Manager";
+
+/// Longest wait for logind, which is called from the state machine loop
+const CALL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Take a delay inhibitor lock on shutdown
+///
+/// The lock is released when the returned descriptor is closed. logind
+/// delays a shutdown for at most its `InhibitDelayMaxSec` while the lock is
+/// held.
+///
+/// # Errors
+/// Returns an error if logind is unreachable or refuses the lock.
+pub async fn inhibit_shutdown(why: &str) -> Result<OwnedFd> {
+    with_timeout(inhibit(why)).await
+}
+
+async fn inhibit(why: &str) -> Result<OwnedFd> {
+    let mut conn = Connection::system().await?;
+    let (reply, fds) = conn
+        .call_with_fds(manager_call("Inhibit").with_body(vec![
+            Value::Str("shutdown".into()),
+            Value::Str("halpid".into()),
+            Value::Str(why.into()),
+            Value::Str("delay".into()),
+        ]))
+        .await
+        .context("logind Inhibit failed")?;
+    let index = match reply.body.first() {
+        Some(Value::U32(index)) => *index as usize,
+        _ => anyhow::bail!("logind Inhibit returned no file descriptor"),
+    };
+    fds.into_iter()
+        .nth(index)
+        .context("logind Inhibit returned no file descriptor")
+}
+
+/// Power off the system through logind
+///
+/// # Errors
+/// Returns an error if logind is unreachable or refuses the request.
+pub async fn power_off() -> Result<()> {
+    with_timeout(manager_action("PowerOff")).await
+}
+
+/// Reboot the system through logind
+///
+/// # Errors
+/// Returns an error if logind is unreachable or refuses the request.
+pub async fn reboot() -> Result<()> {
+    with_timeout(manager_action("Reboot")).await
+}
+
+/// Hibernate the system through logind
+///
+/// # Errors
+/// Returns an error if logind is unreachable or refuses the request.
+pub async fn hibernate() -> Result<()> {
+    with_timeout(manager_action("Hibernate")).await
+}
+
+/// Call a Manager power method without interactive authorization
+async fn manager_action(member: &str) -> Result<()> {
+    let mut conn = Connection::system().await?;
+    conn.call(manager_call(member).with_body(vec![Value::Bool(false)]))
+        .await
+        .with_context(|| format!("logind {} failed", member))?;
+    Ok(())
+}
+
+async fn with_timeout<T>(call: impl Future<Output = Result<T>>) -> Result<T> {
+    tokio::time::timeout(CALL_TIMEOUT, call)
+        .await
+        .context("logind did not respond")?
+}
+
+fn manager_call(member: &str) -> Message {
+    Message::method_call(BUS_NAME, MANAGER_PATH, MANAGER_INTERFACE, member)
+}
@@ -1,23 +1,50 @@
 pub mod daemon;
+pub mod dbus;
+pub mod drift;
+pub mod estimate;
+pub mod events;
+pub mod gpio;
+pub mod health;
+pub mod hooks;
+pub mod http_client;
 pub mod i2c;
+pub mod identify;
+pub mod influx;
+pub mod led_night;
+pub mod logging;
+pub mod logind;
+pub mod metrics;
+pub mod migrate;
+pub mod n2k;
+pub mod nut;
+pub mod selftest;
 pub mod server;
+pub mod snmp;
 pub mod state_machine;
+pub mod stats;
+pub mod sun;
+pub mod tasks;
+pub mod upower;
+pub mod webhooks;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::{Mutex, RwLock};
-use tracing::{error, info};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
 
-use halpi_common::config::Config;
+use halpi_common::config::{
+    Config, DEFAULT_DEVICE_ID, LogFormat, LoggingConfig, PartialConfig, UsbDefaultsConfig,
+};
 
-use i2c::HalpiDevice;
+use daemon::kernel_watchdog::KernelWatchdog;
+use i2c::DeviceHandle;
 use server::app::AppState;
 use state_machine::StateMachine;
+use tasks::RestartPolicy;
 
 /// HALPI2 power monitor and watchdog daemon
-#[derive(Parser)]
+#[derive(Parser, Clone)]
 #[command(name = "halpid")]
 #[command(about = "HALPI2 power monitor and watchdog daemon", long_about = None)]
 #[command(version)]
@@ -34,6 +61,10 @@ struct Cli {
     #[arg(long, value_parser = clap::value_parser!(u8))]
     i2c_addr: Option<u8>,
 
+    /// I2C device node, instead of the bus number
+    #[arg(long, value_name = "PATH", conflicts_with = "i2c_bus")]
+    i2c_device: Option<PathBuf>,
+
     /// Unix socket path
     #[arg(long)]
     socket: Option<PathBuf>,
@@ -49,58 +80,192 @@ struct Cli {
     /// Poweroff command (empty string for dry-run)
     #[arg(long)]
     poweroff: Option<String>,
+
+    /// Log line format: text or json
+    #[arg(long, value_name = "FORMAT")]
+    log_format: Option<LogFormat>,
+
+    /// Log every I2C transfer at trace level
+    #[arg(long)]
+    i2c_trace: bool,
+
+    /// Write I2C transfer traces to this file instead of the main log
+    #[arg(long, value_name = "FILE")]
+    i2c_trace_file: Option<PathBuf>,
+
+    /// Simulate the controller instead of using the I2C bus; host power
+    /// actions become dry runs
+    #[arg(long)]
+    simulate: bool,
+
+    /// Play back this scenario file in the simulated controller
+    #[arg(long, value_name = "FILE", requires = "simulate")]
+    scenario: Option<PathBuf>,
+
+    /// Append every I2C transfer to this file, for replay with --replay
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Replay I2C transfers recorded with --record instead of using the
+    /// I2C bus; host power actions become dry runs
+    #[arg(long, value_name = "FILE", conflicts_with = "simulate")]
+    replay: Option<PathBuf>,
+
+    /// Validate the configuration and exit
+    #[arg(long)]
+    check_config: bool,
+
+    /// Print the effective configuration (yaml or json) and exit
+    #[arg(long, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "yaml")]
+    dump_config: Option<DumpFormat>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Commands that run instead of the daemon
+#[derive(Subcommand, Clone)]
+enum Command {
+    /// Convert a Python halpid 4.x configuration file and exit
+    MigrateConfig {
+        /// Configuration file of the Python daemon
+        #[arg(value_name = "OLD_CONF")]
+        input: PathBuf,
+
+        /// Write the new configuration to this file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+
+        /// Overwrite the output file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// Output format of `--dump-config`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DumpFormat {
+    Yaml,
+    Json,
+}
+
+impl std::str::FromStr for DumpFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "yaml" => Ok(DumpFormat::Yaml),
+            "json" => Ok(DumpFormat::Json),
+            other => Err(format!(
+                "unknown format '{}' (expected 'yaml' or 'json')",
+                other
+            )),
+        }
+    }
+}
+
+impl Cli {
+    /// The options that override configuration file settings
+    fn overrides(&self) -> PartialConfig {
+        PartialConfig {
+            i2c_bus: self.i2c_bus,
+            i2c_addr: self.i2c_addr,
+            i2c_device: self.i2c_device.clone(),
+            socket: self.socket.clone(),
+            blackout_time_limit: self.blackout_time_limit,
+            blackout_voltage_limit: self.blackout_voltage_limit,
+            poweroff: self.poweroff.clone(),
+            ..Default::default()
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() {
+    let cli = Cli::parse();
+
+    if let Some(Command::MigrateConfig {
+        input,
+        output,
+        force,
+    }) = &cli.command
+    {
+        std::process::exit(migrate_config(input, output.as_deref(), *force));
+    }
+
+    if cli.check_config || cli.dump_config.is_some() {
+        std::process::exit(inspect_config(&cli));
+    }
+
+    // Load configuration before initializing tracing, which it configures;
+    // errors are reported once logging is up
+    let loaded = cli.conf.as_ref().map(Config::load);
+
     // Initialize tracing
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "halpid=info".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    let mut log_config = match &loaded {
+        Some(Ok(c)) => c.logging.clone(),
+        _ => LoggingConfig::default(),
+    };
+    if let Some(format) = cli.log_format {
+        log_config.format = format;
+    }
+    if cli.i2c_trace {
+        log_config.i2c_trace = true;
+    }
+    if let Some(path) = &cli.i2c_trace_file {
+        log_config.i2c_trace_file = Some(path.clone());
+    }
+    logging::init(&log_config);
 
     info!("halpid - HALPI2 power monitor and watchdog daemon");
     info!("Version: {}", env!("CARGO_PKG_VERSION"));
 
-    let cli = Cli::parse();
-
-    // Load configuration
-    let mut config = if let Some(conf_path) = cli.conf {
-        match Config::from_file(&conf_path) {
-            Ok(c) => {
+    let mut config = match loaded {
+        Some(Ok(c)) => {
+            if let Some(conf_path) = &cli.conf {
                 info!("Loaded configuration from {}", conf_path.display());
-                c
-            }
-            Err(e) => {
-                error!("Failed to load configuration: {}", e);
-                std::process::exit(1);
             }
+            c
         }
-    } else {
-        Config::default()
+        Some(Err(e)) => {
+            error!("Failed to load configuration: {}", e);
+            std::process::exit(1);
+        }
+        None => Config::default(),
     };
 
     // Apply CLI overrides
-    if let Some(i2c_bus) = cli.i2c_bus {
-        config.i2c_bus = i2c_bus;
+    config.merge(cli.overrides());
+    // A zero poll interval or a watchdog shorter than it must not start
+    if let Err(e) = config.validate() {
+        error!("Invalid configuration: {}", e);
+        std::process::exit(1);
     }
-    if let Some(i2c_addr) = cli.i2c_addr {
-        config.i2c_addr = i2c_addr;
+    // Without a real controller, nothing may power the system off; the
+    // state machine, the services and reloads keep it that way
+    let offline = cli.simulate || cli.replay.is_some();
+    if offline {
+        config.poweroff.clear();
     }
-    if let Some(socket) = cli.socket {
-        config.socket = Some(socket);
-    }
-    if let Some(blackout_time_limit) = cli.blackout_time_limit {
-        config.blackout_time_limit = blackout_time_limit;
-    }
-    if let Some(blackout_voltage_limit) = cli.blackout_voltage_limit {
-        config.blackout_voltage_limit = blackout_voltage_limit;
+
+    // A device node given by path overrides the bus number
+    if let Some(path) = &config.i2c_device {
+        match i2c::scan::bus_for_path(path) {
+            Ok(bus) => {
+                info!("I2C device {} is bus {}", path.display(), bus);
+                config.i2c_bus = bus;
+            }
+            Err(e) => {
+                error!("Invalid i2c-device: {}", e);
+                std::process::exit(1);
+            }
+        }
     }
-    if let Some(poweroff) = cli.poweroff {
-        config.poweroff = poweroff;
+
+    // Device ids become API paths; reject duplicates before routing
+    if let Err(e) = config.validate_devices() {
+        error!("Invalid configuration: {}", e);
+        std::process::exit(1);
     }
 
     info!(
@@ -108,36 +273,209 @@ async fn main() {
         config.i2c_bus, config.i2c_addr
     );
 
-    // Open I2C device
-    let device = match HalpiDevice::new(config.i2c_bus, config.i2c_addr) {
-        Ok(dev) => {
-            info!("Opened I2C device");
-            Arc::new(Mutex::new(dev))
-        }
+    // Look up the user to run as before opening anything
+    let credentials = match daemon::privileges::Credentials::resolve(
+        config.user.as_deref(),
+        config.group.as_deref(),
+    ) {
+        Ok(credentials) => credentials,
         Err(e) => {
-            error!("Failed to open I2C device: {}", e);
+            error!("Invalid user or group: {}", e);
             std::process::exit(1);
         }
     };
 
+    // Make sure a crash cannot leave the hardware watchdog running
+    let mut watchdog_guard = (!offline).then(|| {
+        daemon::safety::install_panic_hook(config.i2c_bus, config.i2c_addr);
+        daemon::safety::WatchdogGuard::new(config.i2c_bus, config.i2c_addr)
+    });
+
+    // Open I2C device. Without it the daemon still serves the API in a
+    // degraded mode and keeps trying to connect in the background.
+    let record = cli
+        .record
+        .as_deref()
+        .map(|path| match i2c::Recorder::create(path) {
+            Ok(recorder) => {
+                info!("Recording I2C transfers to {}", path.display());
+                recorder
+            }
+            Err(e) => {
+                error!("{:#}", e);
+                std::process::exit(1);
+            }
+        });
+    let options = i2c::DeviceOptions {
+        pec: config.i2c_pec,
+        record,
+    };
+    let device = if cli.simulate {
+        warn!("Simulating the HALPI2 controller; the poweroff command is a dry run");
+        let simulator = match &cli.scenario {
+            Some(path) => match i2c::Scenario::load(path) {
+                Ok(scenario) => {
+                    info!(
+                        "Playing back scenario {} ({} steps)",
+                        path.display(),
+                        scenario.steps.len()
+                    );
+                    i2c::Simulator::with_scenario(scenario)
+                }
+                Err(e) => {
+                    error!("{:#}", e);
+                    std::process::exit(1);
+                }
+            },
+            None => i2c::Simulator::new(),
+        };
+        let mut device = i2c::HalpiDevice::simulated(config.i2c_bus, config.i2c_addr, simulator);
+        device.set_pec(options.pec);
+        device.set_recorder(options.record.clone());
+        DeviceHandle::new(device)
+    } else if let Some(path) = &cli.replay {
+        warn!(
+            "Replaying I2C transfers from {}; the poweroff command is a dry run",
+            path.display()
+        );
+        let replay = match i2c::Replay::load(path, true) {
+            Ok(replay) => replay,
+            Err(e) => {
+                error!("{:#}", e);
+                std::process::exit(1);
+            }
+        };
+        let mut device = i2c::HalpiDevice::replayed(config.i2c_bus, config.i2c_addr, replay);
+        device.set_pec(options.pec);
+        DeviceHandle::new(device)
+    } else {
+        match DeviceHandle::open(config.i2c_bus, config.i2c_addr, options.clone()).await {
+            Ok(device) => {
+                info!("Opened I2C device");
+                device
+            }
+            Err((device, e)) => {
+                warn!("Failed to open I2C device, starting without it: {}", e);
+                // Point at the right bus or address if the controller answers
+                // elsewhere
+                let (bus, addr) = (config.i2c_bus, config.i2c_addr);
+                tasks::spawn("device-scan", async move {
+                    let detected = tokio::task::spawn_blocking(move || {
+                        i2c::scan::scan(&i2c::scan::candidate_addresses(addr))
+                    })
+                    .await?;
+                    if let Some(hint) = i2c::scan::suggestion(&detected, bus, addr) {
+                        warn!("{}", hint);
+                    }
+                    Ok(())
+                });
+                let handle = device.clone();
+                tasks::spawn("device-connect", async move {
+                    handle.reconnect(i2c::handle::RECONNECT_INTERVAL).await;
+                    Ok(())
+                });
+                device
+            }
+        }
+    };
+
+    spawn_usb_defaults(
+        DEFAULT_DEVICE_ID.to_string(),
+        device.clone(),
+        config.usb_defaults,
+    );
+    let extra_devices = open_extra_devices(&config.devices, options).await;
+
+    // The Pi's own watchdog, disabled again on exit like the HALPI2 one
+    let kernel_watchdog = if config.kernel_watchdog.enabled {
+        let path = &config.kernel_watchdog.device;
+        match KernelWatchdog::open(path, config.kernel_watchdog.timeout) {
+            Ok(watchdog) => {
+                info!("Opened kernel watchdog {}", path.display());
+                Some(watchdog)
+            }
+            Err(e) => {
+                warn!("Failed to open kernel watchdog {}: {}", path.display(), e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // The state directory keeps the auto-restart setting of a reboot until
+    // the next start; without it, reboots through the API are refused
+    let reboot_marker = (!offline).then(|| {
+        let owner = credentials
+            .as_ref()
+            .map(daemon::privileges::Credentials::uid);
+        if let Err(e) = daemon::reboot::prepare_state_dir(owner) {
+            warn!("Failed to create {}: {}", daemon::reboot::STATE_DIR, e);
+        }
+        PathBuf::from(daemon::reboot::AUTO_RESTART_MARKER)
+    });
+
     let config_arc = Arc::new(RwLock::new(config.clone()));
 
     // Create shared state for HTTP server
-    let app_state = AppState::new(device.clone(), config_arc.clone());
+    let app_state = AppState::new(device.clone(), config_arc.clone())
+        .with_devices(extra_devices)
+        .with_limits(config.api_limits)
+        .with_offline(offline)
+        .with_reboot_marker(reboot_marker.clone());
+
+    // Event bus shared by the state machine, the HTTP server and all exporters
+    let events = app_state.events.clone();
 
     // Get socket path for cleanup
-    let socket_path = config
-        .socket
-        .clone()
-        .unwrap_or_else(|| PathBuf::from("/run/halpid/halpid.sock"));
+    let socket_path = app_state.socket_path().await;
+
+    // Bind the API socket up front so readiness is only reported once
+    // clients can connect
+    let owner = credentials
+        .as_ref()
+        .map(daemon::privileges::Credentials::uid);
+    let listener = match server::app::bind_socket(&app_state, owner)
+        .await
+        .and_then(|listener| Ok(listener.into_std()?))
+    {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Failed to bind API socket: {:#}", e);
+            std::process::exit(1);
+        }
+    };
 
-    // Spawn concurrent tasks
+    // Everything that needs root is open now
+    if let Some(credentials) = &credentials
+        && let Err(e) = credentials.apply()
+    {
+        error!("Failed to drop privileges: {}", e);
+        std::process::exit(1);
+    }
+
+    // Undo the auto-restart a reboot through the API turned on
+    if let Some(marker) = reboot_marker {
+        let device = device.clone();
+        tasks::spawn("auto-restart", async move {
+            daemon::reboot::restore_auto_restart(device, &marker).await
+        });
+    }
+
+    // Spawn concurrent tasks. Failed tasks are restarted by the supervisor;
+    // the HTTP server and the state machine are critical and shut the
+    // daemon down if they keep failing.
     let server_handle = {
         let app_state = app_state.clone();
-        tokio::spawn(async move {
-            info!("Starting HTTP server");
-            if let Err(e) = server::app::run_server(app_state).await {
-                error!("Server error: {}", e);
+        // Restarts serve the socket bound above too, since it cannot be
+        // bound anew once privileges are dropped
+        tasks::supervise("http-server", RestartPolicy::CRITICAL, move || {
+            let app_state = app_state.clone();
+            let listener = listener.try_clone();
+            async move {
+                info!("Starting HTTP server");
+                let listener = tokio::net::UnixListener::from_std(listener?)?;
+                server::app::serve(listener, app_state).await
             }
         })
     };
@@ -145,36 +483,273 @@ async fn main() {
     let state_machine_handle = {
         let device = device.clone();
         let config = config_arc.clone();
-        tokio::spawn(async move {
-            info!("Starting state machine");
-            let mut sm = StateMachine::new(device, config);
-            sm.run().await;
+        let events = events.clone();
+        let status = app_state.status.clone();
+        let kernel_watchdog = kernel_watchdog.clone();
+        tasks::supervise("state-machine", RestartPolicy::CRITICAL, move || {
+            let device = device.clone();
+            let config = config.clone();
+            let events = events.clone();
+            let status = status.clone();
+            let kernel_watchdog = kernel_watchdog.clone();
+            async move {
+                info!("Starting state machine");
+                let mut sm = StateMachine::new(device, config, events, status)
+                    .with_kernel_watchdog(kernel_watchdog)
+                    .with_offline(offline);
+                sm.run().await;
+                Ok(())
+            }
         })
     };
 
+    let services = daemon::services::Services::start(
+        device.clone(),
+        events.clone(),
+        app_state.status.clone(),
+        &config,
+        offline,
+    );
+
+    // SIGHUP reloads the configuration; command line options keep
+    // precedence over the reloaded file
+    {
+        let path = cli.conf.clone();
+        let config = config_arc.clone();
+        tasks::spawn(
+            "config-reload",
+            daemon::signals::reload_on_hangup(path, config, cli.overrides(), services, offline),
+        );
+    }
+
+    // The state machine is running once it has published a sample. Without
+    // a controller there is nothing to wait for: the API is serving in
+    // degraded mode.
+    {
+        let device = device.clone();
+        let events = events.clone();
+        tasks::spawn("systemd-ready", async move {
+            if device.is_present() {
+                events.wait_for_sample().await;
+            }
+            if daemon::notify::ready() {
+                info!("Notified systemd of startup completion");
+            }
+            Ok(())
+        });
+    }
+
     let signal_handle = tokio::spawn(async move {
         daemon::wait_for_signal().await;
     });
 
-    // Wait for any task to complete (signal will finish first on shutdown)
-    tokio::select! {
-        _ = server_handle => {
-            info!("Server task completed");
-        }
-        _ = state_machine_handle => {
-            info!("State machine task completed");
-        }
+    // Wait for a critical task to end or a shutdown signal
+    let failed = tokio::select! {
+        result = server_handle => task_ended("Server", result),
+        result = state_machine_handle => task_ended("State machine", result),
         _ = signal_handle => {
             info!("Signal received, initiating shutdown");
+            false
         }
-    }
+    };
 
     // Run cleanup
-    daemon::signals::cleanup(device, &socket_path).await;
+    if daemon::signals::cleanup(device, &socket_path).await
+        && let Some(guard) = &mut watchdog_guard
+    {
+        guard.defuse();
+    }
+    if let Some(watchdog) = &kernel_watchdog {
+        watchdog.disarm();
+    }
+
+    if failed {
+        error!("Daemon stopped after repeated task failures");
+        drop(watchdog_guard);
+        std::process::exit(1);
+    }
 
     info!("Daemon shutdown complete");
 }
 
+/// Handle `--check-config` and `--dump-config`; returns the exit code
+///
+/// The configuration is loaded as at startup (file, drop-ins and command
+/// line options), but no devices are opened. Validation covers all
+/// sections, not just the ones checked at startup.
+fn inspect_config(cli: &Cli) -> i32 {
+    let mut config = match &cli.conf {
+        Some(path) => match Config::load(path) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("halpid: {}", e);
+                return 1;
+            }
+        },
+        None => Config::default(),
+    };
+    config.merge(cli.overrides());
+
+    if cli.check_config {
+        if let Err(e) = config.validate() {
+            eprintln!("halpid: {}", e);
+            return 1;
+        }
+        if cli.dump_config.is_none() {
+            println!("Configuration is valid");
+        }
+    }
+    if let Some(format) = cli.dump_config {
+        match render_config(&config, format) {
+            Ok(rendered) => print!("{}", rendered),
+            Err(e) => {
+                eprintln!("halpid: failed to serialize configuration: {}", e);
+                return 1;
+            }
+        }
+    }
+    0
+}
+
+/// Serialize `config` for `--dump-config`, with secrets masked
+fn render_config(config: &Config, format: DumpFormat) -> anyhow::Result<String> {
+    let config = config.redacted();
+    Ok(match format {
+        DumpFormat::Yaml => serde_yaml::to_string(&config)?,
+        DumpFormat::Json => serde_json::to_string_pretty(&config)? + "\n",
+    })
+}
+
+/// Handle `migrate-config`; returns the exit code
+///
+/// Warnings about dropped or renamed options go to stderr so that the
+/// migrated configuration can be redirected from stdout.
+fn migrate_config(input: &std::path::Path, output: Option<&std::path::Path>, force: bool) -> i32 {
+    let contents = match std::fs::read_to_string(input) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("halpid: failed to read {}: {}", input.display(), e);
+            return 1;
+        }
+    };
+    let rendered = match migrate::migrate(&contents).and_then(|migration| {
+        for warning in &migration.warnings {
+            eprintln!("halpid: warning: {}", warning);
+        }
+        migration.render(&input.display().to_string())
+    }) {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            eprintln!("halpid: cannot migrate {}: {}", input.display(), e);
+            return 1;
+        }
+    };
+
+    let Some(output) = output else {
+        print!("{}", rendered);
+        return 0;
+    };
+    if output.exists() && !force {
+        eprintln!(
+            "halpid: {} already exists (use --force to overwrite)",
+            output.display()
+        );
+        return 1;
+    }
+    if let Err(e) = std::fs::write(output, rendered) {
+        eprintln!("halpid: failed to write {}: {}", output.display(), e);
+        return 1;
+    }
+    eprintln!("halpid: wrote {}", output.display());
+    0
+}
+
+/// Open the additional controllers
+///
+/// Like the primary controller, a controller that cannot be opened is
+/// connected in the background. Controllers whose device node cannot be
+/// resolved are skipped.
+async fn open_extra_devices(
+    devices: &[halpi_common::config::DeviceConfig],
+    options: i2c::DeviceOptions,
+) -> Vec<(String, DeviceHandle)> {
+    let mut handles = Vec::new();
+    for device in devices {
+        let bus = match &device.i2c_device {
+            Some(path) => match i2c::scan::bus_for_path(path) {
+                Ok(bus) => bus,
+                Err(e) => {
+                    warn!("Skipping device {}: invalid i2c-device: {}", device.id, e);
+                    continue;
+                }
+            },
+            None => device.i2c_bus,
+        };
+        let handle = match DeviceHandle::open(bus, device.i2c_addr, options.clone()).await {
+            Ok(handle) => {
+                info!(
+                    "Opened device {} at bus {}, address 0x{:02X}",
+                    device.id, bus, device.i2c_addr
+                );
+                handle
+            }
+            Err((handle, e)) => {
+                warn!(
+                    "Failed to open device {}, starting without it: {}",
+                    device.id, e
+                );
+                let reconnect = handle.clone();
+                tasks::spawn("device-connect", async move {
+                    reconnect.reconnect(i2c::handle::RECONNECT_INTERVAL).await;
+                    Ok(())
+                });
+                handle
+            }
+        };
+        spawn_usb_defaults(device.id.clone(), handle.clone(), device.usb_defaults);
+        handles.push((device.id.clone(), handle));
+    }
+    handles
+}
+
+/// Apply the configured USB port defaults once the controller is connected
+fn spawn_usb_defaults(id: String, device: DeviceHandle, defaults: UsbDefaultsConfig) {
+    if defaults.is_empty() {
+        return;
+    }
+    tasks::spawn("usb-defaults", async move {
+        let port_bits = device
+            .with_present(move |device| {
+                let port_bits = defaults.apply(device.get_usb_port_state()?);
+                device.set_usb_port_state(port_bits)?;
+                Ok::<_, i2c::I2cError>(port_bits)
+            })
+            .await??;
+        info!(
+            "Applied USB defaults to device {}: port state {:04b}",
+            id, port_bits
+        );
+        Ok(())
+    });
+}
+
+fn task_ended(name: &str, result: Result<anyhow::Result<()>, tokio::task::JoinError>) -> bool {
+    match result {
+        Ok(Ok(())) => {
+            info!("{} task completed", name);
+            false
+        }
+        Ok(Err(e)) => {
+            error!("{} task failed: {:#}", name, e);
+            true
+        }
+        Err(e) => {
+            error!("{} task supervisor ended: {}", name, e);
+            true
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -192,10 +767,12 @@ mod tests {
         assert!(cli.conf.is_none());
         assert!(cli.i2c_bus.is_none());
         assert!(cli.i2c_addr.is_none());
+        assert!(cli.i2c_device.is_none());
         assert!(cli.socket.is_none());
         assert!(cli.blackout_time_limit.is_none());
         assert!(cli.blackout_voltage_limit.is_none());
         assert!(cli.poweroff.is_none());
+        assert!(cli.log_format.is_none());
     }
 
     #[test]
@@ -222,6 +799,44 @@ mod tests {
         assert_eq!(cli.i2c_addr, Some(109)); // 0x6D = 109
     }
 
+    #[test]
+    fn test_cli_i2c_trace() {
+        let cli = Cli::try_parse_from(["halpid", "--i2c-trace"]).unwrap();
+        assert!(cli.i2c_trace);
+        assert!(cli.i2c_trace_file.is_none());
+
+        let cli =
+            Cli::try_parse_from(["halpid", "--i2c-trace-file", "/tmp/i2c-trace.log"]).unwrap();
+        assert_eq!(
+            cli.i2c_trace_file,
+            Some(PathBuf::from("/tmp/i2c-trace.log"))
+        );
+    }
+
+    #[test]
+    fn test_cli_record_replay() {
+        let cli = Cli::try_parse_from(["halpid", "--record", "/tmp/i2c.jsonl"]).unwrap();
+        assert_eq!(cli.record, Some(PathBuf::from("/tmp/i2c.jsonl")));
+
+        let cli = Cli::try_parse_from(["halpid", "--replay", "/tmp/i2c.jsonl"]).unwrap();
+        assert_eq!(cli.replay, Some(PathBuf::from("/tmp/i2c.jsonl")));
+
+        assert!(Cli::try_parse_from(["halpid", "--replay", "a", "--record", "b"]).is_err());
+        assert!(Cli::try_parse_from(["halpid", "--replay", "a", "--simulate"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_i2c_device() {
+        let cli = Cli::try_parse_from(["halpid", "--i2c-device", "/dev/i2c-usb"]).unwrap();
+        assert_eq!(cli.i2c_device, Some(PathBuf::from("/dev/i2c-usb")));
+
+        // Either the bus number or the device node
+        assert!(
+            Cli::try_parse_from(["halpid", "--i2c-bus", "1", "--i2c-device", "/dev/i2c-1"])
+                .is_err()
+        );
+    }
+
     #[test]
     fn test_cli_socket() {
         let cli = Cli::try_parse_from(["halpid", "--socket", "/run/halpid/halpid.sock"]).unwrap();
@@ -281,4 +896,75 @@ mod tests {
         assert_eq!(cli.blackout_voltage_limit, Some(9.0));
         assert_eq!(cli.poweroff, Some("/sbin/poweroff".to_string()));
     }
+
+    #[test]
+    fn test_cli_log_format() {
+        let cli = Cli::try_parse_from(["halpid", "--log-format", "json"]).unwrap();
+        assert_eq!(cli.log_format, Some(LogFormat::Json));
+
+        assert!(Cli::try_parse_from(["halpid", "--log-format", "xml"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_inspect_config() {
+        let cli = Cli::try_parse_from(["halpid", "--check-config"]).unwrap();
+        assert!(cli.check_config);
+        assert_eq!(cli.dump_config, None);
+
+        let cli = Cli::try_parse_from(["halpid", "--dump-config"]).unwrap();
+        assert_eq!(cli.dump_config, Some(DumpFormat::Yaml));
+        let cli = Cli::try_parse_from(["halpid", "--dump-config", "json"]).unwrap();
+        assert_eq!(cli.dump_config, Some(DumpFormat::Json));
+        assert!(Cli::try_parse_from(["halpid", "--dump-config", "toml"]).is_err());
+
+        let cli = Cli::try_parse_from(["halpid", "--check-config", "--i2c-bus", "20"]).unwrap();
+        assert_eq!(inspect_config(&cli), 1);
+        let cli = Cli::try_parse_from(["halpid", "--check-config"]).unwrap();
+        assert_eq!(inspect_config(&cli), 0);
+    }
+
+    #[test]
+    fn test_cli_migrate_config() {
+        let cli = Cli::try_parse_from(["halpid", "migrate-config", "old.conf"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::MigrateConfig { ref input, output: None, force: false })
+                if input == &PathBuf::from("old.conf")
+        ));
+        assert!(Cli::try_parse_from(["halpid", "migrate-config"]).is_err());
+
+        let dir = std::env::temp_dir().join(format!("halpid-migrate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("old.conf");
+        let output = dir.join("halpid.conf");
+        std::fs::write(&input, "i2c_bus: 1\nblackout-time-limit: 8\n").unwrap();
+
+        assert_eq!(migrate_config(&input, Some(&output), false), 0);
+        let config = Config::load(&output).unwrap();
+        assert_eq!(config.blackout_time_limit, 8.0);
+        // An existing file is only replaced with --force
+        assert_eq!(migrate_config(&input, Some(&output), false), 1);
+        assert_eq!(migrate_config(&input, Some(&output), true), 0);
+        assert_eq!(migrate_config(&dir.join("missing.conf"), None, false), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_render_config() {
+        let mut config = Config::default();
+        config.influxdb.token = Some("secret-token".to_string());
+        config.nut.password = Some("secret-password".to_string());
+
+        let yaml = render_config(&config, DumpFormat::Yaml).unwrap();
+        assert!(!yaml.contains("secret"));
+        let parsed: Config = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(parsed.i2c_bus, config.i2c_bus);
+        assert_eq!(parsed.influxdb.token.as_deref(), Some("********"));
+
+        let json = render_config(&config, DumpFormat::Json).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed["blackout-voltage-limit"], 9.0);
+        assert_eq!(parsed["nut"]["password"], "********");
+    }
 }
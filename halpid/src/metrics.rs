@@ -0,0 +1,304 @@
+//! Process-wide metrics in Prometheus text format
+//!
+//! Metrics are plain atomics in statics so they can be updated from the
+//! blocking I2C code without threading a registry through every call.
+//! Per-register I2C error counters live in the device itself and are passed
+//! in. [`render`] produces the exposition served at `GET /metrics`.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::i2c::I2cStats;
+
+/// Upper bounds of the I2C latency buckets in seconds
+///
+/// A healthy transfer at 100 kHz takes well under a millisecond; the upper
+/// buckets catch clock stretching and firmware stalls.
+pub const I2C_LATENCY_BUCKETS: [f64; 12] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0,
+];
+
+/// Cumulative latency histogram with fixed buckets
+pub struct Histogram<const N: usize> {
+    bounds: [f64; N],
+    buckets: [AtomicU64; N],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl<const N: usize> Histogram<N> {
+    pub const fn new(bounds: [f64; N]) -> Self {
+        Self {
+            bounds,
+            buckets: [const { AtomicU64::new(0) }; N],
+            count: AtomicU64::new(0),
+            sum_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Record one observation
+    pub fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        if let Some(index) = self.bounds.iter().position(|bound| secs <= *bound) {
+            self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Total number of observations
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Append the `_bucket`, `_sum` and `_count` series for `labels`
+    fn write_series(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, bound, cumulative
+            );
+        }
+        let count = self.count();
+        let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, count);
+        let sum = self.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9;
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, count);
+    }
+}
+
+/// I2C transfer metrics
+pub struct I2cMetrics {
+    pub read: Histogram<12>,
+    pub write: Histogram<12>,
+}
+
+/// Metrics for all I2C transfers made by this process
+pub static I2C: I2cMetrics = I2cMetrics {
+    read: Histogram::new(I2C_LATENCY_BUCKETS),
+    write: Histogram::new(I2C_LATENCY_BUCKETS),
+};
+
+/// HTTP API request counters
+pub struct HttpMetrics {
+    requests: AtomicU64,
+    in_flight: AtomicU64,
+    /// Responses by status class, 1xx to 5xx
+    responses: [AtomicU64; 5],
+}
+
+impl HttpMetrics {
+    /// Record the start of a request
+    pub fn begin(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record the response to a request started with [`begin`](Self::begin)
+    pub fn finish(&self, status: u16) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+        if let Some(class) = (status / 100).checked_sub(1)
+            && let Some(counter) = self.responses.get(class as usize)
+        {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Total number of requests received
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Number of requests currently being handled
+    pub fn in_flight(&self) -> u64 {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Response counts by status class, as `("2xx", count)` pairs
+    pub fn responses(&self) -> [(&'static str, u64); 5] {
+        let classes = ["1xx", "2xx", "3xx", "4xx", "5xx"];
+        std::array::from_fn(|i| (classes[i], self.responses[i].load(Ordering::Relaxed)))
+    }
+}
+
+/// Metrics for the HTTP API
+pub static HTTP: HttpMetrics = HttpMetrics {
+    requests: AtomicU64::new(0),
+    in_flight: AtomicU64::new(0),
+    responses: [const { AtomicU64::new(0) }; 5],
+};
+
+/// Write the `# HELP` and `# TYPE` header for a metric
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Render all metrics in the Prometheus text exposition format
+pub fn render(i2c_stats: &I2cStats) -> String {
+    let mut out = String::new();
+
+    write_header(&mut out, "halpid_build_info", "gauge", "Daemon version.");
+    let _ = writeln!(
+        out,
+        "halpid_build_info{{version=\"{}\"}} 1",
+        env!("CARGO_PKG_VERSION")
+    );
+
+    let name = "halpid_i2c_transfer_duration_seconds";
+    write_header(
+        &mut out,
+        name,
+        "histogram",
+        "Duration of individual I2C transfer attempts.",
+    );
+    I2C.read.write_series(&mut out, name, "op=\"read\"");
+    I2C.write.write_series(&mut out, name, "op=\"write\"");
+
+    let name = "halpid_i2c_transfers_total";
+    write_header(&mut out, name, "counter", "I2C transfers by register.");
+    for (reg, stats) in i2c_stats.registers() {
+        let _ = writeln!(
+            out,
+            "{}{{register=\"0x{:02X}\"}} {}",
+            name, reg, stats.transfers
+        );
+    }
+
+    let name = "halpid_i2c_retries_total";
+    write_header(
+        &mut out,
+        name,
+        "counter",
+        "I2C transfer retries by register.",
+    );
+    for (reg, stats) in i2c_stats.registers() {
+        let _ = writeln!(
+            out,
+            "{}{{register=\"0x{:02X}\"}} {}",
+            name, reg, stats.retries
+        );
+    }
+
+    let name = "halpid_i2c_errors_total";
+    write_header(
+        &mut out,
+        name,
+        "counter",
+        "I2C errors by register and kind.",
+    );
+    for (reg, stats) in i2c_stats.registers() {
+        for (kind, count) in [
+            ("transient", stats.transient_errors),
+            ("permanent", stats.permanent_errors),
+        ] {
+            let _ = writeln!(
+                out,
+                "{}{{register=\"0x{:02X}\",kind=\"{}\"}} {}",
+                name, reg, kind, count
+            );
+        }
+    }
+
+    let name = "halpid_i2c_reopens_total";
+    write_header(
+        &mut out,
+        name,
+        "counter",
+        "I2C device reopens after persistent errors.",
+    );
+    let _ = writeln!(out, "{} {}", name, i2c_stats.reopens());
+
+    let name = "halpid_http_requests_total";
+    write_header(&mut out, name, "counter", "HTTP API requests received.");
+    let _ = writeln!(out, "{} {}", name, HTTP.requests());
+
+    let name = "halpid_http_requests_in_flight";
+    write_header(&mut out, name, "gauge", "HTTP API requests being handled.");
+    let _ = writeln!(out, "{} {}", name, HTTP.in_flight());
+
+    let name = "halpid_http_responses_total";
+    write_header(
+        &mut out,
+        name,
+        "counter",
+        "HTTP API responses by status class.",
+    );
+    for (class, count) in HTTP.responses() {
+        let _ = writeln!(out, "{}{{class=\"{}\"}} {}", name, class, count);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets() {
+        let histogram = Histogram::new([0.001, 0.01]);
+        histogram.observe(Duration::from_micros(500));
+        histogram.observe(Duration::from_millis(5));
+        histogram.observe(Duration::from_millis(50));
+
+        let mut out = String::new();
+        histogram.write_series(&mut out, "test", "op=\"read\"");
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "test_bucket{op=\"read\",le=\"0.001\"} 1",
+                "test_bucket{op=\"read\",le=\"0.01\"} 2",
+                "test_bucket{op=\"read\",le=\"+Inf\"} 3",
+                "test_sum{op=\"read\"} 0.0555",
+                "test_count{op=\"read\"} 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_http_metrics() {
+        let http = HttpMetrics {
+            requests: AtomicU64::new(0),
+            in_flight: AtomicU64::new(0),
+            responses: [const { AtomicU64::new(0) }; 5],
+        };
+        http.begin();
+        http.begin();
+        assert_eq!(http.in_flight(), 2);
+        http.finish(200);
+        http.finish(503);
+        assert_eq!(http.requests(), 2);
+        assert_eq!(http.in_flight(), 0);
+
+        let responses = http.responses();
+        assert_eq!(responses[1], ("2xx", 1));
+        assert_eq!(responses[4], ("5xx", 1));
+    }
+
+    #[test]
+    fn test_render() {
+        let mut stats = I2cStats::new();
+        stats.register_mut(0x20).transfers += 2;
+        stats.register_mut(0x20).permanent_errors += 1;
+        let output = render(&stats);
+        assert!(output.contains("# TYPE halpid_i2c_transfer_duration_seconds histogram\n"));
+        assert!(output.contains("halpid_i2c_transfer_duration_seconds_count{op=\"write\"}"));
+        assert!(output.contains("halpid_http_responses_total{class=\"5xx\"}"));
+        assert!(output.contains("halpid_i2c_transfers_total{register=\"0x20\"} 2\n"));
+        assert!(output.contains("halpid_i2c_reopens_total 0\n"));
+        assert!(
+            output.contains("halpid_i2c_errors_total{register=\"0x20\",kind=\"permanent\"} 1\n")
+        );
+        assert!(output.contains(&format!(
+            "halpid_build_info{{version=\"{}\"}} 1",
+            env!("CARGO_PKG_VERSION")
+        )));
+    }
+}
@@ -0,0 +1,179 @@
+//! Conversion of Python halpid 4.x configuration files
+//!
+//! The Python daemon reads a flat YAML mapping of its command line options.
+//! Keys may be spelled with dashes or, as the Python argument names,
+//! with underscores, and numbers are sometimes quoted. The migration maps
+//! the options to the current schema and drops the ones this daemon does
+//! not support, with a warning for each. A custom `poweroff` command turns
+//! off the logind integration, so the command keeps being run.
+
+use halpi_common::config::{Config, DEFAULT_POWEROFF_COMMAND, PartialConfig};
+use serde_yaml::{Mapping, Value};
+
+/// Options of the Python daemon that map to the same key here
+const SUPPORTED_KEYS: &[&str] = &[
+    "i2c-bus",
+    "i2c-addr",
+    "blackout-time-limit",
+    "blackout-voltage-limit",
+    "socket",
+    "socket-group",
+    "poweroff",
+];
+
+/// Result of migrating a configuration file
+#[derive(Debug)]
+pub struct Migration {
+    /// The settings in the current schema, as they will be written
+    pub settings: Mapping,
+    /// Options that were dropped or changed, one message each
+    pub warnings: Vec<String>,
+}
+
+impl Migration {
+    /// Render the migrated settings as a configuration file
+    pub fn render(&self, source: &str) -> anyhow::Result<String> {
+        let mut out = format!("# Migrated from {} by halpid migrate-config\n", source);
+        if !self.settings.is_empty() {
+            out.push_str(&serde_yaml::to_string(&self.settings)?);
+        }
+        Ok(out)
+    }
+}
+
+/// Migrate the contents of a Python halpid configuration file
+///
+/// # Errors
+/// Returns an error if the file is not a YAML mapping, or if the migrated
+/// settings have the wrong type or do not pass validation.
+pub fn migrate(contents: &str) -> anyhow::Result<Migration> {
+    let old: Value = serde_yaml::from_str(contents)?;
+    let old = match old {
+        Value::Null => Mapping::new(),
+        Value::Mapping(mapping) => mapping,
+        _ => anyhow::bail!("expected a mapping of option names to values"),
+    };
+
+    let mut settings = Mapping::new();
+    let mut warnings = Vec::new();
+    for (key, value) in old {
+        let Some(name) = key.as_str() else {
+            warnings.push(format!("ignoring non-string key {:?}", key));
+            continue;
+        };
+        let key = name.trim().replace('_', "-").to_ascii_lowercase();
+        if !SUPPORTED_KEYS.contains(&key.as_str()) {
+            warnings.push(format!("unsupported option '{}' dropped", name));
+            continue;
+        }
+        // The Python daemon treats a missing value as the default
+        if value.is_null() {
+            continue;
+        }
+        if key != name {
+            warnings.push(format!("option '{}' renamed to '{}'", name, key));
+        }
+        let value = unquote_number(&key, value)?;
+        settings.insert(Value::String(key), value);
+    }
+
+    let custom_poweroff = settings
+        .get("poweroff")
+        .and_then(Value::as_str)
+        .is_some_and(|command| !command.is_empty() && command != DEFAULT_POWEROFF_COMMAND);
+    if custom_poweroff {
+        warnings.push("custom 'poweroff' command kept by setting 'logind: false'".to_string());
+        settings.insert(Value::String("logind".into()), Value::Bool(false));
+    }
+
+    let partial: PartialConfig = serde_yaml::from_value(Value::Mapping(settings.clone()))?;
+    Config::from(partial).validate()?;
+
+    Ok(Migration { settings, warnings })
+}
+
+/// Convert quoted numbers, e.g. `i2c-addr: "0x6d"`, to YAML numbers
+fn unquote_number(key: &str, value: Value) -> anyhow::Result<Value> {
+    let Value::String(text) = &value else {
+        return Ok(value);
+    };
+    let text = text.trim();
+    let number = match key {
+        "i2c-bus" | "i2c-addr" => {
+            let parsed = match text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+                Some(hex) => u8::from_str_radix(hex, 16),
+                None => text.parse(),
+            };
+            let parsed =
+                parsed.map_err(|_| anyhow::anyhow!("{}: invalid value '{}'", key, text))?;
+            Value::Number(parsed.into())
+        }
+        "blackout-time-limit" | "blackout-voltage-limit" => {
+            let parsed: f64 = text
+                .parse()
+                .map_err(|_| anyhow::anyhow!("{}: invalid value '{}'", key, text))?;
+            Value::Number(parsed.into())
+        }
+        _ => return Ok(value),
+    };
+    Ok(number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_supported_keys() {
+        let old = "i2c-bus: 1\ni2c-addr: 0x6D\nblackout-time-limit: 10\nsocket-group: adm\npoweroff: /sbin/poweroff\n";
+        let migration = migrate(old).unwrap();
+        assert!(migration.warnings.is_empty());
+
+        let config: Config = serde_yaml::from_str(&migration.render("old.conf").unwrap()).unwrap();
+        assert_eq!(config.i2c_addr, 0x6D);
+        assert_eq!(config.blackout_time_limit, 10.0);
+        assert_eq!(config.socket_group, "adm");
+        assert!(config.logind);
+    }
+
+    #[test]
+    fn test_migrate_custom_poweroff() {
+        let migration = migrate("poweroff: /usr/local/bin/halt\n").unwrap();
+        assert_eq!(migration.warnings.len(), 1);
+
+        let config: Config = serde_yaml::from_str(&migration.render("old.conf").unwrap()).unwrap();
+        assert_eq!(config.poweroff, "/usr/local/bin/halt");
+        assert!(!config.logind);
+    }
+
+    #[test]
+    fn test_migrate_spellings_and_unsupported() {
+        let old = "i2c_addr: \"0x6e\"\nBlackout_Voltage_Limit: \"8.5\"\nsocket:\ndebug: true\n";
+        let migration = migrate(old).unwrap();
+        assert_eq!(migration.settings.len(), 2);
+        assert_eq!(migration.warnings.len(), 3);
+        assert!(migration.warnings.iter().any(|w| w.contains("'debug'")));
+
+        let rendered = migration.render("old.conf").unwrap();
+        assert!(rendered.starts_with("# Migrated from old.conf"));
+        let config: Config = serde_yaml::from_str(&rendered).unwrap();
+        assert_eq!(config.i2c_addr, 0x6E);
+        assert_eq!(config.blackout_voltage_limit, 8.5);
+        assert_eq!(config.socket, None);
+    }
+
+    #[test]
+    fn test_migrate_invalid() {
+        assert!(migrate("- i2c-bus\n").is_err());
+        assert!(migrate("i2c-addr: \"0xZZ\"\n").is_err());
+        assert!(migrate("blackout-voltage-limit: 30\n").is_err());
+
+        // An empty file migrates to the defaults
+        let migration = migrate("").unwrap();
+        assert!(migration.settings.is_empty());
+        assert_eq!(
+            migration.render("old.conf").unwrap(),
+            "# Migrated from old.conf by halpid migrate-config\n"
+        );
+    }
+}
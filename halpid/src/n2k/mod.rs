@@ -0,0 +1,301 @@
+//! NMEA 2000 transmission over SocketCAN
+//!
+//! Periodically broadcasts the DC supply and supercapacitor status so they
+//! show up on the boat's N2K network:
+//! - PGN 127508 Battery Status for the DC input (voltage, current, PCB temperature)
+//! - PGN 127508 Battery Status for the supercapacitor (voltage)
+//! - PGN 127506 DC Detailed Status for the supercapacitor (state of charge)
+//!
+//! The node claims a source address at startup (PGN 60928) and defends it
+//! against contending claims as required for bus participation.
+
+pub mod pgn;
+pub mod socketcan;
+
+use anyhow::Context;
+use tokio::io::unix::AsyncFd;
+use tokio::time::{Duration, interval};
+use tracing::{debug, warn};
+
+use halpi_common::config::Nmea2000Config;
+use halpi_common::types::Measurements;
+use halpi_common::units::Volts;
+
+use crate::events::EventBus;
+use crate::i2c::DeviceHandle;
+use pgn::{CanId, N2kMessage};
+use socketcan::{CanFrame, CanSocket};
+
+/// Supercap voltage treated as empty when the controller threshold cannot be read
+const FALLBACK_SUPERCAP_EMPTY_VOLTAGE: Volts = Volts(6.0);
+
+/// Source address claim state
+///
+/// Implements the ISO 11783-5 arbitration rule: on a conflicting claim the
+/// node with the numerically lower NAME keeps the address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressClaim {
+    name: u64,
+    address: u8,
+}
+
+impl AddressClaim {
+    /// Start with the preferred address
+    pub fn new(name: u64, preferred: u8) -> Self {
+        Self {
+            name,
+            address: preferred.min(pgn::MAX_CLAIMABLE_ADDRESS),
+        }
+    }
+
+    /// Currently held address (`NULL_ADDRESS` if none could be claimed)
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Our NAME
+    pub fn name(&self) -> u64 {
+        self.name
+    }
+
+    /// Handle an address claim received from another node
+    ///
+    /// Returns true if our claim must be (re)transmitted.
+    pub fn on_claim(&mut self, source: u8, their_name: u64) -> bool {
+        if source != self.address || their_name == self.name {
+            return false;
+        }
+
+        if self.name < their_name {
+            // We win; reassert our claim
+            return true;
+        }
+
+        // We lose; move to the next address
+        self.address = if self.address >= pgn::MAX_CLAIMABLE_ADDRESS {
+            pgn::NULL_ADDRESS
+        } else {
+            self.address + 1
+        };
+        true
+    }
+}
+
+/// Run the NMEA 2000 transmitter until an unrecoverable socket error occurs
+pub async fn run(
+    device: DeviceHandle,
+    events: EventBus,
+    config: Nmea2000Config,
+) -> anyhow::Result<()> {
+    let socket = CanSocket::open(&config.interface)
+        .with_context(|| format!("Failed to open CAN interface {}", config.interface))?;
+    let socket = AsyncFd::new(socket).context("Failed to register CAN socket")?;
+
+    // Identity and thresholds don't change at runtime; read them once the
+    // controller is connected
+    let (unique_number, empty_voltage) = device
+        .with_present(|dev| {
+            let unique_number = dev
+                .get_device_id()
+                .ok()
+                .and_then(|id| u64::from_str_radix(&id, 16).ok())
+                .unwrap_or(0) as u32;
+            let empty_voltage = dev
+                .get_solo_power_off_threshold()
+                .map_or(FALLBACK_SUPERCAP_EMPTY_VOLTAGE, Volts);
+            (unique_number, empty_voltage)
+        })
+        .await?;
+
+    let mut claim = AddressClaim::new(pgn::device_name(unique_number), config.source_address);
+    send_message(
+        &socket,
+        &pgn::address_claim(claim.name()),
+        claim.address(),
+        0,
+    );
+
+    let mut ticker = interval(Duration::from_secs_f64(config.interval));
+    let mut sid: u8 = 0;
+    let mut fast_packet_seq: u8 = 0;
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                if claim.address() == pgn::NULL_ADDRESS {
+                    continue;
+                }
+
+                let Some(sample) = events.current() else {
+                    debug!("Skipping NMEA 2000 transmission: no current measurements");
+                    continue;
+                };
+                let measurements = sample.measurements;
+
+                for msg in status_messages(&measurements, &config, sid, empty_voltage) {
+                    send_message(&socket, &msg, claim.address(), fast_packet_seq);
+                    fast_packet_seq = (fast_packet_seq + 1) & 0x7;
+                }
+                sid = (sid + 1) % 253;
+            }
+
+            guard = socket.readable() => {
+                let mut guard = guard.context("CAN socket failed")?;
+                while let Ok(result) = guard.try_io(|inner| inner.get_ref().recv()) {
+                    let frame = result.context("Failed to read from CAN socket")?;
+                    handle_frame(&socket, &mut claim, &frame);
+                }
+            }
+        }
+    }
+}
+
+/// Build the periodic status messages from a measurement sample
+fn status_messages(
+    m: &Measurements,
+    config: &Nmea2000Config,
+    sid: u8,
+    empty_voltage: Volts,
+) -> Vec<N2kMessage> {
+    let soc = (m.supercap_charge(empty_voltage) * 100.0).round() as u8;
+
+    vec![
+        pgn::battery_status(
+            sid,
+            config.supply_instance,
+            Some(m.dcin_voltage),
+            Some(m.input_current),
+            Some(m.pcb_temperature),
+        ),
+        pgn::battery_status(
+            sid,
+            config.supercap_instance,
+            Some(m.supercap_voltage),
+            None,
+            None,
+        ),
+        pgn::dc_detailed_status(
+            sid,
+            config.supercap_instance,
+            pgn::DC_TYPE_BATTERY,
+            Some(soc),
+            None,
+        ),
+    ]
+}
+
+/// React to address claims and ISO requests from other nodes
+fn handle_frame(socket: &AsyncFd<CanSocket>, claim: &mut AddressClaim, frame: &CanFrame) {
+    let id = CanId::from_raw(frame.id);
+
+    match id.pgn {
+        pgn::PGN_ISO_ADDRESS_CLAIM if frame.len == 8 => {
+            let their_name = u64::from_le_bytes(frame.data);
+            let previous = claim.address();
+            if claim.on_claim(id.source, their_name) {
+                if claim.address() != previous {
+                    warn!(
+                        "NMEA 2000 address {} taken by another node, moving to {}",
+                        previous,
+                        claim.address()
+                    );
+                }
+                send_message(
+                    socket,
+                    &pgn::address_claim(claim.name()),
+                    claim.address(),
+                    0,
+                );
+            }
+        }
+        pgn::PGN_ISO_REQUEST => {
+            let addressed_to_us =
+                id.destination == pgn::BROADCAST_ADDRESS || id.destination == claim.address();
+            if addressed_to_us
+                && pgn::parse_iso_request(frame.payload()) == Some(pgn::PGN_ISO_ADDRESS_CLAIM)
+            {
+                send_message(
+                    socket,
+                    &pgn::address_claim(claim.name()),
+                    claim.address(),
+                    0,
+                );
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Send a message, dropping it if the interface queue is full
+fn send_message(socket: &AsyncFd<CanSocket>, msg: &N2kMessage, source: u8, sequence: u8) {
+    for frame in msg.to_frames(source, sequence) {
+        if let Err(e) = socket.get_ref().send(&frame) {
+            if e.kind() == std::io::ErrorKind::WouldBlock {
+                debug!("CAN transmit queue full, dropping PGN {}", msg.pgn);
+            } else {
+                warn!("Failed to send PGN {}: {}", msg.pgn, e);
+            }
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use halpi_common::types::PowerState;
+    use halpi_common::units::{Amps, Kelvin, Volts};
+
+    #[test]
+    fn test_address_claim_win_keeps_address() {
+        let mut claim = AddressClaim::new(10, 128);
+        assert!(claim.on_claim(128, 20));
+        assert_eq!(claim.address(), 128);
+    }
+
+    #[test]
+    fn test_address_claim_loss_moves_on() {
+        let mut claim = AddressClaim::new(20, 128);
+        assert!(claim.on_claim(128, 10));
+        assert_eq!(claim.address(), 129);
+    }
+
+    #[test]
+    fn test_address_claim_unrelated_address_ignored() {
+        let mut claim = AddressClaim::new(20, 128);
+        assert!(!claim.on_claim(42, 10));
+        assert_eq!(claim.address(), 128);
+    }
+
+    #[test]
+    fn test_address_claim_exhausted() {
+        let mut claim = AddressClaim::new(20, pgn::MAX_CLAIMABLE_ADDRESS);
+        assert!(claim.on_claim(pgn::MAX_CLAIMABLE_ADDRESS, 10));
+        assert_eq!(claim.address(), pgn::NULL_ADDRESS);
+    }
+
+    #[test]
+    fn test_status_messages() {
+        let m = Measurements {
+            dcin_voltage: Volts(12.3),
+            supercap_voltage: Volts(10.0),
+            input_current: Amps(0.8),
+            mcu_temperature: Kelvin(300.0),
+            pcb_temperature: Kelvin(305.0),
+            power_state: PowerState::OperationalCoOp,
+            watchdog_elapsed: 0.0,
+            measured_at: Utc::now(),
+        };
+        let config = Nmea2000Config::default();
+        let msgs = status_messages(&m, &config, 5, Volts(6.0));
+
+        assert_eq!(msgs.len(), 3);
+        assert_eq!(msgs[0].pgn, pgn::PGN_BATTERY_STATUS);
+        assert_eq!(msgs[0].data[0], config.supply_instance);
+        assert_eq!(msgs[1].data[0], config.supercap_instance);
+        assert_eq!(msgs[2].pgn, pgn::PGN_DC_DETAILED_STATUS);
+        assert_eq!(msgs[2].data[1], config.supercap_instance);
+        assert!(msgs[2].data[3] > 0 && msgs[2].data[3] < 100);
+    }
+}
@@ -0,0 +1,356 @@
+//! NMEA 2000 message encoding
+//!
+//! Covers the handful of PGNs the daemon transmits, CAN identifier packing,
+//! and fast-packet segmentation for messages longer than one frame.
+//!
+//! Field layouts follow the public canboat PGN database. Unavailable values
+//! are encoded as all-ones ("data not available") per NMEA 2000 convention.
+
+use halpi_common::units::{Amps, Kelvin, Volts};
+
+use super::socketcan::{CAN_MAX_DATA, CanFrame};
+
+/// ISO Request
+pub const PGN_ISO_REQUEST: u32 = 59904;
+
+/// ISO Address Claim
+pub const PGN_ISO_ADDRESS_CLAIM: u32 = 60928;
+
+/// DC Detailed Status
+pub const PGN_DC_DETAILED_STATUS: u32 = 127506;
+
+/// Battery Status
+pub const PGN_BATTERY_STATUS: u32 = 127508;
+
+/// Destination address for broadcast (global) messages
+pub const BROADCAST_ADDRESS: u8 = 255;
+
+/// Source address used when no address could be claimed
+pub const NULL_ADDRESS: u8 = 254;
+
+/// Highest address a node may claim
+pub const MAX_CLAIMABLE_ADDRESS: u8 = 251;
+
+/// Sequence ID value meaning "not tied to other messages"
+pub const SID_UNAVAILABLE: u8 = 0xFF;
+
+/// DC type code for batteries in PGN 127506
+pub const DC_TYPE_BATTERY: u8 = 0;
+
+// NAME fields identifying the daemon on the bus
+const NAME_MANUFACTURER_CODE: u64 = 2046; // no assigned NMEA manufacturer code
+const NAME_DEVICE_FUNCTION: u64 = 170; // Battery
+const NAME_DEVICE_CLASS: u64 = 35; // Electrical Generation
+const NAME_INDUSTRY_GROUP: u64 = 4; // Marine
+
+/// An NMEA 2000 message before segmentation into CAN frames
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct N2kMessage {
+    pub priority: u8,
+    pub pgn: u32,
+    pub destination: u8,
+    pub data: Vec<u8>,
+}
+
+/// Decoded fields of a 29-bit NMEA 2000 CAN identifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CanId {
+    pub priority: u8,
+    pub pgn: u32,
+    pub source: u8,
+    pub destination: u8,
+}
+
+impl CanId {
+    /// Pack into a 29-bit identifier
+    pub fn to_raw(self) -> u32 {
+        let pf = (self.pgn >> 8) & 0xFF;
+        let mut id = ((self.priority as u32 & 0x7) << 26) | ((self.pgn & 0x3FFFF) << 8);
+        if pf < 240 {
+            // PDU1: the PS field carries the destination address
+            id = (id & !0xFF00) | ((self.destination as u32) << 8);
+        }
+        id | self.source as u32
+    }
+
+    /// Unpack a 29-bit identifier
+    pub fn from_raw(id: u32) -> Self {
+        let priority = ((id >> 26) & 0x7) as u8;
+        let source = (id & 0xFF) as u8;
+        let pf = (id >> 16) & 0xFF;
+        let ps = ((id >> 8) & 0xFF) as u8;
+        let dp = (id >> 24) & 0x3;
+
+        if pf < 240 {
+            Self {
+                priority,
+                pgn: (dp << 16) | (pf << 8),
+                source,
+                destination: ps,
+            }
+        } else {
+            Self {
+                priority,
+                pgn: (dp << 16) | (pf << 8) | ps as u32,
+                source,
+                destination: BROADCAST_ADDRESS,
+            }
+        }
+    }
+}
+
+impl N2kMessage {
+    /// Segment the message into CAN frames sent from `source`
+    ///
+    /// Messages of up to 8 bytes go out as a single frame; longer ones use
+    /// the fast-packet protocol with the given 3-bit sequence counter.
+    pub fn to_frames(&self, source: u8, sequence: u8) -> Vec<CanFrame> {
+        let id = CanId {
+            priority: self.priority,
+            pgn: self.pgn,
+            source,
+            destination: self.destination,
+        }
+        .to_raw();
+
+        if self.data.len() <= CAN_MAX_DATA {
+            let mut payload = [0xFFu8; CAN_MAX_DATA];
+            payload[..self.data.len()].copy_from_slice(&self.data);
+            return vec![CanFrame::new(id, &payload)];
+        }
+
+        let seq = (sequence & 0x7) << 5;
+        let mut frames = Vec::new();
+
+        // First frame: sequence/counter, total length, 6 data bytes
+        let mut first = [0xFFu8; CAN_MAX_DATA];
+        first[0] = seq;
+        first[1] = self.data.len() as u8;
+        let head = self.data.len().min(6);
+        first[2..2 + head].copy_from_slice(&self.data[..head]);
+        frames.push(CanFrame::new(id, &first));
+
+        // Subsequent frames: sequence/counter, 7 data bytes
+        for (counter, chunk) in self.data[head..].chunks(7).enumerate() {
+            let mut frame = [0xFFu8; CAN_MAX_DATA];
+            frame[0] = seq | ((counter as u8 + 1) & 0x1F);
+            frame[1..1 + chunk.len()].copy_from_slice(chunk);
+            frames.push(CanFrame::new(id, &frame));
+        }
+
+        frames
+    }
+}
+
+/// Build the 64-bit ISO NAME for this node
+///
+/// `unique_number` is truncated to its 21-bit field.
+pub fn device_name(unique_number: u32) -> u64 {
+    (unique_number as u64 & 0x1F_FFFF)
+        | (NAME_MANUFACTURER_CODE << 21)
+        | (NAME_DEVICE_FUNCTION << 40)
+        | (NAME_DEVICE_CLASS << 49)
+        | (NAME_INDUSTRY_GROUP << 60)
+        | (1 << 63) // arbitrary address capable
+}
+
+/// PGN 60928 ISO Address Claim
+pub fn address_claim(name: u64) -> N2kMessage {
+    N2kMessage {
+        priority: 6,
+        pgn: PGN_ISO_ADDRESS_CLAIM,
+        destination: BROADCAST_ADDRESS,
+        data: name.to_le_bytes().to_vec(),
+    }
+}
+
+/// Extract the requested PGN from an ISO Request payload
+pub fn parse_iso_request(payload: &[u8]) -> Option<u32> {
+    if payload.len() < 3 {
+        return None;
+    }
+    Some(u32::from_le_bytes([payload[0], payload[1], payload[2], 0]))
+}
+
+/// PGN 127508 Battery Status
+///
+/// * `voltage` - 0.01 V resolution
+/// * `current` - 0.1 A resolution, signed
+/// * `temperature` - 0.01 K resolution
+pub fn battery_status(
+    sid: u8,
+    instance: u8,
+    voltage: Option<Volts>,
+    current: Option<Amps>,
+    temperature: Option<Kelvin>,
+) -> N2kMessage {
+    let mut data = Vec::with_capacity(8);
+    data.push(instance);
+    data.extend_from_slice(&encode_u16(voltage.map(|v| v.0), 0.01).to_le_bytes());
+    data.extend_from_slice(&encode_i16(current.map(|a| a.0), 0.1).to_le_bytes());
+    data.extend_from_slice(&encode_u16(temperature.map(|k| k.0), 0.01).to_le_bytes());
+    data.push(sid);
+
+    N2kMessage {
+        priority: 6,
+        pgn: PGN_BATTERY_STATUS,
+        destination: BROADCAST_ADDRESS,
+        data,
+    }
+}
+
+/// PGN 127506 DC Detailed Status
+///
+/// * `state_of_charge` - percent (0-100)
+/// * `time_remaining` - seconds (1 minute resolution)
+pub fn dc_detailed_status(
+    sid: u8,
+    instance: u8,
+    dc_type: u8,
+    state_of_charge: Option<u8>,
+    time_remaining: Option<f32>,
+) -> N2kMessage {
+    let mut data = Vec::with_capacity(11);
+    data.push(sid);
+    data.push(instance);
+    data.push(dc_type);
+    data.push(state_of_charge.map(|soc| soc.min(100)).unwrap_or(0xFF));
+    data.push(0xFF); // state of health
+    data.extend_from_slice(&encode_u16(time_remaining, 60.0).to_le_bytes());
+    data.extend_from_slice(&0xFFFFu16.to_le_bytes()); // ripple voltage
+    data.extend_from_slice(&0xFFFFu16.to_le_bytes()); // amp hours
+
+    N2kMessage {
+        priority: 6,
+        pgn: PGN_DC_DETAILED_STATUS,
+        destination: BROADCAST_ADDRESS,
+        data,
+    }
+}
+
+/// Encode an unsigned value; out-of-range and missing values become "not available"
+fn encode_u16(value: Option<f32>, resolution: f32) -> u16 {
+    match value {
+        Some(v) if v.is_finite() => {
+            let raw = (v / resolution).round();
+            if (0.0..0xFFFD as f32).contains(&raw) {
+                raw as u16
+            } else {
+                0xFFFF
+            }
+        }
+        _ => 0xFFFF,
+    }
+}
+
+/// Encode a signed value; out-of-range and missing values become "not available"
+fn encode_i16(value: Option<f32>, resolution: f32) -> i16 {
+    match value {
+        Some(v) if v.is_finite() => {
+            let raw = (v / resolution).round();
+            if (i16::MIN as f32..0x7FFD as f32).contains(&raw) {
+                raw as i16
+            } else {
+                0x7FFF
+            }
+        }
+        _ => 0x7FFF,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_can_id_pdu2_round_trip() {
+        let id = CanId {
+            priority: 6,
+            pgn: PGN_BATTERY_STATUS,
+            source: 0x80,
+            destination: BROADCAST_ADDRESS,
+        };
+        let raw = id.to_raw();
+        assert_eq!(raw, 0x19F2_1480);
+        assert_eq!(CanId::from_raw(raw), id);
+    }
+
+    #[test]
+    fn test_can_id_pdu1_carries_destination() {
+        let id = CanId {
+            priority: 6,
+            pgn: PGN_ISO_REQUEST,
+            source: 0x10,
+            destination: 0x80,
+        };
+        let raw = id.to_raw();
+        assert_eq!(raw, 0x18EA_8010);
+        assert_eq!(CanId::from_raw(raw), id);
+    }
+
+    #[test]
+    fn test_battery_status_encoding() {
+        let msg = battery_status(
+            7,
+            1,
+            Some(Volts(12.5)),
+            Some(Amps(-1.5)),
+            Some(Kelvin(298.15)),
+        );
+        assert_eq!(msg.data.len(), 8);
+        assert_eq!(msg.data[0], 1);
+        assert_eq!(u16::from_le_bytes([msg.data[1], msg.data[2]]), 1250);
+        assert_eq!(i16::from_le_bytes([msg.data[3], msg.data[4]]), -15);
+        assert_eq!(u16::from_le_bytes([msg.data[5], msg.data[6]]), 29815);
+        assert_eq!(msg.data[7], 7);
+    }
+
+    #[test]
+    fn test_battery_status_unavailable_fields() {
+        let msg = battery_status(0, 0, Some(Volts(10.0)), None, Some(Kelvin(f32::NAN)));
+        assert_eq!(&msg.data[3..5], &[0xFF, 0x7F]);
+        assert_eq!(&msg.data[5..7], &[0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_single_frame_is_padded() {
+        let msg = address_claim(device_name(1));
+        let frames = msg.to_frames(0x80, 0);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].len, 8);
+    }
+
+    #[test]
+    fn test_fast_packet_segmentation() {
+        let msg = dc_detailed_status(1, 2, DC_TYPE_BATTERY, Some(80), None);
+        assert_eq!(msg.data.len(), 11);
+
+        let frames = msg.to_frames(0x80, 3);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].data[0], 3 << 5);
+        assert_eq!(frames[0].data[1], 11);
+        assert_eq!(&frames[0].data[2..8], &msg.data[..6]);
+        assert_eq!(frames[1].data[0], (3 << 5) | 1);
+        assert_eq!(&frames[1].data[1..6], &msg.data[6..]);
+        assert_eq!(&frames[1].data[6..], &[0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_device_name_fields() {
+        let name = device_name(0xFFFF_FFFF);
+        assert_eq!(name & 0x1F_FFFF, 0x1F_FFFF);
+        assert_eq!((name >> 21) & 0x7FF, NAME_MANUFACTURER_CODE);
+        assert_eq!((name >> 60) & 0x7, NAME_INDUSTRY_GROUP);
+        assert_eq!(name >> 63, 1);
+    }
+
+    #[test]
+    fn test_parse_iso_request() {
+        let payload = PGN_ISO_ADDRESS_CLAIM.to_le_bytes();
+        assert_eq!(
+            parse_iso_request(&payload[..3]),
+            Some(PGN_ISO_ADDRESS_CLAIM)
+        );
+        assert_eq!(parse_iso_request(&[0x00]), None);
+    }
+}
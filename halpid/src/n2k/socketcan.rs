@@ -0,0 +1,170 @@
+//! Minimal raw SocketCAN socket for extended-frame (29-bit) CAN traffic
+//!
+//! NMEA 2000 only uses extended frames, so standard, RTR, and error frames
+//! are filtered out on receive.
+
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+/// Maximum payload of a classic CAN frame
+pub const CAN_MAX_DATA: usize = 8;
+
+/// A classic CAN frame with a 29-bit identifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CanFrame {
+    /// 29-bit extended identifier (without the EFF flag)
+    pub id: u32,
+    /// Frame payload (only the first `len` bytes are valid)
+    pub data: [u8; CAN_MAX_DATA],
+    /// Payload length (0-8)
+    pub len: usize,
+}
+
+impl CanFrame {
+    /// Create a frame from an identifier and up to 8 bytes of payload
+    pub fn new(id: u32, payload: &[u8]) -> Self {
+        let len = payload.len().min(CAN_MAX_DATA);
+        let mut data = [0u8; CAN_MAX_DATA];
+        data[..len].copy_from_slice(&payload[..len]);
+        Self { id, data, len }
+    }
+
+    /// Valid payload bytes
+    pub fn payload(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+/// Non-blocking raw CAN socket bound to a single interface
+pub struct CanSocket {
+    fd: OwnedFd,
+}
+
+impl CanSocket {
+    /// Open a raw CAN socket on the named interface (e.g., `can0`)
+    ///
+    /// # Errors
+    /// Returns an error if the interface does not exist or the socket cannot be bound.
+    pub fn open(interface: &str) -> io::Result<Self> {
+        let name = CString::new(interface)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid interface name"))?;
+
+        let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if ifindex == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let raw_fd = unsafe {
+            libc::socket(
+                libc::PF_CAN,
+                libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                libc::CAN_RAW,
+            )
+        };
+        if raw_fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(raw_fd) };
+
+        let mut addr: libc::sockaddr_can = unsafe { mem::zeroed() };
+        addr.can_family = libc::AF_CAN as libc::sa_family_t;
+        addr.can_ifindex = ifindex as libc::c_int;
+
+        let result = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &addr as *const libc::sockaddr_can as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_can>() as libc::socklen_t,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { fd })
+    }
+
+    /// Send a single extended frame
+    ///
+    /// Returns `WouldBlock` if the interface transmit queue is full.
+    pub fn send(&self, frame: &CanFrame) -> io::Result<()> {
+        let mut raw: libc::can_frame = unsafe { mem::zeroed() };
+        raw.can_id = (frame.id & libc::CAN_EFF_MASK) | libc::CAN_EFF_FLAG;
+        raw.can_dlc = frame.len as u8;
+        raw.data[..frame.len].copy_from_slice(frame.payload());
+
+        let written = unsafe {
+            libc::write(
+                self.fd.as_raw_fd(),
+                &raw as *const libc::can_frame as *const libc::c_void,
+                mem::size_of::<libc::can_frame>(),
+            )
+        };
+        if written < 0 {
+            let err = io::Error::last_os_error();
+            // ENOBUFS is how the CAN stack reports a full transmit queue
+            if err.raw_os_error() == Some(libc::ENOBUFS) {
+                return Err(io::Error::from(io::ErrorKind::WouldBlock));
+            }
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    /// Receive the next extended data frame
+    ///
+    /// Returns `WouldBlock` when no frame is pending.
+    pub fn recv(&self) -> io::Result<CanFrame> {
+        loop {
+            let mut raw: libc::can_frame = unsafe { mem::zeroed() };
+            let read = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    &mut raw as *mut libc::can_frame as *mut libc::c_void,
+                    mem::size_of::<libc::can_frame>(),
+                )
+            };
+            if read < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let is_extended = raw.can_id & libc::CAN_EFF_FLAG != 0;
+            let is_special = raw.can_id & (libc::CAN_RTR_FLAG | libc::CAN_ERR_FLAG) != 0;
+            if !is_extended || is_special {
+                continue;
+            }
+
+            let len = (raw.can_dlc as usize).min(CAN_MAX_DATA);
+            return Ok(CanFrame::new(
+                raw.can_id & libc::CAN_EFF_MASK,
+                &raw.data[..len],
+            ));
+        }
+    }
+}
+
+impl AsRawFd for CanSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_can_frame_truncates_payload() {
+        let frame = CanFrame::new(0x1234, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(frame.len, 8);
+        assert_eq!(frame.payload(), &[1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_open_missing_interface() {
+        assert!(CanSocket::open("halpi-nonexistent0").is_err());
+    }
+}
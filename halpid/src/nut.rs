@@ -0,0 +1,523 @@
+//! Network UPS Tools (NUT) compatible server
+//!
+//! Speaks the `upsd` network protocol (TCP port 3493) so that `upsmon` and
+//! other NUT clients can monitor the HALPI2 like any other UPS. The
+//! supercapacitor is reported as the UPS battery; during a blackout the
+//! status changes to `OB`, and to `OB LB` once the charge runs low or the
+//! controller has started its blackout shutdown, which makes `upsmon`
+//! shut down the clients it protects.
+//!
+//! Only the read-only subset of the protocol is implemented. Instant
+//! commands and writable variables are refused.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::time::{Duration, MissedTickBehavior, interval};
+use tracing::{debug, info, warn};
+
+use halpi_common::config::NutConfig;
+use halpi_common::types::{Measurements, PowerState};
+use halpi_common::units::Volts;
+
+use crate::events::EventBus;
+use crate::i2c::DeviceHandle;
+
+/// Supported network protocol version
+const NETVER: &str = "1.3";
+
+/// How often the shared UPS variables are refreshed
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Longest command line accepted from a client
+const MAX_LINE_LENGTH: usize = 1024;
+
+/// Supercap voltage treated as empty when the controller threshold cannot be read
+const FALLBACK_EMPTY_VOLTAGE: Volts = Volts(6.0);
+
+/// Static device information read once at startup
+#[derive(Debug, Clone, Default)]
+pub struct DeviceInfo {
+    pub serial: String,
+    pub firmware: String,
+    pub empty_voltage: Volts,
+}
+
+/// Current UPS variables, or `None` before the first successful read
+pub type Variables = Option<BTreeMap<&'static str, String>>;
+
+/// Compute the NUT `ups.status` value
+pub fn ups_status(m: &Measurements, charge_percent: f32, low_charge: u8) -> String {
+    let mut flags = Vec::new();
+    match m.power_state {
+        PowerState::BlackoutShutdown => flags.extend(["FSD", "OB", "LB"]),
+        state if state.is_blackout() => {
+            flags.push("OB");
+            if charge_percent < low_charge as f32 {
+                flags.push("LB");
+            }
+        }
+        _ => {
+            flags.push("OL");
+            if charge_percent < 98.0 {
+                flags.push("CHRG");
+            }
+        }
+    }
+    flags.join(" ")
+}
+
+/// Build the UPS variable table from a measurement sample
+pub fn variables(
+    m: &Measurements,
+    info: &DeviceInfo,
+    low_charge: u8,
+) -> BTreeMap<&'static str, String> {
+    let charge = (m.supercap_charge(info.empty_voltage) * 100.0).round();
+
+    let mut vars = BTreeMap::new();
+    vars.insert("battery.charge", format!("{}", charge));
+    vars.insert("battery.charge.low", low_charge.to_string());
+    vars.insert("battery.type", "supercapacitor".to_string());
+    vars.insert("battery.voltage", format!("{:.2}", m.supercap_voltage.0));
+    vars.insert("device.mfr", "Hat Labs".to_string());
+    vars.insert("device.model", "HALPI2".to_string());
+    vars.insert("device.serial", info.serial.clone());
+    vars.insert("device.type", "ups".to_string());
+    vars.insert("driver.name", "halpid".to_string());
+    vars.insert("driver.version", env!("CARGO_PKG_VERSION").to_string());
+    vars.insert("input.current", format!("{:.2}", m.input_current.0));
+    vars.insert("input.voltage", format!("{:.2}", m.dcin_voltage.0));
+    vars.insert("ups.firmware", info.firmware.clone());
+    vars.insert("ups.mfr", "Hat Labs".to_string());
+    vars.insert("ups.model", "HALPI2".to_string());
+    vars.insert("ups.serial", info.serial.clone());
+    vars.insert("ups.status", ups_status(m, charge, low_charge));
+    vars.insert(
+        "ups.temperature",
+        format!("{:.1}", m.pcb_temperature.celsius().0),
+    );
+    vars
+}
+
+/// Per-connection protocol state
+#[derive(Debug, Default)]
+pub struct Session {
+    username: Option<String>,
+    password: Option<String>,
+    logged_in: bool,
+    pub done: bool,
+}
+
+/// Split a command line into words, honoring double quotes and backslash escapes
+fn tokenize(line: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut in_quotes = false;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if in_quotes => current.push(chars.next()?),
+            '"' => {
+                in_quotes = !in_quotes;
+                in_word = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_quotes {
+        return None;
+    }
+    if in_word {
+        words.push(current);
+    }
+    Some(words)
+}
+
+/// Quote a value for a protocol response
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Process one command line and return the response (without trailing newline)
+pub fn handle_command(
+    line: &str,
+    session: &mut Session,
+    vars: &Variables,
+    config: &NutConfig,
+) -> String {
+    let Some(words) = tokenize(line) else {
+        return "ERR INVALID-ARGUMENT".to_string();
+    };
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    let ups = config.ups_name.as_str();
+
+    let known_ups = |name: &str| name == ups;
+    let with_vars = |f: &dyn Fn(&BTreeMap<&'static str, String>) -> String| match vars {
+        Some(vars) => f(vars),
+        None => "ERR DATA-STALE".to_string(),
+    };
+
+    match words.as_slice() {
+        ["VER"] => format!("halpid {} (NUT compatible)", env!("CARGO_PKG_VERSION")),
+        ["NETVER"] => NETVER.to_string(),
+        ["HELP"] => {
+            "Commands: HELP VER NETVER GET LIST USERNAME PASSWORD LOGIN LOGOUT PRIMARY".to_string()
+        }
+        ["STARTTLS"] => "ERR FEATURE-NOT-CONFIGURED".to_string(),
+
+        ["LIST", "UPS"] => format!(
+            "BEGIN LIST UPS\nUPS {} {}\nEND LIST UPS",
+            ups,
+            quote("HALPI2 supercapacitor backup")
+        ),
+        ["LIST", "VAR", name] if known_ups(name) => with_vars(&|vars| {
+            let mut out = format!("BEGIN LIST VAR {}\n", ups);
+            for (var, value) in vars {
+                out.push_str(&format!("VAR {} {} {}\n", ups, var, quote(value)));
+            }
+            out.push_str(&format!("END LIST VAR {}", ups));
+            out
+        }),
+        ["LIST", "RW" | "CMD" | "CLIENT", name] if known_ups(name) => {
+            format!(
+                "BEGIN LIST {kind} {ups}\nEND LIST {kind} {ups}",
+                kind = words[1],
+                ups = ups
+            )
+        }
+        ["LIST", _, _, ..] => "ERR UNKNOWN-UPS".to_string(),
+
+        ["GET", "VAR", name, var] if known_ups(name) => with_vars(&|vars| match vars.get(*var) {
+            Some(value) => format!("VAR {} {} {}", ups, var, quote(value)),
+            None => "ERR VAR-NOT-SUPPORTED".to_string(),
+        }),
+        ["GET", "TYPE", name, var] if known_ups(name) => with_vars(&|vars| match vars.get(*var) {
+            Some(value) if value.parse::<f64>().is_ok() => {
+                format!("TYPE {} {} NUMBER", ups, var)
+            }
+            Some(_) => format!("TYPE {} {} STRING:64", ups, var),
+            None => "ERR VAR-NOT-SUPPORTED".to_string(),
+        }),
+        ["GET", "DESC", name, var] if known_ups(name) => {
+            format!("DESC {} {} {}", ups, var, quote("Description unavailable"))
+        }
+        ["GET", "UPSDESC", name] if known_ups(name) => {
+            format!("UPSDESC {} {}", ups, quote("HALPI2 supercapacitor backup"))
+        }
+        ["GET", "NUMLOGINS", name] if known_ups(name) => format!("NUMLOGINS {} 0", ups),
+        ["GET", _, _, ..] => "ERR UNKNOWN-UPS".to_string(),
+
+        ["USERNAME", user] => {
+            if session.username.is_some() {
+                return "ERR ALREADY-SET-USERNAME".to_string();
+            }
+            session.username = Some(user.to_string());
+            "OK".to_string()
+        }
+        ["PASSWORD", password] => {
+            if session.password.is_some() {
+                return "ERR ALREADY-SET-PASSWORD".to_string();
+            }
+            session.password = Some(password.to_string());
+            "OK".to_string()
+        }
+        ["LOGIN", name] | ["PRIMARY", name] | ["MASTER", name] => {
+            if !known_ups(name) {
+                return "ERR UNKNOWN-UPS".to_string();
+            }
+            if let Err(e) = check_credentials(session, config) {
+                return e.to_string();
+            }
+            match words[0] {
+                "LOGIN" => {
+                    if session.logged_in {
+                        return "ERR ALREADY-LOGGED-IN".to_string();
+                    }
+                    session.logged_in = true;
+                    "OK".to_string()
+                }
+                "PRIMARY" => "OK PRIMARY-GRANTED".to_string(),
+                _ => "OK MASTER-GRANTED".to_string(),
+            }
+        }
+        ["LOGOUT"] => {
+            session.done = true;
+            "OK Goodbye".to_string()
+        }
+
+        ["SET", ..] | ["INSTCMD", ..] | ["FSD", ..] => "ERR ACCESS-DENIED".to_string(),
+        [] => "ERR UNKNOWN-COMMAND".to_string(),
+        [
+            "GET" | "LIST" | "USERNAME" | "PASSWORD" | "LOGIN" | "PRIMARY" | "MASTER",
+            ..,
+        ] => "ERR INVALID-ARGUMENT".to_string(),
+        _ => "ERR UNKNOWN-COMMAND".to_string(),
+    }
+}
+
+/// Check the session credentials against the configured user, if any
+fn check_credentials(session: &Session, config: &NutConfig) -> Result<(), &'static str> {
+    let (Some(user), Some(password)) = (&config.username, &config.password) else {
+        return Ok(());
+    };
+    match (&session.username, &session.password) {
+        (None, _) => Err("ERR USERNAME-REQUIRED"),
+        (_, None) => Err("ERR PASSWORD-REQUIRED"),
+        (Some(u), Some(p)) if u == user && p == password => Ok(()),
+        _ => Err("ERR ACCESS-DENIED"),
+    }
+}
+
+/// Serve one client connection
+async fn serve_client(
+    stream: TcpStream,
+    mut vars: watch::Receiver<Variables>,
+    config: Arc<NutConfig>,
+) -> Result<()> {
+    let (read_half, mut write_half) = stream.into_split();
+    let mut lines = BufReader::new(read_half);
+    let mut session = Session::default();
+    let mut line = String::new();
+
+    while !session.done {
+        line.clear();
+        let n = (&mut lines)
+            .take(MAX_LINE_LENGTH as u64)
+            .read_line(&mut line)
+            .await?;
+        if n == 0 {
+            break;
+        }
+        if !line.ends_with('\n') && n >= MAX_LINE_LENGTH {
+            write_half.write_all(b"ERR INVALID-ARGUMENT\n").await?;
+            break;
+        }
+
+        let response = {
+            let current = vars.borrow_and_update();
+            handle_command(line.trim_end(), &mut session, &current, &config)
+        };
+        write_half.write_all(response.as_bytes()).await?;
+        write_half.write_all(b"\n").await?;
+    }
+
+    Ok(())
+}
+
+/// Periodically refresh the shared UPS variables
+async fn refresh_snapshot(
+    events: EventBus,
+    info: DeviceInfo,
+    low_charge: u8,
+    tx: watch::Sender<Variables>,
+) {
+    let mut ticker = interval(POLL_INTERVAL);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+        // Without a current sample clients see DATA-STALE
+        let vars = events
+            .current()
+            .map(|sample| variables(&sample.measurements, &info, low_charge));
+        tx.send_replace(vars);
+    }
+}
+
+/// Run the NUT server until the listener fails
+pub async fn run(device: DeviceHandle, events: EventBus, config: NutConfig) -> Result<()> {
+    let listener = TcpListener::bind(&config.listen)
+        .await
+        .with_context(|| format!("Failed to bind NUT server to {}", config.listen))?;
+    info!(
+        "NUT server listening on {} (UPS name '{}')",
+        config.listen, config.ups_name
+    );
+
+    // Identity is only known once the controller is connected
+    let info = device
+        .with_present(|dev| DeviceInfo {
+            serial: dev.get_device_id().unwrap_or_default(),
+            firmware: dev
+                .get_firmware_version()
+                .map(|v| v.to_string())
+                .unwrap_or_default(),
+            empty_voltage: dev
+                .get_solo_power_off_threshold()
+                .map_or(FALLBACK_EMPTY_VOLTAGE, Volts),
+        })
+        .await?;
+
+    let (tx, rx) = watch::channel(None);
+    tokio::spawn(refresh_snapshot(events, info, config.low_charge, tx));
+
+    let config = Arc::new(config);
+    loop {
+        let (stream, peer) = listener.accept().await.context("NUT accept failed")?;
+        debug!("NUT client connected from {}", peer);
+        let vars = rx.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_client(stream, vars, config).await {
+                warn!("NUT client {} error: {}", peer, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use halpi_common::config::DEFAULT_NUT_LOW_CHARGE;
+    use halpi_common::units::{Amps, Kelvin, Volts};
+
+    fn sample(power_state: PowerState, supercap_voltage: f32) -> Measurements {
+        Measurements {
+            dcin_voltage: Volts(12.0),
+            supercap_voltage: Volts(supercap_voltage),
+            input_current: Amps(0.5),
+            mcu_temperature: Kelvin(300.0),
+            pcb_temperature: Kelvin(300.0),
+            power_state,
+            watchdog_elapsed: 0.0,
+            measured_at: Utc::now(),
+        }
+    }
+
+    fn vars_for(m: &Measurements) -> Variables {
+        let info = DeviceInfo {
+            serial: "abc".to_string(),
+            firmware: "3.1.0".to_string(),
+            empty_voltage: Volts(6.0),
+        };
+        Some(variables(m, &info, DEFAULT_NUT_LOW_CHARGE))
+    }
+
+    #[test]
+    fn test_ups_status() {
+        let m = sample(PowerState::OperationalCoOp, 8.0);
+        assert_eq!(ups_status(&m, 100.0, 20), "OL");
+        assert_eq!(ups_status(&m, 50.0, 20), "OL CHRG");
+
+        let m = sample(PowerState::BlackoutCoOp, 8.0);
+        assert_eq!(ups_status(&m, 50.0, 20), "OB");
+        assert_eq!(ups_status(&m, 10.0, 20), "OB LB");
+
+        let m = sample(PowerState::BlackoutShutdown, 8.0);
+        assert_eq!(ups_status(&m, 50.0, 20), "FSD OB LB");
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize(r#"PASSWORD "my \"secret\" pw""#).unwrap(),
+            vec!["PASSWORD", r#"my "secret" pw"#]
+        );
+        assert_eq!(tokenize("GET  VAR halpi ups.status").unwrap().len(), 4);
+        assert!(tokenize(r#"USERNAME "unterminated"#).is_none());
+    }
+
+    #[test]
+    fn test_get_and_list_var() {
+        let config = NutConfig::default();
+        let vars = vars_for(&sample(PowerState::BlackoutCoOp, 8.0));
+        let mut session = Session::default();
+
+        assert_eq!(
+            handle_command("GET VAR halpi ups.status", &mut session, &vars, &config),
+            "VAR halpi ups.status \"OB\""
+        );
+        assert_eq!(
+            handle_command("GET VAR halpi nope", &mut session, &vars, &config),
+            "ERR VAR-NOT-SUPPORTED"
+        );
+        assert_eq!(
+            handle_command("GET VAR other ups.status", &mut session, &vars, &config),
+            "ERR UNKNOWN-UPS"
+        );
+
+        let list = handle_command("LIST VAR halpi", &mut session, &vars, &config);
+        assert!(list.starts_with("BEGIN LIST VAR halpi\n"));
+        assert!(list.contains("VAR halpi input.voltage \"12.00\"\n"));
+        assert!(list.ends_with("END LIST VAR halpi"));
+    }
+
+    #[test]
+    fn test_stale_data() {
+        let config = NutConfig::default();
+        let mut session = Session::default();
+        assert_eq!(
+            handle_command("GET VAR halpi ups.status", &mut session, &None, &config),
+            "ERR DATA-STALE"
+        );
+    }
+
+    #[test]
+    fn test_login_with_credentials() {
+        let config = NutConfig {
+            username: Some("monuser".to_string()),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let vars = vars_for(&sample(PowerState::OperationalCoOp, 8.0));
+        let mut session = Session::default();
+
+        assert_eq!(
+            handle_command("LOGIN halpi", &mut session, &vars, &config),
+            "ERR USERNAME-REQUIRED"
+        );
+        handle_command("USERNAME monuser", &mut session, &vars, &config);
+        handle_command("PASSWORD wrong", &mut session, &vars, &config);
+        assert_eq!(
+            handle_command("PRIMARY halpi", &mut session, &vars, &config),
+            "ERR ACCESS-DENIED"
+        );
+
+        let mut session = Session::default();
+        handle_command("USERNAME monuser", &mut session, &vars, &config);
+        handle_command("PASSWORD secret", &mut session, &vars, &config);
+        assert_eq!(
+            handle_command("LOGIN halpi", &mut session, &vars, &config),
+            "OK"
+        );
+        assert_eq!(
+            handle_command("PRIMARY halpi", &mut session, &vars, &config),
+            "OK PRIMARY-GRANTED"
+        );
+        assert_eq!(
+            handle_command("LOGOUT", &mut session, &vars, &config),
+            "OK Goodbye"
+        );
+        assert!(session.done);
+    }
+
+    #[test]
+    fn test_write_commands_refused() {
+        let config = NutConfig::default();
+        let mut session = Session::default();
+        assert_eq!(
+            handle_command("FSD halpi", &mut session, &None, &config),
+            "ERR ACCESS-DENIED"
+        );
+        assert_eq!(
+            handle_command("BOGUS", &mut session, &None, &config),
+            "ERR UNKNOWN-COMMAND"
+        );
+    }
+}
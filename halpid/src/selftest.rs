@@ -0,0 +1,187 @@
+//! Startup self-test of the controller
+//!
+//! Before the state machine arms the watchdog, it reads the registers the
+//! daemon relies on and compares the firmware version with
+//! `self-test.min-firmware` and the hardware version with the hardware the
+//! protocol describes. Registers that do not respond only make
+//! `GET /health` degraded. Unsupported firmware or hardware also makes the
+//! API refuse requests that change the controller, whose registers may
+//! mean something else there; monitoring and the blackout handling carry
+//! on. The test is repeated after a firmware update through the API.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::{error, info, warn};
+
+use halpi_common::protocol::SUPPORTED_HARDWARE_MAJOR;
+use halpi_common::types::Version;
+
+use crate::i2c::{DeviceHandle, HalpiDevice, I2cError};
+use crate::state_machine::StatusHandle;
+
+/// Result of the self-test
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SelfTestReport {
+    pub checked_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub firmware_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hardware_version: Option<String>,
+    pub min_firmware: String,
+    /// Registers that could not be read and unsupported versions
+    pub failures: Vec<String>,
+    /// False if the firmware or hardware is not supported
+    pub compatible: bool,
+}
+
+impl SelfTestReport {
+    /// True if every check passed
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// The failures on one line, for the log and the systemd status
+    pub fn summary(&self) -> String {
+        self.failures.join("; ")
+    }
+}
+
+/// The value read, or `None` with the failure recorded
+fn read<T>(failures: &mut Vec<String>, name: &str, result: Result<T, I2cError>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(e) => {
+            failures.push(format!("cannot read the {}: {}", name, e));
+            None
+        }
+    }
+}
+
+/// Read the key registers and check the versions
+///
+/// An unprogrammed hardware version, as on early units, is not a failure.
+pub fn check(device: &mut HalpiDevice, min_firmware: &Version) -> SelfTestReport {
+    let mut failures = Vec::new();
+    let hardware = read(
+        &mut failures,
+        "hardware version",
+        device.get_hardware_version(),
+    );
+    let firmware = read(
+        &mut failures,
+        "firmware version",
+        device.get_firmware_version(),
+    );
+    read(&mut failures, "device ID", device.get_device_id());
+    read(&mut failures, "power state", device.get_power_state());
+    read(
+        &mut failures,
+        "watchdog timeout",
+        device.get_watchdog_timeout(),
+    );
+    read(&mut failures, "measurements", device.get_measurements());
+
+    let mut compatible = true;
+    if let Some(firmware) = &firmware {
+        if firmware.is_unavailable() {
+            failures.push("the controller reports no firmware version".to_string());
+            compatible = false;
+        } else if firmware < min_firmware {
+            failures.push(format!(
+                "firmware {} is older than the minimum {}",
+                firmware, min_firmware
+            ));
+            compatible = false;
+        }
+    }
+    if let Some(hardware) = &hardware
+        && !hardware.is_unavailable()
+        && !SUPPORTED_HARDWARE_MAJOR.contains(&hardware.major)
+    {
+        failures.push(format!(
+            "hardware {} is not supported (expected major version {}-{})",
+            hardware,
+            SUPPORTED_HARDWARE_MAJOR.start(),
+            SUPPORTED_HARDWARE_MAJOR.end()
+        ));
+        compatible = false;
+    }
+
+    SelfTestReport {
+        checked_at: Utc::now(),
+        firmware_version: firmware.map(|version| version.to_string()),
+        hardware_version: hardware.map(|version| version.to_string()),
+        min_firmware: min_firmware.to_string(),
+        failures,
+        compatible,
+    }
+}
+
+/// Test the controller, log the result and record it in `status`
+///
+/// # Errors
+/// Returns `I2cError` if the controller is not connected.
+pub async fn run(
+    device: &DeviceHandle,
+    status: &StatusHandle,
+    min_firmware: Version,
+) -> Result<SelfTestReport, I2cError> {
+    let report = device
+        .with(move |device| check(device, &min_firmware))
+        .await?;
+    if !report.compatible {
+        error!(
+            "Controller is not supported, refusing API requests that change it: {}",
+            report.summary()
+        );
+    } else if !report.passed() {
+        warn!("Controller self-test failed: {}", report.summary());
+    } else {
+        info!(
+            "Controller self-test passed (firmware {}, hardware {})",
+            report.firmware_version.as_deref().unwrap_or("unknown"),
+            report.hardware_version.as_deref().unwrap_or("unknown")
+        );
+    }
+    status.set_self_test(Some(report.clone()));
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i2c::Simulator;
+    use crate::i2c::sim::Fault;
+    use halpi_common::protocol;
+
+    #[test]
+    fn test_check() {
+        let simulator = Simulator::new();
+        let mut device = HalpiDevice::simulated(1, 0x6D, simulator.clone());
+
+        let report = check(&mut device, &Version::new(2, 0, 0));
+        assert!(report.passed());
+        assert!(report.compatible);
+        assert_eq!(report.firmware_version.as_deref(), Some("3.3.0"));
+        assert_eq!(report.hardware_version.as_deref(), Some("1.0.0"));
+
+        let report = check(&mut device, &Version::new(3, 4, 0));
+        assert!(!report.compatible);
+        assert_eq!(
+            report.summary(),
+            "firmware 3.3.0 is older than the minimum 3.4.0"
+        );
+
+        // A register that does not respond is a failure, not an incompatibility
+        simulator
+            .inject(Fault::Nak {
+                register: Some(protocol::REG_DEVICE_ID),
+                count: 100,
+            })
+            .unwrap();
+        let report = check(&mut device, &Version::new(2, 0, 0));
+        assert!(report.compatible);
+        assert_eq!(report.failures.len(), 1);
+        assert!(report.failures[0].starts_with("cannot read the device ID"));
+    }
+}
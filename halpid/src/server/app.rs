@@ -1,50 +1,167 @@
 //! Axum application setup and shared state
 
 use axum::Router;
-use halpi_common::config::Config;
+use axum::extract::{DefaultBodyLimit, Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use halpi_common::api::{ApiError, ErrorCode};
+use halpi_common::config::{ApiLimitsConfig, Config, CorsConfig, DEFAULT_DEVICE_ID};
 use halpi_common::error::{AppError, ServerError};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Instant;
 use tokio::sync::RwLock;
+use tower_http::cors::{AllowOrigin, CorsLayer};
 use tower_http::trace::TraceLayer;
 
-use crate::i2c::device::HalpiDevice;
+use super::limit::{ApiLimits, limit_requests};
+use super::peer::PeerCredentials;
+use crate::events::EventBus;
+use crate::i2c::{DeviceHandle, IdentityCache};
+use crate::identify::Identify;
+use crate::state_machine::StatusHandle;
+
+/// Tracing target of the API access log
+pub const ACCESS_TARGET: &str = "halpid::access";
+
+/// API socket used when none is configured
+pub const DEFAULT_SOCKET_PATH: &str = "/run/halpid/halpid.sock";
+
+/// An additional controller, served under `/devices/{id}`
+#[derive(Clone)]
+pub struct DeviceEntry {
+    pub id: String,
+    pub device: DeviceHandle,
+    pub identity: IdentityCache,
+    pub identify: Identify,
+}
 
 /// Shared application state accessible to all handlers
 #[derive(Clone)]
 pub struct AppState {
-    /// I2C device interface (mutex-protected for exclusive access)
-    pub device: Arc<Mutex<HalpiDevice>>,
+    /// I2C device interface (mutex-protected, possibly not connected yet)
+    ///
+    /// The primary controller, except in the routers for additional
+    /// controllers under `/devices/{id}`.
+    pub device: DeviceHandle,
+    /// Id of the controller in `/devices/{id}`
+    pub device_id: String,
+    /// Controller identity, read once and refreshed after firmware updates
+    pub identity: IdentityCache,
+    /// LED identification of the controller
+    pub identify: Identify,
+    /// Additional controllers
+    pub devices: Arc<Vec<DeviceEntry>>,
     /// Configuration (read-write lock for concurrent reads)
     pub config: Arc<RwLock<Config>>,
+    /// Daemon event bus
+    pub events: EventBus,
+    /// State of the power management state machine
+    pub status: StatusHandle,
     /// Daemon version string
     pub version: &'static str,
+    /// When the daemon started
+    pub started: Instant,
+    /// Limits on the requests that access a controller
+    pub limits: Arc<ApiLimits>,
+    /// The controller is simulated or replayed; the wake alarm is not set
+    pub offline: bool,
+    /// Marker of a reboot that turned auto-restart on, to turn it off again
+    /// at the next start; without one, a reboot leaves auto-restart on
+    pub reboot_marker: Option<PathBuf>,
 }
 
 impl AppState {
     /// Create new application state
-    pub fn new(device: Arc<Mutex<HalpiDevice>>, config: Arc<RwLock<Config>>) -> Self {
+    pub fn new(device: DeviceHandle, config: Arc<RwLock<Config>>) -> Self {
         Self {
             device,
+            device_id: DEFAULT_DEVICE_ID.to_string(),
+            identity: IdentityCache::new(),
+            identify: Identify::new(),
+            devices: Arc::new(Vec::new()),
             config,
+            events: EventBus::new(),
+            status: StatusHandle::new(),
             version: env!("CARGO_PKG_VERSION"),
+            started: Instant::now(),
+            limits: Arc::new(ApiLimits::new(ApiLimitsConfig::default())),
+            offline: false,
+            reboot_marker: None,
         }
     }
-}
 
-/// Run the HTTP server on a Unix socket
-pub async fn run_server(state: AppState) -> anyhow::Result<()> {
-    use std::path::PathBuf;
-    use tokio::net::UnixListener;
+    /// Apply `config` to the requests that access a controller
+    pub fn with_limits(mut self, config: ApiLimitsConfig) -> Self {
+        self.limits = Arc::new(ApiLimits::new(config));
+        self
+    }
 
-    let socket_path = {
-        let config = state.config.read().await;
-        config
+    /// Leave the host's RTC wake alarm alone, for a simulated or replayed
+    /// controller
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Record reboots that turn auto-restart on in `path`
+    pub fn with_reboot_marker(mut self, path: Option<PathBuf>) -> Self {
+        self.reboot_marker = path;
+        self
+    }
+
+    /// Add controllers served under `/devices/{id}`
+    pub fn with_devices(mut self, devices: Vec<(String, DeviceHandle)>) -> Self {
+        self.devices = Arc::new(
+            devices
+                .into_iter()
+                .map(|(id, device)| DeviceEntry {
+                    id,
+                    device,
+                    identity: IdentityCache::new(),
+                    identify: Identify::new(),
+                })
+                .collect(),
+        );
+        self
+    }
+
+    /// State for the routes of an additional controller
+    fn for_device(&self, entry: &DeviceEntry) -> Self {
+        Self {
+            device: entry.device.clone(),
+            device_id: entry.id.clone(),
+            identity: entry.identity.clone(),
+            identify: entry.identify.clone(),
+            ..self.clone()
+        }
+    }
+
+    /// Path of the API socket
+    pub async fn socket_path(&self) -> PathBuf {
+        self.config
+            .read()
+            .await
             .socket
             .clone()
-            .unwrap_or_else(|| PathBuf::from("/run/halpid/halpid.sock"))
-    };
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_SOCKET_PATH))
+    }
+}
+
+/// Bind the API socket configured in `state`
+///
+/// Binding separately from [`serve`] lets the caller report readiness only
+/// once clients can connect. The socket belongs to `owner`, the user the
+/// daemon will run as, or else the current user, and to the configured
+/// `socket-group`.
+pub async fn bind_socket(
+    state: &AppState,
+    owner: Option<libc::uid_t>,
+) -> anyhow::Result<tokio::net::UnixListener> {
+    use tokio::net::UnixListener;
+
+    let socket_path = state.socket_path().await;
+    let group = state.config.read().await.socket_group.clone();
 
     // Remove existing socket if it exists
     if socket_path.exists() {
@@ -59,39 +176,61 @@ pub async fn run_server(state: AppState) -> anyhow::Result<()> {
     let listener = UnixListener::bind(&socket_path)?;
 
     // Set socket permissions and group ownership
-    setup_socket_permissions(&socket_path, "halpid").await?;
+    setup_socket_permissions(&socket_path, &group, owner).await?;
 
     tracing::info!("HTTP server listening on {}", socket_path.display());
 
-    let app = create_app(state);
+    Ok(listener)
+}
+
+/// Serve the HTTP API on a bound socket
+pub async fn serve(listener: tokio::net::UnixListener, state: AppState) -> anyhow::Result<()> {
+    let (cors, access_log) = {
+        let config = state.config.read().await;
+        (
+            cors_layer(&config.cors),
+            config.logging.access_log_enabled(),
+        )
+    };
+    let mut app = create_app(state);
+    if access_log {
+        app = app.layer(axum::middleware::from_fn(log_access));
+    }
+    if let Some(cors) = cors {
+        app = app.layer(cors);
+    }
 
-    axum::serve(listener, app.into_make_service())
-        .await
-        .map_err(|e| anyhow::anyhow!("Server error: {}", e))?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<PeerCredentials>(),
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("Server error: {}", e))?;
 
     Ok(())
 }
 
-/// Create the Axum application with all routes and middleware
-pub fn create_app(state: AppState) -> Router {
-    use super::handlers::{config, flash, health, shutdown, usb, values};
+/// Routes reading and configuring one controller
+///
+/// Served at the top level for the primary controller and under
+/// `/devices/{id}` for every controller, all under the same `limits`.
+fn device_routes(limits: &Arc<ApiLimits>) -> Router<AppState> {
+    use super::handlers::{config, led, usb, values};
 
     Router::new()
-        // Health and version endpoints
-        .route("/", axum::routing::get(health::root))
-        .route("/version", axum::routing::get(health::version))
         // Values endpoints
         .route("/values", axum::routing::get(values::get_all_values))
-        .route("/values/{key}", axum::routing::get(values::get_value))
+        .route(
+            "/values/{key}",
+            axum::routing::get(values::get_value).put(values::put_value),
+        )
         // Configuration endpoints
         .route("/config", axum::routing::get(config::get_all_config))
+        .route("/config/diff", axum::routing::get(config::get_config_diff))
         .route(
             "/config/{key}",
             axum::routing::get(config::get_config).put(config::put_config),
         )
-        // Shutdown and standby endpoints
-        .route("/shutdown", axum::routing::post(shutdown::post_shutdown))
-        .route("/standby", axum::routing::post(shutdown::post_standby))
         // USB port control endpoints
         .route(
             "/usb",
@@ -101,19 +240,223 @@ pub fn create_app(state: AppState) -> Router {
             "/usb/{port}",
             axum::routing::get(usb::get_usb).put(usb::put_usb),
         )
+        // Front LED endpoints
+        .route("/led", axum::routing::get(led::get_led).put(led::put_led))
+        .route("/identify", axum::routing::post(led::post_identify))
+        .route_layer(axum::middleware::from_fn_with_state(
+            limits.clone(),
+            limit_requests,
+        ))
+}
+
+/// Create the Axum application with all routes and middleware
+pub fn create_app(state: AppState) -> Router {
+    use super::handlers::{
+        debug, devices, events, flash, health, maintenance, metrics, power_schedule, rtc, shutdown,
+        state, stats,
+    };
+
+    let mut app = Router::new()
+        // Health and version endpoints
+        .route("/", axum::routing::get(health::root))
+        .route("/version", axum::routing::get(health::version))
+        .route("/info", axum::routing::get(health::info))
+        .route("/health", axum::routing::get(health::health))
+        // Power management state
+        .route("/state", axum::routing::get(state::get_state))
+        .route(
+            "/maintenance",
+            axum::routing::post(maintenance::post_maintenance),
+        )
+        // Event history and stream
+        .route("/events", axum::routing::get(events::get_events))
+        .route(
+            "/events/stream",
+            axum::routing::get(events::get_event_stream),
+        )
+        // Measurement statistics
+        .route("/stats", axum::routing::get(stats::get_stats))
+        .route("/stats/history", axum::routing::get(stats::get_history))
+        // Prometheus metrics
+        .route("/metrics", axum::routing::get(metrics::get_metrics))
+        // Runtime diagnostics (administrators only)
+        .route("/debug/runtime", axum::routing::get(debug::get_runtime))
+        .route("/debug/scan", axum::routing::get(debug::get_scan))
+        .route("/debug/bundle", axum::routing::get(debug::get_bundle))
+        .route(
+            "/debug/sim",
+            axum::routing::get(debug::get_sim).post(debug::post_sim),
+        )
+        // Values, configuration and USB endpoints of the primary controller
+        .merge(
+            device_routes(&state.limits).route_layer(axum::middleware::from_fn_with_state(
+                state.status.clone(),
+                refuse_incompatible,
+            )),
+        )
+        // Shutdown, standby and reboot endpoints
+        .merge(
+            Router::new()
+                .route("/shutdown", axum::routing::post(shutdown::post_shutdown))
+                .route(
+                    "/standby",
+                    axum::routing::get(shutdown::get_standby)
+                        .post(shutdown::post_standby)
+                        .delete(shutdown::delete_standby),
+                )
+                .route("/reboot", axum::routing::post(shutdown::post_reboot))
+                .route_layer(axum::middleware::from_fn_with_state(
+                    state.status.clone(),
+                    refuse_incompatible,
+                )),
+        )
+        .route(
+            "/shutdown/cancel",
+            axum::routing::post(shutdown::post_shutdown_cancel),
+        )
+        .route(
+            "/shutdown/schedule",
+            axum::routing::get(shutdown::get_shutdown_schedule)
+                .post(shutdown::post_shutdown_schedule)
+                .delete(shutdown::delete_shutdown_schedule),
+        )
+        .route(
+            "/power-schedule",
+            axum::routing::get(power_schedule::get_power_schedule),
+        )
+        // Hardware clock
+        .route("/rtc", axum::routing::get(rtc::get_rtc).put(rtc::put_rtc))
         // Firmware upload endpoint
-        .route("/flash", axum::routing::post(flash::post_flash))
-        // Add tracing middleware
+        .route(
+            "/flash",
+            axum::routing::post(flash::post_flash)
+                .layer(DefaultBodyLimit::max(
+                    state.limits.config().max_firmware_size() + flash::MULTIPART_OVERHEAD,
+                ))
+                .layer(axum::middleware::from_fn_with_state(
+                    state.limits.clone(),
+                    limit_requests,
+                )),
+        )
+        // Controller list and per-controller endpoints
+        .route("/devices", axum::routing::get(devices::get_devices))
+        .nest(
+            &format!("/devices/{}", DEFAULT_DEVICE_ID),
+            device_routes(&state.limits).route_layer(axum::middleware::from_fn_with_state(
+                state.status.clone(),
+                refuse_incompatible,
+            )),
+        );
+    for entry in state.devices.iter() {
+        app = app.nest(
+            &format!("/devices/{}", entry.id),
+            device_routes(&state.limits).with_state(state.for_device(entry)),
+        );
+    }
+
+    app
+        // Add request counting and tracing middleware
+        .layer(axum::middleware::from_fn(count_requests))
         .layer(TraceLayer::new_for_http())
         // Add shared state
         .with_state(state)
 }
 
-/// Set Unix socket permissions and group ownership
+/// CORS layer answering preflight requests and adding the
+/// `Access-Control-Allow-*` headers for the configured origins
+///
+/// Returns `None` if no origins are configured. Origins that are not valid
+/// header values are skipped; configuration validation rejects them.
+pub fn cors_layer(config: &CorsConfig) -> Option<CorsLayer> {
+    use axum::http::{HeaderValue, Method, header};
+
+    if config.allowed_origins.is_empty() {
+        return None;
+    }
+    let origins = if config.allows_any() {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            config
+                .allowed_origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+        )
+    };
+    Some(
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods([Method::GET, Method::PUT, Method::POST, Method::DELETE])
+            .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION]),
+    )
+}
+
+/// Middleware logging each request under [`ACCESS_TARGET`] once its
+/// response head is ready
+///
+/// Streamed bodies, such as `/events/stream`, are logged when the stream
+/// opens.
+async fn log_access(request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let peer = request
+        .extensions()
+        .get::<axum::extract::ConnectInfo<PeerCredentials>>()
+        .map(|info| info.0);
+    let response = next.run(request).await;
+    let duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+    tracing::info!(
+        target: ACCESS_TARGET,
+        %method,
+        path,
+        status = response.status().as_u16(),
+        duration_ms,
+        uid = peer.and_then(|peer| peer.uid),
+        pid = peer.and_then(|peer| peer.pid),
+        "{} {} {} {:.1} ms",
+        method,
+        path,
+        response.status().as_u16(),
+        duration_ms
+    );
+    response
+}
+
+/// Middleware answering requests other than reads with `INCOMPATIBLE`
+/// while the self-test found the controller unsupported
+async fn refuse_incompatible(
+    State(status): State<StatusHandle>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !request.method().is_safe()
+        && let Some(report) = status.self_test().filter(|report| !report.compatible)
+    {
+        return ApiError::new(
+            ErrorCode::Incompatible,
+            format!("Controller is not supported: {}", report.summary()),
+        )
+        .with_detail(serde_json::json!(report))
+        .into_response();
+    }
+    next.run(request).await
+}
+
+/// Middleware updating the HTTP request counters
+async fn count_requests(request: Request, next: Next) -> Response {
+    crate::metrics::HTTP.begin();
+    let response = next.run(request).await;
+    crate::metrics::HTTP.finish(response.status().as_u16());
+    response
+}
+
+/// Set Unix socket permissions and ownership
 #[cfg(unix)]
 pub async fn setup_socket_permissions(
     socket_path: &Path,
     group_name: &str,
+    owner: Option<libc::uid_t>,
 ) -> Result<(), AppError> {
     use std::os::unix::fs::PermissionsExt;
 
@@ -127,14 +470,18 @@ pub async fn setup_socket_permissions(
     })?;
 
     // Set group ownership
-    set_socket_group(socket_path, group_name)?;
+    set_socket_group(socket_path, group_name, owner)?;
 
     Ok(())
 }
 
-/// Set the group ownership of the socket file
+/// Set the group ownership of the socket file, and the owner if given
 #[cfg(unix)]
-fn set_socket_group(socket_path: &Path, group_name: &str) -> Result<(), AppError> {
+fn set_socket_group(
+    socket_path: &Path,
+    group_name: &str,
+    owner: Option<libc::uid_t>,
+) -> Result<(), AppError> {
     use std::ffi::CString;
 
     // Get group ID from group name
@@ -154,8 +501,8 @@ fn set_socket_group(socket_path: &Path, group_name: &str) -> Result<(), AppError
 
     let gid = unsafe { (*grp).gr_gid };
 
-    // Get current user ID (don't change ownership)
-    let uid = unsafe { libc::getuid() };
+    // Keep the current user unless the daemon will run as another one
+    let uid = owner.unwrap_or_else(|| unsafe { libc::getuid() });
 
     // Change ownership - handle invalid UTF-8 in path
     let path_str = socket_path
@@ -190,11 +537,7 @@ mod tests {
 
     #[test]
     fn test_app_state_creation() {
-        // Skip test if I2C hardware not available
-        let device = match HalpiDevice::new(1, 0x6D) {
-            Ok(d) => Arc::new(Mutex::new(d)),
-            Err(_) => return,
-        };
+        let device = DeviceHandle::missing(1, 0x6D);
         let config = Arc::new(RwLock::new(Config::default()));
         let state = AppState::new(device, config);
 
@@ -203,15 +546,118 @@ mod tests {
 
     #[test]
     fn test_create_app() {
-        // Skip test if I2C hardware not available
-        let device = match HalpiDevice::new(1, 0x6D) {
-            Ok(d) => Arc::new(Mutex::new(d)),
-            Err(_) => return,
-        };
+        let device = DeviceHandle::missing(1, 0x6D);
         let config = Arc::new(RwLock::new(Config::default()));
         let state = AppState::new(device, config);
 
         let _app = create_app(state);
         // If this compiles and runs, the router is created successfully
     }
+
+    #[tokio::test]
+    async fn test_cors_layer() {
+        use axum::body::Body;
+        use axum::http::header::{ACCESS_CONTROL_ALLOW_ORIGIN, ORIGIN};
+        use tower::ServiceExt;
+
+        assert!(cors_layer(&CorsConfig::default()).is_none());
+
+        let device = DeviceHandle::missing(1, 0x6D);
+        let config = Arc::new(RwLock::new(Config::default()));
+        let cors = CorsConfig {
+            allowed_origins: vec!["https://halpi.local:9090".to_string()],
+        };
+        let app = create_app(AppState::new(device, config)).layer(cors_layer(&cors).unwrap());
+
+        let preflight = |origin: &str| {
+            axum::http::Request::options("/values")
+                .header(ORIGIN, origin)
+                .header("access-control-request-method", "PUT")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app
+            .clone()
+            .oneshot(preflight("https://halpi.local:9090"))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://halpi.local:9090"
+        );
+        let response = app
+            .oneshot(preflight("https://elsewhere.example"))
+            .await
+            .unwrap();
+        assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[tokio::test]
+    async fn test_request_limits() {
+        use axum::body::Body;
+        use axum::http::StatusCode;
+        use tower::ServiceExt;
+
+        let device = DeviceHandle::missing(1, 0x6D);
+        let config = Arc::new(RwLock::new(Config::default()));
+        let state = AppState::new(device, config).with_limits(ApiLimitsConfig {
+            rate: 0.1,
+            burst: 1,
+            max_concurrent: 1,
+            ..Default::default()
+        });
+        let app = create_app(state);
+        let get = |path: &str| axum::http::Request::get(path).body(Body::empty()).unwrap();
+
+        let response = app.clone().oneshot(get("/values")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        // The bucket is shared by all controllers
+        let response = app
+            .clone()
+            .oneshot(get("/devices/default/config"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "10");
+        // Endpoints without controller access are not limited
+        let response = app.oneshot(get("/version")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_device_routes() {
+        use axum::body::Body;
+        use axum::http::StatusCode;
+        use tower::ServiceExt;
+
+        let device = DeviceHandle::missing(1, 0x6D);
+        let config = Arc::new(RwLock::new(Config::default()));
+        let state = AppState::new(device, config).with_devices(vec![(
+            "expansion".to_string(),
+            DeviceHandle::missing(1, 0x6E),
+        )]);
+        let app = create_app(state);
+
+        // Without hardware every controller is unavailable, but routed
+        for path in [
+            "/values",
+            "/devices/default/values",
+            "/devices/expansion/values",
+        ] {
+            let request = axum::http::Request::get(path).body(Body::empty()).unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(
+                response.status(),
+                StatusCode::SERVICE_UNAVAILABLE,
+                "{}",
+                path
+            );
+        }
+
+        let request = axum::http::Request::get("/devices/other/values")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
 }
@@ -7,41 +7,86 @@ use axum::Json;
 use axum::extract::{Path, State};
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
+use halpi_common::api::{ApiError, ConfigDiffResponse, ConfigResponse, ErrorCode};
+use halpi_common::config::DEFAULT_DEVICE_ID;
 use serde_json::json;
 
+use super::{device_error_code, device_unavailable};
+use crate::drift;
+use crate::i2c::I2cError;
 use crate::server::app::AppState;
 
 /// GET /config - Get all configuration values from controller
 pub async fn get_all_config(State(state): State<AppState>) -> Response {
-    let mut device = state.device.lock().await;
-
     // Read all configuration values from controller registers
-    let watchdog_timeout = device.get_watchdog_timeout().unwrap_or(0);
-    let power_on_threshold = device.get_power_on_threshold().unwrap_or(0.0);
-    let solo_power_off_threshold = device.get_solo_power_off_threshold().unwrap_or(0.0);
-    let led_brightness = device.get_led_brightness().unwrap_or(0);
-    let auto_restart = device.get_auto_restart().unwrap_or(false);
-    let solo_depleting_timeout = device.get_solo_depleting_timeout().unwrap_or(0);
-
-    drop(device);
-
-    let config_json = json!({
-        "watchdog_timeout": watchdog_timeout as f64 / 1000.0, // Convert ms to seconds
-        "power_on_threshold": power_on_threshold,
-        "solo_power_off_threshold": solo_power_off_threshold,
-        "led_brightness": led_brightness,
-        "auto_restart": auto_restart,
-        "solo_depleting_timeout": solo_depleting_timeout as f64 / 1000.0, // Convert ms to seconds
-    });
+    let values = state
+        .device
+        .with(|device| {
+            (
+                device.get_watchdog_timeout().unwrap_or(0),
+                device.get_power_on_threshold().unwrap_or(0.0),
+                device.get_solo_power_off_threshold().unwrap_or(0.0),
+                device.get_led_brightness().unwrap_or(0),
+                device.get_auto_restart().unwrap_or(false),
+                device.get_solo_depleting_timeout().unwrap_or(0),
+            )
+        })
+        .await;
+    let (
+        watchdog_timeout,
+        power_on_threshold,
+        solo_power_off_threshold,
+        led_brightness,
+        auto_restart,
+        solo_depleting_timeout,
+    ) = match values {
+        Ok(values) => values,
+        Err(e) => return device_unavailable(e),
+    };
+
+    let config = ConfigResponse {
+        watchdog_timeout: watchdog_timeout as f64 / 1000.0, // Convert ms to seconds
+        power_on_threshold,
+        solo_power_off_threshold,
+        led_brightness,
+        auto_restart,
+        solo_depleting_timeout: solo_depleting_timeout as f64 / 1000.0, // Convert ms to seconds
+    };
+
+    (StatusCode::OK, Json(config)).into_response()
+}
 
-    (StatusCode::OK, Json(config_json)).into_response()
+/// GET /config/diff - Compare the controller with the `controller` configuration
+///
+/// Reads the declared settings now rather than waiting for the next
+/// periodic check. Only the primary controller has declared settings.
+pub async fn get_config_diff(State(state): State<AppState>) -> Response {
+    let config = state.config.read().await.controller.clone();
+    let enforce = config.enforce;
+    if state.device_id != DEFAULT_DEVICE_ID {
+        let diff = ConfigDiffResponse {
+            drift: Vec::new(),
+            enforce: false,
+        };
+        return (StatusCode::OK, Json(diff)).into_response();
+    }
+    match state
+        .device
+        .with(move |device| drift::check(device, &config))
+        .await
+    {
+        Ok(Ok(drift)) => {
+            (StatusCode::OK, Json(ConfigDiffResponse { drift, enforce })).into_response()
+        }
+        Ok(Err(e)) => device_error(e).into_response(),
+        Err(e) => device_unavailable(e),
+    }
 }
 
 /// GET /config/:key - Get a specific configuration value from controller
 pub async fn get_config(State(state): State<AppState>, Path(key): Path<String>) -> Response {
-    let mut device = state.device.lock().await;
-
-    let value = match key.as_str() {
+    let requested = key.clone();
+    let value = state.device.with(move |device| match requested.as_str() {
         "watchdog_timeout" => device
             .get_watchdog_timeout()
             .map(|v| json!(v as f64 / 1000.0))
@@ -55,17 +100,20 @@ pub async fn get_config(State(state): State<AppState>, Path(key): Path<String>)
             .map(|v| json!(v as f64 / 1000.0))
             .ok(),
         _ => None,
+    });
+    let value = match value.await {
+        Ok(value) => value,
+        Err(e) => return device_unavailable(e),
     };
 
-    drop(device);
-
     match value {
         Some(v) => (StatusCode::OK, Json(v)).into_response(),
-        None => (
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": format!("Unknown config key: {}", key)})),
+        None => ApiError::new(
+            ErrorCode::UnknownKey,
+            format!("Unknown config key: {}", key),
         )
-            .into_response(),
+        .with_detail(json!({"key": key}))
+        .into_response(),
     }
 }
 
@@ -75,51 +123,49 @@ pub async fn put_config(
     Path(key): Path<String>,
     Json(payload): Json<serde_json::Value>,
 ) -> Response {
-    let mut device = state.device.lock().await;
-
-    let result = match key.as_str() {
+    let requested = key.clone();
+    let result = state.device.with(move |device| match requested.as_str() {
         "watchdog_timeout" => {
             if let Some(value) = payload.as_f64() {
                 let timeout_ms = (value * 1000.0) as u16;
                 device
                     .set_watchdog_timeout(timeout_ms)
-                    .map_err(|e| e.to_string())
+                    .map_err(device_error)
             } else {
-                Err("Invalid value type".to_string())
+                Err(ApiError::new(ErrorCode::InvalidValue, "Invalid value type"))
             }
         }
         "power_on_threshold" => {
             if let Some(value) = payload.as_f64() {
                 device
                     .set_power_on_threshold(value as f32)
-                    .map_err(|e| e.to_string())
+                    .map_err(device_error)
             } else {
-                Err("Invalid value type".to_string())
+                Err(ApiError::new(ErrorCode::InvalidValue, "Invalid value type"))
             }
         }
         "solo_power_off_threshold" => {
             if let Some(value) = payload.as_f64() {
                 device
                     .set_solo_power_off_threshold(value as f32)
-                    .map_err(|e| e.to_string())
+                    .map_err(device_error)
             } else {
-                Err("Invalid value type".to_string())
-            }
-        }
-        "led_brightness" => {
-            if let Some(value) = payload.as_u64() {
-                device
-                    .set_led_brightness(value as u8)
-                    .map_err(|e| e.to_string())
-            } else {
-                Err("Invalid value type".to_string())
+                Err(ApiError::new(ErrorCode::InvalidValue, "Invalid value type"))
             }
         }
+        "led_brightness" => match payload.as_u64().map(u8::try_from) {
+            Some(Ok(value)) => device.set_led_brightness(value).map_err(device_error),
+            Some(Err(_)) => Err(ApiError::new(
+                ErrorCode::InvalidValue,
+                "led_brightness must be an integer from 0 to 255",
+            )),
+            None => Err(ApiError::new(ErrorCode::InvalidValue, "Invalid value type")),
+        },
         "auto_restart" => {
             if let Some(value) = payload.as_bool() {
-                device.set_auto_restart(value).map_err(|e| e.to_string())
+                device.set_auto_restart(value).map_err(device_error)
             } else {
-                Err("Invalid value type".to_string())
+                Err(ApiError::new(ErrorCode::InvalidValue, "Invalid value type"))
             }
         }
         "solo_depleting_timeout" => {
@@ -127,36 +173,44 @@ pub async fn put_config(
                 let timeout_ms = (value * 1000.0) as u32;
                 device
                     .set_solo_depleting_timeout(timeout_ms)
-                    .map_err(|e| e.to_string())
+                    .map_err(device_error)
             } else {
-                Err("Invalid value type".to_string())
+                Err(ApiError::new(ErrorCode::InvalidValue, "Invalid value type"))
             }
         }
-        _ => Err(format!("Unknown config key: {}", key)),
+        _ => Err(ApiError::new(
+            ErrorCode::UnknownKey,
+            format!("Unknown config key: {}", requested),
+        )
+        .with_detail(json!({"key": requested}))),
+    });
+    let result = match result.await {
+        Ok(result) => result,
+        Err(e) => return device_unavailable(e),
     };
 
-    drop(device);
-
     match result {
         Ok(_) => (StatusCode::OK, Json(json!({"status": "ok"}))).into_response(),
-        Err(e) => (StatusCode::BAD_REQUEST, Json(json!({"error": e}))).into_response(),
+        Err(e) => e.into_response(),
     }
 }
 
+fn device_error(error: I2cError) -> ApiError {
+    ApiError::new(device_error_code(&error), error.to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::i2c::device::HalpiDevice;
+    use crate::i2c::DeviceHandle;
+    use crate::i2c::{HalpiDevice, Simulator};
     use halpi_common::config::Config;
     use std::sync::Arc;
-    use tokio::sync::{Mutex, RwLock};
+    use tokio::sync::RwLock;
 
     #[tokio::test]
     async fn test_get_all_config() {
-        let device = match HalpiDevice::new(1, 0x6D) {
-            Ok(d) => Arc::new(Mutex::new(d)),
-            Err(_) => return,
-        };
+        let device = DeviceHandle::new(HalpiDevice::simulated(1, 0x6D, Simulator::new()));
         let config = Arc::new(RwLock::new(Config::default()));
         let state = AppState::new(device, config);
 
@@ -166,23 +220,17 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_config_valid_key() {
-        let device = match HalpiDevice::new(1, 0x6D) {
-            Ok(d) => Arc::new(Mutex::new(d)),
-            Err(_) => return,
-        };
+        let device = DeviceHandle::new(HalpiDevice::simulated(1, 0x6D, Simulator::new()));
         let config = Arc::new(RwLock::new(Config::default()));
         let state = AppState::new(device, config);
 
-        let response = get_config(State(state), Path("i2c_bus".to_string())).await;
+        let response = get_config(State(state), Path("led_brightness".to_string())).await;
         assert_eq!(response.status(), StatusCode::OK);
     }
 
     #[tokio::test]
     async fn test_get_config_invalid_key() {
-        let device = match HalpiDevice::new(1, 0x6D) {
-            Ok(d) => Arc::new(Mutex::new(d)),
-            Err(_) => return,
-        };
+        let device = DeviceHandle::new(HalpiDevice::simulated(1, 0x6D, Simulator::new()));
         let config = Arc::new(RwLock::new(Config::default()));
         let state = AppState::new(device, config);
 
@@ -0,0 +1,422 @@
+//! Runtime diagnostics endpoint handler
+//!
+//! Reports the daemon's internal health for triaging a daemon that appears
+//! stuck: which tasks are still running, whether event subscribers keep up,
+//! whether the device lock is held, request counters and memory usage. The
+//! bus scan helps when the controller does not answer at the configured
+//! location. The support bundle collects everything in one report to
+//! attach to an issue. In simulate mode, faults are injected into the
+//! simulated controller here.
+
+use axum::Json;
+use axum::extract::{ConnectInfo, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use halpi_common::api::{ApiError, ErrorCode};
+use serde_json::{Value, json};
+use tokio::process::Command;
+use tracing::info;
+
+use super::{config, health, state, values};
+use crate::events::CHANNEL_CAPACITY;
+use crate::i2c::scan::{self, Detected};
+use crate::i2c::sim::Fault;
+use crate::i2c::{I2cError, Simulator};
+use crate::metrics;
+use crate::server::app::AppState;
+use crate::server::peer::PeerCredentials;
+use crate::tasks;
+
+/// GET /debug/runtime - Daemon runtime diagnostics
+///
+/// Restricted to root and the daemon's own user.
+pub async fn get_runtime(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<PeerCredentials>,
+) -> Response {
+    if !peer.is_admin() {
+        return ApiError::new(
+            ErrorCode::Forbidden,
+            "Runtime diagnostics are restricted to administrators",
+        )
+        .into_response();
+    }
+
+    (StatusCode::OK, Json(runtime_report(&state))).into_response()
+}
+
+/// GET /debug/scan - Scan all I2C buses for the controller
+///
+/// Restricted to root and the daemon's own user.
+pub async fn get_scan(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<PeerCredentials>,
+) -> Response {
+    if !peer.is_admin() {
+        return ApiError::new(
+            ErrorCode::Forbidden,
+            "Bus scans are restricted to administrators",
+        )
+        .into_response();
+    }
+
+    let (bus, addr) = (state.device.bus(), state.device.addr());
+    let result = tokio::task::spawn_blocking(move || {
+        let buses = scan::buses();
+        let detected = scan::scan(&scan::candidate_addresses(addr));
+        (buses, detected)
+    })
+    .await;
+    match result {
+        Ok((buses, detected)) => (
+            StatusCode::OK,
+            Json(scan_report(bus, addr, &buses, &detected)),
+        )
+            .into_response(),
+        Err(e) => {
+            ApiError::new(ErrorCode::DeviceError, format!("Bus scan failed: {}", e)).into_response()
+        }
+    }
+}
+
+/// Number of journal lines in the support bundle
+const BUNDLE_LOG_LINES: u32 = 1000;
+
+/// GET /debug/bundle - Support bundle
+///
+/// Collects the responses of the other endpoints together with the daemon
+/// configuration with secrets masked, recent state transitions and alerts,
+/// a register dump and recent log lines. Restricted to root and the
+/// daemon's own user.
+pub async fn get_bundle(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<PeerCredentials>,
+) -> Response {
+    if !peer.is_admin() {
+        return ApiError::new(
+            ErrorCode::Forbidden,
+            "Support bundles are restricted to administrators",
+        )
+        .into_response();
+    }
+
+    let daemon_config = state.config.read().await.redacted();
+    let registers = match state.device.with(|device| device.dump_registers()).await {
+        Ok(registers) => registers_report(&registers),
+        Err(e) => json!({"error": e.to_string()}),
+    };
+    let bundle = json!({
+        "generated_at": chrono::Utc::now().to_rfc3339(),
+        "info": section(health::info(State(state.clone())).await).await,
+        "health": section(health::health(State(state.clone())).await).await,
+        "state": section(state::get_state(State(state.clone())).await).await,
+        "values": section(values::get_all_values(State(state.clone())).await).await,
+        "controller_config": section(config::get_all_config(State(state.clone())).await).await,
+        "daemon_config": daemon_config,
+        "events": state.events.history(),
+        "registers": registers,
+        "runtime": runtime_report(&state),
+        "logs": recent_logs().await,
+    });
+    (StatusCode::OK, Json(bundle)).into_response()
+}
+
+/// GET /debug/sim - Values and active faults of the simulated controller
+///
+/// Restricted to root and the daemon's own user.
+pub async fn get_sim(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<PeerCredentials>,
+) -> Response {
+    match simulator(&state, &peer).await {
+        Ok(simulator) => (StatusCode::OK, Json(simulator.snapshot())).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// POST /debug/sim - Inject a fault into the simulated controller
+///
+/// Responds with the simulator's values and faults after the injection.
+/// Only available in simulate mode (409 otherwise); restricted to root and
+/// the daemon's own user.
+pub async fn post_sim(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<PeerCredentials>,
+    Json(fault): Json<Fault>,
+) -> Response {
+    let simulator = match simulator(&state, &peer).await {
+        Ok(simulator) => simulator,
+        Err(response) => return response,
+    };
+    if let Err(e) = simulator.inject(fault.clone()) {
+        return ApiError::new(ErrorCode::InvalidValue, e).into_response();
+    }
+    info!("Injected simulator fault: {:?}", fault);
+    (StatusCode::OK, Json(simulator.snapshot())).into_response()
+}
+
+/// The simulator of the device, or the error response
+async fn simulator(state: &AppState, peer: &PeerCredentials) -> Result<Simulator, Response> {
+    if !peer.is_admin() {
+        return Err(ApiError::new(
+            ErrorCode::Forbidden,
+            "The simulator is restricted to administrators",
+        )
+        .into_response());
+    }
+    match state.device.with(|device| device.simulator()).await {
+        Ok(Some(simulator)) => Ok(simulator),
+        Ok(None) => Err(ApiError::new(
+            ErrorCode::Conflict,
+            "The daemon is not running in simulate mode",
+        )
+        .into_response()),
+        Err(e) => Err(super::device_unavailable(e)),
+    }
+}
+
+/// JSON body of an endpoint response, or its error
+async fn section(response: Response) -> Value {
+    let status = response.status();
+    let body = match axum::body::to_bytes(response.into_body(), usize::MAX).await {
+        Ok(body) => serde_json::from_slice(&body)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned())),
+        Err(e) => json!({"error": e.to_string()}),
+    };
+    if status.is_success() {
+        body
+    } else {
+        json!({"status": status.as_u16(), "response": body})
+    }
+}
+
+/// Raw register contents as hex bytes, keyed by register
+fn registers_report(registers: &[(u8, Result<Vec<u8>, I2cError>)]) -> Value {
+    let registers: serde_json::Map<String, Value> = registers
+        .iter()
+        .map(|(reg, result)| {
+            let value = match result {
+                Ok(bytes) => bytes
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                Err(e) => format!("error: {}", e),
+            };
+            (format!("0x{:02X}", reg), Value::String(value))
+        })
+        .collect();
+    Value::Object(registers)
+}
+
+/// Recent daemon log lines from the journal
+async fn recent_logs() -> String {
+    let output = Command::new("journalctl")
+        .args(["--unit", "halpid", "--no-pager", "--output", "short-iso"])
+        .args(["--lines", &BUNDLE_LOG_LINES.to_string()])
+        .output()
+        .await;
+    match output {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+        Ok(output) => format!(
+            "journalctl failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(e) => format!("Cannot run journalctl: {}", e),
+    }
+}
+
+/// Build the scan report
+fn scan_report(bus: u8, addr: u8, buses: &[u8], detected: &[Detected]) -> Value {
+    let devices: Vec<Value> = detected
+        .iter()
+        .map(|d| {
+            json!({
+                "bus": d.bus,
+                "addr": format!("0x{:02X}", d.addr),
+                "hardware_version": d.hardware_version.to_string(),
+                "firmware_version": d.firmware_version.to_string(),
+            })
+        })
+        .collect();
+    json!({
+        "configured": {"bus": bus, "addr": format!("0x{:02X}", addr)},
+        "buses": buses,
+        "detected": devices,
+        "suggestion": scan::suggestion(detected, bus, addr),
+    })
+}
+
+/// Collect the diagnostics report
+fn runtime_report(state: &AppState) -> Value {
+    let runtime = tokio::runtime::Handle::current().metrics();
+    let sample_age = state
+        .events
+        .latest()
+        .map(|sample| (chrono::Utc::now() - sample.timestamp).num_milliseconds() as f64 / 1000.0);
+    let responses: serde_json::Map<String, Value> = metrics::HTTP
+        .responses()
+        .into_iter()
+        .map(|(class, count)| (class.to_string(), count.into()))
+        .collect();
+
+    json!({
+        "runtime": {
+            "workers": runtime.num_workers(),
+            "alive_tasks": runtime.num_alive_tasks(),
+            "global_queue_depth": runtime.global_queue_depth(),
+        },
+        "tasks": tasks::snapshot(),
+        "events": {
+            "subscribers": state.events.subscriber_count(),
+            "queue_depth": state.events.queue_depth(),
+            "capacity": CHANNEL_CAPACITY,
+            "latest_sample_age": sample_age,
+        },
+        "device": {
+            "present": state.device.is_present(),
+            "locked": state.device.is_locked(),
+        },
+        "http": {
+            "requests": metrics::HTTP.requests(),
+            "in_flight": metrics::HTTP.in_flight(),
+            "responses": responses,
+        },
+        "memory": memory_usage(),
+    })
+}
+
+/// Memory usage of the daemon process from `/proc/self/status`
+fn memory_usage() -> Value {
+    let Ok(status) = std::fs::read_to_string("/proc/self/status") else {
+        return Value::Null;
+    };
+    json!({
+        "rss_bytes": status_bytes(&status, "VmRSS"),
+        "peak_rss_bytes": status_bytes(&status, "VmHWM"),
+        "virtual_bytes": status_bytes(&status, "VmSize"),
+    })
+}
+
+/// Parse a `Key:   1234 kB` line of a proc status file as bytes
+fn status_bytes(status: &str, key: &str) -> Option<u64> {
+    status.lines().find_map(|line| {
+        let value = line.strip_prefix(key)?.strip_prefix(':')?;
+        let kib = value
+            .trim()
+            .strip_suffix("kB")?
+            .trim()
+            .parse::<u64>()
+            .ok()?;
+        Some(kib * 1024)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halpi_common::types::Version;
+
+    #[test]
+    fn test_status_bytes() {
+        let status = "Name:\thalpid\nVmHWM:\t    2048 kB\nVmRSS:\t    1024 kB\n";
+        assert_eq!(status_bytes(status, "VmRSS"), Some(1024 * 1024));
+        assert_eq!(status_bytes(status, "VmHWM"), Some(2048 * 1024));
+        assert_eq!(status_bytes(status, "VmSize"), None);
+        assert_eq!(status_bytes(status, "Name"), None);
+    }
+
+    #[test]
+    fn test_scan_report() {
+        let detected = [Detected {
+            bus: 3,
+            addr: 0x6D,
+            hardware_version: Version::from_bytes([0, 3, 0, 255]),
+            firmware_version: Version::from_bytes([3, 1, 0, 255]),
+        }];
+        let report = scan_report(1, 0x6D, &[1, 3], &detected);
+        assert_eq!(report["configured"]["addr"], "0x6D");
+        assert_eq!(report["buses"], json!([1, 3]));
+        assert_eq!(report["detected"][0]["bus"], 3);
+        assert_eq!(report["detected"][0]["firmware_version"], "3.1.0");
+        assert!(
+            report["suggestion"]
+                .as_str()
+                .unwrap()
+                .contains("i2c-bus: 3")
+        );
+
+        let report = scan_report(1, 0x6D, &[], &[]);
+        assert_eq!(report["detected"], json!([]));
+        assert!(report["suggestion"].is_null());
+    }
+
+    #[test]
+    fn test_registers_report() {
+        let registers = [
+            (0x04, Ok(vec![3, 1, 0, 0xFF])),
+            (0x1B, Err(I2cError::Cancelled)),
+        ];
+        let report = registers_report(&registers);
+        assert_eq!(report["0x04"], "03 01 00 ff");
+        assert!(report["0x1B"].as_str().unwrap().starts_with("error: "));
+    }
+
+    #[tokio::test]
+    async fn test_section() {
+        let ok = (StatusCode::OK, Json(json!({"a": 1}))).into_response();
+        assert_eq!(section(ok).await, json!({"a": 1}));
+
+        let missing = ApiError::new(ErrorCode::I2cUnavailable, "missing").into_response();
+        let report = section(missing).await;
+        assert_eq!(report["status"], 503);
+        assert_eq!(report["response"]["error"], "missing");
+    }
+
+    #[tokio::test]
+    async fn test_sim() {
+        use crate::i2c::{DeviceHandle, HalpiDevice};
+        use halpi_common::config::Config;
+        use std::sync::Arc;
+        use tokio::sync::RwLock;
+
+        let admin = PeerCredentials {
+            uid: Some(0),
+            gid: Some(0),
+            pid: None,
+        };
+        let config = Arc::new(RwLock::new(Config::default()));
+        let fault = || Fault::VoltageDrop {
+            v_in: 0.0,
+            duration: None,
+        };
+
+        let state = AppState::new(DeviceHandle::missing(1, 0x6D), config.clone());
+        let response = post_sim(State(state), ConnectInfo(admin), Json(fault())).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let device = HalpiDevice::simulated(1, 0x6D, Simulator::new());
+        let state = AppState::new(DeviceHandle::new(device), config);
+        let response = post_sim(State(state.clone()), ConnectInfo(admin), Json(fault())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let snapshot = section(response).await;
+        assert_eq!(snapshot["dcin_voltage"], 0.0);
+        assert_eq!(snapshot["faults"][0]["fault"], "voltage-drop");
+
+        let user = PeerCredentials {
+            uid: Some(u32::MAX),
+            gid: None,
+            pid: None,
+        };
+        let response = get_sim(State(state), ConnectInfo(user)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let memory = memory_usage();
+        assert!(memory["rss_bytes"].as_u64().unwrap() > 0);
+    }
+}
@@ -0,0 +1,65 @@
+//! Controller list endpoint handler
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use halpi_common::config::DEFAULT_DEVICE_ID;
+use serde_json::{Value, json};
+
+use crate::i2c::DeviceHandle;
+use crate::server::app::AppState;
+
+/// GET /devices - List the configured controllers
+///
+/// The primary controller comes first, under the id `default`.
+pub async fn get_devices(State(state): State<AppState>) -> Response {
+    let mut devices = vec![device_json(DEFAULT_DEVICE_ID, &state.device, true)];
+    devices.extend(
+        state
+            .devices
+            .iter()
+            .map(|entry| device_json(&entry.id, &entry.device, false)),
+    );
+    (StatusCode::OK, Json(Value::Array(devices))).into_response()
+}
+
+/// Describe one controller
+fn device_json(id: &str, device: &DeviceHandle, primary: bool) -> Value {
+    json!({
+        "id": id,
+        "bus": device.bus(),
+        "addr": format!("0x{:02X}", device.addr()),
+        "present": device.is_present(),
+        "primary": primary,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halpi_common::config::Config;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn test_get_devices() {
+        let config = Arc::new(RwLock::new(Config::default()));
+        let state = AppState::new(DeviceHandle::missing(1, 0x6D), config).with_devices(vec![(
+            "expansion".to_string(),
+            DeviceHandle::missing(1, 0x6E),
+        )]);
+
+        let response = get_devices(State(state)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let devices: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(devices[0]["id"], "default");
+        assert_eq!(devices[0]["primary"], true);
+        assert_eq!(devices[1]["id"], "expansion");
+        assert_eq!(devices[1]["addr"], "0x6E");
+        assert_eq!(devices[1]["present"], false);
+    }
+}
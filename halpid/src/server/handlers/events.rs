@@ -0,0 +1,158 @@
+//! Event history and stream endpoint handlers
+//!
+//! The history holds the state transitions, alerts and voltage excursions
+//! kept by the event bus; the stream follows all events as they are
+//! published, as server-sent events.
+
+use std::convert::Infallible;
+
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, Stream};
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use halpi_common::api::{ApiError, ErrorCode};
+use halpi_common::events::DaemonEvent;
+
+use crate::server::app::AppState;
+
+/// Query of the history endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct HistoryQuery {
+    /// Only events from this long ago, e.g. "1h"
+    pub since: Option<String>,
+}
+
+/// Query of the stream endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct StreamQuery {
+    /// Also stream measurement samples, which are published several times
+    /// a second
+    #[serde(default)]
+    pub measurements: bool,
+}
+
+/// GET /events - Recent state transitions, alerts and voltage excursions, oldest first
+pub async fn get_events(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> Response {
+    let since = match query.since.as_deref().map(parse_since) {
+        None => None,
+        Some(Ok(since)) => Some(since),
+        Some(Err(e)) => {
+            return ApiError::new(ErrorCode::InvalidRequest, e).into_response();
+        }
+    };
+    let events = filter_since(state.events.history(), since);
+    (StatusCode::OK, Json(events)).into_response()
+}
+
+/// GET /events/stream - Follow events as server-sent events
+///
+/// Each event is a JSON object with a `type` field, sent as an SSE event of
+/// the same name. A subscriber that falls behind skips the events it
+/// missed.
+pub async fn get_event_stream(
+    State(state): State<AppState>,
+    Query(query): Query<StreamQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = state.events.subscribe();
+    let measurements = query.measurements;
+    let events = stream::unfold(receiver, move |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(DaemonEvent::Measurement(_)) if !measurements => continue,
+                Ok(event) => return Some((Ok(sse_event(&event)), receiver)),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+/// Server-sent event for a daemon event
+fn sse_event(event: &DaemonEvent) -> Event {
+    Event::default()
+        .event(event.name())
+        .json_data(event)
+        .unwrap_or_else(|_| Event::default().comment("unserializable event"))
+}
+
+/// Start time of a `since` duration such as "1h"
+fn parse_since(since: &str) -> Result<DateTime<Utc>, String> {
+    let seconds = halpi_common::duration::parse_duration(since)?;
+    i64::try_from(seconds)
+        .ok()
+        .and_then(chrono::Duration::try_seconds)
+        .and_then(|age| Utc::now().checked_sub_signed(age))
+        .ok_or_else(|| format!("since {} is out of range", since))
+}
+
+/// Events at or after `since`
+fn filter_since(events: Vec<DaemonEvent>, since: Option<DateTime<Utc>>) -> Vec<DaemonEvent> {
+    match since {
+        Some(since) => events
+            .into_iter()
+            .filter(|event| event.timestamp() >= since)
+            .collect(),
+        None => events,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halpi_common::events::{Alert, AlertKind};
+
+    fn alert(age_s: i64) -> DaemonEvent {
+        let mut alert = Alert::new(AlertKind::BlackoutDetected, "test");
+        alert.timestamp -= chrono::Duration::seconds(age_s);
+        DaemonEvent::Alert(alert)
+    }
+
+    #[test]
+    fn test_filter_since() {
+        let events = vec![alert(7200), alert(600), alert(0)];
+        assert_eq!(filter_since(events.clone(), None).len(), 3);
+        let since = parse_since("1h").unwrap();
+        assert_eq!(filter_since(events, Some(since)).len(), 2);
+
+        assert!(parse_since("soon").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_events() {
+        use crate::i2c::DeviceHandle;
+        use halpi_common::config::Config;
+        use std::sync::Arc;
+        use tokio::sync::RwLock;
+
+        let config = Arc::new(RwLock::new(Config::default()));
+        let state = AppState::new(DeviceHandle::missing(1, 0x6D), config);
+        state.events.publish(alert(0));
+
+        let query = HistoryQuery {
+            since: Some("10m".to_string()),
+        };
+        let response = get_events(State(state.clone()), Query(query)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let events: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(events[0]["kind"], "blackout-detected");
+
+        let query = HistoryQuery {
+            since: Some("soon".to_string()),
+        };
+        let response = get_events(State(state), Query(query)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
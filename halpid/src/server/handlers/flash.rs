@@ -1,75 +1,294 @@
 //! Firmware upload endpoint handler
+//!
+//! The upload is a `multipart/form-data` body with a single `firmware`
+//! field. The image is read in chunks up to `api-limits.max-firmware-kb`,
+//! so an oversized upload is refused with 413 before it fills the memory.
+//! The upload stays possible while the controller fails the self-test's
+//! compatibility check, which is repeated afterwards, so that outdated
+//! firmware can be replaced.
 
-use axum::Json;
-use axum::extract::{Multipart, State};
-use axum::http::StatusCode;
+use axum::extract::State;
+use axum::extract::multipart::{Multipart, MultipartRejection};
+use axum::http::{HeaderMap, StatusCode, header};
 use axum::response::{IntoResponse, Response};
+use halpi_common::api::{ApiError, ErrorCode};
+use halpi_common::events::{DaemonEvent, DfuProgress};
 use serde_json::json;
 
+use super::device_unavailable;
+use crate::selftest;
 use crate::server::app::AppState;
 
+/// Room for the multipart boundaries and headers around the image in the
+/// request body limit
+pub const MULTIPART_OVERHEAD: usize = 16 * 1024;
+
+/// Why an upload was refused
+#[derive(Debug, PartialEq)]
+enum UploadError {
+    /// The image exceeds the limit in bytes (413)
+    TooLarge(usize),
+    /// The request or the field is not of the expected type (415)
+    UnsupportedType(String),
+    /// The form is malformed or has unexpected fields (400)
+    Invalid(String),
+}
+
+impl IntoResponse for UploadError {
+    fn into_response(self) -> Response {
+        let error = match self {
+            UploadError::TooLarge(limit) => ApiError::new(
+                ErrorCode::PayloadTooLarge,
+                format!(
+                    "Firmware exceeds the maximum size of {} KiB (api-limits.max-firmware-kb)",
+                    limit / 1024
+                ),
+            )
+            .with_detail(json!({"limit": limit})),
+            UploadError::UnsupportedType(message) => {
+                ApiError::new(ErrorCode::UnsupportedMediaType, message)
+            }
+            UploadError::Invalid(message) => ApiError::new(ErrorCode::InvalidRequest, message),
+        };
+        error.into_response()
+    }
+}
+
 /// POST /flash - Upload firmware to device
-pub async fn post_flash(State(state): State<AppState>, mut multipart: Multipart) -> Response {
-    // Extract firmware file from multipart form data
-    let firmware_data = match extract_firmware(&mut multipart).await {
+pub async fn post_flash(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    multipart: Result<Multipart, MultipartRejection>,
+) -> Response {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !content_type.starts_with("multipart/form-data") {
+        return UploadError::UnsupportedType(format!(
+            "Content-Type must be multipart/form-data, not '{}'",
+            content_type
+        ))
+        .into_response();
+    }
+    let mut multipart = match multipart {
+        Ok(multipart) => multipart,
+        Err(e) => return UploadError::Invalid(e.body_text()).into_response(),
+    };
+
+    let limit = state.limits.config().max_firmware_size();
+    let firmware_data = match extract_firmware(&mut multipart, limit).await {
         Ok(data) => data,
-        Err(e) => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({"error": format!("Failed to extract firmware: {}", e)})),
-            )
-                .into_response();
-        }
+        Err(e) => return e.into_response(),
     };
 
     if firmware_data.is_empty() {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(json!({"error": "Firmware file is empty"})),
-        )
-            .into_response();
+        return UploadError::Invalid("Firmware file is empty".to_string()).into_response();
     }
 
-    // Acquire device lock for the entire upload process
-    let mut device = state.device.lock().await;
-
-    // Upload firmware using high-level method with progress tracking
-    if let Err(e) = device.upload_firmware(&firmware_data, |_written, _total| {
-        // Progress callback - silent for now
-        // Could add tracing::debug!() here for verbose logging
-    }) {
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": format!("Failed to upload firmware: {}", e)})),
-        )
+    // Hold the device for the entire upload process
+    let total = firmware_data.len();
+    let progress_state = state.clone();
+    let result = state
+        .device
+        .with(move |device| {
+            publish_dfu(&progress_state, DfuProgress::Started { total });
+
+            // Upload firmware using high-level method with progress tracking
+            device.upload_firmware(&firmware_data, |written, total| {
+                publish_dfu(&progress_state, DfuProgress::Writing { written, total });
+            })
+        })
+        .await;
+
+    // New firmware reports a new version; a failed update may have reset
+    // the controller, so re-read the identity either way
+    state.identity.invalidate();
+
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            publish_dfu(
+                &state,
+                DfuProgress::Failed {
+                    error: e.to_string(),
+                },
+            );
+            return ApiError::new(
+                ErrorCode::DeviceError,
+                format!("Failed to upload firmware: {}", e),
+            )
             .into_response();
+        }
+        Err(e) => return device_unavailable(e),
+    }
+
+    publish_dfu(&state, DfuProgress::Completed { total });
+
+    let self_test = state.config.read().await.self_test.clone();
+    if self_test.enabled
+        && let Ok(min_firmware) = self_test.min_firmware()
+        && let Err(e) = selftest::run(&state.device, &state.status, min_firmware).await
+    {
+        tracing::warn!("Cannot repeat the controller self-test: {}", e);
     }
 
     (StatusCode::NO_CONTENT, ()).into_response()
 }
 
-/// Extract firmware data from multipart form
-async fn extract_firmware(multipart: &mut Multipart) -> Result<Vec<u8>, String> {
-    while let Some(field) = multipart
-        .next_field()
-        .await
-        .map_err(|e| format!("Failed to read multipart field: {}", e))?
-    {
-        if let Some(name) = field.name()
-            && name == "firmware"
+/// Publish a firmware upload progress event
+fn publish_dfu(state: &AppState, progress: DfuProgress) {
+    state.events.publish(DaemonEvent::Dfu {
+        timestamp: chrono::Utc::now(),
+        progress,
+    });
+}
+
+/// Extract the firmware image from the multipart form
+///
+/// The form must have exactly one `firmware` field, sent as
+/// `application/octet-stream` or without a type, of at most `limit` bytes.
+async fn extract_firmware(multipart: &mut Multipart, limit: usize) -> Result<Vec<u8>, UploadError> {
+    let read_error = |e: axum::extract::multipart::MultipartError| {
+        if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            UploadError::TooLarge(limit)
+        } else {
+            UploadError::Invalid(format!("Failed to read multipart form: {}", e.body_text()))
+        }
+    };
+
+    let mut firmware: Option<Vec<u8>> = None;
+    while let Some(mut field) = multipart.next_field().await.map_err(read_error)? {
+        match field.name() {
+            Some("firmware") if firmware.is_none() => {}
+            Some("firmware") => {
+                return Err(UploadError::Invalid(
+                    "Only one 'firmware' field is allowed".to_string(),
+                ));
+            }
+            name => {
+                return Err(UploadError::Invalid(format!(
+                    "Unexpected field '{}'; only 'firmware' is accepted",
+                    name.unwrap_or_default()
+                )));
+            }
+        }
+        if let Some(field_type) = field.content_type()
+            && field_type != "application/octet-stream"
         {
-            let data = field
-                .bytes()
-                .await
-                .map_err(|e| format!("Failed to read firmware data: {}", e))?;
-            return Ok(data.to_vec());
+            return Err(UploadError::UnsupportedType(format!(
+                "The firmware field must be application/octet-stream, not '{}'",
+                field_type
+            )));
+        }
+
+        let mut data = Vec::new();
+        while let Some(chunk) = field.chunk().await.map_err(read_error)? {
+            if data.len() + chunk.len() > limit {
+                return Err(UploadError::TooLarge(limit));
+            }
+            data.extend_from_slice(&chunk);
         }
+        firmware = Some(data);
     }
 
-    Err("No 'firmware' field found in multipart form".to_string())
+    firmware.ok_or_else(|| {
+        UploadError::Invalid("No 'firmware' field found in multipart form".to_string())
+    })
 }
 
 #[cfg(test)]
 mod tests {
-    // No tests needed for flash handler - integration testing required with actual hardware
+    use super::*;
+    use crate::i2c::DeviceHandle;
+    use crate::server::create_app;
+    use axum::body::Body;
+    use halpi_common::config::{ApiLimitsConfig, Config};
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+    use tower::ServiceExt;
+
+    const BOUNDARY: &str = "halpi-test-boundary";
+
+    /// Multipart body with the given fields of (name, content type, data)
+    fn form(fields: &[(&str, Option<&str>, &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (name, content_type, data) in fields {
+            body.extend_from_slice(format!("--{}\r\n", BOUNDARY).as_bytes());
+            body.extend_from_slice(
+                format!(
+                    "Content-Disposition: form-data; name=\"{}\"; filename=\"fw.bin\"\r\n",
+                    name
+                )
+                .as_bytes(),
+            );
+            if let Some(content_type) = content_type {
+                body.extend_from_slice(format!("Content-Type: {}\r\n", content_type).as_bytes());
+            }
+            body.extend_from_slice(b"\r\n");
+            body.extend_from_slice(data);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+        body
+    }
+
+    async fn post(content_type: &str, body: Vec<u8>) -> StatusCode {
+        let config = Arc::new(RwLock::new(Config::default()));
+        let state =
+            AppState::new(DeviceHandle::missing(1, 0x6D), config).with_limits(ApiLimitsConfig {
+                max_firmware_kb: 64,
+                ..Default::default()
+            });
+        let request = axum::http::Request::post("/flash")
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap();
+        create_app(state).oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_flash_validation() {
+        let multipart = format!("multipart/form-data; boundary={}", BOUNDARY);
+        let octets = Some("application/octet-stream");
+
+        assert_eq!(
+            post("application/octet-stream", vec![1, 2, 3]).await,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+        assert_eq!(
+            post(&multipart, form(&[("firmware", Some("text/plain"), b"fw")])).await,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+        assert_eq!(
+            post(&multipart, form(&[("image", octets, b"fw")])).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            post(
+                &multipart,
+                form(&[("firmware", octets, b"fw"), ("firmware", octets, b"fw")])
+            )
+            .await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            post(&multipart, form(&[("firmware", octets, b"")])).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            post(&multipart, form(&[("firmware", octets, &[0; 65 * 1024])])).await,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        // Beyond the request body limit too
+        assert_eq!(
+            post(&multipart, form(&[("firmware", octets, &[0; 200 * 1024])])).await,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        // A valid upload reaches the device
+        assert_eq!(
+            post(&multipart, form(&[("firmware", None, &[0; 1024])])).await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
 }
@@ -1,11 +1,16 @@
-//! Health and version endpoint handlers
+//! Health, version and info endpoint handlers
 
 use axum::Json;
 use axum::extract::State;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use serde_json::json;
+use halpi_common::api::{API_VERSION, VersionResponse};
+use halpi_common::protocol::Capabilities;
+use serde_json::{Value, json};
 
+use crate::drift::DriftReport;
+use crate::i2c::{DeviceIdentity, I2cStats};
+use crate::selftest::SelfTestReport;
 use crate::server::app::AppState;
 
 /// GET / - Root health check endpoint
@@ -17,13 +22,120 @@ pub async fn root() -> Response {
 
 /// GET /version - Version information endpoint
 ///
-/// Returns JSON object with daemon version
+/// Returns JSON object with daemon version, plus the controller's hardware
+/// and firmware versions and device ID once they are known
 pub async fn version(State(state): State<AppState>) -> Response {
-    let version_json = json!({
-        "daemon_version": state.version
-    });
+    let identity = state.identity.get(&state.device).await.ok();
+
+    (
+        StatusCode::OK,
+        Json(version_report(state.version, identity)),
+    )
+        .into_response()
+}
+
+/// Build the version report
+fn version_report(daemon_version: &str, identity: Option<DeviceIdentity>) -> VersionResponse {
+    VersionResponse {
+        daemon_version: daemon_version.to_string(),
+        api_version: API_VERSION,
+        hardware_version: identity
+            .as_ref()
+            .map(|identity| identity.hardware_version.to_string()),
+        firmware_version: identity
+            .as_ref()
+            .map(|identity| identity.firmware_version.to_string()),
+        device_id: identity.map(|identity| identity.device_id),
+    }
+}
+
+/// GET /info - Daemon and controller identity in one place
+///
+/// Adds the I2C location, API socket and daemon uptime to the version
+/// report, for support requests, and which features the controller's
+/// hardware and firmware support once they are known.
+pub async fn info(State(state): State<AppState>) -> Response {
+    let identity = state.identity.get(&state.device).await.ok();
+    let capabilities = identity
+        .as_ref()
+        .map(|identity| capability_map(&identity.capabilities()));
+    let mut report = json!(version_report(state.version, identity));
+    if let Some(capabilities) = capabilities {
+        report["capabilities"] = capabilities;
+    }
+    report["device_present"] = json!(state.device.is_present());
+    report["i2c_bus"] = json!(state.device.bus());
+    report["i2c_addr"] = json!(format!("0x{:02X}", state.device.addr()));
+    report["socket"] = json!(state.socket_path().await);
+    report["uptime_s"] = json!(state.started.elapsed().as_secs());
+
+    (StatusCode::OK, Json(report)).into_response()
+}
 
-    (StatusCode::OK, Json(version_json)).into_response()
+/// Each feature by key, with whether the controller supports it
+fn capability_map(capabilities: &Capabilities) -> Value {
+    capabilities
+        .map()
+        .into_iter()
+        .map(|(feature, supported)| (feature.key().to_string(), json!(supported)))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// GET /health - Daemon health and I2C error statistics
+///
+/// The status is "degraded" when the controller has not been connected yet,
+/// the state machine has not published a current measurement sample, i.e.
+/// the controller is not being polled, the controller failed the startup
+/// self-test, or the controller settings differ from the `controller`
+/// configuration.
+pub async fn health(State(state): State<AppState>) -> Response {
+    let sampling = state.events.current().is_some();
+    let drift = state.status.drift();
+    let self_test = state.status.self_test();
+    let stats = state
+        .device
+        .with(|device| device.stats().clone())
+        .await
+        .ok();
+
+    (
+        StatusCode::OK,
+        Json(health_report(
+            sampling,
+            stats.as_ref(),
+            drift.as_ref(),
+            self_test.as_ref(),
+        )),
+    )
+        .into_response()
+}
+
+/// Build the health report; `stats` is `None` if the device is missing,
+/// `drift` if no controller settings are checked and `self_test` if the
+/// self-test has not run
+fn health_report(
+    sampling: bool,
+    stats: Option<&I2cStats>,
+    drift: Option<&DriftReport>,
+    self_test: Option<&SelfTestReport>,
+) -> Value {
+    let drifted = drift.is_some_and(|report| !report.drift.is_empty());
+    let failed = self_test.is_some_and(|report| !report.passed());
+    let healthy = sampling && stats.is_some() && !drifted && !failed;
+    let mut report = json!({
+        "status": if healthy { "ok" } else { "degraded" },
+        "device": if stats.is_some() { "present" } else { "missing" },
+        "sampling": sampling,
+        "i2c": stats.cloned().unwrap_or_default().to_json(),
+    });
+    if let Some(drift) = drift {
+        report["controller_config"] = json!(drift);
+    }
+    if let Some(self_test) = self_test {
+        report["self_test"] = json!(self_test);
+    }
+    report
 }
 
 #[cfg(test)]
@@ -36,18 +148,108 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[test]
+    fn test_version_report() {
+        let report = version_report("5.0.0", None);
+        assert_eq!(
+            json!(report),
+            json!({"daemon_version": "5.0.0", "api_version": API_VERSION})
+        );
+
+        let identity = DeviceIdentity {
+            hardware_version: halpi_common::types::Version::new(1, 0, 0),
+            firmware_version: halpi_common::types::Version::new(3, 1, 0),
+            device_id: "0123456789abcdef".to_string(),
+        };
+        let capabilities = capability_map(&identity.capabilities());
+        assert_eq!(capabilities["led_pattern"], false);
+        assert_eq!(capabilities["usb_ports"], true);
+        let report = version_report("5.0.0", Some(identity));
+        assert_eq!(report.firmware_version.as_deref(), Some("3.1.0"));
+        assert_eq!(report.device_id.as_deref(), Some("0123456789abcdef"));
+    }
+
+    #[tokio::test]
+    async fn test_info_device_missing() {
+        use crate::i2c::DeviceHandle;
+        use halpi_common::config::Config;
+        use std::sync::Arc;
+        use tokio::sync::RwLock;
+
+        let config = Arc::new(RwLock::new(Config::default()));
+        let state = AppState::new(DeviceHandle::missing(1, 0x6D), config);
+
+        let response = info(State(state)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let report: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(report["device_present"], false);
+        assert_eq!(report["i2c_bus"], 1);
+        assert_eq!(report["i2c_addr"], "0x6D");
+        assert_eq!(report["socket"], "/run/halpid/halpid.sock");
+        assert!(report["firmware_version"].is_null());
+        assert_eq!(report["uptime_s"], 0);
+    }
+
+    #[test]
+    fn test_health_report() {
+        let stats = I2cStats::new();
+        let report = health_report(true, Some(&stats), None, None);
+        assert_eq!(report["status"], "ok");
+        assert_eq!(report["device"], "present");
+        assert_eq!(report["i2c"]["totals"]["permanent_errors"], 0);
+        assert!(report.get("controller_config").is_none());
+
+        let report = health_report(false, Some(&stats), None, None);
+        assert_eq!(report["status"], "degraded");
+        assert_eq!(report["sampling"], false);
+
+        let mut drift = DriftReport {
+            checked_at: chrono::Utc::now(),
+            drift: Vec::new(),
+        };
+        let report = health_report(true, Some(&stats), Some(&drift), None);
+        assert_eq!(report["status"], "ok");
+        assert_eq!(report["controller_config"]["drift"], json!([]));
+        drift.drift.push(halpi_common::api::ConfigDrift {
+            key: "led_brightness".to_string(),
+            expected: json!(40),
+            actual: json!(255),
+        });
+        let report = health_report(true, Some(&stats), Some(&drift), None);
+        assert_eq!(report["status"], "degraded");
+        assert_eq!(report["controller_config"]["drift"][0]["actual"], 255);
+
+        let self_test = SelfTestReport {
+            checked_at: chrono::Utc::now(),
+            firmware_version: Some("2.1.0".to_string()),
+            hardware_version: Some("1.0.0".to_string()),
+            min_firmware: "3.0.0".to_string(),
+            failures: vec!["firmware 2.1.0 is older than the minimum 3.0.0".to_string()],
+            compatible: false,
+        };
+        let report = health_report(true, Some(&stats), None, Some(&self_test));
+        assert_eq!(report["status"], "degraded");
+        assert_eq!(report["self_test"]["compatible"], false);
+        assert_eq!(report["self_test"]["min_firmware"], "3.0.0");
+
+        let report = health_report(false, None, None, None);
+        assert_eq!(report["status"], "degraded");
+        assert_eq!(report["device"], "missing");
+        assert_eq!(report["i2c"]["totals"]["transfers"], 0);
+    }
+
     #[tokio::test]
     async fn test_version_endpoint() {
-        use crate::i2c::device::HalpiDevice;
+        use crate::i2c::DeviceHandle;
+        use crate::i2c::{HalpiDevice, Simulator};
         use halpi_common::config::Config;
         use std::sync::Arc;
-        use tokio::sync::{Mutex, RwLock};
+        use tokio::sync::RwLock;
 
-        // Skip test if I2C hardware not available
-        let device = match HalpiDevice::new(1, 0x6D) {
-            Ok(d) => Arc::new(Mutex::new(d)),
-            Err(_) => return,
-        };
+        let device = DeviceHandle::new(HalpiDevice::simulated(1, 0x6D, Simulator::new()));
         let config = Arc::new(RwLock::new(Config::default()));
         let state = AppState::new(device, config);
 
@@ -0,0 +1,284 @@
+//! Front LED endpoint handlers
+//!
+//! Brightness needs firmware 2.0.0, patterns and colors 3.3.0. Settings the
+//! firmware does not support are reported as null. Identification ramps
+//! the brightness, so it works with any firmware that has it.
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use halpi_common::api::{ApiError, ErrorCode};
+use halpi_common::protocol::{Feature, LedColor, LedPattern};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::time::Duration;
+use tracing::info;
+
+use super::{device_error_code, device_unavailable};
+use crate::i2c::I2cError;
+use crate::i2c::device::HalpiDevice;
+use crate::identify::{DEFAULT_IDENTIFY_DURATION, MAX_IDENTIFY_DURATION};
+use crate::server::app::AppState;
+use halpi_common::duration::deserialize_seconds;
+
+/// Request body for the LED endpoint; settings not given are kept
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LedRequest {
+    #[serde(default)]
+    pub brightness: Option<u8>,
+    #[serde(default)]
+    pub pattern: Option<LedPattern>,
+    #[serde(default)]
+    pub color: Option<LedColor>,
+}
+
+/// GET /led - LED brightness, pattern and color
+pub async fn get_led(State(state): State<AppState>) -> Response {
+    match state.device.with(read_led).await {
+        Ok(Ok(report)) => (StatusCode::OK, Json(report)).into_response(),
+        Ok(Err(e)) => led_error("read", e),
+        Err(e) => device_unavailable(e),
+    }
+}
+
+/// PUT /led - Set the LED brightness, pattern and/or color
+pub async fn put_led(State(state): State<AppState>, Json(payload): Json<LedRequest>) -> Response {
+    if payload.brightness.is_none() && payload.pattern.is_none() && payload.color.is_none() {
+        return ApiError::new(
+            ErrorCode::InvalidRequest,
+            "Give at least one of brightness, pattern and color",
+        )
+        .into_response();
+    }
+
+    let result = state
+        .device
+        .with(move |device| {
+            // Pattern and color need newer firmware than brightness, so
+            // nothing is changed if they are not supported
+            if let Some(color) = payload.color {
+                device.set_led_color(color)?;
+            }
+            if let Some(pattern) = payload.pattern {
+                device.set_led_pattern(pattern)?;
+            }
+            if let Some(brightness) = payload.brightness {
+                device.set_led_brightness(brightness)?;
+            }
+            read_led(device)
+        })
+        .await;
+    match result {
+        Ok(Ok(report)) => {
+            info!(
+                "LED set to brightness {}, pattern {}, color {}",
+                report["brightness"], report["pattern"], report["color"]
+            );
+            (StatusCode::OK, Json(report)).into_response()
+        }
+        Ok(Err(e)) => led_error("set", e),
+        Err(e) => device_unavailable(e),
+    }
+}
+
+/// Request body for the identify endpoint
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdentifyRequest {
+    /// Seconds to ramp the LED, or a duration such as "1m"
+    #[serde(
+        default = "default_identify_duration",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub duration: u64,
+}
+
+fn default_identify_duration() -> u64 {
+    DEFAULT_IDENTIFY_DURATION
+}
+
+/// POST /identify - Ramp the LED up and down for a while
+///
+/// The brightness is restored afterwards. A request while the LED is
+/// ramping extends the time.
+pub async fn post_identify(
+    State(state): State<AppState>,
+    Json(payload): Json<IdentifyRequest>,
+) -> Response {
+    if !(1..=MAX_IDENTIFY_DURATION).contains(&payload.duration) {
+        return ApiError::new(
+            ErrorCode::InvalidValue,
+            format!("Duration must be 1-{} seconds", MAX_IDENTIFY_DURATION),
+        )
+        .into_response();
+    }
+
+    // The brightness to restore is read when the ramp starts, not while
+    // it runs
+    let duration = Duration::from_secs(payload.duration);
+    if !state.identify.extend(duration) {
+        let brightness = match state
+            .device
+            .with(|device| device.get_led_brightness())
+            .await
+        {
+            Ok(Ok(brightness)) => brightness,
+            Ok(Err(e)) => return led_error("read", e),
+            Err(e) => return device_unavailable(e),
+        };
+        state
+            .identify
+            .start(state.device.clone(), brightness, duration);
+    }
+    (
+        StatusCode::ACCEPTED,
+        Json(json!({"duration_s": payload.duration})),
+    )
+        .into_response()
+}
+
+/// Read the LED settings the firmware supports
+fn read_led(device: &mut HalpiDevice) -> Result<Value, I2cError> {
+    let brightness = match device.get_led_brightness() {
+        Ok(brightness) => Some(brightness),
+        Err(I2cError::Unsupported { .. }) => None,
+        Err(e) => return Err(e),
+    };
+    let (pattern, color) = if device.supports(Feature::LedPattern)? {
+        (
+            Some(device.get_led_pattern()?),
+            Some(device.get_led_color()?),
+        )
+    } else {
+        (None, None)
+    };
+    Ok(led_report(brightness, pattern, color))
+}
+
+fn led_report(
+    brightness: Option<u8>,
+    pattern: Option<LedPattern>,
+    color: Option<LedColor>,
+) -> Value {
+    json!({
+        "brightness": brightness,
+        "pattern": pattern,
+        "color": color,
+        "patterns_supported": pattern.is_some(),
+    })
+}
+
+/// Response for a failed LED access
+///
+/// Settings the firmware does not support are reported as not implemented,
+/// not as a bad request.
+fn led_error(operation: &str, error: I2cError) -> Response {
+    ApiError::new(
+        device_error_code(&error),
+        format!("Failed to {} LED: {}", operation, error),
+    )
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i2c::DeviceHandle;
+    use halpi_common::config::Config;
+    use halpi_common::protocol::Capabilities;
+    use halpi_common::types::Version;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[test]
+    fn test_led_report() {
+        let red = LedColor {
+            red: 0xFF,
+            green: 0,
+            blue: 0,
+        };
+        let report = led_report(Some(128), Some(LedPattern::FastBlink), Some(red));
+        assert_eq!(report["brightness"], 128);
+        assert_eq!(report["pattern"], "fast-blink");
+        assert_eq!(report["color"], "#ff0000");
+        assert_eq!(report["patterns_supported"], true);
+
+        let report = led_report(Some(128), None, None);
+        assert!(report["pattern"].is_null());
+        assert_eq!(report["patterns_supported"], false);
+    }
+
+    #[test]
+    fn test_led_request() {
+        let request: LedRequest =
+            serde_json::from_value(json!({"pattern": "blink", "color": "yellow"})).unwrap();
+        assert_eq!(request.pattern, Some(LedPattern::Blink));
+        assert_eq!(request.color.unwrap().to_string(), "#ffff00");
+        assert_eq!(request.brightness, None);
+
+        assert!(serde_json::from_value::<LedRequest>(json!({"pattern": "strobe"})).is_err());
+        assert!(serde_json::from_value::<LedRequest>(json!({"brightness": 256})).is_err());
+        assert!(serde_json::from_value::<LedRequest>(json!({"colour": "red"})).is_err());
+    }
+
+    #[test]
+    fn test_led_error_status() {
+        let unsupported = I2cError::Unsupported {
+            feature: Feature::LedPattern,
+            capabilities: Capabilities {
+                hardware: Version::new(1, 0, 0),
+                firmware: Version::new(3, 2, 0),
+            },
+        };
+        assert_eq!(
+            led_error("set", unsupported).status(),
+            StatusCode::NOT_IMPLEMENTED
+        );
+        assert_eq!(
+            led_error("set", I2cError::Cancelled).status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn test_identify_request() {
+        let request: IdentifyRequest = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(request.duration, DEFAULT_IDENTIFY_DURATION);
+        let request: IdentifyRequest = serde_json::from_value(json!({"duration": "1m"})).unwrap();
+        assert_eq!(request.duration, 60);
+    }
+
+    #[tokio::test]
+    async fn test_post_identify() {
+        let device = DeviceHandle::missing(1, 0x6D);
+        let state = AppState::new(device, Arc::new(RwLock::new(Config::default())));
+        let request = |duration| Json(IdentifyRequest { duration });
+
+        let response = post_identify(State(state.clone()), request(0)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = post_identify(State(state.clone()), request(3600)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = post_identify(State(state.clone()), request(10)).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(!state.identify.running());
+    }
+
+    #[tokio::test]
+    async fn test_put_led_empty() {
+        let device = DeviceHandle::missing(1, 0x6D);
+        let state = AppState::new(device, Arc::new(RwLock::new(Config::default())));
+        let response = put_led(State(state), Json(LedRequest::default())).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_get_led_device_missing() {
+        let device = DeviceHandle::missing(1, 0x6D);
+        let state = AppState::new(device, Arc::new(RwLock::new(Config::default())));
+        let response = get_led(State(state)).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
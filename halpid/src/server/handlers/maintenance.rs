@@ -0,0 +1,96 @@
+//! Maintenance mode endpoint handler
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use halpi_common::api::{ApiError, ErrorCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::info;
+
+use crate::server::app::AppState;
+
+/// Maintenance mode duration if the request does not give one, in seconds
+pub const DEFAULT_DURATION: u64 = 3600;
+
+/// Longest maintenance mode duration, in seconds
+pub const MAX_DURATION: u64 = 7 * 24 * 3600;
+
+/// Request body for maintenance endpoint
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MaintenanceRequest {
+    pub enabled: bool,
+    /// Seconds until maintenance mode ends by itself
+    #[serde(default)]
+    pub duration: Option<u64>,
+}
+
+/// POST /maintenance - Switch maintenance mode on or off
+///
+/// In maintenance mode, a blackout exceeding the time limit does not shut
+/// the system down. Measurements, alerts and the watchdog carry on as usual.
+pub async fn post_maintenance(
+    State(state): State<AppState>,
+    Json(payload): Json<MaintenanceRequest>,
+) -> Response {
+    if !payload.enabled {
+        if state.status.maintenance_until().is_some() {
+            info!("Maintenance mode disabled");
+        }
+        state.status.set_maintenance(None);
+        return (StatusCode::OK, Json(json!({"enabled": false}))).into_response();
+    }
+
+    let duration = payload.duration.unwrap_or(DEFAULT_DURATION);
+    if !(1..=MAX_DURATION).contains(&duration) {
+        return ApiError::new(
+            ErrorCode::InvalidValue,
+            format!("duration must be 1-{} seconds", MAX_DURATION),
+        )
+        .into_response();
+    }
+
+    let until = Utc::now() + chrono::Duration::seconds(duration as i64);
+    info!(
+        "Maintenance mode enabled until {}, blackouts will not shut down",
+        until.to_rfc3339()
+    );
+    state.status.set_maintenance(Some(until));
+    (
+        StatusCode::OK,
+        Json(json!({"enabled": true, "until": until.to_rfc3339()})),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i2c::DeviceHandle;
+    use halpi_common::config::Config;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn test_post_maintenance() {
+        let device = DeviceHandle::missing(1, 0x6D);
+        let config = Arc::new(RwLock::new(Config::default()));
+        let state = AppState::new(device, config);
+
+        let request = |enabled, duration| Json(MaintenanceRequest { enabled, duration });
+        let response = post_maintenance(State(state.clone()), request(true, Some(1800))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let until = state.status.maintenance_until().unwrap();
+        assert!(until > Utc::now() + chrono::Duration::seconds(1790));
+
+        let response = post_maintenance(State(state.clone()), request(true, Some(0))).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(state.status.maintenance_until(), Some(until));
+
+        let response = post_maintenance(State(state.clone()), request(false, None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(state.status.maintenance_until(), None);
+    }
+}
@@ -0,0 +1,38 @@
+//! Prometheus metrics endpoint handler
+
+use axum::extract::State;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+
+use crate::metrics;
+use crate::server::app::AppState;
+
+/// Content type of the Prometheus text exposition format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// GET /metrics - Daemon metrics in Prometheus text format
+pub async fn get_metrics(State(state): State<AppState>) -> Response {
+    let stats = state
+        .device
+        .with(|device| device.stats().clone())
+        .await
+        .unwrap_or_default();
+    metrics_response(metrics::render(&stats))
+}
+
+/// Wrap a rendered exposition in a response
+fn metrics_response(body: String) -> Response {
+    (StatusCode::OK, [(header::CONTENT_TYPE, CONTENT_TYPE)], body).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_metrics_endpoint() {
+        let response = metrics_response(metrics::render(&Default::default()));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], CONTENT_TYPE);
+    }
+}
@@ -1,8 +1,42 @@
 //! HTTP request handlers
 
 pub mod config;
+pub mod debug;
+pub mod devices;
+pub mod events;
 pub mod flash;
 pub mod health;
+pub mod led;
+pub mod maintenance;
+pub mod metrics;
+pub mod power_schedule;
+pub mod rtc;
 pub mod shutdown;
+pub mod state;
+pub mod stats;
 pub mod usb;
 pub mod values;
+
+use axum::response::{IntoResponse, Response};
+use halpi_common::api::{ApiError, ErrorCode};
+
+use crate::i2c::I2cError;
+
+/// Response for a device that cannot be locked
+///
+/// A controller that has not been connected yet is reported as 503 Service
+/// Unavailable, so clients can tell it apart from a failed transfer.
+pub(crate) fn device_unavailable(error: I2cError) -> Response {
+    ApiError::new(ErrorCode::I2cUnavailable, error.to_string()).into_response()
+}
+
+/// Error code for a failed controller access
+///
+/// A feature the controller's firmware or hardware lacks is reported as 501
+/// Not Implemented with the versions it needs, instead of a device error.
+pub(crate) fn device_error_code(error: &I2cError) -> ErrorCode {
+    match error {
+        I2cError::Unsupported { .. } => ErrorCode::NotSupported,
+        _ => ErrorCode::DeviceError,
+    }
+}
@@ -0,0 +1,61 @@
+//! Power schedule endpoint handler
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use chrono::Local;
+use serde_json::{Value, json};
+
+use halpi_common::config::PowerWindowConfig;
+
+use crate::server::app::AppState;
+use crate::state_machine::schedule::next_window;
+
+/// GET /power-schedule - Configured standby windows and the next one
+pub async fn get_power_schedule(State(state): State<AppState>) -> Response {
+    let config = state.config.read().await;
+    (
+        StatusCode::OK,
+        Json(schedule_report(&config.power_schedule)),
+    )
+        .into_response()
+}
+
+/// Build the power schedule report
+fn schedule_report(windows: &[PowerWindowConfig]) -> Value {
+    let next = next_window(windows, &Local::now()).map(|window| {
+        json!({
+            "off": window.off.with_timezone(&Local).to_rfc3339(),
+            "on": window.on.with_timezone(&Local).to_rfc3339(),
+        })
+    });
+    json!({"windows": windows, "next": next})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_report() {
+        let report = schedule_report(&[]);
+        assert_eq!(report["windows"], json!([]));
+        assert!(report["next"].is_null());
+
+        let windows = [PowerWindowConfig {
+            off: "01:00".to_string(),
+            on: "06:00".to_string(),
+            days: vec![chrono::Weekday::Sun],
+        }];
+        let report = schedule_report(&windows);
+        assert_eq!(report["windows"][0]["days"], json!(["Sun"]));
+        assert!(
+            report["next"]["off"]
+                .as_str()
+                .unwrap()
+                .contains("T01:00:00")
+        );
+        assert!(report["next"]["on"].as_str().unwrap().contains("T06:00:00"));
+    }
+}
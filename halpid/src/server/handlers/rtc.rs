@@ -0,0 +1,111 @@
+//! Hardware RTC endpoint handlers
+
+use axum::Json;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use halpi_common::api::{ApiError, ErrorCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::path::Path;
+use tracing::info;
+
+use super::shutdown::parse_datetime;
+use crate::daemon::clock;
+use crate::daemon::rtc::{self, DEFAULT_RTC_DEVICE};
+
+/// Request body for the RTC endpoint
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct RtcRequest {
+    /// Time to set (ISO 8601); the system time if not given
+    #[serde(default)]
+    pub time: Option<String>,
+}
+
+/// GET /rtc - RTC time and its drift from the system time
+pub async fn get_rtc() -> Response {
+    match rtc::read(Path::new(DEFAULT_RTC_DEVICE)) {
+        Ok(time) => (
+            StatusCode::OK,
+            Json(rtc_report(time, Utc::now(), clock::synchronized())),
+        )
+            .into_response(),
+        Err(e) => rtc_error("read", e),
+    }
+}
+
+/// PUT /rtc - Set the RTC to the given time, or to the system time
+pub async fn put_rtc(Json(payload): Json<RtcRequest>) -> Response {
+    let time = match payload.time.as_deref().map(parse_datetime) {
+        None => Utc::now(),
+        Some(Ok(timestamp)) => match DateTime::from_timestamp(timestamp as i64, 0) {
+            Some(time) => time,
+            None => return bad_time(format!("timestamp {} out of range", timestamp)),
+        },
+        Some(Err(e)) => return bad_time(e),
+    };
+
+    let device = Path::new(DEFAULT_RTC_DEVICE);
+    if let Err(e) = rtc::set(device, time) {
+        return rtc_error("set", e);
+    }
+    info!("RTC set to {}", time.to_rfc3339());
+    match rtc::read(device) {
+        Ok(time) => (
+            StatusCode::OK,
+            Json(rtc_report(time, Utc::now(), clock::synchronized())),
+        )
+            .into_response(),
+        Err(e) => rtc_error("read", e),
+    }
+}
+
+/// Build the RTC report
+///
+/// A positive drift means the RTC is ahead of the system clock. The RTC
+/// has a resolution of one second. `synchronized` tells whether the system
+/// clock itself can be trusted.
+fn rtc_report(rtc: DateTime<Utc>, system: DateTime<Utc>, synchronized: Option<bool>) -> Value {
+    let drift = (rtc - system).num_milliseconds() as f64 / 1000.0;
+    json!({
+        "rtc": rtc.to_rfc3339(),
+        "system": system.to_rfc3339(),
+        "drift_s": drift,
+        "system_synchronized": synchronized,
+    })
+}
+
+fn bad_time(error: String) -> Response {
+    ApiError::new(ErrorCode::InvalidTime, format!("Invalid time: {}", error)).into_response()
+}
+
+fn rtc_error(operation: &str, error: std::io::Error) -> Response {
+    ApiError::new(
+        ErrorCode::SystemError,
+        format!(
+            "Failed to {} RTC {}: {}",
+            operation, DEFAULT_RTC_DEVICE, error
+        ),
+    )
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rtc_report() {
+        let system = DateTime::parse_from_rfc3339("2025-11-08T12:00:00.250Z")
+            .unwrap()
+            .to_utc();
+        let rtc = DateTime::parse_from_rfc3339("2025-11-08T12:00:03Z")
+            .unwrap()
+            .to_utc();
+        let report = rtc_report(rtc, system, Some(true));
+        assert_eq!(report["rtc"], "2025-11-08T12:00:03+00:00");
+        assert_eq!(report["drift_s"], 2.75);
+        assert_eq!(report["system_synchronized"], true);
+        assert_eq!(rtc_report(system, rtc, None)["drift_s"], -2.75);
+    }
+}
@@ -4,34 +4,198 @@ use axum::Json;
 use axum::extract::State;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::path::Path;
 
-use chrono::TimeZone;
+use chrono::{DateTime, FixedOffset, Local, LocalResult, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use tokio::time::Duration;
+use tracing::{info, warn};
 
+use halpi_common::api::{ApiError, ErrorCode, ScheduleRequest, StandbyRequest};
+use halpi_common::config::LocationConfig;
+
+use super::device_unavailable;
+use crate::daemon::clock::{self, WakeTimeError};
+use crate::daemon::{power, reboot};
 use crate::server::app::AppState;
+use crate::state_machine::DaemonState;
+use crate::sun::SunWake;
 
-/// Request body for standby endpoint
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(untagged)]
-pub enum StandbyRequest {
-    /// Standby with delay in seconds
-    Delay { delay: u32 },
-    /// Standby with specific datetime (ISO 8601 format)
-    Datetime { datetime: String },
-}
+/// How long a cancel request waits for the state machine to act on it
+const CANCEL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Longest time ahead a shutdown can be scheduled, in seconds
+pub const MAX_SCHEDULE_DELAY: u64 = 7 * 24 * 3600;
 
 /// POST /shutdown - Request system shutdown
 pub async fn post_shutdown(State(state): State<AppState>) -> Response {
-    let mut device = state.device.lock().await;
+    match state.device.with(|device| device.request_shutdown()).await {
+        Ok(Ok(())) => (StatusCode::NO_CONTENT, ()).into_response(),
+        Ok(Err(e)) => ApiError::new(
+            ErrorCode::DeviceError,
+            format!("Failed to request shutdown: {}", e),
+        )
+        .into_response(),
+        Err(e) => device_unavailable(e),
+    }
+}
+
+/// POST /reboot - Request a shutdown after which the controller powers the
+/// system back on
+///
+/// Enables auto-restart before requesting the shutdown. The controller
+/// reads the setting when the power is cut, so it has to stay enabled
+/// until then; if it was off, the reboot marker has the daemon turn it off
+/// again after the restart.
+pub async fn post_reboot(State(state): State<AppState>) -> Response {
+    let auto_restart = match state.device.with(|device| device.get_auto_restart()).await {
+        Ok(Ok(enabled)) => enabled,
+        Ok(Err(e)) => {
+            return ApiError::new(
+                ErrorCode::DeviceError,
+                format!("Failed to request reboot: {}", e),
+            )
+            .into_response();
+        }
+        Err(e) => return device_unavailable(e),
+    };
+    if !auto_restart
+        && let Some(marker) = &state.reboot_marker
+        && let Err(e) = reboot::mark_auto_restart(marker)
+    {
+        return ApiError::new(
+            ErrorCode::SystemError,
+            format!("Failed to write {}: {}", marker.display(), e),
+        )
+        .into_response();
+    }
+
+    let result = state
+        .device
+        .with(|device| {
+            device.set_auto_restart(true)?;
+            device.request_shutdown()
+        })
+        .await;
+    match result {
+        Ok(Ok(())) => {
+            info!("Reboot requested: shutdown with auto-restart");
+            (StatusCode::NO_CONTENT, ()).into_response()
+        }
+        Ok(Err(e)) => ApiError::new(
+            ErrorCode::DeviceError,
+            format!("Failed to request reboot: {}", e),
+        )
+        .into_response(),
+        Err(e) => device_unavailable(e),
+    }
+}
 
-    match device.request_shutdown() {
-        Ok(()) => (StatusCode::NO_CONTENT, ()).into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": format!("Failed to request shutdown: {}", e)})),
+/// POST /shutdown/cancel - Cancel a pending blackout shutdown
+///
+/// Only possible until the state machine carries out the blackout action,
+/// while the pre-shutdown hook, grace period or abort window still run.
+pub async fn post_shutdown_cancel(State(state): State<AppState>) -> Response {
+    let mut updates = state.status.subscribe();
+    if state.status.get().state != DaemonState::Shutdown {
+        return ApiError::new(ErrorCode::Conflict, "No shutdown is pending").into_response();
+    }
+
+    state.status.request_cancel();
+    let done = tokio::time::timeout(
+        CANCEL_TIMEOUT,
+        updates.wait_for(|status| status.state != DaemonState::Shutdown),
+    )
+    .await;
+    match done {
+        Ok(Ok(status)) if status.state == DaemonState::Ok => {
+            (StatusCode::NO_CONTENT, ()).into_response()
+        }
+        Ok(Ok(_)) => ApiError::new(
+            ErrorCode::Conflict,
+            "The blackout action has already been carried out",
         )
-            .into_response(),
+        .into_response(),
+        _ => {
+            // Do not let the request cancel a later shutdown
+            state.status.take_cancel();
+            ApiError::new(
+                ErrorCode::StateMachineUnavailable,
+                "State machine did not respond",
+            )
+            .into_response()
+        }
+    }
+}
+
+/// GET /shutdown/schedule - Get the scheduled shutdown
+pub async fn get_shutdown_schedule(State(state): State<AppState>) -> Response {
+    (
+        StatusCode::OK,
+        Json(schedule_report(state.status.scheduled_shutdown())),
+    )
+        .into_response()
+}
+
+/// POST /shutdown/schedule - Schedule a shutdown
+///
+/// Replaces any earlier schedule. When the time comes, the state machine
+/// shuts down through the usual grace period, which can still be cancelled.
+pub async fn post_shutdown_schedule(
+    State(state): State<AppState>,
+    Json(payload): Json<ScheduleRequest>,
+) -> Response {
+    let now = Utc::now();
+    let at = match payload {
+        ScheduleRequest::Delay { delay } => i64::try_from(delay)
+            .ok()
+            .and_then(chrono::Duration::try_seconds)
+            .and_then(|delay| now.checked_add_signed(delay))
+            .ok_or_else(|| format!("delay {} is out of range", delay)),
+        ScheduleRequest::At { at } => parse_schedule_time(&at, now),
+    };
+    let at = match at {
+        Ok(at) => at,
+        Err(e) => {
+            return ApiError::new(ErrorCode::InvalidTime, e).into_response();
+        }
+    };
+
+    let delay = (at - now).num_seconds();
+    if delay < 1 || delay as u64 > MAX_SCHEDULE_DELAY {
+        return ApiError::new(
+            ErrorCode::InvalidTime,
+            format!(
+                "Shutdown must be scheduled 1-{} seconds ahead",
+                MAX_SCHEDULE_DELAY
+            ),
+        )
+        .into_response();
+    }
+
+    info!("Shutdown scheduled at {}", at.to_rfc3339());
+    state.status.set_scheduled_shutdown(Some(at));
+    (StatusCode::OK, Json(schedule_report(Some(at)))).into_response()
+}
+
+/// DELETE /shutdown/schedule - Clear the scheduled shutdown
+pub async fn delete_shutdown_schedule(State(state): State<AppState>) -> Response {
+    if state.status.scheduled_shutdown().is_some() {
+        info!("Scheduled shutdown cleared");
+    }
+    state.status.set_scheduled_shutdown(None);
+    (StatusCode::NO_CONTENT, ()).into_response()
+}
+
+/// JSON description of a shutdown schedule
+pub fn schedule_report(at: Option<DateTime<Utc>>) -> serde_json::Value {
+    match at {
+        Some(at) => json!({
+            "scheduled": true,
+            "at": at.to_rfc3339(),
+            "remaining_s": (at - Utc::now()).num_seconds().max(0),
+        }),
+        None => json!({"scheduled": false, "at": null, "remaining_s": null}),
     }
 }
 
@@ -40,7 +204,6 @@ pub async fn post_standby(
     State(state): State<AppState>,
     Json(payload): Json<StandbyRequest>,
 ) -> Response {
-    use std::process::Command;
     use std::time::{SystemTime, UNIX_EPOCH};
 
     // Calculate wakeup time based on request type
@@ -50,116 +213,389 @@ pub async fn post_standby(
             let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
                 Ok(duration) => duration.as_secs(),
                 Err(e) => {
-                    return (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        Json(json!({"error": format!("System time is before Unix epoch: {}", e)})),
+                    return ApiError::new(
+                        ErrorCode::SystemError,
+                        format!("System time is before Unix epoch: {}", e),
                     )
-                        .into_response();
+                    .into_response();
                 }
             };
-            now + delay as u64
+            match now.checked_add(delay) {
+                Some(timestamp) => timestamp,
+                None => {
+                    return ApiError::new(
+                        ErrorCode::InvalidTime,
+                        format!("Delay {} is out of range", delay),
+                    )
+                    .into_response();
+                }
+            }
         }
         StandbyRequest::Datetime { datetime } => {
             // Parse ISO 8601 datetime string
             // For simplicity, we'll use chrono for parsing
-            match parse_datetime(&datetime) {
+            let timestamp = match parse_datetime(&datetime) {
                 Ok(timestamp) => timestamp,
                 Err(e) => {
-                    return (
-                        StatusCode::BAD_REQUEST,
-                        Json(json!({"error": format!("Invalid datetime format: {}", e)})),
+                    return ApiError::new(
+                        ErrorCode::InvalidTime,
+                        format!("Invalid datetime format: {}", e),
                     )
-                        .into_response();
+                    .into_response();
                 }
+            };
+            let wake_at = DateTime::from_timestamp(timestamp as i64, 0).unwrap_or_default();
+            match check_wake_time(wake_at) {
+                Ok(timestamp) => timestamp,
+                Err(e) => return e.into_response(),
             }
         }
-    };
-
-    // Set RTC alarm using rtcwake
-    let rtcwake_result = Command::new("rtcwake")
-        .arg("-m")
-        .arg("no") // Don't suspend, just set alarm
-        .arg("-t")
-        .arg(wakeup_timestamp.to_string())
-        .output();
-
-    match rtcwake_result {
-        Ok(output) => {
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(json!({"error": format!("rtcwake failed: {}", stderr)})),
+        StandbyRequest::WakeAt { wake_at } => {
+            let wake = match SunWake::parse(&wake_at) {
+                Ok(wake) => wake,
+                Err(e) => {
+                    return ApiError::new(ErrorCode::InvalidTime, e).into_response();
+                }
+            };
+            let Some(location) = state.config.read().await.location else {
+                return ApiError::new(
+                    ErrorCode::Conflict,
+                    "No location configured for sunrise and sunset times",
+                )
+                .into_response();
+            };
+            let Some(wake_at) = wake.next_after(&location, Utc::now()) else {
+                return ApiError::new(
+                    ErrorCode::InvalidRequest,
+                    format!("No {} at the configured location in the next days", wake_at),
                 )
-                    .into_response();
-            }
-        }
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": format!("Failed to execute rtcwake: {}", e)})),
-            )
                 .into_response();
+            };
+            info!(
+                "Standby until {} at {}",
+                wake_at.to_rfc3339(),
+                location_name(&location)
+            );
+            match check_wake_time(wake_at) {
+                Ok(timestamp) => timestamp,
+                Err(e) => return e.into_response(),
+            }
         }
+    };
+
+    // Set RTC alarm using rtcwake
+    if state.offline {
+        warn!(
+            "Dry-run mode: not setting the wake alarm to {}",
+            wakeup_timestamp
+        );
+    } else if let Err(e) = power::set_wake_alarm(wakeup_timestamp).await {
+        return ApiError::new(ErrorCode::SystemError, e.to_string()).into_response();
     }
 
     // Now request standby via I2C
-    let mut device = state.device.lock().await;
-    match device.request_standby() {
-        Ok(()) => (StatusCode::NO_CONTENT, ()).into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": format!("Failed to request standby: {}", e)})),
+    match state.device.with(|device| device.request_standby()).await {
+        Ok(Ok(())) => (StatusCode::NO_CONTENT, ()).into_response(),
+        Ok(Err(e)) => ApiError::new(
+            ErrorCode::DeviceError,
+            format!("Failed to request standby: {}", e),
         )
-            .into_response(),
-    }
-}
-
-/// Parse ISO 8601 datetime string to Unix timestamp
-fn parse_datetime(datetime: &str) -> Result<u64, String> {
-    // Try parsing with different formats
-    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(datetime) {
-        Ok(dt.timestamp() as u64)
-    } else if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(datetime, "%Y-%m-%d %H:%M:%S") {
-        // Interpret as local timezone
-        match chrono::Local.from_local_datetime(&dt) {
-            chrono::LocalResult::Single(dt_with_tz) => Ok(dt_with_tz.timestamp() as u64),
-            _ => Err(format!(
-                "Could not interpret datetime '{}' as local time",
-                datetime
-            )),
+        .into_response(),
+        Err(e) => device_unavailable(e),
+    }
+}
+
+/// GET /standby - Get the RTC wake alarm
+pub async fn get_standby() -> Response {
+    match power::wake_alarm(Path::new(power::WAKE_ALARM_PATH)) {
+        Ok(alarm) => (StatusCode::OK, Json(wake_alarm_report(alarm))).into_response(),
+        Err(e) => ApiError::new(
+            ErrorCode::SystemError,
+            format!("Failed to read wake alarm: {}", e),
+        )
+        .into_response(),
+    }
+}
+
+/// DELETE /standby - Clear the RTC wake alarm
+///
+/// The controller keeps a standby it has already started; without the
+/// alarm, the system then stays off until power is cycled.
+pub async fn delete_standby() -> Response {
+    match power::clear_wake_alarm(Path::new(power::WAKE_ALARM_PATH)) {
+        Ok(()) => {
+            info!("Wake alarm cleared");
+            (StatusCode::NO_CONTENT, ()).into_response()
         }
-    } else {
-        Err(format!(
-            "Could not parse datetime: {}. Expected ISO 8601 format (e.g., '2025-11-08T12:00:00Z' or '2025-11-08 12:00:00')",
+        Err(e) => ApiError::new(
+            ErrorCode::SystemError,
+            format!("Failed to clear wake alarm: {}", e),
+        )
+        .into_response(),
+    }
+}
+
+/// JSON description of the RTC wake alarm
+fn wake_alarm_report(alarm: Option<u64>) -> serde_json::Value {
+    match alarm.and_then(|timestamp| DateTime::from_timestamp(timestamp as i64, 0)) {
+        Some(at) => json!({
+            "wake_alarm": true,
+            "at": at.to_rfc3339(),
+            "remaining_s": (at - Utc::now()).num_seconds().max(0),
+        }),
+        None => json!({"wake_alarm": false, "at": null, "remaining_s": null}),
+    }
+}
+
+/// Check a wakeup at a datetime, returning its Unix timestamp
+///
+/// Unlike a delay, a datetime is only as good as the system clock.
+fn check_wake_time(wake_at: DateTime<Utc>) -> Result<u64, ApiError> {
+    clock::check_wake_time(wake_at)
+        .map(|()| wake_at.timestamp() as u64)
+        .map_err(|e| {
+            let code = match e {
+                WakeTimeError::ClockImplausible(_) => ErrorCode::Conflict,
+                WakeTimeError::InPast(_) => ErrorCode::InvalidTime,
+            };
+            ApiError::new(code, format!("Cannot schedule wakeup: {}", e))
+        })
+}
+
+/// Coordinates of `location` for logging
+fn location_name(location: &LocationConfig) -> String {
+    format!("{:.3}, {:.3}", location.latitude, location.longitude)
+}
+
+/// Parse an ISO 8601 datetime string to a Unix timestamp
+///
+/// Accepts RFC 3339 datetimes with an offset (`2025-12-31T23:59:59+02:00`),
+/// and datetimes without one (`2025-12-31 23:59:59` or
+/// `2025-12-31T23:59:59`) followed by a time zone: an IANA name
+/// (`Europe/Helsinki`), `UTC` or an offset (`+02:00`). Without a time zone,
+/// the datetime is in the system's local time zone, which may not be where
+/// the boat is.
+pub(super) fn parse_datetime(datetime: &str) -> Result<u64, String> {
+    let datetime = datetime.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(datetime) {
+        return Ok(dt.timestamp() as u64);
+    }
+
+    let (naive, zone) = match datetime.rsplit_once(' ') {
+        Some((naive, zone)) if parse_naive_datetime(naive).is_some() => (naive, Some(zone)),
+        _ => (datetime, None),
+    };
+    let Some(naive) = parse_naive_datetime(naive) else {
+        return Err(format!(
+            "Could not parse datetime: {}. Expected ISO 8601 format (e.g., '2025-11-08T12:00:00Z', '2025-11-08 12:00:00' or '2025-11-08 12:00:00 Europe/Helsinki')",
             datetime
-        ))
+        ));
+    };
+
+    let timestamp = match zone {
+        None => resolve_local(&Local, &naive, "local time"),
+        Some("UTC" | "Z") => resolve_local(&Utc, &naive, "UTC"),
+        Some(zone) if zone.starts_with(['+', '-']) => {
+            let offset = zone
+                .parse::<FixedOffset>()
+                .map_err(|_| format!("Invalid UTC offset '{}' (e.g., '+02:00')", zone))?;
+            resolve_local(&offset, &naive, zone)
+        }
+        Some(zone) => {
+            let tz = zone
+                .parse::<chrono_tz::Tz>()
+                .map_err(|_| format!("Unknown time zone '{}' (e.g., 'Europe/Helsinki')", zone))?;
+            resolve_local(&tz, &naive, zone)
+        }
+    }?;
+    Ok(timestamp as u64)
+}
+
+/// Parse a datetime without time zone, with a space or `T` separator
+fn parse_naive_datetime(datetime: &str) -> Option<NaiveDateTime> {
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(datetime, format).ok())
+}
+
+/// Unix timestamp of the local datetime `naive` in time zone `tz`
+///
+/// Fails for datetimes skipped or repeated by a daylight saving time change.
+fn resolve_local<Tz: TimeZone>(tz: &Tz, naive: &NaiveDateTime, zone: &str) -> Result<i64, String> {
+    match tz.from_local_datetime(naive) {
+        LocalResult::Single(dt) => Ok(dt.timestamp()),
+        LocalResult::Ambiguous(..) => Err(format!(
+            "Datetime '{}' is ambiguous in {} (daylight saving time change)",
+            naive, zone
+        )),
+        LocalResult::None => Err(format!(
+            "Datetime '{}' does not exist in {} (daylight saving time change)",
+            naive, zone
+        )),
+    }
+}
+
+/// Parse a shutdown time
+///
+/// A time of day ("22:30" or "22:30:15") means its next occurrence in local
+/// time. Anything else is parsed as a datetime like for standby.
+fn parse_schedule_time(at: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let time = NaiveTime::parse_from_str(at, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(at, "%H:%M:%S"));
+    let Ok(time) = time else {
+        let timestamp = parse_datetime(at)?;
+        return DateTime::from_timestamp(timestamp as i64, 0)
+            .ok_or_else(|| format!("Datetime out of range: {}", at));
+    };
+
+    let today = now.with_timezone(&Local).date_naive();
+    for date in today.iter_days().take(2) {
+        if let Some(local) = Local.from_local_datetime(&date.and_time(time)).earliest()
+            && local > now
+        {
+            return Ok(local.with_timezone(&Utc));
+        }
     }
+    Err(format!("Could not interpret time '{}' as local time", at))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::i2c::device::HalpiDevice;
+    use crate::i2c::DeviceHandle;
+    use crate::i2c::{HalpiDevice, Simulator};
     use halpi_common::config::Config;
     use std::sync::Arc;
-    use tokio::sync::{Mutex, RwLock};
+    use tokio::sync::RwLock;
 
     #[tokio::test]
     async fn test_post_shutdown() {
-        let device = match HalpiDevice::new(1, 0x6D) {
-            Ok(d) => Arc::new(Mutex::new(d)),
-            Err(_) => return,
-        };
+        let device = DeviceHandle::new(HalpiDevice::simulated(1, 0x6D, Simulator::new()));
         let config = Arc::new(RwLock::new(Config::default()));
         let state = AppState::new(device, config);
 
         let response = post_shutdown(State(state)).await;
-        // Will be 204 or 500 depending on I2C availability
-        assert!(
-            response.status() == StatusCode::NO_CONTENT
-                || response.status() == StatusCode::INTERNAL_SERVER_ERROR
-        );
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_post_reboot_device_missing() {
+        let device = DeviceHandle::missing(1, 0x6D);
+        let config = Arc::new(RwLock::new(Config::default()));
+        let state = AppState::new(device, config);
+
+        let response = post_reboot(State(state)).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_post_shutdown_cancel() {
+        let device = DeviceHandle::missing(1, 0x6D);
+        let config = Arc::new(RwLock::new(Config::default()));
+        let state = AppState::new(device, config);
+
+        let response = post_shutdown_cancel(State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        // Stand in for the state machine acting on the request
+        state.status.set(DaemonState::Shutdown);
+        let status = state.status.clone();
+        tokio::spawn(async move {
+            while !status.take_cancel() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            status.set(DaemonState::Ok);
+        });
+        let response = post_shutdown_cancel(State(state)).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_schedule() {
+        let device = DeviceHandle::missing(1, 0x6D);
+        let config = Arc::new(RwLock::new(Config::default()));
+        let state = AppState::new(device, config);
+
+        let request = Json(ScheduleRequest::Delay { delay: 2700 });
+        let response = post_shutdown_schedule(State(state.clone()), request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let at = state.status.scheduled_shutdown().unwrap();
+        assert!(at > Utc::now() + chrono::Duration::seconds(2690));
+
+        let request = Json(ScheduleRequest::At {
+            at: "2020-01-01T00:00:00Z".to_string(),
+        });
+        let response = post_shutdown_schedule(State(state.clone()), request).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(state.status.scheduled_shutdown(), Some(at));
+
+        let response = delete_shutdown_schedule(State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(state.status.scheduled_shutdown(), None);
+    }
+
+    #[test]
+    fn test_parse_schedule_time() {
+        let now = Utc::now();
+        let at = parse_schedule_time("22:30", now).unwrap();
+        assert!(at > now && at <= now + chrono::Duration::hours(25));
+        let local = at.with_timezone(&Local);
+        assert_eq!(local.format("%H:%M:%S").to_string(), "22:30:00");
+
+        assert!(parse_schedule_time("22:30:15", now).is_ok());
+        assert!(parse_schedule_time("2025-11-08T12:00:00Z", now).is_ok());
+        assert!(parse_schedule_time("25:00", now).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_post_standby_wake_at() {
+        let device = DeviceHandle::missing(1, 0x6D);
+        let config = Arc::new(RwLock::new(Config::default()));
+        let state = AppState::new(device, config);
+
+        let request = |wake_at: &str| {
+            Json(StandbyRequest::WakeAt {
+                wake_at: wake_at.to_string(),
+            })
+        };
+        let response = post_standby(State(state.clone()), request("dawn")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = post_standby(State(state), request("sunrise+30m")).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[test]
+    fn test_wake_alarm_report() {
+        let report = wake_alarm_report(Some(1767225599));
+        assert_eq!(report["wake_alarm"], true);
+        assert_eq!(report["at"], "2025-12-31T23:59:59+00:00");
+        assert_eq!(report["remaining_s"], 0);
+
+        let report = wake_alarm_report(None);
+        assert_eq!(report["wake_alarm"], false);
+        assert!(report["at"].is_null());
+    }
+
+    #[test]
+    fn test_standby_request_durations() {
+        let parse = |json: &str| serde_json::from_str::<StandbyRequest>(json).unwrap();
+        assert!(matches!(
+            parse(r#"{"delay": 300}"#),
+            StandbyRequest::Delay { delay: 300 }
+        ));
+        assert!(matches!(
+            parse(r#"{"delay": "2h30m"}"#),
+            StandbyRequest::Delay { delay: 9000 }
+        ));
+        assert!(matches!(
+            parse(r#"{"datetime": "2025-11-08 12:00:00"}"#),
+            StandbyRequest::Datetime { .. }
+        ));
+        assert!(matches!(
+            parse(r#"{"wake_at": "sunrise+30m"}"#),
+            StandbyRequest::WakeAt { .. }
+        ));
+        assert!(serde_json::from_str::<StandbyRequest>(r#"{"delay": "soon"}"#).is_err());
     }
 
     #[test]
@@ -174,6 +610,31 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_parse_datetime_time_zones() {
+        let utc = parse_datetime("2025-12-31T21:59:59Z").unwrap();
+        assert_eq!(
+            parse_datetime("2025-12-31T23:59:59 Europe/Helsinki"),
+            Ok(utc)
+        );
+        assert_eq!(
+            parse_datetime("2025-12-31 23:59:59 Europe/Helsinki"),
+            Ok(utc)
+        );
+        assert_eq!(parse_datetime("2025-12-31 23:59:59 +02:00"), Ok(utc));
+        assert_eq!(parse_datetime("2025-12-31 21:59:59 UTC"), Ok(utc));
+        // Summer time in Helsinki
+        assert_eq!(
+            parse_datetime("2025-07-01 12:00:00 Europe/Helsinki"),
+            parse_datetime("2025-07-01T09:00:00Z")
+        );
+
+        assert!(parse_datetime("2025-12-31 23:59:59 Mars/Olympus").is_err());
+        assert!(parse_datetime("2025-12-31 23:59:59 +25:00").is_err());
+        // Skipped by the change to summer time
+        assert!(parse_datetime("2025-03-30 03:30:00 Europe/Helsinki").is_err());
+    }
+
     #[test]
     fn test_parse_datetime_invalid() {
         let result = parse_datetime("not a date");
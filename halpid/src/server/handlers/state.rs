@@ -0,0 +1,90 @@
+//! Power management state endpoint handler
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use serde_json::{Value, json};
+
+use halpi_common::config::{BlackoutAction, Config};
+
+use crate::server::app::AppState;
+use crate::state_machine::MachineStatus;
+
+/// GET /state - State machine state, maintenance mode, the scheduled
+/// shutdown, the estimated supercap runtime and the configured blackout
+/// action
+pub async fn get_state(State(state): State<AppState>) -> Response {
+    let status = state.status.get();
+    let maintenance = state.status.maintenance_until();
+    let scheduled = state.status.scheduled_shutdown();
+    let runtime = state.status.runtime();
+    let config = state.config.read().await;
+
+    (
+        StatusCode::OK,
+        Json(state_report(
+            &status,
+            maintenance,
+            scheduled,
+            runtime,
+            &config,
+        )),
+    )
+        .into_response()
+}
+
+/// Build the state report
+fn state_report(
+    status: &MachineStatus,
+    maintenance: Option<DateTime<Utc>>,
+    scheduled: Option<DateTime<Utc>>,
+    runtime: Option<f64>,
+    config: &Config,
+) -> Value {
+    let mut report = json!({
+        "state": status.state,
+        "since": status.since.to_rfc3339(),
+        "blackout_action": config.blackout_action.name(),
+        "maintenance_until": maintenance.map(|until| until.to_rfc3339()),
+        "shutdown_scheduled_at": scheduled.map(|at| at.to_rfc3339()),
+        "estimated_runtime_s": runtime,
+    });
+    if config.blackout_action == BlackoutAction::Standby {
+        report["blackout_wake_after"] = json!(config.blackout_wake_after);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_machine::{DaemonState, StatusHandle};
+
+    #[test]
+    fn test_state_report() {
+        let status = StatusHandle::new();
+        status.set(DaemonState::Ok);
+
+        let report = state_report(&status.get(), None, None, None, &Config::default());
+        assert_eq!(report["state"], "ok");
+        assert!(report["maintenance_until"].is_null());
+        assert!(report["shutdown_scheduled_at"].is_null());
+        assert!(report["estimated_runtime_s"].is_null());
+        assert_eq!(report["blackout_action"], "poweroff");
+        assert!(report.get("blackout_wake_after").is_none());
+
+        let config = Config {
+            blackout_action: BlackoutAction::Standby,
+            ..Default::default()
+        };
+        let until = Utc::now();
+        let report = state_report(&status.get(), Some(until), Some(until), Some(42.0), &config);
+        assert_eq!(report["maintenance_until"], until.to_rfc3339());
+        assert_eq!(report["shutdown_scheduled_at"], until.to_rfc3339());
+        assert_eq!(report["estimated_runtime_s"], 42.0);
+        assert_eq!(report["blackout_action"], "standby");
+        assert_eq!(report["blackout_wake_after"], 3600.0);
+    }
+}
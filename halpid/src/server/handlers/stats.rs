@@ -0,0 +1,156 @@
+//! Measurement statistics and history endpoint handlers
+
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use halpi_common::api::{ApiError, ErrorCode};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::server::app::AppState;
+use crate::stats::{HISTORY_RETENTION_S, RETENTION_S};
+
+/// Window used when none is given, in seconds
+const DEFAULT_WINDOW_S: u64 = 15 * 60;
+
+/// Query of the stats endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct StatsQuery {
+    /// Window to summarize, e.g. "15m" (default 15 minutes, at most an hour)
+    pub window: Option<String>,
+}
+
+/// Query of the history endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct HistoryQuery {
+    /// How far back to go, e.g. "24h" (the default, and the most kept)
+    pub since: Option<String>,
+}
+
+/// GET /stats - Minimum, maximum and average of the measurements over a
+/// recent window
+pub async fn get_stats(State(state): State<AppState>, Query(query): Query<StatsQuery>) -> Response {
+    let window_s = match query.window.as_deref().map(parse_window) {
+        None => DEFAULT_WINDOW_S,
+        Some(Ok(window_s)) => window_s,
+        Some(Err(e)) => {
+            return ApiError::new(ErrorCode::InvalidRequest, e).into_response();
+        }
+    };
+    match state.events.stats(window_s) {
+        Some(report) => (StatusCode::OK, Json(report)).into_response(),
+        None => ApiError::new(ErrorCode::NoData, "No measurements in the window").into_response(),
+    }
+}
+
+/// GET /stats/history - Measurement summaries per second for up to an
+/// hour back, per minute beyond that
+pub async fn get_history(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> Response {
+    let since_s = match query.since.as_deref().map(parse_since) {
+        None => HISTORY_RETENTION_S,
+        Some(Ok(since_s)) => since_s,
+        Some(Err(e)) => {
+            return ApiError::new(ErrorCode::InvalidRequest, e).into_response();
+        }
+    };
+    let (resolution_s, rows) = state.events.measurement_history(since_s);
+    (
+        StatusCode::OK,
+        Json(json!({"resolution_s": resolution_s, "rows": rows})),
+    )
+        .into_response()
+}
+
+/// Window in seconds, between one second and [`RETENTION_S`]
+fn parse_window(window: &str) -> Result<u64, String> {
+    parse_bounded("window", window, RETENTION_S)
+}
+
+/// History start in seconds ago, between one second and
+/// [`HISTORY_RETENTION_S`]
+fn parse_since(since: &str) -> Result<u64, String> {
+    parse_bounded("since", since, HISTORY_RETENTION_S)
+}
+
+fn parse_bounded(name: &str, duration: &str, max_s: u64) -> Result<u64, String> {
+    let seconds = halpi_common::duration::parse_duration(duration)?;
+    if !(1..=max_s).contains(&seconds) {
+        return Err(format!(
+            "{} must be between 1 s and {} s, got {}",
+            name, max_s, duration
+        ));
+    }
+    Ok(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i2c::DeviceHandle;
+    use chrono::Utc;
+    use halpi_common::config::Config;
+    use halpi_common::events::{DaemonEvent, Sample};
+    use halpi_common::types::{Measurements, PowerState};
+    use halpi_common::units::{Amps, Kelvin, Volts};
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[test]
+    fn test_parse_window() {
+        assert_eq!(parse_window("15m"), Ok(900));
+        assert_eq!(parse_window("1h"), Ok(3600));
+        assert!(parse_window("2h").is_err());
+        assert!(parse_window("0").is_err());
+        assert!(parse_window("soon").is_err());
+
+        assert_eq!(parse_since("24h"), Ok(86400));
+        assert!(parse_since("2d").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_stats() {
+        let config = Arc::new(RwLock::new(Config::default()));
+        let state = AppState::new(DeviceHandle::missing(1, 0x6D), config);
+
+        let response = get_stats(State(state.clone()), Query(StatsQuery::default())).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        state
+            .events
+            .publish(DaemonEvent::Measurement(Sample::now(Measurements {
+                dcin_voltage: Volts(12.0),
+                supercap_voltage: Volts(9.5),
+                input_current: Amps(0.5),
+                mcu_temperature: Kelvin(300.0),
+                pcb_temperature: Kelvin(300.0),
+                power_state: PowerState::OperationalCoOp,
+                watchdog_elapsed: 0.0,
+                measured_at: Utc::now(),
+            })));
+        let query = StatsQuery {
+            window: Some("5m".to_string()),
+        };
+        let response = get_stats(State(state.clone()), Query(query)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(report["window_s"], 300);
+        assert_eq!(report["samples"], 1);
+        assert_eq!(report["values"]["V_in"]["max"], 12.0);
+
+        let response = get_history(State(state), Query(HistoryQuery::default())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let history: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(history["resolution_s"], 60);
+        assert_eq!(history["rows"][0]["V_in"]["avg"], 12.0);
+    }
+}
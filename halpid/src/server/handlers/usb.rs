@@ -4,55 +4,61 @@ use axum::Json;
 use axum::extract::{Path, State};
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
+use halpi_common::api::{ApiError, ErrorCode, UsbState};
 use serde_json::json;
 
+use super::{device_error_code, device_unavailable};
 use crate::server::app::AppState;
 
 /// GET /usb - Get all USB port states
+///
+/// `defaults` lists the ports with a configured state at startup.
 pub async fn get_all_usb(State(state): State<AppState>) -> Response {
-    let mut device = state.device.lock().await;
-
-    match device.get_usb_port_state() {
-        Ok(port_bits) => {
-            let usb_json = json!({
-                "usb0": (port_bits & 0x01) != 0,
-                "usb1": (port_bits & 0x02) != 0,
-                "usb2": (port_bits & 0x04) != 0,
-                "usb3": (port_bits & 0x08) != 0,
-            });
-            (StatusCode::OK, Json(usb_json)).into_response()
-        }
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": format!("Failed to get USB port states: {}", e)})),
-        )
+    let defaults = state
+        .config
+        .read()
+        .await
+        .usb_defaults_for(&state.device_id)
+        .copied()
+        .unwrap_or_default();
+    state
+        .device
+        .with(move |device| match device.get_usb_port_state() {
+            Ok(port_bits) => {
+                (StatusCode::OK, Json(UsbState::new(port_bits, defaults))).into_response()
+            }
+            Err(e) => ApiError::new(
+                device_error_code(&e),
+                format!("Failed to get USB port states: {}", e),
+            )
             .into_response(),
-    }
+        })
+        .await
+        .unwrap_or_else(device_unavailable)
 }
 
 /// GET /usb/:port - Get specific USB port state
 pub async fn get_usb(State(state): State<AppState>, Path(port): Path<u8>) -> Response {
     if port > 3 {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(json!({"error": "Invalid port number, must be 0-3"})),
-        )
+        return ApiError::new(ErrorCode::InvalidPort, "Invalid port number, must be 0-3")
             .into_response();
     }
 
-    let mut device = state.device.lock().await;
-
-    match device.get_usb_port_state() {
-        Ok(port_bits) => {
-            let enabled = (port_bits & (1 << port)) != 0;
-            (StatusCode::OK, Json(json!(enabled))).into_response()
-        }
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": format!("Failed to get USB port state: {}", e)})),
-        )
+    state
+        .device
+        .with(move |device| match device.get_usb_port_state() {
+            Ok(port_bits) => {
+                let enabled = (port_bits & (1 << port)) != 0;
+                (StatusCode::OK, Json(json!(enabled))).into_response()
+            }
+            Err(e) => ApiError::new(
+                device_error_code(&e),
+                format!("Failed to get USB port state: {}", e),
+            )
             .into_response(),
-    }
+        })
+        .await
+        .unwrap_or_else(device_unavailable)
 }
 
 /// PUT /usb - Set all USB port states
@@ -68,59 +74,63 @@ pub async fn put_all_usb(
     let usb2 = payload.get("usb2").and_then(|v| v.as_bool());
     let usb3 = payload.get("usb3").and_then(|v| v.as_bool());
 
-    let mut device = state.device.lock().await;
-
-    // Read current port state
-    let current_bits = match device.get_usb_port_state() {
-        Ok(bits) => bits,
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": format!("Failed to get current USB port states: {}", e)})),
-            )
-                .into_response();
-        }
-    };
-
-    // Update only specified fields
-    let mut port_bits = current_bits;
-    if let Some(val) = usb0 {
-        if val {
-            port_bits |= 0x01;
-        } else {
-            port_bits &= !0x01;
-        }
-    }
-    if let Some(val) = usb1 {
-        if val {
-            port_bits |= 0x02;
-        } else {
-            port_bits &= !0x02;
-        }
-    }
-    if let Some(val) = usb2 {
-        if val {
-            port_bits |= 0x04;
-        } else {
-            port_bits &= !0x04;
-        }
-    }
-    if let Some(val) = usb3 {
-        if val {
-            port_bits |= 0x08;
-        } else {
-            port_bits &= !0x08;
-        }
-    }
-
-    match device.set_usb_port_state(port_bits) {
-        Ok(()) => (StatusCode::NO_CONTENT, ()).into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": format!("Failed to set USB port states: {}", e)})),
-        )
-            .into_response(),
-    }
+    state
+        .device
+        .with(move |device| {
+            // Read current port state
+            let current_bits = match device.get_usb_port_state() {
+                Ok(bits) => bits,
+                Err(e) => {
+                    return ApiError::new(
+                        device_error_code(&e),
+                        format!("Failed to get current USB port states: {}", e),
+                    )
+                    .into_response();
+                }
+            };
+
+            // Update only specified fields
+            let mut port_bits = current_bits;
+            if let Some(val) = usb0 {
+                if val {
+                    port_bits |= 0x01;
+                } else {
+                    port_bits &= !0x01;
+                }
+            }
+            if let Some(val) = usb1 {
+                if val {
+                    port_bits |= 0x02;
+                } else {
+                    port_bits &= !0x02;
+                }
+            }
+            if let Some(val) = usb2 {
+                if val {
+                    port_bits |= 0x04;
+                } else {
+                    port_bits &= !0x04;
+                }
+            }
+            if let Some(val) = usb3 {
+                if val {
+                    port_bits |= 0x08;
+                } else {
+                    port_bits &= !0x08;
+                }
+            }
+
+            match device.set_usb_port_state(port_bits) {
+                Ok(()) => (StatusCode::NO_CONTENT, ()).into_response(),
+                Err(e) => ApiError::new(
+                    device_error_code(&e),
+                    format!("Failed to set USB port states: {}", e),
+                )
+                .into_response(),
+            }
+        })
+        .await
+        .unwrap_or_else(device_unavailable)
 }
 
 /// PUT /usb/:port - Set specific USB port state
@@ -130,91 +140,88 @@ pub async fn put_usb(
     Json(payload): Json<bool>,
 ) -> Response {
     if port > 3 {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(json!({"error": "Invalid port number, must be 0-3"})),
-        )
+        return ApiError::new(ErrorCode::InvalidPort, "Invalid port number, must be 0-3")
             .into_response();
     }
 
-    let mut device = state.device.lock().await;
-
-    // Read current state
-    let current_bits = match device.get_usb_port_state() {
-        Ok(bits) => bits,
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": format!("Failed to get current USB port state: {}", e)})),
-            )
-                .into_response();
-        }
-    };
-
-    // Update specific bit
-    let new_bits = if payload {
-        current_bits | (1 << port)
-    } else {
-        current_bits & !(1 << port)
-    };
-
-    // Write back
-    match device.set_usb_port_state(new_bits) {
-        Ok(()) => (StatusCode::NO_CONTENT, ()).into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": format!("Failed to set USB port state: {}", e)})),
-        )
-            .into_response(),
-    }
+    state
+        .device
+        .with(move |device| {
+            // Read current state
+            let current_bits = match device.get_usb_port_state() {
+                Ok(bits) => bits,
+                Err(e) => {
+                    return ApiError::new(
+                        device_error_code(&e),
+                        format!("Failed to get current USB port state: {}", e),
+                    )
+                    .into_response();
+                }
+            };
+
+            // Update specific bit
+            let new_bits = if payload {
+                current_bits | (1 << port)
+            } else {
+                current_bits & !(1 << port)
+            };
+
+            // Write back
+            match device.set_usb_port_state(new_bits) {
+                Ok(()) => (StatusCode::NO_CONTENT, ()).into_response(),
+                Err(e) => ApiError::new(
+                    device_error_code(&e),
+                    format!("Failed to set USB port state: {}", e),
+                )
+                .into_response(),
+            }
+        })
+        .await
+        .unwrap_or_else(device_unavailable)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::i2c::device::HalpiDevice;
+    use crate::i2c::DeviceHandle;
+    use crate::i2c::{HalpiDevice, Simulator};
     use halpi_common::config::Config;
     use std::sync::Arc;
-    use tokio::sync::{Mutex, RwLock};
+    use tokio::sync::RwLock;
 
     #[tokio::test]
     async fn test_get_all_usb() {
-        let device = match HalpiDevice::new(1, 0x6D) {
-            Ok(d) => Arc::new(Mutex::new(d)),
-            Err(_) => return,
-        };
+        let device = DeviceHandle::new(HalpiDevice::simulated(1, 0x6D, Simulator::new()));
+        let config = Arc::new(RwLock::new(Config::default()));
+        let state = AppState::new(device, config);
+
+        let response = get_all_usb(State(state)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_get_all_usb_device_missing() {
+        let device = DeviceHandle::missing(1, 0x6D);
         let config = Arc::new(RwLock::new(Config::default()));
         let state = AppState::new(device, config);
 
         let response = get_all_usb(State(state)).await;
-        assert!(
-            response.status() == StatusCode::OK
-                || response.status() == StatusCode::INTERNAL_SERVER_ERROR
-        );
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
     }
 
     #[tokio::test]
     async fn test_get_usb_valid_port() {
-        let device = match HalpiDevice::new(1, 0x6D) {
-            Ok(d) => Arc::new(Mutex::new(d)),
-            Err(_) => return,
-        };
+        let device = DeviceHandle::new(HalpiDevice::simulated(1, 0x6D, Simulator::new()));
         let config = Arc::new(RwLock::new(Config::default()));
         let state = AppState::new(device, config);
 
         let response = get_usb(State(state), Path(0)).await;
-        assert!(
-            response.status() == StatusCode::OK
-                || response.status() == StatusCode::INTERNAL_SERVER_ERROR
-        );
+        assert_eq!(response.status(), StatusCode::OK);
     }
 
     #[tokio::test]
     async fn test_get_usb_invalid_port() {
-        let device = match HalpiDevice::new(1, 0x6D) {
-            Ok(d) => Arc::new(Mutex::new(d)),
-            Err(_) => return,
-        };
+        let device = DeviceHandle::new(HalpiDevice::simulated(1, 0x6D, Simulator::new()));
         let config = Arc::new(RwLock::new(Config::default()));
         let state = AppState::new(device, config);
 
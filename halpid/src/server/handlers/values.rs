@@ -4,68 +4,65 @@ use axum::Json;
 use axum::extract::{Path, State};
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
+use halpi_common::api::{ApiError, ErrorCode, ValuesResponse, value_key};
 use serde_json::Value;
 use serde_json::json;
 
+use super::{device_error_code, device_unavailable};
+use crate::i2c::{DeviceIdentity, I2cError};
 use crate::server::app::AppState;
 
 /// GET /values - Get all sensor readings and device information
 pub async fn get_all_values(State(state): State<AppState>) -> Response {
-    // Acquire device lock and read all values at once to minimize lock time
-    let mut device = state.device.lock().await;
-
-    // Read all measurements
-    let measurements = match device.get_measurements() {
-        Ok(m) => m,
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": e.to_string()})),
-            )
-                .into_response();
-        }
-    };
+    let version = state.version;
+    let identity = state
+        .identity
+        .get(&state.device)
+        .await
+        .unwrap_or_else(|_| DeviceIdentity::unknown());
+    let calibration = *state.config.read().await.calibration_for(&state.device_id);
+    // Read all values in one operation to minimize lock time
+    state
+        .device
+        .with(move |device| {
+            // Read all measurements
+            let mut measurements = match device.get_measurements() {
+                Ok(m) => m,
+                Err(e) => {
+                    return ApiError::new(ErrorCode::DeviceError, e.to_string()).into_response();
+                }
+            };
+
+            calibration.apply(&mut measurements);
+
+            // Read additional state values
+            let raspi_power_state = device.get_5v_output_enabled().unwrap_or(false);
+            let watchdog_timeout = device.get_watchdog_timeout().unwrap_or(0);
+            let watchdog_enabled = watchdog_timeout > 0;
+
+            let response = ValuesResponse {
+                daemon_version: version.to_string(),
+                hardware_version: identity.hardware_version.to_string(),
+                firmware_version: identity.firmware_version.to_string(),
+                device_id: identity.device_id,
+                dcin_voltage: measurements.dcin_voltage,
+                supercap_voltage: measurements.supercap_voltage,
+                input_current: measurements.input_current,
+                mcu_temperature: measurements.mcu_temperature,
+                pcb_temperature: measurements.pcb_temperature,
+                state: measurements.power_state,
+                output_5v_enabled: raspi_power_state,
+                watchdog_enabled,
+                watchdog_timeout: watchdog_timeout as f64 / 1000.0, // Convert ms to seconds
+                watchdog_elapsed: measurements.watchdog_elapsed,
+                measured_at: measurements.measured_at,
+                calibrated: calibration.calibrated_keys(),
+            };
 
-    // Read version information
-    let hardware_version = device
-        .get_hardware_version()
-        .unwrap_or_else(|_| halpi_common::types::Version::from_bytes([255, 0, 0, 0]));
-    let firmware_version = device
-        .get_firmware_version()
-        .unwrap_or_else(|_| halpi_common::types::Version::from_bytes([255, 0, 0, 0]));
-
-    // Read device ID
-    let device_id = device
-        .get_device_id()
-        .unwrap_or_else(|_| "0000000000000000".to_string());
-
-    // Read additional state values
-    let raspi_power_state = device.get_5v_output_enabled().unwrap_or(false);
-    let watchdog_timeout = device.get_watchdog_timeout().unwrap_or(0);
-    let watchdog_enabled = watchdog_timeout > 0;
-
-    // Release lock
-    drop(device);
-
-    // Build response JSON
-    let response_json = json!({
-        "daemon_version": state.version,
-        "hardware_version": hardware_version.to_string(),
-        "firmware_version": firmware_version.to_string(),
-        "device_id": device_id,
-        "V_in": measurements.dcin_voltage,
-        "V_cap": measurements.supercap_voltage,
-        "I_in": measurements.input_current,
-        "T_mcu": measurements.mcu_temperature,
-        "T_pcb": measurements.pcb_temperature,
-        "state": measurements.power_state.name(),
-        "5v_output_enabled": raspi_power_state,
-        "watchdog_enabled": watchdog_enabled,
-        "watchdog_timeout": watchdog_timeout as f64 / 1000.0, // Convert ms to seconds
-        "watchdog_elapsed": measurements.watchdog_elapsed,
-    });
-
-    (StatusCode::OK, Json(response_json)).into_response()
+            (StatusCode::OK, Json(response.with_aliases())).into_response()
+        })
+        .await
+        .unwrap_or_else(device_unavailable)
 }
 
 /// Helper function to check if a key requires device access
@@ -89,7 +86,11 @@ fn requires_device_access(key: &str) -> bool {
 }
 
 /// GET /values/:key - Get a specific value by key
+///
+/// Keys of the Python daemon, such as `V_supercap`, are accepted as aliases.
 pub async fn get_value(State(state): State<AppState>, Path(key): Path<String>) -> Response {
+    let key = value_key(&key).to_string();
+
     // Handle daemon_version without device access
     if key == "daemon_version" {
         let value = json!(state.version);
@@ -98,112 +99,221 @@ pub async fn get_value(State(state): State<AppState>, Path(key): Path<String>) -
 
     // Check if key is valid and requires device access
     if !requires_device_access(&key) {
-        return (
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": format!("Unknown key: {}", key)})),
-        )
+        return ApiError::new(ErrorCode::UnknownKey, format!("Unknown key: {}", key))
+            .with_detail(json!({"key": key}))
             .into_response();
     }
 
+    // Identity values are served from the cache
+    if matches!(
+        key.as_str(),
+        "hardware_version" | "firmware_version" | "device_id"
+    ) {
+        let identity = match state.identity.get(&state.device).await {
+            Ok(identity) => identity,
+            Err(e @ I2cError::DeviceMissing { .. }) => return device_unavailable(e),
+            Err(_) => DeviceIdentity::unknown(),
+        };
+        let value = match key.as_str() {
+            "hardware_version" => json!(identity.hardware_version.to_string()),
+            "firmware_version" => json!(identity.firmware_version.to_string()),
+            _ => json!(identity.device_id),
+        };
+        return (StatusCode::OK, Json(value)).into_response();
+    }
+
+    let calibration = *state.config.read().await.calibration_for(&state.device_id);
+
     // Lock device and read the requested value
-    let mut device = state.device.lock().await;
-
-    let value: Result<Value, String> = match key.as_str() {
-        "hardware_version" => device
-            .get_hardware_version()
-            .map(|v| json!(v.to_string()))
-            .or_else(|_| {
-                Ok(json!(
-                    halpi_common::types::Version::from_bytes([255, 0, 0, 0]).to_string()
-                ))
-            }),
-        "firmware_version" => device
-            .get_firmware_version()
-            .map(|v| json!(v.to_string()))
-            .or_else(|_| {
-                Ok(json!(
-                    halpi_common::types::Version::from_bytes([255, 0, 0, 0]).to_string()
-                ))
-            }),
-        "device_id" => device
-            .get_device_id()
-            .map(|id| json!(id))
-            .or_else(|_| Ok(json!("0000000000000000"))),
-        "5v_output_enabled" => device
-            .get_5v_output_enabled()
-            .map(|v| json!(v))
-            .map_err(|e| e.to_string()),
-        "watchdog_timeout" => device
-            .get_watchdog_timeout()
-            .map(|v| json!(v as f64 / 1000.0))
-            .map_err(|e| e.to_string()),
-        "watchdog_enabled" => device
-            .get_watchdog_timeout()
-            .map(|v| json!(v > 0))
-            .map_err(|e| e.to_string()),
-        "V_in" | "V_cap" | "I_in" | "T_mcu" | "T_pcb" | "state" | "watchdog_elapsed" => {
-            match device.get_measurements() {
-                Ok(m) => Ok(match key.as_str() {
-                    "V_in" => json!(m.dcin_voltage),
-                    "V_cap" => json!(m.supercap_voltage),
-                    "I_in" => json!(m.input_current),
-                    "T_mcu" => json!(m.mcu_temperature),
-                    "T_pcb" => json!(m.pcb_temperature),
-                    "state" => json!(m.power_state.name()),
-                    "watchdog_elapsed" => json!(m.watchdog_elapsed),
-                    _ => unreachable!(),
-                }),
-                Err(e) => Err(e.to_string()),
+    state
+        .device
+        .with(move |device| {
+            let value: Result<Value, String> = match key.as_str() {
+                "5v_output_enabled" => device
+                    .get_5v_output_enabled()
+                    .map(|v| json!(v))
+                    .map_err(|e| e.to_string()),
+                "watchdog_timeout" => device
+                    .get_watchdog_timeout()
+                    .map(|v| json!(v as f64 / 1000.0))
+                    .map_err(|e| e.to_string()),
+                "watchdog_enabled" => device
+                    .get_watchdog_timeout()
+                    .map(|v| json!(v > 0))
+                    .map_err(|e| e.to_string()),
+                // One transaction per key instead of a full measurement read
+                "V_in" => device
+                    .get_dcin_voltage()
+                    .map(|v| json!(calibration.v_in.apply(v.0)))
+                    .map_err(|e| e.to_string()),
+                "V_cap" => device
+                    .get_supercap_voltage()
+                    .map(|v| json!(calibration.v_supercap.apply(v.0)))
+                    .map_err(|e| e.to_string()),
+                "I_in" => device
+                    .get_input_current()
+                    .map(|v| json!(calibration.i_in.apply(v.0)))
+                    .map_err(|e| e.to_string()),
+                "T_mcu" => device
+                    .get_mcu_temperature()
+                    .map(|v| json!(calibration.t_mcu.apply_temperature(v)))
+                    .map_err(|e| e.to_string()),
+                "T_pcb" => device
+                    .get_pcb_temperature()
+                    .map(|v| json!(calibration.t_pcb.apply_temperature(v)))
+                    .map_err(|e| e.to_string()),
+                "state" => device
+                    .get_power_state()
+                    .map(|s| json!(s.name()))
+                    .map_err(|e| e.to_string()),
+                "watchdog_elapsed" => device
+                    .get_watchdog_elapsed()
+                    .map(|v| json!(v))
+                    .map_err(|e| e.to_string()),
+                _ => unreachable!(),
+            };
+
+            match value {
+                Ok(v) => (StatusCode::OK, Json(v)).into_response(),
+                Err(e) => ApiError::new(ErrorCode::DeviceError, e).into_response(),
+            }
+        })
+        .await
+        .unwrap_or_else(device_unavailable)
+}
+
+/// PUT /values/:key - Set a writable runtime value
+///
+/// `led_brightness` takes 0-255, `5v_output_enabled` a boolean and
+/// `watchdog_timeout` seconds up to 65.535 (0 disables the watchdog).
+pub async fn put_value(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Json(payload): Json<Value>,
+) -> Response {
+    let key = value_key(&key).to_string();
+    let invalid = |expected: &str| {
+        ApiError::new(
+            ErrorCode::InvalidValue,
+            format!("{} must be {}", key, expected),
+        )
+        .into_response()
+    };
+    let result = match key.as_str() {
+        "led_brightness" => match payload.as_u64().and_then(|v| u8::try_from(v).ok()) {
+            Some(brightness) => {
+                state
+                    .device
+                    .with(move |device| device.set_led_brightness(brightness))
+                    .await
+            }
+            None => return invalid("an integer from 0 to 255"),
+        },
+        "5v_output_enabled" => match payload.as_bool() {
+            Some(enabled) => {
+                state
+                    .device
+                    .with(move |device| device.set_5v_output_enabled(enabled))
+                    .await
+            }
+            None => return invalid("true or false"),
+        },
+        "watchdog_timeout" => match payload.as_f64().filter(|v| (0.0..=65.535).contains(v)) {
+            Some(seconds) => {
+                let timeout_ms = (seconds * 1000.0).round() as u16;
+                state
+                    .device
+                    .with(move |device| device.set_watchdog_timeout(timeout_ms))
+                    .await
             }
+            None => return invalid("a number of seconds from 0 to 65.535"),
+        },
+        _ if requires_device_access(&key) || key == "daemon_version" => {
+            return ApiError::new(ErrorCode::ReadOnly, format!("{} is read-only", key))
+                .into_response();
+        }
+        _ => {
+            return ApiError::new(ErrorCode::UnknownKey, format!("Unknown key: {}", key))
+                .with_detail(json!({"key": key}))
+                .into_response();
         }
-        _ => unreachable!(),
     };
-
-    drop(device);
-
-    match value {
-        Ok(v) => (StatusCode::OK, Json(v)).into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e}))).into_response(),
+    match result {
+        Ok(Ok(())) => (StatusCode::OK, Json(json!({"status": "ok"}))).into_response(),
+        Err(e) => device_unavailable(e),
+        Ok(Err(e)) => ApiError::new(device_error_code(&e), e.to_string()).into_response(),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::i2c::device::HalpiDevice;
+    use crate::i2c::DeviceHandle;
+    use crate::i2c::{HalpiDevice, Simulator};
     use halpi_common::config::Config;
     use std::sync::Arc;
-    use tokio::sync::{Mutex, RwLock};
+    use tokio::sync::RwLock;
 
     #[tokio::test]
     async fn test_get_all_values_structure() {
-        // Skip test if I2C hardware not available
-        let device = match HalpiDevice::new(1, 0x6D) {
-            Ok(d) => Arc::new(Mutex::new(d)),
-            Err(_) => return,
-        };
+        let device = DeviceHandle::new(HalpiDevice::simulated(1, 0x6D, Simulator::new()));
         let config = Arc::new(RwLock::new(Config::default()));
         let state = AppState::new(device, config);
 
         let response = get_all_values(State(state)).await;
-        // Response will be 500 if no I2C device, but should be a valid response structure
-        assert!(
-            response.status() == StatusCode::OK
-                || response.status() == StatusCode::INTERNAL_SERVER_ERROR
-        );
+        assert_eq!(response.status(), StatusCode::OK);
     }
 
     #[tokio::test]
     async fn test_get_value_unknown_key() {
-        // Skip test if I2C hardware not available
-        let device = match HalpiDevice::new(1, 0x6D) {
-            Ok(d) => Arc::new(Mutex::new(d)),
-            Err(_) => return,
-        };
+        let device = DeviceHandle::new(HalpiDevice::simulated(1, 0x6D, Simulator::new()));
         let config = Arc::new(RwLock::new(Config::default()));
         let state = AppState::new(device, config);
 
         let response = get_value(State(state), Path("invalid_key".to_string())).await;
         assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
+
+    #[tokio::test]
+    async fn test_put_value_validation() {
+        let config = Arc::new(RwLock::new(Config::default()));
+        let state = AppState::new(DeviceHandle::missing(1, 0x6D), config);
+        let put = |key: &str, value: Value| {
+            put_value(State(state.clone()), Path(key.to_string()), Json(value))
+        };
+
+        assert_eq!(
+            put("led_brightness", json!(256)).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            put("5v_output_enabled", json!("off")).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            put("watchdog_timeout", json!(70)).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            put("V_supercap", json!(12)).await.status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        assert_eq!(
+            put("V_in", json!(12)).await.status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        let response = put("led_brightnes", json!(12)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let error: ApiError = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error.code, ErrorCode::UnknownKey);
+        assert_eq!(error.detail, Some(json!({"key": "led_brightnes"})));
+        // Valid values reach the device
+        assert_eq!(
+            put("led_brightness", json!(128)).await.status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
 }
@@ -0,0 +1,174 @@
+//! Limits on API requests that access a controller
+//!
+//! Every such request takes the device lock the state machine polls
+//! through, so a client polling in a tight loop could delay the polls. Each
+//! client process gets a token bucket of `burst` requests refilled at
+//! `rate` per second; requests beyond it are refused with 429 Too Many
+//! Requests. At most `max-concurrent` of these requests run at once, the
+//! others wait for their turn.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{HeaderValue, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use halpi_common::api::{ApiError, ErrorCode};
+use halpi_common::config::ApiLimitsConfig;
+use serde_json::json;
+use tokio::sync::Semaphore;
+
+use super::peer::PeerCredentials;
+
+/// Buckets kept before those of idle clients are dropped
+const MAX_BUCKETS: usize = 64;
+
+/// Client a bucket belongs to: the uid and pid of the peer process
+type Client = (Option<u32>, Option<i32>);
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Rate and concurrency limits shared by all device routes
+pub struct ApiLimits {
+    config: ApiLimitsConfig,
+    buckets: Mutex<HashMap<Client, Bucket>>,
+    running: Semaphore,
+}
+
+impl ApiLimits {
+    pub fn new(config: ApiLimitsConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+            running: Semaphore::new(config.max_concurrent),
+        }
+    }
+
+    pub fn config(&self) -> &ApiLimitsConfig {
+        &self.config
+    }
+
+    /// Take a token for a request of `client` at `now`
+    ///
+    /// Returns the seconds until a token is available if none is left.
+    fn take(&self, client: Client, now: Instant) -> Result<(), f64> {
+        let rate = self.config.rate;
+        if rate == 0.0 {
+            return Ok(());
+        }
+        let burst = self.config.burst as f64;
+        let refilled = |bucket: &Bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated);
+            (bucket.tokens + elapsed.as_secs_f64() * rate).min(burst)
+        };
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(&client) {
+            buckets.retain(|_, bucket| refilled(bucket) < burst);
+        }
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        bucket.tokens = refilled(bucket);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return Err((1.0 - bucket.tokens) / rate);
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+/// Middleware applying [`ApiLimits`] to a request
+pub async fn limit_requests(
+    State(limits): State<Arc<ApiLimits>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<PeerCredentials>>()
+        .map_or((None, None), |info| (info.0.uid, info.0.pid));
+    if let Err(wait) = limits.take(client, Instant::now()) {
+        let retry_after = (wait.ceil() as u64).max(1);
+        let mut response = ApiError::new(
+            ErrorCode::RateLimited,
+            format!(
+                "Too many requests; at most {} per second are allowed",
+                limits.config.rate
+            ),
+        )
+        .with_detail(json!({"retry_after": retry_after}))
+        .into_response();
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        return response;
+    }
+
+    // The semaphore is never closed
+    let _permit = limits.running.acquire().await;
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limits(rate: f64, burst: u32) -> ApiLimits {
+        ApiLimits::new(ApiLimitsConfig {
+            rate,
+            burst,
+            max_concurrent: 1,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let limits = limits(10.0, 2);
+        let now = Instant::now();
+        let client = (Some(1000), Some(42));
+        assert!(limits.take(client, now).is_ok());
+        assert!(limits.take(client, now).is_ok());
+        let wait = limits.take(client, now).unwrap_err();
+        assert!((wait - 0.1).abs() < 1e-9);
+
+        // Other clients have their own bucket
+        assert!(limits.take((Some(1000), Some(43)), now).is_ok());
+
+        // One token is back after 1 / rate seconds
+        let later = now + Duration::from_millis(100);
+        assert!(limits.take(client, later).is_ok());
+        assert!(limits.take(client, later).is_err());
+    }
+
+    #[test]
+    fn test_unlimited_rate() {
+        let limits = limits(0.0, 1);
+        let now = Instant::now();
+        for _ in 0..100 {
+            assert!(limits.take((None, None), now).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_idle_buckets_dropped() {
+        let limits = limits(10.0, 1);
+        let now = Instant::now();
+        for pid in 0..MAX_BUCKETS as i32 {
+            limits.take((None, Some(pid)), now).unwrap();
+        }
+        // Once refilled, idle buckets make room for new clients
+        let later = now + Duration::from_secs(1);
+        limits.take((None, Some(-1)), later).unwrap();
+        assert_eq!(limits.buckets.lock().unwrap().len(), 1);
+    }
+}
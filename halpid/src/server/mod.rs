@@ -5,5 +5,10 @@
 
 pub mod app;
 pub mod handlers;
+pub mod limit;
+pub mod peer;
+
+#[cfg(test)]
+mod tests;
 
 pub use app::{AppState, create_app};
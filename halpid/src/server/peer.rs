@@ -0,0 +1,78 @@
+//! Credentials of clients connected to the API socket
+//!
+//! The socket is only accessible to the `halpid` group, which is enough for
+//! reading values and controlling the device. Endpoints exposing daemon
+//! internals are further restricted to administrators, identified by the
+//! peer credentials the kernel attaches to each Unix socket connection.
+
+use axum::extract::connect_info::Connected;
+use axum::serve::IncomingStream;
+use tokio::net::{UnixListener, UnixStream};
+
+/// Credentials of the process on the other end of a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCredentials {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub pid: Option<i32>,
+}
+
+impl PeerCredentials {
+    /// Read the peer credentials of a connected stream
+    pub fn from_stream(stream: &UnixStream) -> Self {
+        match stream.peer_cred() {
+            Ok(cred) => Self {
+                uid: Some(cred.uid()),
+                gid: Some(cred.gid()),
+                pid: cred.pid(),
+            },
+            Err(e) => {
+                tracing::debug!("Failed to read peer credentials: {}", e);
+                Self {
+                    uid: None,
+                    gid: None,
+                    pid: None,
+                }
+            }
+        }
+    }
+
+    /// True if the peer is root or runs as the daemon's own user
+    pub fn is_admin(&self) -> bool {
+        // SAFETY: geteuid has no preconditions and cannot fail
+        let daemon_uid = unsafe { libc::geteuid() };
+        matches!(self.uid, Some(uid) if uid == 0 || uid == daemon_uid)
+    }
+}
+
+impl Connected<IncomingStream<'_, UnixListener>> for PeerCredentials {
+    fn connect_info(stream: IncomingStream<'_, UnixListener>) -> Self {
+        Self::from_stream(stream.io())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_peer_credentials() {
+        let (a, _b) = UnixStream::pair().unwrap();
+        let peer = PeerCredentials::from_stream(&a);
+        assert_eq!(peer.uid, Some(unsafe { libc::geteuid() }));
+        assert_eq!(peer.pid, Some(std::process::id() as i32));
+        assert!(peer.is_admin());
+
+        let unknown = PeerCredentials {
+            uid: None,
+            gid: None,
+            pid: None,
+        };
+        assert!(!unknown.is_admin());
+        let other = PeerCredentials {
+            uid: Some(u32::MAX - 1),
+            ..unknown
+        };
+        assert!(!other.is_admin());
+    }
+}
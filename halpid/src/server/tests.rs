@@ -0,0 +1,471 @@
+//! End-to-end tests of the HTTP API
+//!
+//! Each test serves the full application, as the daemon does, on a
+//! temporary Unix socket in front of a simulated controller, and talks
+//! HTTP/1.1 to it like the CLI. The simulator's snapshots show what the
+//! requests did to the controller.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::http::{Method, Request, StatusCode, header};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper_util::rt::TokioIo;
+use serde_json::{Value, json};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+
+use halpi_common::config::Config;
+use halpi_common::events::{DaemonEvent, DfuProgress};
+use halpi_common::protocol::DFUState;
+use halpi_common::types::{PowerState, Version};
+
+use super::app::{AppState, serve};
+use crate::daemon;
+use crate::events::EventBus;
+use crate::i2c::sim::{DfuFailure, Fault};
+use crate::i2c::{DeviceHandle, HalpiDevice, Simulator};
+use crate::selftest;
+use crate::state_machine::StatusHandle;
+
+/// The API of a simulated controller on a temporary socket
+struct TestServer {
+    dir: PathBuf,
+    socket: PathBuf,
+    simulator: Simulator,
+    device: DeviceHandle,
+    events: EventBus,
+    status: StatusHandle,
+    server: JoinHandle<anyhow::Result<()>>,
+}
+
+impl TestServer {
+    /// Serve the API with the default configuration
+    async fn start(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("halpid-api-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("halpid.sock");
+
+        let simulator = Simulator::new();
+        let device = DeviceHandle::new(HalpiDevice::simulated(1, 0x6D, simulator.clone()));
+        let state = AppState::new(device.clone(), Arc::new(RwLock::new(Config::default())))
+            .with_reboot_marker(Some(dir.join("reboot-auto-restart")));
+        let events = state.events.clone();
+        let status = state.status.clone();
+        let listener = UnixListener::bind(&socket).unwrap();
+        let server = tokio::spawn(serve(listener, state));
+        Self {
+            dir,
+            socket,
+            simulator,
+            device,
+            events,
+            status,
+            server,
+        }
+    }
+
+    /// Send a request on a new connection, returning the status and body
+    async fn send(&self, request: Request<Full<Bytes>>) -> (StatusCode, Bytes) {
+        let stream = UnixStream::connect(&self.socket).await.unwrap();
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .unwrap();
+        tokio::spawn(connection);
+        let response = sender.send_request(request).await.unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, body)
+    }
+
+    /// Send a request with an optional JSON body, returning the status
+    /// and the JSON response, or null for an empty one
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(format!("http://localhost{}", path))
+            .header(header::HOST, "localhost");
+        let body = match body {
+            Some(body) => {
+                request = request.header(header::CONTENT_TYPE, "application/json");
+                Full::new(Bytes::from(body.to_string()))
+            }
+            None => Full::default(),
+        };
+        let (status, body) = self.send(request.body(body).unwrap()).await;
+        // Rejections by axum's extractors are plain text
+        let json = if body.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&body)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned()))
+        };
+        (status, json)
+    }
+
+    async fn get(&self, path: &str) -> (StatusCode, Value) {
+        self.request(Method::GET, path, None).await
+    }
+
+    async fn put(&self, path: &str, body: Value) -> (StatusCode, Value) {
+        self.request(Method::PUT, path, Some(body)).await
+    }
+
+    async fn post(&self, path: &str, body: Value) -> (StatusCode, Value) {
+        self.request(Method::POST, path, Some(body)).await
+    }
+
+    /// Upload `firmware` to `/flash` as a multipart form
+    async fn flash(&self, firmware: &[u8]) -> (StatusCode, Bytes) {
+        const BOUNDARY: &str = "halpid-test-boundary";
+        let mut body = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"firmware\"; filename=\"fw.bin\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            BOUNDARY
+        )
+        .into_bytes();
+        body.extend_from_slice(firmware);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+        let request = Request::post("http://localhost/flash")
+            .header(header::HOST, "localhost")
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .body(Full::new(Bytes::from(body)))
+            .unwrap();
+        self.send(request).await
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.server.abort();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[tokio::test]
+async fn test_values() {
+    let server = TestServer::start("values").await;
+
+    let (status, values) = server.get("/values").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!((values["V_in"].as_f64().unwrap() - 12.0).abs() < 0.1);
+    assert_eq!(values["state"], "OperationalCoOp");
+    assert_eq!(values["firmware_version"], "3.3.0");
+    assert_eq!(values["V_supercap"], values["V_cap"]);
+
+    server
+        .simulator
+        .inject(Fault::VoltageDrop {
+            v_in: 0.0,
+            duration: None,
+        })
+        .unwrap();
+    let (status, v_in) = server.get("/values/V_in").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(v_in.as_f64().unwrap() < 0.1);
+    assert_eq!(server.get("/values/state").await.1, "BlackoutCoOp");
+
+    let (status, _) = server.put("/values/led_brightness", json!(40)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(server.get("/config/led_brightness").await.1, 40);
+    let (status, error) = server.get("/values/V_out").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(error["code"], "UNKNOWN_KEY");
+    assert_eq!(
+        server.put("/values/V_in", json!(12)).await.0,
+        StatusCode::METHOD_NOT_ALLOWED
+    );
+}
+
+#[tokio::test]
+async fn test_config() {
+    let server = TestServer::start("config").await;
+
+    let (status, config) = server.get("/config").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(config["led_brightness"], 255);
+    assert_eq!(config["auto_restart"], true);
+
+    for (key, value) in [
+        ("led_brightness", json!(40)),
+        ("auto_restart", json!(false)),
+        ("solo_depleting_timeout", json!(5.0)),
+        ("watchdog_timeout", json!(10.0)),
+    ] {
+        let (status, _) = server.put(&format!("/config/{}", key), value.clone()).await;
+        assert_eq!(status, StatusCode::OK, "{}", key);
+        let (status, read) = server.get(&format!("/config/{}", key)).await;
+        assert_eq!((status, read), (StatusCode::OK, value), "{}", key);
+    }
+    let (status, threshold) = server.get("/config/power_on_threshold").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!((threshold.as_f64().unwrap() - 9.0).abs() < 0.01);
+
+    assert_eq!(server.get("/config/i2c_bus").await.0, StatusCode::NOT_FOUND);
+    let (status, error) = server.put("/config/led_brightness", json!("dim")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["code"], "INVALID_VALUE");
+    let (status, error) = server.put("/config/led_brightness", json!(300)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["code"], "INVALID_VALUE");
+    assert_eq!(server.get("/config/led_brightness").await.1, 40);
+
+    let (status, diff) = server.get("/config/diff").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(diff["drift"], json!([]));
+}
+
+#[tokio::test]
+async fn test_usb() {
+    let server = TestServer::start("usb").await;
+
+    let (status, usb) = server.get("/usb").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(usb["usb0"], true);
+
+    assert_eq!(
+        server.put("/usb/2", json!(false)).await.0,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(server.get("/usb/2").await, (StatusCode::OK, json!(false)));
+    assert_eq!(
+        server
+            .put("/usb", json!({"usb0": false, "usb2": true}))
+            .await
+            .0,
+        StatusCode::NO_CONTENT
+    );
+    let usb = server.get("/usb").await.1;
+    assert_eq!(
+        [&usb["usb0"], &usb["usb1"], &usb["usb2"], &usb["usb3"]],
+        [&json!(false), &json!(true), &json!(true), &json!(true)]
+    );
+
+    let (status, error) = server.get("/usb/4").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["code"], "INVALID_PORT");
+}
+
+#[tokio::test]
+async fn test_shutdown() {
+    let server = TestServer::start("shutdown").await;
+
+    // Nothing to cancel without a blackout
+    let (status, error) = server.post("/shutdown/cancel", json!({})).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(error["code"], "CONFLICT");
+
+    assert_eq!(
+        server.post("/shutdown", json!({})).await.0,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+        server.simulator.snapshot().state,
+        PowerState::ManualShutdown
+    );
+}
+
+#[tokio::test]
+async fn test_reboot() {
+    let server = TestServer::start("reboot").await;
+    server.put("/config/auto_restart", json!(false)).await;
+
+    assert_eq!(
+        server.post("/reboot", json!({})).await.0,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+        server.simulator.snapshot().state,
+        PowerState::ManualShutdown
+    );
+    assert_eq!(server.get("/config/auto_restart").await.1, true);
+
+    // Off again once the daemon has restarted
+    let marker = server.dir.join("reboot-auto-restart");
+    assert!(marker.exists());
+    daemon::reboot::restore_auto_restart(server.device.clone(), &marker)
+        .await
+        .unwrap();
+    assert_eq!(server.get("/config/auto_restart").await.1, false);
+    assert!(!marker.exists());
+
+    // Nothing to restore if auto-restart was on already
+    server.put("/config/auto_restart", json!(true)).await;
+    server.post("/reboot", json!({})).await;
+    assert!(!marker.exists());
+}
+
+#[tokio::test]
+async fn test_standby() {
+    // Requests that would set the RTC alarm run `rtcwake`, so only the
+    // ones refused before reaching the controller are exercised
+    let server = TestServer::start("standby").await;
+
+    let (status, error) = server
+        .post("/standby", json!({"datetime": "tomorrow"}))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["code"], "INVALID_TIME");
+
+    let (status, error) = server
+        .post("/standby", json!({"datetime": "2020-01-01T00:00:00Z"}))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["code"], "INVALID_TIME");
+
+    let (status, error) = server.post("/standby", json!({"wake_at": "sunrise"})).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(error["code"], "CONFLICT");
+
+    assert_eq!(
+        server.post("/standby", json!({"delay": "soon"})).await.0,
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+    assert_eq!(
+        server.simulator.snapshot().state,
+        PowerState::OperationalCoOp
+    );
+}
+
+#[tokio::test]
+async fn test_flash() {
+    let server = TestServer::start("flash").await;
+    let firmware = vec![0xA5; 6000];
+
+    let mut events = server.events.subscribe();
+
+    let (status, _) = server.flash(&firmware).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(server.simulator.snapshot().dfu_state, DFUState::Idle);
+    let mut progress = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        if let DaemonEvent::Dfu { progress: step, .. } = event {
+            progress.push(step);
+        }
+    }
+    assert_eq!(
+        progress.first(),
+        Some(&DfuProgress::Started { total: 6000 })
+    );
+    assert_eq!(
+        progress.last(),
+        Some(&DfuProgress::Completed { total: 6000 })
+    );
+
+    // A failed update is reported and aborted
+    server
+        .simulator
+        .inject(Fault::DfuError {
+            state: DfuFailure::WriteError,
+            after_blocks: 1,
+        })
+        .unwrap();
+    let (status, body) = server.flash(&firmware).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    let error: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["code"], "DEVICE_ERROR");
+    assert_eq!(server.simulator.snapshot().dfu_state, DFUState::Idle);
+}
+
+#[tokio::test]
+async fn test_incompatible_firmware() {
+    let server = TestServer::start("incompatible").await;
+    selftest::run(&server.device, &server.status, Version::new(3, 4, 0))
+        .await
+        .unwrap();
+
+    // Monitoring goes on
+    assert_eq!(server.get("/values").await.0, StatusCode::OK);
+    assert_eq!(server.get("/config/led_brightness").await.1, 255);
+    let (status, health) = server.get("/health").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(health["status"], "degraded");
+    assert_eq!(health["self_test"]["compatible"], false);
+    assert_eq!(health["self_test"]["firmware_version"], "3.3.0");
+
+    // Changes are refused
+    for (method, path) in [
+        (Method::PUT, "/config/led_brightness"),
+        (Method::PUT, "/devices/default/values/led_brightness"),
+        (Method::PUT, "/usb/2"),
+        (Method::POST, "/shutdown"),
+    ] {
+        let (status, error) = server.request(method, path, Some(json!(40))).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{}", path);
+        assert_eq!(error["code"], "INCOMPATIBLE", "{}", path);
+        assert_eq!(error["detail"]["min_firmware"], "3.4.0");
+    }
+    assert_eq!(
+        server.simulator.snapshot().state,
+        PowerState::OperationalCoOp
+    );
+
+    // Firmware can still be updated, and the self-test is repeated with
+    // the configured minimum
+    assert_eq!(server.flash(&[0xA5; 100]).await.0, StatusCode::NO_CONTENT);
+    assert!(server.status.is_compatible());
+    assert_eq!(
+        server.put("/config/led_brightness", json!(40)).await.0,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn test_unsupported_hardware() {
+    let server = TestServer::start("prototype").await;
+    server.simulator.set_hardware(Version::new(0, 9, 0));
+
+    let (_, info) = server.get("/info").await;
+    assert_eq!(info["capabilities"]["usb_ports"], false);
+    assert_eq!(info["capabilities"]["led_pattern"], true);
+
+    // Port requests are refused instead of accessing the register
+    for (method, path) in [(Method::GET, "/usb"), (Method::PUT, "/usb/2")] {
+        let (status, error) = server.request(method, path, Some(json!(false))).await;
+        assert_eq!(status, StatusCode::NOT_IMPLEMENTED, "{}", path);
+        assert_eq!(error["code"], "NOT_SUPPORTED");
+        assert!(
+            error["error"]
+                .as_str()
+                .unwrap()
+                .ends_with("requires hardware 1.0.0 or later (controller is 0.9.0)")
+        );
+    }
+
+    // Other features are unaffected
+    assert_eq!(
+        server.put("/config/led_brightness", json!(40)).await.0,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn test_device_errors() {
+    let server = TestServer::start("errors").await;
+
+    // NAKs beyond the retries surface as device errors, not crashes
+    server
+        .simulator
+        .inject(Fault::Nak {
+            register: None,
+            count: 100,
+        })
+        .unwrap();
+    let (status, error) = server.get("/usb").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(error["code"], "DEVICE_ERROR");
+
+    server.simulator.inject(Fault::Clear).unwrap();
+    assert_eq!(server.get("/usb").await.0, StatusCode::OK);
+}
@@ -0,0 +1,454 @@
+//! SNMP AgentX subagent
+//!
+//! Connects to the local master agent (net-snmp `snmpd` with
+//! `master agentx`) and registers a read-only subtree under the configured
+//! enterprise OID:
+//!
+//! | OID suffix | Object              | Type      | Unit         |
+//! |------------|---------------------|-----------|--------------|
+//! | `.1.0`     | daemon version      | string    |              |
+//! | `.2.1.0`   | DC input voltage    | Gauge32   | millivolts   |
+//! | `.2.2.0`   | supercap voltage    | Gauge32   | millivolts   |
+//! | `.2.3.0`   | input current       | Gauge32   | milliamperes |
+//! | `.2.4.0`   | MCU temperature     | Integer   | 0.1 °C       |
+//! | `.2.5.0`   | PCB temperature     | Integer   | 0.1 °C       |
+//! | `.2.6.0`   | power state         | Integer   | firmware enum|
+//! | `.2.7.0`   | power state name    | string    |              |
+//! | `.2.8.0`   | supercap charge     | Gauge32   | percent      |
+//! | `.2.9.0`   | watchdog elapsed    | Gauge32   | milliseconds |
+//! | `.3.1.0`   | device ID           | string    |              |
+//! | `.3.2.0`   | firmware version    | string    |              |
+//! | `.3.3.0`   | hardware version    | string    |              |
+//!
+//! The session is re-established automatically if the master agent restarts.
+
+pub mod pdu;
+
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UnixStream};
+use tokio::sync::watch;
+use tokio::time::{Duration, MissedTickBehavior, interval, sleep};
+use tracing::{info, warn};
+
+use halpi_common::config::SnmpConfig;
+use halpi_common::types::Measurements;
+use halpi_common::units::{Kelvin, Volts};
+
+use crate::events::EventBus;
+use crate::i2c::DeviceHandle;
+use pdu::{Header, Oid, PduBuilder, Request, SearchRange, VarValue};
+
+/// How often the MIB values are refreshed
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Delay before reconnecting to the master agent
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// Timeout (seconds) the master should apply to our responses
+const SESSION_TIMEOUT: u8 = 5;
+
+/// Supercap voltage treated as empty when the controller threshold cannot be read
+const FALLBACK_EMPTY_VOLTAGE: Volts = Volts(6.0);
+
+/// Static device information read once at startup
+#[derive(Debug, Clone, Default)]
+pub struct DeviceInfo {
+    pub device_id: String,
+    pub firmware_version: String,
+    pub hardware_version: String,
+    pub empty_voltage: Volts,
+}
+
+/// Snapshot of the exported objects, keyed by full instance OID
+pub type Mib = BTreeMap<Oid, VarValue>;
+
+/// Build the MIB snapshot from a measurement sample
+pub fn build_mib(base: &Oid, m: &Measurements, info: &DeviceInfo) -> Mib {
+    let milli = |v: f32| VarValue::Gauge32((v.max(0.0) * 1000.0).round() as u32);
+    let deci_celsius = |t: Kelvin| VarValue::Integer((t.celsius().0 * 10.0).round() as i32);
+    let string = |s: &str| VarValue::OctetString(s.as_bytes().to_vec());
+
+    let mut mib = Mib::new();
+    mib.insert(base.child(&[1, 0]), string(env!("CARGO_PKG_VERSION")));
+    mib.insert(base.child(&[2, 1, 0]), milli(m.dcin_voltage.0));
+    mib.insert(base.child(&[2, 2, 0]), milli(m.supercap_voltage.0));
+    mib.insert(base.child(&[2, 3, 0]), milli(m.input_current.0));
+    mib.insert(base.child(&[2, 4, 0]), deci_celsius(m.mcu_temperature));
+    mib.insert(base.child(&[2, 5, 0]), deci_celsius(m.pcb_temperature));
+    mib.insert(
+        base.child(&[2, 6, 0]),
+        VarValue::Integer(m.power_state as i32),
+    );
+    mib.insert(base.child(&[2, 7, 0]), string(m.power_state.name()));
+    mib.insert(
+        base.child(&[2, 8, 0]),
+        VarValue::Gauge32((m.supercap_charge(info.empty_voltage) * 100.0).round() as u32),
+    );
+    mib.insert(base.child(&[2, 9, 0]), milli(m.watchdog_elapsed));
+    mib.insert(base.child(&[3, 1, 0]), string(&info.device_id));
+    mib.insert(base.child(&[3, 2, 0]), string(&info.firmware_version));
+    mib.insert(base.child(&[3, 3, 0]), string(&info.hardware_version));
+    mib
+}
+
+/// Resolve a Get search range
+///
+/// Requests below a known object but with the wrong instance yield
+/// `noSuchInstance`; anything else not in the MIB yields `noSuchObject`.
+fn lookup_exact(mib: &Mib, range: &SearchRange) -> (Oid, VarValue) {
+    let value = match mib.get(&range.start) {
+        Some(value) => value.clone(),
+        None => {
+            let under_object = mib.keys().any(|instance| {
+                let object = &instance.0[..instance.0.len() - 1];
+                range.start.0.starts_with(object)
+            });
+            if under_object {
+                VarValue::NoSuchInstance
+            } else {
+                VarValue::NoSuchObject
+            }
+        }
+    };
+    (range.start.clone(), value)
+}
+
+/// Resolve a GetNext search range
+fn lookup_next(mib: &Mib, range: &SearchRange) -> (Oid, VarValue) {
+    let candidate = mib
+        .range(range.start.clone()..)
+        .find(|(oid, _)| range.include || **oid != range.start);
+
+    match candidate {
+        Some((oid, value)) if range.end.is_null() || *oid < range.end => {
+            (oid.clone(), value.clone())
+        }
+        _ => (range.start.clone(), VarValue::EndOfMibView),
+    }
+}
+
+/// Build the response to a master agent request, or `None` if no reply is due
+pub fn respond(header: &Header, request: &Request, mib: Option<&Mib>) -> Option<Vec<u8>> {
+    let varbinds = |pdu: &mut PduBuilder, items: Vec<(Oid, VarValue)>| {
+        for (oid, value) in items {
+            pdu.varbind(&oid, &value);
+        }
+    };
+
+    let error_response = |error| Some(PduBuilder::response(header, error, 0).build());
+
+    match request {
+        Request::Get(ranges) => {
+            let Some(mib) = mib else {
+                return error_response(pdu::ERROR_GEN_ERR);
+            };
+            let mut pdu = PduBuilder::response(header, pdu::ERROR_NONE, 0);
+            varbinds(
+                &mut pdu,
+                ranges.iter().map(|r| lookup_exact(mib, r)).collect(),
+            );
+            Some(pdu.build())
+        }
+        Request::GetNext(ranges) => {
+            let Some(mib) = mib else {
+                return error_response(pdu::ERROR_GEN_ERR);
+            };
+            let mut pdu = PduBuilder::response(header, pdu::ERROR_NONE, 0);
+            varbinds(
+                &mut pdu,
+                ranges.iter().map(|r| lookup_next(mib, r)).collect(),
+            );
+            Some(pdu.build())
+        }
+        Request::GetBulk {
+            non_repeaters,
+            max_repetitions,
+            ranges,
+        } => {
+            let Some(mib) = mib else {
+                return error_response(pdu::ERROR_GEN_ERR);
+            };
+            let split = (*non_repeaters as usize).min(ranges.len());
+            let mut items: Vec<(Oid, VarValue)> = ranges[..split]
+                .iter()
+                .map(|r| lookup_next(mib, r))
+                .collect();
+
+            // Repeaters are interleaved row by row, as in SNMP GetBulk
+            let mut cursors: Vec<SearchRange> = ranges[split..].to_vec();
+            for _ in 0..*max_repetitions {
+                if cursors.is_empty() {
+                    break;
+                }
+                let mut all_done = true;
+                for cursor in cursors.iter_mut() {
+                    let (oid, value) = lookup_next(mib, cursor);
+                    if value != VarValue::EndOfMibView {
+                        all_done = false;
+                    }
+                    cursor.start = oid.clone();
+                    cursor.include = false;
+                    items.push((oid, value));
+                }
+                if all_done {
+                    break;
+                }
+            }
+
+            let mut pdu = PduBuilder::response(header, pdu::ERROR_NONE, 0);
+            varbinds(&mut pdu, items);
+            Some(pdu.build())
+        }
+        Request::TestSet => Some(PduBuilder::response(header, pdu::ERROR_NOT_WRITABLE, 1).build()),
+        Request::OtherSet => error_response(pdu::ERROR_NONE),
+        Request::Unsupported(_) => error_response(pdu::ERROR_PARSE),
+        Request::Response { .. } | Request::Close => None,
+    }
+}
+
+/// A byte stream to the master agent
+trait MasterStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> MasterStream for T {}
+
+/// Connect to the master agent
+///
+/// `tcp:host:port` addresses use TCP; anything else is a Unix socket path.
+async fn connect(master: &str) -> Result<Box<dyn MasterStream>> {
+    if let Some(addr) = master.strip_prefix("tcp:") {
+        let stream = TcpStream::connect(addr)
+            .await
+            .with_context(|| format!("Failed to connect to AgentX master at {}", addr))?;
+        Ok(Box::new(stream))
+    } else {
+        let path = master.strip_prefix("unix:").unwrap_or(master);
+        let stream = UnixStream::connect(path)
+            .await
+            .with_context(|| format!("Failed to connect to AgentX master at {}", path))?;
+        Ok(Box::new(stream))
+    }
+}
+
+async fn read_pdu(stream: &mut (impl AsyncRead + Unpin)) -> Result<(Header, Vec<u8>)> {
+    let mut fixed = [0u8; pdu::HEADER_SIZE];
+    stream.read_exact(&mut fixed).await?;
+    let header = Header::decode(&fixed).map_err(anyhow::Error::msg)?;
+    if header.payload_length as usize > pdu::MAX_PAYLOAD_SIZE {
+        bail!("AgentX PDU too large ({} bytes)", header.payload_length);
+    }
+    let mut payload = vec![0u8; header.payload_length as usize];
+    stream.read_exact(&mut payload).await?;
+    Ok((header, payload))
+}
+
+/// Wait for the response to one of our own PDUs
+async fn expect_response(stream: &mut (impl AsyncRead + Unpin), what: &str) -> Result<Header> {
+    let (header, payload) = read_pdu(stream).await?;
+    match pdu::decode_request(&header, &payload).map_err(anyhow::Error::msg)? {
+        Request::Response { error: 0 } => Ok(header),
+        Request::Response { error } => bail!("AgentX {} failed with error {}", what, error),
+        other => bail!("Unexpected AgentX PDU during {}: {:?}", what, other),
+    }
+}
+
+/// Run one AgentX session until it fails or the master closes it
+async fn session(
+    config: &SnmpConfig,
+    base: &Oid,
+    mib: &watch::Receiver<Option<Mib>>,
+) -> Result<()> {
+    let mut stream = connect(&config.master).await?;
+
+    stream
+        .write_all(&pdu::open(1, SESSION_TIMEOUT, "HALPI2 halpid subagent"))
+        .await?;
+    let session_id = expect_response(&mut stream, "Open").await?.session_id;
+
+    stream
+        .write_all(&pdu::register(session_id, 2, base))
+        .await?;
+    expect_response(&mut stream, "Register").await?;
+    info!("AgentX session {} registered {}", session_id, base);
+
+    loop {
+        let (header, payload) = read_pdu(&mut stream).await?;
+        let request = match pdu::decode_request(&header, &payload) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Malformed AgentX PDU: {}", e);
+                Request::Unsupported(header.pdu_type)
+            }
+        };
+        if request == Request::Close {
+            bail!("AgentX master closed the session");
+        }
+
+        let reply = {
+            let current = mib.borrow();
+            respond(&header, &request, current.as_ref())
+        };
+        if let Some(reply) = reply {
+            stream.write_all(&reply).await?;
+        }
+    }
+}
+
+/// Periodically refresh the MIB snapshot
+async fn refresh_snapshot(
+    events: EventBus,
+    base: Oid,
+    info: DeviceInfo,
+    tx: watch::Sender<Option<Mib>>,
+) {
+    let mut ticker = interval(POLL_INTERVAL);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+        let mib = events
+            .current()
+            .map(|sample| build_mib(&base, &sample.measurements, &info));
+        tx.send_replace(mib);
+    }
+}
+
+/// Run the subagent, reconnecting to the master agent as needed
+pub async fn run(device: DeviceHandle, events: EventBus, config: SnmpConfig) -> Result<()> {
+    let base: Oid = config
+        .base_oid
+        .parse()
+        .map_err(anyhow::Error::msg)
+        .context("Invalid snmp.base-oid")?;
+
+    // Identity is only known once the controller is connected
+    let info = device
+        .with_present(|dev| DeviceInfo {
+            device_id: dev.get_device_id().unwrap_or_default(),
+            firmware_version: dev
+                .get_firmware_version()
+                .map(|v| v.to_string())
+                .unwrap_or_default(),
+            hardware_version: dev
+                .get_hardware_version()
+                .map(|v| v.to_string())
+                .unwrap_or_default(),
+            empty_voltage: dev
+                .get_solo_power_off_threshold()
+                .map_or(FALLBACK_EMPTY_VOLTAGE, Volts),
+        })
+        .await?;
+
+    let (tx, rx) = watch::channel(None);
+    tokio::spawn(refresh_snapshot(events, base.clone(), info, tx));
+
+    loop {
+        if let Err(e) = session(&config, &base, &rx).await {
+            warn!(
+                "AgentX session ended: {:#}; retrying in {}s",
+                e,
+                RECONNECT_DELAY.as_secs()
+            );
+        }
+        sleep(RECONNECT_DELAY).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use halpi_common::types::PowerState;
+    use halpi_common::units::{Amps, Kelvin, Volts};
+
+    fn base() -> Oid {
+        "1.3.6.1.4.1.99999.1".parse().unwrap()
+    }
+
+    fn mib() -> Mib {
+        let m = Measurements {
+            dcin_voltage: Volts(12.345),
+            supercap_voltage: Volts(9.5),
+            input_current: Amps(0.25),
+            mcu_temperature: Kelvin(300.0),
+            pcb_temperature: Kelvin(298.15),
+            power_state: PowerState::OperationalCoOp,
+            watchdog_elapsed: 1.5,
+            measured_at: Utc::now(),
+        };
+        build_mib(&base(), &m, &DeviceInfo::default())
+    }
+
+    fn range(start: Oid, include: bool) -> SearchRange {
+        SearchRange {
+            start,
+            include,
+            end: Oid::default(),
+        }
+    }
+
+    #[test]
+    fn test_build_mib_values() {
+        let mib = mib();
+        assert_eq!(mib[&base().child(&[2, 1, 0])], VarValue::Gauge32(12345));
+        assert_eq!(mib[&base().child(&[2, 5, 0])], VarValue::Integer(250));
+        assert_eq!(mib[&base().child(&[2, 6, 0])], VarValue::Integer(4));
+    }
+
+    #[test]
+    fn test_lookup_exact() {
+        let mib = mib();
+        let (_, value) = lookup_exact(&mib, &range(base().child(&[2, 2, 0]), false));
+        assert_eq!(value, VarValue::Gauge32(9500));
+
+        let (_, value) = lookup_exact(&mib, &range(base().child(&[2, 2, 1]), false));
+        assert_eq!(value, VarValue::NoSuchInstance);
+
+        let (_, value) = lookup_exact(&mib, &range(base().child(&[7]), false));
+        assert_eq!(value, VarValue::NoSuchObject);
+    }
+
+    #[test]
+    fn test_lookup_next_walk() {
+        let mib = mib();
+        let (oid, _) = lookup_next(&mib, &range(base(), false));
+        assert_eq!(oid, base().child(&[1, 0]));
+
+        let (oid, _) = lookup_next(&mib, &range(base().child(&[1, 0]), false));
+        assert_eq!(oid, base().child(&[2, 1, 0]));
+
+        let (oid, _) = lookup_next(&mib, &range(base().child(&[1, 0]), true));
+        assert_eq!(oid, base().child(&[1, 0]));
+
+        let (_, value) = lookup_next(&mib, &range(base().child(&[3, 3, 0]), false));
+        assert_eq!(value, VarValue::EndOfMibView);
+    }
+
+    #[test]
+    fn test_get_bulk_repeats() {
+        let mib = mib();
+        let header = Header::default();
+        let request = Request::GetBulk {
+            non_repeaters: 0,
+            max_repetitions: 3,
+            ranges: vec![range(base(), false)],
+        };
+        let reply = respond(&header, &request, Some(&mib)).unwrap();
+        let mut fixed = [0u8; pdu::HEADER_SIZE];
+        fixed.copy_from_slice(&reply[..pdu::HEADER_SIZE]);
+        let reply_header = Header::decode(&fixed).unwrap();
+        assert_eq!(
+            reply_header.payload_length as usize,
+            reply.len() - pdu::HEADER_SIZE
+        );
+        assert!(reply.len() > pdu::HEADER_SIZE + 8 + 3 * 8);
+    }
+
+    #[test]
+    fn test_set_refused() {
+        let reply = respond(&Header::default(), &Request::TestSet, None).unwrap();
+        // error field follows the 4-byte sysUpTime
+        let error = u16::from_be_bytes([reply[pdu::HEADER_SIZE + 4], reply[pdu::HEADER_SIZE + 5]]);
+        assert_eq!(error, pdu::ERROR_NOT_WRITABLE);
+    }
+}
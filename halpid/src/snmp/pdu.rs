@@ -0,0 +1,573 @@
+//! AgentX protocol data units (RFC 2741)
+//!
+//! Encodes the PDUs a read-only subagent sends (Open, Register, Response)
+//! and decodes the requests the master agent forwards (Get, GetNext,
+//! GetBulk, and the Set phases, which are refused).
+
+use std::fmt;
+
+/// Length of the fixed PDU header
+pub const HEADER_SIZE: usize = 20;
+
+/// Largest PDU payload accepted from the master agent
+pub const MAX_PAYLOAD_SIZE: usize = 1 << 16;
+
+const AGENTX_VERSION: u8 = 1;
+
+/// Header flag: multi-byte fields are big-endian
+const FLAG_NETWORK_BYTE_ORDER: u8 = 0x10;
+
+/// Header flag: a context octet string precedes the payload
+const FLAG_NON_DEFAULT_CONTEXT: u8 = 0x08;
+
+/// `internet` prefix (1.3.6.1) that OIDs may be compressed against
+const INTERNET_PREFIX: [u32; 4] = [1, 3, 6, 1];
+
+/// PDU types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PduType {
+    Open = 1,
+    Close = 2,
+    Register = 3,
+    Get = 5,
+    GetNext = 6,
+    GetBulk = 7,
+    TestSet = 8,
+    CommitSet = 9,
+    UndoSet = 10,
+    CleanupSet = 11,
+    Ping = 13,
+    Response = 18,
+}
+
+impl PduType {
+    fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            1 => PduType::Open,
+            2 => PduType::Close,
+            3 => PduType::Register,
+            5 => PduType::Get,
+            6 => PduType::GetNext,
+            7 => PduType::GetBulk,
+            8 => PduType::TestSet,
+            9 => PduType::CommitSet,
+            10 => PduType::UndoSet,
+            11 => PduType::CleanupSet,
+            13 => PduType::Ping,
+            18 => PduType::Response,
+            _ => return None,
+        })
+    }
+}
+
+/// Response error codes
+pub const ERROR_NONE: u16 = 0;
+pub const ERROR_GEN_ERR: u16 = 5;
+pub const ERROR_NOT_WRITABLE: u16 = 17;
+pub const ERROR_PARSE: u16 = 266;
+
+/// An object identifier
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Oid(pub Vec<u32>);
+
+impl Oid {
+    /// Append sub-identifiers
+    pub fn child(&self, subids: &[u32]) -> Oid {
+        let mut oid = self.0.clone();
+        oid.extend_from_slice(subids);
+        Oid(oid)
+    }
+
+    /// True if `self` lies within the subtree rooted at `prefix`
+    pub fn starts_with(&self, prefix: &Oid) -> bool {
+        self.0.starts_with(&prefix.0)
+    }
+
+    /// True for the zero-length OID
+    pub fn is_null(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl std::str::FromStr for Oid {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim_start_matches('.')
+            .split('.')
+            .map(|part| {
+                part.parse::<u32>()
+                    .map_err(|_| format!("invalid OID '{}'", s))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Oid)
+    }
+}
+
+impl fmt::Display for Oid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self.0.iter().map(u32::to_string).collect();
+        write!(f, "{}", parts.join("."))
+    }
+}
+
+/// A variable binding value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VarValue {
+    Integer(i32),
+    OctetString(Vec<u8>),
+    Null,
+    Counter32(u32),
+    Gauge32(u32),
+    TimeTicks(u32),
+    Counter64(u64),
+    NoSuchObject,
+    NoSuchInstance,
+    EndOfMibView,
+}
+
+impl VarValue {
+    fn type_code(&self) -> u16 {
+        match self {
+            VarValue::Integer(_) => 2,
+            VarValue::OctetString(_) => 4,
+            VarValue::Null => 5,
+            VarValue::Counter32(_) => 65,
+            VarValue::Gauge32(_) => 66,
+            VarValue::TimeTicks(_) => 67,
+            VarValue::Counter64(_) => 70,
+            VarValue::NoSuchObject => 128,
+            VarValue::NoSuchInstance => 129,
+            VarValue::EndOfMibView => 130,
+        }
+    }
+}
+
+/// Fixed header shared by all PDUs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Header {
+    pub pdu_type: u8,
+    pub flags: u8,
+    pub session_id: u32,
+    pub transaction_id: u32,
+    pub packet_id: u32,
+    pub payload_length: u32,
+}
+
+impl Header {
+    /// Parse the fixed header
+    pub fn decode(buf: &[u8; HEADER_SIZE]) -> Result<Self, String> {
+        if buf[0] != AGENTX_VERSION {
+            return Err(format!("unsupported AgentX version {}", buf[0]));
+        }
+        let big_endian = buf[2] & FLAG_NETWORK_BYTE_ORDER != 0;
+        let word = |i: usize| {
+            let bytes = [buf[i], buf[i + 1], buf[i + 2], buf[i + 3]];
+            if big_endian {
+                u32::from_be_bytes(bytes)
+            } else {
+                u32::from_le_bytes(bytes)
+            }
+        };
+        Ok(Self {
+            pdu_type: buf[1],
+            flags: buf[2],
+            session_id: word(4),
+            transaction_id: word(8),
+            packet_id: word(12),
+            payload_length: word(16),
+        })
+    }
+}
+
+/// A search range from a Get/GetNext/GetBulk request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchRange {
+    pub start: Oid,
+    pub include: bool,
+    pub end: Oid,
+}
+
+/// A request forwarded by the master agent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    Get(Vec<SearchRange>),
+    GetNext(Vec<SearchRange>),
+    GetBulk {
+        non_repeaters: u16,
+        max_repetitions: u16,
+        ranges: Vec<SearchRange>,
+    },
+    TestSet,
+    /// CommitSet, UndoSet, and CleanupSet all get an empty success response
+    OtherSet,
+    /// A response to one of our own PDUs (Open, Register, Close)
+    Response {
+        error: u16,
+    },
+    /// The master is closing the session
+    Close,
+    /// Anything else; answered with a parse error
+    Unsupported(u8),
+}
+
+/// Decode a request payload given its header
+///
+/// # Errors
+/// Returns an error if the payload is malformed.
+pub fn decode_request(header: &Header, payload: &[u8]) -> Result<Request, String> {
+    let mut r = Reader {
+        buf: payload,
+        pos: 0,
+        big_endian: header.flags & FLAG_NETWORK_BYTE_ORDER != 0,
+    };
+
+    let Some(pdu_type) = PduType::from_u8(header.pdu_type) else {
+        return Ok(Request::Unsupported(header.pdu_type));
+    };
+
+    if header.flags & FLAG_NON_DEFAULT_CONTEXT != 0
+        && matches!(pdu_type, PduType::Get | PduType::GetNext | PduType::GetBulk)
+    {
+        r.octet_string()?;
+    }
+
+    Ok(match pdu_type {
+        PduType::Get => Request::Get(r.search_ranges()?),
+        PduType::GetNext => Request::GetNext(r.search_ranges()?),
+        PduType::GetBulk => {
+            let non_repeaters = r.u16()?;
+            let max_repetitions = r.u16()?;
+            Request::GetBulk {
+                non_repeaters,
+                max_repetitions,
+                ranges: r.search_ranges()?,
+            }
+        }
+        PduType::TestSet => Request::TestSet,
+        PduType::CommitSet | PduType::UndoSet | PduType::CleanupSet => Request::OtherSet,
+        PduType::Response => {
+            r.u32()?; // sysUpTime
+            Request::Response { error: r.u16()? }
+        }
+        PduType::Close => Request::Close,
+        other => Request::Unsupported(other as u8),
+    })
+}
+
+/// Builds a PDU in network byte order
+pub struct PduBuilder {
+    header: Header,
+    payload: Vec<u8>,
+}
+
+impl PduBuilder {
+    /// Start a PDU of the given type
+    pub fn new(pdu_type: PduType, session_id: u32, transaction_id: u32, packet_id: u32) -> Self {
+        Self {
+            header: Header {
+                pdu_type: pdu_type as u8,
+                flags: FLAG_NETWORK_BYTE_ORDER,
+                session_id,
+                transaction_id,
+                packet_id,
+                payload_length: 0,
+            },
+            payload: Vec::new(),
+        }
+    }
+
+    /// Start a response to a request
+    pub fn response(request: &Header, error: u16, index: u16) -> Self {
+        let mut pdu = Self::new(
+            PduType::Response,
+            request.session_id,
+            request.transaction_id,
+            request.packet_id,
+        );
+        pdu.u32(0); // sysUpTime
+        pdu.u16(error);
+        pdu.u16(index);
+        pdu
+    }
+
+    pub fn u8(&mut self, value: u8) -> &mut Self {
+        self.payload.push(value);
+        self
+    }
+
+    pub fn u16(&mut self, value: u16) -> &mut Self {
+        self.payload.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn u32(&mut self, value: u32) -> &mut Self {
+        self.payload.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    /// Append an OID, compressing the `internet` prefix where possible
+    pub fn oid(&mut self, oid: &Oid, include: bool) -> &mut Self {
+        let subids = &oid.0;
+        let (prefix, rest) = if subids.len() > 4
+            && subids[..4] == INTERNET_PREFIX
+            && subids[4] > 0
+            && subids[4] < 256
+        {
+            (subids[4] as u8, &subids[5..])
+        } else {
+            (0, &subids[..])
+        };
+        self.u8(rest.len() as u8);
+        self.u8(prefix);
+        self.u8(include as u8);
+        self.u8(0);
+        for subid in rest {
+            self.u32(*subid);
+        }
+        self
+    }
+
+    pub fn octet_string(&mut self, data: &[u8]) -> &mut Self {
+        self.u32(data.len() as u32);
+        self.payload.extend_from_slice(data);
+        let padding = (4 - data.len() % 4) % 4;
+        self.payload.extend(std::iter::repeat_n(0, padding));
+        self
+    }
+
+    /// Append a variable binding
+    pub fn varbind(&mut self, name: &Oid, value: &VarValue) -> &mut Self {
+        self.u16(value.type_code());
+        self.u16(0);
+        self.oid(name, false);
+        match value {
+            VarValue::Integer(v) => {
+                self.u32(*v as u32);
+            }
+            VarValue::Counter32(v) | VarValue::Gauge32(v) | VarValue::TimeTicks(v) => {
+                self.u32(*v);
+            }
+            VarValue::Counter64(v) => {
+                self.payload.extend_from_slice(&v.to_be_bytes());
+            }
+            VarValue::OctetString(data) => {
+                self.octet_string(data);
+            }
+            VarValue::Null
+            | VarValue::NoSuchObject
+            | VarValue::NoSuchInstance
+            | VarValue::EndOfMibView => {}
+        }
+        self
+    }
+
+    /// Serialize header and payload
+    pub fn build(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_SIZE + self.payload.len());
+        out.push(AGENTX_VERSION);
+        out.push(self.header.pdu_type);
+        out.push(self.header.flags);
+        out.push(0);
+        out.extend_from_slice(&self.header.session_id.to_be_bytes());
+        out.extend_from_slice(&self.header.transaction_id.to_be_bytes());
+        out.extend_from_slice(&self.header.packet_id.to_be_bytes());
+        out.extend_from_slice(&(self.payload.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.payload);
+        out
+    }
+}
+
+/// Open PDU establishing a session
+pub fn open(packet_id: u32, timeout_s: u8, description: &str) -> Vec<u8> {
+    let mut pdu = PduBuilder::new(PduType::Open, 0, 0, packet_id);
+    pdu.u8(timeout_s).u8(0).u8(0).u8(0);
+    pdu.oid(&Oid::default(), false);
+    pdu.octet_string(description.as_bytes());
+    pdu.build()
+}
+
+/// Register PDU claiming a subtree
+pub fn register(session_id: u32, packet_id: u32, subtree: &Oid) -> Vec<u8> {
+    let mut pdu = PduBuilder::new(PduType::Register, session_id, 0, packet_id);
+    pdu.u8(0).u8(127).u8(0).u8(0); // default timeout, default priority, no range
+    pdu.oid(subtree, false);
+    pdu.build()
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], String> {
+        let end = self.pos + len;
+        let bytes = self
+            .buf
+            .get(self.pos..end)
+            .ok_or_else(|| "truncated PDU".to_string())?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.take(2)?;
+        let bytes = [b[0], b[1]];
+        Ok(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.take(4)?;
+        let bytes = [b[0], b[1], b[2], b[3]];
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn oid(&mut self) -> Result<(Oid, bool), String> {
+        let n_subid = self.u8()? as usize;
+        let prefix = self.u8()?;
+        let include = self.u8()? != 0;
+        self.u8()?;
+
+        let mut subids = Vec::with_capacity(n_subid + 5);
+        if prefix != 0 {
+            subids.extend_from_slice(&INTERNET_PREFIX);
+            subids.push(prefix as u32);
+        }
+        for _ in 0..n_subid {
+            subids.push(self.u32()?);
+        }
+        Ok((Oid(subids), include))
+    }
+
+    fn octet_string(&mut self) -> Result<Vec<u8>, String> {
+        let len = self.u32()? as usize;
+        let data = self.take(len)?.to_vec();
+        self.take((4 - len % 4) % 4)?;
+        Ok(data)
+    }
+
+    fn search_ranges(&mut self) -> Result<Vec<SearchRange>, String> {
+        let mut ranges = Vec::new();
+        while self.pos < self.buf.len() {
+            let (start, include) = self.oid()?;
+            let (end, _) = self.oid()?;
+            ranges.push(SearchRange {
+                start,
+                include,
+                end,
+            });
+        }
+        Ok(ranges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_for(bytes: &[u8]) -> Header {
+        let mut fixed = [0u8; HEADER_SIZE];
+        fixed.copy_from_slice(&bytes[..HEADER_SIZE]);
+        Header::decode(&fixed).unwrap()
+    }
+
+    #[test]
+    fn test_oid_parse_and_display() {
+        let oid: Oid = ".1.3.6.1.4.1.2021".parse().unwrap();
+        assert_eq!(oid.0, vec![1, 3, 6, 1, 4, 1, 2021]);
+        assert_eq!(oid.to_string(), "1.3.6.1.4.1.2021");
+        assert!("1.3.x".parse::<Oid>().is_err());
+    }
+
+    #[test]
+    fn test_oid_prefix_compression_round_trip() {
+        let oid: Oid = "1.3.6.1.4.1.2021.10".parse().unwrap();
+        let mut pdu = PduBuilder::new(PduType::GetNext, 1, 2, 3);
+        pdu.oid(&oid, true).oid(&Oid::default(), false);
+        let bytes = pdu.build();
+
+        // Prefix byte 4 replaces 1.3.6.1.4
+        assert_eq!(&bytes[HEADER_SIZE..HEADER_SIZE + 4], &[3, 4, 1, 0]);
+
+        let header = header_for(&bytes);
+        assert_eq!(header.payload_length as usize, bytes.len() - HEADER_SIZE);
+        let request = decode_request(&header, &bytes[HEADER_SIZE..]).unwrap();
+        assert_eq!(
+            request,
+            Request::GetNext(vec![SearchRange {
+                start: oid,
+                include: true,
+                end: Oid::default(),
+            }])
+        );
+    }
+
+    #[test]
+    fn test_little_endian_request() {
+        // GetBulk in little-endian byte order, non_repeaters=0, max_repetitions=10
+        let mut bytes = vec![1, PduType::GetBulk as u8, 0, 0];
+        bytes.extend_from_slice(&7u32.to_le_bytes());
+        bytes.extend_from_slice(&8u32.to_le_bytes());
+        bytes.extend_from_slice(&9u32.to_le_bytes());
+        let payload: Vec<u8> = [
+            &0u16.to_le_bytes()[..],
+            &10u16.to_le_bytes()[..],
+            &[1, 4, 0, 0],
+            &1u32.to_le_bytes()[..],
+            &[0, 0, 0, 0],
+        ]
+        .concat();
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+
+        let header = header_for(&bytes);
+        assert_eq!(header.session_id, 7);
+        match decode_request(&header, &bytes[HEADER_SIZE..]).unwrap() {
+            Request::GetBulk {
+                max_repetitions,
+                ranges,
+                ..
+            } => {
+                assert_eq!(max_repetitions, 10);
+                assert_eq!(ranges[0].start.0, vec![1, 3, 6, 1, 4, 1]);
+            }
+            other => panic!("unexpected request {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_response_varbinds() {
+        let request = Header {
+            pdu_type: PduType::Get as u8,
+            session_id: 1,
+            transaction_id: 2,
+            packet_id: 3,
+            ..Default::default()
+        };
+        let mut pdu = PduBuilder::response(&request, ERROR_NONE, 0);
+        pdu.varbind(
+            &"1.3.6.1.4.1.1.1".parse().unwrap(),
+            &VarValue::OctetString(b"abcde".to_vec()),
+        );
+        let bytes = pdu.build();
+        let header = header_for(&bytes);
+        assert_eq!(header.pdu_type, PduType::Response as u8);
+        assert_eq!(header.packet_id, 3);
+        // 8 response fields + 4 varbind type + 4+12 OID + 4+8 padded string
+        assert_eq!(header.payload_length, 40);
+    }
+}
@@ -0,0 +1,143 @@
+//! Input overcurrent and current spike detection
+
+use std::time::Instant;
+
+use halpi_common::config::InputCurrentConfig;
+use tokio::time::Duration;
+
+/// Weight of a new reading in the running average of the input current
+const AVERAGE_SMOOTHING: f64 = 0.05;
+
+/// Shortest time between two spike events
+const SPIKE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A change in the input current condition
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CurrentEvent {
+    /// The current stayed above the limit for the sustain time
+    Overcurrent,
+    /// The current stayed below the limit for the sustain time
+    Normal,
+    /// A reading this far (A) above the running average
+    Spike(f64),
+}
+
+/// Tracks the input current against the configured limits
+#[derive(Debug, Default)]
+pub struct CurrentMonitor {
+    /// Running average of the input current in A
+    average: Option<f64>,
+    /// Whether the current is in overcurrent
+    overcurrent: bool,
+    /// Since when the current has been on the other side of the limit
+    crossed_at: Option<Instant>,
+    /// When the last spike event was raised
+    spike_at: Option<Instant>,
+}
+
+impl CurrentMonitor {
+    /// Whether the current is in overcurrent
+    pub fn overcurrent(&self) -> bool {
+        self.overcurrent
+    }
+
+    /// Record a reading of `current` (A) taken at `now`
+    ///
+    /// Returns the event the reading completes, if any. A spike is reported
+    /// at most once per minute, and not while in overcurrent.
+    pub fn update(
+        &mut self,
+        config: &InputCurrentConfig,
+        current: f64,
+        now: Instant,
+    ) -> Option<CurrentEvent> {
+        let average = *self.average.get_or_insert(current);
+        self.average = Some(average + AVERAGE_SMOOTHING * (current - average));
+
+        let crossed = (current > config.limit) != self.overcurrent;
+        if !crossed {
+            self.crossed_at = None;
+        } else {
+            let since = *self.crossed_at.get_or_insert(now);
+            if now.duration_since(since).as_secs_f64() >= config.sustain {
+                self.crossed_at = None;
+                self.overcurrent = !self.overcurrent;
+                return Some(if self.overcurrent {
+                    CurrentEvent::Overcurrent
+                } else {
+                    CurrentEvent::Normal
+                });
+            }
+        }
+
+        let rise = current - average;
+        let spike_due = self
+            .spike_at
+            .is_none_or(|at| now.duration_since(at) >= SPIKE_INTERVAL);
+        if config.spike > 0.0 && rise > config.spike && !self.overcurrent && spike_due {
+            self.spike_at = Some(now);
+            return Some(CurrentEvent::Spike(rise));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overcurrent() {
+        let config = InputCurrentConfig {
+            enabled: true,
+            spike: 0.0,
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let at = |seconds: f64| start + Duration::from_secs_f64(seconds);
+        let mut monitor = CurrentMonitor::default();
+
+        assert_eq!(monitor.update(&config, 1.0, at(0.0)), None);
+        assert_eq!(monitor.update(&config, 5.0, at(1.0)), None);
+        // A dip below the limit restarts the sustain time
+        assert_eq!(monitor.update(&config, 3.0, at(3.0)), None);
+        assert_eq!(monitor.update(&config, 5.0, at(4.0)), None);
+        assert_eq!(monitor.update(&config, 5.0, at(8.0)), None);
+        assert_eq!(
+            monitor.update(&config, 5.0, at(9.0)),
+            Some(CurrentEvent::Overcurrent)
+        );
+        assert!(monitor.overcurrent());
+
+        assert_eq!(monitor.update(&config, 2.0, at(10.0)), None);
+        assert_eq!(
+            monitor.update(&config, 2.0, at(15.0)),
+            Some(CurrentEvent::Normal)
+        );
+        assert!(!monitor.overcurrent());
+    }
+
+    #[test]
+    fn test_spike() {
+        let config = InputCurrentConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let at = |seconds: u64| start + Duration::from_secs(seconds);
+        let mut monitor = CurrentMonitor::default();
+
+        assert_eq!(monitor.update(&config, 1.0, at(0)), None);
+        assert_eq!(monitor.update(&config, 2.5, at(1)), None);
+        let Some(CurrentEvent::Spike(rise)) = monitor.update(&config, 3.5, at(2)) else {
+            panic!("expected a spike");
+        };
+        assert!((rise - 2.425).abs() < 1e-9);
+        // Rate limited
+        assert_eq!(monitor.update(&config, 3.5, at(3)), None);
+        assert!(matches!(
+            monitor.update(&config, 3.9, at(63)),
+            Some(CurrentEvent::Spike(_))
+        ));
+    }
+}
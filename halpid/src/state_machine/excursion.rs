@@ -0,0 +1,138 @@
+//! Input voltage sag and surge detection
+
+use chrono::{DateTime, Utc};
+use halpi_common::config::VoltageEventsConfig;
+use halpi_common::events::{ExcursionKind, VoltageExcursion};
+use halpi_common::units::Volts;
+
+/// An excursion in progress
+#[derive(Debug)]
+struct Ongoing {
+    kind: ExcursionKind,
+    start: DateTime<Utc>,
+    /// Lowest voltage of a sag or highest of a surge so far
+    extreme: f32,
+}
+
+/// Tracks the input voltage against the sag and surge limits
+#[derive(Debug, Default)]
+pub struct ExcursionMonitor {
+    ongoing: Option<Ongoing>,
+}
+
+impl ExcursionMonitor {
+    /// Record a reading of `voltage` taken at `at`
+    ///
+    /// Returns the excursion the reading ends, if it lasted at least
+    /// `min-duration`. A reading beyond the opposite limit ends the
+    /// excursion and starts a new one.
+    pub fn update(
+        &mut self,
+        config: &VoltageEventsConfig,
+        voltage: Volts,
+        at: DateTime<Utc>,
+    ) -> Option<VoltageExcursion> {
+        let kind = if (voltage.0 as f64) < config.sag {
+            Some(ExcursionKind::Sag)
+        } else if (voltage.0 as f64) > config.surge {
+            Some(ExcursionKind::Surge)
+        } else {
+            None
+        };
+
+        if let Some(ongoing) = &mut self.ongoing
+            && Some(ongoing.kind) == kind
+        {
+            ongoing.extreme = match ongoing.kind {
+                ExcursionKind::Sag => ongoing.extreme.min(voltage.0),
+                ExcursionKind::Surge => ongoing.extreme.max(voltage.0),
+            };
+            return None;
+        }
+
+        let ended = self.ongoing.take();
+        self.ongoing = kind.map(|kind| Ongoing {
+            kind,
+            start: at,
+            extreme: voltage.0,
+        });
+        let ended = ended?;
+        let duration = (at - ended.start).as_seconds_f64();
+        if duration < config.min_duration {
+            return None;
+        }
+        let depth = match ended.kind {
+            ExcursionKind::Sag => config.sag as f32 - ended.extreme,
+            ExcursionKind::Surge => ended.extreme - config.surge as f32,
+        };
+        Some(VoltageExcursion {
+            timestamp: ended.start,
+            kind: ended.kind,
+            extreme: Volts(ended.extreme),
+            depth: Volts(depth),
+            duration,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(min_duration: f64) -> VoltageEventsConfig {
+        VoltageEventsConfig {
+            enabled: true,
+            sag: 11.0,
+            surge: 15.0,
+            min_duration,
+        }
+    }
+
+    #[test]
+    fn test_sag() {
+        let config = config(0.0);
+        let start = Utc::now();
+        let at = |ms: i64| start + chrono::Duration::milliseconds(ms);
+        let mut monitor = ExcursionMonitor::default();
+
+        assert_eq!(monitor.update(&config, Volts(12.0), at(0)), None);
+        assert_eq!(monitor.update(&config, Volts(10.5), at(100)), None);
+        assert_eq!(monitor.update(&config, Volts(10.0), at(200)), None);
+        assert_eq!(monitor.update(&config, Volts(10.8), at(300)), None);
+        let sag = monitor.update(&config, Volts(12.0), at(600)).unwrap();
+        assert_eq!(sag.kind, ExcursionKind::Sag);
+        assert_eq!(sag.timestamp, at(100));
+        assert_eq!(sag.extreme, Volts(10.0));
+        assert!((sag.depth.0 - 1.0).abs() < 1e-6);
+        assert!((sag.duration - 0.5).abs() < 1e-9);
+        assert_eq!(monitor.update(&config, Volts(12.0), at(700)), None);
+    }
+
+    #[test]
+    fn test_surge_after_sag() {
+        let config = config(0.0);
+        let start = Utc::now();
+        let at = |ms: i64| start + chrono::Duration::milliseconds(ms);
+        let mut monitor = ExcursionMonitor::default();
+
+        assert_eq!(monitor.update(&config, Volts(10.0), at(0)), None);
+        let sag = monitor.update(&config, Volts(16.0), at(100)).unwrap();
+        assert_eq!(sag.kind, ExcursionKind::Sag);
+        let surge = monitor.update(&config, Volts(14.0), at(300)).unwrap();
+        assert_eq!(surge.kind, ExcursionKind::Surge);
+        assert_eq!(surge.extreme, Volts(16.0));
+    }
+
+    #[test]
+    fn test_min_duration() {
+        let config = config(1.0);
+        let start = Utc::now();
+        let at = |ms: i64| start + chrono::Duration::milliseconds(ms);
+        let mut monitor = ExcursionMonitor::default();
+
+        assert_eq!(monitor.update(&config, Volts(10.0), at(0)), None);
+        assert_eq!(monitor.update(&config, Volts(12.0), at(500)), None);
+        assert_eq!(monitor.update(&config, Volts(10.0), at(600)), None);
+        assert!(monitor.update(&config, Volts(12.0), at(1600)).is_some());
+    }
+}
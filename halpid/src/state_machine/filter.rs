@@ -0,0 +1,110 @@
+//! Glitch rejection and smoothing of measurements
+
+use halpi_common::config::{ChannelFilter, MeasurementFilterConfig};
+use halpi_common::types::Measurements;
+use halpi_common::units::{Amps, Kelvin, Volts};
+
+/// Filter state of one channel
+#[derive(Debug, Default)]
+struct ChannelState {
+    /// The two readings before the current one, oldest first
+    previous: [Option<f32>; 2],
+    /// Exponential moving average of the glitch-filtered readings
+    average: Option<f32>,
+}
+
+impl ChannelState {
+    /// Record a reading and return the filtered value
+    fn update(&mut self, config: &ChannelFilter, reading: f32) -> f32 {
+        let value = match self.previous {
+            [Some(a), Some(b)] if config.reject_glitches => median(a, b, reading),
+            _ => reading,
+        };
+        self.previous = [self.previous[1], Some(reading)];
+
+        let smoothing = config.smoothing as f32;
+        let average = match self.average {
+            Some(average) if smoothing > 0.0 => smoothing * average + (1.0 - smoothing) * value,
+            _ => value,
+        };
+        self.average = Some(average);
+        average
+    }
+}
+
+fn median(a: f32, b: f32, c: f32) -> f32 {
+    a.max(b).min(a.min(b).max(c))
+}
+
+/// Filters the measurements of the state machine, channel by channel
+#[derive(Debug, Default)]
+pub struct MeasurementFilter {
+    v_in: ChannelState,
+    v_supercap: ChannelState,
+    i_in: ChannelState,
+    t_mcu: ChannelState,
+    t_pcb: ChannelState,
+}
+
+impl MeasurementFilter {
+    /// Record a sample and replace its values by the filtered ones
+    pub fn apply(&mut self, config: &MeasurementFilterConfig, measurements: &mut Measurements) {
+        measurements.dcin_voltage =
+            Volts(self.v_in.update(&config.v_in, measurements.dcin_voltage.0));
+        measurements.supercap_voltage = Volts(
+            self.v_supercap
+                .update(&config.v_supercap, measurements.supercap_voltage.0),
+        );
+        measurements.input_current =
+            Amps(self.i_in.update(&config.i_in, measurements.input_current.0));
+        measurements.mcu_temperature = Kelvin(
+            self.t_mcu
+                .update(&config.t_mcu, measurements.mcu_temperature.0),
+        );
+        measurements.pcb_temperature = Kelvin(
+            self.t_pcb
+                .update(&config.t_pcb, measurements.pcb_temperature.0),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(reject_glitches: bool, smoothing: f64) -> ChannelFilter {
+        ChannelFilter {
+            reject_glitches,
+            smoothing,
+        }
+    }
+
+    #[test]
+    fn test_reject_glitches() {
+        let config = filter(true, 0.0);
+        let mut state = ChannelState::default();
+        assert_eq!(state.update(&config, 12.0), 12.0);
+        assert_eq!(state.update(&config, 12.1), 12.1);
+        // A single low reading is dropped
+        assert_eq!(state.update(&config, 0.0), 12.0);
+        assert_eq!(state.update(&config, 12.0), 12.0);
+        assert_eq!(state.update(&config, 12.0), 12.0);
+        // A real drop follows one reading later
+        assert_eq!(state.update(&config, 8.0), 12.0);
+        assert_eq!(state.update(&config, 8.0), 8.0);
+    }
+
+    #[test]
+    fn test_smoothing() {
+        let config = filter(false, 0.5);
+        let mut state = ChannelState::default();
+        assert_eq!(state.update(&config, 12.0), 12.0);
+        assert_eq!(state.update(&config, 8.0), 10.0);
+        assert_eq!(state.update(&config, 8.0), 9.0);
+
+        // Unfiltered channels pass readings through
+        let mut state = ChannelState::default();
+        assert_eq!(state.update(&ChannelFilter::default(), 12.0), 12.0);
+        assert_eq!(state.update(&ChannelFilter::default(), 0.0), 0.0);
+    }
+}
@@ -1,34 +1,51 @@
 //! State machine implementation for power management
 
+use serde::Serialize;
+use std::os::fd::OwnedFd;
 use std::sync::Arc;
 use std::time::Instant;
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
 use tokio::time::{Duration, interval};
 use tracing::{error, info, warn};
 
-use halpi_common::config::Config;
+use halpi_common::config::{BlackoutAction, Config, HookEvent};
+use halpi_common::events::{Alert, AlertKind, DaemonEvent, Sample};
+use halpi_common::protocol::Capabilities;
+use halpi_common::types::{Measurements, PowerState};
+use halpi_common::units::Volts;
 
-use crate::i2c::HalpiDevice;
+use super::StatusHandle;
+use super::current::{CurrentEvent, CurrentMonitor};
+use super::excursion::ExcursionMonitor;
+use super::filter::MeasurementFilter;
+use super::schedule::PowerSchedule;
+use super::shedding::{ShedCause, ShedPorts};
+use super::thermal::ThermalLevel;
+use crate::daemon::kernel_watchdog::KernelWatchdog;
+use crate::daemon::{clock, notify, power, safety};
+use crate::estimate::DischargeEstimator;
+use crate::events::EventBus;
+use crate::hooks;
+use crate::i2c::{DeviceHandle, I2cError};
+use crate::logind;
+use crate::selftest;
 
-/// Watchdog timeout in milliseconds (10 seconds)
-///
-/// This timeout must be longer than the state machine polling interval (100ms).
-/// The firmware automatically feeds the watchdog on ANY I2C operation, so our
-/// regular polling (get_measurements every 100ms) keeps it alive.
-///
-/// If the timeout expires (firmware cannot communicate with daemon), the system
-/// will be hard power-cycled as an emergency recovery mechanism.
-const WATCHDOG_TIMEOUT_MS: u16 = 10000;
+/// Supercap power-off threshold assumed if the controller does not report it
+const FALLBACK_POWER_OFF_THRESHOLD: Volts = Volts(6.0);
 
-/// State machine polling interval in milliseconds (100ms)
+/// How often the supercap runtime estimate is updated during a blackout
 ///
-/// CRITICAL: This 0.1 second interval is essential for responsive power management.
-/// The tight polling loop ensures quick detection of blackout events. Each poll reads
-/// I2C registers, which automatically feeds the watchdog in the firmware.
-const STATE_MACHINE_POLL_INTERVAL_MS: u64 = 100;
+/// Longer than the polling interval so that single voltage readings do not
+/// dominate the discharge rate.
+const ESTIMATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Message broadcast to logged-in users when a shutdown is cancelled
+const CANCEL_MESSAGE: &str = "halpid: shutdown cancelled.";
 
 /// Daemon state machine states
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum DaemonState {
     /// Initial state - initializing watchdog
     Start,
@@ -42,25 +59,127 @@ pub enum DaemonState {
     Dead,
 }
 
+/// Why the state machine is shutting down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShutdownReason {
+    /// The blackout time limit was exceeded or the supercap is running out
+    Blackout,
+    /// The controller temperature exceeded the shutdown limit
+    OverTemperature,
+    /// A shutdown was scheduled through the API
+    Scheduled,
+    /// A power schedule window started; standby until the given time
+    PowerSchedule(chrono::DateTime<chrono::Utc>),
+}
+
 /// Power management state machine
 pub struct StateMachine {
     state: DaemonState,
-    device: Arc<Mutex<HalpiDevice>>,
+    device: DeviceHandle,
     config: Arc<RwLock<Config>>,
+    events: EventBus,
+    status: StatusHandle,
+    /// Glitch rejection and smoothing of the samples
+    filter: MeasurementFilter,
     blackout_start: Option<Instant>,
+    /// Whole seconds of blackout last reported to systemd
+    blackout_reported: Option<u64>,
+    /// Whether the shutdown skipped in maintenance mode was logged
+    maintenance_logged: bool,
+    power_state: Option<PowerState>,
+    /// systemd watchdog timeout, if the service has one
+    watchdog_timeout: Option<Duration>,
+    /// When the systemd watchdog was last fed
+    watchdog_fed: Option<Instant>,
+    /// Linux watchdog device, if enabled
+    kernel_watchdog: Option<KernelWatchdog>,
+    /// The pre-shutdown hook, which the poweroff command waits for
+    pre_shutdown: Option<JoinHandle<()>>,
+    /// End of the grace period or abort window before the blackout action
+    grace_until: Option<Instant>,
+    /// Supercap voltage below which the shutdown stops waiting
+    supercap_floor: Volts,
+    /// Controller's supercap power-off threshold, read when a blackout starts
+    power_off_threshold: Volts,
+    /// Supercap runtime estimate of the current blackout
+    estimator: DischargeEstimator,
+    /// When the runtime estimate was last updated
+    estimated_at: Option<Instant>,
+    /// logind delay inhibitor lock, held until the blackout action
+    inhibitor: Option<OwnedFd>,
+    /// Why the current shutdown was started
+    shutdown_reason: ShutdownReason,
+    /// Current over-temperature protection level
+    thermal: ThermalLevel,
+    /// Input overcurrent and spike detection
+    current: CurrentMonitor,
+    /// Input voltage sag and surge detection
+    excursions: ExcursionMonitor,
+    /// USB ports turned off to shed load, to be turned on again
+    shed_ports: ShedPorts,
+    /// Recurring standby windows
+    power_schedule: PowerSchedule,
+    /// The controller is simulated or replayed: host power actions are
+    /// only logged
+    offline: bool,
 }
 
 impl StateMachine {
     /// Create a new state machine
-    pub fn new(device: Arc<Mutex<HalpiDevice>>, config: Arc<RwLock<Config>>) -> Self {
+    ///
+    /// Every measurement the state machine takes is published on `events`,
+    /// and every state transition is recorded in `status`.
+    pub fn new(
+        device: DeviceHandle,
+        config: Arc<RwLock<Config>>,
+        events: EventBus,
+        status: StatusHandle,
+    ) -> Self {
+        status.set(DaemonState::Start);
         Self {
             state: DaemonState::Start,
             device,
             config,
+            events,
+            status,
+            filter: MeasurementFilter::default(),
             blackout_start: None,
+            blackout_reported: None,
+            maintenance_logged: false,
+            power_state: None,
+            watchdog_timeout: notify::watchdog_timeout(),
+            watchdog_fed: None,
+            kernel_watchdog: None,
+            pre_shutdown: None,
+            grace_until: None,
+            supercap_floor: FALLBACK_POWER_OFF_THRESHOLD,
+            power_off_threshold: FALLBACK_POWER_OFF_THRESHOLD,
+            estimator: DischargeEstimator::default(),
+            estimated_at: None,
+            inhibitor: None,
+            shutdown_reason: ShutdownReason::Blackout,
+            thermal: ThermalLevel::Normal,
+            current: CurrentMonitor::default(),
+            excursions: ExcursionMonitor::default(),
+            shed_ports: ShedPorts::default(),
+            power_schedule: PowerSchedule::default(),
+            offline: false,
         }
     }
 
+    /// Pet `watchdog` after every successful iteration
+    pub fn with_kernel_watchdog(mut self, watchdog: Option<KernelWatchdog>) -> Self {
+        self.kernel_watchdog = watchdog;
+        self
+    }
+
+    /// Only log the host power actions: the wake alarm, hibernation,
+    /// powering off and the blackout command
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
     /// Get current state
     pub fn state(&self) -> DaemonState {
         self.state
@@ -68,51 +187,175 @@ impl StateMachine {
 
     /// Run the state machine loop
     ///
-    /// CRITICAL: Polls every 0.1 seconds (100ms) for responsive power management
+    /// Polls at the configured `poll-interval` (100 ms by default). Each poll
+    /// reads the controller, which also feeds the hardware watchdog, so the
+    /// interval must stay well below `watchdog-timeout`. Both are startup
+    /// settings and are read once here.
     pub async fn run(&mut self) {
         info!("Starting power management state machine");
 
-        // Critical timing: 0.1 second polling interval
-        let mut ticker = interval(Duration::from_millis(STATE_MACHINE_POLL_INTERVAL_MS));
+        let (poll_interval, hardware_watchdog) = {
+            let config = self.config.read().await;
+            (
+                Duration::from_secs_f64(config.poll_interval),
+                Duration::from_millis(config.watchdog_timeout_ms() as u64),
+            )
+        };
+
+        if self.device.is_present() {
+            self.notify_status(&status_text(self.state, 0.0));
+        } else {
+            notify::status("Waiting for HALPI2 controller");
+        }
+        if let Some(timeout) = self.watchdog_timeout {
+            info!("systemd watchdog enabled ({:.1}s)", timeout.as_secs_f64());
+            if timeout >= hardware_watchdog {
+                warn!(
+                    "systemd WatchdogSec ({:.1}s) is not shorter than the hardware watchdog \
+                     ({:.1}s); a hung daemon will be power cycled before systemd restarts it",
+                    timeout.as_secs_f64(),
+                    hardware_watchdog.as_secs_f64()
+                );
+            }
+        }
+
+        let mut ticker = interval(poll_interval);
 
         loop {
             ticker.tick().await;
 
-            if let Err(e) = self.tick().await {
-                error!("State machine error: {}", e);
+            // Nothing to poll until the controller is connected; the
+            // watchdog is initialized in the Start state once it is. Once
+            // access is suspended, the controller watchdog is left to expire.
+            if !self.device.is_present() || self.device.is_suspended() {
+                self.feed_watchdogs();
+                continue;
             }
+
+            match self.tick().await {
+                Ok(()) => self.feed_watchdogs(),
+                Err(e) => self.log_error(&e),
+            }
+        }
+    }
+
+    /// Log a failed state machine iteration
+    fn log_error(&self, e: &anyhow::Error) {
+        match e.downcast_ref::<I2cError>() {
+            Some(i2c) => error!(
+                state = ?self.state,
+                error_kind = i2c.kind(),
+                register = i2c.register().map(|reg| format!("0x{:02X}", reg)),
+                "State machine error: {}",
+                e
+            ),
+            None => error!(state = ?self.state, "State machine error: {}", e),
+        }
+    }
+
+    /// Feed the systemd and kernel watchdogs after a successful iteration
+    ///
+    /// systemd recommends notifying at half the timeout, so the watchdog is
+    /// not fed on every tick. A deadlocked loop stops feeding it and
+    /// systemd restarts the daemon before the hardware watchdog cuts power.
+    fn feed_watchdogs(&mut self) {
+        if let Some(watchdog) = &self.kernel_watchdog {
+            watchdog.pet();
+        }
+        let Some(timeout) = self.watchdog_timeout else {
+            return;
+        };
+        let due = self
+            .watchdog_fed
+            .is_none_or(|fed| fed.elapsed() >= timeout / 2);
+        if due {
+            notify::watchdog();
+            self.watchdog_fed = Some(Instant::now());
         }
     }
 
     /// Execute one state machine iteration
     async fn tick(&mut self) -> anyhow::Result<()> {
-        let config = self.config.read().await;
+        // Hold the guard on a local handle so `self` stays mutably borrowable
+        let config = self.config.clone();
+        let config = config.read().await;
 
         match self.state {
             DaemonState::Start => {
-                info!("Initializing watchdog");
-                let mut device = self.device.lock().await;
-                device.set_watchdog_timeout(WATCHDOG_TIMEOUT_MS)?;
-                drop(device);
+                // Cached by the device, so handlers know the features
+                match self.device.run(|device| device.capabilities()).await {
+                    Ok(capabilities) => log_capabilities(&capabilities),
+                    Err(e) => warn!("Cannot detect the controller hardware: {}", e),
+                }
+
+                // Once per daemon run, not again when retrying the watchdog
+                if config.self_test.enabled
+                    && self.status.self_test().is_none()
+                    && let Ok(min_firmware) = config.self_test.min_firmware()
+                    && let Err(e) = selftest::run(&self.device, &self.status, min_firmware).await
+                {
+                    warn!("Cannot run the controller self-test: {}", e);
+                }
+
+                info!("Initializing watchdog ({:.1}s)", config.watchdog_timeout);
+                let timeout_ms = config.watchdog_timeout_ms();
+                self.device
+                    .run(move |device| device.set_watchdog_timeout(timeout_ms))
+                    .await?;
                 drop(config);
 
                 self.transition_to(DaemonState::Ok);
             }
 
             DaemonState::Ok => {
-                // Read DC input voltage
-                let v_in = {
-                    let mut device = self.device.lock().await;
-                    device.get_measurements()?.dcin_voltage
-                };
+                self.rearm_watchdog_after_panic(config.watchdog_timeout_ms())
+                    .await?;
+
+                let measurements = self.sample(&config).await?;
+                if self.check_temperature(&config, &measurements).await {
+                    self.begin_shutdown(&config, ShutdownReason::OverTemperature)
+                        .await;
+                    drop(config);
+                    self.transition_to(DaemonState::Shutdown);
+                    return Ok(());
+                }
+                self.check_input_current(&config, &measurements).await;
+                if self.check_scheduled_shutdown() {
+                    self.begin_shutdown(&config, ShutdownReason::Scheduled)
+                        .await;
+                    drop(config);
+                    self.transition_to(DaemonState::Shutdown);
+                    return Ok(());
+                }
+                if let Some(wake_at) = self.check_power_schedule(&config) {
+                    self.begin_shutdown(&config, ShutdownReason::PowerSchedule(wake_at))
+                        .await;
+                    drop(config);
+                    self.transition_to(DaemonState::Shutdown);
+                    return Ok(());
+                }
+                let v_in = measurements.dcin_voltage;
+                let limit = Volts(config.blackout_voltage_limit as f32);
 
                 // Check for blackout
-                if v_in < config.blackout_voltage_limit as f32 {
+                if v_in < limit {
                     warn!(
-                        "Detected blackout (V_in = {:.2}V < {:.2}V)",
-                        v_in, config.blackout_voltage_limit
+                        voltage = v_in.0,
+                        "Detected blackout (V_in = {:.2} < {:.2})", v_in, limit
+                    );
+                    self.alert(
+                        AlertKind::BlackoutDetected,
+                        format!("Input voltage {:.2} below blackout limit", v_in),
                     );
                     self.blackout_start = Some(Instant::now());
+                    self.power_off_threshold = self
+                        .device
+                        .run(|device| device.get_solo_power_off_threshold())
+                        .await
+                        .map_or(FALLBACK_POWER_OFF_THRESHOLD, Volts);
+                    self.estimator = DischargeEstimator::default();
+                    self.estimated_at = None;
+                    hooks::run(&config.hooks, HookEvent::BlackoutStart);
                     drop(config);
                     self.transition_to(DaemonState::Blackout);
                 }
@@ -121,49 +364,127 @@ impl StateMachine {
             }
 
             DaemonState::Blackout => {
-                // Read DC input voltage
-                let v_in = {
-                    let mut device = self.device.lock().await;
-                    device.get_measurements()?.dcin_voltage
-                };
-
-                // Check for power restoration
-                if v_in > config.blackout_voltage_limit as f32 {
-                    info!("Power resumed (V_in = {:.2}V)", v_in);
+                self.rearm_watchdog_after_panic(config.watchdog_timeout_ms())
+                    .await?;
+
+                let measurements = self.sample(&config).await?;
+                if self.check_temperature(&config, &measurements).await {
+                    self.begin_shutdown(&config, ShutdownReason::OverTemperature)
+                        .await;
+                    drop(config);
+                    self.transition_to(DaemonState::Shutdown);
+                    return Ok(());
+                }
+                self.check_input_current(&config, &measurements).await;
+                if self.check_scheduled_shutdown() {
+                    self.begin_shutdown(&config, ShutdownReason::Scheduled)
+                        .await;
+                    drop(config);
+                    self.transition_to(DaemonState::Shutdown);
+                    return Ok(());
+                }
+                if let Some(wake_at) = self.check_power_schedule(&config) {
+                    self.begin_shutdown(&config, ShutdownReason::PowerSchedule(wake_at))
+                        .await;
+                    drop(config);
+                    self.transition_to(DaemonState::Shutdown);
+                    return Ok(());
+                }
+                let v_in = measurements.dcin_voltage;
+
+                // Check for power restoration, above the hysteresis band so a
+                // supply hovering at the limit does not flap
+                if v_in > Volts(config.blackout_recovery_voltage() as f32) {
+                    info!(voltage = v_in.0, "Power resumed (V_in = {:.2})", v_in);
+                    self.alert(
+                        AlertKind::PowerRestored,
+                        format!("Input voltage restored to {:.2}", v_in),
+                    );
                     self.blackout_start = None;
+                    hooks::run(&config.hooks, HookEvent::PowerRestored);
                     drop(config);
                     self.transition_to(DaemonState::Ok);
                 } else if let Some(start) = self.blackout_start {
-                    // Check timeout
+                    // Check timeout and the remaining supercap runtime
                     let elapsed = start.elapsed().as_secs_f64();
-                    if elapsed > config.blackout_time_limit {
-                        warn!("Blacked out for {:.1}s, initiating shutdown", elapsed);
-                        drop(config);
-                        self.transition_to(DaemonState::Shutdown);
-                        return Ok(());
+                    let runtime = self.estimate_runtime(elapsed, measurements.supercap_voltage);
+                    let runtime_low = config.runtime_shutdown
+                        && config.shutdown_duration > 0.0
+                        && runtime.is_some_and(|runtime| runtime < config.shutdown_duration);
+                    if elapsed > config.blackout_time_limit || runtime_low {
+                        if let Some(until) = self.status.maintenance_until() {
+                            // Keep monitoring, but leave the power to the user
+                            if !self.maintenance_logged {
+                                warn!(
+                                    "Blacked out for {:.1}s, not shutting down in maintenance mode (until {})",
+                                    elapsed,
+                                    until.to_rfc3339()
+                                );
+                                self.maintenance_logged = true;
+                            }
+                        } else {
+                            let reason = match runtime {
+                                Some(runtime) if runtime_low => {
+                                    format!("Supercap runtime estimated at {:.0} s", runtime)
+                                }
+                                _ => format!("Blacked out for {:.1} s", elapsed),
+                            };
+                            warn!("{}, initiating shutdown", reason);
+                            self.alert(
+                                AlertKind::ShutdownInitiated,
+                                format!("{}, shutting down", reason),
+                            );
+                            self.begin_shutdown(&config, ShutdownReason::Blackout).await;
+                            drop(config);
+                            self.transition_to(DaemonState::Shutdown);
+                            return Ok(());
+                        }
                     }
+                    self.report_blackout(elapsed);
                 }
 
                 // Note: Watchdog is automatically fed by the get_measurements() call above
             }
 
             DaemonState::Shutdown => {
-                // Notify device of shutdown
-                let mut device = self.device.lock().await;
-                device.request_shutdown()?;
-                drop(device);
-
-                // Execute poweroff command
-                if !config.poweroff.is_empty() {
-                    info!("Executing: {}", config.poweroff);
-                    // Use shell to execute the command, matching Python implementation behavior
-                    std::process::Command::new("sh")
-                        .arg("-c")
-                        .arg(&config.poweroff)
-                        .spawn()?;
-                } else {
-                    warn!("Dry-run mode: poweroff command is empty");
+                // Keep polling while the pre-shutdown hook runs or the grace
+                // period lasts; the reads feed the hardware watchdog
+                if self.shutdown_pending() {
+                    let measurements = self.sample(&config).await?;
+                    let v_in = measurements.dcin_voltage;
+                    let blackout = self.shutdown_reason == ShutdownReason::Blackout;
+                    let recovery = Volts(config.blackout_recovery_voltage() as f32);
+                    let cancel = if blackout && v_in > recovery {
+                        info!(voltage = v_in.0, "Power resumed (V_in = {:.2})", v_in);
+                        hooks::run(&config.hooks, HookEvent::PowerRestored);
+                        Some(format!("input voltage restored to {:.2}", v_in))
+                    } else if self.status.take_cancel() {
+                        Some("cancelled through the API".to_string())
+                    } else if blackout && self.status.maintenance_until().is_some() {
+                        Some("maintenance mode enabled".to_string())
+                    } else {
+                        None
+                    };
+                    if let Some(reason) = cancel {
+                        self.cancel_shutdown(&config, &reason);
+                        drop(config);
+                        self.transition_to(DaemonState::Ok);
+                        return Ok(());
+                    }
+
+                    let supercap = measurements.supercap_voltage;
+                    if supercap > self.supercap_floor {
+                        return Ok(());
+                    }
+                    warn!(
+                        voltage = supercap.0,
+                        "Supercap at {:.2}, not waiting any longer", supercap
+                    );
                 }
+                self.pre_shutdown = None;
+                self.grace_until = None;
+
+                self.blackout_action(&config).await?;
                 drop(config);
 
                 self.transition_to(DaemonState::Dead);
@@ -178,9 +499,582 @@ impl StateMachine {
         Ok(())
     }
 
+    /// Start the pre-shutdown hook and the grace period
+    ///
+    /// Both end early once the supercap voltage falls within the grace
+    /// `supercap-margin` of the controller's power-off threshold. With
+    /// `logind`, a delay lock keeps other shutdowns from overtaking them.
+    /// The blackout action waits at least the abort window, in which
+    /// returning power cancels the shutdown.
+    async fn begin_shutdown(&mut self, config: &Config, reason: ShutdownReason) {
+        self.shutdown_reason = reason;
+        // A request made before this shutdown does not cancel it
+        self.status.take_cancel();
+
+        if config.logind && !self.offline {
+            match logind::inhibit_shutdown("Blackout shutdown pending").await {
+                Ok(lock) => self.inhibitor = Some(lock),
+                Err(e) => warn!("Cannot take logind inhibitor lock: {:#}", e),
+            }
+        }
+        self.pre_shutdown = hooks::run(&config.hooks, HookEvent::PreShutdown);
+
+        self.supercap_floor =
+            Volts(self.power_off_threshold.0 + config.shutdown_grace.supercap_margin as f32);
+
+        let grace = &config.shutdown_grace;
+        let wait = grace.period.max(grace.abort_window);
+        if wait > 0.0 {
+            self.grace_until = Some(Instant::now() + Duration::from_secs_f64(wait));
+        }
+        if grace.period > 0.0 {
+            info!("Grace period of {:.0}s before shutdown", grace.period);
+            if grace.wall {
+                power::broadcast(&grace_message(grace.period));
+            }
+        }
+    }
+
+    /// Abandon a pending shutdown before the blackout action
+    ///
+    /// A running pre-shutdown hook is left to finish.
+    fn cancel_shutdown(&mut self, config: &Config, reason: &str) {
+        info!("Shutdown cancelled: {}", reason);
+        self.alert(
+            AlertKind::ShutdownCancelled,
+            format!("Shutdown cancelled: {}", reason),
+        );
+        let grace = &config.shutdown_grace;
+        if grace.period > 0.0 && grace.wall {
+            power::broadcast(CANCEL_MESSAGE);
+        }
+        self.pre_shutdown = None;
+        self.grace_until = None;
+        self.inhibitor = None;
+        self.blackout_start = None;
+    }
+
+    /// Update the supercap runtime estimate at most every [`ESTIMATE_INTERVAL`]
+    ///
+    /// Returns the latest estimate in seconds, once the discharge rate is
+    /// known.
+    fn estimate_runtime(&mut self, elapsed: f64, supercap_voltage: Volts) -> Option<f64> {
+        if self
+            .estimated_at
+            .is_none_or(|at| at.elapsed() >= ESTIMATE_INTERVAL)
+        {
+            self.estimated_at = Some(Instant::now());
+            let runtime =
+                self.estimator
+                    .update(elapsed, supercap_voltage, self.power_off_threshold, true);
+            self.status.set_runtime(runtime);
+        }
+        self.status.runtime()
+    }
+
+    /// True while the pre-shutdown hook runs or the grace period lasts
+    fn shutdown_pending(&self) -> bool {
+        let hook_running = self
+            .pre_shutdown
+            .as_ref()
+            .is_some_and(|hook| !hook.is_finished());
+        let in_grace = self.grace_until.is_some_and(|until| Instant::now() < until);
+        hook_running || in_grace
+    }
+
+    /// Carry out the configured blackout action
+    ///
+    /// Standby falls back to a plain shutdown if the wake alarm cannot be
+    /// set, so the system is never left without a way to power up again.
+    /// Offline, the controller is still told to shut down or stand by, but
+    /// the host is left running.
+    async fn blackout_action(&mut self, config: &Config) -> anyhow::Result<()> {
+        // Standby or hibernation would not let an overheated system cool
+        // down, and a scheduled shutdown is meant to turn the system off
+        let mut action = match self.shutdown_reason {
+            ShutdownReason::Blackout => config.blackout_action,
+            ShutdownReason::OverTemperature | ShutdownReason::Scheduled => BlackoutAction::Poweroff,
+            ShutdownReason::PowerSchedule(_) => BlackoutAction::Standby,
+        };
+        info!("Blackout action: {}", action.name());
+
+        if action == BlackoutAction::Standby {
+            let wake_at = match self.shutdown_reason {
+                ShutdownReason::PowerSchedule(on) => on.timestamp() as u64,
+                _ => chrono::Utc::now().timestamp() as u64 + config.blackout_wake_after as u64,
+            };
+            let alarm = if self.offline {
+                warn!("Dry-run mode: not setting the wake alarm to {}", wake_at);
+                Ok(())
+            } else {
+                power::set_wake_alarm(wake_at).await
+            };
+            match alarm {
+                Ok(()) => {
+                    self.device.run(|device| device.request_standby()).await?;
+                }
+                Err(e) => {
+                    warn!("Cannot set wake alarm, shutting down instead: {}", e);
+                    action = BlackoutAction::Poweroff;
+                }
+            }
+        }
+        if action != BlackoutAction::Standby {
+            // Notify device of shutdown
+            self.device.run(|device| device.request_shutdown()).await?;
+        }
+
+        let command = match action {
+            BlackoutAction::Poweroff | BlackoutAction::Standby => config.poweroff.as_str(),
+            BlackoutAction::Command => config.blackout_command.as_str(),
+            BlackoutAction::Hibernate => "systemctl hibernate",
+        };
+        if self.offline {
+            warn!(
+                "Dry-run mode: not taking the {} action without a real controller",
+                action.name()
+            );
+            return Ok(());
+        }
+        if command.is_empty() {
+            warn!("Dry-run mode: poweroff command is empty");
+            return Ok(());
+        }
+
+        // Our own lock would delay the shutdown we are about to request
+        self.inhibitor = None;
+        let via_logind = match action {
+            BlackoutAction::Hibernate => config.logind,
+            BlackoutAction::Poweroff | BlackoutAction::Standby => config.logind_power_off(),
+            BlackoutAction::Command => false,
+        };
+        if via_logind {
+            let requested = if action == BlackoutAction::Hibernate {
+                logind::hibernate().await
+            } else {
+                logind::power_off().await
+            };
+            match requested {
+                Ok(()) => {
+                    info!("Requested {} from logind", action.name());
+                    return Ok(());
+                }
+                Err(e) => warn!("{:#}, running the command instead", e),
+            }
+        }
+        info!("Executing: {}", command);
+        power::spawn_shell(command)
+    }
+
+    /// Re-enable the hardware watchdog if the panic hook disabled it
+    ///
+    /// A panic in another task does not stop the state machine, so the
+    /// watchdog protection is restored as long as it keeps running.
+    async fn rearm_watchdog_after_panic(&mut self, timeout_ms: u16) -> anyhow::Result<()> {
+        if !safety::take_disarmed() {
+            return Ok(());
+        }
+        warn!("Re-enabling hardware watchdog after panic");
+        let result = self
+            .device
+            .run(move |device| device.set_watchdog_timeout(timeout_ms))
+            .await;
+        if let Err(e) = result {
+            // Try again on the next tick
+            safety::set_disarmed();
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Read measurements and publish them on the event bus
+    ///
+    /// Also publishes a state transition event when the controller power
+    /// state differs from the previous sample, and runs the standby and
+    /// host-unresponsive hooks when the controller enters those states.
+    /// The measurements are corrected by the configured `calibration` and
+    /// passed through the `measurement-filter`. With `voltage-events`, an
+    /// input voltage sag or surge is published once it is over.
+    async fn sample(&mut self, config: &Config) -> anyhow::Result<Measurements> {
+        let mut measurements = self.device.run(|device| device.get_measurements()).await?;
+        config.calibration.apply(&mut measurements);
+        self.filter
+            .apply(&config.measurement_filter, &mut measurements);
+        let hooks = &config.hooks;
+
+        let sample = Sample::new(measurements.clone());
+        if let Some(from) = self.power_state
+            && from != measurements.power_state
+        {
+            self.events.publish(DaemonEvent::StateTransition {
+                timestamp: sample.timestamp,
+                from,
+                to: measurements.power_state,
+            });
+            match measurements.power_state {
+                PowerState::EnteringStandby => {
+                    hooks::run(hooks, HookEvent::StandbyEnter);
+                }
+                PowerState::HostUnresponsive => {
+                    hooks::run(hooks, HookEvent::HostUnresponsive);
+                }
+                _ => {}
+            }
+        }
+        self.power_state = Some(measurements.power_state);
+        self.events.publish(DaemonEvent::Measurement(sample));
+
+        if config.voltage_events.enabled
+            && let Some(excursion) = self.excursions.update(
+                &config.voltage_events,
+                measurements.dcin_voltage,
+                measurements.measured_at,
+            )
+        {
+            info!(
+                voltage = excursion.extreme.0,
+                "Input voltage {} to {:.2} for {:.1}s",
+                excursion.kind.name(),
+                excursion.extreme,
+                excursion.duration
+            );
+            self.events
+                .publish(DaemonEvent::VoltageExcursion(excursion));
+        }
+
+        Ok(measurements)
+    }
+
+    /// Update the over-temperature protection level from `measurements`
+    ///
+    /// Raises alerts and sheds or restores USB ports on level changes.
+    /// Returns true when the temperature calls for a protective shutdown.
+    async fn check_temperature(&mut self, config: &Config, measurements: &Measurements) -> bool {
+        let limits = &config.temperature;
+        let temperature = measurements
+            .mcu_temperature
+            .celsius()
+            .as_f64()
+            .max(measurements.pcb_temperature.celsius().as_f64());
+        let level = if limits.enabled {
+            self.thermal.next(limits, temperature)
+        } else {
+            ThermalLevel::Normal
+        };
+        let previous = std::mem::replace(&mut self.thermal, level);
+        if level == previous {
+            return false;
+        }
+
+        if level > previous {
+            warn!(
+                temperature,
+                "Temperature {:.1}°C, over-temperature level {}",
+                temperature,
+                level.name()
+            );
+        } else {
+            info!(
+                temperature,
+                "Temperature {:.1}°C, over-temperature level {}",
+                temperature,
+                level.name()
+            );
+        }
+        match level {
+            ThermalLevel::Normal => self.alert(
+                AlertKind::TemperatureNormal,
+                format!("Temperature back to {:.1} °C", temperature),
+            ),
+            ThermalLevel::Warning | ThermalLevel::Shed if level > previous => self.alert(
+                AlertKind::OverTemperature,
+                format!(
+                    "Temperature {:.1} °C above the {} limit",
+                    temperature,
+                    level.name()
+                ),
+            ),
+            ThermalLevel::Shutdown => self.alert(
+                AlertKind::OverTemperatureShutdown,
+                format!("Temperature {:.1} °C, shutting down", temperature),
+            ),
+            _ => {}
+        }
+
+        if level >= ThermalLevel::Shed && previous < ThermalLevel::Shed {
+            self.shed_usb_ports(ShedCause::Temperature, limits.shed_mask())
+                .await;
+        } else if level < ThermalLevel::Shed && previous >= ThermalLevel::Shed {
+            self.restore_usb_ports(ShedCause::Temperature).await;
+        }
+        level == ThermalLevel::Shutdown
+    }
+
+    /// Check whether a scheduled shutdown is due
+    ///
+    /// Returns true, clearing the schedule, once the scheduled time has
+    /// passed.
+    fn check_scheduled_shutdown(&mut self) -> bool {
+        let Some(at) = self.status.take_due_shutdown(chrono::Utc::now()) else {
+            return false;
+        };
+        warn!("Scheduled shutdown time {} reached", at.to_rfc3339());
+        self.alert(
+            AlertKind::ShutdownInitiated,
+            "Scheduled shutdown time reached, shutting down".to_string(),
+        );
+        true
+    }
+
+    /// Check whether a power schedule window started
+    ///
+    /// Returns the wake time of the window. Windows starting in
+    /// maintenance mode, or while the system clock is clearly wrong, are
+    /// skipped.
+    fn check_power_schedule(&mut self, config: &Config) -> Option<chrono::DateTime<chrono::Utc>> {
+        let window = self
+            .power_schedule
+            .update(&config.power_schedule, &chrono::Local::now())?;
+        let on = window.on.with_timezone(&chrono::Local).to_rfc3339();
+        if self.status.maintenance_until().is_some() {
+            warn!(
+                "Power schedule standby until {} skipped in maintenance mode",
+                on
+            );
+            return None;
+        }
+        if let Err(e) = clock::check_wake_time(window.on) {
+            warn!("Power schedule standby until {} skipped: {}", on, e);
+            return None;
+        }
+        warn!("Power schedule window started, standby until {}", on);
+        self.alert(
+            AlertKind::ShutdownInitiated,
+            format!("Power schedule standby until {}", on),
+        );
+        Some(window.on)
+    }
+
+    /// Check the input current in `measurements` for overcurrent and spikes
+    ///
+    /// Raises alerts, and sheds USB ports during overcurrent.
+    async fn check_input_current(&mut self, config: &Config, measurements: &Measurements) {
+        let limits = &config.input_current;
+        let current = measurements.input_current.as_f64();
+        if !limits.enabled {
+            if self.current.overcurrent() {
+                self.restore_usb_ports(ShedCause::InputCurrent).await;
+            }
+            self.current = CurrentMonitor::default();
+            return;
+        }
+
+        match self.current.update(limits, current, Instant::now()) {
+            Some(CurrentEvent::Overcurrent) => {
+                warn!(
+                    current,
+                    "Input overcurrent (I_in = {:.2}A > {:.2}A for {:.1}s)",
+                    current,
+                    limits.limit,
+                    limits.sustain
+                );
+                self.alert(
+                    AlertKind::InputOvercurrent,
+                    format!(
+                        "Input current {:.2} A above the {:.2} A limit",
+                        current, limits.limit
+                    ),
+                );
+                self.shed_usb_ports(ShedCause::InputCurrent, limits.shed_mask())
+                    .await;
+            }
+            Some(CurrentEvent::Normal) => {
+                info!(current, "Input current normal (I_in = {:.2}A)", current);
+                self.alert(
+                    AlertKind::InputCurrentNormal,
+                    format!("Input current back to {:.2} A", current),
+                );
+                self.restore_usb_ports(ShedCause::InputCurrent).await;
+            }
+            Some(CurrentEvent::Spike(rise)) => {
+                warn!(
+                    current,
+                    "Input current spike (I_in = {:.2}A, {:.2}A above average)", current, rise
+                );
+                self.alert(
+                    AlertKind::InputCurrentSpike,
+                    format!(
+                        "Input current spiked to {:.2} A, {:.2} A above average",
+                        current, rise
+                    ),
+                );
+            }
+            None => {}
+        }
+    }
+
+    /// Turn off the USB ports in `mask` that are on, holding them for `cause`
+    async fn shed_usb_ports(&mut self, cause: ShedCause, mask: u8) {
+        if mask & !self.shed_ports.held(cause) == 0 {
+            return;
+        }
+        let result = async {
+            let ports = self
+                .device
+                .run(|device| device.get_usb_port_state())
+                .await?;
+            let shed = self.shed_ports.shed(cause, mask, ports);
+            if shed != 0 {
+                self.device
+                    .run(move |device| device.set_usb_port_state(ports & !shed))
+                    .await?;
+            }
+            Ok::<_, I2cError>(shed)
+        }
+        .await;
+        match result {
+            Ok(0) => {}
+            Ok(shed) => warn!("Turned off USB ports {} to shed load", port_list(shed)),
+            Err(e) => warn!("Failed to turn off USB ports: {}", e),
+        }
+    }
+
+    /// Turn the USB ports held for `cause` on again
+    ///
+    /// Ports that another cause still holds stay off.
+    async fn restore_usb_ports(&mut self, cause: ShedCause) {
+        let shed = self.shed_ports.restore(cause);
+        if shed == 0 {
+            return;
+        }
+        let result = self
+            .device
+            .run(move |device| {
+                let ports = device.get_usb_port_state()?;
+                device.set_usb_port_state(ports | shed)
+            })
+            .await;
+        match result {
+            Ok(()) => info!("Turned USB ports {} on again", port_list(shed)),
+            Err(e) => warn!("Failed to turn USB ports on again: {}", e),
+        }
+    }
+
+    /// Publish an alert on the event bus
+    fn alert(&self, kind: AlertKind, message: String) {
+        self.events
+            .publish(DaemonEvent::Alert(Alert::new(kind, message)));
+    }
+
+    /// Update the systemd status with the blackout duration
+    ///
+    /// Sent at most once per second rather than on every tick.
+    fn report_blackout(&mut self, elapsed: f64) {
+        let seconds = elapsed as u64;
+        if self.blackout_reported != Some(seconds) {
+            self.blackout_reported = Some(seconds);
+            self.notify_status(&status_text(DaemonState::Blackout, elapsed));
+        }
+    }
+
     /// Transition to a new state with logging
     fn transition_to(&mut self, new_state: DaemonState) {
-        info!("State transition: {:?} -> {:?}", self.state, new_state);
+        info!(
+            state = ?new_state,
+            previous_state = ?self.state,
+            "State transition: {:?} -> {:?}",
+            self.state,
+            new_state
+        );
         self.state = new_state;
+        self.status.set(new_state);
+        if new_state == DaemonState::Ok {
+            self.status.set_runtime(None);
+        }
+        self.blackout_reported = None;
+        self.maintenance_logged = false;
+        self.notify_status(&status_text(new_state, 0.0));
+    }
+
+    /// Update the systemd status line, followed by the self-test failures
+    fn notify_status(&self, text: &str) {
+        match self.status.self_test().filter(|report| !report.passed()) {
+            Some(report) => notify::status(&format!(
+                "{} (self-test failed: {})",
+                text,
+                report.summary()
+            )),
+            None => notify::status(text),
+        };
+    }
+}
+
+/// USB port numbers in `mask`, e.g. `2, 3`
+fn port_list(mask: u8) -> String {
+    (0..4)
+        .filter(|port| mask & (1 << port) != 0)
+        .map(|port| port.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Log the controller versions and the features they lack
+fn log_capabilities(capabilities: &Capabilities) {
+    info!(
+        "Controller hardware {}, firmware {}",
+        capabilities.hardware, capabilities.firmware
+    );
+    for (feature, supported) in capabilities.map() {
+        if !supported {
+            info!("Feature not available: {}", capabilities.explain(feature));
+        }
+    }
+}
+
+/// Message broadcast to logged-in users at the start of the grace period
+fn grace_message(period: f64) -> String {
+    format!(
+        "halpid: input power lost. The system will shut down in {:.0} seconds.",
+        period
+    )
+}
+
+/// systemd status line for `state`
+fn status_text(state: DaemonState, blackout_elapsed: f64) -> String {
+    match state {
+        DaemonState::Start => "Initializing watchdog".to_string(),
+        DaemonState::Ok => "Monitoring input power".to_string(),
+        DaemonState::Blackout => format!("Blackout: {:.1}s elapsed", blackout_elapsed),
+        DaemonState::Shutdown => "Blackout time limit exceeded, shutting down".to_string(),
+        DaemonState::Dead => "Shutdown requested, waiting for power loss".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_text() {
+        assert_eq!(
+            status_text(DaemonState::Blackout, 3.24),
+            "Blackout: 3.2s elapsed"
+        );
+        assert_eq!(status_text(DaemonState::Ok, 0.0), "Monitoring input power");
+    }
+
+    #[test]
+    fn test_port_list() {
+        assert_eq!(port_list(0b1100), "2, 3");
+        assert_eq!(port_list(0b0001), "0");
+        assert_eq!(port_list(0), "");
+    }
+
+    #[test]
+    fn test_grace_message() {
+        assert_eq!(
+            grace_message(30.0),
+            "halpid: input power lost. The system will shut down in 30 seconds."
+        );
     }
 }
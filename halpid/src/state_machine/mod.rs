@@ -1,7 +1,16 @@
 //! Power management state machine
 
+pub mod current;
+pub mod excursion;
+pub mod filter;
 pub mod machine;
+pub mod schedule;
+pub mod shedding;
+pub mod status;
+pub mod thermal;
 
 pub use machine::DaemonState;
 
 pub use machine::StateMachine;
+
+pub use status::{MachineStatus, StatusHandle};
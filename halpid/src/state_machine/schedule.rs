@@ -0,0 +1,158 @@
+//! Recurring standby windows of the power schedule
+
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+
+use halpi_common::config::PowerWindowConfig;
+
+/// Days ahead searched for the next window; every window recurs weekly
+const SEARCH_DAYS: usize = 8;
+
+/// A concrete occurrence of a power schedule window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowerWindow {
+    /// When standby starts
+    pub off: DateTime<Utc>,
+    /// When the RTC wakes the system
+    pub on: DateTime<Utc>,
+}
+
+/// The first window starting after `after`, in the time zone of `after`
+///
+/// Windows with invalid times are skipped; the configuration validation
+/// rejects them.
+pub fn next_window<Tz: TimeZone>(
+    windows: &[PowerWindowConfig],
+    after: &DateTime<Tz>,
+) -> Option<PowerWindow> {
+    let tz = after.timezone();
+    let today = after.date_naive();
+    let mut next: Option<PowerWindow> = None;
+    for window in windows {
+        let Ok((off_time, on_time)) = window.times() else {
+            continue;
+        };
+        for date in today.iter_days().take(SEARCH_DAYS) {
+            if !window.starts_on(date.weekday()) {
+                continue;
+            }
+            let Some(off) = tz.from_local_datetime(&date.and_time(off_time)).earliest() else {
+                continue;
+            };
+            if off <= *after {
+                continue;
+            }
+            let on_date = if on_time > off_time {
+                date
+            } else {
+                date.succ_opt()?
+            };
+            let Some(on) = tz.from_local_datetime(&on_date.and_time(on_time)).latest() else {
+                continue;
+            };
+            let candidate = PowerWindow {
+                off: off.with_timezone(&Utc),
+                on: on.with_timezone(&Utc),
+            };
+            if next.is_none_or(|next| candidate.off < next.off) {
+                next = Some(candidate);
+            }
+            break;
+        }
+    }
+    next
+}
+
+/// Tracks when the power schedule was last checked
+///
+/// A window is due once its start passes between two checks. Starting the
+/// daemon, or waking up early, within a window thus does not put the
+/// system back into standby.
+#[derive(Debug, Default)]
+pub struct PowerSchedule {
+    checked: Option<DateTime<Utc>>,
+}
+
+impl PowerSchedule {
+    /// Check the schedule at `now`, returning the window that became due
+    pub fn update<Tz: TimeZone>(
+        &mut self,
+        windows: &[PowerWindowConfig],
+        now: &DateTime<Tz>,
+    ) -> Option<PowerWindow> {
+        let checked = self.checked.replace(now.with_timezone(&Utc))?;
+        let window = next_window(windows, &checked.with_timezone(&now.timezone()))?;
+        (window.off <= now.with_timezone(&Utc)).then_some(window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Weekday;
+
+    fn window(off: &str, on: &str, days: &[Weekday]) -> PowerWindowConfig {
+        PowerWindowConfig {
+            off: off.to_string(),
+            on: on.to_string(),
+            days: days.to_vec(),
+        }
+    }
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    #[test]
+    fn test_next_window() {
+        // 2025-11-08 is a Saturday
+        let now = at("2025-11-08T12:00:00Z");
+        let windows = [window("01:00", "06:00", &[])];
+        assert_eq!(
+            next_window(&windows, &now),
+            Some(PowerWindow {
+                off: at("2025-11-09T01:00:00Z"),
+                on: at("2025-11-09T06:00:00Z"),
+            })
+        );
+
+        // Ends on the following day
+        let windows = [window("22:00", "07:30", &[Weekday::Mon])];
+        assert_eq!(
+            next_window(&windows, &now),
+            Some(PowerWindow {
+                off: at("2025-11-10T22:00:00Z"),
+                on: at("2025-11-11T07:30:00Z"),
+            })
+        );
+
+        // The earliest of several windows
+        let windows = [
+            window("22:00", "07:30", &[Weekday::Mon]),
+            window("13:00", "14:00", &[Weekday::Sat]),
+        ];
+        assert_eq!(
+            next_window(&windows, &now).map(|window| window.off),
+            Some(at("2025-11-08T13:00:00Z"))
+        );
+
+        assert_eq!(next_window(&[], &now), None);
+    }
+
+    #[test]
+    fn test_power_schedule() {
+        let windows = [window("01:00", "06:00", &[])];
+        let mut schedule = PowerSchedule::default();
+
+        // Not due when first checked within the window
+        assert_eq!(schedule.update(&windows, &at("2025-11-08T02:00:00Z")), None);
+        assert_eq!(schedule.update(&windows, &at("2025-11-09T00:59:59Z")), None);
+        assert_eq!(
+            schedule.update(&windows, &at("2025-11-09T01:00:00Z")),
+            Some(PowerWindow {
+                off: at("2025-11-09T01:00:00Z"),
+                on: at("2025-11-09T06:00:00Z"),
+            })
+        );
+        assert_eq!(schedule.update(&windows, &at("2025-11-09T01:00:01Z")), None);
+    }
+}
@@ -0,0 +1,73 @@
+//! USB port load shedding shared by the protection policies
+
+/// Why USB ports were turned off
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShedCause {
+    Temperature,
+    InputCurrent,
+}
+
+impl ShedCause {
+    fn index(self) -> usize {
+        match self {
+            ShedCause::Temperature => 0,
+            ShedCause::InputCurrent => 1,
+        }
+    }
+}
+
+/// USB ports turned off to shed load, by cause
+///
+/// A port stays off while any cause that turned it off, or wanted it off
+/// when it already was, still holds it. Ports that were off to begin with
+/// are never turned on.
+#[derive(Debug, Default)]
+pub struct ShedPorts {
+    held: [u8; 2],
+}
+
+impl ShedPorts {
+    /// Hold the ports in `mask` for `cause`, given the enabled `ports`
+    ///
+    /// Returns the ports to turn off.
+    pub fn shed(&mut self, cause: ShedCause, mask: u8, ports: u8) -> u8 {
+        self.held[cause.index()] |= mask & (ports | self.all());
+        mask & ports
+    }
+
+    /// Release the ports held for `cause`
+    ///
+    /// Returns the ports to turn on again.
+    pub fn restore(&mut self, cause: ShedCause) -> u8 {
+        let released = std::mem::take(&mut self.held[cause.index()]);
+        released & !self.all()
+    }
+
+    /// Ports held for `cause`
+    pub fn held(&self, cause: ShedCause) -> u8 {
+        self.held[cause.index()]
+    }
+
+    fn all(&self) -> u8 {
+        self.held.iter().fold(0, |all, held| all | held)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shed_ports() {
+        let mut shed = ShedPorts::default();
+        // Port 1 is off already and stays off
+        assert_eq!(shed.shed(ShedCause::Temperature, 0b0110, 0b1101), 0b0100);
+        assert_eq!(shed.held(ShedCause::Temperature), 0b0100);
+
+        // Port 2 is held by both causes
+        assert_eq!(shed.shed(ShedCause::InputCurrent, 0b1100, 0b1001), 0b1000);
+        assert_eq!(shed.restore(ShedCause::Temperature), 0);
+        assert_eq!(shed.restore(ShedCause::InputCurrent), 0b1100);
+        assert_eq!(shed.restore(ShedCause::InputCurrent), 0);
+    }
+}
@@ -0,0 +1,241 @@
+//! State machine status shared with the HTTP API
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+use super::DaemonState;
+use crate::drift::DriftReport;
+use crate::selftest::SelfTestReport;
+
+/// Current state of the state machine
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MachineStatus {
+    pub state: DaemonState,
+    /// When the state was entered
+    pub since: DateTime<Utc>,
+}
+
+/// Cloneable handle to the state machine status
+///
+/// The state machine updates it on every transition; API handlers read it,
+/// can ask the state machine to cancel a pending shutdown, schedule a
+/// shutdown, and switch maintenance mode on and off. The controller
+/// self-test and settings drift check record their results here too.
+#[derive(Clone)]
+pub struct StatusHandle {
+    status: watch::Sender<MachineStatus>,
+    cancel: Arc<AtomicBool>,
+    /// End of maintenance mode, in which blackouts do not shut down
+    maintenance: Arc<Mutex<Option<DateTime<Utc>>>>,
+    /// Estimated supercap runtime during a blackout, in seconds
+    runtime: Arc<Mutex<Option<f64>>>,
+    /// When the state machine is to shut the system down
+    scheduled_shutdown: Arc<Mutex<Option<DateTime<Utc>>>>,
+    /// Last controller settings drift check, if any ran
+    drift: Arc<Mutex<Option<DriftReport>>>,
+    /// Last controller self-test, if any ran
+    self_test: Arc<Mutex<Option<SelfTestReport>>>,
+}
+
+impl StatusHandle {
+    /// Create a handle in the initial state
+    pub fn new() -> Self {
+        let (status, _) = watch::channel(MachineStatus {
+            state: DaemonState::Start,
+            since: Utc::now(),
+        });
+        Self {
+            status,
+            cancel: Arc::new(AtomicBool::new(false)),
+            maintenance: Arc::new(Mutex::new(None)),
+            runtime: Arc::new(Mutex::new(None)),
+            scheduled_shutdown: Arc::new(Mutex::new(None)),
+            drift: Arc::new(Mutex::new(None)),
+            self_test: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Record a transition to `state`
+    pub fn set(&self, state: DaemonState) {
+        self.status.send_replace(MachineStatus {
+            state,
+            since: Utc::now(),
+        });
+    }
+
+    /// The current status
+    pub fn get(&self) -> MachineStatus {
+        self.status.borrow().clone()
+    }
+
+    /// Whether the system is shutting down or waiting for the controller to
+    /// cut power
+    ///
+    /// Services that read the controller on their own skip their reads
+    /// then: every transfer feeds the hardware watchdog, which has to run
+    /// out in the Dead state.
+    pub fn is_shutting_down(&self) -> bool {
+        matches!(
+            self.status.borrow().state,
+            DaemonState::Shutdown | DaemonState::Dead
+        )
+    }
+
+    /// Watch for state transitions
+    pub fn subscribe(&self) -> watch::Receiver<MachineStatus> {
+        self.status.subscribe()
+    }
+
+    /// Ask the state machine to cancel a pending shutdown
+    pub fn request_cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// Take a cancel request, clearing it
+    pub fn take_cancel(&self) -> bool {
+        self.cancel.swap(false, Ordering::Relaxed)
+    }
+
+    /// Enable maintenance mode until `until`, or disable it with `None`
+    pub fn set_maintenance(&self, until: Option<DateTime<Utc>>) {
+        *self.maintenance.lock().unwrap() = until;
+    }
+
+    /// Record the estimated supercap runtime, `None` outside blackouts
+    pub fn set_runtime(&self, seconds: Option<f64>) {
+        *self.runtime.lock().unwrap() = seconds;
+    }
+
+    /// Estimated supercap runtime in seconds, once known during a blackout
+    pub fn runtime(&self) -> Option<f64> {
+        *self.runtime.lock().unwrap()
+    }
+
+    /// Schedule a shutdown at `at`, or clear the schedule with `None`
+    pub fn set_scheduled_shutdown(&self, at: Option<DateTime<Utc>>) {
+        *self.scheduled_shutdown.lock().unwrap() = at;
+    }
+
+    /// Time of the scheduled shutdown, if one is scheduled
+    pub fn scheduled_shutdown(&self) -> Option<DateTime<Utc>> {
+        *self.scheduled_shutdown.lock().unwrap()
+    }
+
+    /// Take the scheduled shutdown if it is due at `now`, clearing it
+    pub fn take_due_shutdown(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut scheduled = self.scheduled_shutdown.lock().unwrap();
+        if scheduled.is_some_and(|at| at <= now) {
+            scheduled.take()
+        } else {
+            None
+        }
+    }
+
+    /// Record a drift check, or clear it with `None` when checks stop
+    pub fn set_drift(&self, report: Option<DriftReport>) {
+        *self.drift.lock().unwrap() = report;
+    }
+
+    /// Result of the last drift check
+    pub fn drift(&self) -> Option<DriftReport> {
+        self.drift.lock().unwrap().clone()
+    }
+
+    /// Record a controller self-test
+    pub fn set_self_test(&self, report: Option<SelfTestReport>) {
+        *self.self_test.lock().unwrap() = report;
+    }
+
+    /// Result of the last controller self-test
+    pub fn self_test(&self) -> Option<SelfTestReport> {
+        self.self_test.lock().unwrap().clone()
+    }
+
+    /// True unless the last self-test found unsupported firmware or hardware
+    pub fn is_compatible(&self) -> bool {
+        self.self_test
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_none_or(|report| report.compatible)
+    }
+
+    /// End of maintenance mode, if it is enabled
+    pub fn maintenance_until(&self) -> Option<DateTime<Utc>> {
+        let mut maintenance = self.maintenance.lock().unwrap();
+        if maintenance.is_some_and(|until| until <= Utc::now()) {
+            *maintenance = None;
+        }
+        *maintenance
+    }
+}
+
+impl Default for StatusHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_handle() {
+        let handle = StatusHandle::new();
+        assert_eq!(handle.get().state, DaemonState::Start);
+
+        let clone = handle.clone();
+        clone.set(DaemonState::Blackout);
+        let status = handle.get();
+        assert_eq!(status.state, DaemonState::Blackout);
+        assert_eq!(serde_json::to_value(&status).unwrap()["state"], "blackout");
+
+        assert!(!handle.take_cancel());
+        clone.request_cancel();
+        assert!(handle.take_cancel());
+        assert!(!handle.take_cancel());
+    }
+
+    #[test]
+    fn test_is_shutting_down() {
+        let handle = StatusHandle::new();
+        handle.set(DaemonState::Blackout);
+        assert!(!handle.is_shutting_down());
+        handle.set(DaemonState::Shutdown);
+        assert!(handle.is_shutting_down());
+        handle.set(DaemonState::Dead);
+        assert!(handle.is_shutting_down());
+    }
+
+    #[test]
+    fn test_maintenance() {
+        let handle = StatusHandle::new();
+        assert_eq!(handle.maintenance_until(), None);
+
+        let until = Utc::now() + chrono::Duration::minutes(30);
+        handle.clone().set_maintenance(Some(until));
+        assert_eq!(handle.maintenance_until(), Some(until));
+        handle.set_maintenance(None);
+        assert_eq!(handle.maintenance_until(), None);
+
+        // Expires by itself
+        handle.set_maintenance(Some(Utc::now() - chrono::Duration::seconds(1)));
+        assert_eq!(handle.maintenance_until(), None);
+    }
+
+    #[test]
+    fn test_scheduled_shutdown() {
+        let handle = StatusHandle::new();
+        let at = Utc::now() + chrono::Duration::minutes(45);
+        handle.clone().set_scheduled_shutdown(Some(at));
+        assert_eq!(handle.scheduled_shutdown(), Some(at));
+
+        assert_eq!(handle.take_due_shutdown(Utc::now()), None);
+        assert_eq!(handle.take_due_shutdown(at), Some(at));
+        assert_eq!(handle.scheduled_shutdown(), None);
+    }
+}
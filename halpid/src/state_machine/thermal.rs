@@ -0,0 +1,82 @@
+//! Over-temperature protection levels
+
+use halpi_common::config::TemperatureConfig;
+
+/// Over-temperature protection level, in increasing severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum ThermalLevel {
+    /// Below the warning limit
+    #[default]
+    Normal,
+    /// Above the warning limit
+    Warning,
+    /// Above the shed limit; shed USB ports are off
+    Shed,
+    /// Above the shutdown limit
+    Shutdown,
+}
+
+impl ThermalLevel {
+    /// Level name as used in configuration and logs
+    pub fn name(&self) -> &'static str {
+        match self {
+            ThermalLevel::Normal => "normal",
+            ThermalLevel::Warning => "warning",
+            ThermalLevel::Shed => "shed",
+            ThermalLevel::Shutdown => "shutdown",
+        }
+    }
+
+    /// The level `temperature` (°C) calls for, coming from `self`
+    ///
+    /// A level is entered at its limit and left only once the temperature
+    /// is `hysteresis` below it, so readings near a limit do not flap.
+    pub fn next(self, config: &TemperatureConfig, temperature: f64) -> Self {
+        let limits = [
+            (ThermalLevel::Warning, config.warning),
+            (ThermalLevel::Shed, config.shed),
+            (ThermalLevel::Shutdown, config.shutdown),
+        ];
+        limits
+            .iter()
+            .rev()
+            .find(|(level, limit)| {
+                let limit = if *level <= self {
+                    limit - config.hysteresis
+                } else {
+                    *limit
+                };
+                temperature >= limit
+            })
+            .map_or(ThermalLevel::Normal, |(level, _)| *level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thermal_levels() {
+        let config = TemperatureConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        let mut level = ThermalLevel::Normal;
+        let mut step = |temperature| {
+            level = level.next(&config, temperature);
+            level
+        };
+
+        assert_eq!(step(60.0), ThermalLevel::Normal);
+        assert_eq!(step(70.0), ThermalLevel::Warning);
+        assert_eq!(step(82.0), ThermalLevel::Shed);
+        // Within the hysteresis of the shed limit
+        assert_eq!(step(77.0), ThermalLevel::Shed);
+        assert_eq!(step(74.0), ThermalLevel::Warning);
+        assert_eq!(step(66.0), ThermalLevel::Warning);
+        assert_eq!(step(64.0), ThermalLevel::Normal);
+        // Straight to the top
+        assert_eq!(step(90.0), ThermalLevel::Shutdown);
+    }
+}
@@ -0,0 +1,327 @@
+//! Measurement statistics
+//!
+//! The state machine samples several times a second, so keeping every
+//! sample for an hour would take megabytes. Instead the samples of each
+//! second are folded into a bucket holding the minimum, maximum and sum of
+//! every quantity, and the buckets of the last [`RETENTION_S`] seconds are
+//! kept. The same is done per minute for the last [`HISTORY_RETENTION_S`]
+//! seconds, for exporting the history of a day.
+//!
+//! Nothing is persisted; the history starts over when the daemon restarts.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use halpi_common::events::Sample;
+
+/// Longest window statistics are kept for, in seconds
+pub const RETENTION_S: u64 = 3600;
+
+/// How long the per-minute history is kept, in seconds
+pub const HISTORY_RETENTION_S: u64 = 24 * 3600;
+
+/// Resolution of the history beyond [`RETENTION_S`], in seconds
+const MINUTE: i64 = 60;
+
+/// Quantities summarized, named as in `/values`
+const QUANTITIES: [&str; 5] = ["V_in", "I_in", "V_cap", "T_mcu", "T_pcb"];
+
+/// Minimum, maximum and average of a quantity over a window
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Summary {
+    pub min: f32,
+    pub max: f32,
+    pub avg: f32,
+}
+
+/// Statistics over a window
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatsReport {
+    /// Requested window in seconds
+    pub window_s: u64,
+    /// Number of samples in the window
+    pub samples: u32,
+    /// Time of the first bucket in the window
+    pub from: DateTime<Utc>,
+    /// Time of the last bucket in the window
+    pub to: DateTime<Utc>,
+    /// Summary per quantity; temperatures are in Kelvin
+    pub values: BTreeMap<&'static str, Summary>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Accumulator {
+    min: f32,
+    max: f32,
+    sum: f64,
+}
+
+impl Accumulator {
+    fn new(value: f32) -> Self {
+        Self {
+            min: value,
+            max: value,
+            sum: value as f64,
+        }
+    }
+
+    fn add(&mut self, value: f32) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value as f64;
+    }
+
+    fn merge(&mut self, other: &Accumulator) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+    }
+}
+
+/// One row of the measurement history
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryRow {
+    /// Start of the interval
+    pub timestamp: DateTime<Utc>,
+    /// Number of samples in the interval
+    pub samples: u32,
+    /// Summary per quantity; temperatures are in Kelvin
+    #[serde(flatten)]
+    pub values: BTreeMap<&'static str, Summary>,
+}
+
+/// Samples of one interval
+#[derive(Debug, Clone)]
+struct Bucket {
+    /// Start of the interval in seconds since the epoch
+    second: i64,
+    count: u32,
+    values: [Accumulator; QUANTITIES.len()],
+}
+
+impl Bucket {
+    fn summaries(&self) -> BTreeMap<&'static str, Summary> {
+        summarize(&self.values, self.count)
+    }
+}
+
+/// Per-second and per-minute aggregates of the recent measurement samples
+#[derive(Debug, Default)]
+pub struct MeasurementStats {
+    /// Oldest first
+    buckets: VecDeque<Bucket>,
+    /// Oldest first
+    minutes: VecDeque<Bucket>,
+}
+
+impl MeasurementStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a sample, dropping buckets older than [`RETENTION_S`]
+    pub fn record(&mut self, sample: &Sample) {
+        let m = &sample.measurements;
+        let values = [
+            m.dcin_voltage.0,
+            m.input_current.0,
+            m.supercap_voltage.0,
+            m.mcu_temperature.0,
+            m.pcb_temperature.0,
+        ];
+        let second = sample.timestamp.timestamp();
+        add_sample(&mut self.buckets, second, values, RETENTION_S);
+        add_sample(
+            &mut self.minutes,
+            second - second.rem_euclid(MINUTE),
+            values,
+            HISTORY_RETENTION_S,
+        );
+    }
+
+    /// Rows of the last `since_s` seconds before `now`, oldest first
+    ///
+    /// Rows are per second for up to [`RETENTION_S`] and per minute beyond
+    /// that, up to [`HISTORY_RETENTION_S`]. Returns the resolution in seconds
+    /// along with the rows.
+    pub fn history(&self, since_s: u64, now: DateTime<Utc>) -> (u64, Vec<HistoryRow>) {
+        let (resolution, buckets) = if since_s <= RETENTION_S {
+            (1, &self.buckets)
+        } else {
+            (MINUTE as u64, &self.minutes)
+        };
+        let start = now.timestamp() - since_s.min(HISTORY_RETENTION_S) as i64;
+        let rows = buckets
+            .iter()
+            // Include the interval `start` falls into
+            .filter(|bucket| bucket.second + resolution as i64 > start)
+            .map(|bucket| HistoryRow {
+                timestamp: DateTime::from_timestamp(bucket.second, 0).unwrap_or(now),
+                samples: bucket.count,
+                values: bucket.summaries(),
+            })
+            .collect();
+        (resolution, rows)
+    }
+
+    /// Statistics of the last `window_s` seconds before `now`, `None` if
+    /// there are no samples in it
+    pub fn report(&self, window_s: u64, now: DateTime<Utc>) -> Option<StatsReport> {
+        let start = now.timestamp() - window_s.min(RETENTION_S) as i64;
+        let mut buckets = self.buckets.iter().filter(|bucket| bucket.second > start);
+        let first = buckets.next()?;
+        let mut last = first;
+        let mut count = first.count;
+        let mut totals = first.values;
+        for bucket in buckets {
+            count += bucket.count;
+            for (total, accumulator) in totals.iter_mut().zip(&bucket.values) {
+                total.merge(accumulator);
+            }
+            last = bucket;
+        }
+
+        Some(StatsReport {
+            window_s,
+            samples: count,
+            from: DateTime::from_timestamp(first.second, 0).unwrap_or(now),
+            to: DateTime::from_timestamp(last.second, 0).unwrap_or(now),
+            values: summarize(&totals, count),
+        })
+    }
+}
+
+/// Add a sample to the bucket starting at `second`, dropping buckets older
+/// than `retention_s`
+fn add_sample(
+    buckets: &mut VecDeque<Bucket>,
+    second: i64,
+    values: [f32; QUANTITIES.len()],
+    retention_s: u64,
+) {
+    match buckets.back_mut() {
+        Some(bucket) if bucket.second == second => {
+            bucket.count += 1;
+            for (accumulator, value) in bucket.values.iter_mut().zip(values) {
+                accumulator.add(value);
+            }
+        }
+        // Samples stamped before the last bucket, e.g. after the clock
+        // was stepped back, start a new one; they age out as usual
+        _ => buckets.push_back(Bucket {
+            second,
+            count: 1,
+            values: values.map(Accumulator::new),
+        }),
+    }
+
+    let oldest = second - retention_s as i64;
+    while buckets
+        .front()
+        .is_some_and(|bucket| bucket.second <= oldest)
+    {
+        buckets.pop_front();
+    }
+}
+
+fn summarize(
+    totals: &[Accumulator; QUANTITIES.len()],
+    count: u32,
+) -> BTreeMap<&'static str, Summary> {
+    QUANTITIES
+        .into_iter()
+        .zip(totals)
+        .map(|(name, total)| {
+            let summary = Summary {
+                min: total.min,
+                max: total.max,
+                avg: (total.sum / count as f64) as f32,
+            };
+            (name, summary)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halpi_common::types::{Measurements, PowerState};
+    use halpi_common::units::{Amps, Kelvin, Volts};
+
+    fn sample(age_s: i64, v_in: f32) -> Sample {
+        let mut sample = Sample::now(Measurements {
+            dcin_voltage: Volts(v_in),
+            supercap_voltage: Volts(9.5),
+            input_current: Amps(0.5),
+            mcu_temperature: Kelvin(300.0),
+            pcb_temperature: Kelvin(300.0),
+            power_state: PowerState::OperationalCoOp,
+            watchdog_elapsed: 0.0,
+            measured_at: Utc::now(),
+        });
+        sample.timestamp -= chrono::Duration::seconds(age_s);
+        sample
+    }
+
+    #[test]
+    fn test_report() {
+        let mut stats = MeasurementStats::new();
+        assert!(stats.report(900, Utc::now()).is_none());
+
+        stats.record(&sample(1200, 14.0));
+        stats.record(&sample(600, 11.0));
+        stats.record(&sample(600, 13.0));
+        stats.record(&sample(0, 12.0));
+
+        let report = stats.report(900, Utc::now()).unwrap();
+        assert_eq!(report.samples, 3);
+        assert_eq!(
+            report.values["V_in"],
+            Summary {
+                min: 11.0,
+                max: 13.0,
+                avg: 12.0
+            }
+        );
+        assert_eq!(report.values["V_cap"].avg, 9.5);
+        assert!(report.to - report.from >= chrono::Duration::seconds(599));
+
+        assert_eq!(stats.report(RETENTION_S, Utc::now()).unwrap().samples, 4);
+    }
+
+    #[test]
+    fn test_retention() {
+        let mut stats = MeasurementStats::new();
+        stats.record(&sample(RETENTION_S as i64 + 10, 14.0));
+        stats.record(&sample(0, 12.0));
+        assert_eq!(stats.buckets.len(), 1);
+        assert_eq!(stats.report(7200, Utc::now()).unwrap().samples, 1);
+        assert_eq!(stats.minutes.len(), 2);
+    }
+
+    #[test]
+    fn test_history() {
+        let mut stats = MeasurementStats::new();
+        stats.record(&sample(7200, 14.0));
+        stats.record(&sample(30, 11.0));
+        stats.record(&sample(0, 12.0));
+
+        let now = Utc::now();
+        let (resolution, rows) = stats.history(60, now);
+        assert_eq!(resolution, 1);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].values["V_in"].max, 12.0);
+
+        let (resolution, rows) = stats.history(24 * 3600, now);
+        assert_eq!(resolution, 60);
+        assert_eq!(rows[0].values["V_in"].min, 14.0);
+        assert_eq!(rows.iter().map(|row| row.samples).sum::<u32>(), 3);
+        assert_eq!(rows[0].timestamp.timestamp() % 60, 0);
+
+        let row = serde_json::to_value(&rows[0]).unwrap();
+        assert_eq!(row["V_in"]["min"], 14.0);
+        assert_eq!(row["samples"], 1);
+    }
+}
@@ -0,0 +1,210 @@
+//! Sunrise and sunset times for wake scheduling
+//!
+//! Uses the sunrise equation with the usual corrections for refraction and
+//! the solar disc, which is accurate to a minute or two away from the
+//! polar regions. There is no sunrise or sunset during polar day or night.
+
+use std::f64::consts::PI;
+
+use chrono::{DateTime, Days, NaiveDate, Utc};
+
+use halpi_common::config::LocationConfig;
+use halpi_common::duration::parse_duration;
+
+/// Julian date of 2000-01-01T12:00:00Z
+const J2000: f64 = 2_451_545.0;
+
+/// Unix timestamp of [`J2000`]
+const J2000_UNIX: f64 = 946_728_000.0;
+
+/// Solar elevation at sunrise and sunset, in degrees
+const HORIZON: f64 = -0.833;
+
+/// Obliquity of the ecliptic, in degrees
+const OBLIQUITY: f64 = 23.4397;
+
+/// Sunrise or sunset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SunEvent {
+    Sunrise,
+    Sunset,
+}
+
+/// A wake time relative to sunrise or sunset, e.g. `sunrise+30m`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SunWake {
+    pub event: SunEvent,
+    /// Seconds after the event; negative for before
+    pub offset: i64,
+}
+
+impl SunWake {
+    /// Parse `sunrise` or `sunset`, optionally followed by `+` or `-` and
+    /// a duration
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let (name, offset) = match text.find(['+', '-']) {
+            Some(index) => text.split_at(index),
+            None => (text, ""),
+        };
+        let event = match name.trim() {
+            "sunrise" => SunEvent::Sunrise,
+            "sunset" => SunEvent::Sunset,
+            _ => {
+                return Err(format!(
+                    "invalid wake time '{}' (e.g. sunrise, sunrise+30m, sunset-1h)",
+                    text
+                ));
+            }
+        };
+        let offset = match offset.split_at_checked(1) {
+            None => 0,
+            Some((sign, duration)) => {
+                let seconds = parse_duration(duration)?;
+                let seconds = i64::try_from(seconds)
+                    .ok()
+                    .filter(|seconds| *seconds <= 86400)
+                    .ok_or_else(|| format!("offset '{}' is longer than a day", duration))?;
+                if sign == "-" { -seconds } else { seconds }
+            }
+        };
+        Ok(Self { event, offset })
+    }
+
+    /// The first wake time after `after` at `location`
+    ///
+    /// Returns `None` if the sun does not rise or set within the next days.
+    pub fn next_after(
+        &self,
+        location: &LocationConfig,
+        after: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        let today = after.date_naive();
+        (0..4)
+            .filter_map(|days| {
+                today
+                    .checked_sub_days(Days::new(1))?
+                    .checked_add_days(Days::new(days))
+            })
+            .filter_map(|date| sun_event(self.event, location, date))
+            .map(|time| time + chrono::Duration::seconds(self.offset))
+            .find(|time| *time > after)
+    }
+}
+
+/// Time of `event` on the UTC day `date` at `location`
+pub fn sun_event(
+    event: SunEvent,
+    location: &LocationConfig,
+    date: NaiveDate,
+) -> Option<DateTime<Utc>> {
+    let noon = date.and_hms_opt(12, 0, 0)?.and_utc().timestamp() as f64;
+    let day = ((noon - J2000_UNIX) / 86400.0).round();
+
+    // Mean solar time at the longitude
+    let mean = day - location.longitude / 360.0;
+    let anomaly = (357.5291 + 0.985_600_28 * mean)
+        .rem_euclid(360.0)
+        .to_radians();
+    let center =
+        1.9148 * anomaly.sin() + 0.02 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+    let ecliptic = (anomaly.to_degrees() + center + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+    let transit = J2000 + mean + 0.0053 * anomaly.sin() - 0.0069 * (2.0 * ecliptic).sin();
+
+    let declination = (ecliptic.sin() * OBLIQUITY.to_radians().sin()).asin();
+    let latitude = location.latitude.to_radians();
+    let cos_hour_angle = (HORIZON.to_radians().sin() - latitude.sin() * declination.sin())
+        / (latitude.cos() * declination.cos());
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+    let hour_angle = cos_hour_angle.acos() / (2.0 * PI);
+
+    let julian = match event {
+        SunEvent::Sunrise => transit - hour_angle,
+        SunEvent::Sunset => transit + hour_angle,
+    };
+    let unix = J2000_UNIX + (julian - J2000) * 86400.0;
+    DateTime::from_timestamp(unix.round() as i64, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELSINKI: LocationConfig = LocationConfig {
+        latitude: 60.17,
+        longitude: 24.94,
+    };
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    fn assert_near(time: Option<DateTime<Utc>>, expected: &str) {
+        let error = (time.unwrap() - at(expected)).num_seconds().abs();
+        assert!(error < 180, "{:?} is not near {}", time, expected);
+    }
+
+    #[test]
+    fn test_sun_event() {
+        let date = NaiveDate::from_ymd_opt(2025, 6, 21).unwrap();
+        // Helsinki midsummer: sunrise 03:54, sunset 22:50 (UTC+3)
+        assert_near(
+            sun_event(SunEvent::Sunrise, &HELSINKI, date),
+            "2025-06-21T00:54:00Z",
+        );
+        assert_near(
+            sun_event(SunEvent::Sunset, &HELSINKI, date),
+            "2025-06-21T19:50:00Z",
+        );
+
+        // Sydney in December: sunrise 05:41 (UTC+11)
+        let sydney = LocationConfig {
+            latitude: -33.87,
+            longitude: 151.21,
+        };
+        let date = NaiveDate::from_ymd_opt(2025, 12, 21).unwrap();
+        assert_near(
+            sun_event(SunEvent::Sunrise, &sydney, date),
+            "2025-12-20T18:41:00Z",
+        );
+
+        // Polar night in Longyearbyen
+        let svalbard = LocationConfig {
+            latitude: 78.22,
+            longitude: 15.65,
+        };
+        assert_eq!(sun_event(SunEvent::Sunrise, &svalbard, date), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        let wake = |event, offset| Ok(SunWake { event, offset });
+        assert_eq!(SunWake::parse("sunrise"), wake(SunEvent::Sunrise, 0));
+        assert_eq!(SunWake::parse("sunrise+30m"), wake(SunEvent::Sunrise, 1800));
+        assert_eq!(
+            SunWake::parse("sunset-1h30m"),
+            wake(SunEvent::Sunset, -5400)
+        );
+        assert!(SunWake::parse("dawn").is_err());
+        assert!(SunWake::parse("sunrise+").is_err());
+        assert!(SunWake::parse("sunrise+2d").is_err());
+    }
+
+    #[test]
+    fn test_next_after() {
+        let wake = SunWake::parse("sunrise+30m").unwrap();
+        // Before and after sunrise on midsummer
+        assert_near(
+            wake.next_after(&HELSINKI, at("2025-06-20T22:00:00Z")),
+            "2025-06-21T01:24:00Z",
+        );
+        assert_near(
+            wake.next_after(&HELSINKI, at("2025-06-21T12:00:00Z")),
+            "2025-06-22T01:24:00Z",
+        );
+    }
+}
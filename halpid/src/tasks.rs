@@ -0,0 +1,399 @@
+//! Registry of long-running daemon tasks
+//!
+//! Tasks started with [`spawn`] are recorded by name together with their
+//! current state, so a daemon that has stopped exporting data can be told
+//! apart from one whose exporter task has died. The registry is a process
+//! global, like the metrics in [`crate::metrics`].
+//!
+//! Long-running tasks are started with [`supervise`], which restarts them
+//! with exponential backoff when they fail or panic. Critical tasks give up
+//! after repeated failures so that the daemon can shut down cleanly and be
+//! restarted by systemd instead of running without them.
+
+use std::any::Any;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::panic::{AssertUnwindSafe, catch_unwind, resume_unwind};
+use std::sync::Mutex;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tracing::{error, warn};
+
+/// Lifecycle state of a registered task
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TaskState {
+    /// Still running
+    Running,
+    /// Returned successfully
+    Finished,
+    /// Returned an error
+    Failed,
+    /// Panicked
+    Panicked,
+    /// Aborted before completion
+    Cancelled,
+    /// Waiting to be restarted after a failure
+    Restarting,
+}
+
+/// Status of one registered task
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskStatus {
+    pub name: &'static str,
+    pub state: TaskState,
+    pub started: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stopped: Option<DateTime<Utc>>,
+    /// Last error or panic message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Number of times the task has been restarted
+    pub restarts: u32,
+}
+
+/// When and how often a supervised task is restarted
+#[derive(Debug, Clone, Copy)]
+pub struct RestartPolicy {
+    /// Delay before the first restart
+    pub initial_backoff: Duration,
+    /// Upper limit of the doubling restart delay
+    pub max_backoff: Duration,
+    /// Give up after this many failures within `failure_window`, or never
+    pub max_failures: Option<usize>,
+    /// Window for counting failures; a run this long resets the backoff
+    pub failure_window: Duration,
+}
+
+impl RestartPolicy {
+    /// Policy for tasks the daemon cannot run without
+    ///
+    /// The short backoff keeps the gap in state machine polling well below
+    /// both the hardware watchdog timeout and systemd's `WatchdogSec`.
+    pub const CRITICAL: RestartPolicy = RestartPolicy {
+        initial_backoff: Duration::from_millis(100),
+        max_backoff: Duration::from_secs(1),
+        max_failures: Some(5),
+        failure_window: Duration::from_secs(60),
+    };
+
+    /// Policy for optional services such as exporters, restarted forever
+    pub const SERVICE: RestartPolicy = RestartPolicy {
+        initial_backoff: Duration::from_secs(1),
+        max_backoff: Duration::from_secs(300),
+        max_failures: None,
+        failure_window: Duration::from_secs(600),
+    };
+}
+
+static TASKS: Mutex<Vec<TaskStatus>> = Mutex::new(Vec::new());
+
+/// Update the state of the task at `index`
+fn set_state(index: usize, state: TaskState, error: Option<String>) {
+    let mut tasks = TASKS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(task) = tasks.get_mut(index) {
+        task.state = state;
+        task.stopped = Some(Utc::now());
+        task.error = error;
+    }
+}
+
+/// Marks a task as cancelled if it is dropped before completing
+struct Guard {
+    index: usize,
+    done: bool,
+}
+
+impl Guard {
+    fn complete(&mut self) {
+        self.done = true;
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if !self.done {
+            set_state(self.index, TaskState::Cancelled, None);
+        }
+    }
+}
+
+/// Add a running task to the registry and return its index
+fn register(name: &'static str) -> usize {
+    let mut tasks = TASKS.lock().unwrap_or_else(|e| e.into_inner());
+    tasks.push(TaskStatus {
+        name,
+        state: TaskState::Running,
+        started: Utc::now(),
+        stopped: None,
+        error: None,
+        restarts: 0,
+    });
+    tasks.len() - 1
+}
+
+/// Mark the task at `index` as running again after a restart
+fn set_restarted(index: usize) {
+    let mut tasks = TASKS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(task) = tasks.get_mut(index) {
+        task.state = TaskState::Running;
+        task.started = Utc::now();
+        task.stopped = None;
+        task.restarts += 1;
+    }
+}
+
+/// Run `future`, catching a panic instead of unwinding
+///
+/// Catching panics here rather than relying on the runtime, which only sees
+/// them after the task's state is gone, lets the caller record them.
+async fn catch_panic<F: Future>(future: F) -> Result<F::Output, Box<dyn Any + Send>> {
+    let mut future = Box::pin(future);
+    std::future::poll_fn(
+        |cx| match catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(Poll::Ready(result)) => Poll::Ready(Ok(result)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(panic) => Poll::Ready(Err(panic)),
+        },
+    )
+    .await
+}
+
+/// Message of a panic payload
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Spawn a named task on the runtime and track its state
+///
+/// An error returned by the task is logged and recorded. The task is not
+/// restarted; see [`supervise`].
+pub fn spawn<F>(name: &'static str, future: F) -> JoinHandle<()>
+where
+    F: Future<Output = anyhow::Result<()>> + Send + 'static,
+{
+    let index = register(name);
+
+    // Created outside the task so that aborting it before the first poll
+    // is recorded too
+    let mut guard = Guard { index, done: false };
+    tokio::spawn(async move {
+        let result = catch_panic(future).await;
+        guard.complete();
+
+        match result {
+            Ok(Ok(())) => set_state(index, TaskState::Finished, None),
+            Ok(Err(e)) => {
+                error!(task = name, "Task {} failed: {:#}", name, e);
+                set_state(index, TaskState::Failed, Some(format!("{:#}", e)));
+            }
+            Err(panic) => {
+                let message = panic_message(panic.as_ref());
+                set_state(index, TaskState::Panicked, Some(message));
+                resume_unwind(panic);
+            }
+        }
+    })
+}
+
+/// Spawn a named task that is restarted according to `policy`
+///
+/// `start` is called to create the task's future for each run. A run that
+/// returns `Ok` ends supervision; errors and panics lead to a restart after
+/// the backoff delay. The returned handle resolves to `Ok` when the task
+/// finished and to an error when the supervisor gave up on it.
+pub fn supervise<F, Fut>(
+    name: &'static str,
+    policy: RestartPolicy,
+    mut start: F,
+) -> JoinHandle<anyhow::Result<()>>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+{
+    let index = register(name);
+    let mut guard = Guard { index, done: false };
+
+    tokio::spawn(async move {
+        let mut failures: VecDeque<Instant> = VecDeque::new();
+        let mut backoff = policy.initial_backoff;
+
+        loop {
+            let started = Instant::now();
+            let (state, message) = match catch_panic(start()).await {
+                Ok(Ok(())) => {
+                    guard.complete();
+                    set_state(index, TaskState::Finished, None);
+                    return Ok(());
+                }
+                Ok(Err(e)) => {
+                    error!(task = name, "Task {} failed: {:#}", name, e);
+                    (TaskState::Failed, format!("{:#}", e))
+                }
+                Err(panic) => {
+                    let message = panic_message(panic.as_ref());
+                    error!(task = name, "Task {} panicked: {}", name, message);
+                    (TaskState::Panicked, message)
+                }
+            };
+
+            let now = Instant::now();
+            if now.duration_since(started) >= policy.failure_window {
+                backoff = policy.initial_backoff;
+            }
+            failures.push_back(now);
+            while failures
+                .front()
+                .is_some_and(|failed| now.duration_since(*failed) >= policy.failure_window)
+            {
+                failures.pop_front();
+            }
+
+            if let Some(max_failures) = policy.max_failures
+                && failures.len() >= max_failures
+            {
+                guard.complete();
+                set_state(index, state, Some(message.clone()));
+                error!(
+                    task = name,
+                    "Task {} failed {} times within {} s, giving up",
+                    name,
+                    failures.len(),
+                    policy.failure_window.as_secs()
+                );
+                anyhow::bail!(
+                    "{} failed {} times within {} s: {}",
+                    name,
+                    failures.len(),
+                    policy.failure_window.as_secs(),
+                    message
+                );
+            }
+
+            set_state(index, TaskState::Restarting, Some(message));
+            warn!(
+                task = name,
+                "Restarting task {} in {:.1}s",
+                name,
+                backoff.as_secs_f64()
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(policy.max_backoff);
+            set_restarted(index);
+        }
+    })
+}
+
+/// Status of all tasks spawned so far
+pub fn snapshot() -> Vec<TaskStatus> {
+    TASKS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_of(name: &str) -> Option<TaskState> {
+        snapshot()
+            .into_iter()
+            .find(|task| task.name == name)
+            .map(|task| task.state)
+    }
+
+    #[tokio::test]
+    async fn test_task_states() {
+        spawn("test-finished", async { Ok(()) }).await.unwrap();
+        assert_eq!(state_of("test-finished"), Some(TaskState::Finished));
+
+        spawn("test-failed", async { anyhow::bail!("boom") })
+            .await
+            .unwrap();
+        let failed = snapshot()
+            .into_iter()
+            .find(|task| task.name == "test-failed")
+            .unwrap();
+        assert_eq!(failed.state, TaskState::Failed);
+        assert_eq!(failed.error.as_deref(), Some("boom"));
+        assert!(failed.stopped.is_some());
+        assert_eq!(failed.restarts, 0);
+
+        assert!(
+            spawn("test-panicked", async { panic!("boom") })
+                .await
+                .is_err()
+        );
+        assert_eq!(state_of("test-panicked"), Some(TaskState::Panicked));
+
+        let handle = spawn("test-running", std::future::pending());
+        tokio::task::yield_now().await;
+        assert_eq!(state_of("test-running"), Some(TaskState::Running));
+        handle.abort();
+        assert!(handle.await.unwrap_err().is_cancelled());
+        assert_eq!(state_of("test-running"), Some(TaskState::Cancelled));
+    }
+
+    const TEST_POLICY: RestartPolicy = RestartPolicy {
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(2),
+        max_failures: Some(3),
+        failure_window: Duration::from_secs(60),
+    };
+
+    #[tokio::test]
+    async fn test_supervise_restarts_until_success() {
+        let runs = std::sync::Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let counter = runs.clone();
+        let handle = supervise("test-flaky", TEST_POLICY, move || {
+            let run = counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async move {
+                match run {
+                    0 => anyhow::bail!("first run fails"),
+                    1 => panic!("second run panics"),
+                    _ => Ok(()),
+                }
+            }
+        });
+
+        assert!(handle.await.unwrap().is_ok());
+        assert_eq!(runs.load(std::sync::atomic::Ordering::SeqCst), 3);
+        let task = snapshot()
+            .into_iter()
+            .find(|task| task.name == "test-flaky")
+            .unwrap();
+        assert_eq!(task.state, TaskState::Finished);
+        assert_eq!(task.restarts, 2);
+    }
+
+    #[tokio::test]
+    async fn test_supervise_gives_up() {
+        let runs = std::sync::Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let counter = runs.clone();
+        let handle = supervise("test-broken", TEST_POLICY, move || {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async { anyhow::bail!("always fails") }
+        });
+
+        let error = handle.await.unwrap().unwrap_err();
+        assert!(error.to_string().contains("always fails"));
+        assert_eq!(runs.load(std::sync::atomic::Ordering::SeqCst), 3);
+        assert_eq!(state_of("test-broken"), Some(TaskState::Failed));
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let panic = catch_unwind(|| panic!("static")).unwrap_err();
+        assert_eq!(panic_message(panic.as_ref()), "static");
+        let panic = catch_unwind(|| panic!("formatted {}", 1)).unwrap_err();
+        assert_eq!(panic_message(panic.as_ref()), "formatted 1");
+    }
+}
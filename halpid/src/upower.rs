@@ -0,0 +1,405 @@
+//! UPower-compatible battery device on D-Bus
+//!
+//! Exports the supercapacitor backup as an object implementing the
+//! `org.freedesktop.UPower.Device` interface, so that tools written against
+//! UPower devices can read backup status: percentage, charging/discharging
+//! state, and an estimated time to empty.
+//!
+//! The object lives under the daemon's own bus name (`fi.hatlabs.halpid`)
+//! at the path UPower would use, and changes are announced with the standard
+//! `PropertiesChanged` signal.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use tokio::time::{Duration, Instant, MissedTickBehavior, interval};
+use tracing::{debug, info};
+
+use halpi_common::config::UpowerConfig;
+use halpi_common::types::Measurements;
+use halpi_common::units::Volts;
+
+use crate::dbus::{self, Connection, Message, MessageType, Value};
+use crate::estimate::DischargeEstimator;
+use crate::events::EventBus;
+use crate::i2c::DeviceHandle;
+
+/// Well-known bus name owned by the daemon
+pub const BUS_NAME: &str = "fi.hatlabs.halpid";
+
+/// Object path of the exported battery device
+pub const DEVICE_PATH: &str = "/org/freedesktop/UPower/devices/battery_halpi_supercap";
+
+/// UPower device interface
+pub const DEVICE_INTERFACE: &str = "org.freedesktop.UPower.Device";
+
+/// Supercap voltage treated as empty when the controller threshold cannot be read
+const FALLBACK_EMPTY_VOLTAGE: Volts = Volts(6.0);
+
+/// Charge fraction at or above which the backup is reported as fully charged
+const FULL_CHARGE: f32 = 0.98;
+
+// UPower enumerations
+const TYPE_BATTERY: u32 = 2;
+const STATE_CHARGING: u32 = 1;
+const STATE_DISCHARGING: u32 = 2;
+const STATE_EMPTY: u32 = 3;
+const STATE_FULLY_CHARGED: u32 = 4;
+const TECHNOLOGY_UNKNOWN: u32 = 0;
+const WARNING_NONE: u32 = 1;
+const WARNING_DISCHARGING: u32 = 2;
+const WARNING_LOW: u32 = 3;
+const WARNING_CRITICAL: u32 = 4;
+const BATTERY_LEVEL_NONE: u32 = 1;
+
+const INTROSPECTION_XML: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="org.freedesktop.UPower.Device">
+    <method name="Refresh"/>
+    <property name="NativePath" type="s" access="read"/>
+    <property name="Vendor" type="s" access="read"/>
+    <property name="Model" type="s" access="read"/>
+    <property name="Serial" type="s" access="read"/>
+    <property name="UpdateTime" type="t" access="read"/>
+    <property name="Type" type="u" access="read"/>
+    <property name="PowerSupply" type="b" access="read"/>
+    <property name="Online" type="b" access="read"/>
+    <property name="IsPresent" type="b" access="read"/>
+    <property name="IsRechargeable" type="b" access="read"/>
+    <property name="State" type="u" access="read"/>
+    <property name="Percentage" type="d" access="read"/>
+    <property name="Voltage" type="d" access="read"/>
+    <property name="Temperature" type="d" access="read"/>
+    <property name="TimeToEmpty" type="x" access="read"/>
+    <property name="TimeToFull" type="x" access="read"/>
+    <property name="Energy" type="d" access="read"/>
+    <property name="EnergyEmpty" type="d" access="read"/>
+    <property name="EnergyFull" type="d" access="read"/>
+    <property name="EnergyFullDesign" type="d" access="read"/>
+    <property name="EnergyRate" type="d" access="read"/>
+    <property name="Capacity" type="d" access="read"/>
+    <property name="Technology" type="u" access="read"/>
+    <property name="WarningLevel" type="u" access="read"/>
+    <property name="BatteryLevel" type="u" access="read"/>
+    <property name="IconName" type="s" access="read"/>
+  </interface>
+  <interface name="org.freedesktop.DBus.Properties">
+    <method name="Get">
+      <arg type="s" direction="in"/><arg type="s" direction="in"/><arg type="v" direction="out"/>
+    </method>
+    <method name="GetAll">
+      <arg type="s" direction="in"/><arg type="a{sv}" direction="out"/>
+    </method>
+    <signal name="PropertiesChanged">
+      <arg type="s"/><arg type="a{sv}"/><arg type="as"/>
+    </signal>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect"><arg type="s" direction="out"/></method>
+  </interface>
+</node>
+"#;
+
+/// Compute the device properties from a measurement sample
+pub fn device_properties(
+    m: &Measurements,
+    empty_voltage: Volts,
+    time_to_empty: Option<f64>,
+    device_id: &str,
+    update_time: u64,
+) -> BTreeMap<&'static str, Value> {
+    let charge = m.supercap_charge(empty_voltage);
+    let percentage = (charge as f64 * 100.0).clamp(0.0, 100.0);
+    let discharging = m.power_state.is_blackout();
+
+    let state = if discharging {
+        if charge <= 0.0 {
+            STATE_EMPTY
+        } else {
+            STATE_DISCHARGING
+        }
+    } else if charge >= FULL_CHARGE {
+        STATE_FULLY_CHARGED
+    } else {
+        STATE_CHARGING
+    };
+
+    let warning_level = match (discharging, percentage) {
+        (false, _) => WARNING_NONE,
+        (true, p) if p < 10.0 => WARNING_CRITICAL,
+        (true, p) if p < 20.0 => WARNING_LOW,
+        (true, _) => WARNING_DISCHARGING,
+    };
+
+    let mut props = BTreeMap::new();
+    props.insert("NativePath", Value::Str("halpi2-supercap".into()));
+    props.insert("Vendor", Value::Str("Hat Labs".into()));
+    props.insert("Model", Value::Str("HALPI2 Supercapacitor".into()));
+    props.insert("Serial", Value::Str(device_id.into()));
+    props.insert("UpdateTime", Value::U64(update_time));
+    props.insert("Type", Value::U32(TYPE_BATTERY));
+    props.insert("PowerSupply", Value::Bool(true));
+    props.insert("Online", Value::Bool(false));
+    props.insert("IsPresent", Value::Bool(true));
+    props.insert("IsRechargeable", Value::Bool(true));
+    props.insert("State", Value::U32(state));
+    props.insert("Percentage", Value::Double(percentage.round()));
+    props.insert("Voltage", Value::Double(m.supercap_voltage.as_f64()));
+    props.insert(
+        "Temperature",
+        Value::Double(m.pcb_temperature.celsius().as_f64()),
+    );
+    props.insert(
+        "TimeToEmpty",
+        Value::I64(time_to_empty.unwrap_or(0.0) as i64),
+    );
+    props.insert("TimeToFull", Value::I64(0));
+    props.insert("Energy", Value::Double(0.0));
+    props.insert("EnergyEmpty", Value::Double(0.0));
+    props.insert("EnergyFull", Value::Double(0.0));
+    props.insert("EnergyFullDesign", Value::Double(0.0));
+    props.insert("EnergyRate", Value::Double(0.0));
+    props.insert("Capacity", Value::Double(100.0));
+    props.insert("Technology", Value::U32(TECHNOLOGY_UNKNOWN));
+    props.insert("WarningLevel", Value::U32(warning_level));
+    props.insert("BatteryLevel", Value::U32(BATTERY_LEVEL_NONE));
+    props.insert(
+        "IconName",
+        Value::Str(icon_name(percentage, state == STATE_CHARGING)),
+    );
+    props
+}
+
+/// Symbolic icon name following the UPower naming scheme
+fn icon_name(percentage: f64, charging: bool) -> String {
+    let level = match percentage {
+        p if p >= 90.0 => "full",
+        p if p >= 60.0 => "good",
+        p if p >= 30.0 => "low",
+        p if p >= 10.0 => "caution",
+        _ => "empty",
+    };
+    if charging {
+        format!("battery-{}-charging-symbolic", level)
+    } else {
+        format!("battery-{}-symbolic", level)
+    }
+}
+
+/// Properties whose values differ between two snapshots
+///
+/// `UpdateTime` is excluded so that unchanged readings do not generate signals.
+fn changed_properties(
+    old: &BTreeMap<&'static str, Value>,
+    new: &BTreeMap<&'static str, Value>,
+) -> Vec<(String, Value)> {
+    new.iter()
+        .filter(|(name, value)| **name != "UpdateTime" && old.get(*name) != Some(*value))
+        .map(|(name, value)| (name.to_string(), value.clone()))
+        .collect()
+}
+
+/// Build the reply to a method call on the exported object
+fn handle_call(call: &Message, props: &BTreeMap<&'static str, Value>) -> Message {
+    let interface = call.interface.as_deref().unwrap_or("");
+    let member = call.member.as_deref().unwrap_or("");
+    let arg = |i: usize| call.body.get(i).and_then(Value::as_str);
+
+    if call.path.as_deref() != Some(DEVICE_PATH) {
+        return Message::error(call, dbus::ERROR_UNKNOWN_OBJECT, "No such object");
+    }
+
+    match (interface, member) {
+        (dbus::PROPERTIES_INTERFACE, "Get") => match (arg(0), arg(1)) {
+            (Some(DEVICE_INTERFACE), Some(name)) => match props.get(name) {
+                Some(value) => {
+                    Message::method_return(call).with_body(vec![value.clone().variant()])
+                }
+                None => Message::error(call, dbus::ERROR_UNKNOWN_PROPERTY, name),
+            },
+            _ => Message::error(call, dbus::ERROR_INVALID_ARGS, "Unknown interface"),
+        },
+        (dbus::PROPERTIES_INTERFACE, "GetAll") => {
+            let entries = if arg(0) == Some(DEVICE_INTERFACE) {
+                props
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.clone()))
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            Message::method_return(call).with_body(vec![Value::dict(entries)])
+        }
+        (dbus::PROPERTIES_INTERFACE, "Set") => Message::error(
+            call,
+            dbus::ERROR_PROPERTY_READ_ONLY,
+            "Properties are read-only",
+        ),
+        (dbus::INTROSPECTABLE_INTERFACE, "Introspect") => {
+            Message::method_return(call).with_body(vec![Value::Str(INTROSPECTION_XML.into())])
+        }
+        (dbus::PEER_INTERFACE, "Ping") | (DEVICE_INTERFACE, "Refresh") => {
+            Message::method_return(call)
+        }
+        _ => Message::error(
+            call,
+            dbus::ERROR_UNKNOWN_METHOD,
+            &format!("Unknown method {}.{}", interface, member),
+        ),
+    }
+}
+
+/// Run the D-Bus battery device until the bus connection is lost
+pub async fn run(device: DeviceHandle, events: EventBus, config: UpowerConfig) -> Result<()> {
+    let mut conn = Connection::system().await?;
+    conn.request_name(BUS_NAME)
+        .await
+        .with_context(|| format!("Failed to acquire D-Bus name {}", BUS_NAME))?;
+    info!("Exporting UPower battery device at {}", DEVICE_PATH);
+
+    // Identity is only known once the controller is connected
+    let (device_id, empty_voltage) = device
+        .with_present(|dev| {
+            let device_id = dev.get_device_id().unwrap_or_default();
+            let empty_voltage = dev
+                .get_solo_power_off_threshold()
+                .map_or(FALLBACK_EMPTY_VOLTAGE, Volts);
+            (device_id, empty_voltage)
+        })
+        .await?;
+
+    let start = Instant::now();
+    let mut estimator = DischargeEstimator::default();
+    let mut props: BTreeMap<&'static str, Value> = BTreeMap::new();
+    let mut ticker = interval(Duration::from_secs_f64(config.interval));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let Some(sample) = events.current() else {
+                    debug!("Skipping UPower update: no current measurements");
+                    continue;
+                };
+                let m = sample.measurements;
+
+                let time_to_empty = estimator.update(
+                    start.elapsed().as_secs_f64(),
+                    m.supercap_voltage,
+                    empty_voltage,
+                    m.power_state.is_blackout(),
+                );
+                let update_time = chrono::Utc::now().timestamp().max(0) as u64;
+                let new_props =
+                    device_properties(&m, empty_voltage, time_to_empty, &device_id, update_time);
+                let changed = changed_properties(&props, &new_props);
+                props = new_props;
+
+                if !changed.is_empty() {
+                    let signal = Message::signal(
+                        DEVICE_PATH,
+                        dbus::PROPERTIES_INTERFACE,
+                        "PropertiesChanged",
+                    )
+                    .with_body(vec![
+                        Value::Str(DEVICE_INTERFACE.into()),
+                        Value::dict(changed),
+                        Value::Array("s".into(), Vec::new()),
+                    ]);
+                    conn.sender.send(&signal).await?;
+                }
+            }
+
+            msg = conn.recv() => {
+                let Some(msg) = msg else {
+                    anyhow::bail!("D-Bus connection closed");
+                };
+                if msg.msg_type != MessageType::MethodCall {
+                    continue;
+                }
+                let reply = handle_call(&msg, &props);
+                if !msg.no_reply_expected() {
+                    conn.sender.send(&reply).await?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use halpi_common::types::PowerState;
+    use halpi_common::units::{Amps, Kelvin, Volts};
+
+    fn sample(supercap_voltage: f32, power_state: PowerState) -> Measurements {
+        Measurements {
+            dcin_voltage: Volts(12.0),
+            supercap_voltage: Volts(supercap_voltage),
+            input_current: Amps(0.5),
+            mcu_temperature: Kelvin(300.0),
+            pcb_temperature: Kelvin(300.0),
+            power_state,
+            watchdog_elapsed: 0.0,
+            measured_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_properties_charging_vs_discharging() {
+        let props = device_properties(
+            &sample(8.0, PowerState::OperationalCoOp),
+            Volts(6.0),
+            None,
+            "id",
+            0,
+        );
+        assert_eq!(props["State"], Value::U32(STATE_CHARGING));
+        assert_eq!(props["WarningLevel"], Value::U32(WARNING_NONE));
+
+        let props = device_properties(
+            &sample(6.2, PowerState::BlackoutCoOp),
+            Volts(6.0),
+            Some(12.0),
+            "id",
+            0,
+        );
+        assert_eq!(props["State"], Value::U32(STATE_DISCHARGING));
+        assert_eq!(props["TimeToEmpty"], Value::I64(12));
+        assert_eq!(props["WarningLevel"], Value::U32(WARNING_CRITICAL));
+    }
+
+    #[test]
+    fn test_changed_properties_ignores_update_time() {
+        let m = sample(8.0, PowerState::OperationalCoOp);
+        let a = device_properties(&m, Volts(6.0), None, "id", 1);
+        let b = device_properties(&m, Volts(6.0), None, "id", 2);
+        assert!(changed_properties(&a, &b).is_empty());
+        assert_eq!(changed_properties(&BTreeMap::new(), &b).len(), b.len() - 1);
+    }
+
+    #[test]
+    fn test_handle_get_property() {
+        let props = device_properties(
+            &sample(8.0, PowerState::OperationalCoOp),
+            Volts(6.0),
+            None,
+            "x",
+            0,
+        );
+        let mut call =
+            Message::method_call(BUS_NAME, DEVICE_PATH, dbus::PROPERTIES_INTERFACE, "Get")
+                .with_body(vec![
+                    Value::Str(DEVICE_INTERFACE.into()),
+                    Value::Str("Type".into()),
+                ]);
+        call.serial = 3;
+        let reply = handle_call(&call, &props);
+        assert_eq!(reply.msg_type, MessageType::MethodReturn);
+        assert_eq!(reply.body, vec![Value::U32(TYPE_BATTERY).variant()]);
+
+        call.path = Some("/other".into());
+        assert_eq!(handle_call(&call, &props).msg_type, MessageType::Error);
+    }
+}
@@ -0,0 +1,464 @@
+//! Webhook notifications
+//!
+//! Listens on the event bus for power and health events and POSTs a JSON
+//! payload to each subscribed endpoint. The payload carries a human-readable
+//! `text` field, so Slack-style incoming webhooks and ntfy work without a
+//! relay; the remaining fields are meant for scripted consumers.
+//!
+//! Deliveries run in their own tasks so a slow or unreachable endpoint never
+//! delays detection. Failed deliveries are retried with exponential backoff;
+//! 4xx responses other than 429 are treated as permanent.
+
+use chrono::{DateTime, Utc};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::Duration;
+use tracing::{debug, info, warn};
+
+use halpi_common::config::{WebhookEvent, WebhooksConfig};
+use halpi_common::events::{DaemonEvent, DfuProgress};
+use halpi_common::types::{Measurements, PowerState, Version};
+use halpi_common::units::Celsius;
+
+use crate::events::EventBus;
+use crate::http_client::HttpClient;
+use crate::i2c::DeviceHandle;
+
+/// Temperature drop below the limit required before the alarm re-arms (°C)
+const TEMPERATURE_HYSTERESIS: f32 = 5.0;
+
+/// Upper bound for the retry delay
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+/// A detected event, ready to be turned into a payload
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub event: WebhookEvent,
+    /// Human-readable summary
+    pub text: String,
+    /// Event-specific fields merged into the payload
+    pub data: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Notification {
+    fn new(event: WebhookEvent, text: String) -> Self {
+        Self {
+            event,
+            text,
+            data: serde_json::Map::new(),
+        }
+    }
+
+    fn with(mut self, key: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.data.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Build the JSON request body
+    pub fn payload(&self, host: &str, device_id: &str, timestamp: &str) -> serde_json::Value {
+        let mut body = serde_json::Map::new();
+        body.insert("event".into(), self.event.name().into());
+        body.insert("text".into(), format!("{}: {}", host, self.text).into());
+        body.insert("host".into(), host.into());
+        body.insert("device_id".into(), device_id.into());
+        body.insert("timestamp".into(), timestamp.into());
+        for (key, value) in &self.data {
+            body.insert(key.clone(), value.clone());
+        }
+        serde_json::Value::Object(body)
+    }
+}
+
+/// Turns daemon events into notifications
+#[derive(Debug)]
+pub struct EventDetector {
+    temperature_limit: f32,
+    firmware: Option<Version>,
+    last: Option<Measurements>,
+    blackout_since: Option<DateTime<Utc>>,
+    temperature_alarm: bool,
+}
+
+impl EventDetector {
+    /// Create a detector; `firmware` is the version running at startup
+    pub fn new(temperature_limit: f32, firmware: Option<Version>) -> Self {
+        Self {
+            temperature_limit,
+            firmware,
+            last: None,
+            blackout_since: None,
+            temperature_alarm: false,
+        }
+    }
+
+    /// Process one event from the bus
+    pub fn handle(&mut self, event: &DaemonEvent) -> Vec<Notification> {
+        match event {
+            DaemonEvent::Measurement(sample) => {
+                let notification = self.check_temperature(&sample.measurements);
+                self.last = Some(sample.measurements.clone());
+                notification.into_iter().collect()
+            }
+            DaemonEvent::StateTransition {
+                timestamp,
+                from,
+                to,
+            } => self.transition(*timestamp, *from, *to),
+            DaemonEvent::Dfu {
+                progress: DfuProgress::Completed { total },
+                ..
+            } => {
+                let mut notification = Notification::new(
+                    WebhookEvent::FirmwareUpdate,
+                    format!("Controller firmware updated ({} bytes)", total),
+                )
+                .with("size", *total);
+                if let Some(previous) = &self.firmware {
+                    notification = notification.with("previous", previous.to_string());
+                }
+                vec![notification]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn transition(
+        &mut self,
+        timestamp: DateTime<Utc>,
+        from: PowerState,
+        to: PowerState,
+    ) -> Vec<Notification> {
+        let mut events = Vec::new();
+        let (v_in, v_cap) = self.last.as_ref().map_or((None, None), |m| {
+            (Some(m.dcin_voltage.0), Some(m.supercap_voltage.0))
+        });
+
+        if to.is_blackout() && !from.is_blackout() {
+            self.blackout_since = Some(timestamp);
+            events.push(
+                Notification::new(
+                    WebhookEvent::BlackoutStart,
+                    "Input power lost, running on supercap".to_string(),
+                )
+                .with("V_in", v_in)
+                .with("V_cap", v_cap)
+                .with("state", to.name()),
+            );
+        } else if !to.is_blackout() && from.is_blackout() {
+            let duration = self
+                .blackout_since
+                .take()
+                .map(|since| (timestamp - since).num_milliseconds() as f64 / 1000.0);
+            let text = match duration {
+                Some(secs) => format!("Input power restored after {:.1} s", secs),
+                None => "Input power restored".to_string(),
+            };
+            events.push(
+                Notification::new(WebhookEvent::BlackoutEnd, text)
+                    .with("V_in", v_in)
+                    .with("duration", duration)
+                    .with("state", to.name()),
+            );
+        }
+
+        if matches!(
+            to,
+            PowerState::BlackoutShutdown | PowerState::ManualShutdown
+        ) {
+            let reason = if to == PowerState::BlackoutShutdown {
+                "blackout"
+            } else {
+                "manual"
+            };
+            events.push(
+                Notification::new(
+                    WebhookEvent::Shutdown,
+                    format!("Shutdown initiated ({})", reason),
+                )
+                .with("reason", reason)
+                .with("V_cap", v_cap)
+                .with("state", to.name()),
+            );
+        }
+
+        events
+    }
+
+    fn check_temperature(&mut self, m: &Measurements) -> Option<Notification> {
+        let (sensor, temperature) = hottest(m);
+        let celsius = temperature.0;
+        if !self.temperature_alarm && celsius > self.temperature_limit {
+            self.temperature_alarm = true;
+            return Some(
+                Notification::new(
+                    WebhookEvent::TemperatureAlarm,
+                    format!(
+                        "{} temperature {:.1} exceeds {:.1}",
+                        sensor,
+                        temperature,
+                        Celsius(self.temperature_limit)
+                    ),
+                )
+                .with("sensor", sensor)
+                .with("temperature", celsius)
+                .with("limit", self.temperature_limit),
+            );
+        }
+        if self.temperature_alarm && celsius < self.temperature_limit - TEMPERATURE_HYSTERESIS {
+            self.temperature_alarm = false;
+        }
+        None
+    }
+}
+
+/// The hotter of the MCU and PCB sensors
+fn hottest(m: &Measurements) -> (&'static str, Celsius) {
+    let mcu = m.mcu_temperature.celsius();
+    let pcb = m.pcb_temperature.celsius();
+    if mcu >= pcb {
+        ("MCU", mcu)
+    } else {
+        ("PCB", pcb)
+    }
+}
+
+/// Delay before retry number `attempt` (1-based)
+pub fn retry_delay(initial: Duration, attempt: u32) -> Duration {
+    initial
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_RETRY_DELAY)
+}
+
+/// POST `body` to `url`, retrying transient failures
+async fn deliver(client: HttpClient, url: String, body: String, retries: u32, delay: Duration) {
+    let headers = [("Content-Type", "application/json".to_string())];
+    for attempt in 0..=retries {
+        if attempt > 0 {
+            tokio::time::sleep(retry_delay(delay, attempt)).await;
+        }
+        match client.post(&url, &headers, body.clone()).await {
+            Ok(response) if response.is_success() => {
+                debug!("Webhook delivered to {}", url);
+                return;
+            }
+            Ok(response) if (400..500).contains(&response.status) && response.status != 429 => {
+                warn!(
+                    "Webhook {} rejected the notification (HTTP {}), not retrying",
+                    url, response.status
+                );
+                return;
+            }
+            Ok(response) => debug!("Webhook {} returned HTTP {}", url, response.status),
+            Err(e) => debug!("Webhook {} failed: {:#}", url, e),
+        }
+    }
+    warn!(
+        "Giving up on webhook {} after {} attempts",
+        url,
+        retries + 1
+    );
+}
+
+/// Local host name used to label notifications
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|s| s.trim().to_string())
+        .unwrap_or_else(|_| "halpi".to_string())
+}
+
+/// Run the notifier until the daemon shuts down
+pub async fn run(
+    device: DeviceHandle,
+    events: EventBus,
+    config: WebhooksConfig,
+) -> anyhow::Result<()> {
+    let client = HttpClient::default();
+    let host = hostname();
+    let retry_delay = Duration::from_secs_f64(config.retry_delay);
+    let mut rx = events.subscribe();
+
+    // Identity is only known once the controller is connected
+    let (device_id, firmware) = device
+        .with_present(|dev| {
+            let device_id = dev
+                .get_device_id()
+                .unwrap_or_else(|_| "0000000000000000".to_string());
+            (device_id, dev.get_firmware_version().ok())
+        })
+        .await?;
+
+    let mut detector = EventDetector::new(config.temperature_limit as f32, firmware);
+
+    loop {
+        let event = match rx.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(skipped)) => {
+                debug!("Webhook notifier skipped {} events", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => return Ok(()),
+        };
+
+        for notification in detector.handle(&event) {
+            info!(
+                "Webhook event {}: {}",
+                notification.event.name(),
+                notification.text
+            );
+            let timestamp = chrono::Utc::now().to_rfc3339();
+            let body = notification
+                .payload(&host, &device_id, &timestamp)
+                .to_string();
+            for endpoint in config
+                .endpoints
+                .iter()
+                .filter(|e| e.wants(notification.event))
+            {
+                tokio::spawn(deliver(
+                    client.clone(),
+                    endpoint.url.clone(),
+                    body.clone(),
+                    config.retries,
+                    retry_delay,
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halpi_common::events::Sample;
+    use halpi_common::units::{Amps, Kelvin, Volts};
+
+    fn measurement(t_mcu_c: f32) -> DaemonEvent {
+        DaemonEvent::Measurement(Sample::now(Measurements {
+            dcin_voltage: Volts(12.0),
+            supercap_voltage: Volts(9.5),
+            input_current: Amps(0.5),
+            mcu_temperature: Celsius(t_mcu_c).kelvin(),
+            pcb_temperature: Kelvin(298.15),
+            power_state: PowerState::OperationalCoOp,
+            watchdog_elapsed: 0.0,
+            measured_at: Utc::now(),
+        }))
+    }
+
+    fn transition(from: PowerState, to: PowerState, timestamp: DateTime<Utc>) -> DaemonEvent {
+        DaemonEvent::StateTransition {
+            timestamp,
+            from,
+            to,
+        }
+    }
+
+    fn kinds(events: &[Notification]) -> Vec<WebhookEvent> {
+        events.iter().map(|n| n.event).collect()
+    }
+
+    #[test]
+    fn test_blackout_cycle() {
+        let mut detector = EventDetector::new(70.0, None);
+        let t0 = Utc::now();
+
+        assert!(detector.handle(&measurement(40.0)).is_empty());
+
+        let events = detector.handle(&transition(
+            PowerState::OperationalCoOp,
+            PowerState::BlackoutCoOp,
+            t0,
+        ));
+        assert_eq!(kinds(&events), vec![WebhookEvent::BlackoutStart]);
+        assert_eq!(events[0].data["V_in"], serde_json::json!(12.0));
+
+        let later = t0 + chrono::Duration::seconds(3);
+        let events = detector.handle(&transition(
+            PowerState::BlackoutCoOp,
+            PowerState::OperationalCoOp,
+            later,
+        ));
+        assert_eq!(kinds(&events), vec![WebhookEvent::BlackoutEnd]);
+        assert_eq!(events[0].data["duration"], serde_json::json!(3.0));
+    }
+
+    #[test]
+    fn test_shutdown_events() {
+        let mut detector = EventDetector::new(70.0, None);
+        let now = Utc::now();
+
+        let events = detector.handle(&transition(
+            PowerState::BlackoutCoOp,
+            PowerState::BlackoutShutdown,
+            now,
+        ));
+        assert_eq!(kinds(&events), vec![WebhookEvent::Shutdown]);
+        assert_eq!(events[0].data["reason"], "blackout");
+
+        let events = detector.handle(&transition(
+            PowerState::OperationalSolo,
+            PowerState::ManualShutdown,
+            now,
+        ));
+        assert_eq!(kinds(&events), vec![WebhookEvent::Shutdown]);
+        assert_eq!(events[0].data["reason"], "manual");
+    }
+
+    #[test]
+    fn test_temperature_alarm_hysteresis() {
+        let mut detector = EventDetector::new(70.0, None);
+
+        let events = detector.handle(&measurement(72.0));
+        assert_eq!(kinds(&events), vec![WebhookEvent::TemperatureAlarm]);
+        assert_eq!(events[0].data["sensor"], "MCU");
+
+        // Still hot, or only slightly cooler: no new alarm
+        assert!(detector.handle(&measurement(73.0)).is_empty());
+        assert!(detector.handle(&measurement(67.0)).is_empty());
+        assert!(detector.handle(&measurement(71.0)).is_empty());
+
+        // Cooled below the hysteresis band, then hot again
+        assert!(detector.handle(&measurement(60.0)).is_empty());
+        let events = detector.handle(&measurement(71.0));
+        assert_eq!(kinds(&events), vec![WebhookEvent::TemperatureAlarm]);
+    }
+
+    #[test]
+    fn test_firmware_update() {
+        let mut detector = EventDetector::new(70.0, Some(Version::new(3, 0, 1)));
+
+        let started = DaemonEvent::Dfu {
+            timestamp: Utc::now(),
+            progress: DfuProgress::Started { total: 65536 },
+        };
+        assert!(detector.handle(&started).is_empty());
+
+        let completed = DaemonEvent::Dfu {
+            timestamp: Utc::now(),
+            progress: DfuProgress::Completed { total: 65536 },
+        };
+        let events = detector.handle(&completed);
+        assert_eq!(kinds(&events), vec![WebhookEvent::FirmwareUpdate]);
+        assert_eq!(events[0].data["previous"], "3.0.1");
+        assert_eq!(events[0].data["size"], 65536);
+    }
+
+    #[test]
+    fn test_payload() {
+        let notification = Notification::new(WebhookEvent::Shutdown, "Shutdown initiated".into())
+            .with("reason", "manual");
+        let body = notification.payload("boat", "0123456789abcdef", "2025-01-01T00:00:00+00:00");
+        assert_eq!(body["event"], "shutdown");
+        assert_eq!(body["text"], "boat: Shutdown initiated");
+        assert_eq!(body["device_id"], "0123456789abcdef");
+        assert_eq!(body["reason"], "manual");
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let initial = Duration::from_secs(2);
+        assert_eq!(retry_delay(initial, 1), Duration::from_secs(2));
+        assert_eq!(retry_delay(initial, 2), Duration::from_secs(4));
+        assert_eq!(retry_delay(initial, 4), Duration::from_secs(16));
+        assert_eq!(retry_delay(initial, 30), MAX_RETRY_DELAY);
+    }
+}